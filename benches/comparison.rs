@@ -0,0 +1,122 @@
+//! nanobit against bincode, postcard and rmp-serde
+//!
+//! ```sh
+//! cargo bench --features bench --bench comparison
+//! ```
+//!
+//! Encoded sizes are printed before the timings. Reports land in
+//! `target/criterion`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use nanobit::bench::{compare, compare_formats, Format};
+use nanobit::Config;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Point {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Login {
+    user_id: u64,
+    session: u32,
+    admin: bool,
+    origin: Point,
+    tag: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogLine {
+    level: String,
+    target: String,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Inventory {
+    stock: BTreeMap<String, u32>,
+    prices: HashMap<u64, f64>,
+}
+
+fn login() -> Login {
+    Login { user_id: 90_210, session: 7, admin: false, origin: Point { x: -4, y: 120, z: 3 }, tag: Some(12) }
+}
+
+fn samples() -> Vec<u64> {
+    (0..10_000u64).map(|i| i * i % 1_000_003).collect()
+}
+
+fn readings() -> Vec<f64> {
+    (0..10_000).map(|i| (i as f64 * 0.01).sin()).collect()
+}
+
+fn logs() -> Vec<LogLine> {
+    (0..500)
+        .map(|i| LogLine {
+            level: ["INFO", "WARN", "DEBUG"][i % 3].to_string(),
+            target: "server::http::handler".to_string(),
+            message: format!("request {i} finished with status {}", 200 + i % 5),
+            fields: vec![
+                ("method".to_string(), "GET".to_string()),
+                ("path".to_string(), format!("/api/items/{i}")),
+            ],
+        })
+        .collect()
+}
+
+fn inventory() -> Inventory {
+    Inventory {
+        stock: (0..1_000).map(|i| (format!("sku-{i:05}"), i * 3)).collect(),
+        prices: (0..1_000u64).map(|i| (i, i as f64 * 1.25)).collect(),
+    }
+}
+
+fn print_sizes<T: Serialize>(name: &str, value: &T) {
+    let sizes: Vec<String> = Format::sizes(value)
+        .into_iter()
+        .map(|(format, size)| format!("{format} {size}"))
+        .collect();
+    println!("{name}: {}", sizes.join(", "));
+}
+
+fn small_struct(c: &mut Criterion) {
+    print_sizes("small_struct", &login());
+    compare(c, "small_struct", &login());
+}
+
+fn large_vectors(c: &mut Criterion) {
+    print_sizes("large_vec_u64", &samples());
+    compare(c, "large_vec_u64", &samples());
+    print_sizes("large_vec_f64", &readings());
+    compare(c, "large_vec_f64", &readings());
+}
+
+fn string_heavy(c: &mut Criterion) {
+    let logs = logs();
+    print_sizes("string_heavy", &logs);
+    // The string table pays off on repeated strings
+    let mut formats = Format::ALL.to_vec();
+    formats.insert(1, Format::Nanobit(Config::new().with_string_table(true)));
+    compare_formats(c, "string_heavy", &logs, &formats);
+}
+
+fn map_heavy(c: &mut Criterion) {
+    print_sizes("map_heavy", &inventory());
+    compare(c, "map_heavy", &inventory());
+}
+
+fn tagged_fields(c: &mut Criterion) {
+    // Every field carries a back-patched byte length
+    let logs = logs();
+    let formats = [Format::Nanobit(Config::new()), Format::Nanobit(Config::new().with_tagged_fields(true))];
+    compare_formats(c, "tagged_fields", &logs, &formats);
+}
+
+criterion_group!(benches, small_struct, large_vectors, string_heavy, map_heavy, tagged_fields);
+criterion_main!(benches);
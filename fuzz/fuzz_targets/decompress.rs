@@ -0,0 +1,17 @@
+//! Decompress arbitrary bytes under an output limit
+//!
+//! Besides not panicking, no container may expand past the limit it is
+//! decoded with.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+const LIMIT: usize = 1 << 20;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(out) = nanobit::decompress_with_limit(data, LIMIT) {
+        assert!(out.len() <= LIMIT);
+    }
+    let _ = nanobit::compression::container_format(data);
+});
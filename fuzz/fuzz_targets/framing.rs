@@ -0,0 +1,37 @@
+//! Read arbitrary bytes through the framing layers
+//!
+//! Covers value streams, record logs, indexed record files and session
+//! messages, which all parse lengths and offsets before any payload.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use nanobit::log::LogReader;
+use nanobit::record_file::RecordFileReader;
+use nanobit::{SessionDecoder, StreamDeserializer, Value};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(stream) = StreamDeserializer::<Value>::new(data) {
+        stream.take(1024).for_each(drop);
+    }
+    if let Ok(items) = nanobit::from_bytes_seq_iter::<Value>(data) {
+        items.take(1024).for_each(drop);
+    }
+
+    let mut log = LogReader::new(Cursor::new(data));
+    while let Ok(Some(_)) = log.next_record::<Value>() {}
+
+    if let Ok(mut file) = RecordFileReader::new(Cursor::new(data)) {
+        for index in 0..16 {
+            let _ = file.get::<Value>(index);
+        }
+    }
+
+    // Every message of a session, split at zero bytes
+    let mut session = SessionDecoder::new();
+    for message in data.split(|&byte| byte == 0) {
+        let _ = session.decode::<Value>(message);
+    }
+});
@@ -0,0 +1,46 @@
+//! Decode arbitrary bytes as payloads of several shapes
+//!
+//! Any input may fail to decode, but none may panic, overflow the stack or
+//! allocate without bound.
+
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use libfuzzer_sys::fuzz_target;
+use nanobit::{Config, Value};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+enum Node {
+    Leaf(u64),
+    Branch(Vec<Node>),
+    Named { name: String, child: Option<Box<Node>> },
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Record<'a> {
+    id: u32,
+    name: &'a str,
+    tags: Vec<String>,
+    scores: BTreeMap<String, f64>,
+    data: (char, i16, Option<bool>),
+    node: Node,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = nanobit::from_bytes::<Value>(data);
+    let _ = nanobit::from_bytes::<Record<'_>>(data);
+    let _ = nanobit::from_bytes::<Vec<Node>>(data);
+    let lenient = Config::new().with_lenient_field_count(true);
+    let _ = nanobit::from_bytes_with_config::<Record<'_>>(data, lenient);
+
+    let _ = nanobit::sniff(data);
+    let _ = nanobit::extract::<Value>(data, &["node", "0"]);
+    let _ = nanobit::diff(data, data);
+    let _ = nanobit::debug::explain(data);
+    let _ = nanobit::validate::validate(data, Default::default());
+    let _ = nanobit::json::to_json(data);
+});
@@ -0,0 +1,74 @@
+//! Arbitrary values must decode to themselves under every encoding option
+
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use nanobit::{Checksum, CompressionFormat, CompressionLevel, Config};
+use serde::{Deserialize, Serialize};
+
+#[derive(Arbitrary, Serialize, Deserialize, Debug, PartialEq)]
+enum Shape {
+    Empty,
+    Circle(u32),
+    Line(i8, i64),
+    Poly { points: Vec<(i16, i16)>, label: Option<String> },
+}
+
+#[derive(Arbitrary, Serialize, Deserialize, Debug, PartialEq)]
+struct Unit;
+
+#[derive(Arbitrary, Serialize, Deserialize, Debug, PartialEq)]
+struct Message {
+    id: u64,
+    flag: bool,
+    small: (u8, u16, i32),
+    letter: char,
+    text: String,
+    #[serde(with = "nanobit::byte_slice")]
+    blob: Vec<u8>,
+    shapes: Vec<Shape>,
+    index: BTreeMap<String, Vec<u32>>,
+    boxed: Option<Box<Shape>>,
+    unit: Unit,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Options {
+    tagged_fields: bool,
+    self_describing: bool,
+    canonical: bool,
+    string_table: bool,
+    compact_chars: bool,
+    fixed_tuples: bool,
+    checksum: bool,
+    compress: bool,
+}
+
+impl Options {
+    fn config(&self) -> Config {
+        Config::new()
+            .with_tagged_fields(self.tagged_fields)
+            .with_self_describing(self.self_describing)
+            .with_canonical(self.canonical)
+            .with_string_table(self.string_table)
+            .with_compact_chars(self.compact_chars)
+            .with_fixed_tuples(self.fixed_tuples)
+            .with_checksum(self.checksum.then_some(Checksum::Crc32c))
+    }
+}
+
+fuzz_target!(|input: (Message, Options)| {
+    let (message, options) = input;
+    let config = options.config();
+    let bytes = nanobit::to_bytes_with_config(&message, config).expect("any value serializes");
+    let decoded: Message = nanobit::from_bytes_with_config(&bytes, config).expect("own output decodes");
+    assert_eq!(decoded, message);
+
+    if options.compress {
+        let packed = nanobit::compress(&bytes, CompressionFormat::NanoBit, CompressionLevel::default()).unwrap();
+        assert_eq!(nanobit::decompress(&packed).unwrap(), bytes);
+    }
+});
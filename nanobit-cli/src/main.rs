@@ -0,0 +1,315 @@
+//! Command-line tool for NanoBit payloads
+//!
+//! Shows what a payload's header says, dumps self-describing payloads as
+//! JSON, checks payloads and their checksums, compresses and decompresses
+//! them and compares two of them. Files are read from standard input when
+//! given as `-` or left out, and binary output goes to standard output
+//! unless `--output` names a file.
+//!
+//! Exits with 0 on success, 1 when `validate` finds a problem or `diff`
+//! finds differences, and 2 on any other error.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use nanobit::header::HeaderFlags;
+use nanobit::validate::{validate, Limits};
+use nanobit::{CompressionFormat, CompressionLevel, Error, Result};
+
+fn cli() -> Command {
+    let input = || Arg::new("input").value_name("FILE").default_value("-").help("Payload to read, or - for stdin");
+    let output = || {
+        Arg::new("output")
+            .short('o')
+            .long("output")
+            .value_name("FILE")
+            .help("Write the result to FILE instead of stdout")
+    };
+    Command::new("nanobit")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Inspect, validate and convert NanoBit payloads")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(Command::new("info").about("Show what the payload header records").arg(input()))
+        .subcommand(
+            Command::new("dump")
+                .about("Print a self-describing payload as JSON")
+                .arg(input())
+                .arg(Arg::new("pretty").long("pretty").action(ArgAction::SetTrue).help("Indent the JSON")),
+        )
+        .subcommand(Command::new("explain").about("Print an annotated hexdump of the payload").arg(input()))
+        .subcommand(
+            Command::new("validate")
+                .about("Check the header, checksum and, when self-describing, every value")
+                .arg(input())
+                .arg(
+                    Arg::new("max-depth")
+                        .long("max-depth")
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .help("Reject values nested deeper than N"),
+                )
+                .arg(
+                    Arg::new("max-len")
+                        .long("max-len")
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .help("Reject sequences and maps longer than N"),
+                ),
+        )
+        .subcommand(
+            Command::new("compress")
+                .about("Wrap the input in a compressed container")
+                .arg(input())
+                .arg(output())
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .value_parser(["lz4", "zstd", "snappy", "nanobit", "stored"])
+                        .default_value("lz4"),
+                )
+                .arg(
+                    Arg::new("level")
+                        .short('l')
+                        .long("level")
+                        .value_parser(["fastest", "default", "best"])
+                        .default_value("default"),
+                ),
+        )
+        .subcommand(Command::new("decompress").about("Unwrap a compressed container").arg(input()).arg(output()))
+        .subcommand(
+            Command::new("diff")
+                .about("List the values that differ between two payloads")
+                .arg(Arg::new("left").value_name("LEFT").required(true))
+                .arg(Arg::new("right").value_name("RIGHT").required(true)),
+        )
+}
+
+fn main() -> ExitCode {
+    match run(&cli().get_matches()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<ExitCode> {
+    let (name, args) = matches.subcommand().expect("a subcommand is required");
+    let input = || read(args.get_one::<String>("input").expect("has a default"));
+    match name {
+        "info" => print(&info(&input()?)?),
+        "dump" => print(&dump(&input()?, args.get_flag("pretty"))?),
+        "explain" => print(&nanobit::debug::explain(&input()?)),
+        "validate" => {
+            let mut limits = Limits::new();
+            if let Some(&depth) = args.get_one::<usize>("max-depth") {
+                limits = limits.with_max_depth(depth);
+            }
+            if let Some(&len) = args.get_one::<usize>("max-len") {
+                limits = limits.with_max_len(len);
+            }
+            match check(&input()?, limits) {
+                Ok(summary) => print(&summary),
+                Err(err) => {
+                    println!("invalid: {err}");
+                    Ok(ExitCode::from(1))
+                }
+            }
+        }
+        "compress" => {
+            let format = parse_format(args.get_one::<String>("format").expect("has a default"));
+            let level = parse_level(args.get_one::<String>("level").expect("has a default"));
+            write(args, &nanobit::compress(&input()?, format, level)?)
+        }
+        "decompress" => write(args, &nanobit::decompress(&input()?)?),
+        "diff" => {
+            let left = read(args.get_one::<String>("left").expect("required"))?;
+            let right = read(args.get_one::<String>("right").expect("required"))?;
+            let report = diff(&left, &right)?;
+            print(&report)?;
+            Ok(if report.is_empty() { ExitCode::SUCCESS } else { ExitCode::from(1) })
+        }
+        other => unreachable!("unknown subcommand {other}"),
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    fs::read(path).map_err(|e| Error::Io(format!("{path}: {e}")))
+}
+
+fn print(text: &str) -> Result<ExitCode> {
+    io::stdout().write_all(text.as_bytes())?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn write(args: &ArgMatches, data: &[u8]) -> Result<ExitCode> {
+    match args.get_one::<String>("output") {
+        Some(path) if path != "-" => fs::write(path, data).map_err(|e| Error::Io(format!("{path}: {e}")))?,
+        _ => io::stdout().write_all(data)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn parse_format(name: &str) -> CompressionFormat {
+    match name {
+        "zstd" => CompressionFormat::ZSTD,
+        "snappy" => CompressionFormat::Snappy,
+        "nanobit" => CompressionFormat::NanoBit,
+        "stored" => CompressionFormat::Stored,
+        _ => CompressionFormat::LZ4,
+    }
+}
+
+fn parse_level(name: &str) -> CompressionLevel {
+    match name {
+        "fastest" => CompressionLevel::Fastest,
+        "best" => CompressionLevel::Best,
+        _ => CompressionLevel::Default,
+    }
+}
+
+// The payload inside a compressed container, or the input itself
+fn unwrap(data: &[u8]) -> Result<std::borrow::Cow<'_, [u8]>> {
+    match nanobit::sniff(data) {
+        Some(info) if info.compression.is_some() => nanobit::decompress(data).map(Into::into),
+        Some(_) => Ok(data.into()),
+        None => Err(Error::InvalidFormat("Not a nanobit payload".to_string())),
+    }
+}
+
+fn info(data: &[u8]) -> Result<String> {
+    let mut out = String::new();
+    describe(data, &mut out)?;
+    if nanobit::sniff(data).is_some_and(|info| info.compression.is_some()) {
+        let inner = nanobit::decompress(data)?;
+        match nanobit::sniff(&inner) {
+            Some(_) => {
+                out.push_str("\ndecompressed:\n");
+                describe(&inner, &mut out)?;
+            }
+            None => out.push_str("\ndecompressed data is not a nanobit payload\n"),
+        }
+    }
+    Ok(out)
+}
+
+fn describe(data: &[u8], out: &mut String) -> Result<()> {
+    let info = nanobit::sniff(data).ok_or_else(|| Error::InvalidFormat("Not a nanobit payload".to_string()))?;
+    let header = info.header;
+    let _ = writeln!(out, "version       {}", header.version);
+    let _ = writeln!(out, "flags         {}", header.flags);
+    if let Some(hash) = header.schema_hash {
+        let _ = writeln!(out, "schema hash   {hash:#010x}");
+    }
+    if let Some(name) = nanobit::peek_type(data)? {
+        let _ = writeln!(out, "type          {name:?}");
+    }
+    match info.compression {
+        Some(format) => {
+            let _ = writeln!(out, "compression   {format:?}");
+        }
+        None => {
+            let _ = writeln!(out, "compression   none");
+        }
+    }
+    if let Some(checksum) = info.checksum {
+        let status = if nanobit::verify_checksum(data).is_ok() { "matches" } else { "MISMATCH" };
+        let _ = writeln!(out, "checksum      {checksum:?} ({status})");
+    }
+    let _ = writeln!(out, "header        {} bytes", info.header_len);
+    let _ = writeln!(out, "body          {} bytes", info.body_len);
+    if let Some(len) = info.decompressed_len {
+        let _ = writeln!(out, "decompressed  {len} bytes");
+    }
+    Ok(())
+}
+
+fn dump(data: &[u8], pretty: bool) -> Result<String> {
+    let payload = unwrap(data)?;
+    let json = nanobit::json::to_json(&payload)?;
+    let mut text = if pretty { serde_json::to_string_pretty(&json) } else { serde_json::to_string(&json) }
+        .map_err(|e| Error::Serde(e.to_string()))?;
+    text.push('\n');
+    Ok(text)
+}
+
+fn check(data: &[u8], limits: Limits) -> Result<String> {
+    let payload = unwrap(data)?;
+    let info = nanobit::sniff(&payload).ok_or_else(|| Error::InvalidFormat("Not a nanobit payload".to_string()))?;
+    if info.checksum.is_some() {
+        nanobit::verify_checksum(&payload)?;
+    }
+    if info.is_encrypted() {
+        return Ok("ok: encrypted, body not checked\n".to_string());
+    }
+    if !info.header.flags.contains(HeaderFlags::SELF_DESCRIBING) {
+        return Ok("ok: header only, the body needs its Rust type to be checked\n".to_string());
+    }
+    let report = validate(&payload, limits)?;
+    Ok(format!(
+        "ok: {} values, nested {} deep, longest collection {}, longest string {}\n",
+        report.values, report.depth, report.longest_collection, report.longest_string
+    ))
+}
+
+fn diff(left: &[u8], right: &[u8]) -> Result<String> {
+    let mut out = String::new();
+    for difference in nanobit::diff(&unwrap(left)?, &unwrap(right)?)? {
+        let _ = writeln!(out, "{difference}");
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nanobit::{Checksum, Config};
+
+    fn payload(value: &serde_json::Value) -> Vec<u8> {
+        let config = Config::new().with_self_describing(true).with_checksum(Some(Checksum::Crc32c));
+        nanobit::to_bytes_with_config(value, config).unwrap()
+    }
+
+    #[test]
+    fn test_commands() {
+        let bytes = payload(&serde_json::json!({ "name": "ada", "tags": [1, 2] }));
+        let packed = nanobit::compress(&bytes, CompressionFormat::NanoBit, CompressionLevel::Default).unwrap();
+
+        let text = info(&packed).unwrap();
+        assert!(text.contains("compression   NanoBit"));
+        assert!(text.contains("flags         self-describing, crc32c"));
+        assert!(text.contains("checksum      Crc32c (matches)"));
+
+        assert_eq!(dump(&packed, false).unwrap(), "{\"name\":\"ada\",\"tags\":[1,2]}\n");
+        assert!(check(&packed, Limits::new()).unwrap().starts_with("ok: 7 values"));
+        assert!(check(&packed, Limits::new().with_max_depth(1)).is_err());
+
+        let mut corrupt = bytes.clone();
+        corrupt[8] ^= 1;
+        assert!(check(&corrupt, Limits::new()).is_err());
+
+        let other = payload(&serde_json::json!({ "name": "ada", "tags": [1, 3] }));
+        assert_eq!(diff(&packed, &bytes).unwrap(), "");
+        assert!(diff(&bytes, &other).unwrap().starts_with("tags.1: changed"));
+    }
+
+    #[test]
+    fn test_cli_arguments() {
+        cli().debug_assert();
+        let matches = cli().try_get_matches_from(["nanobit", "compress", "-f", "zstd", "in.nb"]).unwrap();
+        let (_, args) = matches.subcommand().unwrap();
+        assert_eq!(parse_format(args.get_one::<String>("format").unwrap()), CompressionFormat::ZSTD);
+        assert!(cli().try_get_matches_from(["nanobit", "compress", "-f", "gzip"]).is_err());
+    }
+}
@@ -0,0 +1,790 @@
+//! # NanoBit Derive
+//!
+//! `#[derive(NanoBit)]` generates `nanobit::encode::Encode` and
+//! `nanobit::encode::Decode` implementations that read and write the nanobit
+//! wire format directly, without going through serde.
+//!
+//! Container attributes:
+//!
+//! - `#[nanobit(tagged)]` writes struct fields as tag + length + value, and
+//!   enum payloads with their length so unknown variants can be skipped
+//! - `#[nanobit(version = N)]` writes a version number before the fields
+//! - `#[nanobit(packed)]` packs `bool` fields and `Option` presence into a
+//!   bitmap written before the other fields
+//! - `#[nanobit(nullable)]` writes a bitmap of one bit per field, as
+//!   `nanobit::nullable` does for serde
+//!
+//! Field attributes:
+//!
+//! - `#[nanobit(tag = N)]` pins the field tag (implies `tagged`)
+//! - `#[nanobit(since = N)]` marks a field added in version `N`
+//! - `#[nanobit(default)]` uses `Default` when the field is absent
+//! - `#[nanobit(skip)]` never encodes the field and decodes it as `Default`
+//!
+//! Variant attributes:
+//!
+//! - `#[nanobit(discriminant = N)]` writes the variant as `N` instead of its
+//!   position; an integer Rust discriminant (`Variant = N`) does the same
+//! - `#[nanobit(other)]` on a unit variant decodes unknown discriminants as
+//!   that variant instead of failing
+//!
+//! `#[derive(Archive)]` generates `nanobit::archive::Archive` for a struct
+//! with named fields, along with a view type that reads each field in place
+//! from an archive.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Expr, ExprLit, Fields, GenericParam,
+    Ident, Index, Lit, LitInt, Member, Type, Variant,
+};
+
+/// Derive direct `Encode` and `Decode` implementations
+#[proc_macro_derive(NanoBit, attributes(nanobit))]
+pub fn derive_nanobit(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// Struct-level options
+#[derive(Default)]
+struct ContainerAttrs {
+    tagged: bool,
+    version: Option<u64>,
+    packed: bool,
+    nullable: bool,
+}
+
+// Variant-level options
+#[derive(Default)]
+struct VariantAttrs {
+    discriminant: Option<u64>,
+    other: bool,
+}
+
+// Field-level options
+#[derive(Default)]
+struct FieldAttrs {
+    tag: Option<u64>,
+    since: Option<u64>,
+    default: bool,
+    skip: bool,
+}
+
+fn parse_container_attrs(attrs: &[Attribute]) -> syn::Result<ContainerAttrs> {
+    let mut parsed = ContainerAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("nanobit")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tagged") {
+                parsed.tagged = true;
+                Ok(())
+            } else if meta.path.is_ident("version") {
+                parsed.version = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("packed") {
+                parsed.packed = true;
+                Ok(())
+            } else if meta.path.is_ident("nullable") {
+                parsed.nullable = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown nanobit container attribute"))
+            }
+        })?;
+    }
+    Ok(parsed)
+}
+
+fn parse_variant_attrs(attrs: &[Attribute]) -> syn::Result<VariantAttrs> {
+    let mut parsed = VariantAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("nanobit")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("discriminant") {
+                parsed.discriminant = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("other") {
+                parsed.other = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown nanobit variant attribute"))
+            }
+        })?;
+    }
+    Ok(parsed)
+}
+
+// Discriminant written for each variant: explicit, or one past the previous
+// variant's as in Rust, so variants can be reordered or removed without
+// changing the others
+fn discriminants(variants: &[(&Variant, VariantAttrs)]) -> syn::Result<Vec<u64>> {
+    let mut next = 0u64;
+    let mut assigned = Vec::with_capacity(variants.len());
+    for (variant, attrs) in variants {
+        let rust = match &variant.discriminant {
+            Some((_, Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }))) => Some(lit.base10_parse::<u64>()?),
+            Some((_, expr)) if attrs.discriminant.is_none() => {
+                return Err(syn::Error::new_spanned(
+                    expr,
+                    "nanobit: use an integer literal or #[nanobit(discriminant = N)]",
+                ))
+            }
+            _ => None,
+        };
+        let discriminant = attrs.discriminant.or(rust).unwrap_or(next);
+        if discriminant > u64::from(u32::MAX) {
+            return Err(syn::Error::new_spanned(variant, "nanobit: discriminants must fit in a u32"));
+        }
+        if assigned.contains(&discriminant) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                format!("nanobit: discriminant {discriminant} is used twice"),
+            ));
+        }
+        assigned.push(discriminant);
+        next = discriminant + 1;
+    }
+    Ok(assigned)
+}
+
+fn parse_field_attrs(attrs: &[Attribute]) -> syn::Result<FieldAttrs> {
+    let mut parsed = FieldAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("nanobit")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                parsed.tag = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("since") {
+                parsed.since = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                parsed.default = true;
+                Ok(())
+            } else if meta.path.is_ident("skip") {
+                parsed.skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown nanobit field attribute"))
+            }
+        })?;
+    }
+    Ok(parsed)
+}
+
+// How a field is written in a packed or nullable struct
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    // A bit in the bitmap
+    Bool,
+    // A presence bit, then the value if present
+    Option,
+    // Written as usual
+    Value,
+}
+
+// Recognize `bool` and `Option<T>` by their spelling, as serde does
+fn field_kind(ty: &Type) -> FieldKind {
+    let Type::Path(path) = ty else {
+        return FieldKind::Value;
+    };
+    match path.path.segments.last() {
+        Some(segment) if path.qself.is_none() && segment.ident == "bool" => FieldKind::Bool,
+        Some(segment) if segment.ident == "Option" && !segment.arguments.is_empty() => FieldKind::Option,
+        _ => FieldKind::Value,
+    }
+}
+
+// A field together with its parsed options
+struct FieldInfo {
+    member: Member,
+    binding: Ident,
+    attrs: FieldAttrs,
+    kind: FieldKind,
+}
+
+fn collect_fields(fields: &Fields) -> syn::Result<Vec<FieldInfo>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(index)),
+            };
+            Ok(FieldInfo {
+                member,
+                binding: format_ident!("__field{}", index),
+                attrs: parse_field_attrs(&field.attrs)?,
+                kind: field_kind(&field.ty),
+            })
+        })
+        .collect()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let container = parse_container_attrs(&input.attrs)?;
+    let name = &input.ident;
+
+    let (encode_body, decode_body) = match &input.data {
+        Data::Struct(data) => {
+            let fields = collect_fields(&data.fields)?;
+            let tagged = container.tagged || fields.iter().any(|f| f.attrs.tag.is_some());
+            if tagged && container.version.is_some() {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "nanobit: `tagged` and `version` cannot be combined",
+                ));
+            }
+            if container.packed && (tagged || container.version.is_some()) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "nanobit: `packed` cannot be combined with `tagged` or `version`",
+                ));
+            }
+            if container.nullable && (container.packed || tagged || container.version.is_some()) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "nanobit: `nullable` cannot be combined with `packed`, `tagged` or `version`",
+                ));
+            }
+            if container.nullable && !matches!(data.fields, Fields::Named(_)) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "nanobit: `nullable` requires a struct with named fields",
+                ));
+            }
+            if tagged && !matches!(data.fields, Fields::Named(_)) {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "nanobit: tagged fields require a struct with named fields",
+                ));
+            }
+
+            let self_fields: Vec<_> = fields.iter().map(|f| f.member.clone()).collect();
+            let bindings: Vec<_> = fields.iter().map(|f| f.binding.clone()).collect();
+            let destructure = quote! { let #name { #(#self_fields: #bindings),* } = self; };
+            let construct = construct(quote!(#name), &data.fields, &fields);
+
+            let (encode, decode) = if container.packed || container.nullable {
+                let nullable = container.nullable;
+                (encode_packed(&fields, nullable), decode_packed(&fields, nullable, construct))
+            } else if tagged {
+                (encode_tagged(&fields), decode_tagged(&fields, construct))
+            } else if let Some(version) = container.version {
+                (
+                    encode_versioned(version, &fields),
+                    decode_versioned(version, &fields, construct),
+                )
+            } else {
+                (
+                    encode_plain(&data.fields, &fields),
+                    decode_plain(&data.fields, &fields, construct),
+                )
+            };
+            (quote! { #destructure #encode }, decode)
+        }
+        Data::Enum(data) => {
+            if container.version.is_some() || container.packed || container.nullable {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    "nanobit: `version`, `packed` and `nullable` apply to structs only",
+                ));
+            }
+
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| Ok((variant, parse_variant_attrs(&variant.attrs)?)))
+                .collect::<syn::Result<Vec<_>>>()?;
+            let discriminants = discriminants(&variants)?;
+            let mut other = None;
+            for (variant, _) in variants.iter().filter(|(_, attrs)| attrs.other) {
+                if other.is_some() || !matches!(variant.fields, Fields::Unit) {
+                    return Err(syn::Error::new_spanned(
+                        variant,
+                        "nanobit: `other` applies to a single unit variant",
+                    ));
+                }
+                other = Some(&variant.ident);
+            }
+
+            let mut encode_arms = Vec::new();
+            let mut decode_arms = Vec::new();
+            for ((variant, _), discriminant) in variants.iter().zip(discriminants) {
+                let ident = &variant.ident;
+                let fields = collect_fields(&variant.fields)?;
+                let members: Vec<_> = fields.iter().map(|f| f.member.clone()).collect();
+                let bindings: Vec<_> = fields.iter().map(|f| f.binding.clone()).collect();
+                let encode = encode_plain(&variant.fields, &fields);
+                let construct = construct(quote!(#name::#ident), &variant.fields, &fields);
+                let decode = decode_plain(&variant.fields, &fields, construct);
+
+                // Tagged enums write the payload's length after the discriminant
+                let encode = if container.tagged {
+                    quote! {
+                        let start = buf.len();
+                        #encode
+                        buf.insert_varint(start, (buf.len() - start) as u64)?;
+                    }
+                } else {
+                    encode
+                };
+                encode_arms.push(quote! {
+                    #name::#ident { #(#members: #bindings),* } => {
+                        buf.write_varint(#discriminant)?;
+                        #encode
+                    }
+                });
+                decode_arms.push(quote! { #discriminant => { #decode } });
+            }
+
+            let unknown = match other {
+                Some(other) => quote! { _ => Ok(#name::#other), },
+                None => quote! { index => Err(::nanobit::encode::unknown_variant(index)), },
+            };
+            let encode = quote! {
+                match self {
+                    #(#encode_arms)*
+                }
+            };
+            // The payload of a tagged enum is read from its own slice, so the
+            // payload of an unknown variant is skipped
+            let decode = if container.tagged {
+                quote! {
+                    let discriminant = reader.read_varint()?;
+                    let len = reader.read_varint()? as usize;
+                    let reader = &mut ::nanobit::buffer::ReadBuffer::new(reader.read_bytes(len)?);
+                    match discriminant {
+                        #(#decode_arms)*
+                        #unknown
+                    }
+                }
+            } else {
+                quote! {
+                    match reader.read_varint()? {
+                        #(#decode_arms)*
+                        #unknown
+                    }
+                }
+            };
+            (encode, decode)
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "nanobit: unions are not supported",
+            ))
+        }
+    };
+
+    // Encode: every type parameter must itself be encodable
+    let mut encode_generics = input.generics.clone();
+    for param in encode_generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::nanobit::encode::Encode));
+    }
+    let (encode_impl, _, encode_where) = encode_generics.split_for_impl();
+
+    // Decode: add 'de, outliving every borrowed lifetime of the type
+    let mut decode_generics = input.generics.clone();
+    let lifetimes: Vec<_> = input.generics.lifetimes().map(|l| l.lifetime.clone()).collect();
+    for param in decode_generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::nanobit::encode::Decode<'de>));
+    }
+    decode_generics.params.insert(0, GenericParam::Lifetime(parse_quote!('de)));
+    {
+        let where_clause = decode_generics.make_where_clause();
+        for lifetime in &lifetimes {
+            where_clause.predicates.push(parse_quote!('de: #lifetime));
+        }
+    }
+    let (decode_impl, _, decode_where) = decode_generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #encode_impl ::nanobit::encode::Encode for #name #ty_generics #encode_where {
+            #[allow(unused_variables)]
+            fn encode(&self, buf: &mut ::nanobit::buffer::WriteBuffer) -> ::nanobit::Result<()> {
+                #encode_body
+                Ok(())
+            }
+        }
+
+        impl #decode_impl ::nanobit::encode::Decode<'de> for #name #ty_generics #decode_where {
+            #[allow(unused_variables)]
+            fn decode(reader: &mut ::nanobit::buffer::ReadBuffer<'de>) -> ::nanobit::Result<Self> {
+                #decode_body
+            }
+        }
+    })
+}
+
+// Build `Path { a: __field0, .. }` / `Path(__field0, ..)` / `Path`
+fn construct(path: TokenStream2, shape: &Fields, fields: &[FieldInfo]) -> TokenStream2 {
+    let bindings = fields.iter().map(|f| &f.binding);
+    match shape {
+        Fields::Named(_) => {
+            let members = fields.iter().map(|f| &f.member);
+            quote! { #path { #(#members: #bindings),* } }
+        }
+        Fields::Unnamed(_) => quote! { #path(#(#bindings),*) },
+        Fields::Unit => path,
+    }
+}
+
+fn encoded_fields(fields: &[FieldInfo]) -> impl Iterator<Item = &FieldInfo> {
+    fields.iter().filter(|f| !f.attrs.skip)
+}
+
+// Same layout as the serde serializer: structs and tuples carry their
+// field count, newtypes are transparent and unit types are empty
+fn encode_plain(shape: &Fields, fields: &[FieldInfo]) -> TokenStream2 {
+    let encoded: Vec<_> = encoded_fields(fields).map(|f| &f.binding).collect();
+    let count = encoded.len() as u64;
+    match shape {
+        Fields::Unnamed(_) if fields.len() == 1 && count == 1 => {
+            quote! { ::nanobit::encode::Encode::encode(#(#encoded)*, buf)?; }
+        }
+        Fields::Unit => quote! {},
+        _ => quote! {
+            buf.write_varint(#count)?;
+            #(::nanobit::encode::Encode::encode(#encoded, buf)?;)*
+        },
+    }
+}
+
+fn decode_plain(shape: &Fields, fields: &[FieldInfo], construct: TokenStream2) -> TokenStream2 {
+    let count = encoded_fields(fields).count() as u64;
+    let reads = fields.iter().map(|f| {
+        let binding = &f.binding;
+        if f.attrs.skip {
+            quote! { let #binding = ::core::default::Default::default(); }
+        } else {
+            quote! { let #binding = ::nanobit::encode::Decode::decode(reader)?; }
+        }
+    });
+    let check_count = match shape {
+        Fields::Unnamed(_) if fields.len() == 1 && count == 1 => quote! {},
+        Fields::Unit => quote! {},
+        _ => quote! {
+            let len = reader.read_varint()?;
+            if len != #count {
+                return Err(::nanobit::encode::field_count_mismatch(#count, len));
+            }
+        },
+    };
+    quote! {
+        #check_count
+        #(#reads)*
+        Ok(#construct)
+    }
+}
+
+// Field count, then one bit per `bool` field and per `Option` field's
+// presence (least significant bit first), then the remaining values in order.
+// Nullable structs give every field a bit, leaving those of other fields zero
+fn encode_packed(fields: &[FieldInfo], nullable: bool) -> TokenStream2 {
+    let count = encoded_fields(fields).count() as u64;
+    let flagged = flag_bits(fields, nullable);
+    let bitmap_len = bitmap_len(fields, nullable);
+    let set_bits = flagged.iter().map(|(f, bit)| {
+        let binding = &f.binding;
+        let (byte, mask) = (bit / 8, 1u8 << (bit % 8));
+        let set = if f.kind == FieldKind::Bool {
+            quote! { *#binding }
+        } else {
+            quote! { #binding.is_some() }
+        };
+        quote! {
+            if #set {
+                bits[#byte] |= #mask;
+            }
+        }
+    });
+    let values = encoded_fields(fields).filter(|f| f.kind != FieldKind::Bool).map(|f| {
+        let binding = &f.binding;
+        if f.kind == FieldKind::Option {
+            quote! {
+                if let ::core::option::Option::Some(value) = #binding {
+                    ::nanobit::encode::Encode::encode(value, buf)?;
+                }
+            }
+        } else {
+            quote! { ::nanobit::encode::Encode::encode(#binding, buf)?; }
+        }
+    });
+    let bitmap = (bitmap_len > 0).then(|| {
+        quote! {
+            let mut bits = [0u8; #bitmap_len];
+            #(#set_bits)*
+            buf.write_bytes(&bits)?;
+        }
+    });
+    quote! {
+        buf.write_varint(#count)?;
+        #bitmap
+        #(#values)*
+    }
+}
+
+fn decode_packed(fields: &[FieldInfo], nullable: bool, construct: TokenStream2) -> TokenStream2 {
+    let count = encoded_fields(fields).count() as u64;
+    let flagged = flag_bits(fields, nullable);
+    let bitmap_len = bitmap_len(fields, nullable);
+    let reads = fields.iter().map(|f| {
+        let binding = &f.binding;
+        let bit = flagged.iter().find(|(flag, _)| flag.binding == f.binding).map(|(_, bit)| *bit);
+        let is_set = bit.map(|bit| {
+            let (byte, mask) = (bit / 8, 1u8 << (bit % 8));
+            quote! { bits[#byte] & #mask != 0 }
+        });
+        match (f.attrs.skip, f.kind) {
+            (true, _) => quote! { let #binding = ::core::default::Default::default(); },
+            (false, FieldKind::Bool) => quote! { let #binding = #is_set; },
+            (false, FieldKind::Option) => quote! {
+                let #binding = if #is_set {
+                    ::core::option::Option::Some(::nanobit::encode::Decode::decode(reader)?)
+                } else {
+                    ::core::option::Option::None
+                };
+            },
+            (false, FieldKind::Value) => quote! { let #binding = ::nanobit::encode::Decode::decode(reader)?; },
+        }
+    });
+    quote! {
+        let len = reader.read_varint()?;
+        if len != #count {
+            return Err(::nanobit::encode::field_count_mismatch(#count, len));
+        }
+        let bits = reader.read_bytes(#bitmap_len)?;
+        #(#reads)*
+        Ok(#construct)
+    }
+}
+
+// Bit index of each encoded `bool` and `Option` field: its index among
+// them, or among all encoded fields when nullable
+fn flag_bits(fields: &[FieldInfo], nullable: bool) -> Vec<(&FieldInfo, usize)> {
+    let flagged = encoded_fields(fields).enumerate().filter(|(_, f)| f.kind != FieldKind::Value);
+    flagged
+        .enumerate()
+        .map(|(bit, (index, f))| (f, if nullable { index } else { bit }))
+        .collect()
+}
+
+fn bitmap_len(fields: &[FieldInfo], nullable: bool) -> usize {
+    if nullable {
+        encoded_fields(fields).count().div_ceil(8)
+    } else {
+        flag_bits(fields, false).len().div_ceil(8)
+    }
+}
+
+fn encode_versioned(version: u64, fields: &[FieldInfo]) -> TokenStream2 {
+    let encoded = encoded_fields(fields).map(|f| &f.binding);
+    quote! {
+        buf.write_varint(#version)?;
+        #(::nanobit::encode::Encode::encode(#encoded, buf)?;)*
+    }
+}
+
+fn decode_versioned(version: u64, fields: &[FieldInfo], construct: TokenStream2) -> TokenStream2 {
+    let reads = fields.iter().map(|f| {
+        let binding = &f.binding;
+        let since = f.attrs.since.unwrap_or(0);
+        if f.attrs.skip {
+            quote! { let #binding = ::core::default::Default::default(); }
+        } else if since == 0 {
+            quote! { let #binding = ::nanobit::encode::Decode::decode(reader)?; }
+        } else {
+            quote! {
+                let #binding = if version >= #since {
+                    ::nanobit::encode::Decode::decode(reader)?
+                } else {
+                    ::core::default::Default::default()
+                };
+            }
+        }
+    });
+    quote! {
+        let version = reader.read_varint()?;
+        if version > #version {
+            return Err(::nanobit::encode::version_too_new(version, #version));
+        }
+        #(#reads)*
+        Ok(#construct)
+    }
+}
+
+// Tag of each encoded field: explicit, or its index among encoded fields
+fn field_tags(fields: &[FieldInfo]) -> Vec<(&FieldInfo, u64)> {
+    encoded_fields(fields)
+        .enumerate()
+        .map(|(index, f)| (f, f.attrs.tag.unwrap_or(index as u64)))
+        .collect()
+}
+
+fn encode_tagged(fields: &[FieldInfo]) -> TokenStream2 {
+    let tagged = field_tags(fields);
+    let count = tagged.len() as u64;
+    let writes = tagged.iter().map(|(f, tag)| {
+        let binding = &f.binding;
+        quote! { ::nanobit::encode::encode_tagged_field(buf, #tag, #binding)?; }
+    });
+    quote! {
+        buf.write_varint(#count)?;
+        #(#writes)*
+    }
+}
+
+fn decode_tagged(fields: &[FieldInfo], construct: TokenStream2) -> TokenStream2 {
+    let tagged = field_tags(fields);
+    let slots = tagged.iter().map(|(f, _)| {
+        let binding = &f.binding;
+        quote! { let mut #binding = ::core::option::Option::None; }
+    });
+    let arms = tagged.iter().map(|(f, tag)| {
+        let binding = &f.binding;
+        quote! {
+            #tag => #binding = ::core::option::Option::Some(
+                ::nanobit::encode::decode_tagged_field(reader, len)?
+            ),
+        }
+    });
+    let finish = fields.iter().map(|f| {
+        let binding = &f.binding;
+        if f.attrs.skip {
+            quote! { let #binding = ::core::default::Default::default(); }
+        } else if f.attrs.default {
+            quote! { let #binding = #binding.unwrap_or_default(); }
+        } else {
+            let name = match &f.member {
+                Member::Named(ident) => ident.to_string(),
+                Member::Unnamed(index) => index.index.to_string(),
+            };
+            quote! {
+                let #binding = match #binding {
+                    ::core::option::Option::Some(value) => value,
+                    ::core::option::Option::None => {
+                        return Err(::nanobit::encode::missing_field(#name))
+                    }
+                };
+            }
+        }
+    });
+    quote! {
+        #(#slots)*
+        let count = reader.read_varint()?;
+        for _ in 0..count {
+            let tag = reader.read_varint()?;
+            let len = reader.read_varint()? as usize;
+            match tag {
+                #(#arms)*
+                // Field from a newer schema: skip its value
+                _ => reader.skip(len)?,
+            }
+        }
+        #(#finish)*
+        Ok(#construct)
+    }
+}
+
+/// Derive `Archive` and a view type reading the struct in place
+///
+/// The view is named after the struct with a `View` suffix and has one
+/// accessor per field, with the field's name and visibility.
+#[proc_macro_derive(Archive)]
+pub fn derive_archive(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_archive(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_archive(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let view = format_ident!("{}View", name);
+    let fields = match &input.data {
+        Data::Struct(data) if matches!(data.fields, Fields::Named(_)) => &data.fields,
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "nanobit: `Archive` requires a struct with named fields",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "nanobit: `Archive` does not support generic structs",
+        ));
+    }
+
+    // Offset of each field within the struct: the sizes of the fields
+    // before it
+    let mut offset = quote! { 0 };
+    let mut offsets = Vec::with_capacity(fields.len());
+    for field in fields {
+        let ty = &field.ty;
+        offsets.push(offset.clone());
+        offset = quote! { #offset + <#ty as ::nanobit::archive::Archive>::SIZE };
+    }
+    let size = offset;
+
+    let archives = fields.iter().zip(&offsets).map(|(field, offset)| {
+        let ident = &field.ident;
+        quote! { ::nanobit::archive::Archive::archive(&self.#ident, writer, at + #offset); }
+    });
+    let verifies = fields.iter().zip(&offsets).map(|(field, offset)| {
+        let ty = &field.ty;
+        quote! { <#ty as ::nanobit::archive::Archive>::verify(bytes, at + #offset)?; }
+    });
+    let accessors = fields.iter().zip(&offsets).map(|(field, offset)| {
+        let (ident, ty, vis) = (&field.ident, &field.ty, &field.vis);
+        let doc = format!("The `{}` field", ident.as_ref().map(Ident::to_string).unwrap_or_default());
+        quote! {
+            #[doc = #doc]
+            #[inline]
+            #vis fn #ident(&self) -> <#ty as ::nanobit::archive::Archive>::View<'a> {
+                <#ty as ::nanobit::archive::Archive>::view(self.bytes, self.at + #offset)
+            }
+        }
+    });
+    let doc = format!("[`{name}`] read in place from an archive");
+
+    Ok(quote! {
+        #[allow(clippy::identity_op)]
+        impl ::nanobit::archive::Archive for #name {
+            const SIZE: usize = #size;
+            type View<'a> = #view<'a>;
+
+            fn archive(&self, writer: &mut ::nanobit::archive::ArchiveWriter, at: usize) {
+                #(#archives)*
+            }
+
+            fn verify(bytes: &[u8], at: usize) -> ::nanobit::Result<()> {
+                ::nanobit::archive::check_bounds(bytes, at, <Self as ::nanobit::archive::Archive>::SIZE)?;
+                #(#verifies)*
+                Ok(())
+            }
+
+            fn view(bytes: &[u8], at: usize) -> #view<'_> {
+                #view { bytes, at }
+            }
+        }
+
+        #[doc = #doc]
+        #[derive(Clone, Copy)]
+        #vis struct #view<'a> {
+            bytes: &'a [u8],
+            at: usize,
+        }
+
+        #[allow(clippy::identity_op)]
+        impl<'a> #view<'a> {
+            #(#accessors)*
+        }
+    })
+}
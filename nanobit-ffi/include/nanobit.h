@@ -0,0 +1,65 @@
+/*
+ * C API for NanoBit payloads
+ *
+ * Every function returns a NanobitStatus. On failure, nanobit_last_error()
+ * describes the error on the calling thread. Buffers filled by the library
+ * must be released with nanobit_buffer_free().
+ *
+ * Decompressed payloads are limited to 64 MiB unless changed with
+ * nanobit_set_max_decompressed_len(). A panic inside the library returns
+ * NANOBIT_PANIC instead of unwinding into the caller.
+ */
+
+#ifndef NANOBIT_H
+#define NANOBIT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum NanobitStatus {
+    NANOBIT_OK = 0,
+    NANOBIT_NULL_POINTER = 1,
+    NANOBIT_INVALID_PAYLOAD = 2,
+    NANOBIT_CHECKSUM_MISMATCH = 3,
+    NANOBIT_COMPRESSION = 4,
+    NANOBIT_NOT_SELF_DESCRIBING = 5,
+    NANOBIT_LIMIT_EXCEEDED = 6,
+    NANOBIT_PANIC = 7,
+} NanobitStatus;
+
+/* Bytes owned by the library; data is NULL for an empty buffer */
+typedef struct NanobitBuffer {
+    uint8_t *data;
+    size_t len;
+} NanobitBuffer;
+
+/* Compress a payload */
+NanobitStatus nanobit_compress(const uint8_t *data, size_t len, NanobitBuffer *out);
+
+/* Decompress a compressed payload */
+NanobitStatus nanobit_decompress(const uint8_t *data, size_t len, NanobitBuffer *out);
+
+/* Transcode a self-describing payload, compressed or not, to UTF-8 JSON */
+NanobitStatus nanobit_to_json(const uint8_t *data, size_t len, NanobitBuffer *out);
+
+/* Check the header, compression and checksum of a payload */
+NanobitStatus nanobit_validate(const uint8_t *data, size_t len);
+
+/* Limit the size of decompressed payloads, for calls on any thread */
+void nanobit_set_max_decompressed_len(size_t max_len);
+
+/* Release a buffer filled by the library; it is left empty */
+void nanobit_buffer_free(NanobitBuffer *buffer);
+
+/* Describe the last error on this thread, or NULL if no call has failed */
+const char *nanobit_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NANOBIT_H */
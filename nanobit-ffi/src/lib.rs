@@ -0,0 +1,358 @@
+//! C API for NanoBit payloads
+//!
+//! Lets programs in other languages check, decompress and inspect payloads
+//! written by Rust services. The declarations are in `include/nanobit.h`.
+//!
+//! Every function returns a [`NanobitStatus`]. On failure, a description of
+//! the error is available from [`nanobit_last_error`] on the same thread.
+//! Output buffers are allocated by the library and must be released with
+//! [`nanobit_buffer_free`].
+//!
+//! Input is treated as untrusted: decompressed payloads are limited to
+//! [`DEFAULT_MAX_DECOMPRESSED_LEN`] bytes unless
+//! [`nanobit_set_max_decompressed_len`] says otherwise, and a panic inside
+//! the library is reported as [`NanobitStatus::Panic`] instead of
+//! unwinding into the caller.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nanobit::header::{Header, HeaderFlags};
+use nanobit::{Error, Value};
+
+/// Result of an API call
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NanobitStatus {
+    /// The call succeeded
+    Ok = 0,
+    /// A required pointer was null
+    NullPointer = 1,
+    /// The input is not a valid payload
+    InvalidPayload = 2,
+    /// The payload's checksum does not match
+    ChecksumMismatch = 3,
+    /// Compression or decompression failed
+    Compression = 4,
+    /// The payload cannot be transcoded because it is not self-describing
+    NotSelfDescribing = 5,
+    /// The payload decompresses to more than the configured limit
+    LimitExceeded = 6,
+    /// The library panicked; the call had no effect
+    Panic = 7,
+}
+
+/// Largest decompressed payload accepted unless configured otherwise
+pub const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
+
+static MAX_DECOMPRESSED_LEN: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DECOMPRESSED_LEN);
+
+/// Bytes owned by the library
+#[repr(C)]
+#[derive(Debug)]
+pub struct NanobitBuffer {
+    /// Start of the bytes, or null for an empty buffer
+    pub data: *mut u8,
+    /// Number of bytes
+    pub len: usize,
+}
+
+impl NanobitBuffer {
+    const EMPTY: Self = Self {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        let data = Box::into_raw(bytes.into_boxed_slice()).cast::<u8>();
+        Self { data, len }
+    }
+}
+
+thread_local! {
+    // Description of the last error on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(status: NanobitStatus, message: impl ToString) -> NanobitStatus {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    status
+}
+
+fn fail_with(err: Error) -> NanobitStatus {
+    let status = match err {
+        Error::ChecksumMismatch { .. } => NanobitStatus::ChecksumMismatch,
+        Error::LimitExceeded { .. } => NanobitStatus::LimitExceeded,
+        Error::Compression(_) => NanobitStatus::Compression,
+        _ => NanobitStatus::InvalidPayload,
+    };
+    fail(status, err)
+}
+
+// Run the body of an API call, turning a panic into a status
+fn guard(call: impl FnOnce() -> NanobitStatus) -> NanobitStatus {
+    match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(status) => status,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            fail(NanobitStatus::Panic, format!("Internal error: {message}"))
+        }
+    }
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    nanobit::decompress_with_limit(data, MAX_DECOMPRESSED_LEN.load(Ordering::Relaxed))
+}
+
+// Borrow the input and output of a call, checking for null pointers
+unsafe fn io<'a>(
+    data: *const u8,
+    len: usize,
+    out: *mut NanobitBuffer,
+) -> Result<(&'a [u8], &'a mut NanobitBuffer), NanobitStatus> {
+    if (data.is_null() && len > 0) || out.is_null() {
+        return Err(fail(NanobitStatus::NullPointer, "Null pointer argument"));
+    }
+    // SAFETY: the caller guarantees `data` points to `len` readable bytes
+    // and `out` to a writable buffer
+    let input = if len == 0 {
+        &[][..]
+    } else {
+        unsafe { std::slice::from_raw_parts(data, len) }
+    };
+    let out = unsafe { &mut *out };
+    *out = NanobitBuffer::EMPTY;
+    Ok((input, out))
+}
+
+// The uncompressed payload in `data`
+fn payload(data: &[u8]) -> Result<std::borrow::Cow<'_, [u8]>, Error> {
+    let (header, _) = Header::parse(data)?;
+    if header.flags.contains(HeaderFlags::COMPRESSED) {
+        return decompress(data).map(Into::into);
+    }
+    Ok(data.into())
+}
+
+/// Compress a payload
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to a writable
+/// [`NanobitBuffer`].
+#[no_mangle]
+pub unsafe extern "C" fn nanobit_compress(data: *const u8, len: usize, out: *mut NanobitBuffer) -> NanobitStatus {
+    guard(|| {
+        let (input, out) = match unsafe { io(data, len, out) } {
+            Ok(io) => io,
+            Err(status) => return status,
+        };
+        match nanobit::compress_default(input) {
+            Ok(bytes) => {
+                *out = NanobitBuffer::from_vec(bytes);
+                NanobitStatus::Ok
+            }
+            Err(err) => fail(NanobitStatus::Compression, err),
+        }
+    })
+}
+
+/// Decompress a payload produced by [`nanobit_compress`] or a Rust service
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to a writable
+/// [`NanobitBuffer`].
+#[no_mangle]
+pub unsafe extern "C" fn nanobit_decompress(data: *const u8, len: usize, out: *mut NanobitBuffer) -> NanobitStatus {
+    guard(|| {
+        let (input, out) = match unsafe { io(data, len, out) } {
+            Ok(io) => io,
+            Err(status) => return status,
+        };
+        match decompress(input) {
+            Ok(bytes) => {
+                *out = NanobitBuffer::from_vec(bytes);
+                NanobitStatus::Ok
+            }
+            Err(err @ Error::LimitExceeded { .. }) => fail_with(err),
+            Err(err) => fail(NanobitStatus::Compression, err),
+        }
+    })
+}
+
+/// Transcode a self-describing payload, compressed or not, to UTF-8 JSON
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to a writable
+/// [`NanobitBuffer`].
+#[no_mangle]
+pub unsafe extern "C" fn nanobit_to_json(data: *const u8, len: usize, out: *mut NanobitBuffer) -> NanobitStatus {
+    guard(|| {
+        let (input, out) = match unsafe { io(data, len, out) } {
+            Ok(io) => io,
+            Err(status) => return status,
+        };
+        let payload = match payload(input) {
+            Ok(payload) => payload,
+            Err(err) => return fail_with(err),
+        };
+        match Header::parse(&payload) {
+            Ok((header, _)) if !header.flags.contains(HeaderFlags::SELF_DESCRIBING) => {
+                return fail(NanobitStatus::NotSelfDescribing, "Payload is not self-describing");
+            }
+            Ok(_) => {}
+            Err(err) => return fail_with(err),
+        }
+        match nanobit::json::to_json(&payload) {
+            Ok(json) => {
+                *out = NanobitBuffer::from_vec(json.to_string().into_bytes());
+                NanobitStatus::Ok
+            }
+            Err(err) => fail_with(err),
+        }
+    })
+}
+
+/// Check that a payload is well formed
+///
+/// Checks the header, the decompressed contents of compressed payloads and
+/// any checksum trailer. Self-describing payloads are also checked value by
+/// value; other payloads need their Rust type for that.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn nanobit_validate(data: *const u8, len: usize) -> NanobitStatus {
+    guard(|| {
+        let mut out = NanobitBuffer::EMPTY;
+        let (input, _) = match unsafe { io(data, len, &mut out) } {
+            Ok(io) => io,
+            Err(status) => return status,
+        };
+        let result = payload(input).and_then(|payload| {
+            let (header, _) = Header::parse(&payload)?;
+            if header.flags.contains(HeaderFlags::CHECKSUM_CRC32C) || header.flags.contains(HeaderFlags::CHECKSUM_XXH64) {
+                nanobit::verify_checksum(&payload)?;
+            }
+            if header.flags.contains(HeaderFlags::SELF_DESCRIBING) {
+                nanobit::from_bytes::<Value>(&payload)?;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => NanobitStatus::Ok,
+            Err(err) => fail_with(err),
+        }
+    })
+}
+
+/// Limit the size of decompressed payloads, for calls on any thread
+///
+/// The default is [`DEFAULT_MAX_DECOMPRESSED_LEN`]. Payloads that would
+/// decompress to more than `max_len` bytes fail with
+/// [`NanobitStatus::LimitExceeded`].
+#[no_mangle]
+pub extern "C" fn nanobit_set_max_decompressed_len(max_len: usize) {
+    MAX_DECOMPRESSED_LEN.store(max_len, Ordering::Relaxed);
+}
+
+/// Release a buffer returned by this library
+///
+/// # Safety
+///
+/// `buffer` must be null or point to a buffer filled by this library that
+/// has not been freed. It is left empty.
+#[no_mangle]
+pub unsafe extern "C" fn nanobit_buffer_free(buffer: *mut NanobitBuffer) {
+    if buffer.is_null() {
+        return;
+    }
+    // SAFETY: the caller guarantees the buffer came from `from_vec`
+    let buffer = unsafe { &mut *buffer };
+    if !buffer.data.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)) });
+    }
+    *buffer = NanobitBuffer::EMPTY;
+}
+
+/// Describe the last error on the calling thread
+///
+/// Returns a NUL-terminated string valid until the next failing call on the
+/// same thread, or null if no call has failed.
+#[no_mangle]
+pub extern "C" fn nanobit_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nanobit::Config;
+    use std::ffi::CStr;
+
+    fn bytes(buffer: &NanobitBuffer) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(buffer.data, buffer.len) }
+    }
+
+    #[test]
+    fn test_c_api() {
+        let value = vec![("a".to_string(), 1u8); 50];
+        let payload = nanobit::to_bytes_with_config(&value, Config::new().with_self_describing(true)).unwrap();
+        let mut compressed = NanobitBuffer::EMPTY;
+        let mut json = NanobitBuffer::EMPTY;
+        unsafe {
+            assert_eq!(nanobit_compress(payload.as_ptr(), payload.len(), &mut compressed), NanobitStatus::Ok);
+            assert_eq!(nanobit_validate(compressed.data, compressed.len), NanobitStatus::Ok);
+            assert_eq!(nanobit_to_json(compressed.data, compressed.len, &mut json), NanobitStatus::Ok);
+            nanobit_buffer_free(&mut compressed);
+        }
+        let expected = serde_json::to_string(&value).unwrap();
+        assert_eq!(bytes(&json), expected.as_bytes());
+        unsafe { nanobit_buffer_free(&mut json) };
+        assert!(json.data.is_null());
+
+        // Failures set a status and a message
+        let plain = nanobit::to_bytes(&value).unwrap();
+        unsafe {
+            assert_eq!(nanobit_validate(plain.as_ptr(), plain.len()), NanobitStatus::Ok);
+            assert_eq!(nanobit_to_json(plain.as_ptr(), plain.len(), &mut json), NanobitStatus::NotSelfDescribing);
+            assert_eq!(nanobit_validate(payload.as_ptr(), payload.len() - 1), NanobitStatus::InvalidPayload);
+            assert!(!CStr::from_ptr(nanobit_last_error()).to_bytes().is_empty());
+            assert_eq!(nanobit_validate(ptr::null(), 1), NanobitStatus::NullPointer);
+        }
+    }
+
+    #[test]
+    fn test_untrusted_input() {
+        let bomb = nanobit::compress_default(&nanobit::to_bytes(&vec![0u8; 1 << 20]).unwrap()).unwrap();
+        let mut out = NanobitBuffer::EMPTY;
+        nanobit_set_max_decompressed_len(1 << 16);
+        unsafe {
+            assert_eq!(nanobit_decompress(bomb.as_ptr(), bomb.len(), &mut out), NanobitStatus::LimitExceeded);
+            assert_eq!(nanobit_validate(bomb.as_ptr(), bomb.len()), NanobitStatus::LimitExceeded);
+        }
+        assert!(out.data.is_null());
+        nanobit_set_max_decompressed_len(DEFAULT_MAX_DECOMPRESSED_LEN);
+        unsafe {
+            assert_eq!(nanobit_decompress(bomb.as_ptr(), bomb.len(), &mut out), NanobitStatus::Ok);
+            nanobit_buffer_free(&mut out);
+        }
+
+        // A panic becomes a status instead of unwinding into C
+        assert_eq!(guard(|| panic!("boom")), NanobitStatus::Panic);
+        let message = unsafe { CStr::from_ptr(nanobit_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "Internal error: boom");
+    }
+}
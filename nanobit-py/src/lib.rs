@@ -0,0 +1,204 @@
+//! Python bindings for NanoBit payloads
+//!
+//! Built with maturin into the `nanobit` Python module:
+//!
+//! ```python
+//! import nanobit
+//!
+//! payload = nanobit.dumps({"id": 7, "tags": ["new"]})
+//! assert nanobit.loads(payload) == {"id": 7, "tags": ["new"]}
+//!
+//! # Plain payloads need the shape of the Rust type that wrote them
+//! event = {
+//!     "id": "u64",
+//!     "kind": nanobit.enum({"Open": None, "Move": ("i32", "i32")}),
+//!     "at": nanobit.option("f64"),
+//! }
+//! with open("events.nb", "rb") as f:
+//!     events = nanobit.loads(f.read(), [event])
+//! ```
+//!
+//! Values map to Python as they do to JSON, except that byte strings become
+//! `bytes`. Unit variants become their name and other variants a
+//! single-entry dict keyed by name.
+
+// The code generated by `#[pyfunction]` converts `PyErr` into itself
+#![allow(clippy::useless_conversion)]
+
+mod schema;
+
+use nanobit::header::{Header, HeaderFlags};
+use nanobit::{Config, Error, Value};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+
+use schema::{Payload, PySchema, Schema, Variants};
+
+/// Encode a Python value as a self-describing payload
+#[pyfunction]
+fn dumps<'py>(py: Python<'py>, value: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyBytes>> {
+    let value = py_to_value(value)?;
+    let bytes = nanobit::to_bytes_with_config(&value, Config::new().with_self_describing(true)).map_err(to_py_err)?;
+    Ok(PyBytes::new_bound(py, &bytes))
+}
+
+/// Decode a payload, compressed or not
+///
+/// Self-describing payloads decode on their own; plain payloads need the
+/// schema of the type that wrote them.
+#[pyfunction]
+#[pyo3(signature = (data, schema = None))]
+fn loads(py: Python<'_>, data: &[u8], schema: Option<&Bound<'_, PyAny>>) -> PyResult<PyObject> {
+    let schema = schema.map(Schema::from_py).transpose()?;
+    let value = decode(data, schema.as_ref()).map_err(to_py_err)?;
+    value_to_py(py, value)
+}
+
+/// Schema of an optional value
+#[pyfunction]
+fn option(schema: &Bound<'_, PyAny>) -> PyResult<PySchema> {
+    Ok(PySchema(Schema::Option(Box::new(Schema::from_py(schema)?))))
+}
+
+/// Schema of a map
+#[pyfunction]
+fn map(key: &Bound<'_, PyAny>, value: &Bound<'_, PyAny>) -> PyResult<PySchema> {
+    Ok(PySchema(Schema::Map(Box::new(Schema::from_py(key)?), Box::new(Schema::from_py(value)?))))
+}
+
+/// Schema of an enum from a dict of variant names to payloads, in
+/// declaration order
+///
+/// A payload is `None` for a unit variant, a tuple of schemas for a tuple
+/// variant, a dict for a struct variant and any other schema for a newtype
+/// variant.
+#[pyfunction]
+#[pyo3(name = "enum")]
+fn enum_(variants: &Bound<'_, PyDict>) -> PyResult<PySchema> {
+    let variants = variants
+        .iter()
+        .map(|(name, payload)| Ok((name.extract()?, Payload::from_py(&payload)?)))
+        .collect::<PyResult<_>>()?;
+    Ok(PySchema(Schema::Enum(Variants::new(variants))))
+}
+
+#[pymodule]
+#[pyo3(name = "nanobit")]
+fn nanobit_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySchema>()?;
+    m.add_function(wrap_pyfunction!(dumps, m)?)?;
+    m.add_function(wrap_pyfunction!(loads, m)?)?;
+    m.add_function(wrap_pyfunction!(option, m)?)?;
+    m.add_function(wrap_pyfunction!(map, m)?)?;
+    m.add_function(wrap_pyfunction!(enum_, m)?)?;
+    Ok(())
+}
+
+fn decode(bytes: &[u8], schema: Option<&Schema>) -> nanobit::Result<Value> {
+    let (header, _) = Header::parse(bytes)?;
+    if header.flags.contains(HeaderFlags::COMPRESSED) {
+        return decode(&nanobit::decompress(bytes)?, schema);
+    }
+    match schema {
+        Some(schema) => nanobit::from_bytes_seed(schema, bytes),
+        None if header.flags.contains(HeaderFlags::SELF_DESCRIBING) => nanobit::from_bytes(bytes),
+        None => Err(Error::InvalidFormat("Payload is not self-describing; pass a schema".to_string())),
+    }
+}
+
+fn to_py_err(err: Error) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn py_to_value(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if value.is_none() {
+        return Ok(Value::Null);
+    }
+    // `bool` is a subclass of `int`, so it is checked first
+    if let Ok(b) = value.downcast::<PyBool>() {
+        return Ok(Value::Bool(b.is_true()));
+    }
+    if value.is_instance_of::<PyInt>() {
+        return match value.extract::<i64>() {
+            Ok(i) => Ok(Value::Int(i)),
+            Err(_) => value.extract::<u64>().map(Value::UInt),
+        };
+    }
+    if let Ok(f) = value.downcast::<PyFloat>() {
+        return Ok(Value::Float(f.value()));
+    }
+    if let Ok(s) = value.downcast::<PyString>() {
+        return Ok(Value::Str(s.to_str()?.to_owned()));
+    }
+    if let Ok(b) = value.downcast::<PyBytes>() {
+        return Ok(Value::Bytes(b.as_bytes().to_vec()));
+    }
+    if let Ok(b) = value.downcast::<PyByteArray>() {
+        return Ok(Value::Bytes(b.to_vec()));
+    }
+    if let Ok(list) = value.downcast::<PyList>() {
+        return list.iter().map(|item| py_to_value(&item)).collect::<PyResult<_>>().map(Value::Seq);
+    }
+    if let Ok(tuple) = value.downcast::<PyTuple>() {
+        return tuple.iter().map(|item| py_to_value(&item)).collect::<PyResult<_>>().map(Value::Seq);
+    }
+    if let Ok(dict) = value.downcast::<PyDict>() {
+        return dict
+            .iter()
+            .map(|(k, v)| Ok((py_to_value(&k)?, py_to_value(&v)?)))
+            .collect::<PyResult<_>>()
+            .map(Value::Map);
+    }
+    Err(PyTypeError::new_err(format!(
+        "Cannot encode values of type {}",
+        value.get_type().name()?
+    )))
+}
+
+fn value_to_py(py: Python<'_>, value: Value) -> PyResult<PyObject> {
+    Ok(match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Int(i) => i.into_py(py),
+        Value::UInt(u) => u.into_py(py),
+        Value::Float(f) => f.into_py(py),
+        Value::Char(c) => c.into_py(py),
+        Value::Str(s) => s.into_py(py),
+        Value::Bytes(b) => PyBytes::new_bound(py, &b).into_py(py),
+        Value::Seq(items) => {
+            let items = items.into_iter().map(|item| value_to_py(py, item)).collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, items).into_py(py)
+        }
+        Value::Map(entries) => {
+            let dict = PyDict::new_bound(py);
+            for (k, v) in entries {
+                dict.set_item(key_to_py(py, k)?, value_to_py(py, v)?)?;
+            }
+            dict.into_py(py)
+        }
+        // Externally tagged, as in JSON
+        Value::Variant { index, name, value } => {
+            let name = if name.is_empty() { index.to_string() } else { name };
+            match *value {
+                Value::Null => name.into_py(py),
+                payload => {
+                    let dict = PyDict::new_bound(py);
+                    dict.set_item(name, value_to_py(py, payload)?)?;
+                    dict.into_py(py)
+                }
+            }
+        }
+    })
+}
+
+// Lists are not hashable, so sequence keys become tuples
+fn key_to_py(py: Python<'_>, key: Value) -> PyResult<PyObject> {
+    match key {
+        Value::Seq(items) => {
+            let items = items.into_iter().map(|item| key_to_py(py, item)).collect::<PyResult<Vec<_>>>()?;
+            Ok(PyTuple::new_bound(py, items).into_py(py))
+        }
+        other => value_to_py(py, other),
+    }
+}
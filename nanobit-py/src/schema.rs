@@ -0,0 +1,522 @@
+//! Shapes of payloads written without type tags
+//!
+//! Plain payloads only make sense next to the type that wrote them, so
+//! Python callers describe that type with a [`Schema`] and decoding follows
+//! it the way the type's `Deserialize` implementation would.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+use nanobit::Value;
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyString, PyTuple};
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, IgnoredAny, MapAccess, SeqAccess, VariantAccess, Visitor};
+
+/// Shape of a value
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Schema {
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Char,
+    Str,
+    Bytes,
+    Unit,
+    Option(Box<Schema>),
+    Seq(Box<Schema>),
+    Tuple(Vec<Schema>),
+    Map(Box<Schema>, Box<Schema>),
+    Struct(Fields),
+    Enum(Variants),
+}
+
+/// Named fields of a struct, in declaration order
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Fields {
+    names: &'static [&'static str],
+    types: Vec<Schema>,
+}
+
+/// Variants of an enum, in declaration order
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Variants {
+    names: &'static [&'static str],
+    payloads: Vec<Payload>,
+}
+
+/// Contents of an enum variant
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Payload {
+    Unit,
+    Newtype(Schema),
+    Tuple(Vec<Schema>),
+    Struct(Fields),
+}
+
+impl Fields {
+    pub(crate) fn new(fields: Vec<(String, Schema)>) -> Self {
+        let (names, types) = fields.into_iter().unzip();
+        Self {
+            names: intern(names),
+            types,
+        }
+    }
+}
+
+impl Variants {
+    pub(crate) fn new(variants: Vec<(String, Payload)>) -> Self {
+        let (names, payloads) = variants.into_iter().unzip();
+        Self {
+            names: intern(names),
+            payloads,
+        }
+    }
+}
+
+// serde takes field and variant names as `'static`, so each distinct list
+// is leaked once and shared by every schema that uses it
+fn intern(names: Vec<String>) -> &'static [&'static str] {
+    static INTERNED: OnceLock<Mutex<HashMap<Vec<String>, &'static [&'static str]>>> = OnceLock::new();
+    let mut interned = INTERNED.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    interned.entry(names).or_insert_with_key(|names| {
+        let names: Vec<&'static str> = names.iter().map(|name| &*Box::leak(name.clone().into_boxed_str())).collect();
+        Box::leak(names.into_boxed_slice())
+    })
+}
+
+/// A schema built by `nanobit.option()`, `nanobit.map()` or `nanobit.enum()`
+#[pyclass(name = "Schema", module = "nanobit", frozen)]
+pub(crate) struct PySchema(pub(crate) Schema);
+
+#[pymethods]
+impl PySchema {
+    fn __repr__(&self) -> String {
+        format!("Schema({:?})", self.0)
+    }
+}
+
+impl Schema {
+    /// Read a schema from its Python description
+    ///
+    /// Type names are strings such as `"u32"` or `"str"`, `[T]` is a list,
+    /// a tuple of schemas is a tuple and a dict of schemas is a struct.
+    pub(crate) fn from_py(schema: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(schema) = schema.downcast::<PySchema>() {
+            return Ok(schema.get().0.clone());
+        }
+        if schema.is_none() {
+            return Ok(Schema::Unit);
+        }
+        if let Ok(name) = schema.downcast::<PyString>() {
+            return Self::named(name.to_str()?);
+        }
+        if let Ok(list) = schema.downcast::<PyList>() {
+            if list.len() == 1 {
+                return Ok(Schema::Seq(Box::new(Self::from_py(&list.get_item(0)?)?)));
+            }
+        }
+        if let Ok(tuple) = schema.downcast::<PyTuple>() {
+            return tuple.iter().map(|item| Self::from_py(&item)).collect::<PyResult<_>>().map(Schema::Tuple);
+        }
+        if let Ok(dict) = schema.downcast::<PyDict>() {
+            return Ok(Schema::Struct(fields_from_py(dict)?));
+        }
+        Err(PyTypeError::new_err(format!("Invalid schema: {schema}")))
+    }
+
+    fn named(name: &str) -> PyResult<Self> {
+        Ok(match name {
+            "bool" => Schema::Bool,
+            "i8" => Schema::I8,
+            "i16" => Schema::I16,
+            "i32" => Schema::I32,
+            "i64" => Schema::I64,
+            "u8" => Schema::U8,
+            "u16" => Schema::U16,
+            "u32" => Schema::U32,
+            "u64" => Schema::U64,
+            "f32" => Schema::F32,
+            "f64" => Schema::F64,
+            "char" => Schema::Char,
+            "str" => Schema::Str,
+            "bytes" => Schema::Bytes,
+            "unit" => Schema::Unit,
+            _ => return Err(PyTypeError::new_err(format!("Unknown schema type: {name:?}"))),
+        })
+    }
+}
+
+fn fields_from_py(dict: &Bound<'_, PyDict>) -> PyResult<Fields> {
+    let fields = dict
+        .iter()
+        .map(|(name, schema)| Ok((name.extract()?, Schema::from_py(&schema)?)))
+        .collect::<PyResult<_>>()?;
+    Ok(Fields::new(fields))
+}
+
+impl Payload {
+    /// Read a variant payload: `None` for a unit variant, a tuple of schemas
+    /// for a tuple variant, a dict for a struct variant and any other schema
+    /// for a newtype variant
+    pub(crate) fn from_py(payload: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if payload.is_none() {
+            return Ok(Payload::Unit);
+        }
+        if let Ok(tuple) = payload.downcast::<PyTuple>() {
+            return tuple.iter().map(|item| Schema::from_py(&item)).collect::<PyResult<_>>().map(Payload::Tuple);
+        }
+        if let Ok(dict) = payload.downcast::<PyDict>() {
+            return Ok(Payload::Struct(fields_from_py(dict)?));
+        }
+        Schema::from_py(payload).map(Payload::Newtype)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for &Schema {
+    type Value = Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match self {
+            Schema::Bool => deserializer.deserialize_bool(ScalarVisitor),
+            Schema::I8 => deserializer.deserialize_i8(ScalarVisitor),
+            Schema::I16 => deserializer.deserialize_i16(ScalarVisitor),
+            Schema::I32 => deserializer.deserialize_i32(ScalarVisitor),
+            Schema::I64 => deserializer.deserialize_i64(ScalarVisitor),
+            Schema::U8 => deserializer.deserialize_u8(ScalarVisitor),
+            Schema::U16 => deserializer.deserialize_u16(ScalarVisitor),
+            Schema::U32 => deserializer.deserialize_u32(ScalarVisitor),
+            Schema::U64 => deserializer.deserialize_u64(ScalarVisitor),
+            Schema::F32 => deserializer.deserialize_f32(ScalarVisitor),
+            Schema::F64 => deserializer.deserialize_f64(ScalarVisitor),
+            Schema::Char => deserializer.deserialize_char(ScalarVisitor),
+            Schema::Str => deserializer.deserialize_string(ScalarVisitor),
+            Schema::Bytes => deserializer.deserialize_byte_buf(ScalarVisitor),
+            Schema::Unit => deserializer.deserialize_unit(ScalarVisitor),
+            Schema::Option(inner) => deserializer.deserialize_option(OptionVisitor(inner)),
+            Schema::Seq(item) => deserializer.deserialize_seq(SeqVisitor(item)),
+            Schema::Tuple(items) => deserializer.deserialize_tuple(items.len(), TupleVisitor(items)),
+            Schema::Map(key, value) => deserializer.deserialize_map(MapVisitor(key, value)),
+            Schema::Struct(fields) => deserializer.deserialize_struct("", fields.names, StructVisitor(fields)),
+            Schema::Enum(variants) => deserializer.deserialize_enum("", variants.names, EnumVisitor(variants)),
+        }
+    }
+}
+
+struct ScalarVisitor;
+
+impl<'de> Visitor<'de> for ScalarVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a scalar value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::UInt(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_char<E>(self, v: char) -> Result<Value, E> {
+        Ok(Value::Char(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Str(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::Str(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+}
+
+struct OptionVisitor<'a>(&'a Schema);
+
+impl<'de> Visitor<'de> for OptionVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an option")
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.deserialize(deserializer)
+    }
+}
+
+struct SeqVisitor<'a>(&'a Schema);
+
+impl<'de> Visitor<'de> for SeqVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(item) = seq.next_element_seed(self.0)? {
+            items.push(item);
+        }
+        Ok(Value::Seq(items))
+    }
+
+    // Self-describing payloads may store byte sequences in bulk
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Seq(v.iter().map(|&byte| Value::UInt(byte.into())).collect()))
+    }
+}
+
+struct TupleVisitor<'a>(&'a [Schema]);
+
+impl<'de> Visitor<'de> for TupleVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a tuple of {} elements", self.0.len())
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut items = Vec::with_capacity(self.0.len());
+        for (index, schema) in self.0.iter().enumerate() {
+            match seq.next_element_seed(schema)? {
+                Some(item) => items.push(item),
+                None => return Err(de::Error::invalid_length(index, &self)),
+            }
+        }
+        Ok(Value::Seq(items))
+    }
+}
+
+struct MapVisitor<'a>(&'a Schema, &'a Schema);
+
+impl<'de> Visitor<'de> for MapVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+        while let Some(entry) = map.next_entry_seed(self.0, self.1)? {
+            entries.push(entry);
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+struct StructVisitor<'a>(&'a Fields);
+
+impl<'de> Visitor<'de> for StructVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a struct with {} fields", self.0.names.len())
+    }
+
+    // Plain structs are their field values in order
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut entries = Vec::with_capacity(self.0.types.len());
+        for (index, (name, schema)) in self.0.names.iter().zip(&self.0.types).enumerate() {
+            match seq.next_element_seed(schema)? {
+                Some(value) => entries.push((Value::Str((*name).to_owned()), value)),
+                None => return Err(de::Error::invalid_length(index, &self)),
+            }
+        }
+        Ok(Value::Map(entries))
+    }
+
+    // Tagged and self-describing structs are keyed by field name
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = Vec::with_capacity(self.0.types.len());
+        while let Some(field) = map.next_key_seed(Identifier(self.0.names))? {
+            match field {
+                Some(index) => {
+                    let value = map.next_value_seed(&self.0.types[index])?;
+                    entries.push((Value::Str(self.0.names[index].to_owned()), value));
+                }
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Value::Map(entries))
+    }
+}
+
+struct EnumVisitor<'a>(&'a Variants);
+
+impl<'de> Visitor<'de> for EnumVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "one of the variants {:?}", self.0.names)
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let (index, variant) = data.variant_seed(Identifier(self.0.names))?;
+        let index = index.ok_or_else(|| de::Error::custom("unknown enum variant"))?;
+        let value = match &self.0.payloads[index] {
+            Payload::Unit => variant.unit_variant().map(|()| Value::Null)?,
+            Payload::Newtype(schema) => variant.newtype_variant_seed(schema)?,
+            Payload::Tuple(items) => variant.tuple_variant(items.len(), TupleVisitor(items))?,
+            Payload::Struct(fields) => variant.struct_variant(fields.names, StructVisitor(fields))?,
+        };
+        Ok(Value::Variant {
+            index: index as u32,
+            name: self.0.names[index].to_owned(),
+            value: Box::new(value),
+        })
+    }
+}
+
+// Position of a field or variant given by index or name, if known
+struct Identifier(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for Identifier {
+    type Value = Option<usize>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Option<usize>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Identifier {
+    type Value = Option<usize>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a field or variant identifier")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Option<usize>, E> {
+        Ok(usize::try_from(v).ok().filter(|&index| index < self.0.len()))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Option<usize>, E> {
+        Ok(self.0.iter().position(|name| *name == v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    enum Kind {
+        Open,
+        Move(i32, i32),
+        Note { text: String },
+    }
+
+    #[derive(Serialize)]
+    struct Event {
+        id: u64,
+        kind: Kind,
+        at: Option<f64>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_decode_with_schema() {
+        let schema = Schema::Seq(Box::new(Schema::Struct(Fields::new(vec![
+            ("id".to_string(), Schema::U64),
+            (
+                "kind".to_string(),
+                Schema::Enum(Variants::new(vec![
+                    ("Open".to_string(), Payload::Unit),
+                    ("Move".to_string(), Payload::Tuple(vec![Schema::I32, Schema::I32])),
+                    ("Note".to_string(), Payload::Struct(Fields::new(vec![("text".to_string(), Schema::Str)]))),
+                ])),
+            ),
+            ("at".to_string(), Schema::Option(Box::new(Schema::F64))),
+            ("tags".to_string(), Schema::Seq(Box::new(Schema::Str))),
+        ]))));
+        let events = vec![
+            Event { id: 1, kind: Kind::Open, at: None, tags: vec![] },
+            Event { id: 2, kind: Kind::Move(-3, 4), at: Some(1.5), tags: vec!["x".to_string()] },
+            Event { id: 3, kind: Kind::Note { text: "hi".to_string() }, at: None, tags: vec![] },
+        ];
+
+        // The schema reads plain payloads exactly as the type's own decoder
+        // reads self-describing ones
+        let plain = nanobit::to_bytes(&events).unwrap();
+        let decoded = nanobit::from_bytes_seed(&schema, &plain).unwrap();
+        assert_eq!(decoded, nanobit::to_value(&events).unwrap());
+
+        let tagged = nanobit::to_bytes_with_config(&events, nanobit::Config::new().with_tagged_fields(true)).unwrap();
+        assert_eq!(nanobit::from_bytes_seed(&schema, &tagged).unwrap(), decoded);
+
+        // A stale schema fails instead of misreading the payload
+        let stale = Schema::Seq(Box::new(Schema::Struct(Fields::new(vec![("id".to_string(), Schema::U64)]))));
+        assert!(nanobit::from_bytes_seed(&stale, &plain).is_err());
+    }
+}
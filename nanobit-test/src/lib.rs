@@ -0,0 +1,249 @@
+//! Round-trip testing utilities for NanoBit
+//!
+//! Checks that a type survives serialization under every wire-format
+//! option, and provides [proptest](https://docs.rs/proptest) strategies
+//! over the serde data model. A type that implements
+//! `proptest::arbitrary::Arbitrary` is property-tested in one line:
+//!
+//! ```rust
+//! use proptest_derive::Arbitrary;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Arbitrary, Serialize, Deserialize, Debug, PartialEq)]
+//! struct Reading {
+//!     sensor: String,
+//!     values: Vec<u32>,
+//! }
+//!
+//! nanobit_test::proptest_roundtrip!(reading_roundtrips: Reading);
+//! # fn main() {}
+//! ```
+//!
+//! Fixed values are checked with [`assert_roundtrip`]:
+//!
+//! ```rust
+//! nanobit_test::assert_roundtrip(&vec![(1u8, String::from("a"))]);
+//! ```
+//!
+//! Failures name the configuration and include an annotated hexdump of
+//! the payload.
+
+use core::fmt::Debug;
+
+use nanobit::{Checksum, Config};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[doc(hidden)]
+pub use proptest;
+
+pub mod strategy;
+
+/// Every configuration [`assert_roundtrip`] checks, with a name for each
+///
+/// Covers each wire-format option on its own, all of them together, and
+/// both checksums.
+pub fn configs() -> Vec<(&'static str, Config)> {
+    let all = Config::new()
+        .with_tagged_fields(true)
+        .with_self_describing(true)
+        .with_canonical(true)
+        .with_string_table(true)
+        .with_compact_chars(true)
+        .with_fixed_tuples(true)
+        .with_niche_options(true);
+    vec![
+        ("default", Config::new()),
+        ("tagged", Config::new().with_tagged_fields(true)),
+        ("self-describing", Config::new().with_self_describing(true)),
+        ("canonical", Config::new().with_canonical(true)),
+        ("string-table", Config::new().with_string_table(true)),
+        ("compact-chars", Config::new().with_compact_chars(true)),
+        ("fixed-tuples", Config::new().with_fixed_tuples(true)),
+        ("niche-options", Config::new().with_niche_options(true)),
+        ("crc32c", Config::new().with_checksum(Some(Checksum::Crc32c))),
+        ("xxh64", Config::new().with_checksum(Some(Checksum::XxHash64))),
+        ("all", all),
+    ]
+}
+
+/// Serialize `value` with `config` and decode it back
+///
+/// Returns a description of the failure if either step fails or the
+/// decoded value differs from `value`.
+pub fn check_roundtrip_with_config<T>(value: &T, config: Config) -> Result<(), String>
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let bytes = nanobit::to_bytes_with_config(value, config)
+        .map_err(|e| format!("serializing {value:?} failed: {e}"))?;
+    match nanobit::from_bytes_with_config::<T>(&bytes, config) {
+        Ok(decoded) if decoded == *value => Ok(()),
+        Ok(decoded) => Err(format!(
+            "{value:?} decoded as {decoded:?}\n{}",
+            nanobit::debug::explain(&bytes)
+        )),
+        Err(e) => Err(format!(
+            "deserializing {value:?} failed: {e}\n{}",
+            nanobit::debug::explain(&bytes)
+        )),
+    }
+}
+
+/// Check that `value` round-trips under every configuration in [`configs`]
+pub fn check_roundtrip<T>(value: &T) -> Result<(), String>
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    for (name, config) in configs() {
+        check_roundtrip_with_config(value, config).map_err(|e| format!("with the {name} config: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Assert that `value` round-trips under `config`
+///
+/// # Panics
+///
+/// If serialization or deserialization fails, or the decoded value
+/// differs from `value`.
+#[track_caller]
+pub fn assert_roundtrip_with_config<T>(value: &T, config: Config)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    if let Err(e) = check_roundtrip_with_config(value, config) {
+        panic!("{e}");
+    }
+}
+
+/// Assert that `value` round-trips under every configuration in [`configs`]
+///
+/// # Panics
+///
+/// If `value` fails to round-trip under any of them.
+#[track_caller]
+pub fn assert_roundtrip<T>(value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    if let Err(e) = check_roundtrip(value) {
+        panic!("{e}");
+    }
+}
+
+/// [`check_roundtrip_with_config`] as a proptest failure, for use inside
+/// `proptest!` bodies with `?`
+pub fn prop_roundtrip<T>(value: &T, config: Config) -> Result<(), proptest::test_runner::TestCaseError>
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    check_roundtrip_with_config(value, config).map_err(proptest::test_runner::TestCaseError::fail)
+}
+
+/// Declare property tests that round-trip arbitrary values of each type
+///
+/// Every test draws values from the type's `Arbitrary` implementation and
+/// a configuration from [`strategy::config`].
+///
+/// ```rust
+/// nanobit_test::proptest_roundtrip! {
+///     strings: String,
+///     maps: std::collections::BTreeMap<u8, Vec<i64>>,
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! proptest_roundtrip {
+    ($($name:ident: $ty:ty),+ $(,)?) => {
+        $crate::proptest::proptest! {
+            $(
+                #[test]
+                fn $name(
+                    value in $crate::proptest::prelude::any::<$ty>(),
+                    config in $crate::strategy::config(),
+                ) {
+                    $crate::prop_roundtrip(&value, config)?;
+                }
+            )+
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+
+    use proptest::prelude::*;
+    use proptest_derive::Arbitrary;
+    use serde::Deserialize;
+
+    #[derive(Arbitrary, Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Start,
+        Tick(u64),
+        Move(i8, i8),
+        Rename { from: String, to: String },
+    }
+
+    #[derive(Arbitrary, Serialize, Deserialize, Debug, PartialEq)]
+    struct Record {
+        id: u32,
+        name: String,
+        tags: Vec<String>,
+        parent: Option<u32>,
+        scores: HashMap<String, i64>,
+        glyph: char,
+        pair: (u16, bool),
+        event: Event,
+    }
+
+    // Serializes fine but decodes to something else
+    #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(into = "u8", from = "u8")]
+    struct Lossy(u8);
+
+    impl From<Lossy> for u8 {
+        fn from(lossy: Lossy) -> u8 {
+            lossy.0
+        }
+    }
+
+    impl From<u8> for Lossy {
+        fn from(byte: u8) -> Lossy {
+            Lossy(byte / 2)
+        }
+    }
+
+    proptest_roundtrip! {
+        test_records_roundtrip: Record,
+        test_nested_collections_roundtrip: BTreeMap<u8, Option<(i32, char)>>,
+    }
+
+    proptest! {
+        #[test]
+        fn test_values_roundtrip_self_describing(value in strategy::value(), config in strategy::config()) {
+            prop_roundtrip(&value, config.with_self_describing(true).with_canonical(false))?;
+        }
+    }
+
+    #[test]
+    fn test_assert_roundtrip() {
+        assert_roundtrip(&(1u8, "two".to_string(), [3.0f32; 2]));
+        assert_roundtrip(&Event::Rename { from: "a".to_string(), to: "b".to_string() });
+    }
+
+    #[test]
+    fn test_failure_names_config() {
+        let error = check_roundtrip(&Lossy(7)).unwrap_err();
+        assert!(error.starts_with("with the default config: Lossy(7) decoded as Lossy(3)"), "{error}");
+        assert!(error.contains("magic \"NANO\""), "{error}");
+    }
+
+    #[test]
+    #[should_panic(expected = "decoded as Lossy(3)")]
+    fn test_assert_roundtrip_panics() {
+        assert_roundtrip_with_config(&Lossy(7), Config::new());
+    }
+}
@@ -0,0 +1,67 @@
+//! Proptest strategies for configurations and dynamic values
+
+use nanobit::{Checksum, Config, Value};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// Any combination of wire-format options, with or without a checksum
+pub fn config() -> impl Strategy<Value = Config> {
+    let checksum = prop_oneof![Just(None), Just(Some(Checksum::Crc32c)), Just(Some(Checksum::XxHash64))];
+    (any::<[bool; 6]>(), checksum).prop_map(|(options, checksum)| {
+        Config::new()
+            .with_tagged_fields(options[0])
+            .with_self_describing(options[1])
+            .with_canonical(options[2])
+            .with_string_table(options[3])
+            .with_compact_chars(options[4])
+            .with_fixed_tuples(options[5])
+            .with_checksum(checksum)
+    })
+}
+
+/// Any scalar [`Value`]: null, booleans, integers, finite floats, chars,
+/// strings and byte strings
+///
+/// NaN is left out since it never compares equal to itself.
+pub fn scalar() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::Int),
+        any::<u64>().prop_map(Value::UInt),
+        any::<f64>().prop_filter("NaN is not equal to itself", |f| !f.is_nan()).prop_map(Value::Float),
+        any::<char>().prop_map(Value::Char),
+        any::<String>().prop_map(Value::Str),
+        vec(any::<u8>(), 0..32).prop_map(Value::Bytes),
+    ]
+}
+
+/// Any [`Value`], nested up to four levels deep
+///
+/// Covers every shape of the serde data model: sequences, maps and enum
+/// variants built over [`scalar`] values. Map keys are distinct.
+///
+/// Values decode only from self-describing payloads, and keep their map
+/// entries in encoded order, so a canonical payload decodes with its maps
+/// sorted.
+pub fn value() -> impl Strategy<Value = Value> {
+    scalar().prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..8).prop_map(Value::Seq),
+            vec((inner.clone(), inner.clone()), 0..8).prop_map(|mut entries| {
+                let mut seen = 0;
+                while seen < entries.len() {
+                    if entries[..seen].iter().any(|(key, _)| *key == entries[seen].0) {
+                        entries.remove(seen);
+                    } else {
+                        seen += 1;
+                    }
+                }
+                Value::Map(entries)
+            }),
+            (any::<u32>(), "[A-Za-z][A-Za-z0-9]{0,15}", inner).prop_map(|(index, name, value)| {
+                Value::Variant { index, name, value: Box::new(value) }
+            }),
+        ]
+    })
+}
@@ -0,0 +1,113 @@
+//! WebAssembly bindings for NanoBit payloads
+//!
+//! Lets browsers read and write the self-describing payloads a Rust backend
+//! sends over WebSockets, without a JSON side channel:
+//!
+//! ```js
+//! import init, { decode, encode } from "nanobit-wasm";
+//!
+//! await init();
+//! socket.binaryType = "arraybuffer";
+//! socket.onmessage = (event) => {
+//!     const message = decode(new Uint8Array(event.data));
+//!     socket.send(encode({ ack: message.id }));
+//! };
+//! ```
+//!
+//! Values map to JavaScript as they do to JSON, except that byte strings
+//! become `Uint8Array`s and integers outside the safe range become `BigInt`s.
+
+use js_sys::{Array, BigInt, Number, Object, Reflect, Uint8Array};
+use nanobit::header::{Header, HeaderFlags};
+use nanobit::{Config, Error, Value};
+use wasm_bindgen::prelude::*;
+
+/// Decode a self-describing payload, compressed or not, into a JavaScript value
+#[wasm_bindgen]
+pub fn decode(bytes: &[u8]) -> Result<JsValue, JsError> {
+    Ok(value_to_js(decode_value(bytes)?))
+}
+
+/// Encode a JavaScript value as a self-describing payload
+///
+/// Objects and `Map`s become maps, arrays become sequences and
+/// `Uint8Array`s become byte strings.
+#[wasm_bindgen]
+pub fn encode(value: JsValue) -> Result<Vec<u8>, JsError> {
+    let value: Value = serde_wasm_bindgen::from_value(value).map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(encode_value(&value)?)
+}
+
+fn decode_value(bytes: &[u8]) -> nanobit::Result<Value> {
+    let (header, _) = Header::parse(bytes)?;
+    if header.flags.contains(HeaderFlags::COMPRESSED) {
+        return decode_value(&nanobit::decompress(bytes)?);
+    }
+    if !header.flags.contains(HeaderFlags::SELF_DESCRIBING) {
+        return Err(Error::InvalidFormat("Payload is not self-describing".to_string()));
+    }
+    nanobit::from_bytes(bytes)
+}
+
+fn encode_value(value: &Value) -> nanobit::Result<Vec<u8>> {
+    nanobit::to_bytes_with_config(value, Config::new().with_self_describing(true))
+}
+
+fn value_to_js(value: Value) -> JsValue {
+    match value {
+        Value::Null => JsValue::NULL,
+        Value::Bool(b) => JsValue::from_bool(b),
+        Value::Int(i) if i.unsigned_abs() <= Number::MAX_SAFE_INTEGER as u64 => JsValue::from_f64(i as f64),
+        Value::Int(i) => BigInt::from(i).into(),
+        Value::UInt(u) if u <= Number::MAX_SAFE_INTEGER as u64 => JsValue::from_f64(u as f64),
+        Value::UInt(u) => BigInt::from(u).into(),
+        Value::Float(f) => JsValue::from_f64(f),
+        Value::Char(c) => JsValue::from_str(c.encode_utf8(&mut [0; 4])),
+        Value::Str(s) => JsValue::from_str(&s),
+        Value::Bytes(b) => Uint8Array::from(&b[..]).into(),
+        Value::Seq(items) => items.into_iter().map(value_to_js).collect::<Array>().into(),
+        // Keys become property names, so scalar keys are stringified
+        Value::Map(entries) => {
+            let object = Object::new();
+            for (k, v) in entries {
+                Reflect::set(&object, &value_to_js(k), &value_to_js(v)).expect("setting a property on a plain object");
+            }
+            object.into()
+        }
+        // Externally tagged, as in JSON
+        Value::Variant { index, name, value } => {
+            let name = if name.is_empty() { index.to_string() } else { name };
+            match *value {
+                Value::Null => JsValue::from_str(&name),
+                payload => {
+                    let object = Object::new();
+                    Reflect::set(&object, &JsValue::from_str(&name), &value_to_js(payload))
+                        .expect("setting a property on a plain object");
+                    object.into()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_frames() {
+        let message = ("tick", 7u32, vec![1.5f64, 2.5]);
+        let plain = nanobit::to_bytes_with_config(&message, Config::new().with_self_describing(true)).unwrap();
+        let value = decode_value(&plain).unwrap();
+        assert_eq!(value, nanobit::to_value(&message).unwrap());
+        assert_eq!(decode_value(&encode_value(&value).unwrap()).unwrap(), value);
+
+        // Compressed frames decode transparently
+        let compressed = nanobit::compress_default(&plain).unwrap();
+        assert_eq!(decode_value(&compressed).unwrap(), value);
+
+        // Payloads without type tags cannot be turned into JavaScript values
+        let untagged = nanobit::to_bytes(&message).unwrap();
+        assert!(matches!(decode_value(&untagged), Err(Error::InvalidFormat(_))));
+    }
+}
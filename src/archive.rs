@@ -0,0 +1,554 @@
+//! Random-access archives read in place
+//!
+//! The regular format is compact but sequential: reaching the last field
+//! of a record means decoding every field before it. An archive lays a
+//! value out at offsets computable from its type instead, so a field is
+//! read straight from the bytes, whether they sit in a `Vec`, a network
+//! buffer or a memory-mapped file, without decoding anything else.
+//!
+//! [`to_bytes`] writes an archive and [`access`] checks one and returns a
+//! view of it. With the `derive` feature, `#[derive(Archive)]` on a struct
+//! with named fields generates its view type, named after the struct with
+//! a `View` suffix, with one accessor per field:
+//!
+//! ```rust
+//! # #[cfg(feature = "derive")]
+//! # {
+//! use nanobit::archive::{self, Archive};
+//!
+//! #[derive(Archive)]
+//! struct Reading {
+//!     sensor: u32,
+//!     celsius: f64,
+//! }
+//!
+//! #[derive(Archive)]
+//! struct Station {
+//!     id: u64,
+//!     name: String,
+//!     readings: Vec<Reading>,
+//!     retired: Option<u32>,
+//! }
+//!
+//! let station = Station {
+//!     id: 7,
+//!     name: "north".into(),
+//!     readings: vec![Reading { sensor: 1, celsius: 21.5 }, Reading { sensor: 2, celsius: 19.0 }],
+//!     retired: None,
+//! };
+//! let bytes = archive::to_bytes(&station)?;
+//!
+//! let view = archive::access::<Station>(&bytes)?;
+//! assert_eq!(view.name(), "north");
+//! assert_eq!(view.readings().get(1).map(|r| r.celsius()), Some(19.0));
+//! assert_eq!(view.retired(), None);
+//! # }
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! [`access`] checks every offset, length, `bool`, `char` and UTF-8 string
+//! up front, so the accessors of the view it returns cannot fail. The
+//! check visits each value once, and is the price of reading untrusted
+//! bytes in place without `unsafe` code.
+//!
+//! Archives are a separate format from [`to_bytes`](crate::to_bytes):
+//! they favour lookups over size, since every integer takes its full width
+//! and every string or vector a reference.
+//!
+//! # Representation
+//!
+//! The magic bytes `NBAR`, then the fixed-size part of the root value.
+//! Numbers are little-endian at their full width, `bool` is one byte, and
+//! `char` four. Struct fields follow each other without padding, and
+//! `Option<T>` is a presence byte followed by the space of `T`, zeroed
+//! when absent. Strings and vectors take eight bytes in place: a `u32`
+//! offset from the start of the archive and a `u32` length, pointing at
+//! the string, or at the elements laid out one after another, later in
+//! the buffer. Arrays `[T; N]` are stored in place. An archive is limited
+//! to 4 GiB.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "derive")]
+pub use nanobit_derive::Archive;
+
+/// Magic bytes at the start of every archive
+pub const ARCHIVE_MAGIC: &[u8; 4] = b"NBAR";
+
+/// A type with a fixed-offset layout that can be read in place
+///
+/// Usually derived, see the [module documentation](self).
+pub trait Archive {
+    /// Bytes the value takes in place inside its parent
+    const SIZE: usize;
+
+    /// The value as read from an archive borrowing for `'a`
+    type View<'a>;
+
+    /// Write the value into the `SIZE` bytes reserved at `at`, appending
+    /// anything stored out of line
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize);
+
+    /// Check that a value at `at` can be viewed
+    fn verify(bytes: &[u8], at: usize) -> Result<()>;
+
+    /// View the value at `at`
+    ///
+    /// The bytes must have passed [`verify`](Self::verify); otherwise the
+    /// view may panic or read garbage, though never unsafely.
+    fn view(bytes: &[u8], at: usize) -> Self::View<'_>;
+}
+
+/// The buffer an archive is written into
+pub struct ArchiveWriter {
+    bytes: Vec<u8>,
+}
+
+impl ArchiveWriter {
+    /// Append `len` zero bytes and return their position
+    #[inline]
+    pub fn reserve(&mut self, len: usize) -> usize {
+        let at = self.bytes.len();
+        self.bytes.resize(at + len, 0);
+        at
+    }
+
+    /// Overwrite reserved bytes at `at`
+    #[inline]
+    pub fn write_at(&mut self, at: usize, bytes: &[u8]) {
+        self.bytes[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Append `bytes` out of line and write a reference to them at `at`
+    pub fn write_out_of_line(&mut self, at: usize, bytes: &[u8]) {
+        let offset = self.bytes.len();
+        self.bytes.extend_from_slice(bytes);
+        self.write_reference(at, offset, bytes.len());
+    }
+
+    /// Write an offset and a length as a reference at `at`
+    pub fn write_reference(&mut self, at: usize, offset: usize, len: usize) {
+        self.write_at(at, &(offset as u32).to_le_bytes());
+        self.write_at(at + 4, &(len as u32).to_le_bytes());
+    }
+}
+
+/// Archive a value
+pub fn to_bytes<T: Archive>(value: &T) -> Result<Vec<u8>> {
+    let mut writer = ArchiveWriter { bytes: Vec::with_capacity(ARCHIVE_MAGIC.len() + T::SIZE) };
+    writer.bytes.extend_from_slice(ARCHIVE_MAGIC);
+    let at = writer.reserve(T::SIZE);
+    value.archive(&mut writer, at);
+    if writer.bytes.len() > u32::MAX as usize {
+        return Err(Error::LimitExceeded { what: "archive size", limit: u32::MAX as usize });
+    }
+    Ok(writer.bytes)
+}
+
+/// Check an archive and view its root value
+pub fn access<T: Archive>(bytes: &[u8]) -> Result<T::View<'_>> {
+    if !bytes.starts_with(ARCHIVE_MAGIC) {
+        return Err(Error::InvalidFormat("Not a nanobit archive".to_string()));
+    }
+    T::verify(bytes, ARCHIVE_MAGIC.len())?;
+    Ok(T::view(bytes, ARCHIVE_MAGIC.len()))
+}
+
+/// Check that `len` bytes at `at` lie inside the archive
+#[inline]
+pub fn check_bounds(bytes: &[u8], at: usize, len: usize) -> Result<()> {
+    match at.checked_add(len) {
+        Some(end) if end <= bytes.len() => Ok(()),
+        _ => Err(Error::UnexpectedEof),
+    }
+}
+
+#[inline]
+fn read<const N: usize>(bytes: &[u8], at: usize) -> [u8; N] {
+    let mut out = [0; N];
+    out.copy_from_slice(&bytes[at..at + N]);
+    out
+}
+
+// Offset and length of an out-of-line value
+#[inline]
+fn reference(bytes: &[u8], at: usize) -> (usize, usize) {
+    (u32::from_le_bytes(read(bytes, at)) as usize, u32::from_le_bytes(read(bytes, at + 4)) as usize)
+}
+
+// Check a reference to `len` items of `size` bytes and return its offset
+// and length
+fn verify_reference(bytes: &[u8], at: usize, size: usize) -> Result<(usize, usize)> {
+    check_bounds(bytes, at, 8)?;
+    let (offset, len) = reference(bytes, at);
+    let span = len.checked_mul(size).ok_or(Error::UnexpectedEof)?;
+    check_bounds(bytes, offset, span)?;
+    Ok((offset, len))
+}
+
+macro_rules! archive_number {
+    ($($ty:ty),*) => {$(
+        impl Archive for $ty {
+            const SIZE: usize = core::mem::size_of::<$ty>();
+            type View<'a> = $ty;
+
+            #[inline]
+            fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+                writer.write_at(at, &self.to_le_bytes());
+            }
+
+            #[inline]
+            fn verify(bytes: &[u8], at: usize) -> Result<()> {
+                check_bounds(bytes, at, Self::SIZE)
+            }
+
+            #[inline]
+            fn view(bytes: &[u8], at: usize) -> $ty {
+                <$ty>::from_le_bytes(read(bytes, at))
+            }
+        }
+    )*};
+}
+
+archive_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Archive for bool {
+    const SIZE: usize = 1;
+    type View<'a> = bool;
+
+    #[inline]
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+        writer.write_at(at, &[u8::from(*self)]);
+    }
+
+    fn verify(bytes: &[u8], at: usize) -> Result<()> {
+        check_bounds(bytes, at, 1)?;
+        match bytes[at] {
+            0 | 1 => Ok(()),
+            other => Err(Error::InvalidFormat(format!("Invalid bool value {other}"))),
+        }
+    }
+
+    #[inline]
+    fn view(bytes: &[u8], at: usize) -> bool {
+        bytes[at] != 0
+    }
+}
+
+impl Archive for char {
+    const SIZE: usize = 4;
+    type View<'a> = char;
+
+    #[inline]
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+        writer.write_at(at, &u32::from(*self).to_le_bytes());
+    }
+
+    fn verify(bytes: &[u8], at: usize) -> Result<()> {
+        check_bounds(bytes, at, 4)?;
+        char::from_u32(u32::from_le_bytes(read(bytes, at)))
+            .map(drop)
+            .ok_or_else(|| Error::InvalidFormat("Invalid char value".to_string()))
+    }
+
+    #[inline]
+    fn view(bytes: &[u8], at: usize) -> char {
+        char::from_u32(u32::from_le_bytes(read(bytes, at))).unwrap_or_default()
+    }
+}
+
+fn verify_str(bytes: &[u8], at: usize) -> Result<()> {
+    let (offset, len) = verify_reference(bytes, at, 1)?;
+    core::str::from_utf8(&bytes[offset..offset + len])
+        .map(drop)
+        .map_err(|_| Error::InvalidFormat("Invalid UTF-8 in archived string".to_string()))
+}
+
+fn view_str(bytes: &[u8], at: usize) -> &str {
+    let (offset, len) = reference(bytes, at);
+    core::str::from_utf8(&bytes[offset..offset + len]).unwrap_or_default()
+}
+
+impl Archive for String {
+    const SIZE: usize = 8;
+    type View<'a> = &'a str;
+
+    #[inline]
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+        writer.write_out_of_line(at, self.as_bytes());
+    }
+
+    #[inline]
+    fn verify(bytes: &[u8], at: usize) -> Result<()> {
+        verify_str(bytes, at)
+    }
+
+    #[inline]
+    fn view(bytes: &[u8], at: usize) -> &str {
+        view_str(bytes, at)
+    }
+}
+
+impl Archive for &str {
+    const SIZE: usize = 8;
+    type View<'a> = &'a str;
+
+    #[inline]
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+        writer.write_out_of_line(at, self.as_bytes());
+    }
+
+    #[inline]
+    fn verify(bytes: &[u8], at: usize) -> Result<()> {
+        verify_str(bytes, at)
+    }
+
+    #[inline]
+    fn view(bytes: &[u8], at: usize) -> &str {
+        view_str(bytes, at)
+    }
+}
+
+impl<T: Archive> Archive for Option<T> {
+    const SIZE: usize = 1 + T::SIZE;
+    type View<'a> = Option<T::View<'a>>;
+
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+        if let Some(value) = self {
+            writer.write_at(at, &[1]);
+            value.archive(writer, at + 1);
+        }
+    }
+
+    fn verify(bytes: &[u8], at: usize) -> Result<()> {
+        check_bounds(bytes, at, Self::SIZE)?;
+        match bytes[at] {
+            0 => Ok(()),
+            1 => T::verify(bytes, at + 1),
+            other => Err(Error::InvalidFormat(format!("Invalid option tag {other}"))),
+        }
+    }
+
+    #[inline]
+    fn view(bytes: &[u8], at: usize) -> Self::View<'_> {
+        (bytes[at] != 0).then(|| T::view(bytes, at + 1))
+    }
+}
+
+// Lay out `items` one after another at `at`
+fn archive_items<T: Archive>(items: &[T], writer: &mut ArchiveWriter, at: usize) {
+    for (index, item) in items.iter().enumerate() {
+        item.archive(writer, at + index * T::SIZE);
+    }
+}
+
+fn verify_items<T: Archive>(bytes: &[u8], at: usize, len: usize) -> Result<()> {
+    // Items without a fixed part, such as `()`, have nothing to check
+    if T::SIZE > 0 {
+        for index in 0..len {
+            T::verify(bytes, at + index * T::SIZE)?;
+        }
+    }
+    Ok(())
+}
+
+impl<T: Archive> Archive for Vec<T> {
+    const SIZE: usize = 8;
+    type View<'a> = ArchivedSlice<'a, T>;
+
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+        let offset = writer.reserve(self.len() * T::SIZE);
+        writer.write_reference(at, offset, self.len());
+        archive_items(self, writer, offset);
+    }
+
+    fn verify(bytes: &[u8], at: usize) -> Result<()> {
+        let (offset, len) = verify_reference(bytes, at, T::SIZE)?;
+        verify_items::<T>(bytes, offset, len)
+    }
+
+    #[inline]
+    fn view(bytes: &[u8], at: usize) -> Self::View<'_> {
+        let (start, len) = reference(bytes, at);
+        ArchivedSlice { bytes, start, len, marker: PhantomData }
+    }
+}
+
+impl<T: Archive, const N: usize> Archive for [T; N] {
+    const SIZE: usize = N * T::SIZE;
+    type View<'a> = ArchivedSlice<'a, T>;
+
+    #[inline]
+    fn archive(&self, writer: &mut ArchiveWriter, at: usize) {
+        archive_items(self, writer, at);
+    }
+
+    fn verify(bytes: &[u8], at: usize) -> Result<()> {
+        check_bounds(bytes, at, Self::SIZE)?;
+        verify_items::<T>(bytes, at, N)
+    }
+
+    #[inline]
+    fn view(bytes: &[u8], at: usize) -> Self::View<'_> {
+        ArchivedSlice { bytes, start: at, len: N, marker: PhantomData }
+    }
+}
+
+/// A sequence read in place from an archive
+///
+/// The view of `Vec<T>` and `[T; N]` fields. Elements are viewed on demand
+/// by [`get`](Self::get) and [`iter`](Self::iter).
+pub struct ArchivedSlice<'a, T> {
+    bytes: &'a [u8],
+    start: usize,
+    len: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Archive> ArchivedSlice<'a, T> {
+    /// Number of elements
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no elements
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// View the element at `index`, or `None` past the end
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<T::View<'a>> {
+        (index < self.len).then(|| T::view(self.bytes, self.start + index * T::SIZE))
+    }
+
+    /// View the elements in order
+    pub fn iter(&self) -> impl Iterator<Item = T::View<'a>> + 'a {
+        let (bytes, start) = (self.bytes, self.start);
+        (0..self.len).map(move |index| T::view(bytes, start + index * T::SIZE))
+    }
+}
+
+impl<'a> ArchivedSlice<'a, u8> {
+    /// The elements as a byte slice
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        &self.bytes[self.start..self.start + self.len]
+    }
+}
+
+impl<T> Clone for ArchivedSlice<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ArchivedSlice<'_, T> {}
+
+impl<'a, T> fmt::Debug for ArchivedSlice<'a, T>
+where
+    T: Archive,
+    T::View<'a>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nanobit_derive::Archive;
+
+    #[derive(Archive)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Archive)]
+    struct Shape {
+        name: String,
+        closed: bool,
+        points: Vec<Point>,
+        origin: Option<Point>,
+        label: Option<String>,
+        tags: Vec<String>,
+        color: [u8; 3],
+        marker: char,
+        weight: f64,
+    }
+
+    fn shape() -> Shape {
+        Shape {
+            name: "triangle".into(),
+            closed: true,
+            points: vec![Point { x: 0, y: 0 }, Point { x: 4, y: 0 }, Point { x: 0, y: -3 }],
+            origin: Some(Point { x: 1, y: 2 }),
+            label: None,
+            tags: vec!["a".into(), "".into(), "ünïcode".into()],
+            color: [255, 128, 0],
+            marker: '△',
+            weight: 0.5,
+        }
+    }
+
+    #[test]
+    fn test_archive_access() {
+        let bytes = to_bytes(&shape()).unwrap();
+        let view = access::<Shape>(&bytes).unwrap();
+        assert_eq!(view.name(), "triangle");
+        assert!(view.closed());
+        assert_eq!(view.points().len(), 3);
+        assert_eq!(view.points().iter().map(|p| (p.x(), p.y())).collect::<Vec<_>>(), [(0, 0), (4, 0), (0, -3)]);
+        assert!(view.points().get(3).is_none());
+        assert_eq!(view.origin().map(|p| p.y()), Some(2));
+        assert_eq!(view.label(), None);
+        assert_eq!(format!("{:?}", view.tags()), r#"["a", "", "ünïcode"]"#);
+        assert_eq!(view.color().as_bytes(), [255, 128, 0]);
+        assert_eq!(view.marker(), '△');
+        assert_eq!(view.weight(), 0.5);
+
+        // Fields sit at offsets fixed by the type
+        assert_eq!(<Shape as Archive>::SIZE, 8 + 1 + 8 + (1 + 8) + (1 + 8) + 8 + 3 + 4 + 8);
+        let weight = ARCHIVE_MAGIC.len() + <Shape as Archive>::SIZE - 8;
+        assert_eq!(bytes[weight..weight + 8], 0.5f64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_archive_rejects_corruption() {
+        let bytes = to_bytes(&shape()).unwrap();
+        assert!(access::<Shape>(b"NANO").is_err());
+        assert!(access::<Shape>(&bytes[..bytes.len() - 1]).is_err());
+
+        // A reference pointing past the end
+        let mut broken = bytes.clone();
+        broken[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(access::<Shape>(&broken).is_err());
+
+        // An invalid bool
+        let mut broken = bytes.clone();
+        broken[4 + 8] = 2;
+        assert!(access::<Shape>(&broken).is_err());
+
+        // Invalid UTF-8 in the name
+        let mut broken = bytes.clone();
+        let (offset, _) = reference(&bytes, 4);
+        broken[offset] = 0xff;
+        assert!(access::<Shape>(&broken).is_err());
+
+        // A length whose span overflows
+        let mut broken = bytes;
+        let points = 4 + 8 + 1;
+        broken[points + 4..points + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(access::<Shape>(&broken).is_err());
+    }
+}
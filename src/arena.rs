@@ -0,0 +1,422 @@
+//! Deserialization into a bump arena
+//!
+//! Decoding nested data with serde allocates every string and vector on
+//! the heap, one at a time, only for them all to be freed together. Types
+//! implementing [`DeserializeIn`] allocate from a [`bumpalo::Bump`] instead,
+//! so a request's worth of data costs a handful of arena chunks and is
+//! released by resetting the arena:
+//!
+//! ```rust
+//! use bumpalo::collections::Vec as BumpVec;
+//! use bumpalo::Bump;
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Request {
+//!     path: String,
+//!     headers: Vec<(String, String)>,
+//! }
+//!
+//! struct RequestView<'bump> {
+//!     path: &'bump str,
+//!     headers: BumpVec<'bump, (&'bump str, &'bump str)>,
+//! }
+//!
+//! nanobit::impl_deserialize_in!(RequestView<'bump> { path, headers });
+//!
+//! let request = Request {
+//!     path: "/orders".into(),
+//!     headers: vec![("accept".into(), "application/json".into())],
+//! };
+//! let bytes = nanobit::to_bytes(&request)?;
+//!
+//! let bump = Bump::new();
+//! let view: RequestView<'_> = nanobit::arena::from_bytes_in(&bytes, &bump)?;
+//! assert_eq!(view.path, "/orders");
+//! assert_eq!(view.headers[0].1, "application/json");
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Strings and slices are copied into the arena, so the input can be
+//! dropped while the decoded value lives on. Structs declared with
+//! [`impl_deserialize_in!`](crate::impl_deserialize_in) read their fields
+//! in order and therefore need plain payloads, without tagged fields or
+//! self-describing mode.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use bumpalo::boxed::Box as BumpBox;
+use bumpalo::collections::{String as BumpString, Vec as BumpVec};
+use serde::de::{DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+
+pub use bumpalo::Bump;
+
+#[doc(hidden)]
+pub use serde;
+
+use crate::config::Config;
+use crate::error::Result;
+
+/// A type that can be deserialized with its allocations in an arena
+pub trait DeserializeIn<'de, 'bump>: Sized {
+    /// Deserialize a value, allocating from `bump`
+    fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+/// A [`DeserializeSeed`] producing a `T` allocated in an arena
+pub struct InArena<'bump, T> {
+    bump: &'bump Bump,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'bump, T> InArena<'bump, T> {
+    /// Seed deserializing a `T` into `bump`
+    #[inline]
+    pub fn new(bump: &'bump Bump) -> Self {
+        Self {
+            bump,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, 'bump, T> DeserializeSeed<'de> for InArena<'bump, T>
+where
+    T: DeserializeIn<'de, 'bump>,
+{
+    type Value = T;
+
+    #[inline]
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_in(deserializer, self.bump)
+    }
+}
+
+/// Deserialize a value, allocating its strings and collections in `bump`
+pub fn from_bytes_in<'de, 'bump, T>(bytes: &'de [u8], bump: &'bump Bump) -> Result<T>
+where
+    T: DeserializeIn<'de, 'bump>,
+{
+    from_bytes_in_with_config(bytes, bump, Config::new())
+}
+
+/// Deserialize a value with the given options, allocating in `bump`
+pub fn from_bytes_in_with_config<'de, 'bump, T>(
+    bytes: &'de [u8],
+    bump: &'bump Bump,
+    config: Config,
+) -> Result<T>
+where
+    T: DeserializeIn<'de, 'bump>,
+{
+    let mut deserializer = crate::Deserializer::with_config(bytes, config)?;
+    T::deserialize_in(&mut deserializer, bump)
+}
+
+// Types without allocations deserialize as usual
+macro_rules! impl_plain {
+    ($($ty:ty),*) => {$(
+        impl<'de, 'bump> DeserializeIn<'de, 'bump> for $ty {
+            #[inline]
+            fn deserialize_in<D>(deserializer: D, _bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                <$ty>::deserialize(deserializer)
+            }
+        }
+    )*};
+}
+
+impl_plain!(bool, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, char, ());
+
+// Copies strings into the arena
+struct StrVisitor<'bump>(&'bump Bump);
+
+impl<'bump> Visitor<'_> for StrVisitor<'bump> {
+    type Value = &'bump str;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> core::result::Result<&'bump str, E> {
+        Ok(self.0.alloc_str(v))
+    }
+}
+
+impl<'de, 'bump> DeserializeIn<'de, 'bump> for &'bump str {
+    fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(StrVisitor(bump))
+    }
+}
+
+impl<'de, 'bump> DeserializeIn<'de, 'bump> for BumpString<'bump> {
+    fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = deserializer.deserialize_str(StrVisitor(bump))?;
+        Ok(BumpString::from_str_in(s, bump))
+    }
+}
+
+// Collects sequence elements into an arena vector
+struct SeqVisitor<'bump, T>(&'bump Bump, PhantomData<fn() -> T>);
+
+impl<'de, 'bump, T> Visitor<'de> for SeqVisitor<'bump, T>
+where
+    T: DeserializeIn<'de, 'bump> + 'bump,
+{
+    type Value = BumpVec<'bump, T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> core::result::Result<Self::Value, A::Error> {
+        // Bound the preallocation, since the length is untrusted
+        let mut values = BumpVec::with_capacity_in(seq.size_hint().unwrap_or(0).min(4096), self.0);
+        while let Some(value) = seq.next_element_seed(InArena::new(self.0))? {
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+impl<'de, 'bump, T> DeserializeIn<'de, 'bump> for BumpVec<'bump, T>
+where
+    T: DeserializeIn<'de, 'bump>,
+{
+    fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(SeqVisitor(bump, PhantomData))
+    }
+}
+
+impl<'de, 'bump, T> DeserializeIn<'de, 'bump> for &'bump [T]
+where
+    T: DeserializeIn<'de, 'bump>,
+{
+    fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        BumpVec::deserialize_in(deserializer, bump).map(BumpVec::into_bump_slice)
+    }
+}
+
+impl<'de, 'bump, T> DeserializeIn<'de, 'bump> for BumpBox<'bump, T>
+where
+    T: DeserializeIn<'de, 'bump>,
+{
+    fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_in(deserializer, bump).map(|value| BumpBox::new_in(value, bump))
+    }
+}
+
+impl<'de, 'bump, T> DeserializeIn<'de, 'bump> for Option<T>
+where
+    T: DeserializeIn<'de, 'bump>,
+{
+    fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct OptionVisitor<'bump, T>(&'bump Bump, PhantomData<fn() -> T>);
+
+        impl<'de, 'bump, T> Visitor<'de> for OptionVisitor<'bump, T>
+        where
+            T: DeserializeIn<'de, 'bump>,
+        {
+            type Value = Option<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an optional value")
+            }
+
+            fn visit_none<E: serde::de::Error>(self) -> core::result::Result<Option<T>, E> {
+                Ok(None)
+            }
+
+            fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> core::result::Result<Option<T>, D::Error> {
+                T::deserialize_in(deserializer, self.0).map(Some)
+            }
+        }
+
+        deserializer.deserialize_option(OptionVisitor(bump, PhantomData))
+    }
+}
+
+macro_rules! impl_tuple {
+    ($len:expr => $($name:ident)+) => {
+        impl<'de, 'bump, $($name),+> DeserializeIn<'de, 'bump> for ($($name,)+)
+        where
+            $($name: DeserializeIn<'de, 'bump>,)+
+        {
+            fn deserialize_in<D>(deserializer: D, bump: &'bump Bump) -> core::result::Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                struct TupleVisitor<'bump, $($name),+>(&'bump Bump, PhantomData<fn() -> ($($name,)+)>);
+
+                impl<'de, 'bump, $($name),+> Visitor<'de> for TupleVisitor<'bump, $($name),+>
+                where
+                    $($name: DeserializeIn<'de, 'bump>,)+
+                {
+                    type Value = ($($name,)+);
+
+                    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                        write!(f, "a tuple of {} elements", $len)
+                    }
+
+                    #[allow(non_snake_case)]
+                    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> core::result::Result<Self::Value, A::Error> {
+                        let mut index = 0;
+                        $(
+                            let $name = seq
+                                .next_element_seed(InArena::new(self.0))?
+                                .ok_or_else(|| serde::de::Error::invalid_length(index, &self))?;
+                            index += 1;
+                        )+
+                        let _ = index;
+                        Ok(($($name,)+))
+                    }
+                }
+
+                deserializer.deserialize_tuple($len, TupleVisitor(bump, PhantomData))
+            }
+        }
+    };
+}
+
+impl_tuple!(1 => T0);
+impl_tuple!(2 => T0 T1);
+impl_tuple!(3 => T0 T1 T2);
+impl_tuple!(4 => T0 T1 T2 T3);
+
+/// Implement [`DeserializeIn`](crate::arena::DeserializeIn) for a struct
+/// with named fields, read in declaration order
+///
+/// The struct takes the arena lifetime as its only generic parameter, or
+/// none at all. Every field type must implement `DeserializeIn`.
+#[macro_export]
+macro_rules! impl_deserialize_in {
+    ($name:ident $(<$bump:lifetime>)? { $($field:ident),* $(,)? }) => {
+        $crate::impl_deserialize_in!(@impl $name [$($bump)?] ['__bump] { $($field),* });
+    };
+    (@impl $name:ident [$bump:lifetime] [$_default:lifetime] { $($field:ident),* }) => {
+        $crate::impl_deserialize_in!(@expand $name [$bump] [<$bump>] { $($field),* });
+    };
+    (@impl $name:ident [] [$default:lifetime] { $($field:ident),* }) => {
+        $crate::impl_deserialize_in!(@expand $name [$default] [] { $($field),* });
+    };
+    (@expand $name:ident [$bump:lifetime] [$($generics:tt)*] { $($field:ident),* }) => {
+        impl<'__de, $bump> $crate::arena::DeserializeIn<'__de, $bump> for $name $($generics)* {
+            fn deserialize_in<__D>(
+                deserializer: __D,
+                bump: &$bump $crate::arena::Bump,
+            ) -> ::core::result::Result<Self, __D::Error>
+            where
+                __D: $crate::arena::serde::Deserializer<'__de>,
+            {
+                struct __Visitor<$bump>(&$bump $crate::arena::Bump);
+
+                impl<'__de, $bump> $crate::arena::serde::de::Visitor<'__de> for __Visitor<$bump> {
+                    type Value = $name $($generics)*;
+
+                    fn expecting(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                        f.write_str(concat!("struct ", stringify!($name)))
+                    }
+
+                    fn visit_seq<__A>(self, mut seq: __A) -> ::core::result::Result<Self::Value, __A::Error>
+                    where
+                        __A: $crate::arena::serde::de::SeqAccess<'__de>,
+                    {
+                        $(
+                            let $field = seq
+                                .next_element_seed($crate::arena::InArena::new(self.0))?
+                                .ok_or_else(|| {
+                                    <__A::Error as $crate::arena::serde::de::Error>::missing_field(stringify!($field))
+                                })?;
+                        )*
+                        Ok($name { $($field),* })
+                    }
+                }
+
+                deserializer.deserialize_struct(stringify!($name), &[$(stringify!($field)),*], __Visitor(bump))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Order {
+        id: u64,
+        customer: String,
+        lines: Vec<(String, u32)>,
+        note: Option<String>,
+    }
+
+    struct OrderView<'bump> {
+        id: u64,
+        customer: BumpString<'bump>,
+        lines: &'bump [(&'bump str, u32)],
+        note: Option<BumpBox<'bump, &'bump str>>,
+    }
+
+    crate::impl_deserialize_in!(OrderView<'bump> { id, customer, lines, note });
+
+    #[derive(Serialize, Debug, PartialEq)]
+    struct Total {
+        amount: u64,
+    }
+
+    crate::impl_deserialize_in!(Total { amount });
+
+    #[test]
+    fn test_from_bytes_in() {
+        let order = Order {
+            id: 42,
+            customer: "acme".into(),
+            lines: vec![("bolt".into(), 100), ("nut".into(), 250)],
+            note: Some("rush".into()),
+        };
+        let bytes = crate::to_bytes(&order).unwrap();
+
+        let bump = Bump::new();
+        let view: OrderView<'_> = from_bytes_in(&bytes, &bump).unwrap();
+        drop(bytes);
+        assert_eq!((view.id, view.customer.as_str()), (42, "acme"));
+        assert_eq!(view.lines, [("bolt", 100), ("nut", 250)]);
+        assert_eq!(view.note.as_deref(), Some(&"rush"));
+        assert!(bump.allocated_bytes() > 0);
+
+        let bytes = crate::to_bytes(&[Total { amount: 5 }]).unwrap();
+        assert_eq!(from_bytes_in::<(Total,)>(&bytes, &bump).unwrap().0, Total { amount: 5 });
+
+        // Field count mismatches are reported, not misread
+        let short = crate::to_bytes(&(1u64, "acme")).unwrap();
+        assert!(from_bytes_in::<OrderView<'_>>(&short, &bump).is_err());
+    }
+}
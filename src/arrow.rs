@@ -0,0 +1,305 @@
+//! Conversion between record sequences and Arrow record batches
+//!
+//! Each record becomes a row and each field a column, so serialized
+//! datasets can be handed to DataFusion, Polars or any other Arrow consumer:
+//!
+//! ```rust
+//! use nanobit::arrow::{from_record_batch, to_record_batch};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Trade { symbol: String, price: f64, size: u32, venue: Option<String> }
+//!
+//! let trades = vec![
+//!     Trade { symbol: "ACME".into(), price: 12.5, size: 100, venue: None },
+//!     Trade { symbol: "INIT".into(), price: 3.25, size: 40, venue: Some("X".into()) },
+//! ];
+//! let batch = to_record_batch(&trades)?;
+//! assert_eq!((batch.num_rows(), batch.num_columns()), (2, 4));
+//!
+//! let back: Vec<Trade> = from_record_batch(&batch)?;
+//! assert_eq!(back, trades);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Records must be structs or maps with string keys whose values are
+//! scalars: integers become `Int64` or `UInt64` columns, floats `Float64`,
+//! strings, chars and unit enum variants `Utf8`, and byte strings `Binary`.
+//! Missing fields and `None` become nulls. Self-describing payloads holding
+//! such records convert without the Rust type through
+//! [`payload_to_record_batch`] and [`record_batch_to_payload`].
+
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    ArrowPrimitiveType, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt32Type,
+    UInt64Type, UInt8Type,
+};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, Float64Array, Int64Array, NullArray, RecordBatch, RecordBatchOptions,
+    StringArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::value::{from_value, to_value, Value};
+
+/// Convert records into a record batch with one column per field
+pub fn to_record_batch<T: Serialize>(records: &[T]) -> Result<RecordBatch> {
+    let rows = records.iter().map(to_value).collect::<Result<Vec<_>>>()?;
+    rows_to_batch(rows)
+}
+
+/// Convert the rows of a record batch into records
+pub fn from_record_batch<T: DeserializeOwned>(batch: &RecordBatch) -> Result<Vec<T>> {
+    batch_to_rows(batch)?.into_iter().map(from_value).collect()
+}
+
+/// Convert a self-describing payload holding a sequence of records into a
+/// record batch
+pub fn payload_to_record_batch(bytes: &[u8]) -> Result<RecordBatch> {
+    match crate::from_bytes::<Value>(bytes)? {
+        Value::Seq(rows) => rows_to_batch(rows),
+        other => Err(Error::InvalidFormat(format!(
+            "Expected a sequence of records, found {}",
+            other.kind()
+        ))),
+    }
+}
+
+/// Encode the rows of a record batch as a self-describing payload
+pub fn record_batch_to_payload(batch: &RecordBatch) -> Result<Vec<u8>> {
+    let rows = Value::Seq(batch_to_rows(batch)?);
+    crate::to_bytes_with_config(&rows, Config::new().with_self_describing(true))
+}
+
+// Arrow type of a column, widened as rows are seen
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Null,
+    Bool,
+    Int,
+    UInt,
+    Float,
+    Str,
+    Bytes,
+}
+
+impl Kind {
+    fn of(name: &str, value: &Value) -> Result<Self> {
+        Ok(match value {
+            Value::Null => Kind::Null,
+            Value::Bool(_) => Kind::Bool,
+            Value::Int(_) => Kind::Int,
+            Value::UInt(_) => Kind::UInt,
+            Value::Float(_) => Kind::Float,
+            Value::Char(_) | Value::Str(_) => Kind::Str,
+            Value::Variant { value, .. } if value.is_null() => Kind::Str,
+            Value::Bytes(_) => Kind::Bytes,
+            other => {
+                return Err(Error::Arrow(format!(
+                    "Column {name} holds a {}, which is not a scalar",
+                    other.kind()
+                )))
+            }
+        })
+    }
+
+    fn merge(self, other: Self, name: &str) -> Result<Self> {
+        Ok(match (self, other) {
+            (a, b) if a == b => a,
+            (Kind::Null, kind) | (kind, Kind::Null) => kind,
+            (Kind::Int, Kind::UInt) | (Kind::UInt, Kind::Int) => Kind::Int,
+            (Kind::Int | Kind::UInt, Kind::Float) | (Kind::Float, Kind::Int | Kind::UInt) => Kind::Float,
+            _ => return Err(Error::Arrow(format!("Column {name} mixes value types"))),
+        })
+    }
+}
+
+struct Column {
+    name: String,
+    kind: Kind,
+    cells: Vec<Value>,
+}
+
+fn rows_to_batch(rows: Vec<Value>) -> Result<RecordBatch> {
+    let row_count = rows.len();
+    let mut columns: Vec<Column> = Vec::new();
+    for (row, record) in rows.into_iter().enumerate() {
+        let Value::Map(entries) = record else {
+            return Err(Error::Arrow(format!("Record {row} is a {}, not a struct or map", record.kind())));
+        };
+        for (key, value) in entries {
+            let Value::Str(name) = key else {
+                return Err(Error::Arrow(format!("Record {row} has a {} key", key.kind())));
+            };
+            let index = match columns.iter().position(|column| column.name == name) {
+                Some(index) => index,
+                None => {
+                    columns.push(Column { name, kind: Kind::Null, cells: Vec::new() });
+                    columns.len() - 1
+                }
+            };
+            let column = &mut columns[index];
+            column.kind = column.kind.merge(Kind::of(&column.name, &value)?, &column.name)?;
+            // Fields missing from earlier records are null
+            column.cells.resize(row, Value::Null);
+            column.cells.push(value);
+        }
+    }
+
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut arrays = Vec::with_capacity(columns.len());
+    for mut column in columns {
+        column.cells.resize(row_count, Value::Null);
+        let array = build_array(&column)?;
+        fields.push(Field::new(column.name, array.data_type().clone(), array.null_count() > 0));
+        arrays.push(array);
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(row_count));
+    RecordBatch::try_new_with_options(Arc::new(Schema::new(fields)), arrays, &options)
+        .map_err(|e| Error::Arrow(e.to_string()))
+}
+
+fn build_array(column: &Column) -> Result<ArrayRef> {
+    let cells = &column.cells;
+    Ok(match column.kind {
+        Kind::Null => Arc::new(NullArray::new(cells.len())),
+        Kind::Bool => Arc::new(cells.iter().map(Value::as_bool).collect::<BooleanArray>()),
+        Kind::UInt => Arc::new(cells.iter().map(Value::as_u64).collect::<UInt64Array>()),
+        Kind::Int => {
+            let mut ints = Vec::with_capacity(cells.len());
+            for cell in cells {
+                ints.push(match cell {
+                    Value::Null => None,
+                    other => Some(other.as_i64().ok_or_else(|| {
+                        Error::Arrow(format!("Column {} holds a value outside the Int64 range", column.name))
+                    })?),
+                });
+            }
+            Arc::new(Int64Array::from(ints))
+        }
+        Kind::Float => Arc::new(cells.iter().map(Value::as_f64).collect::<Float64Array>()),
+        Kind::Str => Arc::new(
+            cells
+                .iter()
+                .map(|cell| match cell {
+                    Value::Null => None,
+                    Value::Char(c) => Some(c.to_string()),
+                    Value::Variant { name, index, .. } if name.is_empty() => Some(index.to_string()),
+                    Value::Variant { name, .. } => Some(name.clone()),
+                    other => other.as_str().map(str::to_owned),
+                })
+                .collect::<StringArray>(),
+        ),
+        Kind::Bytes => Arc::new(cells.iter().map(Value::as_bytes).collect::<BinaryArray>()),
+    })
+}
+
+fn batch_to_rows(batch: &RecordBatch) -> Result<Vec<Value>> {
+    let schema = batch.schema();
+    let mut columns = Vec::with_capacity(batch.num_columns());
+    for (field, array) in schema.fields().iter().zip(batch.columns()) {
+        columns.push(column_values(field.name(), array)?.into_iter());
+    }
+    let rows = (0..batch.num_rows())
+        .map(|_| {
+            let entries = schema
+                .fields()
+                .iter()
+                .zip(&mut columns)
+                .map(|(field, cells)| (Value::Str(field.name().clone()), cells.next().unwrap_or_default()))
+                .collect();
+            Value::Map(entries)
+        })
+        .collect();
+    Ok(rows)
+}
+
+fn column_values(name: &str, array: &ArrayRef) -> Result<Vec<Value>> {
+    fn primitive<T: ArrowPrimitiveType>(array: &ArrayRef, cell: impl Fn(T::Native) -> Value) -> Vec<Value> {
+        array.as_primitive::<T>().iter().map(|v| v.map_or(Value::Null, &cell)).collect()
+    }
+
+    Ok(match array.data_type() {
+        DataType::Null => vec![Value::Null; array.len()],
+        DataType::Boolean => array.as_boolean().iter().map(|v| v.map_or(Value::Null, Value::Bool)).collect(),
+        DataType::Int8 => primitive::<Int8Type>(array, |v| Value::Int(v.into())),
+        DataType::Int16 => primitive::<Int16Type>(array, |v| Value::Int(v.into())),
+        DataType::Int32 => primitive::<Int32Type>(array, |v| Value::Int(v.into())),
+        DataType::Int64 => primitive::<Int64Type>(array, Value::Int),
+        DataType::UInt8 => primitive::<UInt8Type>(array, |v| Value::UInt(v.into())),
+        DataType::UInt16 => primitive::<UInt16Type>(array, |v| Value::UInt(v.into())),
+        DataType::UInt32 => primitive::<UInt32Type>(array, |v| Value::UInt(v.into())),
+        DataType::UInt64 => primitive::<UInt64Type>(array, Value::UInt),
+        DataType::Float32 => primitive::<Float32Type>(array, |v| Value::Float(v.into())),
+        DataType::Float64 => primitive::<Float64Type>(array, Value::Float),
+        DataType::Utf8 => strings(array.as_string::<i32>().iter()),
+        DataType::LargeUtf8 => strings(array.as_string::<i64>().iter()),
+        DataType::Binary => bytes(array.as_binary::<i32>().iter()),
+        DataType::LargeBinary => bytes(array.as_binary::<i64>().iter()),
+        other => return Err(Error::Arrow(format!("Column {name} has unsupported type {other}"))),
+    })
+}
+
+fn strings<'a>(cells: impl Iterator<Item = Option<&'a str>>) -> Vec<Value> {
+    cells.map(|v| v.map_or(Value::Null, |s| Value::Str(s.to_owned()))).collect()
+}
+
+fn bytes<'a>(cells: impl Iterator<Item = Option<&'a [u8]>>) -> Vec<Value> {
+    cells.map(|v| v.map_or(Value::Null, |b| Value::Bytes(b.to_vec()))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Side {
+        Buy,
+        Sell,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Fill {
+        id: u64,
+        delta: i32,
+        side: Side,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_record_batch_round_trip() {
+        let fills = vec![
+            Fill { id: 1, delta: -5, side: Side::Buy, note: None },
+            Fill { id: 2, delta: 7, side: Side::Sell, note: Some("late".to_string()) },
+        ];
+        let batch = to_record_batch(&fills).unwrap();
+        let types: Vec<_> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| (f.name().clone(), f.data_type().clone(), f.is_nullable()))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("id".to_string(), DataType::UInt64, false),
+                ("delta".to_string(), DataType::Int64, false),
+                ("side".to_string(), DataType::Utf8, false),
+                ("note".to_string(), DataType::Utf8, true),
+            ]
+        );
+        assert_eq!(from_record_batch::<Fill>(&batch).unwrap(), fills);
+
+        // Self-describing payloads convert without the Rust type
+        let payload = record_batch_to_payload(&batch).unwrap();
+        assert_eq!(payload_to_record_batch(&payload).unwrap(), batch);
+        assert!(to_record_batch(&[vec![1u8]]).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Async deserialization over tokio readers
+//!
+//! Input is read with non-blocking I/O into memory and then decoded, so
+//! values can still borrow from it. What has been read is kept in the
+//! [`AsyncDeserializer`], so a read that is cancelled can be resumed.
+
+use serde::de::{Deserialize, DeserializeOwned};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::stream::StreamDeserializer;
+
+/// Reads a payload or stream from an async reader for decoding
+pub struct AsyncDeserializer {
+    buffer: Vec<u8>,
+    config: Config,
+}
+
+impl AsyncDeserializer {
+    /// Read everything from `reader`
+    pub async fn from_reader<R>(reader: R) -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        Self::from_reader_with_config(reader, Config::new()).await
+    }
+
+    /// Read everything from `reader`, decoding with the given options
+    pub async fn from_reader_with_config<R>(mut reader: R, config: Config) -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut de = Self::with_config(config);
+        de.read_from(&mut reader).await?;
+        Ok(de)
+    }
+
+    /// Create an empty deserializer to fill with [`read_from`](Self::read_from)
+    pub fn new() -> Self {
+        Self::with_config(Config::new())
+    }
+
+    /// Create an empty deserializer decoding with the given options
+    pub fn with_config(config: Config) -> Self {
+        Self { buffer: Vec::new(), config }
+    }
+
+    /// Read from `reader` to its end, adding to what was already read
+    ///
+    /// # Cancel safety
+    ///
+    /// Each chunk is stored in the deserializer as soon as it is read, so
+    /// dropping this future, for example in `tokio::select!` or on a timeout,
+    /// loses nothing. Calling it again with the same reader carries on where
+    /// the cancelled call stopped.
+    pub async fn read_from<R>(&mut self, reader: &mut R) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+    {
+        while reader.read_buf(&mut self.buffer).await? != 0 {}
+        Ok(())
+    }
+
+    /// Decode the input as a single value
+    pub fn deserialize<'de, T>(&'de self) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        crate::from_bytes_with_config(&self.buffer, self.config)
+    }
+
+    /// Iterate over the input as a stream of values, such as one written by
+    /// an [`AsyncSerializer`](crate::AsyncSerializer)
+    pub fn values<'de, T>(&'de self) -> Result<StreamDeserializer<'de, T>>
+    where
+        T: Deserialize<'de>,
+    {
+        StreamDeserializer::with_config(&self.buffer, self.config)
+    }
+
+    /// The bytes read from the input
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Default for AsyncDeserializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Deserialize a value from bytes
+///
+/// Decoding never blocks, so this is [`from_bytes`](crate::from_bytes) for
+/// use in async code.
+pub async fn from_bytes_async<T>(bytes: &[u8]) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    crate::from_bytes(bytes)
+}
+
+/// Deserialize a value from an async reader
+///
+/// This is not cancel safe: the bytes read so far live in the returned
+/// future, so dropping it loses them. Where cancellation is expected, keep an
+/// [`AsyncDeserializer`] and call [`read_from`](AsyncDeserializer::read_from)
+/// again after a cancelled read, or read frames with a
+/// [`MessageStream`](crate::MessageStream).
+pub async fn from_reader_async<R, T>(reader: R) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    AsyncDeserializer::from_reader(reader).await?.deserialize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AsyncSerializer;
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use tokio::io::ReadBuf;
+
+    // Yields one byte per read, and stalls before every other one
+    struct Dribble<'a> {
+        data: &'a [u8],
+        stalled: bool,
+    }
+
+    impl AsyncRead for Dribble<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.stalled = !self.stalled;
+            if self.stalled {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            if let Some((&byte, rest)) = self.data.split_first() {
+                buf.put_slice(&[byte]);
+                self.data = rest;
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_roundtrip() {
+        let bytes = crate::to_bytes(&vec!["a".to_string(), "b".to_string()]).unwrap();
+        let value: Vec<String> = from_reader_async(bytes.as_slice()).await.unwrap();
+        assert_eq!(value, ["a", "b"]);
+
+        let mut serializer = AsyncSerializer::new(Vec::new());
+        for i in 0..3u16 {
+            serializer.serialize(&i).await.unwrap();
+        }
+        let stream = serializer.finish().await.unwrap();
+        let de = AsyncDeserializer::from_reader(stream.as_slice()).await.unwrap();
+        let values: Vec<u16> = de.values().unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(values, [0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_resume_cancelled_read() {
+        use tokio::io::AsyncWriteExt;
+
+        let bytes = crate::to_bytes(&vec!["a".to_string(), "b".to_string()]).unwrap();
+        let (mut tx, mut rx) = tokio::io::duplex(64);
+        tx.write_all(&bytes[..3]).await.unwrap();
+
+        // The read takes the first bytes, then waits for more and is dropped
+        let mut de = AsyncDeserializer::new();
+        tokio::select! {
+            biased;
+            _ = de.read_from(&mut rx) => unreachable!(),
+            _ = std::future::ready(()) => {}
+        }
+        assert_eq!(de.as_bytes(), &bytes[..3]);
+
+        tx.write_all(&bytes[3..]).await.unwrap();
+        drop(tx);
+        de.read_from(&mut rx).await.unwrap();
+        let value: Vec<String> = de.deserialize().unwrap();
+        assert_eq!(value, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_read_in_single_bytes() {
+        let value = (vec!["a".to_string(), "b".repeat(200)], Some(-7i64), [1.5f32; 3]);
+        let bytes = crate::to_bytes(&value).unwrap();
+
+        let mut reader = Dribble { data: &bytes, stalled: false };
+        let mut de = AsyncDeserializer::new();
+        de.read_from(&mut reader).await.unwrap();
+        assert_eq!(de.as_bytes(), bytes);
+        let decoded: (Vec<String>, Option<i64>, [f32; 3]) = de.deserialize().unwrap();
+        assert_eq!(decoded, crate::from_bytes::<(Vec<String>, Option<i64>, [f32; 3])>(&bytes).unwrap());
+        assert_eq!(decoded, value);
+
+        let decoded: (Vec<String>, Option<i64>, [f32; 3]) =
+            from_reader_async(Dribble { data: &bytes, stalled: false }).await.unwrap();
+        assert_eq!(decoded, value);
+    }
+}
@@ -0,0 +1,251 @@
+//! Async serialization over tokio writers
+//!
+//! Values are encoded in memory and written with non-blocking I/O. An
+//! [`AsyncSerializer`] writes a stream of values after a single header, in
+//! the format read by [`StreamDeserializer`](crate::StreamDeserializer).
+
+use serde::Serialize;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::buffer::WriteBuffer;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::header::Header;
+use crate::ser::Serializer;
+
+/// Writes a stream of values to an async writer
+pub struct AsyncSerializer<W> {
+    writer: W,
+    config: Config,
+    scratch: WriteBuffer,
+    header_written: bool,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncSerializer<W> {
+    /// Create a serializer writing to `writer`
+    pub fn new(writer: W) -> Self {
+        Self::with_config(writer, Config::new())
+    }
+
+    /// Create a serializer using the given configuration
+    ///
+    /// Checksums cannot be streamed and are rejected on the first write.
+    pub fn with_config(writer: W, config: Config) -> Self {
+        Self {
+            writer,
+            config,
+            scratch: WriteBuffer::new(),
+            header_written: false,
+        }
+    }
+
+    /// Append a value to the stream
+    pub async fn serialize<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.write_header().await?;
+        self.encode(value)?;
+        self.writer.write_all(self.scratch.as_slice()).await?;
+        Ok(())
+    }
+
+    /// Flush buffered output of the inner writer
+    pub async fn flush(&mut self) -> Result<()> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Write the header if nothing was written, flush and return the writer
+    pub async fn finish(mut self) -> Result<W> {
+        self.write_header().await?;
+        self.writer.flush().await?;
+        Ok(self.writer)
+    }
+
+    async fn write_header(&mut self) -> Result<()> {
+        if self.header_written {
+            return Ok(());
+        }
+        if self.config.checksum().is_some() {
+            return Err(Error::InvalidFormat(
+                "Checksums are not supported by async serializers".to_string(),
+            ));
+        }
+        let mut header = Vec::with_capacity(6);
+        self.config.header().write_to(&mut header);
+        self.writer.write_all(&header).await?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    // Encode a value's body into the scratch buffer
+    fn encode<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let scratch = core::mem::take(&mut self.scratch);
+        let mut serializer = Serializer::with_buffer(scratch, self.config);
+        let result = value.serialize(&mut serializer);
+        self.scratch = serializer.into_buffer();
+        result
+    }
+}
+
+/// Serialize a value to bytes
+///
+/// Encoding never blocks, so this is [`to_bytes`](crate::to_bytes) for use
+/// in async code.
+pub async fn to_bytes_async<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    crate::to_bytes(&value)
+}
+
+/// Serialize a value to an async writer
+pub async fn to_writer_async<W, T>(mut writer: W, value: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize + ?Sized,
+{
+    let bytes = crate::to_bytes(&value)?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Serialize the items of an iterator as a sequence to an async writer
+///
+/// With an exact length each item is written as it is produced; otherwise
+/// the sequence is buffered until the length is known. The output is the
+/// same as [`to_bytes_from_iter`](crate::to_bytes_from_iter).
+pub async fn to_writer_from_iter_async<W, I>(mut writer: W, iter: I) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let iter = iter.into_iter();
+    let len = match iter.size_hint() {
+        (lower, Some(upper)) if lower == upper => lower,
+        _ => {
+            let bytes = crate::to_bytes_from_iter(iter)?;
+            writer.write_all(&bytes).await?;
+            writer.flush().await?;
+            return Ok(());
+        }
+    };
+
+    let mut prefix = Vec::with_capacity(16);
+    Header::new(Config::new().header_flags()).write_to(&mut prefix);
+    let mut length = WriteBuffer::with_capacity(10);
+    length.write_varint(len as u64)?;
+    prefix.extend_from_slice(length.as_slice());
+    writer.write_all(&prefix).await?;
+
+    let mut serializer = AsyncSerializer::new(&mut writer);
+    serializer.header_written = true;
+    let mut count = 0;
+    for item in iter {
+        count += 1;
+        if count > len {
+            break;
+        }
+        serializer.serialize(&item).await?;
+    }
+    if count != len {
+        return Err(Error::Serde(format!(
+            "Iterator yielded {count} items, but its size hint promised {len}"
+        )));
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use std::io;
+
+    // Accepts at most three bytes per write, and stalls before every other one
+    #[derive(Default)]
+    struct Trickle {
+        written: Vec<u8>,
+        stalled: bool,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.stalled = !self.stalled;
+            if self.stalled {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_stream() {
+        let mut serializer = AsyncSerializer::new(Vec::new());
+        serializer.serialize("one").await.unwrap();
+        serializer.serialize(&2u32).await.unwrap();
+        let bytes = serializer.finish().await.unwrap();
+
+        let mut stream = crate::StreamSerializer::new(Vec::new(), Config::new()).unwrap();
+        stream.serialize("one").unwrap();
+        stream.serialize(&2u32).unwrap();
+        assert_eq!(bytes, stream.into_inner().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_partial_writes() {
+        let values = vec!["first".to_string(), "x".repeat(300), String::new()];
+        let mut serializer = AsyncSerializer::new(Trickle::default());
+        for value in &values {
+            serializer.serialize(value).await.unwrap();
+        }
+        let bytes = serializer.finish().await.unwrap().written;
+
+        let mut stream = crate::StreamSerializer::new(Vec::new(), Config::new()).unwrap();
+        for value in &values {
+            stream.serialize(value).unwrap();
+        }
+        assert_eq!(bytes, stream.into_inner().unwrap());
+
+        let decoded = crate::AsyncDeserializer::from_reader(bytes.as_slice()).await.unwrap();
+        let decoded = decoded.values::<String>().unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(decoded, values);
+
+        let mut writer = Trickle::default();
+        to_writer_async(&mut writer, &values).await.unwrap();
+        assert_eq!(writer.written, crate::to_bytes(&values).unwrap());
+        let decoded: Vec<String> = crate::from_reader_async(writer.written.as_slice()).await.unwrap();
+        assert_eq!(decoded, values);
+    }
+
+    #[tokio::test]
+    async fn test_async_from_iter() {
+        let expected = crate::to_bytes(&(0..50u64).collect::<Vec<_>>()).unwrap();
+
+        let mut out = Vec::new();
+        to_writer_from_iter_async(&mut out, 0..50u64).await.unwrap();
+        assert_eq!(out, expected);
+
+        let mut out = Vec::new();
+        to_writer_from_iter_async(&mut out, (0..100u64).filter(|i| i % 2 == 1).map(|i| i / 2))
+            .await
+            .unwrap();
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,203 @@
+//! Benchmark harness comparing nanobit with other serde formats
+//!
+//! [`compare`] benchmarks serializing and deserializing one value with
+//! every [`Format`] in a Criterion group, so any `Serialize` +
+//! `DeserializeOwned` type can be measured against bincode, postcard and
+//! MessagePack:
+//!
+//! ```rust,no_run
+//! use criterion::{criterion_group, criterion_main, Criterion};
+//! use nanobit::bench::{compare, Format};
+//!
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Order {
+//!     id: u64,
+//!     items: Vec<(String, u32)>,
+//! }
+//!
+//! fn orders(c: &mut Criterion) {
+//!     let order = Order { id: 7, items: vec![("apple".to_string(), 3); 20] };
+//!     for (format, size) in Format::sizes(&order) {
+//!         println!("{format}: {size} bytes");
+//!     }
+//!     compare(c, "order", &order);
+//! }
+//!
+//! criterion_group!(benches, orders);
+//! criterion_main!(benches);
+//! ```
+//!
+//! The crate's own suite in `benches/comparison.rs` runs small structs,
+//! large vectors, string-heavy and map-heavy workloads through the same
+//! harness.
+
+use core::fmt;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// A serialization format under comparison
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// nanobit with the given configuration
+    Nanobit(Config),
+    /// bincode 1.x with its default options
+    Bincode,
+    /// postcard
+    Postcard,
+    /// MessagePack through rmp-serde, with structs as arrays
+    MessagePack,
+}
+
+impl Format {
+    /// Every format, with nanobit in its default configuration
+    pub const ALL: [Format; 4] = [Format::Nanobit(Config::new()), Format::Bincode, Format::Postcard, Format::MessagePack];
+
+    /// Short name used in benchmark ids
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Nanobit(_) => "nanobit",
+            Format::Bincode => "bincode",
+            Format::Postcard => "postcard",
+            Format::MessagePack => "rmp-serde",
+        }
+    }
+
+    /// Serialize `value` in this format
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Format::Nanobit(config) => crate::to_bytes_with_config(value, *config),
+            Format::Bincode => bincode::serialize(value).map_err(|e| Error::Serde(format!("bincode: {e}"))),
+            Format::Postcard => postcard::to_allocvec(value).map_err(|e| Error::Serde(format!("postcard: {e}"))),
+            Format::MessagePack => rmp_serde::to_vec(value).map_err(|e| Error::Serde(format!("MessagePack: {e}"))),
+        }
+    }
+
+    /// Deserialize a value this format wrote
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        match self {
+            Format::Nanobit(config) => crate::from_bytes_with_config(bytes, *config),
+            Format::Bincode => bincode::deserialize(bytes).map_err(|e| Error::Serde(format!("bincode: {e}"))),
+            Format::Postcard => postcard::from_bytes(bytes).map_err(|e| Error::Serde(format!("postcard: {e}"))),
+            Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| Error::Serde(format!("MessagePack: {e}"))),
+        }
+    }
+
+    /// Encoded size of `value` in each of [`Format::ALL`]
+    ///
+    /// # Panics
+    ///
+    /// If a format cannot serialize `value`.
+    pub fn sizes<T: Serialize>(value: &T) -> Vec<(Format, usize)> {
+        Format::ALL.iter().map(|format| (*format, format.encoded(value).len())).collect()
+    }
+
+    fn encoded<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        self.encode(value).unwrap_or_else(|e| panic!("{self} cannot serialize the value: {e}"))
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Benchmark `value` with every format in [`Format::ALL`]
+///
+/// Adds `serialize` and `deserialize` benchmarks for each format to the
+/// Criterion groups `{name}/serialize` and `{name}/deserialize`.
+///
+/// # Panics
+///
+/// If a format cannot serialize `value` or decode it back.
+pub fn compare<T>(c: &mut Criterion, name: &str, value: &T)
+where
+    T: Serialize + DeserializeOwned,
+{
+    compare_formats(c, name, value, &Format::ALL)
+}
+
+/// Benchmark `value` with the given formats
+///
+/// Use it to compare nanobit configurations, for instance
+/// `Format::Nanobit(Config::new().with_string_table(true))`, against the
+/// others. Benchmark ids are the format names, with the position in
+/// `formats` appended when a name repeats.
+///
+/// # Panics
+///
+/// If a format cannot serialize `value` or decode it back.
+pub fn compare_formats<T>(c: &mut Criterion, name: &str, value: &T, formats: &[Format])
+where
+    T: Serialize + DeserializeOwned,
+{
+    let payloads: Vec<(String, Format, Vec<u8>)> = formats
+        .iter()
+        .enumerate()
+        .map(|(i, format)| {
+            let repeated = formats.iter().filter(|other| other.name() == format.name()).count() > 1;
+            let id = if repeated { format!("{format}-{i}") } else { format.to_string() };
+            let bytes = format.encoded(value);
+            if let Err(e) = format.decode::<T>(&bytes) {
+                panic!("{format} cannot decode its own payload: {e}");
+            }
+            (id, *format, bytes)
+        })
+        .collect();
+
+    let mut group = c.benchmark_group(format!("{name}/serialize"));
+    for (id, format, bytes) in &payloads {
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(id), value, |b, value| {
+            b.iter(|| format.encode(value).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{name}/deserialize"));
+    for (id, format, bytes) in &payloads {
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(id), bytes, |b, bytes| {
+            b.iter(|| format.decode::<T>(bytes).unwrap())
+        });
+    }
+    group.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Order {
+        id: u64,
+        note: Option<String>,
+        items: BTreeMap<String, u32>,
+    }
+
+    #[test]
+    fn test_formats_roundtrip() {
+        let order = Order {
+            id: 300,
+            note: Some("leave at door".to_string()),
+            items: BTreeMap::from([("apple".to_string(), 3), ("pear".to_string(), 1)]),
+        };
+        for format in Format::ALL.into_iter().chain([Format::Nanobit(Config::new().with_tagged_fields(true))]) {
+            let bytes = format.encode(&order).unwrap();
+            assert_eq!(format.decode::<Order>(&bytes).unwrap(), order, "{format}");
+        }
+
+        let sizes = Format::sizes(&order);
+        assert_eq!(sizes.len(), 4);
+        assert_eq!(sizes[0], (Format::ALL[0], crate::to_bytes(&order).unwrap().len()));
+        assert!(Format::Postcard.decode::<Order>(b"\xff").is_err());
+    }
+}
@@ -1,4 +1,52 @@
 //! High-performance buffer implementations for binary I/O
+//!
+//! [`WriteBuffer`] and [`ReadBuffer`] are also a low-level codec for
+//! writing the nanobit wire format by hand, without serde. In the default
+//! (plain) encoding:
+//!
+//! - integers and floats are little-endian and fixed-width
+//! - lengths, map sizes, struct field counts and enum variant indexes are
+//!   varints ([`WriteBuffer::write_seq_header`], [`WriteBuffer::write_variant`])
+//! - strings and byte strings are a varint length followed by the bytes
+//! - `Option` is a `0` or `1` byte, followed by the value when present
+//! - tuples and fixed-size arrays are written as sequences
+//!
+//! These rules are stable: data written with them is read by
+//! [`from_bytes`](crate::from_bytes) once a header is added, and the other
+//! way around.
+//!
+//! ```rust
+//! use nanobit::buffer::{ReadBuffer, WriteBuffer};
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! enum Event {
+//!     Ping,
+//!     Order { id: u64, items: Vec<String>, note: Option<String> },
+//! }
+//!
+//! let mut buf = WriteBuffer::new();
+//! buf.write_variant(1)?;
+//! buf.write_seq_header(3)?; // field count
+//! buf.write_u64(42)?;
+//! buf.write_seq_header(2)?;
+//! buf.write_str("apple")?;
+//! buf.write_str("pear")?;
+//! buf.write_option(None::<&str>, WriteBuffer::write_str)?;
+//!
+//! // Identical to the serde body, after the 5-byte header
+//! let event = Event::Order { id: 42, items: vec!["apple".into(), "pear".into()], note: None };
+//! assert_eq!(buf.as_slice(), &nanobit::to_bytes(&event)?[5..]);
+//!
+//! let mut reader = ReadBuffer::new(buf.as_slice());
+//! assert_eq!(reader.read_variant()?, 1);
+//! assert_eq!(reader.read_seq_header()?, 3);
+//! assert_eq!(reader.read_u64()?, 42);
+//! let items = (0..reader.read_seq_header()?).map(|_| reader.read_str()).collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(items, ["apple", "pear"]);
+//! assert_eq!(reader.read_option(ReadBuffer::read_str)?, None);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
 
 #[cfg(not(feature = "std"))]
 use alloc::{vec, vec::Vec};
@@ -26,6 +74,15 @@ impl WriteBuffer {
         }
     }
 
+    // Wrap an existing allocation
+    #[cfg(feature = "bytes")]
+    pub(crate) fn from_vec(data: Vec<u8>) -> Self {
+        Self {
+            capacity: data.capacity(),
+            data,
+        }
+    }
+
     /// Write a single byte
     #[inline]
     pub fn write_u8(&mut self, value: u8) -> Result<()> {
@@ -99,6 +156,52 @@ impl WriteBuffer {
         self.write_u8(value as u8)
     }
 
+    /// Insert a varint at an earlier position, shifting everything after it
+    ///
+    /// This moves every byte after `position`; to back-patch lengths while
+    /// writing, prefer [`reserve_varint`](Self::reserve_varint).
+    pub fn insert_varint(&mut self, position: usize, value: u64) -> Result<()> {
+        if position > self.data.len() {
+            return Err(Error::BufferOverflow);
+        }
+
+        let mut encoded = [0u8; 10];
+        let len = encode_varint(value, &mut encoded);
+        self.data.splice(position..position, encoded[..len].iter().copied());
+        Ok(())
+    }
+
+    /// Reserve a byte for a varint that is only known once the data
+    /// following it has been written, returning its position
+    ///
+    /// Fill it in with [`patch_varint`](Self::patch_varint).
+    #[inline]
+    pub fn reserve_varint(&mut self) -> usize {
+        self.data.push(0);
+        self.data.len() - 1
+    }
+
+    /// Write a varint into the byte reserved at `position`
+    ///
+    /// Values under 128 fit the reserved byte and are written in place, so
+    /// short lengths never move the data after them. Larger values move it
+    /// once by the extra bytes they need.
+    pub fn patch_varint(&mut self, position: usize, value: u64) -> Result<()> {
+        if position >= self.data.len() {
+            return Err(Error::BufferOverflow);
+        }
+
+        let mut encoded = [0u8; 10];
+        let len = encode_varint(value, &mut encoded);
+        if len > 1 {
+            let end = self.data.len();
+            self.data.resize(end + len - 1, 0);
+            self.data.copy_within(position + 1..end, position + len);
+        }
+        self.data[position..position + len].copy_from_slice(&encoded[..len]);
+        Ok(())
+    }
+
     /// Write raw bytes
     #[inline]
     pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
@@ -117,6 +220,58 @@ impl WriteBuffer {
         self.write_byte_slice(s.as_bytes())
     }
 
+    /// Write a char as its UTF-8 bytes, without a length prefix
+    #[inline]
+    pub fn write_char_utf8(&mut self, ch: char) -> Result<()> {
+        self.write_bytes(ch.encode_utf8(&mut [0; 4]).as_bytes())
+    }
+
+    /// Write a bool as a `0` or `1` byte
+    #[inline]
+    pub fn write_bool(&mut self, value: bool) -> Result<()> {
+        self.write_u8(value as u8)
+    }
+
+    /// Write a char as a little-endian u32
+    #[inline]
+    pub fn write_char(&mut self, ch: char) -> Result<()> {
+        self.write_u32(ch as u32)
+    }
+
+    /// Write an `Option`, using `write` for the value when present
+    #[inline]
+    pub fn write_option<T>(
+        &mut self,
+        value: Option<T>,
+        write: impl FnOnce(&mut Self, T) -> Result<()>,
+    ) -> Result<()> {
+        match value {
+            None => self.write_u8(0),
+            Some(value) => {
+                self.write_u8(1)?;
+                write(self, value)
+            }
+        }
+    }
+
+    /// Write the element count that starts a sequence, tuple or struct
+    #[inline]
+    pub fn write_seq_header(&mut self, len: usize) -> Result<()> {
+        self.write_varint(len as u64)
+    }
+
+    /// Write the entry count that starts a map
+    #[inline]
+    pub fn write_map_header(&mut self, len: usize) -> Result<()> {
+        self.write_varint(len as u64)
+    }
+
+    /// Write the index of an enum variant, which precedes its fields
+    #[inline]
+    pub fn write_variant(&mut self, index: u32) -> Result<()> {
+        self.write_varint(index as u64)
+    }
+
     /// Get the current length of the buffer
     #[inline]
     pub fn len(&self) -> usize {
@@ -147,6 +302,12 @@ impl WriteBuffer {
         &self.data
     }
 
+    // Mutable view of the contents, for reordering written data in place
+    #[inline]
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
     /// Convert the buffer into a `Vec<u8`>
     pub fn into_vec(self) -> Vec<u8> {
         self.data
@@ -156,6 +317,24 @@ impl WriteBuffer {
     pub fn clear(&mut self) {
         self.data.clear();
     }
+
+    // Drop everything after the first `len` bytes
+    #[inline]
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+    }
+}
+
+// Encode `value` as a varint into `out`, returning its length
+fn encode_varint(mut value: u64, out: &mut [u8; 10]) -> usize {
+    let mut len = 0;
+    while value >= 0x80 {
+        out[len] = (value as u8) | 0x80;
+        value >>= 7;
+        len += 1;
+    }
+    out[len] = value as u8;
+    len + 1
 }
 
 impl Default for WriteBuffer {
@@ -164,6 +343,103 @@ impl Default for WriteBuffer {
     }
 }
 
+#[cfg(feature = "std")]
+impl std::io::Write for WriteBuffer {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.data.extend_from_slice(buf);
+        Ok(())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Number of idle buffers a [`BufferPool`] keeps by default
+#[cfg(feature = "std")]
+pub const DEFAULT_POOL_SIZE: usize = 64;
+
+/// A thread-safe pool of reusable [`WriteBuffer`]s
+///
+/// Serializing into a recycled buffer skips allocating a fresh scratch
+/// buffer for every call; see [`to_bytes_pooled`](crate::to_bytes_pooled).
+/// Buffers that grew far past the pool's buffer capacity are shrunk before
+/// being kept, so one large value does not pin its memory.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: std::sync::Mutex<Vec<WriteBuffer>>,
+    buffer_capacity: usize,
+    max_buffers: usize,
+}
+
+#[cfg(feature = "std")]
+impl BufferPool {
+    /// Create a pool of default-sized buffers
+    pub fn new() -> Self {
+        Self::with_limits(crate::DEFAULT_BUFFER_SIZE, DEFAULT_POOL_SIZE)
+    }
+
+    /// Create a pool handing out buffers of `buffer_capacity` bytes and
+    /// keeping at most `max_buffers` idle ones
+    pub fn with_limits(buffer_capacity: usize, max_buffers: usize) -> Self {
+        Self {
+            buffers: std::sync::Mutex::new(Vec::new()),
+            buffer_capacity,
+            max_buffers,
+        }
+    }
+
+    /// Take an empty buffer from the pool, allocating one if none is idle
+    pub fn acquire(&self) -> WriteBuffer {
+        self.lock()
+            .pop()
+            .unwrap_or_else(|| WriteBuffer::with_capacity(self.buffer_capacity))
+    }
+
+    /// Return a buffer to the pool for reuse
+    pub fn release(&self, mut buffer: WriteBuffer) {
+        buffer.clear();
+        if buffer.data.capacity() > self.buffer_capacity.saturating_mul(8) {
+            buffer.data.shrink_to(self.buffer_capacity);
+        }
+        let mut buffers = self.lock();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+
+    /// Number of idle buffers in the pool
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check if the pool has no idle buffers
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // A panic while holding the lock cannot leave the list inconsistent
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<WriteBuffer>> {
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// A high-performance read buffer for binary deserialization
 #[derive(Debug)]
 pub struct ReadBuffer<'a> {
@@ -179,11 +455,16 @@ impl<'a> ReadBuffer<'a> {
 
     /// Read a single byte
     #[inline]
+    #[cfg_attr(feature = "fast-unsafe", allow(unsafe_code))]
     pub fn read_u8(&mut self) -> Result<u8> {
         if self.position >= self.data.len() {
             return Err(Error::UnexpectedEof);
         }
+        #[cfg(not(feature = "fast-unsafe"))]
         let value = self.data[self.position];
+        // SAFETY: `position < data.len()` was checked above
+        #[cfg(feature = "fast-unsafe")]
+        let value = unsafe { *self.data.get_unchecked(self.position) };
         self.position += 1;
         Ok(value)
     }
@@ -251,7 +532,53 @@ impl<'a> ReadBuffer<'a> {
     }
 
     /// Read a variable-length unsigned integer (varint)
+    #[inline]
     pub fn read_varint(&mut self) -> Result<u64> {
+        // Single-byte values are the most common by far
+        if let Some(&byte) = self.data.get(self.position) {
+            if byte < 0x80 {
+                self.position += 1;
+                return Ok(byte as u64);
+            }
+        }
+        if let Some(value) = self.read_varint_window() {
+            return Ok(value);
+        }
+        self.read_varint_slow()
+    }
+
+    /// Read up to `out.len()` varints, stopping early at the end of input
+    ///
+    /// Returns the number of values read.
+    pub fn read_varints(&mut self, out: &mut [u64]) -> Result<usize> {
+        for (count, slot) in out.iter_mut().enumerate() {
+            *slot = match self.read_varint_window() {
+                Some(value) => value,
+                None if self.has_remaining() => self.read_varint_slow()?,
+                None => return Ok(count),
+            };
+        }
+        Ok(out.len())
+    }
+
+    // Decode a varint from a fixed-size window, with no bounds check per
+    // byte. Returns `None` near the end of input or for 10-byte varints.
+    #[inline]
+    fn read_varint_window(&mut self) -> Option<u64> {
+        let bytes = self.data.get(self.position..)?.first_chunk::<9>()?;
+        let mut value = 0u64;
+        for (i, &byte) in bytes.iter().enumerate() {
+            value |= ((byte & 0x7F) as u64) << (7 * i);
+            if byte < 0x80 {
+                self.position += i + 1;
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    #[cold]
+    fn read_varint_slow(&mut self) -> Result<u64> {
         let mut result = 0u64;
         let mut shift = 0;
 
@@ -275,11 +602,17 @@ impl<'a> ReadBuffer<'a> {
 
     /// Read a specific number of bytes
     #[inline]
+    #[cfg_attr(feature = "fast-unsafe", allow(unsafe_code))]
     pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
-        if self.position + len > self.data.len() {
+        if len > self.remaining() {
             return Err(Error::UnexpectedEof);
         }
+        #[cfg(not(feature = "fast-unsafe"))]
         let bytes = &self.data[self.position..self.position + len];
+        // SAFETY: `position + len <= data.len()` was checked above, and
+        // `position <= data.len()` always holds so the sum cannot overflow
+        #[cfg(feature = "fast-unsafe")]
+        let bytes = unsafe { self.data.get_unchecked(self.position..self.position + len) };
         self.position += len;
         Ok(bytes)
     }
@@ -290,9 +623,90 @@ impl<'a> ReadBuffer<'a> {
         self.read_bytes(len)
     }
 
+    /// Read a char written by [`WriteBuffer::write_char_utf8`]
+    pub fn read_char_utf8(&mut self) -> Result<char> {
+        let len = match self.peek_u8()? {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return Err(Error::InvalidFormat("Invalid char value".to_string())),
+        };
+        core::str::from_utf8(self.read_bytes(len)?)
+            .ok()
+            .and_then(|s| s.chars().next())
+            .ok_or_else(|| Error::InvalidFormat("Invalid char value".to_string()))
+    }
+
+    /// Read a bool written by [`WriteBuffer::write_bool`]
+    #[inline]
+    pub fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    /// Read a char written by [`WriteBuffer::write_char`]
+    #[inline]
+    pub fn read_char(&mut self) -> Result<char> {
+        char::from_u32(self.read_u32()?)
+            .ok_or_else(|| Error::InvalidFormat("Invalid char value".to_string()))
+    }
+
+    /// Read an `Option`, using `read` for the value when present
+    #[inline]
+    pub fn read_option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.read_u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            _ => Err(Error::InvalidFormat("Invalid option tag".to_string())),
+        }
+    }
+
+    /// Read the element count that starts a sequence, tuple or struct
+    ///
+    /// The count comes from the input: check it against [`remaining`]
+    /// before reserving space for that many elements.
+    ///
+    /// [`remaining`]: Self::remaining
+    #[inline]
+    pub fn read_seq_header(&mut self) -> Result<usize> {
+        self.read_length()
+    }
+
+    /// Read the entry count that starts a map
+    #[inline]
+    pub fn read_map_header(&mut self) -> Result<usize> {
+        self.read_length()
+    }
+
+    /// Read the index of an enum variant
+    #[inline]
+    pub fn read_variant(&mut self) -> Result<u32> {
+        u32::try_from(self.read_varint()?)
+            .map_err(|_| Error::InvalidFormat("Variant index out of range".to_string()))
+    }
+
+    fn read_length(&mut self) -> Result<usize> {
+        usize::try_from(self.read_varint()?)
+            .map_err(|_| Error::InvalidFormat("Length out of range".to_string()))
+    }
+
     /// Read a length-prefixed string
     pub fn read_str(&mut self) -> Result<&'a str> {
-        let bytes = self.read_byte_slice()?;
+        let len = self.read_varint()? as usize;
+        self.read_str_bytes(len)
+    }
+
+    // Read a string of `len` bytes whose length prefix was already read
+    #[inline]
+    #[cfg_attr(feature = "fast-unsafe", allow(unsafe_code))]
+    pub(crate) fn read_str_bytes(&mut self, len: usize) -> Result<&'a str> {
+        let bytes = self.read_bytes(len)?;
+        // Most strings on the wire are ASCII, which needs no decoding
+        #[cfg(feature = "fast-unsafe")]
+        if bytes.is_ascii() {
+            // SAFETY: ASCII bytes are always valid UTF-8
+            return Ok(unsafe { core::str::from_utf8_unchecked(bytes) });
+        }
         core::str::from_utf8(bytes).map_err(|_| {
             Error::InvalidFormat("Invalid UTF-8 string".to_string())
         })
@@ -328,7 +742,7 @@ impl<'a> ReadBuffer<'a> {
     /// Skip a number of bytes
     #[inline]
     pub fn skip(&mut self, count: usize) -> Result<()> {
-        if self.position + count > self.data.len() {
+        if count > self.remaining() {
             return Err(Error::UnexpectedEof);
         }
         self.position += count;
@@ -342,6 +756,28 @@ impl<'a> ReadBuffer<'a> {
     }
 }
 
+#[cfg(feature = "std")]
+impl std::io::Read for ReadBuffer<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = &self.data[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::BufRead for ReadBuffer<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(&self.data[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = (self.position + amt).min(self.data.len());
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -359,8 +795,8 @@ mod tests {
         buf.write_i16(-1234).unwrap();
         buf.write_i32(-123456789).unwrap();
         buf.write_i64(-123456789012345).unwrap();
-        buf.write_f32(3.14159).unwrap();
-        buf.write_f64(2.718281828459045).unwrap();
+        buf.write_f32(core::f32::consts::PI).unwrap();
+        buf.write_f64(core::f64::consts::E).unwrap();
         buf.write_str("Hello, NanoBit!").unwrap();
 
         // Read them back
@@ -374,8 +810,8 @@ mod tests {
         assert_eq!(reader.read_i16().unwrap(), -1234);
         assert_eq!(reader.read_i32().unwrap(), -123456789);
         assert_eq!(reader.read_i64().unwrap(), -123456789012345);
-        assert!((reader.read_f32().unwrap() - 3.14159).abs() < f32::EPSILON);
-        assert!((reader.read_f64().unwrap() - 2.718281828459045).abs() < f64::EPSILON);
+        assert!((reader.read_f32().unwrap() - core::f32::consts::PI).abs() < f32::EPSILON);
+        assert!((reader.read_f64().unwrap() - core::f64::consts::E).abs() < f64::EPSILON);
         assert_eq!(reader.read_str().unwrap(), "Hello, NanoBit!");
     }
 
@@ -397,6 +833,80 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_varint_batch() {
+        let values: Vec<u64> = (0..64).map(|i| (1u64 << i) - 1).chain([u64::MAX, 0, 300]).collect();
+        let mut buf = WriteBuffer::new();
+        for &value in &values {
+            buf.write_varint(value).unwrap();
+        }
+
+        // Windowed reads near the end of input fall back to byte reads
+        let mut reader = ReadBuffer::new(buf.as_slice());
+        let mut out = [0u64; 50];
+        assert_eq!(reader.read_varints(&mut out).unwrap(), 50);
+        assert_eq!(out[..], values[..50]);
+        assert_eq!(reader.read_varints(&mut out).unwrap(), values.len() - 50);
+        assert_eq!(out[..values.len() - 50], values[50..]);
+        assert!(!reader.has_remaining());
+
+        // Truncated varints are still errors
+        assert!(ReadBuffer::new(&[0x80; 9]).read_varints(&mut out).is_err());
+    }
+
+    #[test]
+    fn test_codec_helpers() {
+        let mut buf = WriteBuffer::new();
+        buf.write_option(Some('é'), WriteBuffer::write_char).unwrap();
+        buf.write_map_header(1).unwrap();
+        buf.write_bool(true).unwrap();
+        buf.write_varint(u64::from(u32::MAX) + 1).unwrap();
+        buf.write_u8(2).unwrap();
+
+        let mut reader = ReadBuffer::new(buf.as_slice());
+        assert_eq!(reader.read_option(ReadBuffer::read_char).unwrap(), Some('é'));
+        assert_eq!(reader.read_map_header().unwrap(), 1);
+        assert!(reader.read_bool().unwrap());
+        assert!(reader.read_variant().is_err());
+        assert!(reader.read_option(ReadBuffer::read_u8).is_err());
+    }
+
+    #[test]
+    fn test_patch_varint() {
+        for value in [0u64, 127, 128, 300, u64::MAX] {
+            let mut buf = WriteBuffer::new();
+            buf.write_u8(0xAA).unwrap();
+            let position = buf.reserve_varint();
+            buf.write_bytes(&[0xBB, 0xCC]).unwrap();
+            buf.patch_varint(position, value).unwrap();
+
+            let mut expected = WriteBuffer::new();
+            expected.write_u8(0xAA).unwrap();
+            expected.write_varint(value).unwrap();
+            expected.write_bytes(&[0xBB, 0xCC]).unwrap();
+            assert_eq!(buf.as_slice(), expected.as_slice());
+        }
+
+        assert!(WriteBuffer::new().patch_varint(0, 1).is_err());
+    }
+
+    #[test]
+    fn test_insert_varint() {
+        let mut buf = WriteBuffer::new();
+        buf.write_u8(0xAA).unwrap();
+        buf.write_u8(0xBB).unwrap();
+
+        buf.insert_varint(1, 300).unwrap();
+        assert_eq!(buf.as_slice(), &[0xAA, 0xAC, 0x02, 0xBB]);
+
+        let mut reader = ReadBuffer::new(buf.as_slice());
+        assert_eq!(reader.read_u8().unwrap(), 0xAA);
+        assert_eq!(reader.read_varint().unwrap(), 300);
+        assert_eq!(reader.read_u8().unwrap(), 0xBB);
+
+        assert!(buf.insert_varint(10, 1).is_err());
+    }
+
     #[test]
     fn test_buffer_overflow_detection() {
         let data = [1, 2, 3];
@@ -409,6 +919,13 @@ mod tests {
 
         // Should fail on 4th byte
         assert!(reader.read_u8().is_err());
+
+        // Lengths that would overflow the position are rejected, not wrapped
+        let mut reader = ReadBuffer::new(&data);
+        reader.read_u8().unwrap();
+        assert!(reader.read_bytes(usize::MAX).is_err());
+        assert!(reader.skip(usize::MAX).is_err());
+        assert!(ReadBuffer::new(&[2, 0xC3, 0x28]).read_str().is_err());
     }
 
     #[test]
@@ -427,4 +944,42 @@ mod tests {
             assert_eq!(reader.read_str().unwrap(), *expected);
         }
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::with_limits(256, 2);
+        let value = vec!["pooled".to_string(); 10];
+
+        for _ in 0..3 {
+            let bytes = crate::to_bytes_pooled(&value, &pool).unwrap();
+            assert_eq!(bytes, crate::to_bytes(&value).unwrap());
+            assert_eq!(pool.len(), 1);
+        }
+
+        // Idle buffers are capped
+        let buffers: Vec<_> = (0..3).map(|_| pool.acquire()).collect();
+        assert!(buffers.iter().all(|b| b.is_empty()));
+        buffers.into_iter().for_each(|b| pool.release(b));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_io_traits() {
+        use std::io::{Read, Write};
+
+        let mut buf = WriteBuffer::new();
+        buf.write_u8(1).unwrap();
+        write!(buf, "x={}", 5).unwrap();
+        assert_eq!(buf.as_slice(), b"\x01x=5");
+
+        let data = buf.into_vec();
+        let mut reader = ReadBuffer::new(&data);
+        assert_eq!(reader.read_u8().unwrap(), 1);
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "x=5");
+        assert!(!reader.has_remaining());
+    }
 }
@@ -0,0 +1,124 @@
+//! Bulk encoding for byte buffers
+//!
+//! Serde sees `Vec<u8>` and `[u8; N]` as sequences and writes them one
+//! element at a time. Annotate such fields with
+//! `#[serde(with = "nanobit::byte_slice")]` to write them with a single
+//! copy instead:
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, PartialEq, Debug)]
+//! struct Image {
+//!     width: u32,
+//!     #[serde(with = "nanobit::byte_slice")]
+//!     pixels: Vec<u8>,
+//!     #[serde(with = "nanobit::byte_slice")]
+//!     digest: [u8; 32],
+//! }
+//!
+//! let image = Image { width: 2, pixels: vec![0; 4096], digest: [7; 32] };
+//! let bytes = nanobit::to_bytes(&image)?;
+//! assert_eq!(nanobit::from_bytes::<Image>(&bytes)?, image);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Without type tags a byte string and a sequence of `u8` have the same
+//! encoding, and self-describing payloads convert between the two when
+//! decoding, so the annotation can be added to existing fields. It is
+//! equivalent to `serde_bytes` for `Vec<u8>`, which works as well.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{Deserializer, Error, SeqAccess, Visitor};
+use serde::Serializer;
+
+/// Serialize a byte buffer as a byte string
+pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: AsRef<[u8]> + ?Sized,
+    S: Serializer,
+{
+    serializer.serialize_bytes(bytes.as_ref())
+}
+
+/// Deserialize a byte buffer such as `Vec<u8>`, `Box<[u8]>` or `[u8; N]`
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: TryFrom<Vec<u8>>,
+    D: Deserializer<'de>,
+{
+    struct BufVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: TryFrom<Vec<u8>>> Visitor<'de> for BufVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a byte string")
+        }
+
+        fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<T, E> {
+            self.visit_byte_buf(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: Error>(self, bytes: Vec<u8>) -> Result<T, E> {
+            let len = bytes.len();
+            T::try_from(bytes).map_err(|_| E::invalid_length(len, &self))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            self.visit_byte_buf(bytes)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(BufVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Config;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Bulk {
+        #[serde(with = "super")]
+        data: Vec<u8>,
+        #[serde(with = "super")]
+        key: [u8; 4],
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct PerElement {
+        data: Vec<u8>,
+        key: [u8; 4],
+    }
+
+    #[test]
+    fn test_byte_slice_interchangeable() {
+        let bulk = Bulk { data: (0..=255).collect(), key: [1, 2, 3, 4] };
+        let plain = PerElement { data: bulk.data.clone(), key: bulk.key };
+
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let a = crate::to_bytes_with_config(&bulk, config).unwrap();
+            let b = crate::to_bytes_with_config(&plain, config).unwrap();
+            assert_eq!(crate::from_bytes::<Bulk>(&a).unwrap(), bulk);
+            assert_eq!(crate::from_bytes::<Bulk>(&b).unwrap(), bulk);
+            assert_eq!(crate::from_bytes::<PerElement>(&a).unwrap(), plain);
+            if config.self_describing() {
+                assert!(a.len() < b.len());
+            }
+        }
+
+        // Fixed-size arrays reject the wrong length
+        let short = crate::to_bytes(&(vec![0u8], vec![0u8; 3])).unwrap();
+        assert!(crate::from_bytes::<Bulk>(&short).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Checksum trailers for corruption detection
+//!
+//! When enabled with [`Config::with_checksum`], a checksum of the header and
+//! body is appended to the payload and its kind is recorded in the header
+//! flags. Decoding verifies the trailer before reading any value.
+//!
+//! [`Config::with_checksum`]: crate::Config::with_checksum
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec::Vec, string::ToString};
+
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+
+/// Checksum algorithm appended after the payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Checksum {
+    /// CRC-32C (Castagnoli), 4 bytes, hardware accelerated where available
+    Crc32c,
+    /// xxHash64, 8 bytes
+    XxHash64,
+}
+
+impl Checksum {
+    /// Number of bytes the trailer occupies
+    #[inline]
+    pub const fn size(self) -> usize {
+        match self {
+            Checksum::Crc32c => 4,
+            Checksum::XxHash64 => 8,
+        }
+    }
+
+    /// Header flag recording this checksum
+    #[inline]
+    pub(crate) const fn flag(self) -> HeaderFlags {
+        match self {
+            Checksum::Crc32c => HeaderFlags::CHECKSUM_CRC32C,
+            Checksum::XxHash64 => HeaderFlags::CHECKSUM_XXH64,
+        }
+    }
+
+    /// Checksum recorded in the header flags, if any
+    pub(crate) fn from_flags(flags: HeaderFlags) -> Result<Option<Self>> {
+        let crc = flags.contains(HeaderFlags::CHECKSUM_CRC32C);
+        let xxh = flags.contains(HeaderFlags::CHECKSUM_XXH64);
+        match (crc, xxh) {
+            (false, false) => Ok(None),
+            (true, false) => Ok(Some(Checksum::Crc32c)),
+            (false, true) => Ok(Some(Checksum::XxHash64)),
+            (true, true) => Err(Error::InvalidFormat(
+                "Conflicting checksum flags".to_string(),
+            )),
+        }
+    }
+
+    /// Compute the checksum of `data`
+    #[cfg(feature = "checksum")]
+    pub fn compute(self, data: &[u8]) -> u64 {
+        match self {
+            Checksum::Crc32c => crc32c::crc32c(data) as u64,
+            Checksum::XxHash64 => xxhash_rust::xxh64::xxh64(data, 0),
+        }
+    }
+
+    // Append the trailer for `data` to `out`
+    #[cfg(feature = "checksum")]
+    pub(crate) fn append(self, out: &mut Vec<u8>) {
+        let sum = self.compute(out);
+        out.extend_from_slice(&sum.to_le_bytes()[..self.size()]);
+    }
+}
+
+/// Verify the checksum trailer of a payload without decoding it
+///
+/// Fails if the payload carries no checksum.
+#[cfg(feature = "checksum")]
+pub fn verify_checksum(data: &[u8]) -> Result<()> {
+    let (header, header_len) = Header::parse(data)?;
+    match Checksum::from_flags(header.flags)? {
+        Some(checksum) => verify(data, header_len, checksum).map(|_| ()),
+        None => Err(Error::InvalidFormat("Payload has no checksum".to_string())),
+    }
+}
+
+/// Split a payload into its header and body, verifying any checksum trailer
+pub(crate) fn split_payload(data: &[u8]) -> Result<(Header, &[u8])> {
+    let (header, header_len) = Header::parse(data)?;
+    if header.flags.contains(HeaderFlags::ENCRYPTED) {
+        return Err(Error::InvalidFormat(
+            "Payload is encrypted; decrypt it first".to_string(),
+        ));
+    }
+    if header.flags.contains(HeaderFlags::COMPRESSED) {
+        return Err(Error::InvalidFormat(
+            "Payload is compressed; decompress it first".to_string(),
+        ));
+    }
+    let end = match Checksum::from_flags(header.flags)? {
+        Some(checksum) => verify(data, header_len, checksum)?,
+        None => data.len(),
+    };
+    Ok((header, &data[header_len..end]))
+}
+
+// Check the trailer and return where it starts
+#[cfg(feature = "checksum")]
+fn verify(data: &[u8], header_len: usize, checksum: Checksum) -> Result<usize> {
+    let end = data
+        .len()
+        .checked_sub(checksum.size())
+        .filter(|end| *end >= header_len)
+        .ok_or_else(|| Error::InvalidFormat("Data too short for checksum".to_string()))?;
+
+    let mut stored = [0u8; 8];
+    stored[..checksum.size()].copy_from_slice(&data[end..]);
+    let expected = u64::from_le_bytes(stored);
+    let actual = checksum.compute(&data[..end]);
+    if expected != actual {
+        return Err(Error::ChecksumMismatch { expected, actual });
+    }
+    Ok(end)
+}
+
+#[cfg(not(feature = "checksum"))]
+fn verify(_data: &[u8], _header_len: usize, _checksum: Checksum) -> Result<usize> {
+    Err(Error::InvalidFormat(
+        "Checksum verification not available - enable 'checksum' feature".to_string(),
+    ))
+}
+
+#[cfg(all(test, feature = "checksum"))]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes_with_config, Config};
+
+    #[test]
+    fn test_checksum_roundtrip() {
+        for checksum in [Checksum::Crc32c, Checksum::XxHash64] {
+            let config = Config::new().with_checksum(Some(checksum));
+            let bytes = to_bytes_with_config(&("hello", 42u32), config).unwrap();
+            assert_eq!(bytes.len(), 6 + 1 + 6 + 4 + checksum.size());
+
+            verify_checksum(&bytes).unwrap();
+            let decoded: (String, u32) = from_bytes(&bytes).unwrap();
+            assert_eq!(decoded, ("hello".to_string(), 42));
+        }
+    }
+
+    #[test]
+    fn test_corruption_detected() {
+        let config = Config::new().with_checksum(Some(Checksum::Crc32c));
+        let mut bytes = to_bytes_with_config(&vec![1u64, 2, 3], config).unwrap();
+        bytes[9] ^= 0x40;
+
+        assert!(matches!(verify_checksum(&bytes), Err(Error::ChecksumMismatch { .. })));
+        assert!(matches!(
+            from_bytes::<Vec<u64>>(&bytes),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+
+        // Truncation is caught too
+        let bytes = to_bytes_with_config(&7u8, config).unwrap();
+        assert!(verify_checksum(&bytes[..bytes.len() - 1]).is_err());
+        assert!(verify_checksum(&crate::to_bytes(&7u8).unwrap()).is_err());
+    }
+}
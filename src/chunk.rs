@@ -0,0 +1,451 @@
+//! Content-addressed chunk storage for successive versions of a payload
+//!
+//! Snapshots of a large state share most of their bytes from one version
+//! to the next, yet each save writes them all again. A [`ChunkStore`]
+//! splits payloads into chunks at boundaries chosen by their content, so
+//! an edit only changes the chunks around it, and stores each chunk once
+//! under the hash of its bytes. Saving a new version writes the chunks
+//! not seen before plus a small manifest listing the chunks in order:
+//!
+//! ```rust
+//! use nanobit::chunk::{ChunkStore, MemoryStorage};
+//!
+//! let mut store = ChunkStore::new(MemoryStorage::new());
+//! let mut state: Vec<u64> = (0..100_000).collect();
+//! let first = store.put(&state)?;
+//!
+//! state[50_000] = 0;
+//! let second = store.put(&state)?;
+//! // Only the chunk holding the edit is new
+//! assert!(second.new_bytes < first.new_bytes / 10);
+//!
+//! let old: Vec<u64> = store.get(first.id)?;
+//! assert_eq!(old[50_000], 50_000);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Storage is pluggable through [`ChunkStorage`]: [`MemoryStorage`] keeps
+//! chunks in a map and [`DirStorage`] in files under a directory.
+//!
+//! Chunks are identified by their 128-bit XXH3 hash, which is fast and
+//! catches accidental corruption: every chunk is checked against its id
+//! when read. It is not a cryptographic hash, so a store should not take
+//! chunks from parties who might craft collisions.
+//!
+//! # Chunking
+//!
+//! A gear hash rolls over the bytes, and a chunk ends where its top bits
+//! are zero, once the chunk has reached the minimum size, or at the
+//! maximum size. Boundaries depend only on the bytes just before them, so
+//! inserting or removing bytes moves the boundaries near the edit and
+//! leaves the rest in place. With [`Chunking::new`], chunks are 2 to
+//! 64 KiB, about 10 KiB on average.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// The hash identifying a chunk or a stored version
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ChunkId(pub [u8; 16]);
+
+impl ChunkId {
+    /// The id of a chunk holding `bytes`
+    pub fn of(bytes: &[u8]) -> Self {
+        ChunkId(xxhash_rust::xxh3::xxh3_128(bytes).to_le_bytes())
+    }
+}
+
+impl fmt::Display for ChunkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for ChunkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChunkId({self})")
+    }
+}
+
+// Gear hash table: a fixed pseudo-random value per byte, from SplitMix64
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0u64;
+    let mut index = 0;
+    while index < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[index] = z ^ (z >> 31);
+        index += 1;
+    }
+    table
+};
+
+/// Where content-defined chunk boundaries fall
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chunking {
+    min: usize,
+    avg: usize,
+    max: usize,
+}
+
+impl Chunking {
+    /// Chunks of 2 to 64 KiB, with boundaries 8 KiB apart on average past
+    /// the minimum
+    pub const fn new() -> Self {
+        Self { min: 2 * 1024, avg: 8 * 1024, max: 64 * 1024 }
+    }
+
+    /// Chunks of `min` to `max` bytes, with boundaries about `avg` bytes
+    /// apart past the minimum
+    ///
+    /// `avg` is rounded up to a power of two, and `max` raised to at
+    /// least `min`. Changing the sizes changes every boundary, so the
+    /// chunks of a store written with other sizes are no longer shared.
+    pub const fn with_sizes(min: usize, avg: usize, max: usize) -> Self {
+        let min = if min == 0 { 1 } else { min };
+        Self { min, avg: avg.next_power_of_two(), max: if max < min { min } else { max } }
+    }
+
+    /// Split `data` into chunks
+    pub fn split<'a>(&self, data: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let chunking = *self;
+        let mut rest = data;
+        std::iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            let (chunk, tail) = rest.split_at(chunking.cut(rest));
+            rest = tail;
+            Some(chunk)
+        })
+    }
+
+    // Length of the chunk at the start of `data`
+    fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min {
+            return data.len();
+        }
+        let end = data.len().min(self.max);
+        let mask = !(u64::MAX >> self.avg.trailing_zeros());
+        let mut hash = 0u64;
+        for (index, &byte) in data.iter().enumerate().take(end).skip(self.min) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            if hash & mask == 0 {
+                return index + 1;
+            }
+        }
+        end
+    }
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Somewhere to keep chunks by id
+pub trait ChunkStorage {
+    /// Whether a chunk is stored
+    fn contains(&self, id: ChunkId) -> Result<bool>;
+
+    /// Read a chunk, or `None` if it is not stored
+    fn read(&self, id: ChunkId) -> Result<Option<Vec<u8>>>;
+
+    /// Store a chunk; storing one already present may be skipped
+    fn write(&mut self, id: ChunkId, bytes: &[u8]) -> Result<()>;
+}
+
+/// Chunks kept in memory
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    chunks: HashMap<ChunkId, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    /// An empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of chunks stored
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Whether no chunks are stored
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+impl ChunkStorage for MemoryStorage {
+    fn contains(&self, id: ChunkId) -> Result<bool> {
+        Ok(self.chunks.contains_key(&id))
+    }
+
+    fn read(&self, id: ChunkId) -> Result<Option<Vec<u8>>> {
+        Ok(self.chunks.get(&id).cloned())
+    }
+
+    fn write(&mut self, id: ChunkId, bytes: &[u8]) -> Result<()> {
+        self.chunks.entry(id).or_insert_with(|| bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Chunks kept as files under a directory
+///
+/// A chunk is stored at `<dir>/<first two hex digits>/<rest of the id>`
+/// and written to a temporary file first, so a crash never leaves a
+/// partial chunk under its id.
+#[derive(Clone, Debug)]
+pub struct DirStorage {
+    dir: PathBuf,
+}
+
+impl DirStorage {
+    /// Use `dir` for chunks, creating it if needed
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, id: ChunkId) -> PathBuf {
+        let hex = id.to_string();
+        self.dir.join(&hex[..2]).join(&hex[2..])
+    }
+}
+
+impl ChunkStorage for DirStorage {
+    fn contains(&self, id: ChunkId) -> Result<bool> {
+        Ok(self.path(id).exists())
+    }
+
+    fn read(&self, id: ChunkId) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(id)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&mut self, id: ChunkId, bytes: &[u8]) -> Result<()> {
+        let path = self.path(id);
+        if path.exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(path.parent().unwrap_or(&self.dir))?;
+        let temp = crate::fs::temp_path(&path);
+        let result = std::fs::write(&temp, bytes).and_then(|()| std::fs::rename(&temp, &path));
+        if result.is_err() {
+            std::fs::remove_file(&temp).ok();
+        }
+        Ok(result?)
+    }
+}
+
+// The chunks of a stored version, itself stored as a chunk
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    len: u64,
+    chunks: Vec<ChunkId>,
+}
+
+/// What storing a version wrote
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stored {
+    /// Id of the version, for [`ChunkStore::materialize`]
+    pub id: ChunkId,
+    /// Length of the payload
+    pub len: u64,
+    /// Number of chunks the payload was split into
+    pub chunks: usize,
+    /// Chunks that were not stored before
+    pub new_chunks: usize,
+    /// Bytes of the new chunks, excluding the manifest
+    pub new_bytes: u64,
+}
+
+/// Stores payloads as deduplicated, content-addressed chunks
+///
+/// See the [module documentation](self).
+pub struct ChunkStore<S> {
+    storage: S,
+    chunking: Chunking,
+}
+
+impl<S: ChunkStorage> ChunkStore<S> {
+    /// A store writing to `storage` with the default [`Chunking`]
+    pub fn new(storage: S) -> Self {
+        Self { storage, chunking: Chunking::new() }
+    }
+
+    /// Split payloads with the given chunk sizes
+    pub fn with_chunking(mut self, chunking: Chunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Serialize a value and store it as a new version
+    pub fn put<T>(&mut self, value: &T) -> Result<Stored>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.put_bytes(&crate::to_bytes(&value)?)
+    }
+
+    /// Store a payload as a new version, writing only unseen chunks
+    pub fn put_bytes(&mut self, bytes: &[u8]) -> Result<Stored> {
+        let mut manifest = Manifest { len: bytes.len() as u64, chunks: Vec::new() };
+        let (mut new_chunks, mut new_bytes) = (0, 0);
+        for chunk in self.chunking.split(bytes) {
+            let id = ChunkId::of(chunk);
+            if !self.storage.contains(id)? {
+                self.storage.write(id, chunk)?;
+                new_chunks += 1;
+                new_bytes += chunk.len() as u64;
+            }
+            manifest.chunks.push(id);
+        }
+
+        let encoded = crate::to_bytes(&manifest)?;
+        let id = ChunkId::of(&encoded);
+        self.storage.write(id, &encoded)?;
+        Ok(Stored { id, len: manifest.len, chunks: manifest.chunks.len(), new_chunks, new_bytes })
+    }
+
+    /// Reassemble the payload of a stored version
+    pub fn materialize(&self, id: ChunkId) -> Result<Vec<u8>> {
+        let manifest: Manifest = crate::from_bytes(&self.read_chunk(id)?)?;
+        let mut bytes = Vec::with_capacity(manifest.len.min(1 << 30) as usize);
+        for chunk in manifest.chunks {
+            bytes.extend_from_slice(&self.read_chunk(chunk)?);
+        }
+        if bytes.len() as u64 != manifest.len {
+            return Err(Error::InvalidFormat(format!("Version {id} has the wrong length")));
+        }
+        Ok(bytes)
+    }
+
+    /// Reassemble and deserialize a version stored with [`put`](Self::put)
+    pub fn get<T>(&self, id: ChunkId) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        crate::from_bytes(&self.materialize(id)?)
+    }
+
+    /// The underlying storage
+    #[inline]
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    // Read a chunk, checking it against its id
+    fn read_chunk(&self, id: ChunkId) -> Result<Vec<u8>> {
+        let bytes = self
+            .storage
+            .read(id)?
+            .ok_or_else(|| Error::InvalidFormat(format!("Missing chunk {id}")))?;
+        if ChunkId::of(&bytes) != id {
+            return Err(Error::InvalidFormat(format!("Chunk {id} is corrupt")));
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deterministic pseudo-random bytes
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunk_boundaries_follow_content() {
+        let chunking = Chunking::new();
+        let data = noise(1 << 20, 7);
+        let chunks: Vec<&[u8]> = chunking.split(&data).collect();
+        assert_eq!(chunks.concat(), data);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 64 * 1024));
+        assert!(chunks[..chunks.len() - 1].iter().all(|chunk| chunk.len() >= 2 * 1024));
+        let average = data.len() / chunks.len();
+        assert!((6 * 1024..16 * 1024).contains(&average), "average chunk {average}");
+
+        // Inserting bytes at the front leaves later chunks intact
+        let mut shifted = b"inserted".to_vec();
+        shifted.extend_from_slice(&data);
+        let ids: std::collections::HashSet<_> = chunks.iter().map(|chunk| ChunkId::of(chunk)).collect();
+        let shared = chunking.split(&shifted).filter(|chunk| ids.contains(&ChunkId::of(chunk))).count();
+        assert!(shared >= chunks.len() - 2);
+
+        assert_eq!(chunking.split(&[]).count(), 0);
+        assert_eq!(Chunking::with_sizes(0, 3, 0), Chunking::with_sizes(1, 4, 1));
+    }
+
+    #[test]
+    fn test_chunk_store_versions() {
+        let mut store = ChunkStore::new(MemoryStorage::new());
+        let mut data = noise(300_000, 3);
+        let first = store.put_bytes(&data).unwrap();
+        assert_eq!(first.new_chunks, first.chunks);
+        assert_eq!(first.new_bytes, data.len() as u64);
+
+        data[150_000] ^= 0xFF;
+        data.splice(10..10, [1, 2, 3]);
+        let second = store.put_bytes(&data).unwrap();
+        assert!(second.new_chunks <= 4);
+        assert!(second.new_bytes < first.new_bytes / 4);
+
+        // Storing a version again writes nothing new
+        let again = store.put_bytes(&data).unwrap();
+        assert_eq!((again.id, again.new_chunks), (second.id, 0));
+
+        assert_eq!(store.materialize(second.id).unwrap(), data);
+        assert_ne!(store.materialize(first.id).unwrap(), data);
+        let empty = store.put_bytes(&[]).unwrap();
+        assert!(store.materialize(empty.id).unwrap().is_empty());
+        assert!(store.materialize(ChunkId([0; 16])).is_err());
+    }
+
+    #[test]
+    fn test_dir_storage() {
+        let dir = std::env::temp_dir().join(format!("nanobit-chunks-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let mut store = ChunkStore::new(DirStorage::open(&dir).unwrap())
+            .with_chunking(Chunking::with_sizes(64, 256, 1024));
+        let values: Vec<String> = (0..2000).map(|i| format!("entry-{i}")).collect();
+        let stored = store.put(&values).unwrap();
+        assert_eq!(store.get::<Vec<String>>(stored.id).unwrap(), values);
+
+        // A damaged chunk is detected on read
+        let chunk = store.chunking.split(&crate::to_bytes(&values).unwrap()).next().map(ChunkId::of).unwrap();
+        let path = store.storage().path(chunk);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[0] ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+        assert!(store.get::<Vec<String>>(stored.id).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
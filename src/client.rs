@@ -0,0 +1,219 @@
+//! Sending and receiving nanobit bodies with reqwest
+//!
+//! [`RequestBuilderExt::nanobit`] sets a request body with the
+//! `application/x-nanobit` content type, and [`ResponseExt::nanobit`] decodes
+//! a response body, decompressing it if needed. Decompression stops at
+//! [`DEFAULT_MAX_DECOMPRESSED_LEN`] bytes, or the limit given to
+//! [`ResponseExt::nanobit_with_limit`], so a small compressed body from an
+//! untrusted server cannot expand into an unbounded allocation:
+//!
+//! ```rust,ignore
+//! use nanobit::client::{RequestBuilderExt, ResponseExt};
+//!
+//! let user: User = client
+//!     .post("https://api.example.com/users")
+//!     .nanobit(&NewUser { name: "ada".into() })?
+//!     .send()
+//!     .await?
+//!     .nanobit()
+//!     .await?;
+//! ```
+//!
+//! Large results can be sent as a series of frames laid out as in
+//! [`framed`](crate::framed), and decoded one at a time while the body
+//! arrives with [`ResponseExt::nanobit_stream`].
+
+use core::future::Future;
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+
+use std::io;
+
+use bytes::{Buf, Bytes};
+use futures_core::Stream;
+use reqwest::header::{self, HeaderValue};
+use reqwest::{RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::framed::FrameReader;
+use crate::header::{Header, HeaderFlags};
+
+/// Media type of nanobit request and response bodies
+pub const CONTENT_TYPE: &str = "application/x-nanobit";
+
+/// Largest decompressed response body accepted by [`ResponseExt::nanobit`]
+pub const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 16 * 1024 * 1024;
+
+/// Nanobit bodies for [`RequestBuilder`]
+pub trait RequestBuilderExt: Sized {
+    /// Send `value` as the request body
+    fn nanobit<T: Serialize + ?Sized>(self, value: &T) -> Result<Self>;
+}
+
+impl RequestBuilderExt for RequestBuilder {
+    fn nanobit<T: Serialize + ?Sized>(self, value: &T) -> Result<Self> {
+        let bytes = crate::to_bytes(&value)?;
+        Ok(self
+            .header(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE))
+            .body(bytes))
+    }
+}
+
+/// Nanobit bodies for [`Response`]
+pub trait ResponseExt {
+    /// Read the whole body and decode it as one value
+    ///
+    /// A compressed body may expand to at most
+    /// [`DEFAULT_MAX_DECOMPRESSED_LEN`] bytes.
+    fn nanobit<T: DeserializeOwned>(self) -> impl Future<Output = Result<T>> + Send;
+
+    /// Read the whole body and decode it as one value, failing with
+    /// [`Error::LimitExceeded`] if a compressed body expands beyond
+    /// `max_decompressed_len` bytes
+    fn nanobit_with_limit<T: DeserializeOwned>(
+        self,
+        max_decompressed_len: usize,
+    ) -> impl Future<Output = Result<T>> + Send;
+
+    /// Decode the body as a stream of frames, each value as soon as its
+    /// frame has arrived
+    fn nanobit_stream<T: DeserializeOwned>(self) -> ResponseStream<T>;
+}
+
+impl ResponseExt for Response {
+    async fn nanobit<T: DeserializeOwned>(self) -> Result<T> {
+        self.nanobit_with_limit(DEFAULT_MAX_DECOMPRESSED_LEN).await
+    }
+
+    async fn nanobit_with_limit<T: DeserializeOwned>(self, max_decompressed_len: usize) -> Result<T> {
+        let bytes = self.bytes().await.map_err(|e| Error::Io(e.to_string()))?;
+        let (header, _) = Header::parse(&bytes)?;
+        if header.flags.contains(HeaderFlags::COMPRESSED) {
+            return crate::from_bytes(&crate::decompress_with_limit(&bytes, max_decompressed_len)?);
+        }
+        crate::from_bytes(&bytes)
+    }
+
+    fn nanobit_stream<T: DeserializeOwned>(self) -> ResponseStream<T> {
+        ResponseStream {
+            body: Box::pin(self.bytes_stream()),
+            chunk: Bytes::new(),
+            frames: FrameReader::new(Config::new()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A [`Stream`] of values decoded from frames in a response body
+///
+/// A frame that fails to decode yields an error and the stream moves on;
+/// an oversized frame or a body error ends it.
+pub struct ResponseStream<T> {
+    body: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>,
+    // Part of the last chunk not yet buffered by `frames`
+    chunk: Bytes,
+    frames: FrameReader,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ResponseStream<T> {
+    /// Refuse frames longer than `max_frame_len` bytes
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.frames.set_max_frame_len(max_frame_len);
+        self
+    }
+}
+
+impl<T: DeserializeOwned> Stream for ResponseStream<T> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<T>>> {
+        let this = self.get_mut();
+        let (body, chunk) = (&mut this.body, &mut this.chunk);
+        this.frames.poll_next(|buf| {
+            while chunk.is_empty() {
+                match ready!(body.as_mut().poll_next(cx)) {
+                    Some(Ok(next)) => *chunk = next,
+                    Some(Err(e)) => return Poll::Ready(Err(io::Error::other(e))),
+                    None => return Poll::Ready(Ok(0)),
+                }
+            }
+            let n = buf.len().min(chunk.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            chunk.advance(n);
+            Poll::Ready(Ok(n))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn response(body: reqwest::Body) -> Response {
+        Response::from(http::Response::new(body))
+    }
+
+    #[tokio::test]
+    async fn test_request_and_response_bodies() {
+        let value = (7u32, vec!["a".to_string(), "b".to_string()]);
+        let request = reqwest::Client::new()
+            .post("http://localhost/items")
+            .nanobit(&value)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+        let body = request.body().unwrap().as_bytes().unwrap().to_vec();
+        assert_eq!(response(body.clone().into()).nanobit::<(u32, Vec<String>)>().await.unwrap(), value);
+        let compressed = crate::compress_default(&body).unwrap();
+        assert_eq!(response(compressed.into()).nanobit::<(u32, Vec<String>)>().await.unwrap(), value);
+
+        // A compressed body expanding past the limit is refused
+        let bomb = crate::compress_default(&crate::to_bytes(&vec![0u8; 1 << 20]).unwrap()).unwrap();
+        let result = response(bomb.clone().into()).nanobit_with_limit::<Vec<u8>>(1 << 16).await;
+        assert!(matches!(result, Err(Error::LimitExceeded { .. })));
+        assert_eq!(response(bomb.into()).nanobit::<Vec<u8>>().await.unwrap().len(), 1 << 20);
+
+        // Frames split across body chunks decode as they complete
+        let mut framed = Vec::new();
+        for i in 0..20u64 {
+            let payload = crate::to_bytes(&(i, format!("item {i}"))).unwrap();
+            framed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            framed.extend_from_slice(&payload);
+        }
+        let chunks: Vec<reqwest::Result<Bytes>> = framed.chunks(5).map(|c| Ok(Bytes::copy_from_slice(c))).collect();
+        let body = reqwest::Body::wrap_stream(futures_util::stream::iter(chunks));
+        let items: Vec<(u64, String)> = response(body)
+            .nanobit_stream()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(items.len(), 20);
+        assert_eq!(items[19], (19, "item 19".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_default_decompression_cap() {
+        // A small body that inflates just past the default cap
+        let payload = crate::to_bytes(&vec![0u8; DEFAULT_MAX_DECOMPRESSED_LEN]).unwrap();
+        assert!(payload.len() > DEFAULT_MAX_DECOMPRESSED_LEN);
+        let bomb = crate::compress_default(&payload).unwrap();
+        assert!(bomb.len() < payload.len() / 100);
+
+        let result = response(bomb.into()).nanobit::<Vec<u8>>().await;
+        assert_eq!(result.unwrap_err(), crate::compression::limit_exceeded(DEFAULT_MAX_DECOMPRESSED_LEN));
+
+        // A larger limit lets the same body through
+        let bomb = crate::compress_default(&payload).unwrap();
+        let decoded = response(bomb.into()).nanobit_with_limit::<Vec<u8>>(payload.len()).await.unwrap();
+        assert_eq!(decoded.len(), DEFAULT_MAX_DECOMPRESSED_LEN);
+    }
+}
@@ -0,0 +1,349 @@
+//! Rust source generation from schema descriptors
+//!
+//! [`to_rust`] turns a [`Schema`] into struct and enum definitions whose
+//! serde attributes reproduce the names and field order of the original
+//! types, so data can be read by a consumer that only has the stored
+//! schema:
+//!
+//! ```rust
+//! use nanobit::schema::Schema;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Reading { sensor: u32, celsius: Option<f64> }
+//!
+//! let stored = nanobit::to_bytes(&Schema::of::<Reading>()?)?;
+//!
+//! let schema: Schema = nanobit::from_bytes(&stored)?;
+//! let source = nanobit::codegen::to_rust(&schema);
+//! assert!(source.contains("pub struct Reading {\n    pub sensor: u32,\n    pub celsius: Option<f64>,\n}"));
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Names that are not Rust identifiers, such as the numeric names that pin
+//! field tags, get a valid identifier and `#[serde(rename)]`. Byte strings
+//! held directly by a field use `nanobit::byte_slice`; nested ones become
+//! plain `Vec<u8>`, which has the same encoding unless the payload is
+//! self-describing. Maps become `BTreeMap`s, and recursive references that
+//! are not behind a sequence or map are boxed.
+
+#[cfg(not(feature = "std"))]
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::schema::{Container, Field, Schema, Type, Variant, VariantKind};
+
+/// Generate Rust definitions for every named type in a schema
+pub fn to_rust(schema: &Schema) -> String {
+    let generator = Generator::new(schema);
+    let mut out = String::from("// Generated by nanobit from a schema descriptor\n\n");
+    if schema.types.values().any(uses_map) {
+        out.push_str("use std::collections::BTreeMap;\n\n");
+    }
+    out.push_str("use serde::{Deserialize, Serialize};\n");
+    for (name, container) in &schema.types {
+        out.push('\n');
+        generator.container(&mut out, name, container);
+    }
+    out
+}
+
+struct Generator<'a> {
+    schema: &'a Schema,
+    // Named types reachable from each named type
+    reachable: BTreeMap<&'a str, BTreeSet<&'a str>>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(schema: &'a Schema) -> Self {
+        let mut reachable = BTreeMap::new();
+        for name in schema.types.keys() {
+            let mut seen = BTreeSet::new();
+            let mut pending = vec![name.as_str()];
+            while let Some(next) = pending.pop() {
+                let mut refs = Vec::new();
+                if let Some(container) = schema.types.get(next) {
+                    container_types(container, &mut |ty| named(ty, &mut refs));
+                }
+                pending.extend(refs.into_iter().filter(|r| seen.insert(*r)));
+            }
+            reachable.insert(name.as_str(), seen);
+        }
+        Self { schema, reachable }
+    }
+
+    fn container(&self, out: &mut String, name: &str, container: &Container) {
+        let derive = "#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]\n";
+        out.push_str(derive);
+        let ident = ident(name);
+        rename(out, "", name, &ident);
+        match container {
+            Container::UnitStruct => {
+                let _ = writeln!(out, "pub struct {ident};");
+            }
+            Container::NewtypeStruct(ty) => {
+                let _ = writeln!(out, "pub struct {ident}({});", self.unnamed(name, core::slice::from_ref(ty), "pub "));
+            }
+            Container::TupleStruct(types) => {
+                let _ = writeln!(out, "pub struct {ident}({});", self.unnamed(name, types, "pub "));
+            }
+            Container::Struct(fields) => {
+                let _ = writeln!(out, "pub struct {ident} {{");
+                self.fields(out, name, fields, "    ", "pub ");
+                out.push_str("}\n");
+            }
+            Container::Enum(variants) => {
+                let _ = writeln!(out, "pub enum {ident} {{");
+                for variant in variants {
+                    self.variant(out, name, variant);
+                }
+                out.push_str("}\n");
+            }
+        }
+    }
+
+    fn variant(&self, out: &mut String, owner: &str, variant: &Variant) {
+        let ident = ident(&variant.name);
+        rename(out, "    ", &variant.name, &ident);
+        match &variant.kind {
+            VariantKind::Unit => {
+                let _ = writeln!(out, "    {ident},");
+            }
+            VariantKind::Newtype(ty) => {
+                let _ = writeln!(out, "    {ident}({}),", self.unnamed(owner, core::slice::from_ref(ty), ""));
+            }
+            VariantKind::Tuple(types) => {
+                let _ = writeln!(out, "    {ident}({}),", self.unnamed(owner, types, ""));
+            }
+            VariantKind::Struct(fields) => {
+                let _ = writeln!(out, "    {ident} {{");
+                self.fields(out, owner, fields, "        ", "");
+                out.push_str("    },\n");
+            }
+        }
+    }
+
+    fn fields(&self, out: &mut String, owner: &str, fields: &[Field], indent: &str, vis: &str) {
+        let mut used = BTreeSet::new();
+        for (index, field) in fields.iter().enumerate() {
+            let mut ident = ident(&field.name);
+            if !used.insert(ident.clone()) {
+                ident = format!("{}_{index}", ident.trim_start_matches("r#"));
+                used.insert(ident.clone());
+            }
+            rename(out, indent, &field.name, &ident);
+            if field.ty == Type::Bytes {
+                let _ = writeln!(out, "{indent}#[serde(with = \"nanobit::byte_slice\")]");
+            }
+            let _ = writeln!(out, "{indent}{vis}{ident}: {},", self.ty(owner, &field.ty, false));
+        }
+    }
+
+    // Unnamed fields, with byte strings annotated
+    fn unnamed(&self, owner: &str, types: &[Type], vis: &str) -> String {
+        let fields: Vec<_> = types
+            .iter()
+            .map(|ty| match ty {
+                Type::Bytes => format!("#[serde(with = \"nanobit::byte_slice\")] {vis}Vec<u8>"),
+                ty => format!("{vis}{}", self.ty(owner, ty, false)),
+            })
+            .collect();
+        fields.join(", ")
+    }
+
+    // Rust type of a value held by `owner`; `indirect` is set below a
+    // sequence or map, which already breaks recursion
+    fn ty(&self, owner: &str, ty: &Type, indirect: bool) -> String {
+        match ty {
+            Type::Unit => "()".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::I8 => "i8".to_string(),
+            Type::I16 => "i16".to_string(),
+            Type::I32 => "i32".to_string(),
+            Type::I64 => "i64".to_string(),
+            Type::I128 => "i128".to_string(),
+            Type::U8 => "u8".to_string(),
+            Type::U16 => "u16".to_string(),
+            Type::U32 => "u32".to_string(),
+            Type::U64 => "u64".to_string(),
+            Type::U128 => "u128".to_string(),
+            Type::F32 => "f32".to_string(),
+            Type::F64 => "f64".to_string(),
+            Type::Char => "char".to_string(),
+            Type::Str => "String".to_string(),
+            Type::Bytes => "Vec<u8>".to_string(),
+            Type::Option(inner) => format!("Option<{}>", self.ty(owner, inner, indirect)),
+            Type::Seq(item) => format!("Vec<{}>", self.ty(owner, item, true)),
+            Type::Tuple(items) if items.len() == 1 => format!("({},)", self.ty(owner, &items[0], indirect)),
+            Type::Tuple(items) => {
+                let items: Vec<_> = items.iter().map(|item| self.ty(owner, item, indirect)).collect();
+                format!("({})", items.join(", "))
+            }
+            Type::Map { key, value } => {
+                format!("BTreeMap<{}, {}>", self.ty(owner, key, true), self.ty(owner, value, true))
+            }
+            Type::Named(name) => {
+                let recursive = name == owner || self.reachable.get(name.as_str()).is_some_and(|r| r.contains(owner));
+                if recursive && !indirect && self.schema.types.contains_key(name) {
+                    format!("Box<{}>", ident(name))
+                } else {
+                    ident(name)
+                }
+            }
+        }
+    }
+}
+
+// Call `f` with every type a container holds
+fn container_types<'a>(container: &'a Container, f: &mut impl FnMut(&'a Type)) {
+    let fields = |fields: &'a [Field], f: &mut dyn FnMut(&'a Type)| fields.iter().for_each(|field| f(&field.ty));
+    match container {
+        Container::UnitStruct => {}
+        Container::NewtypeStruct(ty) => f(ty),
+        Container::TupleStruct(types) => types.iter().for_each(f),
+        Container::Struct(list) => fields(list, f),
+        Container::Enum(variants) => {
+            for variant in variants {
+                match &variant.kind {
+                    VariantKind::Unit => {}
+                    VariantKind::Newtype(ty) => f(ty),
+                    VariantKind::Tuple(types) => types.iter().for_each(&mut *f),
+                    VariantKind::Struct(list) => fields(list, f),
+                }
+            }
+        }
+    }
+}
+
+// Collect the named types a type refers to
+fn named<'a>(ty: &'a Type, out: &mut Vec<&'a str>) {
+    match ty {
+        Type::Option(inner) | Type::Seq(inner) => named(inner, out),
+        Type::Tuple(items) => items.iter().for_each(|item| named(item, out)),
+        Type::Map { key, value } => {
+            named(key, out);
+            named(value, out);
+        }
+        Type::Named(name) => out.push(name),
+        _ => {}
+    }
+}
+
+fn uses_map(container: &Container) -> bool {
+    fn has_map(ty: &Type) -> bool {
+        match ty {
+            Type::Map { .. } => true,
+            Type::Option(inner) | Type::Seq(inner) => has_map(inner),
+            Type::Tuple(items) => items.iter().any(has_map),
+            _ => false,
+        }
+    }
+    let mut found = false;
+    container_types(container, &mut |ty| found |= has_map(ty));
+    found
+}
+
+// Write `#[serde(rename)]` when the identifier differs from the name
+fn rename(out: &mut String, indent: &str, name: &str, ident: &str) {
+    if ident.trim_start_matches("r#") != name {
+        let _ = writeln!(out, "{indent}#[serde(rename = {name:?})]");
+    }
+}
+
+// A Rust identifier for a serialized name
+fn ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn", "for",
+        "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct",
+        "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen",
+        "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+    ];
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert_str(0, "field_");
+    }
+    if ident == name && KEYWORDS.contains(&name) {
+        return format!("r#{name}");
+    }
+    if matches!(ident.as_str(), "_" | "self" | "Self" | "super" | "crate") {
+        ident.push('_');
+    }
+    ident
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_rust() {
+        let field = |name: &str, tag, ty| Field { name: name.to_string(), tag, ty };
+        let node = || Type::Named("Node".to_string());
+        let mut types = BTreeMap::new();
+        types.insert(
+            "Node".to_string(),
+            Container::Struct(vec![
+                field("3", 3, Type::U32),
+                field("type", 1, Type::Str),
+                field("data", 2, Type::Bytes),
+                field("parent", 3, Type::Option(Box::new(node()))),
+                field("children", 4, Type::Map { key: Box::new(Type::Str), value: Box::new(node()) }),
+            ]),
+        );
+        types.insert(
+            "wire.Kind".to_string(),
+            Container::Enum(vec![
+                Variant { name: "Empty".to_string(), kind: VariantKind::Unit },
+                Variant { name: "Leaf".to_string(), kind: VariantKind::Newtype(node()) },
+                Variant { name: "Pair".to_string(), kind: VariantKind::Tuple(vec![Type::Bytes, Type::Char]) },
+                Variant {
+                    name: "Span".to_string(),
+                    kind: VariantKind::Struct(vec![field("at", 0, Type::Tuple(vec![Type::U8, Type::U8]))]),
+                },
+            ]),
+        );
+        let schema = Schema { root: node(), types };
+
+        assert_eq!(
+            to_rust(&schema),
+            r#"// Generated by nanobit from a schema descriptor
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    #[serde(rename = "3")]
+    pub field_3: u32,
+    pub r#type: String,
+    #[serde(with = "nanobit::byte_slice")]
+    pub data: Vec<u8>,
+    pub parent: Option<Box<Node>>,
+    pub children: BTreeMap<String, Node>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "wire.Kind")]
+pub enum wire_Kind {
+    Empty,
+    Leaf(Node),
+    Pair(#[serde(with = "nanobit::byte_slice")] Vec<u8>, char),
+    Span {
+        at: (u8, u8),
+    },
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Fast paths for ordered maps, unordered sets and inline vectors
+//!
+//! Three opt-in wrappers cover what generic serde cannot know about a
+//! collection:
+//!
+//! - [`OrderedMap`] keeps a map's iteration order in
+//!   [canonical](crate::Config::with_canonical) payloads, which otherwise
+//!   sort entries by key. Use it for `IndexMap` and other maps whose order
+//!   is part of their meaning.
+//! - [`UnorderedSet`] sorts the elements of a `HashSet` or other
+//!   collection whose order means nothing in canonical payloads, which
+//!   otherwise keep the order of every sequence.
+//! - [`Bulk`] writes a `SmallVec`, `ArrayVec` or `Vec` of fixed-width
+//!   numbers as one little-endian block, like
+//!   [`PrimitiveSlice`](crate::PrimitiveSlice), instead of element by
+//!   element.
+//!
+//! Each can also be named in `#[serde(with)]` through the [`ordered`],
+//! [`unordered`] and [`bulk`] modules, leaving the field's type alone:
+//!
+//! ```rust
+//! # #[cfg(all(feature = "indexmap", feature = "smallvec"))] {
+//! use indexmap::IndexMap;
+//! use serde::{Deserialize, Serialize};
+//! use smallvec::SmallVec;
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Form {
+//!     #[serde(with = "nanobit::collections::ordered")]
+//!     fields: IndexMap<String, String>,
+//!     #[serde(with = "nanobit::collections::bulk")]
+//!     checksums: SmallVec<[u32; 4]>,
+//! }
+//!
+//! let form = Form {
+//!     fields: IndexMap::from([("name".into(), "Ada".into()), ("email".into(), "ada@example.com".into())]),
+//!     checksums: SmallVec::from_slice(&[7, 11]),
+//! };
+//! let bytes = nanobit::to_bytes_canonical(&form)?;
+//! let decoded: Form = nanobit::from_bytes(&bytes)?;
+//! assert!(decoded.fields.keys().eq(["name", "email"]));
+//! assert_eq!(decoded, form);
+//! # }
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! The `indexmap`, `smallvec` and `arrayvec` features enable the serde
+//! support of those crates along with the [`Bulk`] impls. An ordered map
+//! is an ordinary map on the wire, an unordered set an ordinary sequence
+//! and a bulk vector an ordinary byte string, so either side can drop the
+//! wrapper as long as the other still reads the same encoding.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use core::ops::{Deref, DerefMut};
+
+use serde::de::{Deserialize, Deserializer, Error as _};
+use serde::ser::{Serialize, Serializer};
+
+use crate::primitive::{Primitive, PrimitiveSlice};
+
+// Newtype name the serializer recognises to leave a canonical map unsorted
+pub(crate) const ORDERED_MAP_MARKER: &str = "$nanobit::private::OrderedMap";
+
+// Newtype name the serializer recognises to sort a canonical sequence
+pub(crate) const UNORDERED_SET_MARKER: &str = "$nanobit::private::UnorderedSet";
+
+/// A map written in its own iteration order, even in canonical mode
+///
+/// Outside canonical mode maps always keep their order, so the wrapper
+/// only matters there. Canonical payloads of two ordered maps holding the
+/// same entries in different orders differ, as their orders do.
+///
+/// ```rust
+/// # #[cfg(feature = "indexmap")] {
+/// use indexmap::IndexMap;
+/// use nanobit::collections::OrderedMap;
+///
+/// let steps = IndexMap::from([("fetch", 3u8), ("build", 1), ("deploy", 2)]);
+/// let bytes = nanobit::to_bytes_canonical(&OrderedMap(&steps))?;
+/// let OrderedMap(decoded) = nanobit::from_bytes::<OrderedMap<IndexMap<String, u8>>>(&bytes)?;
+/// assert!(decoded.keys().eq(["fetch", "build", "deploy"]));
+/// # }
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct OrderedMap<M>(pub M);
+
+impl<M> Deref for OrderedMap<M> {
+    type Target = M;
+
+    #[inline]
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M> DerefMut for OrderedMap<M> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.0
+    }
+}
+
+impl<M: Serialize> Serialize for OrderedMap<M> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        ordered::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, M: Deserialize<'de>> Deserialize<'de> for OrderedMap<M> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        M::deserialize(deserializer).map(OrderedMap)
+    }
+}
+
+/// Use [`OrderedMap`] for a field with `#[serde(with)]`
+pub mod ordered {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialize a map in its iteration order, even in canonical mode
+    pub fn serialize<M, S>(map: &M, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        M: Serialize + ?Sized,
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(super::ORDERED_MAP_MARKER, map)
+    }
+
+    /// Deserialize a map as usual
+    pub fn deserialize<'de, M, D>(deserializer: D) -> Result<M, D::Error>
+    where
+        M: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        M::deserialize(deserializer)
+    }
+}
+
+/// A set whose elements are sorted by their encoding in canonical mode
+///
+/// Canonical payloads keep the order of sequences, since serde does not
+/// say which sequences are sets, so two equal `HashSet`s iterated in
+/// different orders would encode differently. Outside canonical mode the
+/// wrapper changes nothing. Elements that encode the same are rejected in
+/// canonical mode, like duplicate map keys.
+///
+/// ```rust
+/// use std::collections::HashSet;
+/// use nanobit::collections::UnorderedSet;
+///
+/// let a: HashSet<String> = ["red", "green", "blue"].map(String::from).into();
+/// let b: HashSet<String> = ["blue", "red", "green"].map(String::from).into();
+/// let bytes = nanobit::to_bytes_canonical(&UnorderedSet(&a))?;
+/// assert_eq!(bytes, nanobit::to_bytes_canonical(&UnorderedSet(&b))?);
+/// assert_eq!(nanobit::from_bytes::<UnorderedSet<HashSet<String>>>(&bytes)?.0, a);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct UnorderedSet<C>(pub C);
+
+impl<C> Deref for UnorderedSet<C> {
+    type Target = C;
+
+    #[inline]
+    fn deref(&self) -> &C {
+        &self.0
+    }
+}
+
+impl<C> DerefMut for UnorderedSet<C> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.0
+    }
+}
+
+impl<C: Serialize> Serialize for UnorderedSet<C> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        unordered::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, C: Deserialize<'de>> Deserialize<'de> for UnorderedSet<C> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        C::deserialize(deserializer).map(UnorderedSet)
+    }
+}
+
+/// Use [`UnorderedSet`] for a field with `#[serde(with)]`
+pub mod unordered {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialize a set with its elements sorted in canonical mode
+    pub fn serialize<C, S>(set: &C, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        C: Serialize + ?Sized,
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(super::UNORDERED_SET_MARKER, set)
+    }
+
+    /// Deserialize a set as usual
+    pub fn deserialize<'de, C, D>(deserializer: D) -> Result<C, D::Error>
+    where
+        C: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        C::deserialize(deserializer)
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Vectors of numbers that [`Bulk`] can write as one block
+pub trait BulkVec: sealed::Sealed + Sized {
+    /// Element type
+    type Item: Primitive;
+
+    /// The elements
+    fn items(&self) -> &[Self::Item];
+
+    /// Build the vector from decoded elements, or `None` if they do not fit
+    fn from_items(items: Vec<Self::Item>) -> Option<Self>;
+}
+
+impl<T: Primitive> sealed::Sealed for Vec<T> {}
+
+impl<T: Primitive> BulkVec for Vec<T> {
+    type Item = T;
+
+    #[inline]
+    fn items(&self) -> &[T] {
+        self
+    }
+
+    #[inline]
+    fn from_items(items: Vec<T>) -> Option<Self> {
+        Some(items)
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<T: Primitive, const N: usize> sealed::Sealed for smallvec::SmallVec<[T; N]> {}
+
+#[cfg(feature = "smallvec")]
+impl<T: Primitive, const N: usize> BulkVec for smallvec::SmallVec<[T; N]> {
+    type Item = T;
+
+    #[inline]
+    fn items(&self) -> &[T] {
+        self
+    }
+
+    fn from_items(items: Vec<T>) -> Option<Self> {
+        // Inline when the elements fit, otherwise reuse the allocation
+        Some(if items.len() <= N { smallvec::SmallVec::from_slice(&items) } else { smallvec::SmallVec::from_vec(items) })
+    }
+}
+
+#[cfg(feature = "arrayvec")]
+impl<T: Primitive, const N: usize> sealed::Sealed for arrayvec::ArrayVec<T, N> {}
+
+#[cfg(feature = "arrayvec")]
+impl<T: Primitive, const N: usize> BulkVec for arrayvec::ArrayVec<T, N> {
+    type Item = T;
+
+    #[inline]
+    fn items(&self) -> &[T] {
+        self
+    }
+
+    fn from_items(items: Vec<T>) -> Option<Self> {
+        arrayvec::ArrayVec::try_from(&items[..]).ok()
+    }
+}
+
+/// A vector of numbers written as one little-endian block
+///
+/// Encoded exactly like a [`PrimitiveSlice`] of the same elements.
+///
+/// ```rust
+/// use nanobit::collections::Bulk;
+///
+/// let readings: Vec<u16> = (0..512).collect();
+/// let bytes = nanobit::to_bytes(&Bulk(readings.clone()))?;
+/// assert_eq!(nanobit::from_bytes::<Bulk<Vec<u16>>>(&bytes)?.0, readings);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bulk<V>(pub V);
+
+impl<V> Deref for Bulk<V> {
+    type Target = V;
+
+    #[inline]
+    fn deref(&self) -> &V {
+        &self.0
+    }
+}
+
+impl<V> DerefMut for Bulk<V> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.0
+    }
+}
+
+impl<V: BulkVec> Serialize for Bulk<V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        bulk::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, V: BulkVec> Deserialize<'de> for Bulk<V> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        bulk::deserialize(deserializer).map(Bulk)
+    }
+}
+
+/// Use [`Bulk`] for a field with `#[serde(with)]`
+pub mod bulk {
+    use super::*;
+
+    /// Serialize the elements as one little-endian block
+    pub fn serialize<V, S>(vec: &V, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        V: BulkVec,
+        S: Serializer,
+    {
+        PrimitiveSlice::new(vec.items()).serialize(serializer)
+    }
+
+    /// Deserialize elements written as one block
+    pub fn deserialize<'de, V, D>(deserializer: D) -> Result<V, D::Error>
+    where
+        V: BulkVec,
+        D: Deserializer<'de>,
+    {
+        let items = PrimitiveSlice::<V::Item>::deserialize(deserializer)?.into_vec();
+        let len = items.len();
+        V::from_items(items).ok_or_else(|| D::Error::custom(format_args!("{len} elements exceed the capacity")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::collections::BTreeMap;
+
+    // A map that serializes its entries in the given order
+    struct Entries<'a>(&'a [(u8, &'a str)]);
+
+    impl Serialize for Entries<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+        }
+    }
+
+    #[test]
+    fn test_ordered_map_keeps_order_in_canonical_mode() {
+        let entries = Entries(&[(3, "c"), (1, "a"), (2, "b")]);
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let canonical = config.with_canonical(true);
+            let plain = crate::to_bytes_with_config(&entries, config).unwrap();
+            let sorted = crate::to_bytes_with_config(&entries, canonical).unwrap();
+            assert_ne!(sorted, plain);
+            assert_eq!(crate::to_bytes_with_config(&OrderedMap(&entries), canonical).unwrap(), plain);
+
+            // Only the wrapped map keeps its order
+            let pair = crate::to_bytes_with_config(&(OrderedMap(&entries), &entries), canonical).unwrap();
+            let expected = crate::to_bytes_with_config(&(&entries, Entries(&[(1, "a"), (2, "b"), (3, "c")])), config).unwrap();
+            assert_eq!(pair, expected);
+            let (first, second): (BTreeMap<u8, String>, BTreeMap<u8, String>) = crate::from_bytes(&pair).unwrap();
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn test_unordered_set_sorted_in_canonical_mode() {
+        use std::collections::HashSet;
+
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Tags {
+            #[serde(with = "unordered")]
+            tags: HashSet<String>,
+        }
+
+        let forward: HashSet<String> = (0..50).map(|i| format!("tag{i}")).collect();
+        let mut backward = HashSet::with_capacity(200);
+        backward.extend((0..50).rev().map(|i| format!("tag{i}")));
+        assert_eq!(forward, backward);
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let canonical = config.with_canonical(true);
+            let a = crate::to_bytes_with_config(&Tags { tags: forward.clone() }, canonical).unwrap();
+            let b = crate::to_bytes_with_config(&Tags { tags: backward.clone() }, canonical).unwrap();
+            assert_eq!(a, b);
+            let decoded: Tags = crate::from_bytes(&a).unwrap();
+            assert_eq!(decoded.tags, forward);
+
+            // Outside canonical mode the iteration order is kept
+            let plain = crate::to_bytes_with_config(&UnorderedSet(&forward), config).unwrap();
+            assert_eq!(plain, crate::to_bytes_with_config(&forward, config).unwrap());
+        }
+        assert!(crate::to_bytes_canonical(&UnorderedSet(vec![1u8, 1])).is_err());
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn test_index_map_roundtrip() {
+        use indexmap::IndexMap;
+
+        let map: IndexMap<String, u32> = [("gamma", 1), ("alpha", 2), ("delta", 3)].map(|(k, v)| (k.to_string(), v)).into();
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&OrderedMap(&map), config.with_canonical(true)).unwrap();
+            let OrderedMap(decoded) = crate::from_bytes::<OrderedMap<IndexMap<String, u32>>>(&bytes).unwrap();
+            assert!(decoded.keys().eq(map.keys()));
+
+            // Without the wrapper canonical mode sorts, and the order is lost
+            let bytes = crate::to_bytes_with_config(&map, config.with_canonical(true)).unwrap();
+            let decoded: IndexMap<String, u32> = crate::from_bytes(&bytes).unwrap();
+            assert!(decoded.keys().eq(["alpha", "delta", "gamma"]));
+        }
+    }
+
+    #[test]
+    fn test_bulk_vec_matches_primitive_slice() {
+        let values: Vec<i32> = (-100..100).collect();
+        let bytes = crate::to_bytes(&Bulk(values.clone())).unwrap();
+        assert_eq!(bytes, crate::to_bytes(&PrimitiveSlice::new(&values)).unwrap());
+        assert_eq!(crate::from_bytes::<Bulk<Vec<i32>>>(&bytes).unwrap().0, values);
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn test_bulk_small_vec() {
+        use smallvec::SmallVec;
+
+        for len in [0, 3, 4, 100] {
+            let values: SmallVec<[f64; 4]> = (0..len).map(|i| i as f64 * 0.5).collect();
+            for config in [Config::new(), Config::new().with_self_describing(true)] {
+                let bytes = crate::to_bytes_with_config(&Bulk(values.clone()), config).unwrap();
+                let Bulk(decoded) = crate::from_bytes::<Bulk<SmallVec<[f64; 4]>>>(&bytes).unwrap();
+                assert_eq!(decoded, values);
+                assert_eq!(decoded.spilled(), len > 4);
+            }
+        }
+    }
+
+    #[cfg(feature = "arrayvec")]
+    #[test]
+    fn test_bulk_array_vec() {
+        use arrayvec::ArrayVec;
+
+        let values: ArrayVec<u64, 8> = (1..=5).collect();
+        let bytes = crate::to_bytes(&Bulk(values.clone())).unwrap();
+        assert_eq!(crate::from_bytes::<Bulk<ArrayVec<u64, 8>>>(&bytes).unwrap().0, values);
+        assert!(crate::from_bytes::<Bulk<ArrayVec<u64, 4>>>(&bytes).is_err());
+    }
+}
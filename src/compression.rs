@@ -2,35 +2,35 @@
 //! 
 //! Supports multiple compression algorithms for maximum flexibility and performance
 
+use crate::checksum::Checksum;
 use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+use crate::metrics::{Operation, Probe};
 use serde::{Serialize, Deserialize};
 
 /// Supported compression formats
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompressionFormat {
     /// LZ4 fast compression
+    #[default]
     LZ4,
     /// ZSTD high-ratio compression  
     ZSTD,
     /// Snappy fast compression
     Snappy,
-    /// Future: Custom nanobit compression
-    #[allow(dead_code)]
+    /// Built-in LZ compression with no external dependencies
     NanoBit,
-}
-
-impl Default for CompressionFormat {
-    fn default() -> Self {
-        Self::LZ4
-    }
+    /// No compression; the data is stored as-is inside the container
+    Stored,
 }
 
 /// Compression level for algorithms that support it
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompressionLevel {
     /// Fastest compression with lower compression ratio
     Fastest,
     /// Default balanced compression
+    #[default]
     Default,
     /// Best compression ratio (slower)
     Best,
@@ -38,23 +38,202 @@ pub enum CompressionLevel {
     Custom(i32),
 }
 
-impl Default for CompressionLevel {
+/// Payloads smaller than this are stored uncompressed by
+/// [`CompressionPolicy::auto`]
+pub const DEFAULT_MIN_COMPRESS_SIZE: usize = 128;
+
+/// When and how to compress data
+///
+/// Small payloads usually grow when compressed, so a policy can store them
+/// raw below a size threshold, or whenever compression does not pay off.
+/// Stored payloads are still wrapped in the container, so [`decompress`]
+/// round-trips them transparently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionPolicy {
+    format: CompressionFormat,
+    level: CompressionLevel,
+    min_size: usize,
+    store_incompressible: bool,
+}
+
+impl CompressionPolicy {
+    /// Always compress with the given format and level
+    pub const fn new(format: CompressionFormat, level: CompressionLevel) -> Self {
+        Self {
+            format,
+            level,
+            min_size: 0,
+            store_incompressible: false,
+        }
+    }
+
+    /// Compress with the default format, storing small or incompressible data raw
+    pub const fn auto() -> Self {
+        Self {
+            format: CompressionFormat::LZ4,
+            level: CompressionLevel::Default,
+            min_size: DEFAULT_MIN_COMPRESS_SIZE,
+            store_incompressible: true,
+        }
+    }
+
+    /// Store data smaller than `min_size` bytes without compressing it
+    pub const fn with_min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Store data raw when compressing it would not make it smaller
+    pub const fn with_store_incompressible(mut self, enabled: bool) -> Self {
+        self.store_incompressible = enabled;
+        self
+    }
+
+    /// Get the compression format
+    #[inline]
+    pub const fn format(&self) -> CompressionFormat {
+        self.format
+    }
+
+    /// Get the compression level
+    #[inline]
+    pub const fn level(&self) -> CompressionLevel {
+        self.level
+    }
+
+    /// Get the minimum size for compression
+    #[inline]
+    pub const fn min_size(&self) -> usize {
+        self.min_size
+    }
+
+    /// Check whether incompressible data is stored raw
+    #[inline]
+    pub const fn store_incompressible(&self) -> bool {
+        self.store_incompressible
+    }
+}
+
+impl Default for CompressionPolicy {
     fn default() -> Self {
-        Self::Default
+        Self::auto()
+    }
+}
+
+impl CompressionFormat {
+    /// Byte identifying this format in the compressed container header
+    pub const fn id(self) -> u8 {
+        match self {
+            CompressionFormat::LZ4 => 1,
+            CompressionFormat::ZSTD => 2,
+            CompressionFormat::Snappy => 3,
+            CompressionFormat::NanoBit => 4,
+            CompressionFormat::Stored => 0,
+        }
+    }
+
+    /// Look up a format by its container byte
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(CompressionFormat::Stored),
+            1 => Ok(CompressionFormat::LZ4),
+            2 => Ok(CompressionFormat::ZSTD),
+            3 => Ok(CompressionFormat::Snappy),
+            4 => Ok(CompressionFormat::NanoBit),
+            other => Err(Error::Compression(format!("Unknown compression format id: {other}"))),
+        }
+    }
+}
+
+/// Length of the compressed container header: magic, version, flags, format
+pub const CONTAINER_HEADER_LEN: usize = 7;
+
+/// Write the compressed container header for `format`
+pub(crate) fn container_header(format: CompressionFormat) -> [u8; CONTAINER_HEADER_LEN] {
+    let mut header = [0u8; CONTAINER_HEADER_LEN];
+    header[..4].copy_from_slice(crate::MAGIC);
+    header[4] = crate::FLAGS_VERSION;
+    header[5] = HeaderFlags::COMPRESSED.bits() as u8;
+    header[6] = format.id();
+    header
+}
+
+/// Read the format from a compressed container header
+///
+/// Returns `None` for data without a nanobit header, such as output of
+/// earlier versions that wrote bare compressed bytes.
+pub fn container_format(data: &[u8]) -> Result<Option<CompressionFormat>> {
+    if data.len() < 4 || &data[0..4] != crate::MAGIC {
+        return Ok(None);
     }
+    let (header, header_len) = Header::parse(data)?;
+    if !header.flags.contains(HeaderFlags::COMPRESSED) {
+        return Err(Error::Compression("Data is not compressed".to_string()));
+    }
+    let id = *data
+        .get(header_len)
+        .ok_or_else(|| Error::Compression("Data too short for compression format".to_string()))?;
+    CompressionFormat::from_id(id).map(Some)
 }
 
 /// Compress data using the specified format
+///
+/// The output starts with a container header recording the format, so
+/// [`decompress`] never has to guess it.
 pub fn compress(data: &[u8], format: CompressionFormat, level: CompressionLevel) -> Result<Vec<u8>> {
-    match format {
-        CompressionFormat::LZ4 => compress_lz4(data, level),
-        CompressionFormat::ZSTD => compress_zstd(data, level),
-        CompressionFormat::Snappy => compress_snappy(data),
-        CompressionFormat::NanoBit => {
-            // Future: Custom compression algorithm
-            Err(Error::Serde("NanoBit compression not yet implemented".to_string()))
-        }
+    let mut out = Vec::new();
+    compress_into(data, format, level, &mut out)?;
+    Ok(out)
+}
+
+/// Compress data into a caller-owned buffer, replacing its contents
+///
+/// Reusing `out` across calls avoids allocating a new buffer per message.
+/// Its contents are unspecified if an error is returned.
+pub fn compress_into(
+    data: &[u8],
+    format: CompressionFormat,
+    level: CompressionLevel,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let probe = Probe::start(Operation::Compress, None);
+    out.clear();
+    out.reserve(CONTAINER_HEADER_LEN + data.len() / 2);
+    out.extend_from_slice(&container_header(format));
+    let result = if format == CompressionFormat::NanoBit {
+        crate::lz::compress_stream(data, level, out);
+        Ok(())
+    } else {
+        compress_frames(data, format, level, out)
+    };
+    probe.finish(&result, if result.is_ok() { out.len() } else { 0 }, Some(data.len()));
+    result
+}
+
+#[cfg(feature = "std")]
+fn compress_frames(data: &[u8], format: CompressionFormat, level: CompressionLevel, out: &mut Vec<u8>) -> Result<()> {
+    use std::io::Write;
+
+    let mut encoder = CompressWriter::new(out, format, level)?;
+    encoder.write_all(data)?;
+    encoder.finish().map(|_| ())
+}
+
+#[cfg(not(feature = "std"))]
+fn compress_frames(_data: &[u8], format: CompressionFormat, _level: CompressionLevel, _out: &mut Vec<u8>) -> Result<()> {
+    Err(Error::Compression(format!("{format:?} compression not available")))
+}
+
+/// Compress data according to a [`CompressionPolicy`]
+pub fn compress_with_policy(data: &[u8], policy: &CompressionPolicy) -> Result<Vec<u8>> {
+    if data.len() < policy.min_size {
+        return compress(data, CompressionFormat::Stored, policy.level);
     }
+    let compressed = compress(data, policy.format, policy.level)?;
+    if policy.store_incompressible && compressed.len() >= data.len() + CONTAINER_HEADER_LEN {
+        return compress(data, CompressionFormat::Stored, policy.level);
+    }
+    Ok(compressed)
 }
 
 /// Compress data using default format and level
@@ -62,44 +241,145 @@ pub fn compress_default(data: &[u8]) -> Result<Vec<u8>> {
     compress(data, CompressionFormat::default(), CompressionLevel::default())
 }
 
-/// Decompress data - automatically detects format from header
+/// Decompress data written by [`compress`]
+///
+/// The format is read from the container header, and data without one is
+/// rejected. Use [`decompress_legacy`] for bare compressed bytes written by
+/// earlier versions.
 pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    decompress_into(data, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress data into a caller-owned buffer, replacing its contents
+///
+/// Reusing `out` across calls avoids allocating a new buffer per message.
+/// Its contents are unspecified if an error is returned.
+pub fn decompress_into(data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    decompress_into_with_limit(data, usize::MAX, out)
+}
+
+/// Decompress data, failing once the output would exceed `max_output_bytes`
+///
+/// Use this for untrusted input: a small compressed payload can otherwise
+/// expand to an arbitrarily large allocation. Returns
+/// [`Error::LimitExceeded`] when the limit is hit.
+pub fn decompress_with_limit(data: &[u8], max_output_bytes: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    decompress_into_with_limit(data, max_output_bytes, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress into a caller-owned buffer with an output size limit
+///
+/// See [`decompress_into`] and [`decompress_with_limit`].
+pub fn decompress_into_with_limit(data: &[u8], max_output_bytes: usize, out: &mut Vec<u8>) -> Result<()> {
+    let probe = Probe::start(Operation::Decompress, None);
+    out.clear();
+    let result = decompress_container(data, max_output_bytes, out);
+    probe.finish(&result, data.len(), result.is_ok().then_some(out.len()));
+    result
+}
+
+fn decompress_container(data: &[u8], max_output_bytes: usize, out: &mut Vec<u8>) -> Result<()> {
     if data.is_empty() {
         return Err(Error::InvalidFormat("Empty compressed data".to_string()));
     }
 
+    let body = &data[CONTAINER_HEADER_LEN.min(data.len())..];
+    match container_format(data)? {
+        Some(CompressionFormat::NanoBit) => crate::lz::decompress_stream(body, max_output_bytes, out),
+        Some(format) => decompress_frames(body, format, max_output_bytes, out),
+        None => Err(Error::Compression(
+            "Missing compressed container header; use decompress_legacy for data from earlier versions"
+                .to_string(),
+        )),
+    }
+}
+
+pub(crate) fn limit_exceeded(limit: usize) -> Error {
+    Error::LimitExceeded {
+        what: "Decompressed size",
+        limit,
+    }
+}
+
+#[cfg(feature = "std")]
+fn decompress_frames(data: &[u8], format: CompressionFormat, limit: usize, out: &mut Vec<u8>) -> Result<()> {
+    use std::io::Read;
+
+    out.reserve((data.len() * 2).min(limit));
+    // Read one byte past the limit to tell "exactly at" from "over"
+    DecompressReader::new(data, format)?
+        .take((limit as u64).saturating_add(1))
+        .read_to_end(out)
+        .map_err(|e| Error::Compression(format!("{format:?} decompression failed: {e}")))?;
+    if out.len() > limit {
+        return Err(limit_exceeded(limit));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "std"))]
+fn decompress_frames(_data: &[u8], format: CompressionFormat, _limit: usize, _out: &mut Vec<u8>) -> Result<()> {
+    Err(Error::Compression(format!("{format:?} decompression not available")))
+}
+
+/// Decompress data that may predate the compressed container header
+///
+/// Containers are decompressed as by [`decompress_with_limit`]. For bare
+/// compressed bytes written by earlier versions the format is guessed from
+/// magic bytes and an LZ4 heuristic, then each enabled decoder is tried in
+/// turn, so a failure cannot say what was wrong. Use this only to read old
+/// data; [`decompress`] never guesses.
+pub fn decompress_legacy(data: &[u8], max_output_bytes: usize) -> Result<Vec<u8>> {
+    if container_format(data)?.is_some() {
+        return decompress_with_limit(data, max_output_bytes);
+    }
+    guess_and_decompress(data, max_output_bytes)
+}
+
+// Bare compressed bytes written before the container header existed
+fn guess_and_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
     // Try to detect format from magic bytes/header
     if data.len() >= 4 {
         // ZSTD magic number: 0xFD2FB528
         if data.len() >= 4 && data[0..4] == [0x28, 0xB5, 0x2F, 0xFD] {
-            return decompress_zstd(data);
+            return decompress_zstd(data, limit);
         }
         
         // Snappy detection (stream format has magic bytes)
         if data.len() >= 6 && &data[0..6] == b"sNaPpY" {
-            return decompress_snappy(data);
+            return decompress_snappy(data, limit);
         }
         
         // LZ4 detection (simple heuristic) - try last since it's more ambiguous
         if is_likely_lz4(data) {
-            return decompress_lz4(data);
+            return decompress_lz4(data, limit);
         }
     }
     
     // Try each format if detection fails, but only if features are enabled
     #[cfg(feature = "multi-compression")]
-    if let Ok(result) = decompress_zstd(data) {
-        return Ok(result);
+    match decompress_zstd(data, limit) {
+        Ok(result) => return Ok(result),
+        Err(e @ Error::LimitExceeded { .. }) => return Err(e),
+        Err(_) => {}
     }
     
     #[cfg(feature = "multi-compression")]
-    if let Ok(result) = decompress_snappy(data) {
-        return Ok(result);
+    match decompress_snappy(data, limit) {
+        Ok(result) => return Ok(result),
+        Err(e @ Error::LimitExceeded { .. }) => return Err(e),
+        Err(_) => {}
     }
     
     #[cfg(feature = "compression")]
-    if let Ok(result) = decompress_lz4(data) {
-        return Ok(result);
+    match decompress_lz4(data, limit) {
+        Ok(result) => return Ok(result),
+        Err(e @ Error::LimitExceeded { .. }) => return Err(e),
+        Err(_) => {}
     }
     
     Err(Error::InvalidFormat("Unable to decompress: unknown format".to_string()))
@@ -107,91 +387,429 @@ pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
 
 /// Check if data appears to be serialized nanobit format
 pub fn is_serialized(data: &[u8]) -> bool {
-    if data.len() < 5 {
-        return false;
+    sniff(data).is_some()
+}
+
+/// What the header of a nanobit payload says about it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadInfo {
+    /// The parsed header, with the format version and flags
+    pub header: Header,
+    /// Bytes the header occupies, including any schema hash and type name
+    pub header_len: usize,
+    /// Format of a compressed container, `None` for an ordinary payload
+    pub compression: Option<CompressionFormat>,
+    /// Checksum trailer announced by the header
+    pub checksum: Option<Checksum>,
+    /// Length of the body between the header and any checksum
+    pub body_len: usize,
+    /// Length after decompressing, when the container records it
+    pub decompressed_len: Option<usize>,
+}
+
+impl PayloadInfo {
+    /// Check whether the body is an encrypted envelope
+    #[inline]
+    pub const fn is_encrypted(&self) -> bool {
+        self.header.flags.contains(HeaderFlags::ENCRYPTED)
     }
-    
-    // Check for nanobit magic bytes and valid version
-    data.len() >= 5 && &data[0..4] == crate::MAGIC && data[4] == crate::VERSION
 }
 
-// LZ4 implementation
-#[cfg(feature = "compression")]
-fn compress_lz4(data: &[u8], _level: CompressionLevel) -> Result<Vec<u8>> {
-    use lz4_flex::compress_prepend_size;
-    Ok(compress_prepend_size(data))
+/// Inspect a payload's header without decoding or verifying its body
+///
+/// Returns `None` unless `data` starts with a header this version
+/// understands and is long enough for what the header announces.
+///
+/// ```rust
+/// use nanobit::{compress, sniff, CompressionFormat, CompressionLevel};
+///
+/// let bytes = nanobit::to_bytes(&"hello")?;
+/// let info = sniff(&bytes).unwrap();
+/// assert_eq!((info.header.version, info.compression, info.body_len), (1, None, 6));
+///
+/// let packed = compress(&bytes, CompressionFormat::NanoBit, CompressionLevel::Default)?;
+/// let info = sniff(&packed).unwrap();
+/// assert_eq!(info.compression, Some(CompressionFormat::NanoBit));
+/// assert_eq!(info.decompressed_len, Some(bytes.len()));
+///
+/// assert!(sniff(b"not a payload").is_none());
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn sniff(data: &[u8]) -> Option<PayloadInfo> {
+    let (header, header_len) = Header::parse(data).ok()?;
+    let checksum = Checksum::from_flags(header.flags).ok()?;
+    let trailer = checksum.map_or(0, Checksum::size);
+    let body = data.get(header_len..data.len().checked_sub(trailer)?)?;
+
+    let (compression, decompressed_len) = if header.flags.contains(HeaderFlags::COMPRESSED) {
+        let (&id, block) = body.split_first()?;
+        let format = CompressionFormat::from_id(id).ok()?;
+        let len = match format {
+            CompressionFormat::Stored => Some(block.len()),
+            // NanoBit blocks start with their uncompressed length
+            CompressionFormat::NanoBit => crate::lz::stream_len(block).ok(),
+            _ => None,
+        };
+        (Some(format), len)
+    } else {
+        (None, None)
+    };
+
+    Some(PayloadInfo {
+        header,
+        header_len,
+        compression,
+        checksum,
+        body_len: body.len(),
+        decompressed_len,
+    })
 }
 
+// Legacy LZ4 blocks with the uncompressed size prepended
 #[cfg(feature = "compression")]
-fn decompress_lz4(data: &[u8]) -> Result<Vec<u8>> {
+fn decompress_lz4(data: &[u8], limit: usize) -> Result<Vec<u8>> {
     use lz4_flex::decompress_size_prepended;
+    if data.len() >= 4 && u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize > limit {
+        return Err(limit_exceeded(limit));
+    }
     decompress_size_prepended(data)
         .map_err(|e| Error::InvalidFormat(format!("LZ4 decompression failed: {e}")))
 }
 
 #[cfg(not(feature = "compression"))]
-fn compress_lz4(_data: &[u8], _level: CompressionLevel) -> Result<Vec<u8>> {
-    Err(Error::Serde("LZ4 compression not available - enable 'compression' feature".to_string()))
-}
-
-#[cfg(not(feature = "compression"))]
-fn decompress_lz4(_data: &[u8]) -> Result<Vec<u8>> {
+fn decompress_lz4(_data: &[u8], _limit: usize) -> Result<Vec<u8>> {
     Err(Error::Serde("LZ4 decompression not available - enable 'compression' feature".to_string()))
 }
 
-// ZSTD implementation
+// ZSTD
 #[cfg(feature = "multi-compression")]
-fn compress_zstd(data: &[u8], level: CompressionLevel) -> Result<Vec<u8>> {
-    let compression_level = match level {
+fn zstd_level(level: CompressionLevel) -> i32 {
+    match level {
         CompressionLevel::Fastest => 1,
         CompressionLevel::Default => 3,
         CompressionLevel::Best => 22,
         CompressionLevel::Custom(l) => l,
-    };
-    
-    zstd::encode_all(data, compression_level)
-        .map_err(|e| Error::InvalidFormat(format!("ZSTD compression failed: {e}")))
+    }
 }
 
 #[cfg(feature = "multi-compression")]
-fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>> {
-    zstd::decode_all(data)
-        .map_err(|e| Error::InvalidFormat(format!("ZSTD decompression failed: {e}")))
-}
+fn decompress_zstd(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    use std::io::Read;
 
-#[cfg(not(feature = "multi-compression"))]
-fn compress_zstd(_data: &[u8], _level: CompressionLevel) -> Result<Vec<u8>> {
-    Err(Error::Serde("ZSTD compression not available - enable 'multi-compression' feature".to_string()))
+    let mut out = Vec::new();
+    zstd::stream::read::Decoder::new(data)
+        .and_then(|decoder| decoder.take((limit as u64).saturating_add(1)).read_to_end(&mut out))
+        .map_err(|e| Error::InvalidFormat(format!("ZSTD decompression failed: {e}")))?;
+    if out.len() > limit {
+        return Err(limit_exceeded(limit));
+    }
+    Ok(out)
 }
 
 #[cfg(not(feature = "multi-compression"))]
-fn decompress_zstd(_data: &[u8]) -> Result<Vec<u8>> {
+fn decompress_zstd(_data: &[u8], _limit: usize) -> Result<Vec<u8>> {
     Err(Error::Serde("ZSTD decompression not available - enable 'multi-compression' feature".to_string()))
 }
 
-// Snappy implementation
+// Legacy raw Snappy blocks
 #[cfg(feature = "multi-compression")]
-fn compress_snappy(data: &[u8]) -> Result<Vec<u8>> {
-    snap::raw::Encoder::new()
-        .compress_vec(data)
-        .map_err(|e| Error::InvalidFormat(format!("Snappy compression failed: {e}")))
-}
-
-#[cfg(feature = "multi-compression")]
-fn decompress_snappy(data: &[u8]) -> Result<Vec<u8>> {
+fn decompress_snappy(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    if snap::raw::decompress_len(data).is_ok_and(|len| len > limit) {
+        return Err(limit_exceeded(limit));
+    }
     snap::raw::Decoder::new()
         .decompress_vec(data)
         .map_err(|e| Error::InvalidFormat(format!("Snappy decompression failed: {e}")))
 }
 
 #[cfg(not(feature = "multi-compression"))]
-fn compress_snappy(_data: &[u8]) -> Result<Vec<u8>> {
-    Err(Error::Serde("Snappy compression not available - enable 'multi-compression' feature".to_string()))
+fn decompress_snappy(_data: &[u8], _limit: usize) -> Result<Vec<u8>> {
+    Err(Error::Serde("Snappy decompression not available - enable 'multi-compression' feature".to_string()))
 }
 
-#[cfg(not(feature = "multi-compression"))]
-fn decompress_snappy(_data: &[u8]) -> Result<Vec<u8>> {
-    Err(Error::Serde("Snappy decompression not available - enable 'multi-compression' feature".to_string()))
+/// Streaming compressor that writes compressed frames to an inner writer
+///
+/// This produces the body of a compressed container without its header;
+/// [`compress`] is a container header followed by this output.
+#[cfg(feature = "std")]
+pub struct CompressWriter<W: std::io::Write> {
+    inner: CompressWriterInner<W>,
+}
+
+#[cfg(feature = "std")]
+enum CompressWriterInner<W: std::io::Write> {
+    Stored(W),
+    // Input is buffered until it fills a NanoBit block
+    NanoBit { writer: W, buffer: Vec<u8>, block: Vec<u8>, level: CompressionLevel },
+    #[cfg(feature = "compression")]
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+    #[cfg(feature = "multi-compression")]
+    Zstd(zstd::stream::Encoder<'static, W>),
+    #[cfg(feature = "multi-compression")]
+    Snappy(Box<snap::write::FrameEncoder<W>>),
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> CompressWriter<W> {
+    /// Create a compressor writing to `writer`
+    #[allow(unused_variables)]
+    pub fn new(writer: W, format: CompressionFormat, level: CompressionLevel) -> Result<Self> {
+        let inner = match format {
+            CompressionFormat::Stored => CompressWriterInner::Stored(writer),
+            CompressionFormat::NanoBit => CompressWriterInner::NanoBit {
+                writer,
+                buffer: Vec::new(),
+                block: Vec::new(),
+                level,
+            },
+            #[cfg(feature = "compression")]
+            CompressionFormat::LZ4 => CompressWriterInner::Lz4(lz4_flex::frame::FrameEncoder::new(writer)),
+            #[cfg(feature = "multi-compression")]
+            CompressionFormat::ZSTD => CompressWriterInner::Zstd(
+                zstd::stream::Encoder::new(writer, zstd_level(level))
+                    .map_err(|e| Error::Compression(format!("ZSTD encoder failed: {e}")))?,
+            ),
+            #[cfg(feature = "multi-compression")]
+            CompressionFormat::Snappy => {
+                CompressWriterInner::Snappy(Box::new(snap::write::FrameEncoder::new(writer)))
+            }
+            #[allow(unreachable_patterns)]
+            other => {
+                return Err(Error::Compression(format!(
+                    "Streaming {other:?} compression not available"
+                )))
+            }
+        };
+        Ok(Self { inner })
+    }
+
+    /// Flush the final frame and return the inner writer
+    pub fn finish(self) -> Result<W> {
+        match self.inner {
+            CompressWriterInner::Stored(writer) => Ok(writer),
+            CompressWriterInner::NanoBit { mut writer, buffer, mut block, level } => {
+                if !buffer.is_empty() {
+                    write_nanobit_block(&mut writer, &buffer, &mut block, level)?;
+                }
+                Ok(writer)
+            }
+            #[cfg(feature = "compression")]
+            CompressWriterInner::Lz4(encoder) => encoder
+                .finish()
+                .map_err(|e| Error::Compression(format!("LZ4 compression failed: {e}"))),
+            #[cfg(feature = "multi-compression")]
+            CompressWriterInner::Zstd(encoder) => encoder
+                .finish()
+                .map_err(|e| Error::Compression(format!("ZSTD compression failed: {e}"))),
+            #[cfg(feature = "multi-compression")]
+            CompressWriterInner::Snappy(encoder) => encoder
+                .into_inner()
+                .map_err(|e| Error::Compression(format!("Snappy compression failed: {e}"))),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> std::io::Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            CompressWriterInner::Stored(writer) => writer.write(buf),
+            CompressWriterInner::NanoBit { writer, buffer, block, level } => {
+                let len = buf.len().min(crate::lz::BLOCK_LEN - buffer.len());
+                buffer.extend_from_slice(&buf[..len]);
+                if buffer.len() == crate::lz::BLOCK_LEN {
+                    write_nanobit_block(writer, buffer, block, *level)?;
+                    buffer.clear();
+                }
+                Ok(len)
+            }
+            #[cfg(feature = "compression")]
+            CompressWriterInner::Lz4(encoder) => encoder.write(buf),
+            #[cfg(feature = "multi-compression")]
+            CompressWriterInner::Zstd(encoder) => encoder.write(buf),
+            #[cfg(feature = "multi-compression")]
+            CompressWriterInner::Snappy(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.inner {
+            CompressWriterInner::Stored(writer) => writer.flush(),
+            // A partial block is only written by `finish`, so blocks stay full
+            CompressWriterInner::NanoBit { writer, .. } => writer.flush(),
+            #[cfg(feature = "compression")]
+            CompressWriterInner::Lz4(encoder) => encoder.flush(),
+            #[cfg(feature = "multi-compression")]
+            CompressWriterInner::Zstd(encoder) => encoder.flush(),
+            #[cfg(feature = "multi-compression")]
+            CompressWriterInner::Snappy(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn write_nanobit_block<W: std::io::Write>(
+    writer: &mut W,
+    data: &[u8],
+    block: &mut Vec<u8>,
+    level: CompressionLevel,
+) -> std::io::Result<()> {
+    block.clear();
+    crate::lz::compress_framed(data, level, block);
+    writer.write_all(block)
+}
+
+/// Streaming decompressor reading frames written by [`CompressWriter`]
+///
+/// To read a whole container, use [`DecompressReader::from_container`].
+/// Output is unlimited unless set with [`with_limit`](Self::with_limit).
+#[cfg(feature = "std")]
+pub struct DecompressReader<R: std::io::Read> {
+    inner: DecompressReaderInner<R>,
+    limit: usize,
+    produced: usize,
+}
+
+#[cfg(feature = "std")]
+enum DecompressReaderInner<R: std::io::Read> {
+    Stored(R),
+    NanoBit { reader: R, block: Vec<u8>, decoded: Vec<u8>, pos: usize },
+    #[cfg(feature = "compression")]
+    Lz4(lz4_flex::frame::FrameDecoder<R>),
+    #[cfg(feature = "multi-compression")]
+    Zstd(zstd::stream::Decoder<'static, std::io::BufReader<R>>),
+    #[cfg(feature = "multi-compression")]
+    Snappy(snap::read::FrameDecoder<R>),
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> DecompressReader<R> {
+    /// Create a decompressor reading from `reader`
+    pub fn new(reader: R, format: CompressionFormat) -> Result<Self> {
+        let inner = match format {
+            CompressionFormat::Stored => DecompressReaderInner::Stored(reader),
+            CompressionFormat::NanoBit => DecompressReaderInner::NanoBit {
+                reader,
+                block: Vec::new(),
+                decoded: Vec::new(),
+                pos: 0,
+            },
+            #[cfg(feature = "compression")]
+            CompressionFormat::LZ4 => DecompressReaderInner::Lz4(lz4_flex::frame::FrameDecoder::new(reader)),
+            #[cfg(feature = "multi-compression")]
+            CompressionFormat::ZSTD => DecompressReaderInner::Zstd(
+                zstd::stream::Decoder::new(reader)
+                    .map_err(|e| Error::Compression(format!("ZSTD decoder failed: {e}")))?,
+            ),
+            #[cfg(feature = "multi-compression")]
+            CompressionFormat::Snappy => DecompressReaderInner::Snappy(snap::read::FrameDecoder::new(reader)),
+            #[allow(unreachable_patterns)]
+            other => {
+                return Err(Error::Compression(format!(
+                    "Streaming {other:?} decompression not available"
+                )))
+            }
+        };
+        Ok(Self {
+            inner,
+            limit: usize::MAX,
+            produced: 0,
+        })
+    }
+
+    /// Fail with [`Error::LimitExceeded`] once more than `max_output_bytes`
+    /// have been decompressed
+    ///
+    /// Use this for untrusted input; see
+    /// [`decompress_with_limit`](crate::decompress_with_limit).
+    pub fn with_limit(mut self, max_output_bytes: usize) -> Self {
+        self.limit = max_output_bytes;
+        self
+    }
+
+    /// Read a compressed container header from `reader`, then decompress the
+    /// frames that follow it
+    pub fn from_container(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; CONTAINER_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let format = container_format(&header)?
+            .ok_or_else(|| Error::Compression("Missing compressed container header".to_string()))?;
+        Self::new(reader, format)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> std::io::Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Ask for one byte past the limit to tell "exactly at" from "over"
+        let allowed = (self.limit - self.produced).saturating_add(1);
+        let len = buf.len().min(allowed);
+        let read = self.read_inner(&mut buf[..len])?;
+        self.produced += read;
+        if self.produced > self.limit {
+            return Err(std::io::Error::other(limit_exceeded(self.limit)));
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> DecompressReader<R> {
+    fn read_inner(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        use std::io::Read;
+
+        match &mut self.inner {
+            DecompressReaderInner::Stored(reader) => reader.read(buf),
+            DecompressReaderInner::NanoBit { reader, block, decoded, pos } => {
+                if *pos == decoded.len() {
+                    *pos = 0;
+                    decoded.clear();
+                    if !read_nanobit_block(reader, block, decoded).map_err(std::io::Error::other)? {
+                        return Ok(0);
+                    }
+                }
+                let len = buf.len().min(decoded.len() - *pos);
+                buf[..len].copy_from_slice(&decoded[*pos..*pos + len]);
+                *pos += len;
+                Ok(len)
+            }
+            #[cfg(feature = "compression")]
+            DecompressReaderInner::Lz4(decoder) => decoder.read(buf),
+            #[cfg(feature = "multi-compression")]
+            DecompressReaderInner::Zstd(decoder) => decoder.read(buf),
+            #[cfg(feature = "multi-compression")]
+            DecompressReaderInner::Snappy(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+// Decode the next NanoBit block into `decoded`, returning false at the end
+// of the stream
+#[cfg(feature = "std")]
+fn read_nanobit_block<R: std::io::Read>(reader: &mut R, block: &mut Vec<u8>, decoded: &mut Vec<u8>) -> Result<bool> {
+    let mut header = [0u8; crate::lz::BLOCK_HEADER_LEN];
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(Error::UnexpectedEof),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    block.resize(crate::lz::framed_len(header)?, 0);
+    reader.read_exact(block).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => Error::UnexpectedEof,
+        _ => e.into(),
+    })?;
+    // Empty blocks are never written, so each block makes progress
+    let len = crate::lz::block_len(block)?;
+    if len == 0 {
+        return Err(Error::Compression("NanoBit decompression failed: empty block".to_string()));
+    }
+    crate::lz::decompress(block, len, decoded)?;
+    Ok(true)
 }
 
 // LZ4 detection heuristic
@@ -285,6 +903,175 @@ mod tests {
         assert!(!is_serialized(&fake_data));
     }
 
+    #[test]
+    fn test_sniff() {
+        let stored = compress(b"raw bytes", CompressionFormat::Stored, CompressionLevel::Default).unwrap();
+        let info = sniff(&stored).unwrap();
+        assert_eq!(info.compression, Some(CompressionFormat::Stored));
+        assert_eq!((info.header_len, info.body_len, info.decompressed_len), (6, 10, Some(9)));
+        assert!(info.checksum.is_none() && !info.is_encrypted());
+
+        #[cfg(feature = "checksum")]
+        {
+            let config = crate::Config::new().with_checksum(Some(Checksum::XxHash64));
+            let bytes = crate::to_bytes_with_config(&42u32, config).unwrap();
+            let info = sniff(&bytes).unwrap();
+            assert_eq!((info.checksum, info.body_len, info.compression), (Some(Checksum::XxHash64), 4, None));
+            // Too short for the announced trailer
+            assert!(sniff(&bytes[..info.header_len + 4]).is_none());
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "compression", feature = "multi-compression"))]
+    fn test_container_header() {
+        let data = b"container".repeat(50);
+        for format in [CompressionFormat::LZ4, CompressionFormat::ZSTD, CompressionFormat::Snappy, CompressionFormat::NanoBit] {
+            let compressed = compress(&data, format, CompressionLevel::Default).unwrap();
+            assert_eq!(&compressed[..6], b"NANO\x02\x20");
+            assert_eq!(container_format(&compressed).unwrap(), Some(format));
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+
+        // Unknown formats and corrupt bodies fail precisely
+        let mut compressed = compress(&data, CompressionFormat::ZSTD, CompressionLevel::Default).unwrap();
+        compressed[6] = 9;
+        assert!(matches!(decompress(&compressed), Err(Error::Compression(_))));
+        let mut compressed = compress(&data, CompressionFormat::ZSTD, CompressionLevel::Default).unwrap();
+        compressed.truncate(12);
+        assert!(decompress(&compressed).is_err());
+
+        // Uncompressed payloads are not mistaken for containers
+        let payload = crate::to_bytes(&1u8).unwrap();
+        assert!(decompress(&payload).is_err());
+
+        // Bare LZ4 blocks from earlier versions need an explicit opt-in
+        let legacy = lz4_flex::compress_prepend_size(&data);
+        let err = decompress(&legacy).unwrap_err();
+        assert!(err.to_string().contains("Missing compressed container header"), "{err}");
+        assert_eq!(decompress_legacy(&legacy, usize::MAX).unwrap(), data);
+        let packed = compress(&data, CompressionFormat::LZ4, CompressionLevel::Default).unwrap();
+        assert_eq!(decompress_legacy(&packed, usize::MAX).unwrap(), data);
+    }
+
+    #[test]
+    fn test_nanobit_streaming() {
+        use std::io::{Read, Write};
+
+        let data: Vec<u8> = (0..crate::lz::BLOCK_LEN * 2 + 1000).map(|i| (i % 253) as u8).collect();
+        let mut writer = CompressWriter::new(Vec::new(), CompressionFormat::NanoBit, CompressionLevel::Fastest).unwrap();
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        let body = writer.finish().unwrap();
+        let packed = compress(&data, CompressionFormat::NanoBit, CompressionLevel::Fastest).unwrap();
+        assert_eq!(body, packed[CONTAINER_HEADER_LEN..]);
+
+        // Blocks are decoded one at a time as they are read
+        let mut reader = DecompressReader::from_container(packed.as_slice()).unwrap();
+        let mut out = Vec::new();
+        let mut buf = [0u8; 777];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(out, data);
+
+        // A stream cut inside a block fails instead of ending early
+        let mut reader = DecompressReader::new(&body[..body.len() - 1], CompressionFormat::NanoBit).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_streaming_limit() {
+        use std::io::Read;
+
+        let data = vec![0u8; 100_000];
+        let mut formats = vec![CompressionFormat::Stored, CompressionFormat::NanoBit];
+        #[cfg(feature = "compression")]
+        formats.push(CompressionFormat::LZ4);
+        #[cfg(feature = "multi-compression")]
+        formats.extend([CompressionFormat::ZSTD, CompressionFormat::Snappy]);
+
+        for format in formats {
+            let packed = compress(&data, format, CompressionLevel::Default).unwrap();
+            let mut out = Vec::new();
+            DecompressReader::from_container(packed.as_slice())
+                .unwrap()
+                .with_limit(data.len())
+                .read_to_end(&mut out)
+                .unwrap();
+            assert_eq!(out, data);
+
+            let err = DecompressReader::from_container(packed.as_slice())
+                .unwrap()
+                .with_limit(1000)
+                .read_to_end(&mut Vec::new())
+                .unwrap_err();
+            assert_eq!(Error::from(err), limit_exceeded(1000), "{format:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_compression_policy() {
+        let policy = CompressionPolicy::auto();
+
+        // Small payloads are stored raw
+        let small = b"tiny message".to_vec();
+        let packed = compress_with_policy(&small, &policy).unwrap();
+        assert_eq!(container_format(&packed).unwrap(), Some(CompressionFormat::Stored));
+        assert_eq!(packed.len(), small.len() + CONTAINER_HEADER_LEN);
+        assert_eq!(decompress(&packed).unwrap(), small);
+
+        // So are payloads that do not shrink
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let packed = compress_with_policy(&noise, &policy).unwrap();
+        assert_eq!(container_format(&packed).unwrap(), Some(CompressionFormat::Stored));
+        assert_eq!(decompress(&packed).unwrap(), noise);
+
+        // Compressible payloads are compressed
+        let text = b"compress me ".repeat(100);
+        let packed = compress_with_policy(&text, &policy).unwrap();
+        assert_eq!(container_format(&packed).unwrap(), Some(CompressionFormat::LZ4));
+        assert_eq!(decompress(&packed).unwrap(), text);
+
+        // A plain policy always compresses
+        let always = CompressionPolicy::new(CompressionFormat::LZ4, CompressionLevel::Default);
+        let packed = compress_with_policy(&small, &always).unwrap();
+        assert_eq!(container_format(&packed).unwrap(), Some(CompressionFormat::LZ4));
+        assert_eq!(decompress(&packed).unwrap(), small);
+    }
+
+    #[test]
+    fn test_reused_buffers() {
+        let mut packed = Vec::new();
+        let mut unpacked = Vec::new();
+        for i in 0..4u8 {
+            let data = vec![i; 100 + i as usize];
+            compress_into(&data, CompressionFormat::NanoBit, CompressionLevel::Default, &mut packed).unwrap();
+            assert_eq!(packed, compress(&data, CompressionFormat::NanoBit, CompressionLevel::Default).unwrap());
+
+            decompress_into(&packed, &mut unpacked).unwrap();
+            assert_eq!(unpacked, data);
+        }
+
+        // Capacity is kept between calls
+        let capacity = unpacked.capacity();
+        decompress_into(&packed, &mut unpacked).unwrap();
+        assert_eq!(unpacked.capacity(), capacity);
+    }
+
     #[test]
     #[cfg(feature = "multi-compression")]
     fn test_compression_levels() {
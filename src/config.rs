@@ -0,0 +1,369 @@
+//! Encoding configuration
+
+use crate::checksum::Checksum;
+use crate::error::Result;
+use crate::header::{Header, HeaderFlags};
+
+/// Options controlling how values are encoded and decoded
+///
+/// Options that change the wire format are recorded in the payload header,
+/// so a deserializer picks them up automatically from the data it reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    tagged_fields: bool,
+    lenient_field_count: bool,
+    max_depth: usize,
+    self_describing: bool,
+    canonical: bool,
+    string_table: bool,
+    compact_chars: bool,
+    fixed_tuples: bool,
+    niche_options: bool,
+    deny_non_finite_floats: bool,
+    checksum: Option<Checksum>,
+    schema_hash: Option<u32>,
+}
+
+/// Nesting depth accepted when decoding unless configured otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Config {
+    /// Create the default configuration
+    pub const fn new() -> Self {
+        Self {
+            tagged_fields: false,
+            lenient_field_count: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            self_describing: false,
+            canonical: false,
+            string_table: false,
+            compact_chars: false,
+            fixed_tuples: false,
+            niche_options: false,
+            deny_non_finite_floats: false,
+            checksum: None,
+            schema_hash: None,
+        }
+    }
+
+    /// Encode struct fields as `tag + length + value` for schema evolution
+    ///
+    /// A field's tag is its declaration index, or the number it is renamed
+    /// to (`#[serde(rename = "7")]`) to pin it explicitly. Fields with tags
+    /// the reader does not know are skipped, and fields missing from the
+    /// payload are left to serde's absent-field handling, so mark new fields
+    /// `#[serde(default)]` (or make them `Option`) to fall back to a default.
+    pub const fn with_tagged_fields(mut self, enabled: bool) -> Self {
+        self.tagged_fields = enabled;
+        self
+    }
+
+    /// Check whether struct fields are tagged
+    #[inline]
+    pub const fn tagged_fields(&self) -> bool {
+        self.tagged_fields
+    }
+
+    /// Accept structs whose encoded field count differs from the Rust type
+    ///
+    /// Missing trailing fields are left to serde's absent-field handling
+    /// (`Option` fields become `None`, `#[serde(default)]` fields use their
+    /// default). Extra trailing fields are skipped when the struct is the
+    /// only value of the payload; nested structs and the values of a
+    /// [stream](crate::stream) need tagged fields for that, since plain
+    /// values carry no length to skip by. Decoding option only.
+    pub const fn with_lenient_field_count(mut self, enabled: bool) -> Self {
+        self.lenient_field_count = enabled;
+        self
+    }
+
+    /// Check whether struct field-count mismatches are tolerated
+    #[inline]
+    pub const fn lenient_field_count(&self) -> bool {
+        self.lenient_field_count
+    }
+
+    /// Fail once values nest deeper than `max_depth` while decoding
+    ///
+    /// Sequences, tuples, maps, structs, enums and options each add a
+    /// level. The limit keeps crafted input from exhausting the stack with
+    /// deeply nested values; raise it for deeply recursive types. Defaults
+    /// to 128. Decoding option only.
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Get the maximum nesting depth accepted when decoding
+    #[inline]
+    pub const fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Precede every value with a type tag
+    ///
+    /// Self-describing payloads can be decoded without the Rust type, into
+    /// [`Value`](crate::Value) or any type using `deserialize_any`. Structs
+    /// are written as maps keyed by field name, so fields may be added,
+    /// removed or reordered; this takes precedence over tagged fields.
+    pub const fn with_self_describing(mut self, enabled: bool) -> Self {
+        self.self_describing = enabled;
+        self
+    }
+
+    /// Check whether values carry type tags
+    #[inline]
+    pub const fn self_describing(&self) -> bool {
+        self.self_describing
+    }
+
+    /// Produce byte-for-byte deterministic output
+    ///
+    /// Map entries are sorted by their encoded keys, `-0.0` is written as
+    /// `0.0` and every NaN as the same quiet NaN. Maps with duplicate keys
+    /// are rejected. Sequences keep their order, since serde does not say
+    /// which ones are sets: wrap a `HashSet` or other unordered collection
+    /// in [`UnorderedSet`](crate::UnorderedSet) to sort its elements too,
+    /// or use a `BTreeSet`. The output is an ordinary payload; encoding
+    /// option only.
+    pub const fn with_canonical(mut self, enabled: bool) -> Self {
+        self.canonical = enabled;
+        self
+    }
+
+    /// Check whether output is canonical
+    #[inline]
+    pub const fn canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Write each distinct string once, in a table at the start of the body
+    ///
+    /// Strings in the value become varint indices into the table, which
+    /// pays off when the same strings repeat. Self-describing and canonical
+    /// payloads keep their strings inline, and streaming serializers reject
+    /// the option since the table must precede the value.
+    pub const fn with_string_table(mut self, enabled: bool) -> Self {
+        self.string_table = enabled;
+        self
+    }
+
+    /// Check whether a string table is requested
+    #[inline]
+    pub const fn string_table(&self) -> bool {
+        self.string_table
+    }
+
+    // Whether strings are actually written to a table
+    #[inline]
+    pub(crate) const fn interns_strings(&self) -> bool {
+        self.string_table && !self.self_describing && !self.canonical
+    }
+
+    /// Write chars as their 1–4 UTF-8 bytes instead of a 4-byte u32
+    ///
+    /// Self-describing payloads keep the fixed-width form so their values
+    /// can be skipped by tag alone.
+    pub const fn with_compact_chars(mut self, enabled: bool) -> Self {
+        self.compact_chars = enabled;
+        self
+    }
+
+    /// Check whether compact chars are requested
+    #[inline]
+    pub const fn compact_chars(&self) -> bool {
+        self.compact_chars
+    }
+
+    // Whether chars are actually written as UTF-8
+    #[inline]
+    pub(crate) const fn utf8_chars(&self) -> bool {
+        self.compact_chars && !self.self_describing
+    }
+
+    /// Write tuples, tuple structs and arrays without a length prefix
+    ///
+    /// Their length is part of the Rust type, so the prefix only repeats
+    /// what the reader already knows. Self-describing payloads keep it so
+    /// their values can be skipped without the type.
+    pub const fn with_fixed_tuples(mut self, enabled: bool) -> Self {
+        self.fixed_tuples = enabled;
+        self
+    }
+
+    /// Check whether tuple lengths are omitted
+    #[inline]
+    pub const fn fixed_tuples(&self) -> bool {
+        self.fixed_tuples
+    }
+
+    // Whether tuple lengths are actually left out
+    #[inline]
+    pub(crate) const fn omits_tuple_lengths(&self) -> bool {
+        self.fixed_tuples && !self.self_describing
+    }
+
+    /// Fold the tag of an `Option` into the length of the value it holds
+    ///
+    /// `Some` of a string, byte string, sequence or map is written as its
+    /// length plus one, and `None` as a zero length, so `Option<&str>` and
+    /// `Option<Vec<T>>` cost nothing over the value itself. Other values
+    /// keep their tag. For `Option<NonZeroU64>` and the other `NonZero`
+    /// integers, use [`nanobit::niche`](crate::niche) on the field instead.
+    /// Self-describing payloads keep their tags so values can be skipped
+    /// without the type.
+    pub const fn with_niche_options(mut self, enabled: bool) -> Self {
+        self.niche_options = enabled;
+        self
+    }
+
+    /// Check whether option tags are folded into lengths
+    #[inline]
+    pub const fn niche_options(&self) -> bool {
+        self.niche_options
+    }
+
+    // Whether option tags are actually folded
+    #[inline]
+    pub(crate) const fn folds_option_tags(&self) -> bool {
+        self.niche_options && !self.self_describing
+    }
+
+    /// Fail on NaN and infinite `f32` and `f64` values
+    ///
+    /// Serializing or deserializing such a value returns
+    /// [`Error::NonFiniteFloat`](crate::Error::NonFiniteFloat) with the
+    /// path to it, like `orders.2.price`: struct fields and enum variants
+    /// by name, sequence, tuple and map entries by index. When decoding a
+    /// self-describing payload, map entries with string keys are named by
+    /// key. The encoding is unchanged, so this applies to whichever side
+    /// sets it.
+    pub const fn with_deny_non_finite_floats(mut self, enabled: bool) -> Self {
+        self.deny_non_finite_floats = enabled;
+        self
+    }
+
+    /// Check whether NaN and infinite floats are rejected
+    #[inline]
+    pub const fn deny_non_finite_floats(&self) -> bool {
+        self.deny_non_finite_floats
+    }
+
+    /// Append a checksum of the payload, verified when decoding
+    #[cfg(feature = "checksum")]
+    pub const fn with_checksum(mut self, checksum: Option<Checksum>) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Get the checksum appended to payloads, if any
+    #[inline]
+    pub const fn checksum(&self) -> Option<Checksum> {
+        self.checksum
+    }
+
+    /// Record the schema hash of the written type in the header
+    ///
+    /// [`from_bytes`](crate::from_bytes) compares it with the schema hash of
+    /// the type being decoded and fails with
+    /// [`Error::SchemaMismatch`](crate::Error::SchemaMismatch) instead of
+    /// misreading the payload. Get the hash from
+    /// [`Schema::hash`](crate::schema::Schema::hash).
+    pub const fn with_schema_hash(mut self, hash: Option<u32>) -> Self {
+        self.schema_hash = hash;
+        self
+    }
+
+    /// Get the schema hash recorded in payload headers, if any
+    #[inline]
+    pub const fn schema_hash(&self) -> Option<u32> {
+        self.schema_hash
+    }
+
+    /// Payload header for this configuration
+    pub(crate) fn header(&self) -> Header {
+        Header::new(self.header_flags()).with_schema_hash(self.schema_hash)
+    }
+
+    /// Header flags recording the wire-format options of this configuration
+    pub(crate) fn header_flags(&self) -> HeaderFlags {
+        let mut flags = HeaderFlags::NONE;
+        flags.set(HeaderFlags::TAGGED_FIELDS, self.tagged_fields);
+        flags.set(HeaderFlags::SELF_DESCRIBING, self.self_describing);
+        flags.set(HeaderFlags::STRING_TABLE, self.interns_strings());
+        flags.set(HeaderFlags::COMPACT_CHARS, self.utf8_chars());
+        flags.set(HeaderFlags::FIXED_TUPLES, self.omits_tuple_lengths());
+        flags.set(HeaderFlags::NICHE_OPTIONS, self.folds_option_tags());
+        if let Some(checksum) = self.checksum {
+            flags.set(checksum.flag(), true);
+        }
+        flags
+    }
+
+    /// Apply the wire-format options recorded in a payload header
+    ///
+    /// Fails if the flags name more than one checksum.
+    pub(crate) fn with_header_flags(mut self, flags: HeaderFlags) -> Result<Self> {
+        self.tagged_fields = flags.contains(HeaderFlags::TAGGED_FIELDS);
+        self.self_describing = flags.contains(HeaderFlags::SELF_DESCRIBING);
+        self.string_table = flags.contains(HeaderFlags::STRING_TABLE);
+        self.compact_chars = flags.contains(HeaderFlags::COMPACT_CHARS);
+        self.fixed_tuples = flags.contains(HeaderFlags::FIXED_TUPLES);
+        self.niche_options = flags.contains(HeaderFlags::NICHE_OPTIONS);
+        self.checksum = Checksum::from_flags(flags)?;
+        Ok(self)
+    }
+}
+
+/// Tag of a struct field in tagged-field mode
+///
+/// Numeric field names pin the tag; otherwise the declaration index is used.
+#[inline]
+pub(crate) fn field_tag(index: usize, name: &str) -> u64 {
+    if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+        if let Ok(tag) = name.parse::<u64>() {
+            return tag;
+        }
+    }
+    index as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn test_header_flags_roundtrip() {
+        let config = Config::new()
+            .with_tagged_fields(true)
+            .with_self_describing(true)
+            .with_string_table(true)
+            .with_compact_chars(true)
+            .with_niche_options(true)
+            .with_checksum(Some(Checksum::XxHash64));
+        let flags = config.header_flags();
+        let decoded = Config::new().with_header_flags(flags).unwrap();
+        assert_eq!(decoded.header_flags(), flags);
+        assert_eq!(decoded.checksum(), Some(Checksum::XxHash64));
+        assert!(decoded.tagged_fields() && decoded.self_describing());
+
+        // Options missing from the header are cleared
+        let decoded = config.with_header_flags(HeaderFlags::NONE).unwrap();
+        assert_eq!(decoded.header_flags(), HeaderFlags::NONE);
+        assert_eq!(decoded.checksum(), None);
+    }
+
+    #[test]
+    fn test_conflicting_checksum_flags() {
+        let mut flags = HeaderFlags::CHECKSUM_CRC32C;
+        flags.set(HeaderFlags::CHECKSUM_XXH64, true);
+        assert!(matches!(Config::new().with_header_flags(flags), Err(Error::InvalidFormat(_))));
+    }
+}
@@ -0,0 +1,488 @@
+//! Borrow from the input when it lives long enough, copy when it does not
+//!
+//! A struct of `&'a str` fields decodes without copying, but only from a
+//! buffer that outlives the value. Bytes that were just decompressed or
+//! read from a socket are dropped at the end of the call, so the same
+//! struct cannot be read from them at all. Declare such fields as
+//! `Cow<'a, str>` or `Cow<'a, [u8]>` with `#[serde(borrow)]` instead, and
+//! hand the input to [`from_bytes_cow`]: borrowed input yields borrowed
+//! fields, owned input yields owned fields, and one type covers both.
+//!
+//! ```rust
+//! use std::borrow::Cow;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Event<'a> {
+//!     id: u64,
+//!     #[serde(borrow)]
+//!     source: Cow<'a, str>,
+//!     #[serde(borrow)]
+//!     reading: Cow<'a, [u8]>,
+//! }
+//!
+//! let event = Event { id: 3, source: "sensor-7".into(), reading: vec![4, 2].into() };
+//! let bytes = nanobit::to_bytes(&event)?;
+//!
+//! // Borrowed input: the strings point into `bytes`
+//! let view: Event = nanobit::from_bytes_cow(Cow::Borrowed(&bytes))?;
+//! assert!(matches!(view.source, Cow::Borrowed(_)));
+//!
+//! // Owned input, such as freshly decompressed data: the strings are copied
+//! let owned: Event<'static> = nanobit::from_bytes_cow(Cow::Owned(bytes.clone()))?;
+//! assert!(matches!(owned.source, Cow::Owned(_)));
+//! assert_eq!(owned, view);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Without `#[serde(borrow)]`, serde always copies into a `Cow` field, so
+//! the attribute is what lets the borrowed path skip the copy. It only
+//! reaches `Cow` fields themselves: a `Cow` inside an `Option`, a `Vec` or
+//! a map is copied either way. Fields of
+//! plain `&'a str` or `&'a [u8]` still need borrowed input; from owned
+//! input they fail with an error rather than dangling.
+//!
+//! [`from_bytes_owned`] is the owned path on its own, for input that is
+//! only borrowed for the duration of the call.
+
+#[cfg(not(feature = "std"))]
+use alloc::{borrow::Cow, string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{
+    Deserialize, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::metrics::{Operation, Probe};
+
+/// Deserialize from bytes that are either borrowed for `'a` or owned
+///
+/// Borrowed input is read with [`from_bytes`](crate::from_bytes), so
+/// `Cow` fields marked `#[serde(borrow)]` point into it. Owned input is
+/// read with [`from_bytes_owned`], which copies them. See the
+/// [module documentation](self).
+pub fn from_bytes_cow<'a, T>(bytes: Cow<'a, [u8]>) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_cow_with_config(bytes, Config::new())
+}
+
+/// Deserialize from borrowed or owned bytes using the given decoding options
+pub fn from_bytes_cow_with_config<'a, T>(bytes: Cow<'a, [u8]>, config: Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    match bytes {
+        Cow::Borrowed(bytes) => crate::from_bytes_with_config(bytes, config),
+        Cow::Owned(bytes) => from_bytes_owned_with_config(&bytes, config),
+    }
+}
+
+/// Deserialize a value of any lifetime without borrowing from `bytes`
+///
+/// Strings and byte strings are handed to the value as temporaries, so
+/// `Cow` fields come out owned and `bytes` may be dropped right after the
+/// call. Fields that can only borrow, such as `&str`, fail with an error.
+pub fn from_bytes_owned<'a, T>(bytes: &[u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_owned_with_config(bytes, Config::new())
+}
+
+/// Deserialize without borrowing from `bytes`, using the given decoding
+/// options
+pub fn from_bytes_owned_with_config<'a, T>(bytes: &[u8], config: Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let probe = Probe::start(Operation::Deserialize, Some(core::any::type_name::<T>()));
+    let result = crate::Deserializer::with_config(bytes, config).and_then(|mut deserializer| {
+        crate::schema::verify_hash::<T>(deserializer.config().schema_hash())?;
+        T::deserialize(Unborrowed::new(&mut deserializer))
+    });
+    probe.finish(&result, bytes.len(), None);
+    result
+}
+
+// Presents a `Deserializer<'x>` as a `Deserializer<'a>` for any `'a` by
+// passing borrowed strings and bytes on as transient ones
+struct Unborrowed<'x, D> {
+    de: D,
+    marker: PhantomData<&'x ()>,
+}
+
+impl<D> Unborrowed<'_, D> {
+    fn new(de: D) -> Self {
+        Self { de, marker: PhantomData }
+    }
+}
+
+// Wraps a visitor, seed or access type of one lifetime for use with another
+struct Adapt<'a, T> {
+    inner: T,
+    marker: PhantomData<&'a ()>,
+}
+
+impl<T> Adapt<'_, T> {
+    fn new(inner: T) -> Self {
+        Self { inner, marker: PhantomData }
+    }
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        fn $method<V>(self, $($arg: $ty,)* visitor: V) -> core::result::Result<V::Value, D::Error>
+        where
+            V: Visitor<'a>,
+        {
+            self.de.$method($($arg,)* Adapt::new(visitor))
+        }
+    )*};
+}
+
+impl<'a, 'x, D> Deserializer<'a> for Unborrowed<'x, D>
+where
+    D: Deserializer<'x>,
+{
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.de.is_human_readable()
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {$(
+        fn $method<E: serde::de::Error>(self, v: $ty) -> core::result::Result<V::Value, E> {
+            self.inner.$method(v)
+        }
+    )*};
+}
+
+impl<'a, 'x, V> Visitor<'x> for Adapt<'a, V>
+where
+    V: Visitor<'a>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_string(String);
+        visit_bytes(&[u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_borrowed_str<E: serde::de::Error>(self, v: &'x str) -> core::result::Result<V::Value, E> {
+        self.inner.visit_str(v)
+    }
+
+    fn visit_borrowed_bytes<E: serde::de::Error>(self, v: &'x [u8]) -> core::result::Result<V::Value, E> {
+        self.inner.visit_bytes(v)
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> core::result::Result<V::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> core::result::Result<V::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> core::result::Result<V::Value, D::Error>
+    where
+        D: Deserializer<'x>,
+    {
+        self.inner.visit_some(Unborrowed::new(deserializer))
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> core::result::Result<V::Value, D::Error>
+    where
+        D: Deserializer<'x>,
+    {
+        self.inner.visit_newtype_struct(Unborrowed::new(deserializer))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> core::result::Result<V::Value, A::Error>
+    where
+        A: SeqAccess<'x>,
+    {
+        self.inner.visit_seq(Adapt::<'x, _>::new(seq))
+    }
+
+    fn visit_map<A>(self, map: A) -> core::result::Result<V::Value, A::Error>
+    where
+        A: MapAccess<'x>,
+    {
+        self.inner.visit_map(Adapt::<'x, _>::new(map))
+    }
+
+    fn visit_enum<A>(self, data: A) -> core::result::Result<V::Value, A::Error>
+    where
+        A: EnumAccess<'x>,
+    {
+        self.inner.visit_enum(Adapt::<'x, _>::new(data))
+    }
+}
+
+impl<'a, 'x, T> DeserializeSeed<'x> for Adapt<'a, T>
+where
+    T: DeserializeSeed<'a>,
+{
+    type Value = T::Value;
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<T::Value, D::Error>
+    where
+        D: Deserializer<'x>,
+    {
+        self.inner.deserialize(Unborrowed::new(deserializer))
+    }
+}
+
+impl<'a, 'x, A> SeqAccess<'a> for Adapt<'x, A>
+where
+    A: SeqAccess<'x>,
+{
+    type Error = A::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> core::result::Result<Option<T::Value>, A::Error>
+    where
+        T: DeserializeSeed<'a>,
+    {
+        self.inner.next_element_seed(Adapt::new(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'x, A> MapAccess<'a> for Adapt<'x, A>
+where
+    A: MapAccess<'x>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> core::result::Result<Option<K::Value>, A::Error>
+    where
+        K: DeserializeSeed<'a>,
+    {
+        self.inner.next_key_seed(Adapt::new(seed))
+    }
+
+    fn next_value_seed<T>(&mut self, seed: T) -> core::result::Result<T::Value, A::Error>
+    where
+        T: DeserializeSeed<'a>,
+    {
+        self.inner.next_value_seed(Adapt::new(seed))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'x, A> EnumAccess<'a> for Adapt<'x, A>
+where
+    A: EnumAccess<'x>,
+{
+    type Error = A::Error;
+    type Variant = Adapt<'x, A::Variant>;
+
+    fn variant_seed<T>(self, seed: T) -> core::result::Result<(T::Value, Self::Variant), A::Error>
+    where
+        T: DeserializeSeed<'a>,
+    {
+        let (value, variant) = self.inner.variant_seed(Adapt::new(seed))?;
+        Ok((value, Adapt::new(variant)))
+    }
+}
+
+impl<'a, 'x, A> VariantAccess<'a> for Adapt<'x, A>
+where
+    A: VariantAccess<'x>,
+{
+    type Error = A::Error;
+
+    fn unit_variant(self) -> core::result::Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> core::result::Result<T::Value, A::Error>
+    where
+        T: DeserializeSeed<'a>,
+    {
+        self.inner.newtype_variant_seed(Adapt::new(seed))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> core::result::Result<V::Value, A::Error>
+    where
+        V: Visitor<'a>,
+    {
+        self.inner.tuple_variant(len, Adapt::new(visitor))
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> core::result::Result<V::Value, A::Error>
+    where
+        V: Visitor<'a>,
+    {
+        self.inner.struct_variant(fields, Adapt::new(visitor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Body<'a> {
+        Empty,
+        Text(#[serde(borrow)] Cow<'a, str>),
+        Pair(u8, #[serde(borrow)] Cow<'a, [u8]>),
+        Named {
+            #[serde(borrow)]
+            key: Cow<'a, str>,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Message<'a> {
+        id: u32,
+        #[serde(borrow)]
+        topic: Cow<'a, str>,
+        #[serde(borrow)]
+        payload: Cow<'a, [u8]>,
+        #[serde(borrow)]
+        headers: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+        #[serde(borrow)]
+        bodies: Vec<Body<'a>>,
+    }
+
+    fn message() -> Message<'static> {
+        Message {
+            id: 12,
+            topic: "orders".into(),
+            payload: Cow::Owned(vec![1, 2, 3]),
+            headers: [("trace".into(), "abc".into())].into_iter().collect(),
+            bodies: vec![
+                Body::Empty,
+                Body::Text("hello".into()),
+                Body::Pair(4, Cow::Owned(vec![5, 6])),
+                Body::Named { key: "k".into() },
+            ],
+        }
+    }
+
+    fn borrowed(message: &Message<'_>) -> bool {
+        let borrowed = |cow: &Cow<'_, str>| matches!(cow, Cow::Borrowed(_));
+        borrowed(&message.topic)
+            && matches!(message.payload, Cow::Borrowed(_))
+            && matches!(&message.bodies[1], Body::Text(text) if borrowed(text))
+    }
+
+    fn owned(message: &Message<'_>) -> bool {
+        let owned = |cow: &Cow<'_, str>| matches!(cow, Cow::Owned(_));
+        owned(&message.topic)
+            && message.headers.iter().all(|(k, v)| owned(k) && owned(v))
+            && matches!(message.payload, Cow::Owned(_))
+            && matches!(&message.bodies[1], Body::Text(text) if owned(text))
+            && matches!(&message.bodies[2], Body::Pair(_, Cow::Owned(_)))
+            && matches!(&message.bodies[3], Body::Named { key: Cow::Owned(_) })
+    }
+
+    #[test]
+    fn test_from_bytes_cow() {
+        let configs = [
+            Config::new(),
+            Config::new().with_string_table(true),
+            Config::new().with_self_describing(true),
+        ];
+        for config in configs {
+            let bytes = crate::to_bytes_with_config(&message(), config).unwrap();
+
+            let view: Message<'_> = from_bytes_cow(Cow::Borrowed(&bytes)).unwrap();
+            assert_eq!(view, message());
+            // Interned strings and self-describing byte sequences are copied
+            if config == Config::new() {
+                assert!(borrowed(&view));
+            }
+
+            let owned_message: Message<'static> = from_bytes_cow(Cow::Owned(bytes.clone())).unwrap();
+            assert_eq!(owned_message, message());
+            assert!(owned(&owned_message));
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_owned_rejects_borrows() {
+        let bytes = crate::to_bytes(&("name", 7u8)).unwrap();
+        assert!(from_bytes_owned::<(&str, u8)>(&bytes).is_err());
+        assert_eq!(from_bytes_owned::<(String, u8)>(&bytes).unwrap(), ("name".to_string(), 7));
+        assert_eq!(crate::from_bytes::<(&str, u8)>(&bytes).unwrap(), ("name", 7));
+    }
+}
@@ -0,0 +1,268 @@
+//! Authenticated encryption envelopes
+//!
+//! An encrypted payload is a complete nanobit payload sealed with an AEAD
+//! cipher and wrapped in a container:
+//!
+//! ```text
+//! ["NANO"] [0x02] [flags: ENCRYPTED] [algorithm: 1 byte] [key id: u32 LE]
+//! [nonce: 12 bytes] [ciphertext + 16 byte tag]
+//! ```
+//!
+//! The container header up to and including the nonce is authenticated as
+//! associated data, so the algorithm and key id cannot be altered. A fresh
+//! random nonce is generated for every envelope.
+//!
+//! Key material and decrypted payloads are overwritten with zeros when they
+//! are dropped.
+
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::rand_core::RngCore;
+use zeroize::Zeroize;
+
+pub use zeroize::Zeroizing;
+
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+
+/// Nonce size shared by the supported ciphers
+const NONCE_LEN: usize = 12;
+
+/// Bytes before the nonce: header, algorithm and key id
+const PREFIX_LEN: usize = 6 + 1 + 4;
+
+/// Length of the authenticated container header
+const ENVELOPE_HEADER_LEN: usize = PREFIX_LEN + NONCE_LEN;
+
+/// AEAD cipher used to seal a payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// ChaCha20-Poly1305, fast without hardware AES support
+    ChaCha20Poly1305,
+    /// AES-256-GCM
+    Aes256Gcm,
+}
+
+impl Algorithm {
+    fn to_byte(self) -> u8 {
+        match self {
+            Algorithm::ChaCha20Poly1305 => 1,
+            Algorithm::Aes256Gcm => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            1 => Ok(Algorithm::ChaCha20Poly1305),
+            2 => Ok(Algorithm::Aes256Gcm),
+            other => Err(Error::Crypto(format!("Unknown cipher algorithm: {other}"))),
+        }
+    }
+}
+
+/// A 256-bit encryption key with its identifier
+///
+/// The key material is overwritten with zeros when the key is dropped. Keys
+/// do not implement `PartialEq`, so they cannot be compared in variable
+/// time; compare [`id`](Key::id)s instead.
+#[derive(Clone)]
+pub struct Key {
+    id: u32,
+    algorithm: Algorithm,
+    bytes: [u8; 32],
+}
+
+impl Key {
+    /// Create a key for the given algorithm
+    ///
+    /// The id is stored in every envelope so the right key can be selected
+    /// during key rotation.
+    pub const fn new(id: u32, algorithm: Algorithm, bytes: [u8; 32]) -> Self {
+        Self { id, algorithm, bytes }
+    }
+
+    /// Generate a random key
+    pub fn generate(id: u32, algorithm: Algorithm) -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let key = Self::new(id, algorithm, bytes);
+        bytes.zeroize();
+        key
+    }
+
+    /// Get the key identifier
+    #[inline]
+    pub const fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Get the cipher algorithm
+    #[inline]
+    pub const fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    // Borrow the key bytes for a cipher without copying them
+    fn material(&self) -> &GenericArray<u8, chacha20poly1305::consts::U32> {
+        GenericArray::from_slice(&self.bytes)
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+    }
+}
+
+impl core::fmt::Debug for Key {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Key")
+            .field("id", &self.id)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serialize a value and seal it in an encrypted envelope
+pub fn serialize_encrypted<T>(value: &T, key: &Key) -> Result<Vec<u8>>
+where
+    T: serde::Serialize,
+{
+    encrypt(&Zeroizing::new(crate::to_bytes(value)?), key)
+}
+
+/// Open an encrypted envelope and deserialize its payload
+pub fn deserialize_encrypted<T>(bytes: &[u8], key: &Key) -> Result<T>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    let payload = decrypt(bytes, key)?;
+    crate::from_bytes(&payload)
+}
+
+/// Seal an already serialized payload in an encrypted envelope
+pub fn encrypt(payload: &[u8], key: &Key) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut out = Vec::with_capacity(ENVELOPE_HEADER_LEN + payload.len() + 16);
+    Header::new(HeaderFlags::ENCRYPTED).write_to(&mut out);
+    out.push(key.algorithm.to_byte());
+    out.extend_from_slice(&key.id.to_le_bytes());
+    out.extend_from_slice(&nonce);
+
+    let sealed = seal(key, &nonce, Payload { msg: payload, aad: &out })?;
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Open an encrypted envelope, returning the serialized payload
+///
+/// The payload is wiped when the returned buffer is dropped.
+pub fn decrypt(envelope: &[u8], key: &Key) -> Result<Zeroizing<Vec<u8>>> {
+    let (algorithm, key_id) = parse_prefix(envelope)?;
+    if key_id != key.id {
+        return Err(Error::Crypto(format!(
+            "Envelope was sealed with key {key_id}, not key {}",
+            key.id
+        )));
+    }
+    if algorithm != key.algorithm {
+        return Err(Error::Crypto(format!(
+            "Envelope uses {algorithm:?}, key is for {:?}",
+            key.algorithm
+        )));
+    }
+
+    let (aad, ciphertext) = envelope.split_at(ENVELOPE_HEADER_LEN);
+    let nonce = &aad[PREFIX_LEN..];
+    open(key, nonce, Payload { msg: ciphertext, aad }).map(Zeroizing::new)
+}
+
+/// Read the key id of an encrypted envelope, to pick the key for [`decrypt`]
+pub fn envelope_key_id(envelope: &[u8]) -> Result<u32> {
+    parse_prefix(envelope).map(|(_, id)| id)
+}
+
+fn parse_prefix(envelope: &[u8]) -> Result<(Algorithm, u32)> {
+    let (header, header_len) = Header::parse(envelope)?;
+    if !header.flags.contains(HeaderFlags::ENCRYPTED) {
+        return Err(Error::Crypto("Payload is not encrypted".to_string()));
+    }
+    if header_len != 6 || envelope.len() < ENVELOPE_HEADER_LEN {
+        return Err(Error::Crypto("Envelope too short".to_string()));
+    }
+    let algorithm = Algorithm::from_byte(envelope[6])?;
+    let mut id = [0u8; 4];
+    id.copy_from_slice(&envelope[7..PREFIX_LEN]);
+    Ok((algorithm, u32::from_le_bytes(id)))
+}
+
+fn seal(key: &Key, nonce: &[u8], payload: Payload<'_, '_>) -> Result<Vec<u8>> {
+    let result = match key.algorithm {
+        Algorithm::ChaCha20Poly1305 => {
+            chacha20poly1305::ChaCha20Poly1305::new(key.material()).encrypt(nonce.into(), payload)
+        }
+        Algorithm::Aes256Gcm => aes_gcm::Aes256Gcm::new(key.material()).encrypt(nonce.into(), payload),
+    };
+    result.map_err(|_| Error::Crypto("Encryption failed".to_string()))
+}
+
+fn open(key: &Key, nonce: &[u8], payload: Payload<'_, '_>) -> Result<Vec<u8>> {
+    let result = match key.algorithm {
+        Algorithm::ChaCha20Poly1305 => {
+            chacha20poly1305::ChaCha20Poly1305::new(key.material()).decrypt(nonce.into(), payload)
+        }
+        Algorithm::Aes256Gcm => aes_gcm::Aes256Gcm::new(key.material()).decrypt(nonce.into(), payload),
+    };
+    result.map_err(|_| Error::Crypto("Decryption failed: wrong key or tampered envelope".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        for algorithm in [Algorithm::ChaCha20Poly1305, Algorithm::Aes256Gcm] {
+            let key = Key::generate(7, algorithm);
+            let value = vec!["secret".to_string(), "data".to_string()];
+
+            let sealed = serialize_encrypted(&value, &key).unwrap();
+            assert_eq!(envelope_key_id(&sealed).unwrap(), 7);
+            assert!(!sealed.windows(6).any(|w| w == b"secret"));
+
+            let opened: Vec<String> = deserialize_encrypted(&sealed, &key).unwrap();
+            assert_eq!(opened, value);
+
+            // The decrypted payload is wiped when dropped
+            let payload: Zeroizing<Vec<u8>> = decrypt(&sealed, &key).unwrap();
+            assert_eq!(*payload, crate::to_bytes(&value).unwrap());
+
+            // Fresh nonce every time
+            assert_ne!(serialize_encrypted(&value, &key).unwrap(), sealed);
+        }
+    }
+
+    #[test]
+    fn test_tampering_rejected() {
+        let key = Key::new(1, Algorithm::ChaCha20Poly1305, [9; 32]);
+        let sealed = serialize_encrypted(&42u64, &key).unwrap();
+
+        let mut flipped = sealed.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(deserialize_encrypted::<u64>(&flipped, &key).is_err());
+
+        // The key id is authenticated
+        let mut relabeled = sealed.clone();
+        relabeled[7] = 2;
+        let other = Key::new(2, Algorithm::ChaCha20Poly1305, [9; 32]);
+        assert!(deserialize_encrypted::<u64>(&relabeled, &other).is_err());
+
+        let wrong = Key::new(1, Algorithm::ChaCha20Poly1305, [8; 32]);
+        assert!(deserialize_encrypted::<u64>(&sealed, &wrong).is_err());
+
+        // Plain decoding refuses envelopes
+        assert!(crate::from_bytes::<u64>(&sealed).is_err());
+    }
+}
@@ -6,53 +6,408 @@ use alloc::{vec::Vec, string::String};
 #[cfg(feature = "std")]
 use std::io::Read;
 
+use core::marker::PhantomData;
+
 use serde::de::{
     Deserialize, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
 };
 
 use crate::buffer::ReadBuffer;
+use crate::config::{field_tag, Config};
 use crate::error::{Error, Result};
+use crate::checksum::split_payload;
+use crate::header::HeaderFlags;
+use crate::metrics::{Operation, Probe};
+use crate::tag;
 
 /// High-performance binary deserializer
 pub struct Deserializer<'de> {
-    reader: ReadBuffer<'de>,
+    pub(crate) reader: ReadBuffer<'de>,
+    config: Config,
+    // Length of the header that precedes the body
+    body_start: usize,
+    at_root: bool,
+    // Start and end of a value whose length is known, such as a tagged field
+    value_bound: Option<(usize, usize)>,
+    // String table of the current top-level value, and whether the first
+    // table was read on construction and not yet used
+    strings: Vec<&'de str>,
+    strings_fresh: bool,
+    // Nesting depth of the value being read, see `Config::with_max_depth`
+    depth: usize,
+    // Position just after the tag of the `Some` being read, and the length
+    // folded into that tag, see `Config::with_niche_options`
+    some_len: Option<(usize, usize)>,
+    // Position where a struct wrapped in `Nullable` must start
+    nullable_at: Option<usize>,
+    // Position where the struct field being read starts, and its bit in the
+    // struct's presence bitmap
+    field_bit: Option<(usize, bool)>,
 }
 
 impl<'de> Deserializer<'de> {
     /// Create a new deserializer from bytes
     pub fn new(data: &'de [u8]) -> Result<Self> {
-        // Verify header
-        if data.len() < 5 {
-            return Err(Error::InvalidFormat("Data too short for header".to_string()));
+        Self::with_config(data, Config::new())
+    }
+
+    /// Create a new deserializer from bytes using the given decoding options
+    ///
+    /// Wire-format options recorded in the payload header take precedence.
+    pub fn with_config(data: &'de [u8], config: Config) -> Result<Self> {
+        // Verify header (magic bytes, version and flags) and any checksum
+        let (header, body) = split_payload(data)?;
+
+        // Create reader over the body between header and trailer
+        let reader = ReadBuffer::new(body);
+        let config = config.with_header_flags(header.flags)?.with_schema_hash(header.schema_hash);
+
+        let mut de = Self {
+            reader,
+            config,
+            // The body is a subslice of `data`
+            body_start: body.as_ptr() as usize - data.as_ptr() as usize,
+            at_root: true,
+            value_bound: None,
+            strings: Vec::new(),
+            strings_fresh: true,
+            depth: 0,
+            some_len: None,
+            nullable_at: None,
+            field_bit: None,
+        };
+        if header.flags.contains(HeaderFlags::SEQ_INDEX) {
+            skip_seq_index(&mut de.reader)?;
+        }
+        de.read_string_table()?;
+        Ok(de)
+    }
+
+    // Create a deserializer over a body whose strings index a table kept by
+    // the caller
+    pub(crate) fn with_string_table(body: &'de [u8], config: Config, strings: Vec<&'de str>) -> Self {
+        Self {
+            strings,
+            ..Self::from_body(body, config.with_string_table(true))
+        }
+    }
+
+    // Create a deserializer over part of a body, with the options of its
+    // payload already applied
+    pub(crate) fn from_body(body: &'de [u8], config: Config) -> Self {
+        Self {
+            reader: ReadBuffer::new(body),
+            config,
+            body_start: 0,
+            at_root: true,
+            value_bound: None,
+            strings: Vec::new(),
+            strings_fresh: true,
+            depth: 0,
+            some_len: None,
+            nullable_at: None,
+            field_bit: None,
+        }
+    }
+
+    // Whether the compound being entered is the top-level value
+    #[inline]
+    pub(crate) fn enter_compound(&mut self) -> bool {
+        core::mem::replace(&mut self.at_root, false)
+    }
+
+    // Decode a value nested one level deeper than the current one
+    #[inline]
+    fn nested<T>(&mut self, decode: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let limit = self.config.max_depth();
+        if self.depth >= limit {
+            return Err(Error::LimitExceeded { what: "Nesting depth", limit });
+        }
+        self.depth += 1;
+        let result = decode(self);
+        self.depth -= 1;
+        result
+    }
+
+    #[inline]
+    fn read_f32(&mut self) -> Result<f32> {
+        let value = self.reader.read_f32()?;
+        self.check_finite(value.into())?;
+        Ok(value)
+    }
+
+    #[inline]
+    fn read_f64(&mut self) -> Result<f64> {
+        let value = self.reader.read_f64()?;
+        self.check_finite(value)?;
+        Ok(value)
+    }
+
+    // Reject NaN and infinities when non-finite floats are denied; the
+    // path is filled in as the error leaves each enclosing value
+    #[inline]
+    fn check_finite(&self, value: f64) -> Result<()> {
+        if self.config.deny_non_finite_floats() && !value.is_finite() {
+            return Err(Error::NonFiniteFloat { value, path: String::new() });
         }
+        Ok(())
+    }
+
+    /// Get the configuration in effect, including options read from the header
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Byte offset into the payload body of the next value to be read
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.reader.position()
+    }
 
-        // Check magic bytes
-        if &data[0..4] != crate::MAGIC {
-            return Err(Error::InvalidFormat("Invalid magic bytes".to_string()));
+    /// Byte offset into the whole payload of the next value to be read,
+    /// counting the header
+    ///
+    /// After an error this is where decoding stopped.
+    #[inline]
+    pub fn byte_offset(&self) -> usize {
+        self.body_start + self.reader.position()
+    }
+
+    /// Number of body bytes not yet read
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.reader.remaining()
+    }
+
+    /// Check whether the whole body has been read
+    #[inline]
+    pub fn is_end(&self) -> bool {
+        !self.reader.has_remaining()
+    }
+
+    /// The body bytes not yet read, without consuming them
+    #[inline]
+    pub fn peek_bytes(&self) -> &'de [u8] {
+        &self.reader.as_slice()[self.reader.position()..]
+    }
+
+    /// Kind of the next value, such as `"u32"` or `"map"`, for
+    /// self-describing payloads
+    ///
+    /// Returns `None` for other payloads, whose bytes do not say what
+    /// they hold, and at the end of the body.
+    pub fn peek_kind(&self) -> Option<&'static str> {
+        if !self.config.self_describing() {
+            return None;
         }
+        self.reader.peek_u8().ok().map(tag::name)
+    }
 
-        // Check version
-        let version = data[4];
-        if version != crate::VERSION {
-            return Err(Error::UnsupportedVersion(version));
+    // Start the next value of a stream of values
+    //
+    // Other values may follow it, so it is not treated as the top-level
+    // value whose trailing bytes can be skipped.
+    #[inline]
+    pub(crate) fn begin_root(&mut self) -> Result<()> {
+        self.at_root = false;
+        if core::mem::take(&mut self.strings_fresh) {
+            return Ok(());
         }
+        self.read_string_table()
+    }
 
-        // Create reader starting after header
-        let reader = ReadBuffer::new(&data[5..]);
+    #[inline]
+    fn uses_string_table(&self) -> bool {
+        self.config.string_table() && !self.config.self_describing()
+    }
 
-        Ok(Self { reader })
+    // Read the string table that precedes each top-level value, if enabled
+    fn read_string_table(&mut self) -> Result<()> {
+        if !self.uses_string_table() {
+            return Ok(());
+        }
+        let count = self.reader.read_varint()?;
+        // Every entry takes at least one byte, which bounds the allocation
+        if count > self.reader.remaining() as u64 {
+            return Err(Error::UnexpectedEof);
+        }
+        self.strings.clear();
+        for _ in 0..count {
+            let s = self.reader.read_str()?;
+            self.strings.push(s);
+        }
+        Ok(())
+    }
+
+    // Claim the presence bit of the struct field being read, if nothing of
+    // the field has been read yet
+    #[inline]
+    fn take_field_bit(&mut self) -> Option<bool> {
+        match self.field_bit.take() {
+            Some((at, set)) if at == self.reader.position() => Some(set),
+            _ => None,
+        }
+    }
+
+    // Read the length that starts a string, byte string, sequence or map,
+    // unless it was folded into the tag of the `Some` just before it
+    #[inline]
+    fn read_len(&mut self) -> Result<usize> {
+        match self.some_len.take() {
+            Some((end, len)) if end == self.reader.position() => Ok(len),
+            _ => Ok(self.reader.read_varint()? as usize),
+        }
+    }
+
+    // Read a string, resolving string table indices
+    #[inline]
+    fn read_str(&mut self) -> Result<&'de str> {
+        if !self.uses_string_table() {
+            if self.some_len.is_some() {
+                let len = self.read_len()?;
+                return self.reader.read_str_bytes(len);
+            }
+            return self.reader.read_str();
+        }
+        let id = self.reader.read_varint()?;
+        self.strings
+            .get(id as usize)
+            .copied()
+            .ok_or_else(|| Error::InvalidFormat(format!("String table index out of range: {id}")))
+    }
+
+    // Skip one self-describing value
+    #[inline]
+    pub(crate) fn skip_value(&mut self) -> Result<()> {
+        skip_value(&mut self.reader)
+    }
+
+    // Visit a self-describing byte string as a sequence of `u8` values
+    fn visit_bytes_as_seq<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.reader.skip(1)?;
+        let bytes = self.reader.read_byte_slice()?;
+        let mut seq = serde::de::value::SeqDeserializer::<_, Error>::new(bytes.iter().copied());
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    // Read a self-describing sequence of `u8` values
+    fn read_u8_seq(&mut self) -> Result<Vec<u8>> {
+        self.reader.skip(1)?;
+        let len = self.reader.read_varint()? as usize;
+        // Each element takes two bytes, which bounds the allocation
+        let mut bytes = Vec::with_capacity(len.min(self.reader.remaining() / 2));
+        for _ in 0..len {
+            match self.reader.read_u8()? {
+                tag::U8 => bytes.push(self.reader.read_u8()?),
+                found => {
+                    return Err(Error::InvalidFormat(format!(
+                        "Expected u8, found {}",
+                        tag::name(found)
+                    )))
+                }
+            }
+        }
+        Ok(bytes)
     }
 }
 
+// Skip the chunk index that precedes an indexed sequence, see `parallel`
+pub(crate) fn skip_seq_index(reader: &mut ReadBuffer<'_>) -> Result<()> {
+    let len = reader.read_varint()? as usize;
+    reader.skip(len)
+}
+
+// Skip one self-describing value without recursing into nested values
+pub(crate) fn skip_value(reader: &mut ReadBuffer<'_>) -> Result<()> {
+    let mut pending: u64 = 1;
+    while pending > 0 {
+        pending -= 1;
+        let found = reader.read_u8()?;
+        if let Some(size) = tag::fixed_size(found) {
+            reader.skip(size)?;
+            continue;
+        }
+        match found {
+            tag::SOME => pending += 1,
+            tag::STR | tag::BYTES => {
+                let len = reader.read_varint()? as usize;
+                reader.skip(len)?;
+            }
+            tag::SEQ => pending = pending.saturating_add(reader.read_varint()?),
+            tag::MAP => {
+                let len = reader.read_varint()?;
+                pending = pending.saturating_add(len.saturating_mul(2));
+            }
+            tag::VARIANT => {
+                reader.read_varint()?;
+                reader.read_str()?;
+                pending += 1;
+            }
+            other => {
+                return Err(Error::InvalidFormat(format!("Unknown type tag: {other:#04x}")))
+            }
+        }
+    }
+    Ok(())
+}
+
 impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
-    #[inline]
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::Serde("deserialize_any is not supported".to_string()))
+        if !self.config.self_describing() {
+            return Err(Error::Serde("deserialize_any is not supported".to_string()));
+        }
+
+        match self.reader.read_u8()? {
+            tag::UNIT => visitor.visit_unit(),
+            tag::NONE => visitor.visit_none(),
+            tag::SOME => self.nested(|de| visitor.visit_some(de)),
+            tag::FALSE => visitor.visit_bool(false),
+            tag::TRUE => visitor.visit_bool(true),
+            tag::I8 => visitor.visit_i8(self.reader.read_i8()?),
+            tag::I16 => visitor.visit_i16(self.reader.read_i16()?),
+            tag::I32 => visitor.visit_i32(self.reader.read_i32()?),
+            tag::I64 => visitor.visit_i64(self.reader.read_i64()?),
+            tag::U8 => visitor.visit_u8(self.reader.read_u8()?),
+            tag::U16 => visitor.visit_u16(self.reader.read_u16()?),
+            tag::U32 => visitor.visit_u32(self.reader.read_u32()?),
+            tag::U64 => visitor.visit_u64(self.reader.read_u64()?),
+            tag::F32 => visitor.visit_f32(self.read_f32()?),
+            tag::F64 => visitor.visit_f64(self.read_f64()?),
+            tag::CHAR => {
+                let value = self.reader.read_u32()?;
+                let ch = char::from_u32(value)
+                    .ok_or_else(|| Error::InvalidFormat("Invalid char value".to_string()))?;
+                visitor.visit_char(ch)
+            }
+            tag::STR => visitor.visit_borrowed_str(self.reader.read_str()?),
+            tag::BYTES => visitor.visit_borrowed_bytes(self.reader.read_byte_slice()?),
+            tag::SEQ => {
+                self.enter_compound();
+                let len = self.reader.read_varint()? as usize;
+                self.nested(|de| visitor.visit_seq(SeqDeserializer::new(de, len)))
+            }
+            tag::MAP => {
+                self.enter_compound();
+                let len = self.reader.read_varint()? as usize;
+                self.nested(|de| visitor.visit_map(MapDeserializer::new(de, len)))
+            }
+            tag::VARIANT => {
+                self.enter_compound();
+                self.nested(|de| visitor.visit_enum(TaggedEnumDeserializer::new(de)?))
+            }
+            other => Err(Error::InvalidFormat(format!("Unknown type tag: {other:#04x}"))),
+        }
     }
 
     #[inline]
@@ -60,6 +415,12 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if let Some(set) = self.take_field_bit() {
+            return visitor.visit_bool(set);
+        }
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         let value = self.reader.read_u8()?;
         visitor.visit_bool(value != 0)
     }
@@ -69,6 +430,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_i8(self.reader.read_i8()?)
     }
 
@@ -77,6 +441,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_i16(self.reader.read_i16()?)
     }
 
@@ -85,6 +452,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_i32(self.reader.read_i32()?)
     }
 
@@ -93,6 +463,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_i64(self.reader.read_i64()?)
     }
 
@@ -101,6 +474,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_u8(self.reader.read_u8()?)
     }
 
@@ -109,6 +485,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_u16(self.reader.read_u16()?)
     }
 
@@ -117,6 +496,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_u32(self.reader.read_u32()?)
     }
 
@@ -125,6 +507,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_u64(self.reader.read_u64()?)
     }
 
@@ -133,7 +518,10 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_f32(self.reader.read_f32()?)
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
+        visitor.visit_f32(self.read_f32()?)
     }
 
     #[inline]
@@ -141,7 +529,10 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_f64(self.reader.read_f64()?)
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
+        visitor.visit_f64(self.read_f64()?)
     }
 
     #[inline]
@@ -149,6 +540,12 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
+        if self.config.utf8_chars() {
+            return visitor.visit_char(self.reader.read_char_utf8()?);
+        }
         let value = self.reader.read_u32()?;
         let ch = char::from_u32(value)
             .ok_or_else(|| Error::InvalidFormat("Invalid char value".to_string()))?;
@@ -160,7 +557,10 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let s = self.reader.read_str()?;
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
+        let s = self.read_str()?;
         visitor.visit_borrowed_str(s)
     }
 
@@ -177,8 +577,15 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let bytes = self.reader.read_byte_slice()?;
-        visitor.visit_borrowed_bytes(bytes)
+        if self.config.self_describing() {
+            if self.reader.peek_u8()? == tag::SEQ {
+                // Bytes written element by element, as a plain `Vec<u8>` is
+                return visitor.visit_byte_buf(self.read_u8_seq()?);
+            }
+            return self.deserialize_any(visitor);
+        }
+        let len = self.read_len()?;
+        visitor.visit_borrowed_bytes(self.reader.read_bytes(len)?)
     }
 
     #[inline]
@@ -194,10 +601,49 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return match self.reader.peek_u8()? {
+                tag::NONE | tag::UNIT => {
+                    self.reader.skip(1)?;
+                    visitor.visit_none()
+                }
+                tag::SOME => {
+                    self.reader.skip(1)?;
+                    self.nested(|de| visitor.visit_some(de))
+                }
+                _ => visitor.visit_some(self),
+            };
+        }
+
+        if let Some(present) = self.take_field_bit() {
+            return match present {
+                true => self.nested(|de| visitor.visit_some(de)),
+                false => visitor.visit_none(),
+            };
+        }
+
+        if self.config.folds_option_tags() {
+            return match self.reader.read_varint()? {
+                0 => visitor.visit_none(),
+                tag => {
+                    // Only a string, byte string, sequence or map may take a folded length
+                    if self.some_len.is_some_and(|(_, len)| len > 0) {
+                        return Err(Error::InvalidFormat("Invalid option tag".to_string()));
+                    }
+                    self.some_len = Some((self.reader.position(), tag as usize - 1));
+                    let value = self.nested(|de| visitor.visit_some(de))?;
+                    match self.some_len.take() {
+                        Some((_, len)) if len > 0 => Err(Error::InvalidFormat("Invalid option tag".to_string())),
+                        _ => Ok(value),
+                    }
+                }
+            };
+        }
+
         let tag = self.reader.read_u8()?;
         match tag {
             0 => visitor.visit_none(),
-            1 => visitor.visit_some(self),
+            1 => self.nested(|de| visitor.visit_some(de)),
             _ => Err(Error::InvalidFormat("Invalid option tag".to_string())),
         }
     }
@@ -207,6 +653,9 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_unit()
     }
 
@@ -219,10 +668,16 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     }
 
     #[inline]
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if name == crate::nullable::NULLABLE_MARKER {
+            self.nullable_at = Some(self.reader.position());
+            let value = visitor.visit_newtype_struct(&mut *self);
+            self.nullable_at = None;
+            return value;
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -231,8 +686,16 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let len = self.reader.read_varint()? as usize;
-        visitor.visit_seq(SeqDeserializer::new(self, len))
+        if self.config.self_describing() {
+            if self.reader.peek_u8()? == tag::BYTES {
+                // Bytes written in bulk, read back as a sequence of `u8`
+                return self.visit_bytes_as_seq(visitor);
+            }
+            return self.deserialize_any(visitor);
+        }
+        self.enter_compound();
+        let len = self.read_len()?;
+        self.nested(|de| visitor.visit_seq(SeqDeserializer::new(de, len)))
     }
 
     #[inline]
@@ -240,13 +703,22 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let expected_len = self.reader.read_varint()? as usize;
-        if expected_len != len {
-            return Err(Error::InvalidFormat(format!(
-                "Tuple length mismatch: expected {len}, got {expected_len}"
-            )));
+        if self.config.self_describing() {
+            if self.reader.peek_u8()? == tag::BYTES {
+                return self.visit_bytes_as_seq(visitor);
+            }
+            return self.deserialize_any(visitor);
+        }
+        self.enter_compound();
+        if !self.config.omits_tuple_lengths() {
+            let expected_len = self.reader.read_varint()? as usize;
+            if expected_len != len {
+                return Err(Error::InvalidFormat(format!(
+                    "Tuple length mismatch: expected {len}, got {expected_len}"
+                )));
+            }
         }
-        visitor.visit_seq(SeqDeserializer::new(self, len))
+        self.nested(|de| visitor.visit_seq(SeqDeserializer::new(de, len)))
     }
 
     #[inline]
@@ -267,8 +739,12 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let len = self.reader.read_varint()? as usize;
-        visitor.visit_map(MapDeserializer::new(self, len))
+        if self.config.self_describing() {
+            return self.deserialize_any(visitor);
+        }
+        self.enter_compound();
+        let len = self.read_len()?;
+        self.nested(|de| visitor.visit_map(MapDeserializer::new(de, len)))
     }
 
     #[inline]
@@ -281,7 +757,23 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if self.config.self_describing() {
+            // Structs are written as maps keyed by field name
+            return self.deserialize_any(visitor);
+        }
+        let nullable = self.nullable_at.take() == Some(self.reader.position());
+        let at_root = self.enter_compound();
         let len = self.reader.read_varint()? as usize;
+        if self.config.tagged_fields() {
+            return self.nested(|de| visitor.visit_map(TaggedFieldDeserializer::new(de, fields, len)));
+        }
+        if nullable && len == fields.len() {
+            let bitmap = self.reader.read_bytes(len.div_ceil(8))?;
+            return self.nested(|de| visitor.visit_seq(SeqDeserializer::fields(de, fields).with_bitmap(bitmap)));
+        }
+        if len != fields.len() && self.config.lenient_field_count() && !nullable {
+            return self.nested(|de| visitor.visit_map(LenientFieldDeserializer::new(de, fields, len, at_root)));
+        }
         if len != fields.len() {
             return Err(Error::InvalidFormat(format!(
                 "Struct field count mismatch: expected {}, got {}",
@@ -289,20 +781,47 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
                 len
             )));
         }
-        visitor.visit_seq(SeqDeserializer::new(self, len))
+        self.nested(|de| visitor.visit_seq(SeqDeserializer::fields(de, fields)))
     }
 
     #[inline]
     fn deserialize_enum<V>(
         self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_enum(EnumDeserializer::new(self))
+        if self.config.self_describing() {
+            return match self.reader.peek_u8()? {
+                tag::VARIANT => self.deserialize_any(visitor),
+                // Unit variant by name, as transcoded from other formats
+                tag::STR => {
+                    self.reader.skip(1)?;
+                    let name = self.reader.read_str()?;
+                    self.nested(|de| visitor.visit_enum(TaggedEnumDeserializer::named(de, name, false)))
+                }
+                // Externally tagged: a single `name => payload` entry
+                tag::MAP => {
+                    self.reader.skip(1)?;
+                    if self.reader.read_varint()? != 1 || self.reader.read_u8()? != tag::STR {
+                        return Err(Error::InvalidFormat(
+                            "Expected a single-entry map keyed by variant name".to_string(),
+                        ));
+                    }
+                    let name = self.reader.read_str()?;
+                    self.nested(|de| visitor.visit_enum(TaggedEnumDeserializer::named(de, name, true)))
+                }
+                found => Err(Error::InvalidFormat(format!(
+                    "Expected enum variant, found {}",
+                    tag::name(found)
+                ))),
+            };
+        }
+        self.enter_compound();
+        self.nested(|de| visitor.visit_enum(EnumDeserializer::new(de, variants)))
     }
 
     #[inline]
@@ -318,7 +837,21 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        if self.config.self_describing() {
+            self.skip_value()?;
+            return visitor.visit_unit();
+        }
+        // Plain values can only be skipped when their length is known
+        match self.value_bound {
+            Some((start, end)) if start == self.reader.position() => {
+                self.reader.skip(end - start)?;
+                visitor.visit_unit()
+            }
+            _ => Err(Error::Serde(
+                "Cannot skip a value without type information; use self-describing mode or tagged fields"
+                    .to_string(),
+            )),
+        }
     }
 }
 
@@ -326,6 +859,11 @@ impl<'de> serde::Deserializer<'de> for &mut Deserializer<'de> {
 struct SeqDeserializer<'a, 'de> {
     de: &'a mut Deserializer<'de>,
     remaining: usize,
+    index: usize,
+    // Names of the elements, for a struct written positionally
+    fields: &'static [&'static str],
+    // Presence bitmap of a struct wrapped in `Nullable`
+    bitmap: &'de [u8],
 }
 
 impl<'a, 'de> SeqDeserializer<'a, 'de> {
@@ -333,8 +871,24 @@ impl<'a, 'de> SeqDeserializer<'a, 'de> {
         Self {
             de,
             remaining: len,
+            index: 0,
+            fields: &[],
+            bitmap: &[],
+        }
+    }
+
+    // The fields of a struct, in declaration order
+    fn fields(de: &'a mut Deserializer<'de>, fields: &'static [&'static str]) -> Self {
+        Self {
+            fields,
+            ..Self::new(de, fields.len())
         }
     }
+
+    // One bit per field, see `crate::nullable`
+    fn with_bitmap(self, bitmap: &'de [u8]) -> Self {
+        Self { bitmap, ..self }
+    }
 }
 
 impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
@@ -348,7 +902,17 @@ impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
             return Ok(None);
         }
         self.remaining -= 1;
-        seed.deserialize(&mut *self.de).map(Some)
+        let index = self.index;
+        self.index += 1;
+        if let Some(byte) = self.bitmap.get(index / 8) {
+            self.de.field_bit = Some((self.de.reader.position(), byte & (1 << (index % 8)) != 0));
+        }
+        let value = seed.deserialize(&mut *self.de);
+        self.de.field_bit = None;
+        value.map(Some).map_err(|e| match self.fields.get(index) {
+            Some(name) => e.within(name),
+            None => e.within(index),
+        })
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -360,6 +924,9 @@ impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
 struct MapDeserializer<'a, 'de> {
     de: &'a mut Deserializer<'de>,
     remaining: usize,
+    index: usize,
+    // Where the key of the current entry starts
+    key_at: usize,
 }
 
 impl<'a, 'de> MapDeserializer<'a, 'de> {
@@ -367,7 +934,19 @@ impl<'a, 'de> MapDeserializer<'a, 'de> {
         Self {
             de,
             remaining: len,
+            index: 0,
+            key_at: 0,
+        }
+    }
+
+    // The current key, if it is a self-describing string; read again only
+    // when an error needs it
+    fn string_key(&self) -> Option<&'de str> {
+        let mut key = ReadBuffer::new(self.de.reader.as_slice().get(self.key_at..)?);
+        if !self.de.config.self_describing() || key.read_u8().ok()? != tag::STR {
+            return None;
         }
+        key.read_str().ok()
     }
 }
 
@@ -382,14 +961,98 @@ impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a, 'de> {
             return Ok(None);
         }
         self.remaining -= 1;
-        seed.deserialize(&mut *self.de).map(Some)
+        self.key_at = self.de.reader.position();
+        seed.deserialize(&mut *self.de).map(Some).map_err(|e| e.within("{key}"))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let index = self.index;
+        self.index += 1;
+        seed.deserialize(&mut *self.de).map_err(|e| match self.string_key() {
+            Some(key) => e.within(key),
+            None => e.within(index),
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+// Struct deserializer for tagged-field mode: each field is tag + length + value
+struct TaggedFieldDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    fields: &'static [&'static str],
+    remaining: usize,
+    value_end: usize,
+    field: &'static str,
+}
+
+impl<'a, 'de> TaggedFieldDeserializer<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, fields: &'static [&'static str], len: usize) -> Self {
+        Self {
+            de,
+            fields,
+            remaining: len,
+            value_end: 0,
+            field: "",
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for TaggedFieldDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            let tag = self.de.reader.read_varint()?;
+            let len = self.de.reader.read_varint()? as usize;
+
+            let known = self
+                .fields
+                .iter()
+                .enumerate()
+                .find(|(index, name)| field_tag(*index, name) == tag);
+
+            match known {
+                Some((_, name)) => {
+                    self.value_end = self.de.reader.position().saturating_add(len);
+                    self.field = name;
+                    let key = serde::de::value::StrDeserializer::<Error>::new(name);
+                    return seed.deserialize(key).map(Some);
+                }
+                // Field from a newer schema: skip its value
+                None => self.de.reader.skip(len)?,
+            }
+        }
+        Ok(None)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
     where
         V: DeserializeSeed<'de>,
     {
-        seed.deserialize(&mut *self.de)
+        let bound = Some((self.de.reader.position(), self.value_end));
+        let outer = core::mem::replace(&mut self.de.value_bound, bound);
+        let value = seed.deserialize(&mut *self.de);
+        self.de.value_bound = outer;
+        let value = value.map_err(|e| e.within(self.field))?;
+        let position = self.de.reader.position();
+        if position > self.value_end {
+            return Err(Error::InvalidFormat(
+                "Tagged field value overran its length".to_string(),
+            ));
+        }
+        // Trailing bytes belong to a newer schema of the value: skip them
+        self.de.reader.skip(self.value_end - position)?;
+        Ok(value)
     }
 
     fn size_hint(&self) -> Option<usize> {
@@ -397,14 +1060,91 @@ impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a, 'de> {
     }
 }
 
+// Struct deserializer for positional fields whose count differs from the type
+struct LenientFieldDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    fields: &'static [&'static str],
+    index: usize,
+    encoded: usize,
+    at_root: bool,
+}
+
+impl<'a, 'de> LenientFieldDeserializer<'a, 'de> {
+    fn new(
+        de: &'a mut Deserializer<'de>,
+        fields: &'static [&'static str],
+        encoded: usize,
+        at_root: bool,
+    ) -> Self {
+        Self {
+            de,
+            fields,
+            index: 0,
+            encoded,
+            at_root,
+        }
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for LenientFieldDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.index < self.encoded.min(self.fields.len()) {
+            let key = serde::de::value::StrDeserializer::<Error>::new(self.fields[self.index]);
+            self.index += 1;
+            return seed.deserialize(key).map(Some);
+        }
+
+        if self.encoded > self.fields.len() {
+            // Plain values carry no length, so extra fields can only be
+            // skipped when nothing follows them
+            if !self.at_root {
+                return Err(Error::InvalidFormat(format!(
+                    "Cannot skip {} extra fields of a struct followed by other values; use tagged fields",
+                    self.encoded - self.fields.len()
+                )));
+            }
+            self.de.reader.skip(self.de.reader.remaining())?;
+            self.encoded = self.fields.len();
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let field = self.fields[self.index - 1];
+        seed.deserialize(&mut *self.de).map_err(|e| e.within(field))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.encoded.min(self.fields.len()) - self.index)
+    }
+}
+
 // Enum deserializer
 struct EnumDeserializer<'a, 'de> {
     de: &'a mut Deserializer<'de>,
+    variants: &'static [&'static str],
+    index: u64,
 }
 
 impl<'a, 'de> EnumDeserializer<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Self {
-        Self { de }
+    fn new(de: &'a mut Deserializer<'de>, variants: &'static [&'static str]) -> Self {
+        Self { de, variants, index: 0 }
+    }
+
+    // Name the variant in the path of an error from its payload
+    fn within(&self, error: Error) -> Error {
+        match usize::try_from(self.index).ok().and_then(|index| self.variants.get(index)) {
+            Some(name) => error.within(name),
+            None => error.within(self.index),
+        }
     }
 }
 
@@ -412,12 +1152,12 @@ impl<'de, 'a> EnumAccess<'de> for EnumDeserializer<'a, 'de> {
     type Error = Error;
     type Variant = Self;
 
-    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    fn variant_seed<V>(mut self, seed: V) -> Result<(V::Value, Self::Variant)>
     where
         V: DeserializeSeed<'de>,
     {
-        let variant_index = self.de.reader.read_varint()?;
-        let val = seed.deserialize(variant_index.into_deserializer())?;
+        self.index = self.de.reader.read_varint()?;
+        let val = seed.deserialize(self.index.into_deserializer())?;
         Ok((val, self))
     }
 }
@@ -433,20 +1173,24 @@ impl<'de, 'a> VariantAccess<'de> for EnumDeserializer<'a, 'de> {
     where
         T: DeserializeSeed<'de>,
     {
-        seed.deserialize(self.de)
+        seed.deserialize(&mut *self.de).map_err(|e| self.within(e))
     }
 
     fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let actual_len = self.de.reader.read_varint()? as usize;
-        if actual_len != len {
-            return Err(Error::InvalidFormat(format!(
-                "Tuple variant length mismatch: expected {len}, got {actual_len}"
-            )));
+        if !self.de.config.omits_tuple_lengths() {
+            let actual_len = self.de.reader.read_varint()? as usize;
+            if actual_len != len {
+                return Err(Error::InvalidFormat(format!(
+                    "Tuple variant length mismatch: expected {len}, got {actual_len}"
+                )));
+            }
         }
-        serde::Deserializer::deserialize_tuple(self.de, len, visitor)
+        // The length is known, so visit the elements directly
+        self.de.enter_compound();
+        visitor.visit_seq(SeqDeserializer::new(&mut *self.de, len)).map_err(|e| self.within(e))
     }
 
     fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
@@ -454,14 +1198,215 @@ impl<'de, 'a> VariantAccess<'de> for EnumDeserializer<'a, 'de> {
         V: Visitor<'de>,
     {
         let len = self.de.reader.read_varint()? as usize;
-        if len != fields.len() {
+        let fields_read = if self.de.config.tagged_fields() {
+            visitor.visit_map(TaggedFieldDeserializer::new(&mut *self.de, fields, len))
+        } else if len != fields.len() && self.de.config.lenient_field_count() {
+            visitor.visit_map(LenientFieldDeserializer::new(&mut *self.de, fields, len, false))
+        } else if len != fields.len() {
             return Err(Error::InvalidFormat(format!(
                 "Struct variant field count mismatch: expected {}, got {}",
                 fields.len(),
                 len
             )));
+        } else {
+            visitor.visit_seq(SeqDeserializer::fields(&mut *self.de, fields))
+        };
+        fields_read.map_err(|e| self.within(e))
+    }
+}
+
+// Enum deserializer for self-describing payloads: index, name, then payload
+struct TaggedEnumDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    index: u32,
+    name: &'de str,
+    has_payload: bool,
+}
+
+impl<'a, 'de> TaggedEnumDeserializer<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>) -> Result<Self> {
+        let index = u32::try_from(de.reader.read_varint()?)
+            .map_err(|_| Error::InvalidFormat("Variant index out of range".to_string()))?;
+        let name = de.reader.read_str()?;
+        Ok(Self {
+            de,
+            index,
+            name,
+            has_payload: true,
+        })
+    }
+
+    // Variant identified by name only
+    fn named(de: &'a mut Deserializer<'de>, name: &'de str, has_payload: bool) -> Self {
+        Self {
+            de,
+            index: 0,
+            name,
+            has_payload,
+        }
+    }
+
+    // Name the variant in the path of an error from its payload
+    fn within(&self, error: Error) -> Error {
+        if self.name.is_empty() {
+            error.within(self.index)
+        } else {
+            error.within(self.name)
         }
-        visitor.visit_seq(SeqDeserializer::new(self.de, len))
+    }
+
+    fn expect_payload(&self) -> Result<()> {
+        if self.has_payload {
+            Ok(())
+        } else {
+            Err(Error::InvalidFormat(format!(
+                "Variant `{}` has no payload",
+                self.name
+            )))
+        }
+    }
+}
+
+impl<'de, 'a> EnumAccess<'de> for TaggedEnumDeserializer<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let val = seed.deserialize(VariantIdDeserializer {
+            index: self.index,
+            name: self.name,
+        })?;
+        Ok((val, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for TaggedEnumDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        if self.has_payload {
+            self.de.skip_value()?;
+        }
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.expect_payload()?;
+        seed.deserialize(&mut *self.de).map_err(|e| self.within(e))
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.expect_payload()?;
+        serde::Deserializer::deserialize_any(&mut *self.de, visitor).map_err(|e| self.within(e))
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.expect_payload()?;
+        serde::Deserializer::deserialize_any(&mut *self.de, visitor).map_err(|e| self.within(e))
+    }
+}
+
+// Variant identifier of a self-describing enum
+//
+// Resolves by name when one was written, otherwise by index. Tuples yield
+// both, which is how `Value` keeps the index and the name.
+struct VariantIdDeserializer<'de> {
+    index: u32,
+    name: &'de str,
+}
+
+impl<'de> serde::Deserializer<'de> for VariantIdDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.name.is_empty() {
+            visitor.visit_u32(self.index)
+        } else {
+            visitor.visit_borrowed_str(self.name)
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        use serde::de::value::{BorrowedStrDeserializer, U32Deserializer};
+
+        struct Parts<'de> {
+            index: Option<u32>,
+            name: Option<&'de str>,
+        }
+
+        impl<'de> SeqAccess<'de> for Parts<'de> {
+            type Error = Error;
+
+            fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+            where
+                T: DeserializeSeed<'de>,
+            {
+                if let Some(index) = self.index.take() {
+                    return seed.deserialize(U32Deserializer::<Error>::new(index)).map(Some);
+                }
+                match self.name.take() {
+                    Some(name) => seed.deserialize(BorrowedStrDeserializer::<Error>::new(name)).map(Some),
+                    None => Ok(None),
+                }
+            }
+        }
+
+        visitor.visit_seq(Parts {
+            index: Some(self.index),
+            name: Some(self.name),
+        })
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.index)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.index)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.index)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.index)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq
+        tuple_struct map struct enum identifier ignored_any
     }
 }
 
@@ -508,12 +1453,146 @@ impl<'de> IntoDeserializer<'de> for u64 {
 }
 
 /// Deserialize from bytes
+///
+/// Payloads that record a schema hash are checked against the schema of
+/// `T` first, see [`schema`](crate::schema).
 pub fn from_bytes<'de, T>(bytes: &'de [u8]) -> Result<T>
 where
     T: Deserialize<'de>,
 {
-    let mut deserializer = Deserializer::new(bytes)?;
-    T::deserialize(&mut deserializer)
+    from_bytes_with_config(bytes, Config::new())
+}
+
+/// Deserialize from bytes using the given decoding options
+pub fn from_bytes_with_config<'de, T>(bytes: &'de [u8], config: Config) -> Result<T>
+where
+    T: Deserialize<'de>,
+{
+    let probe = Probe::start(Operation::Deserialize, Some(core::any::type_name::<T>()));
+    let result = Deserializer::with_config(bytes, config).and_then(|mut deserializer| {
+        crate::schema::verify_hash::<T>(deserializer.config.schema_hash())?;
+        T::deserialize(&mut deserializer)
+    });
+    probe.finish(&result, bytes.len(), None);
+    result
+}
+
+/// Deserialize from bytes with a stateful seed
+///
+/// Seeds carry state into deserialization, such as an interner or an
+/// id mapping, which `Deserialize` implementations cannot reach.
+pub fn from_bytes_seed<'de, S>(seed: S, bytes: &'de [u8]) -> Result<S::Value>
+where
+    S: DeserializeSeed<'de>,
+{
+    from_bytes_seed_with_config(seed, bytes, Config::new())
+}
+
+/// Deserialize from bytes with a stateful seed and the given options
+pub fn from_bytes_seed_with_config<'de, S>(seed: S, bytes: &'de [u8], config: Config) -> Result<S::Value>
+where
+    S: DeserializeSeed<'de>,
+{
+    let mut deserializer = Deserializer::with_config(bytes, config)?;
+    seed.deserialize(&mut deserializer)
+}
+
+/// Iterates over the elements of a serialized sequence, decoding on demand
+///
+/// Created by [`from_bytes_seq_iter`]. Yields `Result<T>` for each element
+/// and stops after the first error.
+pub struct SeqIter<'de, T> {
+    de: Deserializer<'de>,
+    remaining: usize,
+    failed: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T> SeqIter<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    /// Start iterating over the sequence in `bytes`
+    pub fn new(bytes: &'de [u8]) -> Result<Self> {
+        Self::with_config(bytes, Config::new())
+    }
+
+    /// Start iterating using the given decoding options
+    pub fn with_config(bytes: &'de [u8], config: Config) -> Result<Self> {
+        let mut de = Deserializer::with_config(bytes, config)?;
+        if de.config.self_describing() {
+            let found = de.reader.read_u8()?;
+            if found != tag::SEQ {
+                return Err(Error::InvalidFormat(format!(
+                    "Expected sequence, found {}",
+                    tag::name(found)
+                )));
+            }
+        }
+        de.enter_compound();
+        let remaining = de.reader.read_varint()? as usize;
+        Ok(Self {
+            de,
+            remaining,
+            failed: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Number of elements not yet read
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl<'de, T> Iterator for SeqIter<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let result = T::deserialize(&mut self.de);
+        self.failed = result.is_err();
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            (0, Some(0))
+        } else {
+            (0, Some(self.remaining))
+        }
+    }
+}
+
+/// Iterate over the elements of a serialized sequence without collecting it
+///
+/// Elements are decoded one at a time as the iterator advances and may
+/// borrow from `bytes`.
+///
+/// ```rust
+/// let bytes = nanobit::to_bytes(&vec!["apple", "banana", "avocado"])?;
+/// let mut matches = Vec::new();
+/// for item in nanobit::from_bytes_seq_iter::<&str>(&bytes)? {
+///     let item = item?;
+///     if item.starts_with('a') {
+///         matches.push(item);
+///     }
+/// }
+/// assert_eq!(matches, ["apple", "avocado"]);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn from_bytes_seq_iter<'de, T>(bytes: &'de [u8]) -> Result<SeqIter<'de, T>>
+where
+    T: Deserialize<'de>,
+{
+    SeqIter::new(bytes)
 }
 
 /// Deserialize from a reader
@@ -529,9 +1608,22 @@ where
     
     // We need to work with owned data for the reader case
     let mut deserializer = Deserializer::new(&buffer)?;
+    crate::schema::verify_hash::<T>(deserializer.config.schema_hash())?;
     T::deserialize(&mut deserializer)
 }
 
+/// Deserialize from a reader with a stateful seed
+#[cfg(feature = "std")]
+pub fn from_reader_seed<R, S, V>(seed: S, mut reader: R) -> Result<V>
+where
+    R: Read,
+    S: for<'de> DeserializeSeed<'de, Value = V>,
+{
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).map_err(Error::from)?;
+    from_bytes_seed(seed, &buffer)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -550,8 +1642,8 @@ mod tests {
     fn test_primitive_deserialization() {
         assert_eq!(from_bytes::<u32>(&to_bytes(&42u32).unwrap()).unwrap(), 42u32);
         assert_eq!(from_bytes::<i64>(&to_bytes(&-100i64).unwrap()).unwrap(), -100i64);
-        assert_eq!(from_bytes::<f64>(&to_bytes(&3.14f64).unwrap()).unwrap(), 3.14f64);
-        assert_eq!(from_bytes::<bool>(&to_bytes(&true).unwrap()).unwrap(), true);
+        assert_eq!(from_bytes::<f64>(&to_bytes(&std::f64::consts::PI).unwrap()).unwrap(), std::f64::consts::PI);
+        assert!(from_bytes::<bool>(&to_bytes(&true).unwrap()).unwrap());
         assert_eq!(from_bytes::<&str>(&to_bytes(&"hello").unwrap()).unwrap(), "hello");
     }
 
@@ -570,6 +1662,65 @@ mod tests {
         assert_eq!(original, deserialized);
     }
 
+    #[test]
+    fn test_seq_iter() {
+        let data: Vec<(u32, String)> = (0..100).map(|i| (i, format!("item-{i}"))).collect();
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&data, config).unwrap();
+            let mut iter = from_bytes_seq_iter::<(u32, &str)>(&bytes).unwrap();
+            assert_eq!(iter.remaining(), 100);
+            assert_eq!(iter.next().unwrap().unwrap(), (0, "item-0"));
+            let rest: Vec<_> = iter.collect::<Result<_>>().unwrap();
+            assert_eq!(rest.len(), 99);
+            assert_eq!(rest[98], (99, "item-99"));
+        }
+
+        let bytes = to_bytes(&vec!["first", "second"]).unwrap();
+        let mut iter = from_bytes_seq_iter::<&str>(&bytes[..bytes.len() - 1]).unwrap();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_ignored_any_skips() {
+        use serde::de::IgnoredAny;
+
+        #[derive(Serialize)]
+        struct Full {
+            id: u32,
+            tags: Vec<String>,
+            inner: TestStruct,
+            last: bool,
+        }
+
+        #[derive(Deserialize)]
+        struct Partial {
+            id: u32,
+            #[allow(dead_code)]
+            tags: IgnoredAny,
+            #[allow(dead_code)]
+            inner: IgnoredAny,
+            last: bool,
+        }
+
+        let full = Full {
+            id: 9,
+            tags: vec!["a".to_string(), "b".to_string()],
+            inner: TestStruct { name: "x".to_string(), age: 1, active: false, scores: vec![1.0] },
+            last: true,
+        };
+        for config in [Config::new().with_tagged_fields(true), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&full, config).unwrap();
+            let partial: Partial = from_bytes(&bytes).unwrap();
+            assert_eq!((partial.id, partial.last), (9, true));
+        }
+
+        // Plain values carry no length to skip by
+        let bytes = to_bytes(&full).unwrap();
+        assert!(from_bytes::<Partial>(&bytes).is_err());
+    }
+
     #[test]
     fn test_collections() {
         let vec_data = vec![1u32, 2, 3, 4, 5];
@@ -601,6 +1752,54 @@ mod tests {
         assert_eq!(none_value, deserialized_none);
     }
 
+    #[test]
+    fn test_unknown_variant_fallback() {
+        #[derive(Serialize)]
+        enum New {
+            Start,
+            Stop,
+            Flush,
+            Reset,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum Old {
+            Start,
+            Stop,
+            #[serde(other)]
+            Unknown,
+        }
+
+        // Positions past the known variants decode as the `other` variant
+        let bytes = to_bytes(&[New::Start, New::Stop, New::Flush, New::Reset]).unwrap();
+        let decoded: [Old; 4] = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, [Old::Start, Old::Stop, Old::Unknown, Old::Unknown]);
+
+        #[derive(Deserialize, Debug)]
+        enum Strict {
+            #[allow(dead_code)]
+            Start,
+        }
+        assert!(from_bytes::<Strict>(&to_bytes(&New::Reset).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_niche_option_tags() {
+        let config = Config::new().with_niche_options(true);
+        let header = crate::to_bytes_with_config(&(), config).unwrap();
+        let payload = |body: &[u8]| [header.as_slice(), body].concat();
+
+        assert_eq!(from_bytes::<Option<String>>(&payload(&[3, b'a', b'b'])).unwrap(), Some("ab".to_string()));
+        assert_eq!(from_bytes::<Option<Vec<u8>>>(&payload(&[1])).unwrap(), Some(vec![]));
+        assert_eq!(from_bytes::<Option<u8>>(&payload(&[1, 7])).unwrap(), Some(7));
+        assert_eq!(from_bytes::<Option<Option<String>>>(&payload(&[1, 0])).unwrap(), Some(None));
+
+        // A length folded into the tag of a value that has none
+        assert!(from_bytes::<Option<u8>>(&payload(&[5, 7])).is_err());
+        assert!(from_bytes::<Option<Option<String>>>(&payload(&[2, 1])).is_err());
+        assert!(from_bytes::<Option<String>>(&payload(&[4, b'a', b'b'])).is_err());
+    }
+
     #[test]
     fn test_enum_roundtrip() {
         #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -608,12 +1807,14 @@ mod tests {
             Variant1,
             Variant2(u32),
             Variant3 { field: String },
+            Variant4(u8, String),
         }
 
         let variants = vec![
             TestEnum::Variant1,
             TestEnum::Variant2(42),
             TestEnum::Variant3 { field: "test".to_string() },
+            TestEnum::Variant4(7, "pair".to_string()),
         ];
 
         for variant in variants {
@@ -650,6 +1851,138 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compact_char_roundtrip() {
+        let config = crate::Config::new().with_compact_chars(true);
+        let text: Vec<char> = "ascii, ünïcode, 世界 🚀".chars().collect();
+        let compact = crate::to_bytes_with_config(&text, config).unwrap();
+        assert!(compact.len() < to_bytes(&text).unwrap().len() / 2);
+        assert_eq!(from_bytes::<Vec<char>>(&compact).unwrap(), text);
+
+        // Truncated and invalid sequences are rejected
+        let body = &compact[..compact.len() - 1];
+        assert!(from_bytes::<Vec<char>>(body).is_err());
+        let mut invalid = crate::to_bytes_with_config(&'é', config).unwrap();
+        *invalid.last_mut().unwrap() = b'x';
+        assert!(from_bytes::<char>(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_tagged_fields_roundtrip() {
+        let config = crate::Config::new().with_tagged_fields(true);
+        let original = TestStruct {
+            name: "Alice".to_string(),
+            age: 30,
+            active: true,
+            scores: vec![95.5, 87.2],
+        };
+
+        let serialized = crate::to_bytes_with_config(&original, config).unwrap();
+        assert_eq!(serialized[4], crate::FLAGS_VERSION);
+        let deserialized: TestStruct = from_bytes(&serialized).unwrap();
+        assert_eq!(original, deserialized);
+    }
+
+    #[test]
+    fn test_tagged_fields_schema_evolution() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct V1 {
+            id: u64,
+            legacy: String,
+            #[serde(rename = "7")]
+            label: String,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct V2 {
+            id: u64,
+            #[serde(skip)]
+            _legacy: (),
+            #[serde(rename = "7")]
+            label: String,
+            #[serde(default)]
+            added: Vec<u32>,
+            note: Option<String>,
+        }
+
+        let config = crate::Config::new().with_tagged_fields(true);
+
+        // Old payload, new type: unknown tag skipped, new fields defaulted
+        let old = V1 { id: 9, legacy: "gone".to_string(), label: "x".to_string() };
+        let bytes = crate::to_bytes_with_config(&old, config).unwrap();
+        let upgraded: V2 = from_bytes(&bytes).unwrap();
+        assert_eq!(upgraded.id, 9);
+        assert_eq!(upgraded.label, "x");
+        assert!(upgraded.added.is_empty());
+        assert_eq!(upgraded.note, None);
+
+        // New payload, old type: extra tags skipped, removed field missing
+        #[derive(Deserialize, Debug)]
+        struct V1Reader {
+            id: u64,
+            #[serde(default)]
+            legacy: String,
+            #[serde(rename = "7")]
+            label: String,
+        }
+        let new = V2 {
+            id: 3,
+            _legacy: (),
+            label: "y".to_string(),
+            added: vec![1, 2],
+            note: Some("n".to_string()),
+        };
+        let bytes = crate::to_bytes_with_config(&new, config).unwrap();
+        let downgraded: V1Reader = from_bytes(&bytes).unwrap();
+        assert_eq!(downgraded.id, 3);
+        assert_eq!(downgraded.legacy, "");
+        assert_eq!(downgraded.label, "y");
+    }
+
+    #[test]
+    fn test_lenient_field_count() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Old {
+            id: u32,
+            name: String,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct New {
+            id: u32,
+            name: String,
+            #[serde(default)]
+            tags: Vec<String>,
+            email: Option<String>,
+        }
+
+        let lenient = crate::Config::new().with_lenient_field_count(true);
+
+        // Missing trailing fields are defaulted
+        let bytes = to_bytes(&Old { id: 1, name: "a".to_string() }).unwrap();
+        assert!(from_bytes::<New>(&bytes).is_err());
+        let new: New = from_bytes_with_config(&bytes, lenient).unwrap();
+        assert_eq!(new, New { id: 1, name: "a".to_string(), tags: vec![], email: None });
+
+        // Extra trailing fields are skipped at the top level
+        let bytes = to_bytes(&New {
+            id: 2,
+            name: "b".to_string(),
+            tags: vec!["t".to_string()],
+            email: Some("e".to_string()),
+        })
+        .unwrap();
+        let old: Old = from_bytes_with_config(&bytes, lenient).unwrap();
+        assert_eq!(old, Old { id: 2, name: "b".to_string() });
+
+        // ...but not inside another value
+        let nested = to_bytes(&vec![New { id: 3, name: "c".to_string(), tags: vec![], email: None }]).unwrap();
+        assert!(from_bytes_with_config::<Vec<Old>>(&nested, lenient).is_err());
+        let nested = to_bytes(&vec![Old { id: 3, name: "c".to_string() }]).unwrap();
+        let upgraded: Vec<New> = from_bytes_with_config(&nested, lenient).unwrap();
+        assert_eq!(upgraded[0].id, 3);
+    }
+
     #[cfg(feature = "std")]
     #[test]
     fn test_reader_deserialization() {
@@ -660,4 +1993,128 @@ mod tests {
         let deserialized: Vec<u32> = from_reader(cursor).unwrap();
         assert_eq!(data, deserialized);
     }
+
+    #[test]
+    fn test_introspection() {
+        let bytes = crate::to_bytes_with_config(&(7u32, "abc"), Config::new().with_self_describing(true)).unwrap();
+        let de = Deserializer::new(&bytes).unwrap();
+        assert_eq!((de.position(), de.byte_offset(), de.remaining()), (0, 6, bytes.len() - 6));
+        assert_eq!(de.peek_kind(), Some("sequence"));
+
+        // A truncated string fails after its length has been read
+        let mut truncated = crate::to_bytes(&(7u32, "abc")).unwrap();
+        truncated.pop();
+        let mut de = Deserializer::new(&truncated).unwrap();
+        assert!(<(u32, String)>::deserialize(&mut de).is_err());
+        assert_eq!(de.byte_offset(), 5 + 1 + 4 + 1);
+        assert_eq!(de.peek_bytes(), b"ab");
+        assert_eq!(de.peek_kind(), None);
+    }
+
+    #[test]
+    fn test_seeded_deserialization() {
+        // Remaps ids while decoding, without a second pass
+        struct Remap<'a>(&'a std::collections::HashMap<u32, u32>);
+
+        impl<'de> DeserializeSeed<'de> for Remap<'_> {
+            type Value = Vec<u32>;
+
+            fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> core::result::Result<Vec<u32>, D::Error> {
+                let ids = Vec::<u32>::deserialize(deserializer)?;
+                Ok(ids.into_iter().map(|id| self.0.get(&id).copied().unwrap_or(id)).collect())
+            }
+        }
+
+        let mapping = [(1, 100), (3, 300)].into_iter().collect();
+        let bytes = to_bytes(&vec![1u32, 2, 3]).unwrap();
+        assert_eq!(from_bytes_seed(Remap(&mapping), &bytes).unwrap(), [100, 2, 300]);
+        assert_eq!(from_reader_seed(Remap(&mapping), &bytes[..]).unwrap(), [100, 2, 300]);
+        assert!(from_bytes_seed(Remap(&mapping), &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_nesting_depth_limit() {
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        enum Tree {
+            Leaf,
+            Node(Box<Tree>),
+        }
+
+        // A million levels of `Node(..)` would overflow the stack unchecked
+        let mut bytes = to_bytes(&()).unwrap();
+        bytes.extend(std::iter::repeat_n(1, 1_000_000));
+        bytes.push(0);
+        assert!(matches!(
+            from_bytes::<Tree>(&bytes),
+            Err(Error::LimitExceeded { what: "Nesting depth", limit: crate::config::DEFAULT_MAX_DEPTH })
+        ));
+
+        let nested = |depth: usize| {
+            let mut bytes = crate::to_bytes_with_config(&(), Config::new().with_self_describing(true)).unwrap();
+            bytes.pop();
+            for _ in 0..depth {
+                bytes.extend_from_slice(&[tag::SEQ, 1]);
+            }
+            bytes.push(tag::UNIT);
+            bytes
+        };
+        assert!(from_bytes::<crate::Value>(&nested(128)).is_ok());
+        assert!(from_bytes::<crate::Value>(&nested(129)).is_err());
+        assert!(from_bytes::<crate::Value>(&nested(1_000_000)).is_err());
+        let config = Config::new().with_max_depth(200);
+        assert!(from_bytes_with_config::<crate::Value>(&nested(200), config).is_ok());
+        assert!(from_bytes_with_config::<crate::Value>(&nested(2), Config::new().with_max_depth(1)).is_err());
+    }
+
+    #[test]
+    fn test_deny_non_finite_floats_names_path() {
+        #[derive(Serialize, Deserialize, Debug, Clone)]
+        enum Reading {
+            Gauge(f32),
+            Span { low: f64, high: f64 },
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Clone)]
+        struct Sensor {
+            name: String,
+            readings: Vec<Reading>,
+            offsets: std::collections::BTreeMap<String, (f64, f64)>,
+        }
+
+        let sensor = Sensor {
+            name: "t1".to_string(),
+            readings: vec![Reading::Gauge(1.0), Reading::Span { low: 0.0, high: f64::INFINITY }],
+            offsets: std::collections::BTreeMap::from([("x".to_string(), (f64::NAN, 0.0))]),
+        };
+        let deny = Config::new().with_deny_non_finite_floats(true);
+        for (config, offsets) in [
+            (Config::new(), "offsets.0.0"),
+            (Config::new().with_tagged_fields(true), "offsets.0.0"),
+            (Config::new().with_self_describing(true), "offsets.x.0"),
+        ] {
+            let bytes = crate::to_bytes_with_config(&sensor, config).unwrap();
+            assert!(from_bytes::<Sensor>(&bytes).is_ok());
+            match from_bytes_with_config::<Sensor>(&bytes, deny) {
+                Err(Error::NonFiniteFloat { value, path }) => {
+                    assert_eq!(value, f64::INFINITY);
+                    assert_eq!(path, "readings.1.Span.high");
+                }
+                other => panic!("expected a non-finite float error, got {other:?}"),
+            }
+
+            let spans = Sensor { readings: vec![], ..sensor.clone() };
+            let bytes = crate::to_bytes_with_config(&spans, config).unwrap();
+            match from_bytes_with_config::<Sensor>(&bytes, deny) {
+                Err(Error::NonFiniteFloat { value, path }) => {
+                    assert!(value.is_nan());
+                    assert_eq!(path, offsets);
+                }
+                other => panic!("expected a non-finite float error, got {other:?}"),
+            }
+            let finite = Sensor { offsets: Default::default(), ..spans };
+            let bytes = crate::to_bytes_with_config(&finite, config).unwrap();
+            assert!(from_bytes_with_config::<Sensor>(&bytes, deny).is_ok());
+        }
+    }
 }
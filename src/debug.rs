@@ -0,0 +1,309 @@
+//! Annotated hexdumps of payloads
+//!
+//! [`explain`] lists a payload's bytes with their offsets and what each
+//! run of bytes means: the header fields, lengths and varints, strings,
+//! map entries and enum variants, and any checksum trailer. Bodies are
+//! broken down value by value when the payload is self-describing; other
+//! bodies are only laid out by their Rust type, so they are dumped whole.
+//! Anything that fails to parse is reported where it happens, followed by
+//! the bytes left over:
+//!
+//! ```rust
+//! use nanobit::Config;
+//!
+//! let bytes = nanobit::to_bytes_with_config(&("id", 7u8), Config::new().with_self_describing(true))?;
+//! print!("{}", nanobit::debug::explain(&bytes));
+//! // 000000  4e 41 4e 4f               magic "NANO"
+//! // 000004  02                        version 2
+//! // 000005  02                        flags: self-describing
+//! // 000006  12 02                     sequence, 2 elements
+//! // 000008  10 02 69 64                 string "id"
+//! // 00000c  09 07                       u8 7
+//! # assert!(nanobit::debug::explain(&bytes).contains("  string \"id\""));
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::{String, ToString}};
+
+use core::fmt::{self, Write};
+
+use crate::buffer::ReadBuffer;
+use crate::compression::{sniff, PayloadInfo};
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+use crate::tag;
+
+/// Bytes shown on each line
+const ROW: usize = 8;
+
+/// Nesting past this depth is reported instead of followed
+const MAX_DEPTH: usize = 128;
+
+/// Longest string shown in full
+const MAX_STR: usize = 40;
+
+/// Render `bytes` as an annotated hexdump
+pub fn explain(bytes: &[u8]) -> String {
+    let mut dump = Dump { data: bytes, out: String::new(), done: 0 };
+    match sniff(bytes) {
+        Some(info) => {
+            if let Err(err) = dump.payload(&info) {
+                dump.error(err);
+            }
+        }
+        None => {
+            let err = match Header::parse(bytes) {
+                Err(err) => err,
+                Ok(_) => Error::InvalidFormat("Payload is too short for its checksum".to_string()),
+            };
+            dump.error(err);
+        }
+    }
+    dump.out
+}
+
+struct Dump<'a> {
+    data: &'a [u8],
+    out: String,
+    // End of the bytes annotated so far
+    done: usize,
+}
+
+impl Dump<'_> {
+    // Annotate `data[start..end]`, continuing long runs on further lines
+    fn line(&mut self, start: usize, end: usize, depth: usize, note: fmt::Arguments<'_>) {
+        let end = end.min(self.data.len());
+        let mut first = true;
+        for offset in (start..end.max(start + 1)).step_by(ROW) {
+            let row = &self.data[offset.min(end)..(offset + ROW).min(end)];
+            let _ = write!(self.out, "{offset:06x}  ");
+            for byte in row {
+                let _ = write!(self.out, "{byte:02x} ");
+            }
+            if first {
+                let pad = (ROW - row.len()) * 3 + 2 + depth * 2;
+                let _ = writeln!(self.out, "{:pad$}{note}", "");
+                first = false;
+            } else {
+                self.out.truncate(self.out.trim_end().len());
+                self.out.push('\n');
+            }
+        }
+        self.done = self.done.max(end);
+    }
+
+    // Report a parse failure and dump whatever was not annotated
+    fn error(&mut self, err: Error) {
+        let _ = writeln!(self.out, "error at {:#x}: {err}", self.done);
+        if self.done < self.data.len() {
+            self.line(self.done, self.data.len(), 0, format_args!("unparsed, {} bytes", self.data.len() - self.done));
+        }
+    }
+
+    fn payload(&mut self, info: &PayloadInfo) -> Result<()> {
+        self.header(info)?;
+        let flags = info.header.flags;
+        let body_start = info.header_len;
+        let body_end = body_start + info.body_len;
+
+        if let Some(format) = info.compression {
+            self.line(body_start, body_start + 1, 0, format_args!("compression {format:?}"));
+            let note = match info.decompressed_len {
+                Some(len) => format!("compressed block, {len} bytes decompressed"),
+                None => "compressed block".to_string(),
+            };
+            self.line(body_start + 1, body_end, 0, format_args!("{note}"));
+        } else if flags.contains(HeaderFlags::ENCRYPTED) {
+            self.line(body_start, body_end, 0, format_args!("encrypted envelope, {} bytes", info.body_len));
+        } else {
+            let mut reader = ReadBuffer::new(&self.data[..body_end]);
+            reader.skip(body_start)?;
+            if flags.contains(HeaderFlags::SEQ_INDEX) {
+                let len = reader.read_varint()? as usize;
+                reader.skip(len)?;
+                self.line(body_start, reader.position(), 0, format_args!("sequence index, {len} bytes"));
+            }
+            if flags.contains(HeaderFlags::SELF_DESCRIBING) {
+                self.value(&mut reader, 0)?;
+                if reader.has_remaining() {
+                    return Err(Error::InvalidFormat(format!("{} trailing bytes after the value", reader.remaining())));
+                }
+            } else if reader.has_remaining() {
+                let start = reader.position();
+                self.line(start, body_end, 0, format_args!("body, {} bytes (layout depends on the type)", body_end - start));
+            }
+        }
+
+        if let Some(checksum) = info.checksum {
+            let status = checksum_status(self.data);
+            self.line(body_end, self.data.len(), 0, format_args!("checksum {checksum:?}{status}"));
+        }
+        Ok(())
+    }
+
+    fn header(&mut self, info: &PayloadInfo) -> Result<()> {
+        let header = info.header;
+        self.line(0, 4, 0, format_args!("magic \"NANO\""));
+        self.line(4, 5, 0, format_args!("version {}", header.version));
+        if header.version == crate::VERSION {
+            return Ok(());
+        }
+        let flags_len = if header.flags.bits() > 0xFF { 2 } else { 1 };
+        self.line(5, 5 + flags_len, 0, format_args!("flags: {}", header.flags));
+        let mut at = 5 + flags_len;
+        if let Some(hash) = header.schema_hash {
+            self.line(at, at + 4, 0, format_args!("schema hash {hash:#010x}"));
+            at += 4;
+        }
+        if header.flags.contains(HeaderFlags::TYPE_NAME) {
+            let mut reader = ReadBuffer::new(&self.data[at..info.header_len]);
+            let name = reader.read_str()?;
+            self.line(at, info.header_len, 0, format_args!("type name {}", Shown(name)));
+        }
+        Ok(())
+    }
+
+    // Annotate one self-describing value and everything inside it
+    fn value(&mut self, reader: &mut ReadBuffer<'_>, depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(Error::InvalidFormat(format!("Nesting deeper than {MAX_DEPTH} levels")));
+        }
+        let start = reader.position();
+        let found = reader.read_u8()?;
+        let note = match found {
+            tag::UNIT => "unit".to_string(),
+            tag::NONE => "none".to_string(),
+            tag::FALSE => "bool false".to_string(),
+            tag::TRUE => "bool true".to_string(),
+            tag::I8 => format!("i8 {}", reader.read_i8()?),
+            tag::I16 => format!("i16 {}", reader.read_i16()?),
+            tag::I32 => format!("i32 {}", reader.read_i32()?),
+            tag::I64 => format!("i64 {}", reader.read_i64()?),
+            tag::U8 => format!("u8 {}", reader.read_u8()?),
+            tag::U16 => format!("u16 {}", reader.read_u16()?),
+            tag::U32 => format!("u32 {}", reader.read_u32()?),
+            tag::U64 => format!("u64 {}", reader.read_u64()?),
+            tag::F32 => format!("f32 {}", reader.read_f32()?),
+            tag::F64 => format!("f64 {}", reader.read_f64()?),
+            tag::CHAR => {
+                let value = reader.read_u32()?;
+                let ch = char::from_u32(value)
+                    .ok_or_else(|| Error::InvalidFormat(format!("Invalid char value {value:#x}")))?;
+                format!("char {ch:?}")
+            }
+            tag::STR => {
+                let bytes = reader.read_byte_slice()?;
+                match core::str::from_utf8(bytes) {
+                    Ok(s) => format!("string {}", Shown(s)),
+                    Err(_) => return Err(Error::InvalidFormat(format!("String of {} bytes is not UTF-8", bytes.len()))),
+                }
+            }
+            tag::BYTES => format!("bytes, {} long", reader.read_byte_slice()?.len()),
+            tag::SOME => {
+                self.line(start, reader.position(), depth, format_args!("some"));
+                return self.value(reader, depth + 1);
+            }
+            tag::SEQ => {
+                let len = reader.read_varint()?;
+                self.line(start, reader.position(), depth, format_args!("sequence, {len} elements"));
+                for _ in 0..len {
+                    self.value(reader, depth + 1)?;
+                }
+                return Ok(());
+            }
+            tag::MAP => {
+                let len = reader.read_varint()?;
+                self.line(start, reader.position(), depth, format_args!("map, {len} entries"));
+                for _ in 0..len {
+                    self.value(reader, depth + 1)?;
+                    self.value(reader, depth + 2)?;
+                }
+                return Ok(());
+            }
+            tag::VARIANT => {
+                let index = reader.read_varint()?;
+                let name = reader.read_str()?;
+                self.line(start, reader.position(), depth, format_args!("variant {index} {}", Shown(name)));
+                return self.value(reader, depth + 1);
+            }
+            other => return Err(Error::InvalidFormat(format!("Unknown type tag: {other:#04x}"))),
+        };
+        self.line(start, reader.position(), depth, format_args!("{note}"));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "checksum")]
+fn checksum_status(data: &[u8]) -> &'static str {
+    match crate::checksum::verify_checksum(data) {
+        Ok(()) => " (matches)",
+        Err(_) => " (MISMATCH)",
+    }
+}
+
+#[cfg(not(feature = "checksum"))]
+fn checksum_status(_data: &[u8]) -> &'static str {
+    ""
+}
+
+// A string quoted and shortened to fit on one line
+struct Shown<'a>(&'a str);
+
+impl fmt::Display for Shown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.char_indices().nth(MAX_STR) {
+            Some((end, _)) => write!(f, "{:?}... ({} bytes)", &self.0[..end], self.0.len()),
+            None => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    enum Event {
+        Login { user: String, attempts: Option<u32> },
+    }
+
+    #[test]
+    fn test_explain_self_describing() {
+        let event = Event::Login { user: "ada".to_string(), attempts: Some(2) };
+        let bytes = crate::to_bytes_with_config(&event, Config::new().with_self_describing(true)).unwrap();
+        let text = explain(&bytes);
+        // Skip the lines continuing a long run of bytes
+        let notes: Vec<&str> = text.lines().filter_map(|line| line.get(8 + ROW * 3 + 1..)).collect();
+        assert_eq!(
+            notes,
+            [
+                " magic \"NANO\"",
+                " version 2",
+                " flags: self-describing",
+                " variant 0 \"Login\"",
+                "   map, 2 entries",
+                "     string \"user\"",
+                "       string \"ada\"",
+                "     string \"attempts\"",
+                "       some",
+                "         u32 2",
+            ]
+        );
+
+        // Truncation is reported with the bytes that could not be read
+        let text = explain(&bytes[..bytes.len() - 2]);
+        assert!(text.lines().rev().nth(1).unwrap().starts_with("error at 0x"));
+        assert!(text.ends_with("unparsed, 3 bytes\n"));
+    }
+
+    #[test]
+    fn test_explain_plain() {
+        let text = explain(&crate::to_bytes(&[1u64; 3]).unwrap());
+        assert!(text.contains("version 1"));
+        assert!(text.contains("body, 25 bytes (layout depends on the type)"));
+        assert!(explain(b"NAN").starts_with("error at 0x0"));
+    }
+}
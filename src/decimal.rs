@@ -0,0 +1,271 @@
+//! Compact encoding for decimal numbers
+//!
+//! `rust_decimal::Decimal` and `bigdecimal::BigDecimal` write themselves
+//! as strings through serde, one byte per digit plus the point and sign.
+//! The [`CompactDecimal`] wrapper, or `#[serde(with = "nanobit::decimal")]`
+//! on a field, writes the scale and the integer mantissa in binary
+//! instead, so `1234.56` takes 5 bytes:
+//!
+//! ```rust
+//! # #[cfg(feature = "rust_decimal")] {
+//! use rust_decimal::Decimal;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Trade {
+//!     #[serde(with = "nanobit::decimal")]
+//!     price: Decimal,
+//!     #[serde(with = "nanobit::decimal")]
+//!     quantity: Decimal,
+//! }
+//!
+//! let trade = Trade { price: Decimal::new(123_456, 2), quantity: Decimal::new(-25, 1) };
+//! let bytes = nanobit::to_bytes(&trade)?;
+//! assert!(bytes.len() < nanobit::to_bytes(&("1234.56", "-2.5"))?.len());
+//! assert_eq!(nanobit::from_bytes::<Trade>(&bytes)?, trade);
+//! # }
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! `Decimal` needs the `rust_decimal` feature and `BigDecimal` the
+//! `bigdecimal` feature. Both share one representation, so a value
+//! written from either decodes into the other when it fits.
+//!
+//! # Representation
+//!
+//! A decimal `±m × 10^-scale` is a byte string. It starts with the varint
+//! `zigzag(scale) << 1 | negative`, followed by the magnitude `m` in
+//! little-endian order without trailing zero bytes, so zero has no
+//! magnitude bytes at all. The mantissa is not normalized: `1.50` keeps
+//! its scale of 2 and reads back as `1.50`.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::ToString, vec::Vec};
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Error as _, Serialize, Serializer};
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+use crate::error::Error;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Decimal numbers that [`CompactDecimal`] can encode
+pub trait DecimalNumber: sealed::Sealed + Sized {
+    /// Write the value as `negative`, `scale` and little-endian magnitude
+    /// with [`write_parts`]
+    fn write_compact(&self, out: &mut WriteBuffer) -> crate::Result<()>;
+
+    /// The value `±magnitude × 10^-scale`, or `None` if the type cannot
+    /// represent it
+    fn from_parts(negative: bool, scale: i64, magnitude: &[u8]) -> Option<Self>;
+}
+
+/// Write the compact representation of `±magnitude × 10^-scale`
+///
+/// `magnitude` is little-endian; trailing zero bytes are dropped. Fails if
+/// `scale` is beyond ±2^62, which leaves no room for the sign bit.
+pub fn write_parts(out: &mut WriteBuffer, negative: bool, scale: i64, magnitude: &[u8]) -> crate::Result<()> {
+    let zigzag = ((scale << 1) ^ (scale >> 63)) as u64;
+    if zigzag >> 63 != 0 {
+        return Err(Error::InvalidFormat("Decimal scale out of range".to_string()));
+    }
+    out.write_varint(zigzag << 1 | negative as u64)?;
+    let len = magnitude.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+    out.write_bytes(&magnitude[..len])
+}
+
+#[cfg(feature = "rust_decimal")]
+impl sealed::Sealed for rust_decimal::Decimal {}
+
+#[cfg(feature = "rust_decimal")]
+impl DecimalNumber for rust_decimal::Decimal {
+    fn write_compact(&self, out: &mut WriteBuffer) -> crate::Result<()> {
+        let magnitude = self.mantissa().unsigned_abs().to_le_bytes();
+        write_parts(out, self.is_sign_negative(), self.scale().into(), &magnitude)
+    }
+
+    fn from_parts(negative: bool, scale: i64, magnitude: &[u8]) -> Option<Self> {
+        // 96-bit mantissa, scale up to 28
+        let scale = u32::try_from(scale).ok().filter(|&scale| scale <= 28)?;
+        if magnitude.len() > 12 {
+            return None;
+        }
+        let mut bytes = [0u8; 16];
+        bytes[..magnitude.len()].copy_from_slice(magnitude);
+        let m = u128::from_le_bytes(bytes);
+        Some(rust_decimal::Decimal::from_parts(m as u32, (m >> 32) as u32, (m >> 64) as u32, negative, scale))
+    }
+}
+
+#[cfg(feature = "bigdecimal")]
+impl sealed::Sealed for bigdecimal::BigDecimal {}
+
+#[cfg(feature = "bigdecimal")]
+impl DecimalNumber for bigdecimal::BigDecimal {
+    fn write_compact(&self, out: &mut WriteBuffer) -> crate::Result<()> {
+        let (digits, scale) = self.as_bigint_and_exponent();
+        let (sign, magnitude) = digits.to_bytes_le();
+        write_parts(out, sign == bigdecimal::num_bigint::Sign::Minus, scale, &magnitude)
+    }
+
+    fn from_parts(negative: bool, scale: i64, magnitude: &[u8]) -> Option<Self> {
+        use bigdecimal::num_bigint::{BigInt, Sign};
+
+        let sign = if negative { Sign::Minus } else { Sign::Plus };
+        Some(bigdecimal::BigDecimal::new(BigInt::from_bytes_le(sign, magnitude), scale))
+    }
+}
+
+/// A decimal number written as its scale and binary mantissa
+///
+/// See the [module documentation](self) for the representation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactDecimal<T>(pub T);
+
+impl<T: DecimalNumber> Serialize for CompactDecimal<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: DecimalNumber> Deserialize<'de> for CompactDecimal<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(CompactDecimal)
+    }
+}
+
+/// Serialize a decimal number in its compact form
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: DecimalNumber,
+    S: Serializer,
+{
+    let mut out = WriteBuffer::with_capacity(16);
+    value.write_compact(&mut out).map_err(S::Error::custom)?;
+    serializer.serialize_bytes(out.as_slice())
+}
+
+/// Deserialize a decimal number written by [`serialize`]
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: DecimalNumber,
+    D: Deserializer<'de>,
+{
+    struct DecimalVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: DecimalNumber> Visitor<'de> for DecimalVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a byte string holding a compact decimal")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<T, E> {
+            let mut input = ReadBuffer::new(bytes);
+            let head = input.read_varint().map_err(E::custom)?;
+            let zigzag = head >> 1;
+            let scale = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            let magnitude = &bytes[input.position()..];
+            if magnitude.last() == Some(&0) {
+                return Err(E::custom("Decimal magnitude has trailing zero bytes"));
+            }
+            T::from_parts(head & 1 == 1, scale, magnitude).ok_or_else(|| E::custom("Decimal out of range"))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(64));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    deserializer.deserialize_bytes(DecimalVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn test_rust_decimal_roundtrip() {
+        use crate::Config;
+        use rust_decimal::Decimal;
+
+        let cases = [
+            (Decimal::ZERO, 1),
+            (Decimal::new(15, 1), 2),
+            (Decimal::new(150, 2), 2),
+            (Decimal::new(-123_456, 2), 4),
+            (Decimal::MAX, 13),
+            (Decimal::MIN, 13),
+            (Decimal::from_parts(1, 0, 0, false, 28), 2),
+        ];
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            for (value, len) in cases {
+                let bytes = crate::to_bytes_with_config(&CompactDecimal(value), config).unwrap();
+                if !config.self_describing() {
+                    assert_eq!(bytes.len() - crate::to_bytes(&()).unwrap().len(), len + 1, "{value}");
+                }
+                let CompactDecimal(decoded) = crate::from_bytes::<CompactDecimal<Decimal>>(&bytes).unwrap();
+                assert_eq!(decoded, value);
+                assert_eq!(decoded.scale(), value.scale());
+            }
+        }
+
+        // The representation, spelled out: zigzag(2) << 1 | negative, then 12345 little-endian
+        let bytes = crate::to_bytes(&CompactDecimal(Decimal::new(-12_345, 2))).unwrap();
+        assert!(bytes.ends_with(&[3, 9, 0x39, 0x30]));
+
+        // Without type tags a byte string is written like a `Vec<u8>`
+        let decode = |bytes: &[u8]| crate::from_bytes::<CompactDecimal<Decimal>>(&crate::to_bytes(&bytes.to_vec()).unwrap());
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[116]).is_err());
+        assert!(decode(&[0, 1, 0]).is_err());
+        assert!(decode(&[0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]).is_err());
+        assert!(decode(&[0, 1]).is_ok());
+    }
+
+    #[cfg(feature = "bigdecimal")]
+    #[test]
+    fn test_bigdecimal_roundtrip() {
+        use bigdecimal::BigDecimal;
+
+        for text in ["0", "-1.50", "123456789012345678901234567890.0987654321", "1e-400", "-7e90"] {
+            let value: BigDecimal = text.parse().unwrap();
+            let bytes = crate::to_bytes(&CompactDecimal(value.clone())).unwrap();
+            let CompactDecimal(decoded) = crate::from_bytes::<CompactDecimal<BigDecimal>>(&bytes).unwrap();
+            assert_eq!(decoded.as_bigint_and_exponent(), value.as_bigint_and_exponent());
+            assert!(bytes.len() < crate::to_bytes(&text).unwrap().len() || text == "0");
+        }
+    }
+
+    #[cfg(all(feature = "rust_decimal", feature = "bigdecimal"))]
+    #[test]
+    fn test_decimal_types_interchangeable() {
+        use bigdecimal::BigDecimal;
+        use rust_decimal::Decimal;
+
+        let bytes = crate::to_bytes(&CompactDecimal(Decimal::new(-123_456, 3))).unwrap();
+        let CompactDecimal(big) = crate::from_bytes::<CompactDecimal<BigDecimal>>(&bytes).unwrap();
+        assert_eq!(big, "-123.456".parse::<BigDecimal>().unwrap());
+
+        let too_precise: BigDecimal = "1e-29".parse().unwrap();
+        let bytes = crate::to_bytes(&CompactDecimal(too_precise)).unwrap();
+        assert!(crate::from_bytes::<CompactDecimal<Decimal>>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+//! Shared `Rc`/`Arc` values written once per payload
+//!
+//! Serde writes the contents of every `Rc` or `Arc` it meets, so a value
+//! shared by a hundred nodes is written a hundred times and decoded as a
+//! hundred copies. Wrapping the pointer in [`Dedup`] and encoding with this
+//! module's [`to_bytes`] writes it once; later occurrences become
+//! back-references, and [`from_bytes`] restores the sharing. A pointer that
+//! refers back to a value still being written is reported as a cycle
+//! instead of recursing forever.
+//!
+//! Outside these functions `Dedup` writes its value inline every time, and
+//! back-references cannot be resolved.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread::LocalKey;
+
+use serde::de::{DeserializeOwned, Deserializer, EnumAccess, Error as _, VariantAccess, Visitor};
+use serde::ser::Error as _;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::config::Config;
+use crate::error::Result;
+
+const NAME: &str = "Dedup";
+const VARIANTS: &[&str] = &["Value", "Ref"];
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Reference-counted pointers that [`Dedup`] can share
+pub trait SharedPointer: sealed::Sealed + Clone + 'static {
+    /// The pointed-to type
+    type Target;
+
+    /// Allocate a pointer to `value`
+    fn new(value: Self::Target) -> Self;
+
+    /// Address identifying the allocation
+    fn addr(this: &Self) -> usize;
+
+    /// The pointed-to value
+    fn get(this: &Self) -> &Self::Target;
+}
+
+impl<T: 'static> sealed::Sealed for Rc<T> {}
+
+impl<T: 'static> SharedPointer for Rc<T> {
+    type Target = T;
+
+    fn new(value: T) -> Self {
+        Rc::new(value)
+    }
+
+    fn addr(this: &Self) -> usize {
+        Rc::as_ptr(this) as usize
+    }
+
+    fn get(this: &Self) -> &T {
+        this
+    }
+}
+
+impl<T: 'static> sealed::Sealed for Arc<T> {}
+
+impl<T: 'static> SharedPointer for Arc<T> {
+    type Target = T;
+
+    fn new(value: T) -> Self {
+        Arc::new(value)
+    }
+
+    fn addr(this: &Self) -> usize {
+        Arc::as_ptr(this) as usize
+    }
+
+    fn get(this: &Self) -> &T {
+        this
+    }
+}
+
+/// An `Rc` or `Arc` whose sharing survives a round trip
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use nanobit::Dedup;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Node {
+///     id: u32,
+///     settings: Dedup<Arc<Vec<String>>>,
+/// }
+///
+/// let settings = Arc::new(vec!["verbose".to_string(); 100]);
+/// let nodes: Vec<Node> = (0..50).map(|id| Node { id, settings: Dedup(settings.clone()) }).collect();
+///
+/// let bytes = nanobit::dedup::to_bytes(&nodes)?;
+/// assert!(bytes.len() < nanobit::to_bytes(&nodes)?.len() / 20);
+///
+/// let nodes: Vec<Node> = nanobit::dedup::from_bytes(&bytes)?;
+/// assert!(Arc::ptr_eq(&nodes[0].settings.0, &nodes[49].settings.0));
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Dedup<P>(pub P);
+
+/// Serialize a value, writing each shared pointer once
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    to_bytes_with_config(value, Config::new())
+}
+
+/// Serialize a value with the given options, writing each shared pointer once
+pub fn to_bytes_with_config<T>(value: &T, config: Config) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    let _scope = Scope::enter(&WRITTEN);
+    crate::to_bytes_with_config(&value, config)
+}
+
+/// Deserialize a value, restoring shared pointers
+pub fn from_bytes<T>(bytes: &[u8]) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    from_bytes_with_config(bytes, Config::new())
+}
+
+/// Deserialize a value with the given options, restoring shared pointers
+pub fn from_bytes_with_config<T>(bytes: &[u8], config: Config) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let _scope = Scope::enter(&READ);
+    crate::from_bytes_with_config(bytes, config)
+}
+
+// Pointers written so far, by address: `None` while the value is being
+// written, then its back-reference id
+#[derive(Default)]
+struct Written {
+    ids: HashMap<usize, Option<u64>>,
+    next: u64,
+}
+
+// Pointers read so far, in back-reference id order
+type Read = Vec<Box<dyn Any>>;
+
+thread_local! {
+    static WRITTEN: RefCell<Option<Written>> = const { RefCell::new(None) };
+    static READ: RefCell<Option<Read>> = const { RefCell::new(None) };
+}
+
+// Installs fresh tracking state for the current thread, restoring the
+// previous state when dropped
+struct Scope<S: 'static> {
+    key: &'static LocalKey<RefCell<Option<S>>>,
+    previous: Option<S>,
+}
+
+impl<S: Default> Scope<S> {
+    fn enter(key: &'static LocalKey<RefCell<Option<S>>>) -> Self {
+        let previous = key.with(|state| state.replace(Some(S::default())));
+        Self { key, previous }
+    }
+}
+
+impl<S> Drop for Scope<S> {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        self.key.with(|state| *state.borrow_mut() = previous);
+    }
+}
+
+impl<P> Serialize for Dedup<P>
+where
+    P: SharedPointer,
+    P::Target: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let addr = P::addr(&self.0);
+        let seen = WRITTEN.with(|state| {
+            state.borrow_mut().as_mut().map(|written| match written.ids.get(&addr) {
+                Some(&id) => Some(id),
+                None => {
+                    written.ids.insert(addr, None);
+                    None
+                }
+            })
+        });
+
+        match seen {
+            // Not tracking
+            None => serializer.serialize_newtype_variant(NAME, 0, VARIANTS[0], P::get(&self.0)),
+            Some(Some(Some(id))) => serializer.serialize_newtype_variant(NAME, 1, VARIANTS[1], &id),
+            Some(Some(None)) => Err(S::Error::custom("Cycle of shared pointers")),
+            Some(None) => {
+                let result = serializer.serialize_newtype_variant(NAME, 0, VARIANTS[0], P::get(&self.0));
+                // Ids are assigned once a value is complete, as when reading
+                WRITTEN.with(|state| {
+                    if let Some(written) = state.borrow_mut().as_mut() {
+                        written.ids.insert(addr, Some(written.next));
+                        written.next += 1;
+                    }
+                });
+                result
+            }
+        }
+    }
+}
+
+impl<'de, P> Deserialize<'de> for Dedup<P>
+where
+    P: SharedPointer,
+    P::Target: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DedupVisitor<P>(PhantomData<P>);
+
+        impl<'de, P> Visitor<'de> for DedupVisitor<P>
+        where
+            P: SharedPointer,
+            P::Target: Deserialize<'de>,
+        {
+            type Value = P;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a shared value or a reference to one")
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> std::result::Result<P, A::Error> {
+                let (variant, access) = data.variant::<Variant>()?;
+                match variant {
+                    Variant::Value => {
+                        let pointer = P::new(access.newtype_variant()?);
+                        READ.with(|state| {
+                            if let Some(read) = state.borrow_mut().as_mut() {
+                                read.push(Box::new(pointer.clone()));
+                            }
+                        });
+                        Ok(pointer)
+                    }
+                    Variant::Ref => {
+                        let id: u64 = access.newtype_variant()?;
+                        READ.with(|state| {
+                            let state = state.borrow();
+                            let read = state.as_ref().ok_or_else(|| {
+                                A::Error::custom("Shared pointer reference read outside dedup::from_bytes")
+                            })?;
+                            read.get(id as usize)
+                                .and_then(|pointer| pointer.downcast_ref::<P>())
+                                .cloned()
+                                .ok_or_else(|| A::Error::custom("Invalid shared pointer reference"))
+                        })
+                    }
+                }
+            }
+        }
+
+        deserializer
+            .deserialize_enum(NAME, VARIANTS, DedupVisitor(PhantomData))
+            .map(Dedup)
+    }
+}
+
+// Variant of the encoded form, by index or by name
+enum Variant {
+    Value,
+    Ref,
+}
+
+impl<'de> Deserialize<'de> for Variant {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct VariantVisitor;
+
+        impl Visitor<'_> for VariantVisitor {
+            type Value = Variant;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("`Value` or `Ref`")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, index: u64) -> std::result::Result<Variant, E> {
+                match index {
+                    0 => Ok(Variant::Value),
+                    1 => Ok(Variant::Ref),
+                    _ => Err(E::invalid_value(serde::de::Unexpected::Unsigned(index), &self)),
+                }
+            }
+
+            fn visit_str<E: serde::de::Error>(self, name: &str) -> std::result::Result<Variant, E> {
+                match name {
+                    "Value" => Ok(Variant::Value),
+                    "Ref" => Ok(Variant::Ref),
+                    _ => Err(E::unknown_variant(name, VARIANTS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(VariantVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Serialize, Deserialize)]
+    struct Graph {
+        nodes: Vec<(u32, Dedup<Rc<String>>)>,
+        nested: Dedup<Rc<Vec<Dedup<Rc<String>>>>>,
+    }
+
+    #[test]
+    fn test_dedup_restores_sharing() {
+        let label = Rc::new("shared label".to_string());
+        let graph = Graph {
+            nodes: (0..10).map(|i| (i, Dedup(label.clone()))).collect(),
+            nested: Dedup(Rc::new(vec![Dedup(label.clone()), Dedup(Rc::new("own".into()))])),
+        };
+
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = to_bytes_with_config(&graph, config).unwrap();
+            let read: Graph = from_bytes(&bytes).unwrap();
+            assert!(read.nodes.iter().all(|(_, l)| Rc::ptr_eq(&l.0, &read.nested.0[0].0)));
+            assert_eq!(*read.nested.0[1].0, "own");
+            assert_eq!(Rc::strong_count(&read.nodes[0].1 .0), 11);
+
+            // Plain functions write every copy, and cannot read references
+            let inline = crate::to_bytes_with_config(&graph, config).unwrap();
+            assert!(bytes.len() < inline.len());
+            assert!(crate::from_bytes::<Graph>(&bytes).is_err());
+            assert_eq!(*from_bytes::<Graph>(&inline).unwrap().nodes[9].1 .0, "shared label");
+        }
+    }
+
+    #[test]
+    fn test_dedup_detects_cycles() {
+        #[derive(Serialize)]
+        struct Link {
+            next: RefCell<Option<Dedup<Rc<Link>>>>,
+        }
+
+        let link = Rc::new(Link { next: RefCell::new(None) });
+        *link.next.borrow_mut() = Some(Dedup(link.clone()));
+        let err = to_bytes(&Dedup(link.clone())).unwrap_err();
+        assert!(err.to_string().contains("Cycle"));
+        link.next.borrow_mut().take();
+    }
+}
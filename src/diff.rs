@@ -0,0 +1,457 @@
+//! Structural comparison of two serialized payloads
+//!
+//! Self-describing payloads are compared value by value: maps and structs
+//! by key, sequences by index, enums by variant. Tagged-field payloads are
+//! compared field by field at the top level. Each difference carries its
+//! path, in the form accepted by [`extract`](crate::extract), and the byte
+//! offsets of the values in both payloads.
+
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::{String, ToString}, vec::Vec};
+
+use core::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::buffer::ReadBuffer;
+use crate::checksum::split_payload;
+use crate::config::{Config, DEFAULT_MAX_DEPTH};
+use crate::de::{skip_seq_index, skip_value};
+use crate::error::{Error, Result};
+use crate::header::HeaderFlags;
+use crate::tag;
+
+/// How a value differs between two payloads
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffKind {
+    /// Present in both with different contents
+    Changed,
+    /// Only present in the right payload
+    Added,
+    /// Only present in the left payload
+    Removed,
+}
+
+/// A value that differs between two payloads
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// Field names, map keys, sequence indexes or variant names leading to the value
+    pub path: Vec<String>,
+    /// How the value differs
+    pub kind: DiffKind,
+    /// Byte offset of the value in the left payload
+    pub left: Option<usize>,
+    /// Byte offset of the value in the right payload
+    pub right: Option<usize>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str("<root>")?;
+        } else {
+            f.write_str(&self.path.join("."))?;
+        }
+        let kind = match self.kind {
+            DiffKind::Changed => "changed",
+            DiffKind::Added => "added",
+            DiffKind::Removed => "removed",
+        };
+        write!(f, ": {kind}")?;
+        match (self.left, self.right) {
+            (Some(left), Some(right)) => write!(f, " (left @{left}, right @{right})"),
+            (Some(left), None) => write!(f, " (left @{left})"),
+            (None, Some(right)) => write!(f, " (right @{right})"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Compare two self-describing or tagged-field payloads
+///
+/// ```rust
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Replica { term: u64, leader: String }
+///
+/// let config = nanobit::Config::new().with_self_describing(true);
+/// let a = nanobit::to_bytes_with_config(&Replica { term: 4, leader: "n1".into() }, config)?;
+/// let b = nanobit::to_bytes_with_config(&Replica { term: 5, leader: "n1".into() }, config)?;
+///
+/// let diffs = nanobit::diff(&a, &b)?;
+/// assert_eq!(diffs.len(), 1);
+/// assert_eq!(diffs[0].path, ["term"]);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn diff(left: &[u8], right: &[u8]) -> Result<Vec<Difference>> {
+    let left = Side::new(left)?;
+    let right = Side::new(right)?;
+    let layout = |flags: HeaderFlags| {
+        (
+            flags.contains(HeaderFlags::SELF_DESCRIBING),
+            flags.contains(HeaderFlags::TAGGED_FIELDS),
+            flags.contains(HeaderFlags::COMPACT_CHARS),
+            flags.contains(HeaderFlags::FIXED_TUPLES),
+            flags.contains(HeaderFlags::NICHE_OPTIONS),
+        )
+    };
+    if layout(left.flags) != layout(right.flags) {
+        return Err(Error::InvalidFormat(
+            "Cannot compare payloads encoded with different options".to_string(),
+        ));
+    }
+
+    let mut diffs = Vec::new();
+    if left.flags.contains(HeaderFlags::SELF_DESCRIBING) {
+        compare(&left, &right, 0, 0, 0, &mut Vec::new(), &mut diffs)?;
+    } else if left.flags.contains(HeaderFlags::STRING_TABLE) || right.flags.contains(HeaderFlags::STRING_TABLE) {
+        // Equal bytes may name different strings in the two tables
+        return Err(Error::InvalidFormat(
+            "Payloads with string tables cannot be compared byte-wise; use diff_as".to_string(),
+        ));
+    } else if left.flags.contains(HeaderFlags::TAGGED_FIELDS) {
+        compare_tagged(&left, &right, &mut diffs)?;
+    } else {
+        return Err(Error::InvalidFormat(
+            "Plain payloads carry no structure to compare; use diff_as".to_string(),
+        ));
+    }
+    Ok(diffs)
+}
+
+/// Compare two payloads of type `T`, which may be in any encoding
+///
+/// Both are decoded and compared as self-describing values; offsets are
+/// not reported since they would not refer to the original payloads.
+pub fn diff_as<T>(left: &[u8], right: &[u8]) -> Result<Vec<Difference>>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let config = Config::new().with_self_describing(true);
+    let left = crate::to_bytes_with_config(&crate::from_bytes::<T>(left)?, config)?;
+    let right = crate::to_bytes_with_config(&crate::from_bytes::<T>(right)?, config)?;
+    let mut diffs = diff(&left, &right)?;
+    for difference in &mut diffs {
+        difference.left = None;
+        difference.right = None;
+    }
+    Ok(diffs)
+}
+
+// The body of one payload and where it starts
+struct Side<'a> {
+    body: &'a [u8],
+    base: usize,
+    flags: HeaderFlags,
+}
+
+impl<'a> Side<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        let (header, mut body) = split_payload(data)?;
+        if header.flags.contains(HeaderFlags::SEQ_INDEX) {
+            let mut reader = ReadBuffer::new(body);
+            skip_seq_index(&mut reader)?;
+            body = &body[reader.position()..];
+        }
+        let base = body.as_ptr() as usize - data.as_ptr() as usize;
+        Ok(Self {
+            body,
+            base,
+            flags: header.flags,
+        })
+    }
+
+    fn reader(&self, position: usize) -> Result<ReadBuffer<'a>> {
+        self.body
+            .get(position..)
+            .map(ReadBuffer::new)
+            .ok_or(Error::UnexpectedEof)
+    }
+
+    // End of the value starting at `position`
+    fn end(&self, position: usize) -> Result<usize> {
+        let mut reader = self.reader(position)?;
+        skip_value(&mut reader)?;
+        Ok(position + reader.position())
+    }
+
+    fn offset(&self, position: usize) -> Option<usize> {
+        Some(self.base + position)
+    }
+}
+
+fn push(
+    diffs: &mut Vec<Difference>,
+    path: &[String],
+    kind: DiffKind,
+    left: Option<usize>,
+    right: Option<usize>,
+) {
+    diffs.push(Difference {
+        path: path.to_vec(),
+        kind,
+        left,
+        right,
+    });
+}
+
+// Compare the self-describing values at `a` in `left` and `b` in `right`,
+// nested `depth` levels deep
+fn compare(
+    left: &Side<'_>,
+    right: &Side<'_>,
+    a: usize,
+    b: usize,
+    depth: usize,
+    path: &mut Vec<String>,
+    diffs: &mut Vec<Difference>,
+) -> Result<()> {
+    if depth >= DEFAULT_MAX_DEPTH {
+        return Err(Error::LimitExceeded { what: "Nesting depth", limit: DEFAULT_MAX_DEPTH });
+    }
+    let mut ra = left.reader(a)?;
+    let mut rb = right.reader(b)?;
+    let (ta, tb) = (ra.read_u8()?, rb.read_u8()?);
+    let changed = |diffs: &mut Vec<Difference>, path: &[String]| {
+        push(diffs, path, DiffKind::Changed, left.offset(a), right.offset(b));
+    };
+
+    match (ta, tb) {
+        (tag::SOME, tag::SOME) => compare(left, right, a + 1, b + 1, depth + 1, path, diffs),
+        (tag::SEQ, tag::SEQ) => {
+            let (la, lb) = (ra.read_varint()?, rb.read_varint()?);
+            let (mut pa, mut pb) = (a + ra.position(), b + rb.position());
+            for index in 0..la.max(lb) {
+                path.push(index.to_string());
+                match (index < la, index < lb) {
+                    (true, true) => compare(left, right, pa, pb, depth + 1, path, diffs)?,
+                    (true, false) => push(diffs, path, DiffKind::Removed, left.offset(pa), None),
+                    _ => push(diffs, path, DiffKind::Added, None, right.offset(pb)),
+                }
+                path.pop();
+                if index < la {
+                    pa = left.end(pa)?;
+                }
+                if index < lb {
+                    pb = right.end(pb)?;
+                }
+            }
+            Ok(())
+        }
+        (tag::MAP, tag::MAP) => {
+            let (la, lb) = (ra.read_varint()?, rb.read_varint()?);
+            let ea = entries(left, a + ra.position(), la)?;
+            let eb = entries(right, b + rb.position(), lb)?;
+            for entry in &ea {
+                path.push(entry.name.clone());
+                match eb.iter().find(|other| other.key == entry.key) {
+                    Some(other) => compare(left, right, entry.value, other.value, depth + 1, path, diffs)?,
+                    None => push(diffs, path, DiffKind::Removed, left.offset(entry.value), None),
+                }
+                path.pop();
+            }
+            for entry in eb.iter().filter(|entry| !ea.iter().any(|other| other.key == entry.key)) {
+                path.push(entry.name.clone());
+                push(diffs, path, DiffKind::Added, None, right.offset(entry.value));
+                path.pop();
+            }
+            Ok(())
+        }
+        (tag::VARIANT, tag::VARIANT) => {
+            let (ia, na) = (ra.read_varint()?, ra.read_str()?);
+            let (ib, nb) = (rb.read_varint()?, rb.read_str()?);
+            if ia != ib || na != nb {
+                changed(diffs, path);
+                return Ok(());
+            }
+            path.push(na.to_string());
+            let result = compare(left, right, a + ra.position(), b + rb.position(), depth + 1, path, diffs);
+            path.pop();
+            result
+        }
+        _ => {
+            let (ea, eb) = (left.end(a)?, right.end(b)?);
+            if left.body[a..ea] != right.body[b..eb] {
+                changed(diffs, path);
+            }
+            Ok(())
+        }
+    }
+}
+
+// A map entry: its encoded key, a readable form of it and where its value starts
+struct Entry<'a> {
+    key: &'a [u8],
+    name: String,
+    value: usize,
+}
+
+fn entries<'a>(side: &Side<'a>, mut position: usize, len: u64) -> Result<Vec<Entry<'a>>> {
+    let mut entries = Vec::new();
+    for _ in 0..len {
+        let value = side.end(position)?;
+        let key = &side.body[position..value];
+        entries.push(Entry {
+            key,
+            name: key_name(key),
+            value,
+        });
+        position = side.end(value)?;
+    }
+    Ok(entries)
+}
+
+// Strings and integers as themselves, other keys as hex
+fn key_name(key: &[u8]) -> String {
+    let mut reader = ReadBuffer::new(&key[1..]);
+    let name = match key[0] {
+        tag::STR => reader.read_str().map(ToString::to_string),
+        tag::U8 => reader.read_u8().map(|v| v.to_string()),
+        tag::U16 => reader.read_u16().map(|v| v.to_string()),
+        tag::U32 => reader.read_u32().map(|v| v.to_string()),
+        tag::U64 => reader.read_u64().map(|v| v.to_string()),
+        tag::I8 => reader.read_i8().map(|v| v.to_string()),
+        tag::I16 => reader.read_i16().map(|v| v.to_string()),
+        tag::I32 => reader.read_i32().map(|v| v.to_string()),
+        tag::I64 => reader.read_i64().map(|v| v.to_string()),
+        _ => Err(Error::NotEnoughData),
+    };
+    name.unwrap_or_else(|_| key.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+// Compare the top-level fields of two tagged-field structs by raw value
+fn compare_tagged(left: &Side<'_>, right: &Side<'_>, diffs: &mut Vec<Difference>) -> Result<()> {
+    let fa = tagged_fields(left)?;
+    let fb = tagged_fields(right)?;
+    for (tag, start, value) in &fa {
+        let path = [tag.to_string()];
+        match fb.iter().find(|(other, _, _)| other == tag) {
+            Some((_, other_start, other)) if value != other => {
+                push(diffs, &path, DiffKind::Changed, left.offset(*start), right.offset(*other_start))
+            }
+            Some(_) => {}
+            None => push(diffs, &path, DiffKind::Removed, left.offset(*start), None),
+        }
+    }
+    for (tag, start, _) in fb.iter().filter(|(tag, _, _)| !fa.iter().any(|(other, _, _)| other == tag)) {
+        push(diffs, &[tag.to_string()], DiffKind::Added, None, right.offset(*start));
+    }
+    Ok(())
+}
+
+// (tag, value offset, value bytes) of each field
+fn tagged_fields<'a>(side: &Side<'a>) -> Result<Vec<(u64, usize, &'a [u8])>> {
+    let mut reader = side.reader(0)?;
+    let count = reader.read_varint()?;
+    let mut fields = Vec::new();
+    for _ in 0..count {
+        let tag = reader.read_varint()?;
+        let len = reader.read_varint()? as usize;
+        let start = reader.position();
+        fields.push((tag, start, reader.read_bytes(len)?));
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    struct State {
+        term: u64,
+        members: Vec<String>,
+        meta: BTreeMap<String, u32>,
+        role: Option<Role>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    enum Role {
+        Leader { since: u64 },
+        Follower,
+    }
+
+    fn state() -> State {
+        State {
+            term: 3,
+            members: vec!["a".to_string(), "b".to_string()],
+            meta: BTreeMap::from([("x".to_string(), 1), ("y".to_string(), 2)]),
+            role: Some(Role::Leader { since: 10 }),
+        }
+    }
+
+    fn paths(diffs: &[Difference]) -> Vec<(String, DiffKind)> {
+        diffs.iter().map(|d| (d.path.join("."), d.kind)).collect()
+    }
+
+    #[test]
+    fn test_diff_self_describing() {
+        let config = Config::new().with_self_describing(true);
+        let old = state();
+        let mut new = state();
+        new.members.push("c".to_string());
+        new.meta.remove("x");
+        new.meta.insert("y".to_string(), 5);
+        new.role = Some(Role::Leader { since: 11 });
+
+        let a = crate::to_bytes_with_config(&old, config).unwrap();
+        let b = crate::to_bytes_with_config(&new, config).unwrap();
+        assert!(diff(&a, &a).unwrap().is_empty());
+
+        let diffs = diff(&a, &b).unwrap();
+        assert_eq!(
+            paths(&diffs),
+            [
+                ("members.2".to_string(), DiffKind::Added),
+                ("meta.x".to_string(), DiffKind::Removed),
+                ("meta.y".to_string(), DiffKind::Changed),
+                ("role.Leader.since".to_string(), DiffKind::Changed),
+            ]
+        );
+
+        // Offsets point at the values themselves
+        let since = &diffs[3];
+        let path: Vec<&str> = since.path.iter().map(String::as_str).collect();
+        assert_eq!(crate::extract::<u64>(&b, &path).unwrap(), Some(11));
+        assert_eq!(b[since.right.unwrap()], tag::U64);
+
+        new.role = Some(Role::Follower);
+        let b = crate::to_bytes_with_config(&new, config).unwrap();
+        assert!(paths(&diff(&a, &b).unwrap()).contains(&("role".to_string(), DiffKind::Changed)));
+    }
+
+    #[test]
+    fn test_diff_tagged_and_typed() {
+        let config = Config::new().with_tagged_fields(true);
+        let mut new = state();
+        new.term = 4;
+        let a = crate::to_bytes_with_config(&state(), config).unwrap();
+        let b = crate::to_bytes_with_config(&new, config).unwrap();
+        let diffs = diff(&a, &b).unwrap();
+        assert_eq!(paths(&diffs), [("0".to_string(), DiffKind::Changed)]);
+        assert!(diffs[0].left.is_some());
+
+        let a = crate::to_bytes(&state()).unwrap();
+        let b = crate::to_bytes(&new).unwrap();
+        assert!(diff(&a, &b).is_err());
+        let diffs = diff_as::<State>(&a, &b).unwrap();
+        assert_eq!(paths(&diffs), [("term".to_string(), DiffKind::Changed)]);
+        assert_eq!(diffs[0].to_string(), "term: changed");
+    }
+
+    #[test]
+    fn test_diff_nesting_limit() {
+        let config = Config::new().with_self_describing(true);
+        let mut bytes = crate::to_bytes_with_config(&(), config).unwrap();
+        bytes.pop();
+        for _ in 0..100_000 {
+            bytes.extend_from_slice(&[tag::SEQ, 1]);
+        }
+        bytes.push(tag::UNIT);
+        assert!(matches!(diff(&bytes, &bytes), Err(Error::LimitExceeded { .. })));
+    }
+}
@@ -0,0 +1,671 @@
+//! Direct encoding without the serde trampoline
+//!
+//! [`Encode`] and [`Decode`] write and read values straight to the nanobit
+//! buffers. They are usually implemented with `#[derive(NanoBit)]` (the
+//! `derive` feature), which also understands per-field attributes for schema
+//! evolution:
+//!
+//! - `#[nanobit(tag = 3)]` pins a field's tag and switches the struct to
+//!   tagged fields (`#[nanobit(tagged)]` does the same using field indexes)
+//! - `#[nanobit(version = 2)]` on a struct writes a version number, and
+//!   `#[nanobit(since = 2)]` marks fields that older payloads do not contain
+//! - `#[nanobit(default)]` fills a field with `Default` when it is absent
+//! - `#[nanobit(skip)]` never encodes a field and decodes it as `Default`
+//! - `#[nanobit(packed)]` on a struct writes its `bool` fields and the
+//!   presence of its `Option` fields as a bitmap after the field count,
+//!   one bit each, so records with many flags take a fraction of the space
+//! - `#[nanobit(nullable)]` on a struct with named fields writes a bitmap
+//!   of one bit per field, the presence of an `Option` or the value of a
+//!   `bool`, followed by only the present values
+//! - `#[nanobit(discriminant = 7)]` on a variant, or an integer Rust
+//!   discriminant, writes the variant as that number instead of its
+//!   position, so variants can be reordered; later variants count on from
+//!   it as in Rust
+//! - `#[nanobit(other)]` on a unit variant decodes unknown discriminants as
+//!   that variant instead of failing, and `#[nanobit(tagged)]` on an enum
+//!   writes each payload's length so an unknown variant's data is skipped
+//!
+//! The encodings match the serde serializer: plain types are byte-for-byte
+//! identical to [`to_bytes`](crate::to_bytes), and tagged structs match
+//! [`Config::with_tagged_fields`](crate::Config::with_tagged_fields).
+//! Nullable structs match the [`Nullable`](crate::nullable::Nullable)
+//! wrapper; packed structs, tagged enums and explicit discriminants have no
+//! serde counterpart. Serde always writes a variant's position, and
+//! `#[serde(other)]` is its fallback for unknown positions.
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+
+/// A type that can write itself in the nanobit wire format
+pub trait Encode {
+    /// Append the encoded value to `buf`
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()>;
+}
+
+/// A type that can read itself from the nanobit wire format
+pub trait Decode<'de>: Sized {
+    /// Decode a value from `reader`, borrowing from its data where possible
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self>;
+}
+
+/// Encode a value to bytes, including the payload header
+pub fn encode_to_bytes<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Encode + ?Sized,
+{
+    let mut buffer = WriteBuffer::new();
+    value.encode(&mut buffer)?;
+
+    let header = Header::new(HeaderFlags::NONE);
+    let mut result = Vec::with_capacity(buffer.len() + header.encoded_len());
+    header.write_to(&mut result);
+    result.extend_from_slice(buffer.as_slice());
+    Ok(result)
+}
+
+/// Decode a value from bytes produced by [`encode_to_bytes`]
+pub fn decode_from_bytes<'de, T>(bytes: &'de [u8]) -> Result<T>
+where
+    T: Decode<'de>,
+{
+    let (_, body) = crate::checksum::split_payload(bytes)?;
+    let mut reader = ReadBuffer::new(body);
+    T::decode(&mut reader)
+}
+
+/// Encode one tagged field: tag, byte length, then the value
+///
+/// Used by derived implementations of tagged structs.
+pub fn encode_tagged_field<T>(buf: &mut WriteBuffer, tag: u64, value: &T) -> Result<()>
+where
+    T: Encode + ?Sized,
+{
+    buf.write_varint(tag)?;
+    let start = buf.reserve_varint();
+    value.encode(buf)?;
+    let len = buf.len() - start - 1;
+    buf.patch_varint(start, len as u64)
+}
+
+/// Decode a tagged field value that occupies exactly `len` bytes
+///
+/// Bytes the value does not consume (added by a newer schema) are skipped.
+/// Used by derived implementations of tagged structs.
+pub fn decode_tagged_field<'de, T>(reader: &mut ReadBuffer<'de>, len: usize) -> Result<T>
+where
+    T: Decode<'de>,
+{
+    let end = reader.position().saturating_add(len);
+    let value = T::decode(reader)?;
+    let position = reader.position();
+    if position > end {
+        return Err(Error::InvalidFormat(
+            "Tagged field value overran its length".to_string(),
+        ));
+    }
+    reader.skip(end - position)?;
+    Ok(value)
+}
+
+/// Error for a field absent from the payload that has no default
+pub fn missing_field(name: &str) -> Error {
+    Error::InvalidFormat(format!("Missing field `{name}`"))
+}
+
+/// Error for a struct or variant whose encoded field count is unexpected
+pub fn field_count_mismatch(expected: u64, actual: u64) -> Error {
+    Error::InvalidFormat(format!("Field count mismatch: expected {expected}, got {actual}"))
+}
+
+/// Error for a versioned payload written by a newer version of the type
+pub fn version_too_new(version: u64, supported: u64) -> Error {
+    Error::InvalidFormat(format!(
+        "Payload version {version} is newer than supported version {supported}"
+    ))
+}
+
+/// Error for an enum variant index that the type does not define
+pub fn unknown_variant(index: u64) -> Error {
+    Error::InvalidFormat(format!("Unknown variant index {index}"))
+}
+
+macro_rules! impl_primitive {
+    ($($ty:ty => $write:ident, $read:ident;)*) => {
+        $(
+            impl Encode for $ty {
+                #[inline]
+                fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+                    buf.$write(*self)
+                }
+            }
+
+            impl<'de> Decode<'de> for $ty {
+                #[inline]
+                fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+                    reader.$read()
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive! {
+    u8 => write_u8, read_u8;
+    u16 => write_u16, read_u16;
+    u32 => write_u32, read_u32;
+    u64 => write_u64, read_u64;
+    i8 => write_i8, read_i8;
+    i16 => write_i16, read_i16;
+    i32 => write_i32, read_i32;
+    i64 => write_i64, read_i64;
+    f32 => write_f32, read_f32;
+    f64 => write_f64, read_f64;
+}
+
+impl Encode for bool {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        buf.write_bool(*self)
+    }
+}
+
+impl<'de> Decode<'de> for bool {
+    #[inline]
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        reader.read_bool()
+    }
+}
+
+impl Encode for char {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        buf.write_char(*self)
+    }
+}
+
+impl<'de> Decode<'de> for char {
+    #[inline]
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        reader.read_char()
+    }
+}
+
+impl Encode for () {
+    #[inline]
+    fn encode(&self, _buf: &mut WriteBuffer) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'de> Decode<'de> for () {
+    #[inline]
+    fn decode(_reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl Encode for str {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        buf.write_str(self)
+    }
+}
+
+impl Encode for String {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        buf.write_str(self)
+    }
+}
+
+impl<'de> Decode<'de> for String {
+    #[inline]
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        reader.read_str().map(String::from)
+    }
+}
+
+impl<'de> Decode<'de> for &'de str {
+    #[inline]
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        reader.read_str()
+    }
+}
+
+impl<'de> Decode<'de> for &'de [u8] {
+    #[inline]
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        reader.read_byte_slice()
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        (**self).encode(buf)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        (**self).encode(buf)
+    }
+}
+
+impl<'de, T: Decode<'de>> Decode<'de> for Box<T> {
+    #[inline]
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        T::decode(reader).map(Box::new)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        buf.write_option(self.as_ref(), |buf, value| value.encode(buf))
+    }
+}
+
+impl<'de, T: Decode<'de>> Decode<'de> for Option<T> {
+    #[inline]
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        reader.read_option(T::decode)
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        buf.write_seq_header(self.len())?;
+        for item in self {
+            item.encode(buf)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        self.as_slice().encode(buf)
+    }
+}
+
+impl<'de, T: Decode<'de>> Decode<'de> for Vec<T> {
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        let len = reader.read_seq_header()?;
+        // Every element takes at least one byte unless it is zero-sized
+        let mut items = Vec::with_capacity(len.min(reader.remaining()));
+        for _ in 0..len {
+            items.push(T::decode(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    #[inline]
+    fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+        self.as_slice().encode(buf)
+    }
+}
+
+impl<'de, T: Decode<'de>, const N: usize> Decode<'de> for [T; N] {
+    fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+        let items: Vec<T> = Vec::decode(reader)?;
+        let len = items.len();
+        items.try_into().map_err(|_| {
+            Error::InvalidFormat(format!("Array length mismatch: expected {N}, got {len}"))
+        })
+    }
+}
+
+macro_rules! impl_tuple {
+    ($len:expr => $($name:ident $index:tt),+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+                buf.write_varint($len)?;
+                $(self.$index.encode(buf)?;)+
+                Ok(())
+            }
+        }
+
+        impl<'de, $($name: Decode<'de>),+> Decode<'de> for ($($name,)+) {
+            fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+                let len = reader.read_varint()?;
+                if len != $len {
+                    return Err(Error::InvalidFormat(format!(
+                        "Tuple length mismatch: expected {}, got {len}",
+                        $len
+                    )));
+                }
+                Ok(($($name::decode(reader)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(1 => A 0);
+impl_tuple!(2 => A 0, B 1);
+impl_tuple!(3 => A 0, B 1, C 2);
+impl_tuple!(4 => A 0, B 1, C 2, D 3);
+impl_tuple!(5 => A 0, B 1, C 2, D 3, E 4);
+impl_tuple!(6 => A 0, B 1, C 2, D 3, E 4, F 5);
+
+#[cfg(feature = "std")]
+mod std_impls {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+    use std::hash::{BuildHasher, Hash};
+
+    impl<K: Encode, V: Encode, S> Encode for HashMap<K, V, S> {
+        fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+            buf.write_map_header(self.len())?;
+            for (key, value) in self {
+                key.encode(buf)?;
+                value.encode(buf)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl<'de, K, V, S> Decode<'de> for HashMap<K, V, S>
+    where
+        K: Decode<'de> + Eq + Hash,
+        V: Decode<'de>,
+        S: BuildHasher + Default,
+    {
+        fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+            let len = reader.read_map_header()?;
+            let mut map = HashMap::with_capacity_and_hasher(len.min(reader.remaining()), S::default());
+            for _ in 0..len {
+                let key = K::decode(reader)?;
+                map.insert(key, V::decode(reader)?);
+            }
+            Ok(map)
+        }
+    }
+
+    impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+        fn encode(&self, buf: &mut WriteBuffer) -> Result<()> {
+            buf.write_map_header(self.len())?;
+            for (key, value) in self {
+                key.encode(buf)?;
+                value.encode(buf)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl<'de, K: Decode<'de> + Ord, V: Decode<'de>> Decode<'de> for BTreeMap<K, V> {
+        fn decode(reader: &mut ReadBuffer<'de>) -> Result<Self> {
+            let len = reader.read_map_header()?;
+            let mut map = BTreeMap::new();
+            for _ in 0..len {
+                let key = K::decode(reader)?;
+                map.insert(key, V::decode(reader)?);
+            }
+            Ok(map)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NanoBit;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(NanoBit, Serialize, Deserialize, Debug, PartialEq)]
+    struct Plain {
+        id: u64,
+        name: String,
+        scores: Vec<f32>,
+        parent: Option<Box<Plain>>,
+    }
+
+    #[derive(NanoBit, Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect(u32, u32),
+        Named { label: String, sides: u8 },
+    }
+
+    #[test]
+    fn test_derive_matches_serde_encoding() {
+        let value = Plain {
+            id: 7,
+            name: "root".to_string(),
+            scores: vec![1.5, 2.5],
+            parent: Some(Box::new(Plain {
+                id: 1,
+                name: "p".to_string(),
+                scores: vec![],
+                parent: None,
+            })),
+        };
+
+        let encoded = encode_to_bytes(&value).unwrap();
+        assert_eq!(encoded, crate::to_bytes(&value).unwrap());
+        assert_eq!(decode_from_bytes::<Plain>(&encoded).unwrap(), value);
+
+        for shape in [
+            Shape::Empty,
+            Shape::Circle(2.0),
+            Shape::Rect(3, 4),
+            Shape::Named { label: "tri".to_string(), sides: 3 },
+        ] {
+            let encoded = encode_to_bytes(&shape).unwrap();
+            assert_eq!(encoded, crate::to_bytes(&shape).unwrap());
+            assert_eq!(decode_from_bytes::<Shape>(&encoded).unwrap(), shape);
+        }
+    }
+
+    #[test]
+    fn test_derive_borrowed_fields() {
+        #[derive(NanoBit, Debug, PartialEq)]
+        struct View<'a> {
+            name: &'a str,
+            data: &'a [u8],
+        }
+
+        let original = View { name: "hello", data: b"raw" };
+        let encoded = encode_to_bytes(&original).unwrap();
+        let view: View<'_> = decode_from_bytes(&encoded).unwrap();
+        assert_eq!(view, original);
+
+        // The borrowed fields point into the encoded bytes
+        let range = encoded.as_ptr_range();
+        assert!(range.contains(&view.name.as_ptr()));
+        assert!(range.contains(&view.data.as_ptr()));
+    }
+
+    #[test]
+    fn test_derive_tagged_fields() {
+        #[derive(NanoBit, Serialize, Debug, PartialEq)]
+        #[nanobit(tagged)]
+        struct V1 {
+            id: u32,
+            name: String,
+        }
+
+        #[derive(NanoBit, Debug, PartialEq)]
+        struct V2 {
+            #[nanobit(tag = 0)]
+            id: u32,
+            #[nanobit(tag = 5, default)]
+            tags: Vec<String>,
+            #[nanobit(skip)]
+            cache: Option<u64>,
+        }
+
+        let v1 = V1 { id: 4, name: "four".to_string() };
+        let encoded = encode_to_bytes(&v1).unwrap();
+
+        // Same body as the serde serializer in tagged-field mode
+        let config = crate::Config::new().with_tagged_fields(true);
+        let serde_bytes = crate::to_bytes_with_config(&v1, config).unwrap();
+        assert_eq!(encoded[5..], serde_bytes[6..]);
+
+        // Unknown tag 1 skipped, missing tag 5 defaulted
+        let v2: V2 = decode_from_bytes(&encoded).unwrap();
+        assert_eq!(v2, V2 { id: 4, tags: vec![], cache: None });
+
+        // Missing field without a default is an error
+        let encoded = encode_to_bytes(&V2 { id: 1, tags: vec![], cache: Some(3) }).unwrap();
+        assert!(decode_from_bytes::<V1>(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_derive_versioned_struct() {
+        #[derive(NanoBit, Debug, PartialEq)]
+        #[nanobit(version = 1)]
+        struct ConfigV1 {
+            name: String,
+        }
+
+        #[derive(NanoBit, Debug, PartialEq)]
+        #[nanobit(version = 2)]
+        struct ConfigV2 {
+            name: String,
+            #[nanobit(since = 2, default)]
+            retries: u32,
+        }
+
+        let encoded = encode_to_bytes(&ConfigV1 { name: "a".to_string() }).unwrap();
+        let upgraded: ConfigV2 = decode_from_bytes(&encoded).unwrap();
+        assert_eq!(upgraded, ConfigV2 { name: "a".to_string(), retries: 0 });
+
+        let encoded = encode_to_bytes(&ConfigV2 { name: "b".to_string(), retries: 3 }).unwrap();
+        assert_eq!(decode_from_bytes::<ConfigV2>(&encoded).unwrap().retries, 3);
+        // Payloads newer than the type are rejected rather than misread
+        assert!(decode_from_bytes::<ConfigV1>(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_derive_packed_flags() {
+        #[derive(NanoBit, Debug, PartialEq, Default, Clone)]
+        #[nanobit(packed)]
+        struct Telemetry {
+            id: u32,
+            online: bool,
+            charging: bool,
+            battery: Option<u8>,
+            label: Option<String>,
+            alerts: [Option<bool>; 2],
+            #[nanobit(skip)]
+            cached: Option<u64>,
+            f0: bool, f1: bool, f2: bool, f3: bool, f4: bool, f5: bool,
+        }
+
+        let sparse = Telemetry { id: 9, charging: true, f5: true, ..Default::default() };
+        let dense = Telemetry {
+            battery: Some(80),
+            label: Some("rack-4".to_string()),
+            alerts: [Some(true), None],
+            ..sparse.clone()
+        };
+        for value in [sparse.clone(), dense] {
+            let encoded = encode_to_bytes(&value).unwrap();
+            assert_eq!(decode_from_bytes::<Telemetry>(&encoded).unwrap(), value);
+        }
+
+        // Ten flags fit in two bytes instead of one byte each
+        let encoded = encode_to_bytes(&sparse).unwrap();
+        assert_eq!(encoded.len(), 5 + 1 + 2 + 4 + 1 + 1 + 1);
+        assert!(decode_from_bytes::<Telemetry>(&encoded[..7]).is_err());
+    }
+
+    #[test]
+    fn test_derive_enum_discriminants() {
+        #[derive(NanoBit, Debug, PartialEq)]
+        enum Level {
+            Debug = 10,
+            Info,
+            Error = 40,
+        }
+
+        // Written at v1; v2 reordered the variants and added `Resize`
+        #[derive(NanoBit, Debug, PartialEq)]
+        #[nanobit(tagged)]
+        enum CommandV1<'a> {
+            #[nanobit(discriminant = 1)]
+            Open(&'a str),
+            #[nanobit(discriminant = 2)]
+            Close,
+            #[nanobit(other)]
+            Unknown,
+        }
+
+        #[derive(NanoBit, Debug, PartialEq)]
+        #[nanobit(tagged)]
+        enum CommandV2<'a> {
+            #[nanobit(discriminant = 3)]
+            Resize { width: u32, height: u32 },
+            #[nanobit(discriminant = 2)]
+            Close,
+            #[nanobit(discriminant = 1)]
+            Open(&'a str),
+        }
+
+        #[derive(NanoBit, Debug, PartialEq)]
+        enum Strict {
+            Close = 2,
+        }
+
+        let levels = encode_to_bytes(&[Level::Debug, Level::Info, Level::Error]).unwrap();
+        assert_eq!(&levels[6..], &[10, 11, 40]);
+
+        let open = encode_to_bytes(&CommandV2::Open("log")).unwrap();
+        assert_eq!(decode_from_bytes::<CommandV1<'_>>(&open).unwrap(), CommandV1::Open("log"));
+        let resize = encode_to_bytes(&(CommandV2::Resize { width: 4, height: 3 }, CommandV2::Close)).unwrap();
+        let decoded: (CommandV1<'_>, CommandV1<'_>) = decode_from_bytes(&resize).unwrap();
+        assert_eq!(decoded, (CommandV1::Unknown, CommandV1::Close));
+
+        // Without a fallback an unknown discriminant fails
+        let close = encode_to_bytes(&Level::Error).unwrap();
+        assert!(decode_from_bytes::<Strict>(&close).is_err());
+        assert_eq!(decode_from_bytes::<Strict>(&encode_to_bytes(&2u8).unwrap()).unwrap(), Strict::Close);
+    }
+
+    #[test]
+    fn test_derive_nullable_matches_serde() {
+        use crate::nullable::Nullable;
+
+        #[derive(NanoBit, Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+        #[nanobit(nullable)]
+        struct Listing {
+            id: u32,
+            title: Option<String>,
+            price: Option<u64>,
+            sold: bool,
+            tags: Vec<String>,
+            a: Option<u8>, b: Option<u8>, c: Option<u8>, d: Option<u8>, e: Option<u8>,
+            nested: Option<Option<u16>>,
+        }
+
+        let sparse = Listing { id: 3, sold: true, e: Some(1), ..Default::default() };
+        let dense = Listing {
+            title: Some("desk".to_string()),
+            price: Some(120),
+            tags: vec!["oak".to_string()],
+            nested: Some(None),
+            ..sparse.clone()
+        };
+        for value in [sparse.clone(), dense] {
+            let encoded = encode_to_bytes(&value).unwrap();
+            assert_eq!(encoded, crate::to_bytes(&Nullable(&value)).unwrap());
+            assert_eq!(decode_from_bytes::<Listing>(&encoded).unwrap(), value);
+            assert_eq!(crate::from_bytes::<Nullable<Listing>>(&encoded).unwrap().0, value);
+        }
+
+        // Eleven fields take a two-byte bitmap, then the id, tags and `e`
+        let encoded = encode_to_bytes(&sparse).unwrap();
+        assert_eq!(encoded.len(), 5 + 1 + 2 + 4 + 1 + 1);
+    }
+}
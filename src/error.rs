@@ -26,12 +26,69 @@ pub enum Error {
     /// Unsupported version
     UnsupportedVersion(u8),
     
+    /// Checksum trailer does not match the payload
+    ChecksumMismatch {
+        /// Checksum stored in the payload
+        expected: u64,
+        /// Checksum computed from the payload
+        actual: u64,
+    },
+
     /// Compression/decompression error
     Compression(String),
+
+    /// Input would exceed a configured size limit
+    LimitExceeded {
+        /// What was being limited
+        what: &'static str,
+        /// The limit that was exceeded
+        limit: usize,
+    },
     
+    /// Encryption or decryption failed
+    Crypto(String),
+
+    /// Conversion to or from an Arrow record batch failed
+    Arrow(String),
+
+    /// Payload was written for a different type than requested
+    TypeMismatch {
+        /// Type name the caller expected
+        expected: String,
+        /// Type name recorded in the payload, if any
+        found: Option<String>,
+    },
+
+    /// Payload was written for a type whose schema differs from the reader's
+    SchemaMismatch {
+        /// Schema hash of the type being decoded
+        expected: u32,
+        /// Schema hash recorded in the payload
+        found: u32,
+    },
+
+    /// A file to load does not exist
+    FileNotFound(String),
+
+    /// A file exists but does not hold a valid payload
+    CorruptFile {
+        /// Path of the file
+        path: String,
+        /// Why decoding failed
+        reason: Box<Error>,
+    },
+
     /// I/O operation failed
     Io(String),
     
+    /// A float was NaN or infinite while non-finite floats are denied
+    NonFiniteFloat {
+        /// The offending value, widened to `f64`
+        value: f64,
+        /// Dot-separated path to the value, empty at the top level
+        path: String,
+    },
+
     /// Serde serialization error
     Serde(String),
     
@@ -47,16 +104,56 @@ impl fmt::Display for Error {
             Error::BufferOverflow => write!(f, "Buffer overflow"),
             Error::NotEnoughData => write!(f, "Not enough data to read"),
             Error::UnsupportedVersion(v) => write!(f, "Unsupported version: {v}"),
+            Error::ChecksumMismatch { expected, actual } => {
+                write!(f, "Checksum mismatch: expected {expected:#x}, got {actual:#x}")
+            }
             Error::Compression(msg) => write!(f, "Compression error: {msg}"),
+            Error::LimitExceeded { what, limit } => write!(f, "{what} exceeds the limit of {limit}"),
+            Error::Crypto(msg) => write!(f, "Crypto error: {msg}"),
+            Error::Arrow(msg) => write!(f, "Arrow error: {msg}"),
+            Error::TypeMismatch { expected, found: Some(found) } => {
+                write!(f, "Type mismatch: expected {expected}, found {found}")
+            }
+            Error::TypeMismatch { expected, found: None } => {
+                write!(f, "Type mismatch: expected {expected}, found an untyped payload")
+            }
+            Error::SchemaMismatch { expected, found } => {
+                write!(f, "Schema mismatch: expected schema {expected:#010x}, payload has {found:#010x}")
+            }
+            Error::FileNotFound(path) => write!(f, "File not found: {path}"),
+            Error::CorruptFile { path, reason } => write!(f, "Corrupt file {path}: {reason}"),
             Error::Io(msg) => write!(f, "I/O error: {msg}"),
+            Error::NonFiniteFloat { value, path } if path.is_empty() => write!(f, "Non-finite float {value}"),
+            Error::NonFiniteFloat { value, path } => write!(f, "Non-finite float {value} at {path}"),
             Error::Serde(msg) => write!(f, "Serialization error: {msg}"),
             Error::Custom(msg) => write!(f, "Error: {msg}"),
         }
     }
 }
 
+impl Error {
+    // Prefix the path of a `NonFiniteFloat` with the part of the value it
+    // was found in; other errors pass through
+    pub(crate) fn within(self, part: impl fmt::Display) -> Self {
+        match self {
+            Error::NonFiniteFloat { value, path } if path.is_empty() => {
+                Error::NonFiniteFloat { value, path: part.to_string() }
+            }
+            Error::NonFiniteFloat { value, path } => Error::NonFiniteFloat { value, path: format!("{part}.{path}") },
+            other => other,
+        }
+    }
+}
+
 #[cfg(feature = "std")]
-impl StdError for Error {}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::CorruptFile { reason, .. } => Some(&**reason),
+            _ => None,
+        }
+    }
+}
 
 impl serde::ser::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
@@ -73,7 +170,12 @@ impl serde::de::Error for Error {
 #[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
-        Error::Io(err.to_string())
+        // Errors raised inside a reader or writer, such as a decompression
+        // limit, come back out unchanged
+        match err.get_ref().and_then(|inner| inner.downcast_ref::<Error>()) {
+            Some(inner) => inner.clone(),
+            None => Error::Io(err.to_string()),
+        }
     }
 }
 
@@ -0,0 +1,263 @@
+//! Partial deserialization of a single value by path
+//!
+//! Extraction walks the payload to the requested value and decodes only
+//! that value; everything before it is skipped without being decoded. This
+//! needs payloads that can be navigated without the type:
+//!
+//! - Self-describing payloads: segments are struct field names or map keys
+//!   (strings or integers), sequence indexes, or enum variant names.
+//!   `Some` is looked through on the way to the target.
+//! - Tagged-field payloads: segments are field tags, the declaration index
+//!   or numeric name of each field along the path.
+
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::ToString};
+
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::tag;
+
+/// Deserialize the value at `path`, or `None` if the path does not exist
+///
+/// ```rust
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct User { id: u64, name: String }
+/// #[derive(Serialize)]
+/// struct Event { user: User, tags: Vec<String> }
+///
+/// let event = Event {
+///     user: User { id: 7, name: "ann".into() },
+///     tags: vec!["a".into(), "b".into()],
+/// };
+/// let config = nanobit::Config::new().with_self_describing(true);
+/// let bytes = nanobit::to_bytes_with_config(&event, config)?;
+///
+/// assert_eq!(nanobit::extract::<u64>(&bytes, &["user", "id"])?, Some(7));
+/// assert_eq!(nanobit::extract::<&str>(&bytes, &["tags", "1"])?, Some("b"));
+/// assert_eq!(nanobit::extract::<u64>(&bytes, &["user", "age"])?, None);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn extract<'de, T>(bytes: &'de [u8], path: &[&str]) -> Result<Option<T>>
+where
+    T: Deserialize<'de>,
+{
+    extract_with_config(bytes, path, Config::new())
+}
+
+/// Deserialize the value at `path` using the given decoding options
+pub fn extract_with_config<'de, T>(bytes: &'de [u8], path: &[&str], config: Config) -> Result<Option<T>>
+where
+    T: Deserialize<'de>,
+{
+    let mut de = Deserializer::with_config(bytes, config)?;
+    if !path.is_empty() && !de.config().self_describing() && !de.config().tagged_fields() {
+        return Err(Error::InvalidFormat(
+            "Extracting by path needs a self-describing or tagged-field payload".to_string(),
+        ));
+    }
+
+    for segment in path {
+        let found = if de.config().self_describing() {
+            seek_described(&mut de, segment)?
+        } else {
+            seek_tagged(&mut de, segment)?
+        };
+        if !found {
+            return Ok(None);
+        }
+    }
+
+    if !path.is_empty() {
+        // The target is nested, never the top-level value
+        de.enter_compound();
+    }
+    T::deserialize(&mut de).map(Some)
+}
+
+// Move to the child `segment` of the self-describing value at the cursor
+fn seek_described(de: &mut Deserializer<'_>, segment: &str) -> Result<bool> {
+    loop {
+        match de.reader.read_u8()? {
+            tag::SOME => continue,
+            tag::MAP => {
+                let len = de.reader.read_varint()?;
+                for _ in 0..len {
+                    if key_matches(de, segment)? {
+                        return Ok(true);
+                    }
+                    de.skip_value()?;
+                }
+                return Ok(false);
+            }
+            tag::SEQ => {
+                let len = de.reader.read_varint()?;
+                let Ok(index) = segment.parse::<u64>() else {
+                    return Ok(false);
+                };
+                if index >= len {
+                    return Ok(false);
+                }
+                for _ in 0..index {
+                    de.skip_value()?;
+                }
+                return Ok(true);
+            }
+            tag::VARIANT => {
+                let index = de.reader.read_varint()?;
+                let name = de.reader.read_str()?;
+                return Ok(name == segment || segment.parse::<u64>() == Ok(index));
+            }
+            _ => return Ok(false),
+        }
+    }
+}
+
+// Read a map key, reporting whether it names `segment`
+fn key_matches(de: &mut Deserializer<'_>, segment: &str) -> Result<bool> {
+    let key = match de.reader.read_u8()? {
+        tag::STR => return Ok(de.reader.read_str()? == segment),
+        tag::U8 => de.reader.read_u8()? as i128,
+        tag::U16 => de.reader.read_u16()? as i128,
+        tag::U32 => de.reader.read_u32()? as i128,
+        tag::U64 => de.reader.read_u64()? as i128,
+        tag::I8 => de.reader.read_i8()? as i128,
+        tag::I16 => de.reader.read_i16()? as i128,
+        tag::I32 => de.reader.read_i32()? as i128,
+        tag::I64 => de.reader.read_i64()? as i128,
+        other => {
+            // Keys of other types never match a path segment
+            skip_tagged_value(de, other)?;
+            return Ok(false);
+        }
+    };
+    Ok(segment.parse::<i128>() == Ok(key))
+}
+
+// Skip the rest of a self-describing value whose tag was already read
+fn skip_tagged_value(de: &mut Deserializer<'_>, found: u8) -> Result<()> {
+    if let Some(size) = tag::fixed_size(found) {
+        return de.reader.skip(size);
+    }
+    match found {
+        tag::SOME => de.skip_value(),
+        tag::BYTES => {
+            let len = de.reader.read_varint()? as usize;
+            de.reader.skip(len)
+        }
+        tag::SEQ => {
+            for _ in 0..de.reader.read_varint()? {
+                de.skip_value()?;
+            }
+            Ok(())
+        }
+        tag::MAP => {
+            for _ in 0..de.reader.read_varint()? {
+                de.skip_value()?;
+                de.skip_value()?;
+            }
+            Ok(())
+        }
+        tag::VARIANT => {
+            de.reader.read_varint()?;
+            de.reader.read_str()?;
+            de.skip_value()
+        }
+        other => Err(Error::InvalidFormat(format!("Unknown type tag: {other:#04x}"))),
+    }
+}
+
+// Move to the field tagged `segment` of the tagged struct at the cursor
+fn seek_tagged(de: &mut Deserializer<'_>, segment: &str) -> Result<bool> {
+    let wanted = segment
+        .parse::<u64>()
+        .map_err(|_| Error::InvalidFormat(format!("Path segment `{segment}` is not a field tag")))?;
+    let count = de.reader.read_varint()?;
+    for _ in 0..count {
+        let tag = de.reader.read_varint()?;
+        let len = de.reader.read_varint()? as usize;
+        if tag == wanted {
+            return Ok(true);
+        }
+        de.reader.skip(len)?;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct User {
+        id: u64,
+        name: String,
+        email: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    enum Payload {
+        Point { x: i32, y: i32 },
+    }
+
+    #[derive(Serialize)]
+    struct Record {
+        users: Vec<User>,
+        scores: BTreeMap<u32, f64>,
+        payload: Payload,
+    }
+
+    fn record() -> Record {
+        Record {
+            users: vec![
+                User { id: 1, name: "ann".to_string(), email: None },
+                User { id: 2, name: "bob".to_string(), email: Some("bob@example.com".to_string()) },
+            ],
+            scores: BTreeMap::from([(10, 0.5), (20, 1.5)]),
+            payload: Payload::Point { x: 3, y: -4 },
+        }
+    }
+
+    #[test]
+    fn test_extract_self_describing() {
+        let bytes = crate::to_bytes_with_config(&record(), Config::new().with_self_describing(true)).unwrap();
+
+        assert_eq!(extract::<&str>(&bytes, &["users", "1", "name"]).unwrap(), Some("bob"));
+        assert_eq!(extract::<Option<&str>>(&bytes, &["users", "1", "email"]).unwrap(), Some(Some("bob@example.com")));
+        assert_eq!(extract::<f64>(&bytes, &["scores", "20"]).unwrap(), Some(1.5));
+        assert_eq!(extract::<i32>(&bytes, &["payload", "Point", "y"]).unwrap(), Some(-4));
+
+        assert_eq!(extract::<u64>(&bytes, &["users", "5", "id"]).unwrap(), None);
+        assert_eq!(extract::<i32>(&bytes, &["payload", "Circle", "r"]).unwrap(), None);
+        assert_eq!(extract::<u64>(&bytes, &["users", "0", "id", "x"]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_extract_tagged_fields() {
+        #[derive(Serialize)]
+        struct Session {
+            token: u64,
+            user: User,
+        }
+
+        let session = Session {
+            token: 99,
+            user: User { id: 3, name: "cy".to_string(), email: None },
+        };
+        let bytes = crate::to_bytes_with_config(&session, Config::new().with_tagged_fields(true)).unwrap();
+        assert_eq!(extract::<u64>(&bytes, &["0"]).unwrap(), Some(99));
+        assert_eq!(extract::<&str>(&bytes, &["1", "1"]).unwrap(), Some("cy"));
+        assert_eq!(extract::<Option<String>>(&bytes, &["1", "2"]).unwrap(), Some(None));
+        assert_eq!(extract::<u64>(&bytes, &["7"]).unwrap(), None);
+        assert!(extract::<u64>(&bytes, &["token"]).is_err());
+
+        let plain = crate::to_bytes(&session).unwrap();
+        assert!(extract::<u64>(&plain, &["0"]).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+//! Compact encoding for bit flags and enum sets
+//!
+//! bitflags and enumset write themselves to binary formats as their full
+//! underlying integer, which nanobit writes at fixed width, so a `u64`
+//! flag set takes 8 bytes even when only the lowest flag is set. The
+//! [`CompactFlags`] and [`CompactEnumSet`] wrappers, or the
+//! [`bitflags`](self::bitflags) and [`enumset`](self::enumset) modules in
+//! `#[serde(with)]`, write the bits as a varint instead:
+//!
+//! ```rust
+//! # #[cfg(feature = "bitflags")] {
+//! use serde::{Deserialize, Serialize};
+//!
+//! bitflags::bitflags! {
+//!     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//!     struct Permissions: u64 {
+//!         const READ = 1;
+//!         const WRITE = 1 << 1;
+//!         const ADMIN = 1 << 40;
+//!     }
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Grant {
+//!     user: u32,
+//!     #[serde(with = "nanobit::flags::bitflags")]
+//!     permissions: Permissions,
+//! }
+//!
+//! let grant = Grant { user: 7, permissions: Permissions::READ | Permissions::WRITE };
+//! let bytes = nanobit::to_bytes(&grant)?;
+//! assert!(bytes.len() < nanobit::to_bytes(&(7u32, 3u64))?.len());
+//! assert_eq!(nanobit::from_bytes::<Grant>(&bytes)?, grant);
+//! # }
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! `CompactFlags` needs the `bitflags` feature and covers flag types whose
+//! bits are `u8` to `u64`. `CompactEnumSet` needs the `enumset` feature
+//! and covers enums with up to 64 variants.
+//!
+//! # Unknown bits
+//!
+//! A payload may hold bits the reading type does not define, written by a
+//! newer version with more flags or simply corrupt. The wrappers and the
+//! `bitflags` and `enumset` modules reject them. Their `truncate`
+//! submodules drop them instead, for readers that should tolerate flags
+//! added later:
+//!
+//! ```rust
+//! # #[cfg(feature = "bitflags")] {
+//! # use serde::{Deserialize, Serialize};
+//! # bitflags::bitflags! {
+//! #     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//! #     struct Permissions: u64 { const READ = 1; const WRITE = 1 << 1; }
+//! # }
+//! #[derive(Serialize, Deserialize)]
+//! struct Grant {
+//!     #[serde(with = "nanobit::flags::bitflags::truncate")]
+//!     permissions: Permissions,
+//! }
+//!
+//! // Bit 2 is a flag this version does not know about
+//! let bytes = nanobit::to_bytes(&Grant { permissions: Permissions::from_bits_retain(0b101) })?;
+//! assert_eq!(nanobit::from_bytes::<Grant>(&bytes)?.permissions, Permissions::READ);
+//! # }
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! # Representation
+//!
+//! A flag set is a byte string holding its bits as a single varint, so up
+//! to 7 flags in the lowest bits take 2 bytes. bitflags and enumset types
+//! with the same bits share one representation.
+
+use core::fmt;
+
+use serde::de::{Deserializer, SeqAccess, Visitor};
+use serde::ser::{Error as _, Serializer};
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+
+// Write `bits` as a byte string holding one varint
+fn serialize_bits<S: Serializer>(bits: u64, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut out = WriteBuffer::with_capacity(10);
+    out.write_varint(bits).map_err(S::Error::custom)?;
+    serializer.serialize_bytes(out.as_slice())
+}
+
+// Read the bits written by `serialize_bits`
+fn deserialize_bits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    struct BitsVisitor;
+
+    impl<'de> Visitor<'de> for BitsVisitor {
+        type Value = u64;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a byte string holding varint flag bits")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<u64, E> {
+            let mut input = ReadBuffer::new(bytes);
+            let bits = input.read_varint().map_err(E::custom)?;
+            if input.position() != bytes.len() {
+                return Err(E::invalid_length(bytes.len(), &self));
+            }
+            Ok(bits)
+        }
+
+        // Self-describing payloads written by the crates' own serde impls
+        fn visit_u64<E: serde::de::Error>(self, bits: u64) -> Result<u64, E> {
+            Ok(bits)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<u64, A::Error> {
+            let mut bytes = [0u8; 10];
+            let mut len = 0;
+            while let Some(byte) = seq.next_element()? {
+                *bytes.get_mut(len).ok_or_else(|| serde::de::Error::invalid_length(len + 1, &self))? = byte;
+                len += 1;
+            }
+            self.visit_bytes(&bytes[..len])
+        }
+    }
+
+    deserializer.deserialize_bytes(BitsVisitor)
+}
+
+fn unknown_bits<E: serde::de::Error>(bits: u64) -> E {
+    E::custom(format_args!("Unknown flag bits in {bits:#x}"))
+}
+
+/// A bitflags value written as a varint
+///
+/// Rejects unknown bits on decode; see the [module documentation](self).
+///
+/// ```rust
+/// use nanobit::flags::CompactFlags;
+///
+/// bitflags::bitflags! {
+///     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///     struct Mode: u32 { const FAST = 1; const SAFE = 2; }
+/// }
+///
+/// let bytes = nanobit::to_bytes(&CompactFlags(Mode::all()))?;
+/// assert_eq!(nanobit::from_bytes::<CompactFlags<Mode>>(&bytes)?.0, Mode::all());
+/// assert!(nanobit::from_bytes::<CompactFlags<Mode>>(&nanobit::to_bytes(&CompactFlags(Mode::from_bits_retain(4)))?).is_err());
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[cfg(feature = "bitflags")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactFlags<T>(pub T);
+
+#[cfg(feature = "bitflags")]
+impl<T> serde::Serialize for CompactFlags<T>
+where
+    T: ::bitflags::Flags,
+    T::Bits: Into<u64>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self::bitflags::serialize(&self.0, serializer)
+    }
+}
+
+#[cfg(feature = "bitflags")]
+impl<'de, T> serde::Deserialize<'de> for CompactFlags<T>
+where
+    T: ::bitflags::Flags,
+    T::Bits: TryFrom<u64>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self::bitflags::deserialize(deserializer).map(CompactFlags)
+    }
+}
+
+/// Use [`CompactFlags`] for a bitflags field with `#[serde(with)]`
+#[cfg(feature = "bitflags")]
+pub mod bitflags {
+    use ::bitflags::Flags;
+    use serde::{Deserializer, Serializer};
+
+    /// Serialize flags as a varint of their bits
+    pub fn serialize<T, S>(flags: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Flags,
+        T::Bits: Into<u64>,
+        S: Serializer,
+    {
+        super::serialize_bits(flags.bits().into(), serializer)
+    }
+
+    /// Deserialize flags, rejecting bits `T` does not define
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Flags,
+        T::Bits: TryFrom<u64>,
+        D: Deserializer<'de>,
+    {
+        let bits = super::deserialize_bits(deserializer)?;
+        T::Bits::try_from(bits).ok().and_then(T::from_bits).ok_or_else(|| super::unknown_bits(bits))
+    }
+
+    /// Like [`bitflags`](super::bitflags), but drops unknown bits on decode
+    pub mod truncate {
+        use super::*;
+
+        pub use super::serialize;
+
+        /// Deserialize flags, dropping bits `T` does not define
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+        where
+            T: Flags,
+            T::Bits: Into<u64> + TryFrom<u64>,
+            D: Deserializer<'de>,
+        {
+            let bits = super::super::deserialize_bits(deserializer)? & T::all().bits().into();
+            let bits = T::Bits::try_from(bits).map_err(|_| super::super::unknown_bits(bits))?;
+            Ok(T::from_bits_truncate(bits))
+        }
+    }
+}
+
+/// An enum set written as a varint
+///
+/// Rejects unknown bits on decode; see the [module documentation](self).
+///
+/// ```rust
+/// use enumset::{EnumSet, EnumSetType};
+/// use nanobit::flags::CompactEnumSet;
+///
+/// #[derive(EnumSetType, Debug)]
+/// enum Weekday { Mon, Tue, Wed, Thu, Fri, Sat, Sun }
+///
+/// let weekend = Weekday::Sat | Weekday::Sun;
+/// let bytes = nanobit::to_bytes(&CompactEnumSet(weekend))?;
+/// assert_eq!(nanobit::from_bytes::<CompactEnumSet<Weekday>>(&bytes)?.0, weekend);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[cfg(feature = "enumset")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactEnumSet<T: ::enumset::EnumSetType>(pub ::enumset::EnumSet<T>);
+
+#[cfg(feature = "enumset")]
+impl<T: ::enumset::EnumSetType> serde::Serialize for CompactEnumSet<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self::enumset::serialize(&self.0, serializer)
+    }
+}
+
+#[cfg(feature = "enumset")]
+impl<'de, T: ::enumset::EnumSetType> serde::Deserialize<'de> for CompactEnumSet<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self::enumset::deserialize(deserializer).map(CompactEnumSet)
+    }
+}
+
+/// Use [`CompactEnumSet`] for an `EnumSet` field with `#[serde(with)]`
+#[cfg(feature = "enumset")]
+pub mod enumset {
+    use ::enumset::{EnumSet, EnumSetType};
+    use serde::ser::Error as _;
+    use serde::{Deserializer, Serializer};
+
+    /// Serialize a set as a varint of its bits
+    ///
+    /// Fails for enums with variants beyond bit 63.
+    pub fn serialize<T, S>(set: &EnumSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: EnumSetType,
+        S: Serializer,
+    {
+        let bits = set.try_as_u64().ok_or_else(|| S::Error::custom("EnumSet holds variants beyond bit 63"))?;
+        super::serialize_bits(bits, serializer)
+    }
+
+    /// Deserialize a set, rejecting bits that are not variants of `T`
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<EnumSet<T>, D::Error>
+    where
+        T: EnumSetType,
+        D: Deserializer<'de>,
+    {
+        let bits = super::deserialize_bits(deserializer)?;
+        EnumSet::try_from_u64(bits).ok_or_else(|| super::unknown_bits(bits))
+    }
+
+    /// Like [`enumset`](super::enumset), but drops unknown bits on decode
+    pub mod truncate {
+        use super::*;
+
+        pub use super::serialize;
+
+        /// Deserialize a set, dropping bits that are not variants of `T`
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<EnumSet<T>, D::Error>
+        where
+            T: EnumSetType,
+            D: Deserializer<'de>,
+        {
+            super::super::deserialize_bits(deserializer).map(EnumSet::from_u64_truncated)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[cfg(feature = "bitflags")]
+    ::bitflags::bitflags! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        struct Small: u8 {
+            const A = 1;
+            const B = 1 << 3;
+        }
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        struct Wide: u64 {
+            const LOW = 1;
+            const HIGH = 1 << 63;
+        }
+    }
+
+    #[cfg(feature = "bitflags")]
+    #[test]
+    fn test_compact_flags_roundtrip() {
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            for flags in [Wide::empty(), Wide::LOW, Wide::HIGH, Wide::all()] {
+                let bytes = crate::to_bytes_with_config(&CompactFlags(flags), config).unwrap();
+                assert_eq!(crate::from_bytes::<CompactFlags<Wide>>(&bytes).unwrap().0, flags);
+            }
+            let bytes = crate::to_bytes_with_config(&CompactFlags(Small::all()), config).unwrap();
+            assert_eq!(crate::from_bytes::<CompactFlags<Small>>(&bytes).unwrap().0, Small::all());
+        }
+
+        // Self-describing payloads holding the plain integer still decode
+        let bytes = crate::to_bytes_with_config(&9u8, Config::new().with_self_describing(true)).unwrap();
+        assert_eq!(crate::from_bytes::<CompactFlags<Small>>(&bytes).unwrap().0, Small::all());
+
+        // Spelled out: a one-byte string holding the varint 1
+        let bytes = crate::to_bytes(&CompactFlags(Wide::LOW)).unwrap();
+        assert!(bytes.ends_with(&[1, 1]));
+        assert!(bytes.len() + 6 <= crate::to_bytes(&1u64).unwrap().len());
+    }
+
+    #[cfg(feature = "bitflags")]
+    #[test]
+    fn test_unknown_flag_bits() {
+        #[derive(serde::Deserialize)]
+        struct Lenient(#[serde(with = "self::bitflags::truncate")] Small);
+
+        for bits in [0b10u64, 0b1_0000_1001, 1 << 40] {
+            let bytes = crate::to_bytes(&CompactFlags(Wide::from_bits_retain(bits))).unwrap();
+            assert!(crate::from_bytes::<CompactFlags<Small>>(&bytes).is_err());
+            let Lenient(flags) = crate::from_bytes(&bytes).unwrap();
+            assert_eq!(flags, Small::from_bits_truncate(bits as u8));
+        }
+
+        // Without type tags a byte string is written like a `Vec<u8>`
+        let decode = |bytes: &[u8]| crate::from_bytes::<CompactFlags<Small>>(&crate::to_bytes(&bytes.to_vec()).unwrap());
+        assert!(decode(&[9]).is_ok());
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[9, 0]).is_err());
+        assert!(decode(&[0x89]).is_err());
+    }
+
+    #[cfg(feature = "enumset")]
+    #[test]
+    fn test_compact_enum_set() {
+        use ::enumset::{EnumSet, EnumSetType};
+
+        #[derive(EnumSetType, Debug)]
+        enum Old {
+            A,
+            B,
+        }
+
+        #[derive(EnumSetType, Debug)]
+        enum New {
+            A,
+            B,
+            C,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Lenient(#[serde(with = "self::enumset::truncate")] EnumSet<Old>);
+
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let set = New::A | New::C;
+            let bytes = crate::to_bytes_with_config(&CompactEnumSet(set), config).unwrap();
+            assert_eq!(crate::from_bytes::<CompactEnumSet<New>>(&bytes).unwrap().0, set);
+            assert!(crate::from_bytes::<CompactEnumSet<Old>>(&bytes).is_err());
+            let Lenient(old) = crate::from_bytes(&bytes).unwrap();
+            assert_eq!(old, EnumSet::only(Old::A));
+        }
+    }
+
+    #[cfg(all(feature = "bitflags", feature = "enumset"))]
+    #[test]
+    fn test_flag_types_interchangeable() {
+        #[derive(::enumset::EnumSetType, Debug)]
+        enum Bit {
+            Zero,
+            One,
+            Two,
+            Three,
+        }
+
+        let bytes = crate::to_bytes(&CompactFlags(Small::A | Small::B)).unwrap();
+        assert_eq!(crate::from_bytes::<CompactEnumSet<Bit>>(&bytes).unwrap().0, Bit::Zero | Bit::Three);
+    }
+}
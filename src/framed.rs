@@ -0,0 +1,478 @@
+//! Typed message channels over async byte streams
+//!
+//! [`MessageSink`] and [`MessageStream`] carry one value per frame:
+//!
+//! ```text
+//! [length: u32 LE] [payload]
+//! ```
+//!
+//! where the payload is a complete nanobit payload with its own header. A
+//! sink and a stream over the two halves of a socket make a typed
+//! protocol. The types here work with tokio; [`crate::futures`] has the same
+//! adapters for `futures-io` readers and writers.
+
+use core::task::{ready, Poll};
+
+use std::io;
+
+use serde::de::DeserializeOwned;
+#[cfg(any(feature = "async", feature = "futures-io"))]
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// Bytes of framing before each payload
+pub const FRAME_HEADER_LEN: usize = 4;
+
+/// Largest frame a [`MessageStream`] accepts unless configured otherwise
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+// Buffered output at which a sink writes before accepting more
+#[cfg(any(feature = "async", feature = "futures-io"))]
+const SINK_HIGH_WATER: usize = 64 * 1024;
+
+// Smallest read into a stream's buffer
+const READ_CHUNK: usize = 8 * 1024;
+
+// Frames encoded by a sink but not yet written
+#[cfg(any(feature = "async", feature = "futures-io"))]
+pub(crate) struct FrameWriter {
+    config: Config,
+    buffer: Vec<u8>,
+    written: usize,
+}
+
+#[cfg(any(feature = "async", feature = "futures-io"))]
+impl FrameWriter {
+    pub(crate) fn new(config: Config) -> Self {
+        Self {
+            config,
+            buffer: Vec::new(),
+            written: 0,
+        }
+    }
+
+    // Whether enough output is buffered to write it before accepting more
+    pub(crate) fn is_full(&self) -> bool {
+        self.buffer.len() - self.written >= SINK_HIGH_WATER
+    }
+
+    pub(crate) fn push<T: Serialize>(&mut self, item: &T) -> Result<()> {
+        let payload = crate::to_bytes_with_config(item, self.config)?;
+        let len = u32::try_from(payload.len()).map_err(|_| Error::LimitExceeded {
+            what: "Frame length",
+            limit: u32::MAX as usize,
+        })?;
+        self.buffer.extend_from_slice(&len.to_le_bytes());
+        self.buffer.extend_from_slice(&payload);
+        Ok(())
+    }
+
+    // Write out everything buffered with `write`
+    pub(crate) fn poll_write(
+        &mut self,
+        mut write: impl FnMut(&[u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<Result<()>> {
+        while self.written < self.buffer.len() {
+            let n = ready!(write(&self.buffer[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(Error::Io("Writer accepted no bytes".to_string())));
+            }
+            self.written += n;
+        }
+        self.buffer.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Input read by a stream but not yet decoded
+pub(crate) struct FrameReader {
+    config: Config,
+    max_frame_len: usize,
+    buffer: Vec<u8>,
+    // Start of the unconsumed part of `buffer`
+    start: usize,
+    done: bool,
+}
+
+impl FrameReader {
+    pub(crate) fn new(config: Config) -> Self {
+        Self {
+            config,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            buffer: Vec::new(),
+            start: 0,
+            done: false,
+        }
+    }
+
+    pub(crate) fn set_max_frame_len(&mut self, max_frame_len: usize) {
+        self.max_frame_len = max_frame_len;
+    }
+
+    // The next complete frame's payload range, or how many bytes it needs
+    fn next_frame(&self) -> Result<core::result::Result<(usize, usize), usize>> {
+        let available = &self.buffer[self.start..];
+        let Some(header) = available.first_chunk::<FRAME_HEADER_LEN>() else {
+            return Ok(Err(FRAME_HEADER_LEN));
+        };
+        let len = u32::from_le_bytes(*header) as usize;
+        if len > self.max_frame_len {
+            return Err(Error::LimitExceeded {
+                what: "Frame length",
+                limit: self.max_frame_len,
+            });
+        }
+        let payload = self.start + FRAME_HEADER_LEN;
+        if available.len() < FRAME_HEADER_LEN + len {
+            return Ok(Err(FRAME_HEADER_LEN + len));
+        }
+        Ok(Ok((payload, payload + len)))
+    }
+
+    // Read more input with `read` so that `needed` bytes are buffered;
+    // returns false at the end of input
+    fn poll_fill(
+        &mut self,
+        needed: usize,
+        read: &mut impl FnMut(&mut [u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<Result<bool>> {
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        let filled = self.buffer.len();
+        self.buffer.resize(filled + (needed - filled).max(READ_CHUNK), 0);
+        let result = read(&mut self.buffer[filled..]);
+        let n = match &result {
+            Poll::Ready(Ok(n)) => *n,
+            _ => 0,
+        };
+        self.buffer.truncate(filled + n);
+        ready!(result)?;
+        Poll::Ready(Ok(n > 0))
+    }
+
+    // Decode the next frame, reading input with `read` as needed
+    pub(crate) fn poll_next<T: DeserializeOwned>(
+        &mut self,
+        mut read: impl FnMut(&mut [u8]) -> Poll<io::Result<usize>>,
+    ) -> Poll<Option<Result<T>>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+            match self.next_frame() {
+                Ok(Ok((start, end))) => {
+                    self.start = end;
+                    let value = crate::from_bytes_with_config(&self.buffer[start..end], self.config);
+                    return Poll::Ready(Some(value));
+                }
+                Ok(Err(needed)) => match ready!(self.poll_fill(needed, &mut read)) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        self.done = true;
+                        if self.start < self.buffer.len() {
+                            return Poll::Ready(Some(Err(Error::UnexpectedEof)));
+                        }
+                    }
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                },
+                // The stream cannot find the next frame after a bad length
+                Err(e) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use tokio_io::{into_message_sink, into_message_stream, MessageSink, MessageStream};
+
+#[cfg(feature = "async")]
+mod tokio_io {
+    use super::*;
+    use core::marker::PhantomData;
+    use core::pin::Pin;
+    use core::task::Context;
+
+    use futures_core::Stream;
+    use futures_util::Sink;
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    /// Create a sink that writes each value as a frame to `writer`
+    ///
+    /// ```rust
+    /// use futures_util::{SinkExt, StreamExt};
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> nanobit::Result<()> {
+    /// let (client, server) = tokio::io::duplex(1024);
+    /// let mut requests = nanobit::into_message_sink::<(u32, String)>(client);
+    /// let mut incoming = nanobit::into_message_stream::<(u32, String)>(server);
+    ///
+    /// requests.send((1, "ping".to_string())).await?;
+    /// assert_eq!(incoming.next().await.transpose()?, Some((1, "ping".to_string())));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_message_sink<T>(writer: impl AsyncWrite + Unpin) -> MessageSink<impl AsyncWrite + Unpin, T> {
+        MessageSink::new(writer)
+    }
+
+    /// Create a stream that reads each frame from `reader` as a value
+    pub fn into_message_stream<T>(reader: impl AsyncRead + Unpin) -> MessageStream<impl AsyncRead + Unpin, T> {
+        MessageStream::new(reader)
+    }
+
+    /// A [`Sink`] of values framed onto a tokio writer
+    pub struct MessageSink<W, T> {
+        writer: W,
+        frames: FrameWriter,
+        _marker: PhantomData<fn(T)>,
+    }
+
+    impl<W: AsyncWrite + Unpin, T> MessageSink<W, T> {
+        /// Create a sink writing to `writer`
+        pub fn new(writer: W) -> Self {
+            Self::with_config(writer, Config::new())
+        }
+
+        /// Create a sink encoding values with the given options
+        pub fn with_config(writer: W, config: Config) -> Self {
+            Self {
+                writer,
+                frames: FrameWriter::new(config),
+                _marker: PhantomData,
+            }
+        }
+
+        /// Get a reference to the inner writer
+        pub fn get_ref(&self) -> &W {
+            &self.writer
+        }
+
+        /// Return the inner writer, dropping any output not yet flushed
+        pub fn into_inner(self) -> W {
+            self.writer
+        }
+
+        fn poll_write_frames(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            let writer = &mut self.writer;
+            self.frames.poll_write(|buf| Pin::new(&mut *writer).poll_write(cx, buf))
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin, T: Serialize> Sink<T> for MessageSink<W, T> {
+        type Error = Error;
+
+        fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            let this = self.get_mut();
+            if this.frames.is_full() {
+                return this.poll_write_frames(cx);
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, item: T) -> Result<()> {
+            self.get_mut().frames.push(&item)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            let this = self.get_mut();
+            ready!(this.poll_write_frames(cx))?;
+            Pin::new(&mut this.writer).poll_flush(cx).map_err(Error::from)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            ready!(self.as_mut().poll_flush(cx))?;
+            Pin::new(&mut self.get_mut().writer).poll_shutdown(cx).map_err(Error::from)
+        }
+    }
+
+    /// A [`Stream`] of values read from frames on a tokio reader
+    ///
+    /// A frame that fails to decode yields an error and the stream moves on to
+    /// the next one. A frame over the length limit yields
+    /// [`Error::LimitExceeded`] and ends the stream, since what follows cannot
+    /// be trusted. The stream also ends with the reader; ending inside a frame
+    /// yields [`Error::UnexpectedEof`] first.
+    ///
+    /// # Cancel safety
+    ///
+    /// Input is kept in the stream until a whole frame has arrived, so
+    /// dropping a pending `next()`, for example in `tokio::select!` or on a
+    /// timeout, loses nothing and the next call picks up where it left off.
+    /// [`from_reader_async`](crate::from_reader_async) has no such state and
+    /// should not be cancelled.
+    pub struct MessageStream<R, T> {
+        reader: R,
+        frames: FrameReader,
+        _marker: PhantomData<fn() -> T>,
+    }
+
+    impl<R: AsyncRead + Unpin, T> MessageStream<R, T> {
+        /// Create a stream reading from `reader`
+        pub fn new(reader: R) -> Self {
+            Self::with_config(reader, Config::new())
+        }
+
+        /// Create a stream decoding values with the given options
+        pub fn with_config(reader: R, config: Config) -> Self {
+            Self {
+                reader,
+                frames: FrameReader::new(config),
+                _marker: PhantomData,
+            }
+        }
+
+        /// Refuse frames longer than `max_frame_len` bytes
+        pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+            self.frames.set_max_frame_len(max_frame_len);
+            self
+        }
+
+        /// Get a reference to the inner reader
+        pub fn get_ref(&self) -> &R {
+            &self.reader
+        }
+
+        /// Return the inner reader, dropping any buffered input
+        pub fn into_inner(self) -> R {
+            self.reader
+        }
+    }
+
+    impl<R: AsyncRead + Unpin, T: DeserializeOwned> Stream for MessageStream<R, T> {
+        type Item = Result<T>;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<T>>> {
+            let this = self.get_mut();
+            let reader = &mut this.reader;
+            this.frames.poll_next(|buf| {
+                let mut read_buf = ReadBuf::new(buf);
+                ready!(Pin::new(&mut *reader).poll_read(cx, &mut read_buf))?;
+                Poll::Ready(Ok(read_buf.filled().len()))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "async")]
+    use futures_util::{FutureExt, SinkExt, StreamExt};
+    use serde::{Deserialize, Serialize};
+    #[cfg(feature = "async")]
+    use tokio::io::AsyncWriteExt;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    enum Message {
+        Hello { name: String },
+        Data(Vec<u8>),
+        Bye,
+    }
+
+    fn framed_bytes(message: &Message) -> Vec<u8> {
+        let payload = crate::to_bytes(message).unwrap();
+        [&(payload.len() as u32).to_le_bytes()[..], &payload].concat()
+    }
+
+    // Decode every frame in `input`, reading a byte at a time and stalling
+    // before every other read
+    fn decode_all(input: &[u8], max_frame_len: usize) -> Vec<Result<Message>> {
+        let mut frames = FrameReader::new(Config::new());
+        frames.set_max_frame_len(max_frame_len);
+        let mut input = input;
+        let mut stalled = false;
+        let mut decoded = Vec::new();
+        loop {
+            let next = frames.poll_next(|buf| {
+                stalled = !stalled;
+                if stalled {
+                    return Poll::Pending;
+                }
+                let n = input.len().min(buf.len()).min(1);
+                buf[..n].copy_from_slice(&input[..n]);
+                input = &input[n..];
+                Poll::Ready(Ok(n))
+            });
+            match next {
+                Poll::Ready(Some(item)) => decoded.push(item),
+                Poll::Ready(None) => return decoded,
+                Poll::Pending => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame_reader() {
+        let messages = [Message::Hello { name: "split".to_string() }, Message::Data(vec![1; 300]), Message::Bye];
+        let input = messages.iter().flat_map(framed_bytes).collect::<Vec<_>>();
+
+        // Frames split across many reads come out whole
+        let decoded = decode_all(&input, DEFAULT_MAX_FRAME_LEN);
+        assert_eq!(decoded.into_iter().collect::<Result<Vec<_>>>().unwrap(), messages);
+
+        // Input ending inside a header or a payload is an error
+        let first = framed_bytes(&messages[0]);
+        assert_eq!(decode_all(&first[..2], DEFAULT_MAX_FRAME_LEN), [Err(Error::UnexpectedEof)]);
+        assert_eq!(decode_all(&input[..first.len() + 10], DEFAULT_MAX_FRAME_LEN), [
+            Ok(messages[0].clone()),
+            Err(Error::UnexpectedEof)
+        ]);
+
+        // A frame over the limit ends the stream, one at the limit does not
+        let payload_len = first.len() - FRAME_HEADER_LEN;
+        assert_eq!(decode_all(&first, payload_len), [Ok(messages[0].clone())]);
+        assert_eq!(decode_all(&input, payload_len - 1), [Err(Error::LimitExceeded {
+            what: "Frame length",
+            limit: payload_len - 1
+        })]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_message_channel() {
+        // A small pipe forces partial reads and writes
+        let (client, server) = tokio::io::duplex(7);
+        let messages = vec![
+            Message::Hello { name: "client".to_string() },
+            Message::Data(vec![7; 1000]),
+            Message::Bye,
+        ];
+
+        let send = async {
+            let mut sink = into_message_sink::<Message>(client);
+            for message in &messages {
+                sink.feed(message.clone()).await.unwrap();
+            }
+            sink.close().await.unwrap();
+        };
+        let receive = into_message_stream::<Message>(server).collect::<Vec<_>>();
+        let ((), received) = tokio::join!(send, receive);
+        assert_eq!(received.into_iter().collect::<Result<Vec<_>>>().unwrap(), messages);
+
+        // A read cancelled mid-frame can be retried
+        let (mut client, server) = tokio::io::duplex(64);
+        let frame = framed_bytes(&Message::Hello { name: "late".to_string() });
+        let mut stream = into_message_stream::<Message>(server);
+        client.write_all(&frame[..6]).await.unwrap();
+        assert!(stream.next().now_or_never().is_none());
+        client.write_all(&frame[6..]).await.unwrap();
+        assert_eq!(stream.next().await.transpose().unwrap(), Some(Message::Hello { name: "late".to_string() }));
+
+        // Oversized and truncated frames are errors
+        let frame = [&5u32.to_le_bytes()[..], b"NANO\x01"].concat();
+        let mut stream = MessageStream::<_, ()>::new(&frame[..]).with_max_frame_len(4);
+        assert!(matches!(stream.next().await, Some(Err(Error::LimitExceeded { .. }))));
+        assert!(stream.next().await.is_none());
+        let mut stream = MessageStream::<_, ()>::new(&frame[..8]);
+        assert_eq!(stream.next().await, Some(Err(Error::UnexpectedEof)));
+    }
+}
@@ -0,0 +1,341 @@
+//! Saving values to files and loading them back
+//!
+//! A save writes the payload to a temporary file next to the target and
+//! renames it into place, so readers see either the old file or the new
+//! one, never a partial write. With [`SaveOptions::with_sync`] the data and
+//! the rename are flushed to disk before the save returns.
+//!
+//! Compressed files carry a container header, so loading detects them
+//! without being told. Loading fails with [`Error::FileNotFound`] when
+//! there is nothing to load and [`Error::CorruptFile`] when the contents
+//! cannot be decoded, such as after a checksum mismatch.
+//!
+//! ```rust
+//! use nanobit::fs::{self, SaveOptions};
+//! use nanobit::Error;
+//!
+//! # let path = std::env::temp_dir().join("nanobit-doc-settings.nb");
+//! fs::save(&path, &("dark", 14u8), SaveOptions::new())?;
+//! let (theme, size): (String, u8) = fs::load(&path)?;
+//!
+//! let missing = fs::load::<_, u8>(path.with_extension("missing"));
+//! assert!(matches!(missing, Err(Error::FileNotFound(_))));
+//! # std::fs::remove_file(&path).ok();
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::compression::{self, CompressionPolicy};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+
+/// How a value is written to a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SaveOptions {
+    config: Config,
+    compression: Option<CompressionPolicy>,
+    sync: bool,
+}
+
+impl SaveOptions {
+    /// Uncompressed, checksummed when the `checksum` feature is enabled,
+    /// and flushed to disk before returning
+    pub const fn new() -> Self {
+        #[cfg(feature = "checksum")]
+        let config = Config::new().with_checksum(Some(crate::Checksum::Crc32c));
+        #[cfg(not(feature = "checksum"))]
+        let config = Config::new();
+        Self {
+            config,
+            compression: None,
+            sync: true,
+        }
+    }
+
+    /// Encode the value with the given options
+    pub const fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Compress the file according to `policy`
+    pub const fn with_compression(mut self, policy: CompressionPolicy) -> Self {
+        self.compression = Some(policy);
+        self
+    }
+
+    /// Flush the file and the rename to disk before returning
+    ///
+    /// Turning this off is faster, but a crash shortly after a save may
+    /// lose it. The file is still never left half-written.
+    pub const fn with_sync(mut self, enabled: bool) -> Self {
+        self.sync = enabled;
+        self
+    }
+
+    /// Get the encoding options
+    #[inline]
+    pub const fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Get the compression policy, if any
+    #[inline]
+    pub const fn compression(&self) -> Option<CompressionPolicy> {
+        self.compression
+    }
+
+    /// Check whether saves are flushed to disk
+    #[inline]
+    pub const fn sync(&self) -> bool {
+        self.sync
+    }
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// File contents for a value
+fn encode<T>(value: &T, options: &SaveOptions) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    let bytes = crate::to_bytes_with_config(&value, options.config)?;
+    match options.compression {
+        Some(policy) => compression::compress_with_policy(&bytes, &policy),
+        None => Ok(bytes),
+    }
+}
+
+// Decode file contents, decompressing them first if needed
+fn decode<T>(path: &Path, bytes: &[u8], config: Config) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let decode = || {
+        let (header, _) = Header::parse(bytes)?;
+        if header.flags.contains(HeaderFlags::COMPRESSED) {
+            let decompressed = compression::decompress(bytes)?;
+            return crate::from_bytes_with_config(&decompressed, config);
+        }
+        crate::from_bytes_with_config(bytes, config)
+    };
+    decode().map_err(|reason| Error::CorruptFile {
+        path: path.display().to_string(),
+        reason: Box::new(reason),
+    })
+}
+
+// Tell a missing file apart from other read failures
+fn read_error(path: &Path, err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => Error::FileNotFound(path.display().to_string()),
+        _ => err.into(),
+    }
+}
+
+// A unique sibling of `path` to write before renaming over it
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.{}.tmp", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+    path.with_file_name(name)
+}
+
+// The directory holding `path`, whose entry changes on rename
+#[cfg(unix)]
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Save a value to a file, replacing it atomically
+pub fn save<P, T>(path: P, value: &T, options: SaveOptions) -> Result<()>
+where
+    P: AsRef<Path>,
+    T: Serialize + ?Sized,
+{
+    let path = path.as_ref();
+    let bytes = encode(value, &options)?;
+    let temp = temp_path(path);
+    let result = write_and_rename(&temp, path, &bytes, options.sync);
+    if result.is_err() {
+        std::fs::remove_file(&temp).ok();
+    }
+    result
+}
+
+fn write_and_rename(temp: &Path, path: &Path, bytes: &[u8], sync: bool) -> Result<()> {
+    let mut file = File::create(temp)?;
+    file.write_all(bytes)?;
+    if sync {
+        file.sync_all()?;
+    }
+    drop(file);
+    std::fs::rename(temp, path)?;
+    #[cfg(unix)]
+    if sync {
+        File::open(parent_dir(path))?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Load a value saved by [`save`]
+pub fn load<P, T>(path: P) -> Result<T>
+where
+    P: AsRef<Path>,
+    T: DeserializeOwned,
+{
+    load_with_config(path, Config::new())
+}
+
+/// Load a value with the given decoding options
+pub fn load_with_config<P, T>(path: P, config: Config) -> Result<T>
+where
+    P: AsRef<Path>,
+    T: DeserializeOwned,
+{
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|err| read_error(path, err))?;
+    decode(path, &bytes, config)
+}
+
+#[cfg(feature = "async")]
+mod async_io {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    /// Save a value to a file, replacing it atomically
+    ///
+    /// ```rust
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> nanobit::Result<()> {
+    /// # let path = std::env::temp_dir().join("nanobit-doc-state.nb");
+    /// nanobit::to_file_async(&path, &vec![1u32, 2, 3]).await?;
+    /// let state: Vec<u32> = nanobit::from_file_async(&path).await?;
+    /// assert_eq!(state, [1, 2, 3]);
+    /// # std::fs::remove_file(&path).ok();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn to_file_async<P, T>(path: P, value: &T) -> Result<()>
+    where
+        P: AsRef<Path>,
+        T: Serialize + ?Sized,
+    {
+        to_file_async_with_options(path, value, SaveOptions::new()).await
+    }
+
+    /// Save a value to a file with the given options, replacing it atomically
+    pub async fn to_file_async_with_options<P, T>(path: P, value: &T, options: SaveOptions) -> Result<()>
+    where
+        P: AsRef<Path>,
+        T: Serialize + ?Sized,
+    {
+        let path = path.as_ref();
+        let bytes = encode(value, &options)?;
+        let temp = temp_path(path);
+        let result = write_and_rename(&temp, path, &bytes, options.sync).await;
+        if result.is_err() {
+            tokio::fs::remove_file(&temp).await.ok();
+        }
+        result
+    }
+
+    async fn write_and_rename(temp: &Path, path: &Path, bytes: &[u8], sync: bool) -> Result<()> {
+        let mut file = tokio::fs::File::create(temp).await?;
+        file.write_all(bytes).await?;
+        if sync {
+            file.sync_all().await?;
+        }
+        drop(file);
+        tokio::fs::rename(temp, path).await?;
+        #[cfg(unix)]
+        if sync {
+            tokio::fs::File::open(parent_dir(path)).await?.sync_all().await?;
+        }
+        Ok(())
+    }
+
+    /// Load a value saved by [`to_file_async`]
+    pub async fn from_file_async<P, T>(path: P) -> Result<T>
+    where
+        P: AsRef<Path>,
+        T: DeserializeOwned,
+    {
+        from_file_async_with_config(path, Config::new()).await
+    }
+
+    /// Load a value with the given decoding options
+    pub async fn from_file_async_with_config<P, T>(path: P, config: Config) -> Result<T>
+    where
+        P: AsRef<Path>,
+        T: DeserializeOwned,
+    {
+        let path = path.as_ref();
+        let bytes = tokio::fs::read(path).await.map_err(|err| read_error(path, err))?;
+        decode(path, &bytes, config)
+    }
+}
+
+#[cfg(feature = "async")]
+pub use async_io::{from_file_async, from_file_async_with_config, to_file_async, to_file_async_with_options};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("nanobit-fs-sync-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.nb");
+        let state = (String::from("ready"), vec![3u64, 1, 4]);
+
+        save(&path, &state, SaveOptions::new()).unwrap();
+        assert_eq!(load::<_, (String, Vec<u64>)>(&path).unwrap(), state);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A damaged file is reported as corrupt, not missing
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(matches!(load::<_, (String, Vec<u64>)>(&path), Err(Error::CorruptFile { .. })));
+        assert!(matches!(load::<_, u8>(dir.join("missing.nb")), Err(Error::FileNotFound(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_file_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("nanobit-fs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.nb");
+        let state: Vec<String> = (0..200).map(|i| format!("entry {}", i % 7)).collect();
+
+        let compressed = SaveOptions::new().with_compression(CompressionPolicy::auto()).with_sync(false);
+        for options in [SaveOptions::new(), compressed] {
+            to_file_async_with_options(&path, &state, options).await.unwrap();
+            assert_eq!(from_file_async::<_, Vec<String>>(&path).await.unwrap(), state);
+        }
+        assert!(std::fs::metadata(&path).unwrap().len() < crate::to_bytes(&state).unwrap().len() as u64);
+
+        // Only the target is left behind
+        to_file_async(&path, &1u8).await.unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        assert!(matches!(from_file_async::<_, u8>(dir.join("missing.nb")).await, Err(Error::FileNotFound(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
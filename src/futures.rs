@@ -0,0 +1,296 @@
+//! Async I/O over `futures-io` readers and writers
+//!
+//! The same entry points as the tokio-based `async` feature, for runtimes
+//! such as smol and async-std whose I/O types implement the `futures-io`
+//! traits. Nothing here depends on an executor.
+//!
+//! ```rust
+//! use futures_util::{SinkExt, StreamExt};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> nanobit::Result<()> {
+//! let mut out = Vec::new();
+//! nanobit::futures::to_writer_async(&mut out, &("reading", 21.5f32)).await?;
+//! let value: (String, f32) = nanobit::futures::from_reader_async(out.as_slice()).await?;
+//! assert_eq!(value, ("reading".to_string(), 21.5));
+//!
+//! let mut sink = nanobit::futures::MessageSink::<_, u32>::new(Vec::new());
+//! sink.send(7).await?;
+//! let framed = sink.into_inner();
+//! let mut stream = nanobit::futures::into_message_stream::<u32>(framed.as_slice());
+//! assert_eq!(stream.next().await.transpose()?, Some(7));
+//! # Ok(())
+//! # }
+//! ```
+
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use futures_util::Sink;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::framed::{FrameReader, FrameWriter};
+
+/// Serialize a value to an async writer
+pub async fn to_writer_async<W, T>(mut writer: W, value: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize + ?Sized,
+{
+    let bytes = crate::to_bytes(&value)?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Deserialize a value from an async reader, reading it to the end
+///
+/// This is not cancel safe: cancelling it loses whatever was read. Use
+/// [`MessageStream`] to read values where cancellation is expected.
+pub async fn from_reader_async<R, T>(reader: R) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    from_reader_async_with_config(reader, Config::new()).await
+}
+
+/// Deserialize a value from an async reader with the given options
+pub async fn from_reader_async_with_config<R, T>(mut reader: R, config: Config) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer).await?;
+    crate::from_bytes_with_config(&buffer, config)
+}
+
+/// Create a sink that writes each value as a frame to `writer`
+pub fn into_message_sink<T>(writer: impl AsyncWrite + Unpin) -> MessageSink<impl AsyncWrite + Unpin, T> {
+    MessageSink::new(writer)
+}
+
+/// Create a stream that reads each frame from `reader` as a value
+pub fn into_message_stream<T>(reader: impl AsyncRead + Unpin) -> MessageStream<impl AsyncRead + Unpin, T> {
+    MessageStream::new(reader)
+}
+
+/// A [`Sink`] of values framed onto a `futures-io` writer
+///
+/// Frames are laid out as described in [`framed`](crate::framed).
+pub struct MessageSink<W, T> {
+    writer: W,
+    frames: FrameWriter,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<W: AsyncWrite + Unpin, T> MessageSink<W, T> {
+    /// Create a sink writing to `writer`
+    pub fn new(writer: W) -> Self {
+        Self::with_config(writer, Config::new())
+    }
+
+    /// Create a sink encoding values with the given options
+    pub fn with_config(writer: W, config: Config) -> Self {
+        Self {
+            writer,
+            frames: FrameWriter::new(config),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Get a reference to the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Return the inner writer, dropping any output not yet flushed
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn poll_write_frames(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let writer = &mut self.writer;
+        self.frames.poll_write(|buf| Pin::new(&mut *writer).poll_write(cx, buf))
+    }
+}
+
+impl<W: AsyncWrite + Unpin, T: Serialize> Sink<T> for MessageSink<W, T> {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        if this.frames.is_full() {
+            return this.poll_write_frames(cx);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<()> {
+        self.get_mut().frames.push(&item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_frames(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx).map_err(Error::from)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().writer).poll_close(cx).map_err(Error::from)
+    }
+}
+
+/// A [`Stream`] of values read from frames on a `futures-io` reader
+///
+/// Errors end the stream as they do for the tokio
+/// [`MessageStream`](crate::framed::MessageStream), and like it, a pending
+/// `next()` can be dropped without losing a partly read frame.
+pub struct MessageStream<R, T> {
+    reader: R,
+    frames: FrameReader,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<R: AsyncRead + Unpin, T> MessageStream<R, T> {
+    /// Create a stream reading from `reader`
+    pub fn new(reader: R) -> Self {
+        Self::with_config(reader, Config::new())
+    }
+
+    /// Create a stream decoding values with the given options
+    pub fn with_config(reader: R, config: Config) -> Self {
+        Self {
+            reader,
+            frames: FrameReader::new(config),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Refuse frames longer than `max_frame_len` bytes
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.frames.set_max_frame_len(max_frame_len);
+        self
+    }
+
+    /// Get a reference to the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Return the inner reader, dropping any buffered input
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin, T: DeserializeOwned> Stream for MessageStream<R, T> {
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<T>>> {
+        let this = self.get_mut();
+        let reader = &mut this.reader;
+        this.frames.poll_next(|buf| Pin::new(&mut *reader).poll_read(cx, buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::future::Future;
+    use futures_util::{SinkExt, StreamExt};
+    use std::io;
+
+    // Accepts at most three bytes per write, and stalls before every other one
+    #[derive(Default)]
+    struct Trickle {
+        written: Vec<u8>,
+        stalled: bool,
+    }
+
+    impl AsyncWrite for Trickle {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.stalled = !self.stalled;
+            if self.stalled {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    // Poll a future to completion without a runtime
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = core::pin::pin!(future);
+        let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_futures_io_roundtrip() {
+        let value = vec![(1u8, "one".to_string()), (2, "two".to_string())];
+        let mut out = futures_util::io::Cursor::new(Vec::new());
+        to_writer_async(&mut out, &value).await.unwrap();
+        assert_eq!(out.get_ref(), &crate::to_bytes(&value).unwrap());
+        let decoded: Vec<(u8, String)> = from_reader_async(out.get_ref().as_slice()).await.unwrap();
+        assert_eq!(decoded, value);
+
+        let mut sink = MessageSink::<_, (u8, String)>::new(Vec::new());
+        for item in &value {
+            sink.feed(item.clone()).await.unwrap();
+        }
+        sink.close().await.unwrap();
+        let framed = sink.into_inner();
+        let received = into_message_stream::<(u8, String)>(framed.as_slice()).collect::<Vec<_>>().await;
+        assert_eq!(received.into_iter().collect::<Result<Vec<_>>>().unwrap(), value);
+
+        // A truncated final frame is an error
+        let mut stream = into_message_stream::<(u8, String)>(&framed[..framed.len() - 1]);
+        assert_eq!(stream.next().await.transpose().unwrap(), Some(value[0].clone()));
+        assert_eq!(stream.next().await, Some(Err(Error::UnexpectedEof)));
+    }
+
+    #[test]
+    fn test_partial_writes() {
+        let value = vec![(1u8, "one".to_string()), (2, "x".repeat(300))];
+        block_on(async {
+            let mut writer = Trickle::default();
+            to_writer_async(&mut writer, &value).await.unwrap();
+            assert_eq!(writer.written, crate::to_bytes(&value).unwrap());
+            let decoded: Vec<(u8, String)> = from_reader_async(writer.written.as_slice()).await.unwrap();
+            assert_eq!(decoded, value);
+
+            let mut sink = MessageSink::<_, (u8, String)>::new(Trickle::default());
+            for item in &value {
+                sink.send(item.clone()).await.unwrap();
+            }
+            sink.close().await.unwrap();
+            let framed = sink.into_inner().written;
+            let received = into_message_stream::<(u8, String)>(framed.as_slice()).collect::<Vec<_>>().await;
+            assert_eq!(received.into_iter().collect::<Result<Vec<_>>>().unwrap(), value);
+        });
+    }
+}
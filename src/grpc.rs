@@ -0,0 +1,180 @@
+//! A tonic [`Codec`] for gRPC services exchanging serde types
+//!
+//! Rust services on both ends of a call can skip protobuf and send nanobit
+//! payloads as gRPC messages. Point the generated code at the codec, for
+//! example with `tonic_build::manual`:
+//!
+//! ```rust,ignore
+//! let method = tonic_build::manual::Method::builder()
+//!     .name("get_user")
+//!     .route_name("GetUser")
+//!     .input_type("crate::UserRequest")
+//!     .output_type("crate::User")
+//!     .codec_path("nanobit::grpc::NanobitCodec")
+//!     .build();
+//! ```
+//!
+//! Message size limits and compression are left to tonic, which applies
+//! them to the encoded payload as it does for any codec.
+
+use core::marker::PhantomData;
+
+use bytes::{Buf, BufMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::Status;
+
+use crate::config::Config;
+
+/// Encodes requests of type `T` and decodes responses of type `U`, or the
+/// other way around on the server
+pub struct NanobitCodec<T, U> {
+    config: Config,
+    _marker: PhantomData<fn(T) -> U>,
+}
+
+impl<T, U> NanobitCodec<T, U> {
+    /// Create a codec with the default options
+    pub const fn new() -> Self {
+        Self::with_config(Config::new())
+    }
+
+    /// Create a codec encoding and decoding with the given options
+    ///
+    /// Both ends of a call must agree on the options, since the generated
+    /// code creates codecs with [`Default`].
+    pub const fn with_config(config: Config) -> Self {
+        Self {
+            config,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, U> Default for NanobitCodec<T, U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, U> Codec for NanobitCodec<T, U>
+where
+    T: Serialize + Send + 'static,
+    U: DeserializeOwned + Send + 'static,
+{
+    type Encode = T;
+    type Decode = U;
+    type Encoder = NanobitEncoder<T>;
+    type Decoder = NanobitDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        NanobitEncoder {
+            config: self.config,
+            _marker: PhantomData,
+        }
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        NanobitDecoder {
+            config: self.config,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Writes each message as a nanobit payload
+pub struct NanobitEncoder<T> {
+    config: Config,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize> Encoder for NanobitEncoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: T, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        let bytes = crate::to_bytes_with_config(&item, self.config)
+            .map_err(|e| Status::internal(format!("Failed to encode message: {e}")))?;
+        dst.put_slice(&bytes);
+        Ok(())
+    }
+}
+
+/// Reads each message from a nanobit payload
+pub struct NanobitDecoder<U> {
+    config: Config,
+    _marker: PhantomData<fn() -> U>,
+}
+
+impl<U: DeserializeOwned> Decoder for NanobitDecoder<U> {
+    type Item = U;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<U>, Status> {
+        let bytes = src.copy_to_bytes(src.remaining());
+        crate::from_bytes_with_config(&bytes, self.config)
+            .map(Some)
+            .map_err(|e| Status::internal(format!("Failed to decode message: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tonic::codec::{EncodeBody, Streaming};
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct User {
+        id: u64,
+        name: String,
+        roles: Vec<String>,
+    }
+
+    #[tokio::test]
+    async fn test_grpc_codec_roundtrip() {
+        let users: Vec<User> = (0..3)
+            .map(|id| User { id, name: format!("user {id}"), roles: vec!["reader".to_string()] })
+            .collect();
+        let mut codec = NanobitCodec::<User, User>::new();
+
+        // Frame the messages as a client would and read them back as a server
+        let source = futures_util::stream::iter(users.clone().into_iter().map(Ok));
+        let body = EncodeBody::new_client(codec.encoder(), source, None, None);
+        let mut streaming = Streaming::new_request(codec.decoder(), body, None, None);
+        for user in &users {
+            assert_eq!(streaming.message().await.unwrap().as_ref(), Some(user));
+        }
+        assert!(streaming.message().await.unwrap().is_none());
+
+        // A message that does not decode is an error status
+        let mut codec = NanobitCodec::<String, User>::new();
+        let source = futures_util::stream::iter([Ok("not a user".to_string())]);
+        let body = EncodeBody::new_client(codec.encoder(), source, None, None);
+        let mut streaming = Streaming::new_request(codec.decoder(), body, None, None);
+        assert_eq!(streaming.message().await.unwrap_err().code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_codec_bad_frames() {
+        let user = User { id: 1, name: "x".repeat(100), roles: Vec::new() };
+
+        // Messages over the limit are refused before they reach the decoder
+        let mut codec = NanobitCodec::<User, User>::new();
+        let source = futures_util::stream::iter([Ok(user)]);
+        let body = EncodeBody::new_client(codec.encoder(), source, None, None);
+        let mut streaming = Streaming::new_request(codec.decoder(), body, None, Some(64));
+        assert_eq!(streaming.message().await.unwrap_err().code(), tonic::Code::OutOfRange);
+
+        // A frame holding bytes that are not a payload
+        let frame = String::from_utf8(vec![0, 0, 0, 0, 3, b'a', b'b', b'c']).unwrap();
+        let mut streaming = Streaming::new_request(codec.decoder(), frame, None, None);
+        assert_eq!(streaming.message().await.unwrap_err().code(), tonic::Code::Internal);
+
+        // A frame cut short of the length it announces
+        let frame = String::from_utf8(vec![0, 0, 0, 0, 100, b'a', b'b', b'c']).unwrap();
+        let mut streaming = Streaming::new_request(codec.decoder(), frame, None, None);
+        assert!(streaming.message().await.is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Content hashes of serialized values
+//!
+//! A value's hash is the digest of its canonical encoding, as produced by
+//! [`to_bytes_canonical`](crate::to_bytes_canonical). The encoding is
+//! streamed into the hasher, so the full byte vector is never built.
+//! Maps hash the same whatever their iteration order, but sequences do
+//! not: wrap a `HashSet` in [`UnorderedSet`](crate::UnorderedSet), or use
+//! a `BTreeSet`, so that equal sets hash the same.
+//! Enable the `blake3` or `sha2` feature to choose an algorithm.
+
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::ser::Serializer;
+
+/// Hash a value with BLAKE3 if enabled, otherwise SHA-256
+///
+/// ```rust
+/// use std::collections::HashMap;
+///
+/// let a = HashMap::from([("x", 1), ("y", 2)]);
+/// let b = HashMap::from([("y", 2), ("x", 1)]);
+/// assert_eq!(nanobit::hash(&a)?, nanobit::hash(&b)?);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn hash<T>(value: &T) -> Result<[u8; 32]>
+where
+    T: Serialize + ?Sized,
+{
+    #[cfg(feature = "blake3")]
+    {
+        hash_blake3(value)
+    }
+    #[cfg(not(feature = "blake3"))]
+    {
+        hash_sha256(value)
+    }
+}
+
+/// BLAKE3 hash of a value's canonical encoding
+#[cfg(feature = "blake3")]
+pub fn hash_blake3<T>(value: &T) -> Result<[u8; 32]>
+where
+    T: Serialize + ?Sized,
+{
+    let mut hasher = blake3::Hasher::new();
+    write_canonical(&mut hasher, value)?;
+    Ok(*hasher.finalize().as_bytes())
+}
+
+/// SHA-256 hash of a value's canonical encoding
+#[cfg(feature = "sha2")]
+pub fn hash_sha256<T>(value: &T) -> Result<[u8; 32]>
+where
+    T: Serialize + ?Sized,
+{
+    use sha2::Digest;
+
+    struct Sha256Writer(sha2::Sha256);
+
+    impl Write for Sha256Writer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.update(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut hasher = Sha256Writer(sha2::Sha256::new());
+    write_canonical(&mut hasher, value)?;
+    Ok(hasher.0.finalize().into())
+}
+
+// Stream the canonical encoding of `value` to `writer`
+fn write_canonical<T>(writer: &mut dyn Write, value: &T) -> Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::to_stream(writer, Config::new().with_canonical(true))?;
+    value.serialize(&mut serializer)?;
+    serializer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn sample() -> HashMap<u32, Vec<String>> {
+        (0..200).map(|i| (i, vec![format!("value-{i}"); 20])).collect()
+    }
+
+    #[test]
+    fn test_hash_matches_canonical_bytes() {
+        let value = sample();
+        let bytes = crate::to_bytes_canonical(&value).unwrap();
+
+        #[cfg(feature = "blake3")]
+        assert_eq!(hash_blake3(&value).unwrap(), *blake3::hash(&bytes).as_bytes());
+        #[cfg(feature = "sha2")]
+        {
+            use sha2::Digest;
+            assert_eq!(hash_sha256(&value).unwrap(), <[u8; 32]>::from(sha2::Sha256::digest(&bytes)));
+        }
+
+        assert_eq!(hash(&value).unwrap(), hash(&sample()).unwrap());
+        assert_ne!(hash(&value).unwrap(), hash(&HashMap::<u32, Vec<String>>::new()).unwrap());
+    }
+}
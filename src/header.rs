@@ -0,0 +1,324 @@
+//! Payload header parsing and encoding
+//!
+//! Every payload starts with the `NANO` magic bytes followed by a version byte.
+//! Version 1 payloads carry nothing else. When an encoding option changes how
+//! the body must be decoded, the header is written as version 2 with a flags
+//! byte after the version, so plain payloads stay byte-for-byte unchanged.
+//! The top bit of the flags byte announces a second flags byte for the
+//! options that did not fit in the first. A schema hash and a type name, if
+//! present, follow the flags in that order.
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec::Vec, string::ToString};
+
+use core::fmt;
+
+use crate::buffer::ReadBuffer;
+use crate::error::{Error, Result};
+
+/// Bit flags describing optional encodings used by a payload
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HeaderFlags(u16);
+
+impl HeaderFlags {
+    /// No optional encodings
+    pub const NONE: Self = Self(0);
+
+    /// Struct fields are written as tag + length + value
+    pub const TAGGED_FIELDS: Self = Self(0x01);
+
+    /// Every value is preceded by a type tag
+    pub const SELF_DESCRIBING: Self = Self(0x02);
+
+    /// A CRC-32C trailer follows the body
+    pub const CHECKSUM_CRC32C: Self = Self(0x04);
+
+    /// An xxHash64 trailer follows the body
+    pub const CHECKSUM_XXH64: Self = Self(0x08);
+
+    /// The body is an encrypted envelope, see `crypto`
+    pub const ENCRYPTED: Self = Self(0x10);
+
+    /// The body is a compressed container, see `compression`
+    pub const COMPRESSED: Self = Self(0x20);
+
+    /// Strings are indices into a table at the start of the body
+    pub const STRING_TABLE: Self = Self(0x40);
+
+    /// A type name follows the flags, see `typed`
+    pub const TYPE_NAME: Self = Self(0x0100);
+
+    /// Chars are written as UTF-8 rather than as a u32
+    pub const COMPACT_CHARS: Self = Self(0x0200);
+
+    /// Tuples and arrays are written without their static length
+    pub const FIXED_TUPLES: Self = Self(0x0400);
+
+    /// A chunk index precedes the body, see `parallel`
+    pub const SEQ_INDEX: Self = Self(0x0800);
+
+    /// A 4-byte hash of the writer's schema follows the flags, see `schema`
+    pub const SCHEMA_HASH: Self = Self(0x1000);
+
+    /// Option tags are folded into the length of the value they hold
+    pub const NICHE_OPTIONS: Self = Self(0x2000);
+
+    /// All flags understood by this version of the library
+    const KNOWN: u16 = Self::TAGGED_FIELDS.0
+        | Self::SELF_DESCRIBING.0
+        | Self::CHECKSUM_CRC32C.0
+        | Self::CHECKSUM_XXH64.0
+        | Self::ENCRYPTED.0
+        | Self::COMPRESSED.0
+        | Self::STRING_TABLE.0
+        | Self::TYPE_NAME.0
+        | Self::COMPACT_CHARS.0
+        | Self::FIXED_TUPLES.0
+        | Self::SEQ_INDEX.0
+        | Self::SCHEMA_HASH.0
+        | Self::NICHE_OPTIONS.0;
+
+    /// Set in the first flags byte when a second one follows
+    const EXTENDED: u8 = 0x80;
+
+    /// Create flags from their raw representation
+    #[inline]
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// Get the raw representation
+    #[inline]
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Check whether no flags are set
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Check whether all flags in `other` are set
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Set or clear the flags in `other`
+    #[inline]
+    pub fn set(&mut self, other: Self, enabled: bool) {
+        if enabled {
+            self.0 |= other.0;
+        } else {
+            self.0 &= !other.0;
+        }
+    }
+}
+
+impl fmt::Display for HeaderFlags {
+    /// Lists the names of the set flags, or `none`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const NAMES: [(HeaderFlags, &str); 13] = [
+            (HeaderFlags::TAGGED_FIELDS, "tagged-fields"),
+            (HeaderFlags::SELF_DESCRIBING, "self-describing"),
+            (HeaderFlags::CHECKSUM_CRC32C, "crc32c"),
+            (HeaderFlags::CHECKSUM_XXH64, "xxh64"),
+            (HeaderFlags::ENCRYPTED, "encrypted"),
+            (HeaderFlags::COMPRESSED, "compressed"),
+            (HeaderFlags::STRING_TABLE, "string-table"),
+            (HeaderFlags::TYPE_NAME, "type-name"),
+            (HeaderFlags::COMPACT_CHARS, "compact-chars"),
+            (HeaderFlags::FIXED_TUPLES, "fixed-tuples"),
+            (HeaderFlags::SEQ_INDEX, "seq-index"),
+            (HeaderFlags::SCHEMA_HASH, "schema-hash"),
+            (HeaderFlags::NICHE_OPTIONS, "niche-options"),
+        ];
+        let mut names = NAMES.iter().filter(|(flag, _)| self.contains(*flag)).map(|(_, name)| name);
+        match names.next() {
+            Some(first) => f.write_str(first)?,
+            None => return f.write_str("none"),
+        }
+        names.try_for_each(|name| write!(f, ", {name}"))
+    }
+}
+
+/// A parsed payload header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Format version byte
+    pub version: u8,
+    /// Optional encodings used by the payload
+    pub flags: HeaderFlags,
+    /// Hash of the schema of the type the payload was written for
+    pub schema_hash: Option<u32>,
+}
+
+impl Header {
+    /// Create the header for a payload using the given flags
+    pub fn new(flags: HeaderFlags) -> Self {
+        let version = if flags.is_empty() {
+            crate::VERSION
+        } else {
+            crate::FLAGS_VERSION
+        };
+        Self { version, flags, schema_hash: None }
+    }
+
+    /// Record the schema hash of the written type, or remove it
+    pub fn with_schema_hash(self, hash: Option<u32>) -> Self {
+        let mut flags = self.flags;
+        flags.set(HeaderFlags::SCHEMA_HASH, hash.is_some());
+        Self { schema_hash: hash, ..Self::new(flags) }
+    }
+
+    /// Parse a header from the start of `data`
+    ///
+    /// Returns the header and the number of bytes it occupies, including
+    /// any schema hash and type name.
+    pub fn parse(data: &[u8]) -> Result<(Self, usize)> {
+        if data.len() < 5 {
+            return Err(Error::InvalidFormat("Data too short for header".to_string()));
+        }
+
+        if &data[0..4] != crate::MAGIC {
+            return Err(Error::InvalidFormat("Invalid magic bytes".to_string()));
+        }
+
+        match data[4] {
+            crate::VERSION => Ok((Self::new(HeaderFlags::NONE), 5)),
+            crate::FLAGS_VERSION => {
+                let too_short = || Error::InvalidFormat("Data too short for header flags".to_string());
+                let low = *data.get(5).ok_or_else(too_short)?;
+                let mut bits = u16::from(low & !HeaderFlags::EXTENDED);
+                let mut len = 6;
+                if low & HeaderFlags::EXTENDED != 0 {
+                    bits |= u16::from(*data.get(6).ok_or_else(too_short)?) << 8;
+                    len = 7;
+                }
+                if bits & !HeaderFlags::KNOWN != 0 {
+                    return Err(Error::InvalidFormat(format!(
+                        "Unsupported header flags: {bits:#06x}"
+                    )));
+                }
+                let flags = HeaderFlags::from_bits(bits);
+                let mut schema_hash = None;
+                if flags.contains(HeaderFlags::SCHEMA_HASH) {
+                    let hash = data
+                        .get(len..len + 4)
+                        .ok_or_else(|| Error::InvalidFormat("Data too short for schema hash".to_string()))?;
+                    schema_hash = Some(u32::from_le_bytes(hash.try_into().expect("slice of 4 bytes")));
+                    len += 4;
+                }
+                if flags.contains(HeaderFlags::TYPE_NAME) {
+                    let mut reader = ReadBuffer::new(&data[len..]);
+                    reader.read_str()?;
+                    len += reader.position();
+                }
+                Ok((
+                    Self {
+                        version: crate::FLAGS_VERSION,
+                        flags,
+                        schema_hash,
+                    },
+                    len,
+                ))
+            }
+            version => Err(Error::UnsupportedVersion(version)),
+        }
+    }
+
+    /// Number of bytes the encoded header occupies, excluding any type name
+    #[inline]
+    pub fn encoded_len(&self) -> usize {
+        let hash_len = if self.schema_hash.is_some() { 4 } else { 0 };
+        if self.version == crate::VERSION {
+            5
+        } else if self.flags.bits() > 0xFF {
+            7 + hash_len
+        } else {
+            6 + hash_len
+        }
+    }
+
+    /// Append the encoded header to `out`
+    ///
+    /// With [`HeaderFlags::TYPE_NAME`] set, the caller writes the name next.
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(crate::MAGIC);
+        out.push(self.version);
+        if self.version != crate::VERSION {
+            let [low, high] = self.flags.bits().to_le_bytes();
+            if high == 0 {
+                out.push(low);
+            } else {
+                out.push(low | HeaderFlags::EXTENDED);
+                out.push(high);
+            }
+            if let Some(hash) = self.schema_hash {
+                out.extend_from_slice(&hash.to_le_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_header_roundtrip() {
+        let header = Header::new(HeaderFlags::NONE);
+        let mut out = Vec::new();
+        header.write_to(&mut out);
+
+        assert_eq!(out, b"NANO\x01");
+        assert_eq!(Header::parse(&out).unwrap(), (header, 5));
+    }
+
+    #[test]
+    fn test_flagged_header_roundtrip() {
+        let header = Header::new(HeaderFlags::TAGGED_FIELDS);
+        let mut out = Vec::new();
+        header.write_to(&mut out);
+
+        assert_eq!(out.len(), header.encoded_len());
+        assert_eq!(out[4], crate::FLAGS_VERSION);
+        let (parsed, len) = Header::parse(&out).unwrap();
+        assert_eq!(len, 6);
+        assert!(parsed.flags.contains(HeaderFlags::TAGGED_FIELDS));
+    }
+
+    #[test]
+    fn test_extended_flags_roundtrip() {
+        let mut flags = HeaderFlags::TAGGED_FIELDS;
+        flags.set(HeaderFlags::TYPE_NAME, true);
+        let mut out = Vec::new();
+        Header::new(flags).write_to(&mut out);
+        assert_eq!(out, b"NANO\x02\x81\x01");
+
+        out.extend_from_slice(b"\x03abc");
+        let (parsed, len) = Header::parse(&out).unwrap();
+        assert_eq!((parsed.flags, parsed.encoded_len(), len), (flags, 7, 11));
+
+        let header = Header::new(flags).with_schema_hash(Some(0xDEAD_BEEF));
+        let mut out = Vec::new();
+        header.write_to(&mut out);
+        out.extend_from_slice(b"\x03abc");
+        assert_eq!(&out[..11], b"NANO\x02\x81\x11\xEF\xBE\xAD\xDE");
+        assert_eq!(Header::parse(&out).unwrap(), (header, 15));
+        assert_eq!(header.encoded_len(), 11);
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        assert!(Header::parse(b"NAN").is_err());
+        assert!(Header::parse(b"XXXX\x01").is_err());
+        assert!(Header::parse(b"NANO\x02").is_err());
+        assert!(Header::parse(b"NANO\x02\x80").is_err());
+        assert!(Header::parse(b"NANO\x02\x80\x80").is_err());
+        assert!(Header::parse(b"NANO\x02\x80\x01\x05abc").is_err());
+        assert!(Header::parse(b"NANO\x02\x80\x10\x01\x02").is_err());
+        assert_eq!(Header::parse(b"NANO\x63"), Err(Error::UnsupportedVersion(0x63)));
+    }
+}
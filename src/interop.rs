@@ -0,0 +1,157 @@
+//! Transcoding between self-describing payloads and MessagePack or CBOR
+//!
+//! Payloads are converted through [`Value`], so only self-describing payloads
+//! (see [`Config::with_self_describing`]) can be transcoded. Enum variants are
+//! written in serde's externally tagged form, which other implementations
+//! understand; payloads read back from either format are self-describing.
+//!
+//! [`Config::with_self_describing`]: crate::Config::with_self_describing
+
+use std::io::{Read, Write};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// Convert a self-describing payload into MessagePack
+#[cfg(feature = "msgpack")]
+pub fn to_msgpack(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    to_msgpack_writer(bytes, &mut out)?;
+    Ok(out)
+}
+
+/// Write a self-describing payload as MessagePack to a writer
+#[cfg(feature = "msgpack")]
+pub fn to_msgpack_writer<W: Write>(bytes: &[u8], mut writer: W) -> Result<()> {
+    let value = portable(crate::from_bytes(bytes)?);
+    rmp_serde::encode::write(&mut writer, &value)
+        .map_err(|e| Error::Serde(format!("MessagePack: {e}")))
+}
+
+/// Convert MessagePack into a self-describing payload
+#[cfg(feature = "msgpack")]
+pub fn from_msgpack(bytes: &[u8]) -> Result<Vec<u8>> {
+    from_msgpack_reader(bytes)
+}
+
+/// Read MessagePack from a reader into a self-describing payload
+#[cfg(feature = "msgpack")]
+pub fn from_msgpack_reader<R: Read>(reader: R) -> Result<Vec<u8>> {
+    let value: Value = rmp_serde::decode::from_read(reader)
+        .map_err(|e| Error::Serde(format!("MessagePack: {e}")))?;
+    encode(&value)
+}
+
+/// Convert a self-describing payload into CBOR
+#[cfg(feature = "cbor")]
+pub fn to_cbor(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    to_cbor_writer(bytes, &mut out)?;
+    Ok(out)
+}
+
+/// Write a self-describing payload as CBOR to a writer
+#[cfg(feature = "cbor")]
+pub fn to_cbor_writer<W: Write>(bytes: &[u8], writer: W) -> Result<()> {
+    let value = portable(crate::from_bytes(bytes)?);
+    ciborium::ser::into_writer(&value, writer).map_err(|e| Error::Serde(format!("CBOR: {e}")))
+}
+
+/// Convert CBOR into a self-describing payload
+#[cfg(feature = "cbor")]
+pub fn from_cbor(bytes: &[u8]) -> Result<Vec<u8>> {
+    from_cbor_reader(bytes)
+}
+
+/// Read CBOR from a reader into a self-describing payload
+#[cfg(feature = "cbor")]
+pub fn from_cbor_reader<R: Read>(reader: R) -> Result<Vec<u8>> {
+    let value: Value =
+        ciborium::de::from_reader(reader).map_err(|e| Error::Serde(format!("CBOR: {e}")))?;
+    encode(&value)
+}
+
+fn encode(value: &Value) -> Result<Vec<u8>> {
+    crate::to_bytes_with_config(value, Config::new().with_self_describing(true))
+}
+
+// Rewrite enum variants in the externally tagged form other formats use:
+// unit variants become their name, others a single-entry map
+fn portable(value: Value) -> Value {
+    match value {
+        Value::Seq(items) => Value::Seq(items.into_iter().map(portable).collect()),
+        Value::Map(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (portable(k), portable(v)))
+                .collect(),
+        ),
+        Value::Variant { index, name, value } => {
+            let name = if name.is_empty() { index.to_string() } else { name };
+            match *value {
+                Value::Null => Value::Str(name),
+                payload => Value::Map(vec![(Value::Str(name), portable(payload))]),
+            }
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Kind {
+        Plain,
+        Sized(u32),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Item {
+        name: String,
+        data: Vec<u8>,
+        kinds: Vec<Kind>,
+        weight: Option<f32>,
+    }
+
+    fn sample() -> Item {
+        Item {
+            name: "crate".to_string(),
+            data: vec![1, 2, 3],
+            kinds: vec![Kind::Plain, Kind::Sized(4)],
+            weight: Some(0.5),
+        }
+    }
+
+    fn self_describing() -> Vec<u8> {
+        crate::to_bytes_with_config(&sample(), Config::new().with_self_describing(true)).unwrap()
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_roundtrip() {
+        let packed = to_msgpack(&self_describing()).unwrap();
+        let foreign: Item = rmp_serde::from_slice(&packed).unwrap();
+        assert_eq!(foreign, sample());
+
+        let foreign = rmp_serde::to_vec_named(&sample()).unwrap();
+        let item: Item = crate::from_bytes(&from_msgpack(&foreign).unwrap()).unwrap();
+        assert_eq!(item, sample());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_roundtrip() {
+        let cbor = to_cbor(&self_describing()).unwrap();
+        let foreign: Item = ciborium::de::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(foreign, sample());
+
+        let mut foreign = Vec::new();
+        ciborium::ser::into_writer(&sample(), &mut foreign).unwrap();
+        let item: Item = crate::from_bytes(&from_cbor(&foreign).unwrap()).unwrap();
+        assert_eq!(item, sample());
+    }
+}
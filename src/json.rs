@@ -0,0 +1,178 @@
+//! Transcoding between self-describing payloads and JSON
+//!
+//! Only self-describing payloads (see [`Config::with_self_describing`]) carry
+//! enough type information to be converted without the Rust type. Payloads
+//! produced by [`from_json`] are self-describing and decode directly into any
+//! type whose serde representation matches the JSON.
+//!
+//! [`Config::with_self_describing`]: crate::Config::with_self_describing
+
+use serde_json::{Map, Number};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// Longest object key made by stringifying a sequence or map key
+///
+/// Each level of nesting escapes the quotes of the level below, so keys
+/// grow exponentially with depth and must be bounded.
+const MAX_COMPOSITE_KEY: usize = 16 * 1024;
+
+/// Convert a self-describing payload into a JSON value
+///
+/// Enum variants use serde's externally tagged representation, byte strings
+/// become arrays of numbers and non-finite floats become `null`.
+pub fn to_json(bytes: &[u8]) -> Result<serde_json::Value> {
+    let value: Value = crate::from_bytes(bytes)?;
+    value_to_json(value, MAX_COMPOSITE_KEY)
+}
+
+/// Encode a JSON value as a self-describing payload
+pub fn from_json(json: &serde_json::Value) -> Result<Vec<u8>> {
+    crate::to_bytes_with_config(json, Config::new().with_self_describing(true))
+}
+
+fn value_to_json(value: Value, max_key: usize) -> Result<serde_json::Value> {
+    use serde_json::Value as Json;
+
+    Ok(match value {
+        Value::Null => Json::Null,
+        Value::Bool(b) => Json::Bool(b),
+        Value::Int(i) => Json::Number(i.into()),
+        Value::UInt(u) => Json::Number(u.into()),
+        Value::Float(f) => Number::from_f64(f).map_or(Json::Null, Json::Number),
+        Value::Char(c) => Json::String(c.to_string()),
+        Value::Str(s) => Json::String(s),
+        Value::Bytes(b) => Json::Array(b.into_iter().map(|byte| Json::Number(byte.into())).collect()),
+        Value::Seq(items) => Json::Array(
+            items
+                .into_iter()
+                .map(|item| value_to_json(item, max_key))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Map(entries) => Json::Object(
+            entries
+                .into_iter()
+                .map(|(k, v)| Ok((json_key(k, max_key)?, value_to_json(v, max_key)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Variant { index, name, value } => {
+            let name = if name.is_empty() { index.to_string() } else { name };
+            match *value {
+                Value::Null => Json::String(name),
+                payload => {
+                    let mut object = Map::new();
+                    object.insert(name, value_to_json(payload, max_key)?);
+                    Json::Object(object)
+                }
+            }
+        }
+    })
+}
+
+// JSON object keys must be strings; scalars are stringified like serde_json does
+fn json_key(key: Value, max_key: usize) -> Result<String> {
+    Ok(match key {
+        Value::Str(s) => s,
+        Value::Char(c) => c.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::UInt(u) => u.to_string(),
+        Value::Float(f) => f.to_string(),
+        other => {
+            let key = value_to_json(other, max_key)?.to_string();
+            if key.len() > max_key {
+                return Err(Error::LimitExceeded { what: "JSON object key length", limit: max_key });
+            }
+            key
+        }
+    })
+}
+
+impl From<Value> for serde_json::Value {
+    fn from(value: Value) -> Self {
+        value_to_json(value, usize::MAX).expect("keys are unbounded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Status {
+        Active,
+        Banned { reason: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        id: u32,
+        name: String,
+        score: f64,
+        email: Option<String>,
+        status: Status,
+    }
+
+    #[test]
+    fn test_json_to_typed() {
+        let json = json!({
+            "id": 5,
+            "name": "ann",
+            "score": 2,
+            "email": null,
+            "status": { "Banned": { "reason": "spam" } }
+        });
+        let bytes = from_json(&json).unwrap();
+        let user: User = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(user.id, 5);
+        assert_eq!(user.score, 2.0);
+        assert_eq!(user.email, None);
+        assert_eq!(user.status, Status::Banned { reason: "spam".to_string() });
+
+        let json = json!({ "id": 1, "name": "b", "score": 0.5, "email": "e", "status": "Active" });
+        let user: User = crate::from_bytes(&from_json(&json).unwrap()).unwrap();
+        assert_eq!(user.status, Status::Active);
+    }
+
+    #[test]
+    fn test_typed_to_json() {
+        let user = User {
+            id: 9,
+            name: "cy".to_string(),
+            score: 1.25,
+            email: Some("c@y".to_string()),
+            status: Status::Active,
+        };
+        let bytes = crate::to_bytes_with_config(&user, Config::new().with_self_describing(true)).unwrap();
+        let json = to_json(&bytes).unwrap();
+        assert_eq!(json, serde_json::to_value(&user).unwrap());
+
+        // JSON survives a round trip through nanobit
+        assert_eq!(to_json(&from_json(&json).unwrap()).unwrap(), json);
+
+        // Plain payloads cannot be transcoded
+        assert!(to_json(&crate::to_bytes(&user).unwrap()).is_err());
+    }
+
+    // Each level of map-in-key nesting doubles the escaping of the key
+    #[test]
+    fn test_nested_composite_keys_are_bounded() {
+        let mut value = Value::Str("x".to_string());
+        for _ in 0..64 {
+            value = Value::Map(vec![(value, Value::Null)]);
+        }
+        let bytes = crate::to_bytes_with_config(&value, Config::new().with_self_describing(true)).unwrap();
+        assert!(matches!(
+            to_json(&bytes),
+            Err(Error::LimitExceeded { what: "JSON object key length", .. })
+        ));
+
+        let shallow = Value::Map(vec![(Value::Seq(vec![Value::UInt(1)]), Value::Bool(true))]);
+        let bytes = crate::to_bytes_with_config(&shallow, Config::new().with_self_describing(true)).unwrap();
+        assert_eq!(to_json(&bytes).unwrap(), serde_json::json!({ "[1]": true }));
+    }
+}
@@ -0,0 +1,136 @@
+//! Storing values in redis and sled
+//!
+//! [`Nanobit<T>`] implements the conversion traits the stores use for
+//! values, so serde types go in and come out without glue code:
+//!
+//! ```rust,ignore
+//! use nanobit::Nanobit;
+//!
+//! // redis
+//! con.set("user:1", Nanobit(&user))?;
+//! let Nanobit(user): Nanobit<User> = con.get("user:1")?;
+//!
+//! // sled
+//! tree.insert("user:1", sled::IVec::try_from(Nanobit(&user))?)?;
+//! let user = tree.get("user:1")?.map(Nanobit::<User>::try_from).transpose()?;
+//! ```
+//!
+//! Stores that take and return plain bytes, such as RocksDB, need nothing
+//! beyond [`to_bytes`](crate::to_bytes) and [`from_bytes`](crate::from_bytes).
+
+pub use crate::Nanobit;
+
+#[cfg(feature = "redis")]
+mod redis_support {
+    use redis::{ErrorKind, FromRedisValue, RedisResult, RedisWrite, ToRedisArgs, Value};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    use super::Nanobit;
+
+    impl<T: Serialize> ToRedisArgs for Nanobit<T> {
+        /// # Panics
+        ///
+        /// Panics if the value cannot be serialized, since redis argument
+        /// conversion cannot fail
+        fn write_redis_args<W>(&self, out: &mut W)
+        where
+            W: ?Sized + RedisWrite,
+        {
+            let bytes = crate::to_bytes(&self.0).expect("value cannot be serialized for redis");
+            out.write_arg(&bytes);
+        }
+    }
+
+    impl<T: DeserializeOwned> FromRedisValue for Nanobit<T> {
+        fn from_redis_value(value: &Value) -> RedisResult<Self> {
+            match value {
+                Value::BulkString(bytes) => crate::from_bytes(bytes).map(Nanobit).map_err(|e| {
+                    (ErrorKind::TypeError, "Invalid nanobit payload", e.to_string()).into()
+                }),
+                other => Err((
+                    ErrorKind::TypeError,
+                    "Response was of incompatible type",
+                    format!("Expected a bulk string, found {other:?}"),
+                )
+                    .into()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+mod sled_support {
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use sled::IVec;
+
+    use super::Nanobit;
+    use crate::error::Error;
+
+    impl<T: Serialize> TryFrom<Nanobit<T>> for IVec {
+        type Error = Error;
+
+        fn try_from(value: Nanobit<T>) -> Result<Self, Error> {
+            crate::to_bytes(&value.0).map(IVec::from)
+        }
+    }
+
+    impl<T: DeserializeOwned> TryFrom<IVec> for Nanobit<T> {
+        type Error = Error;
+
+        fn try_from(value: IVec) -> Result<Self, Error> {
+            Self::try_from(&value)
+        }
+    }
+
+    impl<T: DeserializeOwned> TryFrom<&IVec> for Nanobit<T> {
+        type Error = Error;
+
+        fn try_from(value: &IVec) -> Result<Self, Error> {
+            crate::from_bytes(value).map(Nanobit)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Session {
+        user: u64,
+        scopes: Vec<String>,
+    }
+
+    fn session() -> Session {
+        Session { user: 42, scopes: vec!["read".to_string(), "write".to_string()] }
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_redis_values() {
+        use redis::{FromRedisValue, ToRedisArgs, Value};
+
+        let args = Nanobit(session()).to_redis_args();
+        assert_eq!(args, [crate::to_bytes(&session()).unwrap()]);
+        let value = Value::BulkString(args[0].clone());
+        assert_eq!(Nanobit::<Session>::from_redis_value(&value).unwrap(), Nanobit(session()));
+
+        // Missing keys come back as nil
+        assert_eq!(Option::<Nanobit<Session>>::from_redis_value(&Value::Nil).unwrap(), None);
+        assert!(Nanobit::<Session>::from_redis_value(&Value::Int(1)).is_err());
+        assert!(Nanobit::<Session>::from_redis_value(&Value::BulkString(b"NANO".to_vec())).is_err());
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_values() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert("session", sled::IVec::try_from(Nanobit(&session())).unwrap()).unwrap();
+        let stored = db.get("session").unwrap().map(Nanobit::<Session>::try_from).transpose().unwrap();
+        assert_eq!(stored, Some(Nanobit(session())));
+        assert!(Nanobit::<Session>::try_from(sled::IVec::from(&b"NANO\x01"[..])).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Deferred decoding of nested values
+//!
+//! A [`Lazy`] field holds a complete nanobit payload, written as a byte
+//! string. Deserializing the outer value only borrows the payload; it is
+//! decoded when [`Lazy::get`] is called.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, Error as _, Visitor};
+use serde::{Serialize, Serializer};
+
+use crate::error::Result;
+use crate::header::Header;
+
+/// A value of type `T` that is decoded on demand
+///
+/// ```rust
+/// use nanobit::Lazy;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Message<'a> {
+///     topic: String,
+///     #[serde(borrow)]
+///     body: Lazy<'a, Vec<u64>>,
+/// }
+///
+/// let body = nanobit::to_bytes(&vec![1u64; 1000])?;
+/// let message = Message { topic: "metrics".into(), body: Lazy::from_encoded(&body)? };
+/// let bytes = nanobit::to_bytes(&message)?;
+///
+/// // Only the topic is decoded here
+/// let message: Message<'_> = nanobit::from_bytes(&bytes)?;
+/// if message.topic == "metrics" {
+///     assert_eq!(message.body.get()?.len(), 1000);
+/// }
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub struct Lazy<'a, T> {
+    bytes: &'a [u8],
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> Lazy<'a, T> {
+    /// Wrap an encoded payload, such as one produced by [`to_bytes`](crate::to_bytes)
+    ///
+    /// Only the header is checked; the value is not decoded.
+    pub fn from_encoded(bytes: &'a [u8]) -> Result<Self> {
+        Header::parse(bytes)?;
+        Ok(Self {
+            bytes,
+            _marker: PhantomData,
+        })
+    }
+
+    /// The encoded payload
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Decode the value
+    pub fn get(&self) -> Result<T>
+    where
+        T: Deserialize<'a>,
+    {
+        crate::from_bytes(self.bytes)
+    }
+}
+
+impl<T> Clone for Lazy<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Lazy<'_, T> {}
+
+impl<T> fmt::Debug for Lazy<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy").field("len", &self.bytes.len()).finish()
+    }
+}
+
+impl<T> Serialize for Lazy<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.bytes)
+    }
+}
+
+impl<'de: 'a, 'a, T> Deserialize<'de> for Lazy<'a, T> {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = &'de [u8];
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("borrowed bytes of an encoded payload")
+            }
+
+            fn visit_borrowed_bytes<E>(self, bytes: &'de [u8]) -> core::result::Result<Self::Value, E> {
+                Ok(bytes)
+            }
+        }
+
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        Lazy::from_encoded(bytes).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Body {
+        values: Vec<u32>,
+        note: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Envelope<'a> {
+        route: u16,
+        #[serde(borrow)]
+        body: Lazy<'a, Body>,
+    }
+
+    #[test]
+    fn test_lazy_roundtrip() {
+        let body = Body { values: (0..100).collect(), note: "hi".to_string() };
+        let encoded = crate::to_bytes(&body).unwrap();
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let envelope = Envelope { route: 7, body: Lazy::from_encoded(&encoded).unwrap() };
+            let bytes = crate::to_bytes_with_config(&envelope, config).unwrap();
+
+            let read: Envelope<'_> = crate::from_bytes(&bytes).unwrap();
+            assert_eq!(read.route, 7);
+            assert_eq!(read.body.as_bytes(), encoded.as_slice());
+            assert_eq!(read.body.get().unwrap(), body);
+        }
+
+        assert!(Lazy::<Body>::from_encoded(b"not a payload").is_err());
+    }
+}
@@ -9,7 +9,7 @@
 //! - **High performance** with minimal allocations
 //! - **Flexible compression** with LZ4 support
 //! - **Serde compatibility** for easy integration
-//! - **No unsafe code** in the core library
+//! - **No unsafe code** in the core library unless `fast-unsafe` is enabled
 //!
 //! ## Quick Start
 //!
@@ -44,36 +44,205 @@
 #[cfg(not(feature = "std"))]
 extern crate alloc;
 
+// Lets derived code refer to `::nanobit` from within this crate's tests
+#[cfg(test)]
+extern crate self as nanobit;
+
 #[cfg(not(feature = "std"))]
 use alloc::{vec, vec::Vec, string::String};
 
 pub mod error;
+pub mod config;
+pub mod header;
+pub mod checksum;
+#[cfg(feature = "crypto")]
+pub mod crypto;
 pub mod ser;
 pub mod de;
 pub mod buffer;
 pub mod compression;
+pub mod encode;
+pub mod value;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+pub mod interop;
+#[cfg(feature = "bytes")]
+pub mod shared;
+#[cfg(all(feature = "std", feature = "checksum"))]
+pub mod log;
+#[cfg(all(feature = "std", feature = "checksum"))]
+pub mod snapshot;
+#[cfg(all(feature = "std", feature = "checksum"))]
+pub mod chunk;
+#[cfg(all(feature = "std", feature = "checksum"))]
+pub mod merkle;
+#[cfg(feature = "std")]
+pub mod record_file;
+#[cfg(feature = "std")]
+pub mod fs;
+pub mod stream;
+pub mod extract;
+pub mod lazy;
+pub mod raw;
+pub mod diff;
+pub mod byte_slice;
+pub mod primitive;
+pub mod packed;
+pub mod narrow;
+pub mod timestamp;
+#[cfg(feature = "uuid")]
+pub mod uuid;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(any(feature = "rust_decimal", feature = "bigdecimal"))]
+pub mod decimal;
+pub mod collections;
+#[cfg(any(feature = "ndarray", feature = "nalgebra"))]
+pub mod matrix;
+#[cfg(any(feature = "bitflags", feature = "enumset"))]
+pub mod flags;
+pub mod niche;
+pub mod nullable;
+pub mod cow;
+pub mod owned;
+pub mod archive;
+pub mod session;
+pub mod typed;
+pub mod schema;
+pub mod codegen;
+pub mod migrate;
+pub mod debug;
+pub mod vectors;
+mod profile;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "std")]
+pub mod text;
+#[cfg(feature = "std")]
+pub mod dedup;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(all(feature = "std", feature = "checksum"))]
+pub mod patch;
+#[cfg(any(feature = "blake3", feature = "sha2"))]
+pub mod hash;
+#[cfg(feature = "bumpalo")]
+pub mod arena;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+#[cfg(feature = "tonic")]
+pub mod grpc;
+#[cfg(any(feature = "axum", feature = "actix-web"))]
+pub mod web;
+#[cfg(feature = "reqwest")]
+pub mod client;
+#[cfg(any(feature = "redis", feature = "sled"))]
+pub mod kv;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(not(feature = "metrics"))]
+mod metrics;
+mod tag;
+mod lz;
 
 #[cfg(feature = "async")]
 pub mod async_ser;
 #[cfg(feature = "async")]
 pub mod async_de;
+#[cfg(any(feature = "async", feature = "futures-io", feature = "reqwest"))]
+pub mod framed;
+#[cfg(feature = "futures-io")]
+pub mod futures;
 
 // Re-export main types
 pub use error::{Error, Result};
-pub use ser::{Serializer, to_bytes, to_writer};
-pub use de::{Deserializer, from_bytes, from_reader};
+pub use config::Config;
+pub use checksum::Checksum;
+#[cfg(feature = "checksum")]
+pub use checksum::verify_checksum;
+pub use ser::{Serializer, to_bytes, to_bytes_canonical, to_bytes_with_config, to_writer, to_bytes_from_iter};
+#[cfg(feature = "std")]
+pub use ser::to_writer_from_iter;
+pub use de::{Deserializer, SeqIter, from_bytes, from_bytes_with_config, from_bytes_seed, from_bytes_seed_with_config, from_bytes_seq_iter, from_reader};
+#[cfg(feature = "std")]
+pub use de::from_reader_seed;
 pub use buffer::{WriteBuffer, ReadBuffer};
+#[cfg(feature = "std")]
+pub use buffer::BufferPool;
+#[cfg(feature = "std")]
+pub use ser::to_bytes_pooled;
+#[cfg(feature = "bytes")]
+pub use shared::{from_shared_bytes, to_bytes_shared};
+pub use encode::{Encode, Decode, encode_to_bytes, decode_from_bytes};
+pub use value::{Value, to_value, from_value};
+pub use stream::StreamDeserializer;
+pub use extract::{extract, extract_with_config};
+pub use lazy::Lazy;
+pub use raw::RawValue;
+pub use primitive::PrimitiveSlice;
+pub use packed::{DeltaEncoded, RunLength};
+pub use narrow::AsF32;
+pub use timestamp::Timestamp;
+#[cfg(feature = "uuid")]
+pub use uuid::UuidBytes;
+#[cfg(feature = "net")]
+pub use net::CompactAddr;
+#[cfg(any(feature = "rust_decimal", feature = "bigdecimal"))]
+pub use decimal::CompactDecimal;
+pub use collections::{Bulk, OrderedMap, UnorderedSet};
+#[cfg(any(feature = "ndarray", feature = "nalgebra"))]
+pub use matrix::DenseArray;
+#[cfg(feature = "bitflags")]
+pub use flags::CompactFlags;
+#[cfg(feature = "enumset")]
+pub use flags::CompactEnumSet;
+pub use niche::NonZeroOption;
+pub use nullable::Nullable;
+pub use owned::Owned;
+pub use cow::{from_bytes_cow, from_bytes_cow_with_config, from_bytes_owned, from_bytes_owned_with_config};
+#[cfg(feature = "half")]
+pub use narrow::AsF16;
+pub use session::{SessionDecoder, SessionEncoder};
+pub use typed::{from_bytes_expect, from_bytes_expect_tag, peek_type, to_bytes_tagged, to_bytes_with_type_tag};
+#[cfg(feature = "std")]
+pub use dedup::Dedup;
+pub use diff::{diff, diff_as, DiffKind, Difference};
+#[cfg(all(feature = "std", feature = "checksum"))]
+pub use patch::{apply_patch, create_patch};
+#[cfg(any(feature = "blake3", feature = "sha2"))]
+pub use hash::hash;
+#[cfg(feature = "parallel")]
+pub use parallel::{from_bytes_parallel, to_bytes_parallel};
+#[cfg(feature = "std")]
+pub use stream::StreamSerializer;
+
+#[cfg(feature = "derive")]
+pub use nanobit_derive::NanoBit;
+#[cfg(all(test, not(feature = "derive")))]
+pub(crate) use nanobit_derive::NanoBit;
 
 #[cfg(feature = "async")]
-pub use async_ser::{AsyncSerializer, to_bytes_async, to_writer_async};
+pub use async_ser::{AsyncSerializer, to_bytes_async, to_writer_async, to_writer_from_iter_async};
 #[cfg(feature = "async")]
 pub use async_de::{AsyncDeserializer, from_bytes_async, from_reader_async};
+#[cfg(feature = "async")]
+pub use fs::{from_file_async, to_file_async};
+#[cfg(feature = "async")]
+pub use framed::{into_message_sink, into_message_stream, MessageSink, MessageStream};
 
 // Enhanced multi-format compression functionality
 pub use compression::{
-    CompressionFormat, CompressionLevel, 
-    compress, decompress, compress_default, is_serialized
+    CompressionFormat, CompressionLevel, CompressionPolicy,
+    compress, decompress, compress_into, decompress_into, compress_default, compress_with_policy,
+    decompress_with_limit, decompress_into_with_limit, decompress_legacy, is_serialized, sniff, PayloadInfo
 };
+#[cfg(feature = "std")]
+pub use compression::{CompressWriter, DecompressReader};
 
 /// Magic bytes to identify NanoBit format
 pub const MAGIC: &[u8] = b"NANO";
@@ -81,9 +250,20 @@ pub const MAGIC: &[u8] = b"NANO";
 /// Current format version
 pub const VERSION: u8 = 1;
 
+/// Format version used when the header carries a flags byte
+pub const FLAGS_VERSION: u8 = 2;
+
 /// Default buffer size for serialization
 pub const DEFAULT_BUFFER_SIZE: usize = 8192;
 
+/// A value that integrations carry as a nanobit payload
+///
+/// The web framework and key-value store features implement their
+/// libraries' conversion traits for this wrapper, the way `Json<T>` works
+/// for JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Nanobit<T>(pub T);
+
 /// Serialize a value to bytes using the default configuration
 pub fn serialize<T>(value: &T) -> Result<Vec<u8>>
 where
@@ -110,6 +290,57 @@ where
     compress(&serialized, CompressionFormat::default(), level)
 }
 
+/// Serialize a value through a streaming compressor directly into `writer`
+///
+/// Output is compressed as it is produced instead of after serializing the
+/// whole value. Read it back with [`from_reader_compressed`].
+#[cfg(all(feature = "std", any(feature = "compression", feature = "multi-compression")))]
+pub fn to_writer_compressed<W, T>(
+    writer: W,
+    value: &T,
+    format: CompressionFormat,
+    level: CompressionLevel,
+) -> Result<W>
+where
+    W: std::io::Write,
+    T: serde::Serialize,
+{
+    let mut writer = writer;
+    writer.write_all(&compression::container_header(format))?;
+    let mut encoder = CompressWriter::new(writer, format, level)?;
+    let mut serializer = Serializer::to_stream(&mut encoder, Config::new())?;
+    value.serialize(&mut serializer)?;
+    serializer.finish()?;
+    encoder.finish()
+}
+
+/// Deserialize a value written by [`to_writer_compressed`], decompressing as
+/// it is read
+///
+/// The compression format is read from the container header.
+#[cfg(all(feature = "std", any(feature = "compression", feature = "multi-compression")))]
+pub fn from_reader_compressed<R, T>(reader: R) -> Result<T>
+where
+    R: std::io::Read,
+    T: for<'de> serde::Deserialize<'de>,
+{
+    from_reader(DecompressReader::from_container(reader)?)
+}
+
+/// Deserialize a value written by [`to_writer_compressed`], refusing to
+/// decompress more than `max_output_bytes`
+///
+/// Fails with [`Error::LimitExceeded`] as soon as the limit is passed, so it
+/// is safe to use on untrusted input.
+#[cfg(all(feature = "std", any(feature = "compression", feature = "multi-compression")))]
+pub fn from_reader_compressed_with_limit<R, T>(reader: R, max_output_bytes: usize) -> Result<T>
+where
+    R: std::io::Read,
+    T: for<'de> serde::Deserialize<'de>,
+{
+    from_reader(DecompressReader::from_container(reader)?.with_limit(max_output_bytes))
+}
+
 /// Deserialize compressed data
 #[cfg(any(feature = "compression", feature = "multi-compression"))]
 pub fn deserialize_compressed<T>(bytes: &[u8]) -> Result<T>
@@ -121,6 +352,20 @@ where
     from_bytes(borrowed_bytes)
 }
 
+/// Deserialize compressed data, refusing to decompress more than
+/// `max_output_bytes`
+///
+/// Fails with [`Error::LimitExceeded`] instead of allocating whatever the
+/// compressed stream claims, so it is safe to use on untrusted input.
+#[cfg(any(feature = "compression", feature = "multi-compression"))]
+pub fn deserialize_compressed_with_limit<T>(bytes: &[u8], max_output_bytes: usize) -> Result<T>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    let decompressed = decompress_with_limit(bytes, max_output_bytes)?;
+    from_bytes(&decompressed)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,8 +413,8 @@ mod tests {
     fn test_primitive_types() {
         assert_eq!(42u32, deserialize::<u32>(&serialize(&42u32).unwrap()).unwrap());
         assert_eq!(-100i64, deserialize::<i64>(&serialize(&-100i64).unwrap()).unwrap());
-        assert_eq!(3.14f64, deserialize::<f64>(&serialize(&3.14f64).unwrap()).unwrap());
-        assert_eq!(true, deserialize::<bool>(&serialize(&true).unwrap()).unwrap());
+        assert_eq!(std::f64::consts::PI, deserialize::<f64>(&serialize(&std::f64::consts::PI).unwrap()).unwrap());
+        assert!(deserialize::<bool>(&serialize(&true).unwrap()).unwrap());
         assert_eq!("hello", deserialize::<&str>(&serialize(&"hello").unwrap()).unwrap());
     }
 
@@ -192,4 +437,65 @@ mod tests {
         let uncompressed = serialize(&data).unwrap();
         assert!(compressed.len() < uncompressed.len());
     }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decompression_limit() {
+        let data = vec![0u8; 100_000];
+        for format in [CompressionFormat::LZ4, CompressionFormat::NanoBit, CompressionFormat::Stored] {
+            let compressed = compress(&data, format, CompressionLevel::Default).unwrap();
+            assert_eq!(decompress_with_limit(&compressed, data.len()).unwrap(), data);
+            assert!(matches!(
+                decompress_with_limit(&compressed, data.len() - 1),
+                Err(Error::LimitExceeded { limit: 99_999, .. })
+            ));
+        }
+
+        let compressed = serialize_compressed(&data, CompressionLevel::Default).unwrap();
+        let decoded: Vec<u8> = deserialize_compressed_with_limit(&compressed, 200_000).unwrap();
+        assert_eq!(decoded, data);
+        assert!(matches!(
+            deserialize_compressed_with_limit::<Vec<u8>>(&compressed, 1024),
+            Err(Error::LimitExceeded { .. })
+        ));
+
+        // Legacy LZ4 blocks are checked against their declared size
+        let legacy = lz4_flex::compress_prepend_size(&data);
+        assert!(matches!(decompress_legacy(&legacy, 1024), Err(Error::LimitExceeded { .. })));
+    }
+
+    #[cfg(all(feature = "compression", feature = "multi-compression"))]
+    #[test]
+    fn test_streaming_compression() {
+        // Large enough to flush through the encoder several times
+        let data: Vec<TestStruct> = (0..2000)
+            .map(|i| TestStruct {
+                name: format!("item-{i}"),
+                value: i,
+                items: vec![i as i32; 64],
+                flag: i % 2 == 0,
+            })
+            .collect();
+        let uncompressed = serialize(&data).unwrap();
+
+        for format in [CompressionFormat::LZ4, CompressionFormat::ZSTD, CompressionFormat::Snappy, CompressionFormat::NanoBit] {
+            let compressed =
+                to_writer_compressed(Vec::new(), &data, format, CompressionLevel::Fastest).unwrap();
+            assert!(compressed.len() < uncompressed.len());
+
+            let decoded: Vec<TestStruct> = from_reader_compressed(compressed.as_slice()).unwrap();
+            assert_eq!(decoded, data);
+
+            // Streamed and in-memory compression share the container format
+            assert_eq!(decompress(&compressed).unwrap(), uncompressed);
+
+            let decoded: Vec<TestStruct> =
+                from_reader_compressed_with_limit(compressed.as_slice(), uncompressed.len()).unwrap();
+            assert_eq!(decoded, data);
+            assert!(matches!(
+                from_reader_compressed_with_limit::<_, Vec<TestStruct>>(compressed.as_slice(), 4096),
+                Err(Error::LimitExceeded { limit: 4096, .. })
+            ));
+        }
+    }
 }
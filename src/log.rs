@@ -0,0 +1,859 @@
+//! Append-only record logs
+//!
+//! A log is a sequence of framed nanobit payloads:
+//!
+//! ```text
+//! [marker: "NBLF"] [length: u32 LE] [crc32c of payload: u32 LE] [payload]
+//! ```
+//!
+//! The marker lets a reader find the next frame after damage by searching
+//! for it, rather than trying every byte offset.
+//!
+//! A crash while appending leaves a partial frame at the end of the log.
+//! [`LogReader`] stops cleanly at such a torn tail and reports where the
+//! valid records end, so the log can be truncated there and reopened.
+//! Damage before the end is an error, unless
+//! [recovery](LogReader::with_recovery) is enabled: the reader then scans
+//! forward for the next intact frame and reports the bytes it skipped.
+//!
+//! Durability is a trade against throughput. A [`LogWriter`] syncs on the
+//! schedule of its [`SyncPolicy`], and [`LogWriter::append_batch`] makes a
+//! batch durable with one sync. [`GroupCommitLog`] lets many threads
+//! append at once, each waiting until its record is on disk, while the
+//! records that arrive during one sync share the next.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::checksum::Checksum;
+use crate::error::{Error, Result};
+
+/// Bytes of framing before each record
+pub const FRAME_HEADER_LEN: usize = 12;
+
+/// Marker starting every frame
+pub const FRAME_MARKER: &[u8; 4] = b"NBLF";
+
+// Bytes read at a time while searching for a frame marker after damage
+const SCAN_CHUNK: usize = 64 * 1024;
+
+/// When a [`LogWriter`] makes appended records durable
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Only flush; durability is left to the OS
+    #[default]
+    Never,
+    /// Sync after every record
+    Always,
+    /// Sync after every `n` records
+    Every(u32),
+    /// Sync on the first append once the interval has passed since the
+    /// last sync
+    ///
+    /// Records appended at the end of a burst stay unsynced until the next
+    /// append; call [`LogWriter::sync_if_due`] from a timer to bound how
+    /// long they wait.
+    Interval(Duration),
+}
+
+/// Appends framed records to a writer
+pub struct LogWriter<W: Write> {
+    writer: W,
+    position: u64,
+    policy: SyncPolicy,
+    unsynced: u32,
+    last_sync: Instant,
+    sync: fn(&mut W) -> std::io::Result<()>,
+}
+
+impl<W: Write> LogWriter<W> {
+    /// Create a log writer appending to `writer`
+    ///
+    /// Syncing a generic writer only flushes it; use [`LogWriter::open`] for
+    /// files that should be synced to disk.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            position: 0,
+            policy: SyncPolicy::Never,
+            unsynced: 0,
+            last_sync: Instant::now(),
+            sync: |writer| writer.flush(),
+        }
+    }
+
+    /// Set when appended records are synced
+    pub fn with_sync_policy(mut self, policy: SyncPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Serialize a value and append it, returning the offset of its frame
+    pub fn append<T>(&mut self, value: &T) -> Result<u64>
+    where
+        T: Serialize,
+    {
+        self.append_raw(&crate::to_bytes(value)?)
+    }
+
+    /// Append an already serialized payload, returning the offset of its frame
+    pub fn append_raw(&mut self, payload: &[u8]) -> Result<u64> {
+        let offset = self.write_frame(payload)?;
+        self.sync_if_due()?;
+        Ok(offset)
+    }
+
+    /// Serialize and append several values, syncing at most once
+    ///
+    /// The batch counts as one append for the sync policy, so with
+    /// [`SyncPolicy::Always`] the whole batch is made durable by a single
+    /// sync. Returns the offset of the first frame.
+    pub fn append_batch<'a, T, I>(&mut self, values: I) -> Result<u64>
+    where
+        T: Serialize + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        let offset = self.position;
+        for value in values {
+            self.write_frame(&crate::to_bytes(value)?)?;
+        }
+        self.sync_if_due()?;
+        Ok(offset)
+    }
+
+    // Write one frame without syncing
+    fn write_frame(&mut self, payload: &[u8]) -> Result<u64> {
+        let len = u32::try_from(payload.len())
+            .map_err(|_| Error::InvalidFormat("Record too large for log frame".to_string()))?;
+        let mut frame = [0u8; FRAME_HEADER_LEN];
+        frame[..4].copy_from_slice(FRAME_MARKER);
+        frame[4..8].copy_from_slice(&len.to_le_bytes());
+        frame[8..].copy_from_slice(&(Checksum::Crc32c.compute(payload) as u32).to_le_bytes());
+        self.writer.write_all(&frame)?;
+        self.writer.write_all(payload)?;
+
+        let offset = self.position;
+        self.position += (FRAME_HEADER_LEN + payload.len()) as u64;
+        self.unsynced = self.unsynced.saturating_add(1);
+        Ok(offset)
+    }
+
+    /// Sync if the policy calls for it, returning whether it did
+    pub fn sync_if_due(&mut self) -> Result<bool> {
+        let due = self.unsynced > 0
+            && match self.policy {
+                SyncPolicy::Never => false,
+                SyncPolicy::Always => true,
+                SyncPolicy::Every(n) => self.unsynced >= n.max(1),
+                SyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+            };
+        if due {
+            self.sync()?;
+        }
+        Ok(due)
+    }
+
+    /// Flush and sync all appended records
+    pub fn sync(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        (self.sync)(&mut self.writer)?;
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// When appended records are synced
+    #[inline]
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// Records appended since the last sync
+    #[inline]
+    pub fn unsynced(&self) -> u32 {
+        self.unsynced
+    }
+
+    /// Bytes written since the writer was created
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Flush and return the inner writer
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl LogWriter<File> {
+    /// Open a log file for appending, creating it if needed
+    ///
+    /// Syncing calls [`File::sync_data`].
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let position = file.metadata()?.len();
+        let mut writer = Self::new(file);
+        writer.position = position;
+        writer.sync = |file| file.sync_data();
+        Ok(writer)
+    }
+}
+
+/// A log file shared between threads that syncs appends in groups
+///
+/// Each [`append`](Self::append) returns once its record is durable. One
+/// appender syncs at a time, without holding the log, so records appended
+/// meanwhile are written straight away and made durable together by the
+/// next sync: under load, many records share each sync. A failed write
+/// or sync leaves the end of the file unknown, so every later append
+/// returns the same error. Before appending to the file again, read it
+/// with a [`LogReader`] and cut it at [`position`](LogReader::position)
+/// if it ends in a [torn tail](LogReader::torn_tail).
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+/// use nanobit::log::GroupCommitLog;
+///
+/// let log = Arc::new(GroupCommitLog::open("queue.log")?);
+/// let workers: Vec<_> = (0..8u32)
+///     .map(|worker| {
+///         let log = Arc::clone(&log);
+///         std::thread::spawn(move || log.append(&worker))
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap()?;
+/// }
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub struct GroupCommitLog {
+    state: Mutex<GroupState>,
+    synced: Condvar,
+    // Second handle to the file, synced outside the lock
+    file: File,
+}
+
+struct GroupState {
+    writer: LogWriter<File>,
+    // Records appended and made durable, counted since opening
+    appended: u64,
+    durable: u64,
+    syncing: bool,
+    syncs: u64,
+    // A failed write or sync leaves the state of the file unknown
+    failed: Option<Error>,
+}
+
+impl GroupCommitLog {
+    /// Open a log file for appending, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let writer = LogWriter::open(path)?;
+        let file = writer.writer.try_clone()?;
+        Ok(Self {
+            state: Mutex::new(GroupState {
+                writer,
+                appended: 0,
+                durable: 0,
+                syncing: false,
+                syncs: 0,
+                failed: None,
+            }),
+            synced: Condvar::new(),
+            file,
+        })
+    }
+
+    /// Serialize a value and append it, returning the offset of its frame
+    /// once it is durable
+    pub fn append<T>(&self, value: &T) -> Result<u64>
+    where
+        T: Serialize,
+    {
+        self.append_raw(&crate::to_bytes(value)?)
+    }
+
+    /// Append an already serialized payload, returning the offset of its
+    /// frame once it is durable
+    pub fn append_raw(&self, payload: &[u8]) -> Result<u64> {
+        let mut state = self.lock();
+        if let Some(error) = &state.failed {
+            return Err(error.clone());
+        }
+        let offset = match state.writer.write_frame(payload) {
+            Ok(offset) => offset,
+            Err(error) => {
+                // A partly written frame would sit in front of later ones
+                if matches!(error, Error::Io(_)) {
+                    state.failed = Some(error.clone());
+                }
+                return Err(error);
+            }
+        };
+        state.appended += 1;
+        let ticket = state.appended;
+
+        loop {
+            if let Some(error) = &state.failed {
+                return Err(error.clone());
+            }
+            if state.durable >= ticket {
+                return Ok(offset);
+            }
+            if state.syncing {
+                state = self.synced.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            }
+
+            // Lead a sync covering everything appended so far
+            state.syncing = true;
+            let target = state.appended;
+            drop(state);
+            let result = self.file.sync_data();
+            state = self.lock();
+            state.syncing = false;
+            match result {
+                Ok(()) => {
+                    state.durable = target;
+                    state.syncs += 1;
+                }
+                Err(error) => state.failed = Some(error.into()),
+            }
+            self.synced.notify_all();
+        }
+    }
+
+    /// Bytes in the log, including records not yet durable
+    pub fn position(&self) -> u64 {
+        self.lock().writer.position()
+    }
+
+    /// Number of syncs performed since opening
+    ///
+    /// Fewer syncs than appends means appends were grouped.
+    pub fn syncs(&self) -> u64 {
+        self.lock().syncs
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GroupState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Reads framed records back from a log
+pub struct LogReader<R: Read> {
+    source: Source<R>,
+    position: u64,
+    torn: bool,
+    // Longest record considered while resynchronizing, if recovering
+    recovery: Option<u32>,
+    skipped: Vec<Range<u64>>,
+}
+
+impl<R: Read> LogReader<R> {
+    /// Create a log reader reading from `reader`
+    pub fn new(reader: R) -> Self {
+        Self {
+            source: Source { reader, pending: VecDeque::new(), offset: 0 },
+            position: 0,
+            torn: false,
+            recovery: None,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Skip damaged regions instead of failing on them
+    ///
+    /// After a damaged record the reader searches forward for the next
+    /// [frame marker](FRAME_MARKER) whose frame is at most
+    /// `max_record_len` long and has a matching checksum, and resumes
+    /// there. The search reads the damaged region once, checking
+    /// checksums only where the marker appears. The skipped byte ranges
+    /// are listed by [`skipped`](Self::skipped). Damage with no intact
+    /// frame after it is a torn tail, as without recovery.
+    ///
+    /// A record is only skipped, never invented, unless damage happens to
+    /// produce a marker followed by a matching length and checksum.
+    pub fn with_recovery(mut self, max_record_len: u32) -> Self {
+        self.recovery = Some(max_record_len);
+        self
+    }
+
+    /// Read the next record's payload, or `None` at the end of the log
+    ///
+    /// A truncated or half-written final record also ends the log; see
+    /// [`torn_tail`](Self::torn_tail). A damaged record followed by further
+    /// data is reported as an error, or skipped with
+    /// [recovery](Self::with_recovery).
+    pub fn next_raw(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.torn {
+            return Ok(None);
+        }
+
+        let start = self.source.offset;
+        let mut frame = [0u8; FRAME_HEADER_LEN];
+        let read = read_full(&mut self.source, &mut frame)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < FRAME_HEADER_LEN {
+            return self.stop_at_torn_tail();
+        }
+
+        let (len, expected) = frame_header(&frame);
+        let marked = frame.starts_with(FRAME_MARKER);
+        // Grows with the data actually present, not the claimed length
+        let mut payload = Vec::new();
+        if marked {
+            (&mut self.source).take(len as u64).read_to_end(&mut payload)?;
+        }
+        let complete = payload.len() == len as usize;
+        let actual = Checksum::Crc32c.compute(&payload) as u32;
+        if marked && complete && actual == expected {
+            self.position = self.source.offset;
+            return Ok(Some(payload));
+        }
+
+        let Some(max_record_len) = self.recovery else {
+            // Damage in the final record is a torn write; anywhere else it is corruption
+            if (marked && !complete) || read_full(&mut self.source, &mut [0u8; 1])? == 0 {
+                return self.stop_at_torn_tail();
+            }
+            if !marked {
+                return Err(Error::InvalidFormat(format!("Missing log frame marker at offset {start}")));
+            }
+            return Err(Error::ChecksumMismatch { expected: expected as u64, actual: actual as u64 });
+        };
+
+        let mut window = frame[1..].to_vec();
+        window.extend_from_slice(&payload);
+        self.resynchronize(start, window, max_record_len)
+    }
+
+    // Search for the next intact frame after damage at `damaged`, with
+    // `window` holding the bytes read from just after it
+    fn resynchronize(
+        &mut self,
+        damaged: u64,
+        mut window: Vec<u8>,
+        max_record_len: u32,
+    ) -> Result<Option<Vec<u8>>> {
+        // Offset in the log of `window[0]`
+        let mut at = damaged + 1;
+        loop {
+            let Some(found) = window.windows(FRAME_MARKER.len()).position(|bytes| bytes == FRAME_MARKER) else {
+                // Keep a tail that may hold the start of a marker
+                let drop = window.len().saturating_sub(FRAME_MARKER.len() - 1);
+                window.drain(..drop);
+                at += drop as u64;
+                let kept = window.len();
+                if self.fill(&mut window, kept + SCAN_CHUNK)? == kept {
+                    return self.stop_at_torn_tail();
+                }
+                continue;
+            };
+            window.drain(..found);
+            at += found as u64;
+
+            let end = if self.fill(&mut window, FRAME_HEADER_LEN)? >= FRAME_HEADER_LEN {
+                let (len, expected) = frame_header(&window);
+                let end = FRAME_HEADER_LEN + len as usize;
+                let intact = len <= max_record_len
+                    && self.fill(&mut window, end)? >= end
+                    && Checksum::Crc32c.compute(&window[FRAME_HEADER_LEN..end]) as u32 == expected;
+                intact.then_some(end)
+            } else {
+                None
+            };
+            if let Some(end) = end {
+                let payload = window[FRAME_HEADER_LEN..end].to_vec();
+                self.source.unread(&window[end..]);
+                self.skipped.push(damaged..at);
+                self.position = self.source.offset;
+                return Ok(Some(payload));
+            }
+            // Not a frame after all; search on past this marker
+            window.drain(..1);
+            at += 1;
+        }
+    }
+
+    // Read into `window` until it holds `len` bytes or the input ends,
+    // returning its length
+    fn fill(&mut self, window: &mut Vec<u8>, len: usize) -> Result<usize> {
+        if window.len() < len {
+            let missing = (len - window.len()) as u64;
+            (&mut self.source).take(missing).read_to_end(window)?;
+        }
+        Ok(window.len())
+    }
+
+    /// Read and deserialize the next record
+    pub fn next_record<T>(&mut self) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.next_raw()? {
+            Some(payload) => crate::from_bytes(&payload).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Iterate over the remaining records as values of type `T`
+    pub fn records<T>(self) -> Records<R, T>
+    where
+        T: DeserializeOwned,
+    {
+        Records {
+            reader: self,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Byte offset just past the last valid record read
+    ///
+    /// After a torn tail, truncating the log to this length removes it.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Whether reading stopped at a truncated or half-written record
+    #[inline]
+    pub fn torn_tail(&self) -> bool {
+        self.torn
+    }
+
+    /// Byte ranges skipped as damaged, with [recovery](Self::with_recovery)
+    #[inline]
+    pub fn skipped(&self) -> &[Range<u64>] {
+        &self.skipped
+    }
+
+    fn stop_at_torn_tail(&mut self) -> Result<Option<Vec<u8>>> {
+        self.torn = true;
+        Ok(None)
+    }
+}
+
+impl LogReader<std::io::BufReader<File>> {
+    /// Open a log file for reading
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(std::io::BufReader::new(File::open(path)?)))
+    }
+}
+
+/// Iterator over the records of a log, created by [`LogReader::records`]
+pub struct Records<R: Read, T> {
+    reader: LogReader<R>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T> Records<R, T> {
+    /// The underlying reader, for its position and torn tail state
+    pub fn reader(&self) -> &LogReader<R> {
+        &self.reader
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for Records<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.next_record().transpose()
+    }
+}
+
+// The input of a log reader, with bytes handed back for rescanning read
+// before the rest
+struct Source<R> {
+    reader: R,
+    pending: VecDeque<u8>,
+    // Offset in the log of the next byte read
+    offset: u64,
+}
+
+impl<R> Source<R> {
+    // Hand back bytes just read, to be read again next
+    fn unread(&mut self, bytes: &[u8]) {
+        self.offset -= bytes.len() as u64;
+        let mut pending = VecDeque::from(bytes.to_vec());
+        pending.append(&mut self.pending);
+        self.pending = pending;
+    }
+}
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = if self.pending.is_empty() {
+            self.reader.read(buf)?
+        } else {
+            self.pending.read(buf)?
+        };
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+// Length and checksum from a frame header
+fn frame_header(frame: &[u8]) -> (u32, u32) {
+    let len = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+    let checksum = u32::from_le_bytes([frame[8], frame[9], frame[10], frame[11]]);
+    (len, checksum)
+}
+
+// Read until `buf` is full or the reader is exhausted
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Event {
+        id: u64,
+        name: String,
+    }
+
+    fn events() -> Vec<Event> {
+        (0..5).map(|id| Event { id, name: format!("event-{id}") }).collect()
+    }
+
+    fn write_log() -> Vec<u8> {
+        let mut writer = LogWriter::new(Vec::new()).with_sync_policy(SyncPolicy::Every(2));
+        for event in events() {
+            writer.append(&event).unwrap();
+        }
+        writer.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_log_roundtrip() {
+        let log = write_log();
+        let records = LogReader::new(log.as_slice()).records::<Event>();
+        let read: Vec<Event> = records.collect::<Result<_>>().unwrap();
+        assert_eq!(read, events());
+
+        let mut reader = LogReader::new(log.as_slice());
+        while reader.next_raw().unwrap().is_some() {}
+        assert_eq!(reader.position(), log.len() as u64);
+        assert!(!reader.torn_tail());
+    }
+
+    #[test]
+    fn test_sync_policies() {
+        let appended = |policy: SyncPolicy, count: u32| {
+            let mut writer = LogWriter::new(Vec::new()).with_sync_policy(policy);
+            for event in events().iter().take(count as usize) {
+                writer.append(event).unwrap();
+            }
+            writer.unsynced()
+        };
+        assert_eq!(appended(SyncPolicy::Never, 5), 5);
+        assert_eq!(appended(SyncPolicy::Always, 5), 0);
+        assert_eq!(appended(SyncPolicy::Every(2), 5), 1);
+        assert_eq!(appended(SyncPolicy::Interval(Duration::ZERO), 5), 0);
+        assert_eq!(appended(SyncPolicy::Interval(Duration::from_secs(3600)), 5), 5);
+
+        let mut writer = LogWriter::new(Vec::new()).with_sync_policy(SyncPolicy::Interval(Duration::from_millis(1)));
+        assert!(!writer.sync_if_due().unwrap());
+        writer.append(&events()[0]).unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(writer.sync_if_due().unwrap());
+        assert_eq!(writer.unsynced(), 0);
+
+        // A batch is framed like separate appends and synced once
+        let mut writer = LogWriter::new(Vec::new()).with_sync_policy(SyncPolicy::Every(2));
+        assert_eq!(writer.append_batch(&events()).unwrap(), 0);
+        assert_eq!(writer.unsynced(), 0);
+        assert_eq!(writer.into_inner().unwrap(), write_log());
+    }
+
+    #[test]
+    fn test_group_commit() {
+        let path = std::env::temp_dir().join(format!("nanobit-group-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let log = std::sync::Arc::new(GroupCommitLog::open(&path).unwrap());
+
+        let threads: Vec<_> = (0..8u64)
+            .map(|thread| {
+                let log = std::sync::Arc::clone(&log);
+                std::thread::spawn(move || {
+                    for id in 0..25 {
+                        log.append(&Event { id: thread * 100 + id, name: String::new() }).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(log.syncs() >= 1 && log.syncs() <= 200);
+
+        let mut ids: Vec<u64> = LogReader::open(&path)
+            .unwrap()
+            .records::<Event>()
+            .map(|event| event.unwrap().id)
+            .collect();
+        ids.sort_unstable();
+        let expected: Vec<u64> = (0..8).flat_map(|thread| (0..25).map(move |id| thread * 100 + id)).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(log.position(), std::fs::metadata(&path).unwrap().len());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_group_commit_poisoned_by_failed_write() {
+        let path = std::env::temp_dir().join(format!("nanobit-group-failed-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let log = GroupCommitLog::open(&path).unwrap();
+        log.append(&events()[0]).unwrap();
+
+        // Writes through a read-only handle fail
+        log.lock().writer = LogWriter::new(File::open(&path).unwrap());
+        assert!(matches!(log.append(&events()[1]), Err(Error::Io(_))));
+
+        // Later appends fail even once writes would succeed again
+        log.lock().writer = LogWriter::open(&path).unwrap();
+        assert!(matches!(log.append(&events()[2]), Err(Error::Io(_))));
+        let records: Vec<Event> = LogReader::open(&path).unwrap().records().collect::<Result<_>>().unwrap();
+        assert_eq!(records, events()[..1]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_tail_stops_cleanly() {
+        let log = write_log();
+        let last_record = {
+            let mut reader = LogReader::new(log.as_slice());
+            for _ in 0..4 {
+                reader.next_raw().unwrap();
+            }
+            reader.position() as usize
+        };
+
+        for cut in last_record + 1..log.len() {
+            let mut reader = LogReader::new(&log[..cut]);
+            let mut count = 0;
+            while reader.next_record::<Event>().unwrap().is_some() {
+                count += 1;
+            }
+            assert_eq!(count, 4);
+            assert!(reader.torn_tail());
+            assert_eq!(reader.position() as usize, last_record);
+        }
+
+        // A garbled final record is a torn write too
+        let mut garbled = log.clone();
+        *garbled.last_mut().unwrap() ^= 0xFF;
+        let read: Vec<Event> = LogReader::new(garbled.as_slice()).records().collect::<Result<_>>().unwrap();
+        assert_eq!(read.len(), 4);
+
+        // But damage before the end is corruption
+        let mut corrupt = log.clone();
+        corrupt[FRAME_HEADER_LEN + 6] ^= 0xFF;
+        let mut reader = LogReader::new(corrupt.as_slice());
+        assert!(matches!(reader.next_raw(), Err(Error::ChecksumMismatch { .. })));
+        let mut corrupt = log.clone();
+        corrupt[1] ^= 0xFF;
+        let mut reader = LogReader::new(corrupt.as_slice());
+        assert!(matches!(reader.next_raw(), Err(Error::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_recovery_resynchronizes() {
+        let log = write_log();
+        let mut frames = Vec::new();
+        let mut reader = LogReader::new(log.as_slice());
+        while reader.next_raw().unwrap().is_some() {
+            frames.push(reader.position() as usize);
+        }
+        fn read(bytes: &[u8]) -> (Vec<u64>, LogReader<&[u8]>) {
+            let mut reader = LogReader::new(bytes).with_recovery(1 << 20);
+            let mut ids = Vec::new();
+            while let Some(event) = reader.next_record::<Event>().unwrap() {
+                ids.push(event.id);
+            }
+            (ids, reader)
+        }
+
+        // A damaged payload in the second record
+        let mut corrupt = log.clone();
+        corrupt[frames[0] + FRAME_HEADER_LEN + 6] ^= 0xFF;
+        let (ids, reader) = read(&corrupt);
+        assert_eq!(ids, [0, 2, 3, 4]);
+        assert_eq!(reader.skipped().len(), 1);
+        assert_eq!(reader.skipped()[0], frames[0] as u64..frames[1] as u64);
+        assert!(!reader.torn_tail());
+
+        // A length claiming the rest of the log, and garbage spliced in
+        let mut corrupt = log.clone();
+        corrupt[frames[1] + 4..frames[1] + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        corrupt.splice(frames[3]..frames[3], [0u8, 0xAB, 0, 0, 0, 0, 0, 0, 0, 0, 7]);
+        let (ids, reader) = read(&corrupt);
+        assert_eq!(ids, [0, 1, 3, 4]);
+        assert_eq!(
+            reader.skipped(),
+            [frames[1] as u64..frames[2] as u64, frames[3] as u64..frames[3] as u64 + 11]
+        );
+        assert_eq!(reader.position(), corrupt.len() as u64);
+
+        // Damage with nothing intact after it is still a torn tail
+        let mut corrupt = log.clone();
+        corrupt[frames[3] + 2] ^= 0xFF;
+        let (ids, reader) = read(&corrupt);
+        assert_eq!(ids, [0, 1, 2, 3]);
+        assert!(reader.skipped().is_empty());
+        assert!(reader.torn_tail());
+        assert_eq!(reader.position(), frames[3] as u64);
+
+        // A long run of garbage, with stray markers, is searched in one pass
+        let mut corrupt = log.clone();
+        let mut state = 1u64;
+        let garbage: Vec<u8> = (0..4 << 20)
+            .map(|i| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                if i % 100_000 < 4 { FRAME_MARKER[i % 100_000] } else { (state >> 56) as u8 }
+            })
+            .collect();
+        corrupt.splice(frames[2]..frames[2], garbage);
+        let (ids, reader) = read(&corrupt);
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+        assert_eq!(reader.skipped().len(), 1);
+        assert_eq!(reader.skipped()[0], frames[2] as u64..frames[2] as u64 + (4 << 20));
+
+        // Empty records are found again, zeroed bytes are not taken for them
+        let mut writer = LogWriter::new(Vec::new());
+        writer.append_raw(&[]).unwrap();
+        let empty = writer.into_inner().unwrap();
+        let mut corrupt = vec![0u8; 100];
+        corrupt.extend_from_slice(&empty);
+        let mut reader = LogReader::new(corrupt.as_slice()).with_recovery(1 << 20);
+        assert_eq!(reader.next_raw().unwrap(), Some(Vec::new()));
+        assert_eq!(reader.skipped().len(), 1);
+        assert_eq!(reader.skipped()[0], 0..100);
+        assert_eq!(reader.next_raw().unwrap(), None);
+    }
+}
@@ -0,0 +1,335 @@
+//! Dependency-free LZ77 compression used by [`CompressionFormat::NanoBit`]
+//!
+//! A block starts with the uncompressed length as a varint, followed by
+//! sequences of:
+//!
+//! ```text
+//! [literal count: varint] [literals] [match length - 4: varint] [offset: varint]
+//! ```
+//!
+//! The last sequence stops after its literals once the uncompressed length
+//! is reached. Lengths and offsets are varints, so the short matches common
+//! in nanobit payloads (repeated field names, small integers) cost two or
+//! three bytes.
+//!
+//! A compressed container holds a stream of blocks of at most
+//! [`BLOCK_LEN`] uncompressed bytes, each prefixed with its compressed
+//! length, so streams are encoded and decoded one block at a time:
+//!
+//! ```text
+//! [compressed length: u32 LE] [block] [compressed length: u32 LE] [block] ...
+//! ```
+//!
+//! [`CompressionFormat::NanoBit`]: crate::CompressionFormat::NanoBit
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec, string::ToString};
+
+use crate::buffer::ReadBuffer;
+use crate::compression::CompressionLevel;
+use crate::error::{Error, Result};
+
+/// Shortest match worth encoding
+const MIN_MATCH: usize = 4;
+
+/// How far back a match may start
+const WINDOW: usize = 1 << 16;
+
+const HASH_BITS: u32 = 14;
+
+/// Marks an empty hash chain slot
+const EMPTY: u32 = u32::MAX;
+
+/// Most uncompressed bytes in one block of a stream
+pub(crate) const BLOCK_LEN: usize = 64 * 1024;
+
+/// Bytes before each block of a stream
+pub(crate) const BLOCK_HEADER_LEN: usize = 4;
+
+/// Longest compressed block of a stream: a four byte match costs at most
+/// five bytes, plus the varints of the block and its last literal run
+pub(crate) const MAX_COMPRESSED_BLOCK_LEN: usize = BLOCK_LEN + BLOCK_LEN / 4 + 16;
+
+// Number of earlier positions to try for each match
+fn search_depth(level: CompressionLevel) -> usize {
+    match level {
+        CompressionLevel::Fastest => 1,
+        CompressionLevel::Default => 8,
+        CompressionLevel::Best => 64,
+        CompressionLevel::Custom(depth) => depth.clamp(1, 256) as usize,
+    }
+}
+
+#[inline]
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Compress `data` as a single block appended to `out`
+pub(crate) fn compress(data: &[u8], level: CompressionLevel, out: &mut Vec<u8>) {
+    push_varint(out, data.len() as u64);
+
+    let depth = search_depth(level);
+    let mut head = vec![EMPTY; 1 << HASH_BITS];
+    let mut chain = vec![EMPTY; data.len()];
+
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= data.len() {
+        let mut best_len = 0;
+        let mut best_offset = 0;
+        let mut candidate = head[hash(&data[pos..])];
+        for _ in 0..depth {
+            if candidate == EMPTY || pos - candidate as usize > WINDOW {
+                break;
+            }
+            let start = candidate as usize;
+            let len = data[start..]
+                .iter()
+                .zip(&data[pos..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best_len {
+                best_len = len;
+                best_offset = pos - start;
+            }
+            candidate = chain[start];
+        }
+
+        insert(data, pos, &mut head, &mut chain);
+        if best_len < MIN_MATCH {
+            pos += 1;
+            continue;
+        }
+
+        push_varint(out, (pos - literal_start) as u64);
+        out.extend_from_slice(&data[literal_start..pos]);
+        push_varint(out, (best_len - MIN_MATCH) as u64);
+        push_varint(out, best_offset as u64);
+
+        let end = pos + best_len;
+        for p in pos + 1..end.min(data.len() + 1 - MIN_MATCH) {
+            insert(data, p, &mut head, &mut chain);
+        }
+        pos = end;
+        literal_start = end;
+    }
+
+    if literal_start < data.len() {
+        push_varint(out, (data.len() - literal_start) as u64);
+        out.extend_from_slice(&data[literal_start..]);
+    }
+}
+
+/// Compress `data` as a stream of length-prefixed blocks appended to `out`
+pub(crate) fn compress_stream(data: &[u8], level: CompressionLevel, out: &mut Vec<u8>) {
+    for block in data.chunks(BLOCK_LEN) {
+        compress_framed(block, level, out);
+    }
+}
+
+/// Compress at most [`BLOCK_LEN`] bytes as one block of a stream
+pub(crate) fn compress_framed(data: &[u8], level: CompressionLevel, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&[0; BLOCK_HEADER_LEN]);
+    compress(data, level, out);
+    let len = (out.len() - start - BLOCK_HEADER_LEN) as u32;
+    out[start..start + BLOCK_HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+}
+
+// Record `pos` as the most recent position with its hash
+#[inline]
+fn insert(data: &[u8], pos: usize, head: &mut [u32], chain: &mut [u32]) {
+    let h = hash(&data[pos..]);
+    chain[pos] = head[h];
+    head[h] = pos as u32;
+}
+
+/// Decompress a block written by [`compress`], appending it to `out`
+///
+/// Fails before decoding if the block claims more than `limit` bytes.
+pub(crate) fn decompress(data: &[u8], limit: usize, out: &mut Vec<u8>) -> Result<()> {
+    let mut input = ReadBuffer::new(data);
+    let total = read_len(&mut input)?;
+    if total > limit {
+        return Err(crate::compression::limit_exceeded(limit));
+    }
+    let start = out.len();
+    // The claimed length is untrusted, so only reserve what the input could plausibly hold
+    out.reserve(total.min(data.len().saturating_mul(4)));
+
+    while out.len() - start < total {
+        let literals = read_len(&mut input)?;
+        if literals > total - (out.len() - start) {
+            return Err(corrupt());
+        }
+        out.extend_from_slice(input.read_bytes(literals).map_err(|_| corrupt())?);
+        if out.len() - start == total {
+            break;
+        }
+
+        let len = read_len(&mut input)?.checked_add(MIN_MATCH).ok_or_else(corrupt)?;
+        let offset = read_len(&mut input)?;
+        if offset == 0 || offset > out.len() - start || len > total - (out.len() - start) {
+            return Err(corrupt());
+        }
+        // Copy byte by byte: the match may overlap the bytes it produces
+        let from = out.len() - offset;
+        for i in 0..len {
+            let byte = out[from + i];
+            out.push(byte);
+        }
+    }
+
+    if input.has_remaining() {
+        return Err(corrupt());
+    }
+    Ok(())
+}
+
+/// Decompress a stream written by [`compress_stream`], appending it to `out`
+///
+/// Fails before decoding a block that would take the output past `limit`.
+pub(crate) fn decompress_stream(data: &[u8], limit: usize, out: &mut Vec<u8>) -> Result<()> {
+    let start = out.len();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (block, tail) = split_block(rest)?;
+        let len = block_len(block)?;
+        if len > limit - (out.len() - start) {
+            return Err(crate::compression::limit_exceeded(limit));
+        }
+        decompress(block, len, out)?;
+        rest = tail;
+    }
+    Ok(())
+}
+
+/// Total uncompressed length of a stream, read from its block headers
+pub(crate) fn stream_len(data: &[u8]) -> Result<usize> {
+    let mut total = 0usize;
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (block, tail) = split_block(rest)?;
+        total = total.checked_add(block_len(block)?).ok_or_else(corrupt)?;
+        rest = tail;
+    }
+    Ok(total)
+}
+
+/// Check a block's length prefix, returning the compressed length it gives
+pub(crate) fn framed_len(header: [u8; BLOCK_HEADER_LEN]) -> Result<usize> {
+    let len = u32::from_le_bytes(header) as usize;
+    if len == 0 || len > MAX_COMPRESSED_BLOCK_LEN {
+        return Err(corrupt());
+    }
+    Ok(len)
+}
+
+/// Uncompressed length of a block, which may not exceed [`BLOCK_LEN`]
+pub(crate) fn block_len(block: &[u8]) -> Result<usize> {
+    let len = read_len(&mut ReadBuffer::new(block))?;
+    if len > BLOCK_LEN {
+        return Err(corrupt());
+    }
+    Ok(len)
+}
+
+// Split the first length-prefixed block off a stream
+fn split_block(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    let header = data.get(..BLOCK_HEADER_LEN).ok_or_else(corrupt)?;
+    let len = framed_len(header.try_into().map_err(|_| corrupt())?)?;
+    let rest = &data[BLOCK_HEADER_LEN..];
+    if rest.len() < len {
+        return Err(corrupt());
+    }
+    Ok(rest.split_at(len))
+}
+
+fn read_len(input: &mut ReadBuffer<'_>) -> Result<usize> {
+    let value = input.read_varint().map_err(|_| corrupt())?;
+    usize::try_from(value).map_err(|_| corrupt())
+}
+
+fn corrupt() -> Error {
+    Error::Compression("NanoBit decompression failed: corrupt block".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        compress(data, level, &mut compressed);
+        let mut out = Vec::new();
+        decompress(&compressed, usize::MAX, &mut out).unwrap();
+        assert_eq!(out, data);
+        compressed
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for level in [CompressionLevel::Fastest, CompressionLevel::Default, CompressionLevel::Best] {
+            assert_eq!(roundtrip(b"", level), [0]);
+            roundtrip(b"abc", level);
+            roundtrip(b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", level);
+
+            let text = b"the quick brown fox jumps over the lazy dog. ".repeat(40);
+            assert!(roundtrip(&text, level).len() < text.len() / 10);
+        }
+
+        // Overlapping runs compress to a single match
+        assert_eq!(roundtrip(&[7u8; 1000], CompressionLevel::Default).len(), 7);
+    }
+
+    #[test]
+    fn test_corrupt_blocks_rejected() {
+        let mut compressed = Vec::new();
+        compress(&b"abcdabcdabcdabcd".repeat(4), CompressionLevel::Default, &mut compressed);
+
+        let mut out = Vec::new();
+        assert!(decompress(&compressed[..compressed.len() - 1], usize::MAX, &mut out).is_err());
+
+        // Offset pointing before the start of the output
+        let bad = [8, 0, 4, 1];
+        assert!(decompress(&bad, usize::MAX, &mut Vec::new()).is_err());
+
+        // Trailing garbage
+        compressed.push(0);
+        assert!(decompress(&compressed, usize::MAX, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_stream() {
+        let data: Vec<u8> = (0..BLOCK_LEN * 2 + 100).map(|i| (i % 251) as u8).collect();
+        let mut compressed = Vec::new();
+        compress_stream(&data, CompressionLevel::Fastest, &mut compressed);
+        assert_eq!(stream_len(&compressed).unwrap(), data.len());
+
+        let mut out = Vec::new();
+        decompress_stream(&compressed, usize::MAX, &mut out).unwrap();
+        assert_eq!(out, data);
+
+        // An empty stream has no blocks
+        let mut empty = Vec::new();
+        compress_stream(b"", CompressionLevel::Default, &mut empty);
+        assert!(empty.is_empty());
+
+        // The limit is checked block by block, before decoding
+        let err = decompress_stream(&compressed, BLOCK_LEN, &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded { limit: BLOCK_LEN, .. }));
+
+        // A truncated stream is rejected
+        assert!(decompress_stream(&compressed[..compressed.len() - 1], usize::MAX, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Block encoding for n-dimensional arrays and matrices
+//!
+//! `ndarray` and `nalgebra` write their arrays through serde element by
+//! element, each number a separate call into the serializer. The
+//! [`DenseArray`] wrapper, or `#[serde(with = "nanobit::matrix")]` on a
+//! field, writes the shape followed by every element in one contiguous
+//! little-endian block, as [`PrimitiveSlice`](crate::PrimitiveSlice) does
+//! for flat slices:
+//!
+//! ```rust
+//! # #[cfg(feature = "ndarray")] {
+//! use ndarray::Array2;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Layer {
+//!     #[serde(with = "nanobit::matrix")]
+//!     weights: Array2<f32>,
+//!     #[serde(with = "nanobit::matrix")]
+//!     bias: ndarray::Array1<f32>,
+//! }
+//!
+//! let layer = Layer {
+//!     weights: Array2::from_shape_fn((64, 32), |(i, j)| (i * j) as f32),
+//!     bias: ndarray::Array1::zeros(32),
+//! };
+//! let bytes = nanobit::to_bytes(&layer)?;
+//! assert!(bytes.len() < 4 * (64 * 32 + 32) + 16);
+//! assert_eq!(nanobit::from_bytes::<Layer>(&bytes)?, layer);
+//! # }
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! The `ndarray` feature covers `ndarray::Array` of any dimension, and the
+//! `nalgebra` feature covers `DMatrix`, `DVector` and `SMatrix`. Elements
+//! are any [`Primitive`] number.
+//!
+//! # Representation
+//!
+//! An array is a byte string. It starts with the number of dimensions and
+//! the length of each as varints, followed by the elements in row-major
+//! order, each little-endian. A 3×2 matrix of `f64` takes 3 bytes of
+//! shape and 48 of data. nalgebra stores matrices column-major but writes
+//! them row-major too, so a matrix written from either crate reads back
+//! into the other.
+//!
+//! Decoding checks that the number of dimensions is one the target type
+//! can hold and that the element data is exactly as long as the shape
+//! requires, before allocating it.
+
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Error as _, Serialize, Serializer};
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+use crate::primitive::Primitive;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Arrays that [`DenseArray`] can encode
+pub trait ArrayData: sealed::Sealed + Sized {
+    /// Element type
+    type Elem: Primitive;
+
+    /// Number of dimensions every value has, or `None` if it varies
+    const NDIM: Option<usize>;
+
+    /// Length of each dimension
+    fn shape(&self) -> Vec<usize>;
+
+    /// Write every element in row-major order, `Elem::SIZE` bytes each
+    fn write_elements(&self, out: &mut [u8]);
+
+    /// Build an array from its shape and row-major elements, or `None` if
+    /// the type cannot have that shape
+    ///
+    /// `elements` always holds as many elements as `shape` requires.
+    fn from_shape(shape: &[usize], elements: Vec<Self::Elem>) -> Option<Self>;
+}
+
+#[cfg(feature = "ndarray")]
+impl<T: Primitive, D: ndarray::Dimension> sealed::Sealed for ndarray::Array<T, D> {}
+
+#[cfg(feature = "ndarray")]
+impl<T: Primitive, D: ndarray::Dimension> ArrayData for ndarray::Array<T, D> {
+    type Elem = T;
+
+    const NDIM: Option<usize> = D::NDIM;
+
+    fn shape(&self) -> Vec<usize> {
+        ndarray::ArrayBase::shape(self).to_vec()
+    }
+
+    fn write_elements(&self, out: &mut [u8]) {
+        // Standard layout is already row-major; anything else is walked in logical order
+        match self.as_slice() {
+            Some(elements) => write_block(elements.iter(), out),
+            None => write_block(self.iter(), out),
+        }
+    }
+
+    fn from_shape(shape: &[usize], elements: Vec<T>) -> Option<Self> {
+        let array = ndarray::Array::from_shape_vec(ndarray::IxDyn(shape), elements).ok()?;
+        array.into_dimensionality().ok()
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Primitive + nalgebra::Scalar> sealed::Sealed for nalgebra::DMatrix<T> {}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Primitive + nalgebra::Scalar> ArrayData for nalgebra::DMatrix<T> {
+    type Elem = T;
+
+    const NDIM: Option<usize> = Some(2);
+
+    fn shape(&self) -> Vec<usize> {
+        vec![self.nrows(), self.ncols()]
+    }
+
+    fn write_elements(&self, out: &mut [u8]) {
+        write_block(self.transpose().iter(), out);
+    }
+
+    fn from_shape(shape: &[usize], elements: Vec<T>) -> Option<Self> {
+        // Row-major elements are the column-major storage of the transpose
+        Some(nalgebra::DMatrix::from_vec(shape[1], shape[0], elements).transpose())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Primitive + nalgebra::Scalar> sealed::Sealed for nalgebra::DVector<T> {}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Primitive + nalgebra::Scalar> ArrayData for nalgebra::DVector<T> {
+    type Elem = T;
+
+    const NDIM: Option<usize> = Some(1);
+
+    fn shape(&self) -> Vec<usize> {
+        vec![self.len()]
+    }
+
+    fn write_elements(&self, out: &mut [u8]) {
+        write_block(self.iter(), out);
+    }
+
+    fn from_shape(_shape: &[usize], elements: Vec<T>) -> Option<Self> {
+        Some(nalgebra::DVector::from_vec(elements))
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Primitive + nalgebra::Scalar, const R: usize, const C: usize> sealed::Sealed for nalgebra::SMatrix<T, R, C> {}
+
+#[cfg(feature = "nalgebra")]
+impl<T: Primitive + nalgebra::Scalar, const R: usize, const C: usize> ArrayData for nalgebra::SMatrix<T, R, C> {
+    type Elem = T;
+
+    const NDIM: Option<usize> = Some(2);
+
+    fn shape(&self) -> Vec<usize> {
+        vec![R, C]
+    }
+
+    fn write_elements(&self, out: &mut [u8]) {
+        write_block(self.transpose().iter(), out);
+    }
+
+    fn from_shape(shape: &[usize], elements: Vec<T>) -> Option<Self> {
+        (shape == [R, C]).then(|| nalgebra::SMatrix::from_row_slice(&elements))
+    }
+}
+
+// Write elements little-endian into consecutive `T::SIZE` chunks of `out`
+fn write_block<'a, T: Primitive>(elements: impl Iterator<Item = &'a T>, out: &mut [u8]) {
+    for (chunk, &value) in out.chunks_exact_mut(T::SIZE).zip(elements) {
+        value.write_le(chunk);
+    }
+}
+
+/// An array written as its shape and one block of elements
+///
+/// See the [module documentation](self) for the representation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DenseArray<A>(pub A);
+
+impl<A: ArrayData> Serialize for DenseArray<A> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, A: ArrayData> Deserialize<'de> for DenseArray<A> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(DenseArray)
+    }
+}
+
+/// Serialize an array as its shape and one block of elements
+pub fn serialize<A, S>(array: &A, serializer: S) -> Result<S::Ok, S::Error>
+where
+    A: ArrayData,
+    S: Serializer,
+{
+    let shape = array.shape();
+    let len = shape.iter().product::<usize>() * A::Elem::SIZE;
+    let mut out = WriteBuffer::with_capacity(len + 10 * (shape.len() + 1));
+    out.write_varint(shape.len() as u64).map_err(S::Error::custom)?;
+    for &dim in &shape {
+        out.write_varint(dim as u64).map_err(S::Error::custom)?;
+    }
+    let mut bytes = out.into_vec();
+    let start = bytes.len();
+    bytes.resize(start + len, 0);
+    array.write_elements(&mut bytes[start..]);
+    serializer.serialize_bytes(&bytes)
+}
+
+/// Deserialize an array written by [`serialize`], checking its shape
+pub fn deserialize<'de, A, D>(deserializer: D) -> Result<A, D::Error>
+where
+    A: ArrayData,
+    D: Deserializer<'de>,
+{
+    struct ArrayVisitor<A>(PhantomData<A>);
+
+    impl<'de, A: ArrayData> Visitor<'de> for ArrayVisitor<A> {
+        type Value = A;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "a byte string holding an array shape and {}-byte elements", A::Elem::SIZE)
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<A, E> {
+            let mut input = ReadBuffer::new(bytes);
+            let ndim = input.read_varint().map_err(E::custom)?;
+            if let Some(expected) = A::NDIM.filter(|&expected| expected as u64 != ndim) {
+                return Err(E::custom(format_args!("Expected an array of {expected} dimensions, found {ndim}")));
+            }
+            let mut shape = Vec::with_capacity((ndim as usize).min(bytes.len()));
+            let mut count = 1usize;
+            for _ in 0..ndim {
+                let dim = usize::try_from(input.read_varint().map_err(E::custom)?).map_err(E::custom)?;
+                count = count.checked_mul(dim).ok_or_else(|| E::custom("Array shape overflows"))?;
+                shape.push(dim);
+            }
+            let data = &bytes[input.position()..];
+            if count.checked_mul(A::Elem::SIZE) != Some(data.len()) {
+                return Err(E::custom(format_args!(
+                    "Array of shape {shape:?} needs {count} elements, found {} bytes",
+                    data.len()
+                )));
+            }
+            let elements = data.chunks_exact(A::Elem::SIZE).map(A::Elem::read_le).collect();
+            A::from_shape(&shape, elements).ok_or_else(|| E::custom(format_args!("Array shape {shape:?} does not fit the target type")))
+        }
+
+        fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<A, S::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+            while let Some(byte) = seq.next_element::<u8>()? {
+                bytes.push(byte);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    deserializer.deserialize_bytes(ArrayVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_ndarray_roundtrip() {
+        use ndarray::{Array, Array0, Array2, Array3, ArrayD, IxDyn};
+
+        let matrix = Array2::from_shape_fn((3, 2), |(i, j)| (i * 10 + j) as f64);
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&DenseArray(matrix.clone()), config).unwrap();
+            assert_eq!(crate::from_bytes::<DenseArray<Array2<f64>>>(&bytes).unwrap().0, matrix);
+            let rows: Vec<Vec<f64>> = matrix.rows().into_iter().map(|row| row.to_vec()).collect();
+            assert!(bytes.len() <= crate::to_bytes_with_config(&rows, config).unwrap().len());
+        }
+
+        // Spelled out: 2 dimensions of 3 and 2, then the rows in order
+        let bytes = crate::to_bytes(&DenseArray(matrix.clone())).unwrap();
+        let data: Vec<u8> = [0.0f64, 1.0, 10.0, 11.0, 20.0, 21.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert!(bytes.ends_with(&[[2, 3, 2].as_slice(), &data].concat()));
+
+        // Transposed views are written in logical order, not memory order
+        let transposed = matrix.t().to_owned();
+        let reversed = matrix.clone().reversed_axes();
+        assert_eq!(crate::to_bytes(&DenseArray(reversed)).unwrap(), crate::to_bytes(&DenseArray(transposed)).unwrap());
+
+        let cube = Array3::from_shape_fn((2, 0, 4), |(i, j, k)| (i + j + k) as u16);
+        let bytes = crate::to_bytes(&DenseArray(cube.clone())).unwrap();
+        assert_eq!(crate::from_bytes::<DenseArray<Array3<u16>>>(&bytes).unwrap().0, cube);
+        let dynamic = crate::from_bytes::<DenseArray<ArrayD<u16>>>(&bytes).unwrap().0;
+        assert_eq!(dynamic.shape(), [2, 0, 4]);
+
+        let scalar = Array0::from_elem((), -7i32);
+        let bytes = crate::to_bytes(&DenseArray(scalar.clone())).unwrap();
+        assert_eq!(crate::from_bytes::<DenseArray<Array0<i32>>>(&bytes).unwrap().0, scalar);
+        assert!(crate::from_bytes::<DenseArray<Array<i32, IxDyn>>>(&bytes).is_ok());
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_ndarray_rejects_bad_shapes() {
+        use ndarray::{Array1, Array2};
+
+        // Without type tags a byte string is written like a `Vec<u8>`
+        let decode = |bytes: &[u8]| crate::from_bytes::<DenseArray<Array2<u8>>>(&crate::to_bytes(&bytes.to_vec()).unwrap());
+        assert!(decode(&[2, 2, 2, 1, 2, 3, 4]).is_ok());
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[2, 2]).is_err());
+        assert!(decode(&[2, 2, 2, 1, 2, 3]).is_err());
+        assert!(decode(&[2, 2, 2, 1, 2, 3, 4, 5]).is_err());
+        assert!(decode(&[3, 1, 1, 1, 0]).is_err());
+        assert!(decode(&[2, 0xff, 0xff, 0xff, 0xff, 0x0f, 0xff, 0xff, 0xff, 0xff, 0x0f]).is_err());
+
+        let bytes = crate::to_bytes(&DenseArray(Array1::from(vec![1.0f32, 2.0]))).unwrap();
+        assert!(crate::from_bytes::<DenseArray<Array2<f32>>>(&bytes).is_err());
+        assert!(crate::from_bytes::<DenseArray<Array1<f64>>>(&bytes).is_err());
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn test_nalgebra_roundtrip() {
+        use nalgebra::{DMatrix, DVector, Matrix2x3};
+
+        let matrix = DMatrix::from_row_slice(2, 3, &[1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let bytes = crate::to_bytes(&DenseArray(matrix.clone())).unwrap();
+        let data: Vec<u8> = [1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert!(bytes.ends_with(&[[2, 2, 3].as_slice(), &data].concat()));
+        assert_eq!(crate::from_bytes::<DenseArray<DMatrix<f64>>>(&bytes).unwrap().0, matrix);
+
+        let fixed = crate::from_bytes::<DenseArray<Matrix2x3<f64>>>(&bytes).unwrap().0;
+        assert_eq!(fixed, Matrix2x3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0));
+        assert_eq!(crate::to_bytes(&DenseArray(fixed)).unwrap(), bytes);
+        assert!(crate::from_bytes::<DenseArray<nalgebra::Matrix3x2<f64>>>(&bytes).is_err());
+        assert!(crate::from_bytes::<DenseArray<DVector<f64>>>(&bytes).is_err());
+
+        let vector = DVector::from_vec(vec![3u32, 1, 4, 1, 5]);
+        let bytes = crate::to_bytes_with_config(&DenseArray(vector.clone()), Config::new().with_self_describing(true)).unwrap();
+        assert_eq!(crate::from_bytes::<DenseArray<DVector<u32>>>(&bytes).unwrap().0, vector);
+    }
+
+    #[cfg(all(feature = "ndarray", feature = "nalgebra"))]
+    #[test]
+    fn test_matrix_types_interchangeable() {
+        let array = ndarray::Array2::from_shape_fn((4, 3), |(i, j)| (i * 3 + j) as f32);
+        let bytes = crate::to_bytes(&DenseArray(array.clone())).unwrap();
+        let matrix = crate::from_bytes::<DenseArray<nalgebra::DMatrix<f32>>>(&bytes).unwrap().0;
+        assert_eq!(matrix[(2, 1)], array[(2, 1)]);
+        assert_eq!(crate::to_bytes(&DenseArray(matrix)).unwrap(), bytes);
+    }
+}
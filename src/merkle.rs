@@ -0,0 +1,457 @@
+//! Merkle trees over chunked payloads
+//!
+//! A checksum over a whole payload says only whether all of it arrived
+//! intact. Hashing the payload's [chunks](crate::chunk) into a Merkle tree
+//! instead lets a receiver check each chunk as it arrives, re-fetch only
+//! the damaged ones, and compare trees with a peer to find which chunks
+//! differ without exchanging the data. Everything hinges on the root
+//! hash: a receiver that knows the root of the payload it expects can
+//! check every chunk against the tree.
+//!
+//! The tree is built from XXH3-128 hashes, like the ids of a
+//! [`ChunkStore`](crate::chunk::ChunkStore). They catch accidental damage
+//! such as truncated downloads, flipped bits and mixed-up versions, but
+//! are not cryptographic: anyone who can alter the data in transit can
+//! craft chunks that match the tree. Protect the root and the data with a
+//! signature or an authenticated channel where tampering matters.
+//!
+//! [`seal`] appends the tree's leaves to a payload as a footer, and
+//! [`open`] checks a sealed payload and strips the footer:
+//!
+//! ```rust
+//! use nanobit::chunk::Chunking;
+//! use nanobit::merkle;
+//!
+//! let payload = nanobit::to_bytes(&vec![7u32; 50_000])?;
+//! let sealed = merkle::seal(&payload, Chunking::new())?;
+//!
+//! // The receiver fetches the footer first and checks chunks as they come
+//! let tail_len = merkle::footer_len(&sealed[sealed.len() - merkle::TRAILER_LEN..])?;
+//! let tree = merkle::footer(&sealed[sealed.len() - tail_len..])?;
+//! for index in 0..tree.len() {
+//!     let range = tree.chunk_range(index).unwrap();
+//!     let chunk = &sealed[range.start as usize..range.end as usize];
+//!     assert!(tree.verify_chunk(index, chunk));
+//! }
+//! assert_eq!(merkle::open(&sealed, Some(tree.root()))?, payload);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! A chunk can also be checked against the root alone, with the sibling
+//! hashes from [`MerkleTree::proof`] and [`verify_proof`].
+//!
+//! # Representation
+//!
+//! Each leaf is the hash of a `0` byte followed by its chunk, and each
+//! inner node the hash of a `1` byte followed by its two children, so no
+//! chunk can pass for an inner node. A node without a sibling moves up a
+//! level unchanged. The sealed form is the payload, the footer encoded as
+//! a nanobit payload holding the length and leaf hash of every chunk, then
+//! the footer length as a `u32` LE and the magic bytes `NBMT`.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunk::{ChunkId, Chunking};
+use crate::error::{Error, Result};
+
+/// Magic bytes ending a sealed payload
+pub const MERKLE_MAGIC: &[u8; 4] = b"NBMT";
+
+/// Length of the trailer after the footer: the footer length and the magic
+pub const TRAILER_LEN: usize = 8;
+
+// Leaf over a chunk
+fn leaf(chunk: &[u8]) -> ChunkId {
+    let mut hasher = xxhash_rust::xxh3::Xxh3Default::new();
+    hasher.update(&[0]);
+    hasher.update(chunk);
+    ChunkId(hasher.digest128().to_le_bytes())
+}
+
+// Inner node over two children
+fn node(left: &ChunkId, right: &ChunkId) -> ChunkId {
+    let mut bytes = [0u8; 33];
+    bytes[0] = 1;
+    bytes[1..17].copy_from_slice(&left.0);
+    bytes[17..].copy_from_slice(&right.0);
+    ChunkId::of(&bytes)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Footer {
+    chunks: Vec<(u32, ChunkId)>,
+}
+
+/// The hash tree of a chunked payload
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTree {
+    // End offset of each chunk in the payload
+    ends: Vec<u64>,
+    // Leaves first, root last
+    levels: Vec<Vec<ChunkId>>,
+}
+
+impl MerkleTree {
+    /// Split `payload` and hash its chunks into a tree
+    pub fn build(payload: &[u8], chunking: Chunking) -> Self {
+        Self::from_chunks(chunking.split(payload).map(|chunk| (chunk.len() as u64, leaf(chunk))))
+    }
+
+    fn from_chunks(chunks: impl IntoIterator<Item = (u64, ChunkId)>) -> Self {
+        let mut ends = Vec::new();
+        let mut leaves = Vec::new();
+        let mut end = 0;
+        for (len, hash) in chunks {
+            end += len;
+            ends.push(end);
+            leaves.push(hash);
+        }
+
+        let mut levels = vec![leaves];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let next = levels
+                .last()
+                .unwrap_or(&Vec::new())
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self { ends, levels }
+    }
+
+    /// The root hash, which identifies the whole payload
+    ///
+    /// An empty payload has the [`ChunkId`] of no bytes.
+    pub fn root(&self) -> ChunkId {
+        match self.levels.last().and_then(|level| level.first()) {
+            Some(root) => *root,
+            None => ChunkId::of(&[]),
+        }
+    }
+
+    /// Number of chunks
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Whether the payload is empty
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Length of the payload
+    pub fn payload_len(&self) -> u64 {
+        self.ends.last().copied().unwrap_or(0)
+    }
+
+    /// Leaf hash of the chunk at `index`
+    pub fn leaf_hash(&self, index: usize) -> Option<ChunkId> {
+        self.levels[0].get(index).copied()
+    }
+
+    /// Byte range of the chunk at `index` within the payload
+    pub fn chunk_range(&self, index: usize) -> Option<Range<u64>> {
+        let end = *self.ends.get(index)?;
+        let start = index.checked_sub(1).map_or(0, |previous| self.ends[previous]);
+        Some(start..end)
+    }
+
+    /// Check a downloaded chunk against the tree
+    pub fn verify_chunk(&self, index: usize, chunk: &[u8]) -> bool {
+        self.chunk_range(index).is_some_and(|range| range.end - range.start == chunk.len() as u64)
+            && self.leaf_hash(index) == Some(leaf(chunk))
+    }
+
+    /// Sibling hashes linking the chunk at `index` to the root
+    pub fn proof(&self, index: usize) -> Option<Vec<ChunkId>> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut proof = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(position ^ 1) {
+                proof.push(*sibling);
+            }
+            position /= 2;
+        }
+        Some(proof)
+    }
+
+    /// Indexes of the chunks of `other` that this tree has no chunk for
+    ///
+    /// These are the chunks a peer holding this tree's payload needs to
+    /// fetch to rebuild the payload of `other`. The trees are compared from
+    /// the top down, and subtrees whose hashes match at the same position
+    /// are skipped without visiting their leaves. A chunk under a subtree
+    /// that differs still counts as present when this tree has it anywhere,
+    /// so content that only moved is not fetched again.
+    pub fn diff(&self, other: &MerkleTree) -> Vec<usize> {
+        let mut missing = Vec::new();
+        if other.is_empty() {
+            return missing;
+        }
+        let have: HashSet<&ChunkId> = self.levels[0].iter().collect();
+        // Nodes at the same level and index cover the same chunk indexes
+        let top = self.levels.len().min(other.levels.len()) - 1;
+        for index in 0..other.levels[top].len() {
+            self.diff_node(other, &have, top, index, &mut missing);
+        }
+        missing
+    }
+
+    fn diff_node(
+        &self,
+        other: &MerkleTree,
+        have: &HashSet<&ChunkId>,
+        level: usize,
+        index: usize,
+        missing: &mut Vec<usize>,
+    ) {
+        let hash = &other.levels[level][index];
+        if self.levels[level].get(index) == Some(hash) {
+            return;
+        }
+        if level == 0 {
+            if !have.contains(hash) {
+                missing.push(index);
+            }
+            return;
+        }
+        for child in [2 * index, 2 * index + 1] {
+            if child < other.levels[level - 1].len() {
+                self.diff_node(other, have, level - 1, child, missing);
+            }
+        }
+    }
+}
+
+/// Check a chunk against a trusted root using a [`MerkleTree::proof`]
+///
+/// `chunks` is the number of chunks in the tree, which decides where a
+/// node has no sibling.
+pub fn verify_proof(root: ChunkId, index: usize, chunks: usize, chunk: &[u8], proof: &[ChunkId]) -> bool {
+    if index >= chunks {
+        return false;
+    }
+    let mut hash = leaf(chunk);
+    let mut siblings = proof.iter();
+    let (mut position, mut width) = (index, chunks);
+    while width > 1 {
+        let promoted = position == width - 1 && width % 2 == 1;
+        if !promoted {
+            let Some(sibling) = siblings.next() else {
+                return false;
+            };
+            hash = if position % 2 == 0 { node(&hash, sibling) } else { node(sibling, &hash) };
+        }
+        position /= 2;
+        width = width.div_ceil(2);
+    }
+    siblings.next().is_none() && hash == root
+}
+
+/// Append the Merkle tree of `payload`'s chunks as a footer
+///
+/// Fails if `chunking` produces chunks of 4 GiB or more, whose length the
+/// footer cannot record.
+pub fn seal(payload: &[u8], chunking: Chunking) -> Result<Vec<u8>> {
+    let chunks = chunking
+        .split(payload)
+        .map(|chunk| {
+            let len = u32::try_from(chunk.len())
+                .map_err(|_| Error::LimitExceeded { what: "Merkle chunk size", limit: u32::MAX as usize })?;
+            Ok((len, leaf(chunk)))
+        })
+        .collect::<Result<_>>()?;
+    let footer = Footer { chunks };
+    let encoded = crate::to_bytes(&footer)?;
+    let footer_len = u32::try_from(encoded.len())
+        .map_err(|_| Error::LimitExceeded { what: "Merkle footer size", limit: u32::MAX as usize })?;
+    let mut sealed = Vec::with_capacity(payload.len() + encoded.len() + TRAILER_LEN);
+    sealed.extend_from_slice(payload);
+    sealed.extend_from_slice(&encoded);
+    sealed.extend_from_slice(&footer_len.to_le_bytes());
+    sealed.extend_from_slice(MERKLE_MAGIC);
+    Ok(sealed)
+}
+
+/// Number of bytes at the end of sealed data holding the footer and the
+/// trailer
+///
+/// Reads the last [`TRAILER_LEN`] bytes of `tail`, which may be those
+/// bytes alone or any longer end of the sealed data.
+pub fn footer_len(tail: &[u8]) -> Result<usize> {
+    if tail.len() < TRAILER_LEN || !tail.ends_with(MERKLE_MAGIC) {
+        return Err(Error::InvalidFormat("Missing Merkle footer".to_string()));
+    }
+    let at = tail.len() - TRAILER_LEN;
+    let len = u32::from_le_bytes([tail[at], tail[at + 1], tail[at + 2], tail[at + 3]]);
+    Ok(len as usize + TRAILER_LEN)
+}
+
+/// Read the tree from the footer at the end of `tail`, without checking
+/// the payload
+///
+/// `tail` is any end of the sealed data holding at least the last
+/// [`footer_len`] bytes, so a receiver can fetch the trailer, then the
+/// footer, and verify chunks as they arrive. The payload length cannot be
+/// checked from the tail alone; [`open`] checks it along with the chunks.
+/// Compare the root with a trusted one before relying on the tree.
+pub fn footer(tail: &[u8]) -> Result<MerkleTree> {
+    let len = footer_len(tail)?;
+    let start = tail
+        .len()
+        .checked_sub(len)
+        .ok_or_else(|| Error::InvalidFormat("Merkle footer longer than the data".to_string()))?;
+    let footer: Footer = crate::from_bytes(&tail[start..tail.len() - TRAILER_LEN])?;
+    Ok(MerkleTree::from_chunks(footer.chunks.into_iter().map(|(len, hash)| (u64::from(len), hash))))
+}
+
+// Split sealed data into the payload and the tree from its footer
+fn split(sealed: &[u8]) -> Result<(&[u8], MerkleTree)> {
+    let tree = footer(sealed)?;
+    let payload_len = sealed.len() - footer_len(sealed)?;
+    if tree.payload_len() != payload_len as u64 {
+        return Err(Error::InvalidFormat("Merkle footer does not match the payload length".to_string()));
+    }
+    Ok((&sealed[..payload_len], tree))
+}
+
+/// Check every chunk of a sealed payload and return the payload
+///
+/// With `root`, the tree must also have that root; without, only
+/// corruption that leaves the footer intact is caught.
+pub fn open(sealed: &[u8], root: Option<ChunkId>) -> Result<&[u8]> {
+    let (payload, tree) = split(sealed)?;
+    if root.is_some_and(|root| root != tree.root()) {
+        return Err(Error::InvalidFormat("Merkle root does not match".to_string()));
+    }
+    for index in 0..tree.len() {
+        let range = tree.chunk_range(index).unwrap_or(0..0);
+        if !tree.verify_chunk(index, &payload[range.start as usize..range.end as usize]) {
+            return Err(Error::InvalidFormat(format!("Chunk {index} does not match the Merkle tree")));
+        }
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    const SMALL: Chunking = Chunking::with_sizes(256, 1024, 4096);
+
+    #[test]
+    fn test_sealed_payload() {
+        let data = payload(100_000, 1);
+        let sealed = seal(&data, SMALL).unwrap();
+        let tree = MerkleTree::build(&data, SMALL);
+        assert!(tree.len() > 20);
+        assert_eq!(footer(&sealed).unwrap(), tree);
+        let tail_len = footer_len(&sealed[sealed.len() - TRAILER_LEN..]).unwrap();
+        assert_eq!(footer(&sealed[sealed.len() - tail_len..]).unwrap(), tree);
+        assert!(footer(&sealed[sealed.len() - tail_len + 1..]).is_err());
+        assert_eq!(open(&sealed, Some(tree.root())).unwrap(), data);
+
+        // A flipped byte is pinned to its chunk
+        let mut damaged = sealed.clone();
+        damaged[50_000] ^= 1;
+        let tree = footer(&damaged).unwrap();
+        let bad: Vec<usize> = (0..tree.len())
+            .filter(|&index| {
+                let range = tree.chunk_range(index).unwrap();
+                !tree.verify_chunk(index, &damaged[range.start as usize..range.end as usize])
+            })
+            .collect();
+        assert_eq!(bad.len(), 1);
+        assert!(tree.chunk_range(bad[0]).unwrap().contains(&50_000));
+        assert!(open(&damaged, None).is_err());
+
+        assert!(open(&sealed, Some(ChunkId::of(b"other"))).is_err());
+        assert!(open(&sealed[..sealed.len() - 1], None).is_err());
+        assert!(open(&sealed[1..], None).is_err());
+        assert!(open(&seal(&[], SMALL).unwrap(), Some(MerkleTree::build(&[], SMALL).root()))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_merkle_proofs() {
+        for len in [1, 2, 3, 5, 8, 13] {
+            let data = payload(len * 4096, len as u64);
+            let tree = MerkleTree::build(&data, Chunking::with_sizes(4096, 1 << 30, 4096));
+            assert_eq!(tree.len(), len);
+            for index in 0..len {
+                let range = tree.chunk_range(index).unwrap();
+                let chunk = &data[range.start as usize..range.end as usize];
+                let proof = tree.proof(index).unwrap();
+                assert!(verify_proof(tree.root(), index, len, chunk, &proof));
+                assert!(!verify_proof(tree.root(), index, len, b"forged", &proof));
+                if len > 1 {
+                    let other = tree.proof((index + 1) % len).unwrap();
+                    assert!(!verify_proof(tree.root(), index, len, chunk, &other));
+                }
+            }
+            assert!(tree.proof(len).is_none());
+        }
+    }
+
+    #[test]
+    fn test_leaf_cannot_pass_for_node() {
+        let data = payload(2 * 4096, 3);
+        let tree = MerkleTree::build(&data, Chunking::with_sizes(4096, 1 << 30, 4096));
+        assert_eq!(tree.len(), 2);
+
+        // A chunk holding an inner node's preimage
+        let mut forged = vec![1u8];
+        forged.extend_from_slice(&tree.leaf_hash(0).unwrap().0);
+        forged.extend_from_slice(&tree.leaf_hash(1).unwrap().0);
+        assert_eq!(ChunkId::of(&forged), tree.root());
+        assert!(!verify_proof(tree.root(), 0, 1, &forged, &[]));
+        assert_ne!(MerkleTree::build(&forged, Chunking::new()).root(), tree.root());
+    }
+
+    #[test]
+    fn test_merkle_diff() {
+        let old = payload(200_000, 9);
+        let mut new = old.clone();
+        new[120_000] ^= 0xFF;
+        new.splice(1_000..1_000, *b"inserted");
+
+        let (old_tree, new_tree) = (MerkleTree::build(&old, SMALL), MerkleTree::build(&new, SMALL));
+        let missing = old_tree.diff(&new_tree);
+        assert!(!missing.is_empty() && missing.len() <= 4, "{missing:?}");
+        let changed: Vec<u64> = vec![1_000, 120_008];
+        for offset in changed {
+            assert!(missing.iter().any(|&index| new_tree.chunk_range(index).unwrap().contains(&offset)));
+        }
+        assert!(old_tree.diff(&old_tree).is_empty());
+        assert_eq!(MerkleTree::build(&[], SMALL).diff(&old_tree), (0..old_tree.len()).collect::<Vec<_>>());
+
+        // Only the changed chunk of an equally shaped tree is reported
+        let fixed = Chunking::with_sizes(1024, 1 << 30, 1024);
+        let mut edited = old.clone();
+        edited[150_000] ^= 1;
+        let (old_tree, edited_tree) = (MerkleTree::build(&old, fixed), MerkleTree::build(&edited, fixed));
+        assert_eq!(old_tree.diff(&edited_tree), [150_000 / 1024]);
+    }
+}
@@ -0,0 +1,261 @@
+//! Instrumentation hooks for serialization calls
+//!
+//! With the `metrics` feature, every call to the top-level entry points —
+//! [`to_bytes`](crate::to_bytes), [`from_bytes`](crate::from_bytes), their
+//! `_with_config` variants, [`compress`](crate::compress) and
+//! [`decompress`](crate::decompress) with their `_into` and `_with_limit`
+//! forms — reports an [`Event`] to the [`Recorder`] installed with
+//! [`set_recorder`]. Events carry the operation, type, payload size,
+//! duration and outcome, so call sites need no wrapping to feed a
+//! dashboard.
+//!
+//! ```rust
+//! # #[cfg(feature = "metrics")] {
+//! use nanobit::metrics::{self, Event};
+//!
+//! metrics::set_recorder(|event: &Event<'_>| {
+//!     println!("{:?} {} bytes in {:?}", event.operation, event.bytes, event.duration);
+//! });
+//! let bytes = nanobit::to_bytes(&vec![1u32, 2, 3]).unwrap();
+//! # metrics::clear_recorder();
+//! # let _ = bytes;
+//! # }
+//! ```
+//!
+//! With no recorder installed each call costs one atomic load. Without the
+//! feature the hooks compile to nothing.
+
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "metrics")]
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "metrics")]
+use crate::error::Error;
+use crate::error::Result;
+
+/// Kind of call an [`Event`] describes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// A value was serialized to a payload
+    Serialize,
+    /// A payload was deserialized into a value
+    Deserialize,
+    /// Data was compressed into a container
+    Compress,
+    /// A container was decompressed
+    Decompress,
+}
+
+/// One instrumented call
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug)]
+pub struct Event<'a> {
+    /// What the call did
+    pub operation: Operation,
+    /// Rust type serialized or deserialized, if any
+    pub type_name: Option<&'static str>,
+    /// Size of the encoded side: the payload written or read, or the
+    /// compressed container
+    ///
+    /// Zero when serialization or compression failed.
+    pub bytes: usize,
+    /// Size of the uncompressed data, for compression calls
+    pub uncompressed_bytes: Option<usize>,
+    /// Time spent in the call
+    pub duration: Duration,
+    /// The error the call returned, if it failed
+    pub error: Option<&'a Error>,
+}
+
+#[cfg(feature = "metrics")]
+impl Event<'_> {
+    /// Uncompressed size over compressed size, for successful compression calls
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.uncompressed_bytes {
+            Some(uncompressed) if self.error.is_none() && self.bytes > 0 => {
+                Some(uncompressed as f64 / self.bytes as f64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the call succeeded
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Receives an [`Event`] for every instrumented call
+///
+/// Recorders are called on the thread that made the call, after it
+/// returns, so they should be quick: update counters or histograms and
+/// leave aggregation to the metrics backend. Closures taking `&Event`
+/// implement this trait.
+#[cfg(feature = "metrics")]
+pub trait Recorder: Send + Sync {
+    /// Record one call
+    fn record(&self, event: &Event<'_>);
+}
+
+#[cfg(feature = "metrics")]
+impl<F> Recorder for F
+where
+    F: Fn(&Event<'_>) + Send + Sync,
+{
+    #[inline]
+    fn record(&self, event: &Event<'_>) {
+        self(event)
+    }
+}
+
+#[cfg(feature = "metrics")]
+static ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "metrics")]
+static RECORDER: RwLock<Option<Arc<dyn Recorder>>> = RwLock::new(None);
+
+/// Install the process-wide recorder, replacing any previous one
+#[cfg(feature = "metrics")]
+pub fn set_recorder<R>(recorder: R)
+where
+    R: Recorder + 'static,
+{
+    *RECORDER.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Arc::new(recorder));
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Remove the process-wide recorder
+#[cfg(feature = "metrics")]
+pub fn clear_recorder() {
+    ENABLED.store(false, Ordering::Release);
+    *RECORDER.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+// Times one call and reports it once finished, if a recorder is installed
+pub(crate) struct Probe {
+    #[cfg(feature = "metrics")]
+    started: Option<(Operation, Option<&'static str>, Instant)>,
+}
+
+impl Probe {
+    #[inline]
+    pub(crate) fn start(operation: Operation, type_name: Option<&'static str>) -> Self {
+        #[cfg(feature = "metrics")]
+        {
+            Self {
+                started: ENABLED
+                    .load(Ordering::Acquire)
+                    .then(|| (operation, type_name, Instant::now())),
+            }
+        }
+        #[cfg(not(feature = "metrics"))]
+        {
+            let _ = (operation, type_name);
+            Self {}
+        }
+    }
+
+    // Report the call; `bytes` and `uncompressed_bytes` are as in `Event`
+    #[inline]
+    pub(crate) fn finish<T>(self, result: &Result<T>, bytes: usize, uncompressed_bytes: Option<usize>) {
+        #[cfg(feature = "metrics")]
+        if let Some((operation, type_name, started)) = self.started {
+            let duration = started.elapsed();
+            let recorder = RECORDER.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+            if let Some(recorder) = recorder {
+                recorder.record(&Event {
+                    operation,
+                    type_name,
+                    bytes,
+                    uncompressed_bytes,
+                    duration,
+                    error: result.as_ref().err(),
+                });
+            }
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = (self, result, bytes, uncompressed_bytes);
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::thread::{self, ThreadId};
+
+    use crate::compression::{CompressionFormat, CompressionLevel};
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Reading {
+        sensor: String,
+        values: Vec<u32>,
+    }
+
+    // Events from this thread only, as other tests run concurrently
+    type Seen = Vec<(Operation, Option<&'static str>, usize, Option<usize>, bool)>;
+
+    fn record_on(thread: ThreadId, seen: Arc<Mutex<Seen>>) -> impl Recorder {
+        move |event: &Event<'_>| {
+            if thread::current().id() == thread {
+                seen.lock().unwrap().push((
+                    event.operation,
+                    event.type_name,
+                    event.bytes,
+                    event.uncompressed_bytes,
+                    event.is_ok(),
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_recorder_sees_each_call() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        set_recorder(record_on(thread::current().id(), seen.clone()));
+
+        let reading = Reading { sensor: "t1".to_string(), values: vec![7; 200] };
+        let bytes = crate::to_bytes(&reading).unwrap();
+        let decoded: Reading = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, reading);
+        assert!(crate::from_bytes::<Reading>(b"nope").is_err());
+
+        let packed = crate::compress(&bytes, CompressionFormat::LZ4, CompressionLevel::default()).unwrap();
+        let unpacked = crate::decompress(&packed).unwrap();
+        assert_eq!(unpacked, bytes);
+        clear_recorder();
+        crate::to_bytes(&reading).unwrap();
+
+        let name = Some(core::any::type_name::<Reading>());
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            [
+                (Operation::Serialize, name, bytes.len(), None, true),
+                (Operation::Deserialize, name, bytes.len(), None, true),
+                (Operation::Deserialize, name, 4, None, false),
+                (Operation::Compress, None, packed.len(), Some(bytes.len()), true),
+                (Operation::Decompress, None, packed.len(), Some(bytes.len()), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        let event = Event {
+            operation: Operation::Compress,
+            type_name: None,
+            bytes: 25,
+            uncompressed_bytes: Some(100),
+            duration: Duration::ZERO,
+            error: None,
+        };
+        assert_eq!(event.compression_ratio(), Some(4.0));
+        assert_eq!(Event { uncompressed_bytes: None, ..event }.compression_ratio(), None);
+        let error = Error::InvalidFormat("bad".to_string());
+        assert_eq!(Event { error: Some(&error), ..event }.compression_ratio(), None);
+    }
+}
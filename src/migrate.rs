@@ -0,0 +1,249 @@
+//! Upgrading stored payloads written by older versions of a type
+//!
+//! A [`Migrations`] chain knows the current version of a type and how to
+//! upgrade each older version to the next one, either on a dynamic
+//! [`Value`] or by converting between the old and new Rust types. Payloads
+//! record their version as a type tag such as `"v3"` (see
+//! [`peek_type`](crate::peek_type)), and [`load_with_migrations`] runs
+//! whichever steps a payload still needs:
+//!
+//! ```rust
+//! use nanobit::migrate::{load_with_migrations, to_bytes_versioned, Migrations};
+//! use nanobit::Value;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct SettingsV1 { volume: u8 }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct SettingsV2 { level: u8 }
+//!
+//! #[derive(Serialize, Deserialize, PartialEq, Debug)]
+//! struct Settings { level: f32 }
+//!
+//! impl From<SettingsV2> for Settings {
+//!     fn from(old: SettingsV2) -> Self {
+//!         Settings { level: f32::from(old.level) / 100.0 }
+//!     }
+//! }
+//!
+//! let migrations = Migrations::<Settings>::new(1)
+//!     // v1 -> v2: `volume` was renamed to `level`
+//!     .value_step(|mut value| {
+//!         if let Value::Map(entries) = &mut value {
+//!             for (key, _) in entries.iter_mut().filter(|(key, _)| key.as_str() == Some("volume")) {
+//!                 *key = Value::Str("level".into());
+//!             }
+//!         }
+//!         Ok(value)
+//!     })
+//!     // v2 -> v3: the level became a fraction
+//!     .typed_step(|old: SettingsV2| Settings::from(old));
+//! assert_eq!(migrations.version(), 3);
+//!
+//! // Written by the first release
+//! let stored = to_bytes_versioned(&SettingsV1 { volume: 40 }, &Migrations::<SettingsV1>::new(1))?;
+//! assert_eq!(load_with_migrations(&stored, &migrations)?, Settings { level: 0.4 });
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Value steps need the older payload to be self-describing, which is how
+//! [`to_bytes_versioned`] writes them unless configured otherwise; typed
+//! steps read either form.
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, format, string::ToString, vec::Vec};
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::ser::Serializer;
+use crate::value::{from_value, to_value, Value};
+
+type Step = Box<dyn Fn(Stage<'_>) -> Result<Value> + Send + Sync>;
+
+// A payload on its way through the chain: still encoded until a step
+// decodes it
+enum Stage<'a> {
+    Bytes(&'a [u8]),
+    Value(Value),
+}
+
+impl Stage<'_> {
+    fn decode<U: DeserializeOwned>(self) -> Result<U> {
+        match self {
+            Stage::Bytes(bytes) => crate::from_bytes(bytes),
+            Stage::Value(value) => from_value(value),
+        }
+    }
+}
+
+/// Versions of a type `T` and the steps that upgrade each one to the next
+pub struct Migrations<T> {
+    first: u32,
+    steps: Vec<Step>,
+    unversioned: Option<u32>,
+    config: Config,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Migrations<T> {
+    /// Start a chain whose oldest supported version is `first_version`
+    ///
+    /// With no steps, `first_version` is also the current version.
+    pub fn new(first_version: u32) -> Self {
+        Self {
+            first: first_version,
+            steps: Vec::new(),
+            unversioned: None,
+            config: Config::new().with_self_describing(true),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Upgrade the current version to the next by rewriting its [`Value`]
+    pub fn value_step<F>(mut self, step: F) -> Self
+    where
+        F: Fn(Value) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.steps.push(Box::new(move |stage| step(stage.decode()?)));
+        self
+    }
+
+    /// Upgrade the current version to the next by converting between the
+    /// types that represent them
+    pub fn typed_step<Old, New, F>(mut self, step: F) -> Self
+    where
+        Old: DeserializeOwned,
+        New: Serialize,
+        F: Fn(Old) -> New + Send + Sync + 'static,
+    {
+        self.steps.push(Box::new(move |stage| to_value(&step(stage.decode()?))));
+        self
+    }
+
+    /// Treat payloads without a version, written before the chain was
+    /// introduced, as `version`
+    pub fn assume_version(mut self, version: u32) -> Self {
+        self.unversioned = Some(version);
+        self
+    }
+
+    /// Encode new payloads with the given options
+    ///
+    /// Payloads are self-describing by default, so that value steps can
+    /// read them once the type has moved on.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Get the version written by [`to_bytes_versioned`]
+    #[inline]
+    pub fn version(&self) -> u32 {
+        self.first + self.steps.len() as u32
+    }
+}
+
+impl<T> fmt::Debug for Migrations<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrations")
+            .field("first", &self.first)
+            .field("version", &self.version())
+            .field("unversioned", &self.unversioned)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serialize a value as the current version of its migration chain
+pub fn to_bytes_versioned<T>(value: &T, migrations: &Migrations<T>) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut serializer = Serializer::with_config(migrations.config);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_bytes_with_type_name(&format!("v{}", migrations.version())))
+}
+
+/// Deserialize a payload written by any supported version, upgrading it
+/// to the current one
+pub fn load_with_migrations<T>(bytes: &[u8], migrations: &Migrations<T>) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let version = match crate::peek_type(bytes)? {
+        Some(tag) => tag
+            .strip_prefix('v')
+            .and_then(|n| n.parse::<u32>().ok())
+            .ok_or_else(|| Error::InvalidFormat(format!("Payload tag {tag:?} is not a version")))?,
+        None => migrations
+            .unversioned
+            .ok_or_else(|| Error::InvalidFormat("Payload has no version".to_string()))?,
+    };
+    if version > migrations.version() {
+        return Err(Error::InvalidFormat(format!(
+            "Payload version {version} is newer than {}",
+            migrations.version()
+        )));
+    }
+    let skip = version
+        .checked_sub(migrations.first)
+        .ok_or_else(|| Error::InvalidFormat(format!("No migration from version {version}")))?;
+
+    let mut stage = Stage::Bytes(bytes);
+    for step in &migrations.steps[skip as usize..] {
+        stage = Stage::Value(step(stage)?);
+    }
+    stage.decode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct UserV1 {
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct User {
+        name: String,
+        admin: bool,
+    }
+
+    #[test]
+    fn test_upgrade_chain() {
+        let migrations = Migrations::<User>::new(1)
+            .assume_version(1)
+            .typed_step(|old: UserV1| User { name: old.name, admin: false })
+            .value_step(|mut value| {
+                if value.get("name").and_then(Value::as_str) == Some("root") {
+                    *value.get_mut("admin").expect("added by v2") = Value::Bool(true);
+                }
+                Ok(value)
+            });
+        let user = |name: &str, admin| User { name: name.to_string(), admin };
+
+        // Plain payloads from before versioning only need typed steps
+        let legacy = crate::to_bytes(&UserV1 { name: "root".to_string() }).unwrap();
+        assert_eq!(load_with_migrations(&legacy, &migrations).unwrap(), user("root", true));
+
+        let v2 = to_bytes_versioned(&user("root", false), &Migrations::new(2)).unwrap();
+        assert_eq!(crate::peek_type(&v2).unwrap(), Some("v2"));
+        assert_eq!(load_with_migrations(&v2, &migrations).unwrap(), user("root", true));
+
+        let current = to_bytes_versioned(&user("root", false), &migrations).unwrap();
+        assert_eq!(load_with_migrations(&current, &migrations).unwrap(), user("root", false));
+
+        let future = to_bytes_versioned(&user("ops", false), &Migrations::new(4)).unwrap();
+        assert!(matches!(load_with_migrations(&future, &migrations), Err(Error::InvalidFormat(_))));
+        assert!(load_with_migrations(&legacy, &Migrations::<User>::new(1)).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Lossy storage of floats at reduced precision
+//!
+//! Telemetry and sensor readings rarely need the 15–17 significant digits
+//! of an `f64`. Two opt-in wrappers store them in fewer bytes:
+//!
+//! - [`AsF32`] writes each value as an `f32`, halving the size of `f64`
+//! - `AsF16` writes each value as an IEEE 754 half-precision float in
+//!   two bytes (`half` feature)
+//!
+//! **Both are lossy.** A value comes back as the nearest representable
+//! number at the narrower width: `f32` keeps about 7 significant digits,
+//! `f16` about 3 and overflows to infinity beyond ±65504. Keep timestamps,
+//! coordinates and money at full width.
+//!
+//! The wrappers cover `f64` and `f32`, and `Option` and `Vec` of those.
+//! Fields can keep their type by naming the matching module in
+//! `#[serde(with)]`:
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Reading {
+//!     at_ms: u64,
+//!     #[serde(with = "nanobit::narrow::as_f32")]
+//!     celsius: f64,
+//!     #[serde(with = "nanobit::narrow::as_f32")]
+//!     samples: Vec<f64>,
+//! }
+//!
+//! let reading = Reading { at_ms: 1_700_000_000_000, celsius: 21.5, samples: vec![0.25; 64] };
+//! let bytes = nanobit::to_bytes(&reading)?;
+//! assert_eq!(nanobit::from_bytes::<Reading>(&bytes)?, reading);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! The narrowed value is an ordinary `f32` (or the `u16` bits of an
+//! `f16`), so the wrappers work in every encoding mode. A payload written
+//! with them must be read with them as well.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Values that [`AsF32`] and `AsF16` can narrow
+pub trait Narrow: sealed::Sealed + Sized {
+    /// Serialize with every float stored as an `f32`
+    fn serialize_f32<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+
+    /// Deserialize floats stored as `f32` by [`serialize_f32`](Narrow::serialize_f32)
+    fn deserialize_f32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+
+    /// Serialize with every float stored as the bits of an `f16`
+    #[cfg(feature = "half")]
+    fn serialize_f16<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+
+    /// Deserialize floats stored as `f16` by [`serialize_f16`](Narrow::serialize_f16)
+    #[cfg(feature = "half")]
+    fn deserialize_f16<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+macro_rules! impl_narrow_float {
+    ($($float:ty),*) => {
+        $(
+            impl sealed::Sealed for $float {}
+
+            impl Narrow for $float {
+                #[inline]
+                fn serialize_f32<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_f32(*self as f32)
+                }
+
+                #[inline]
+                fn deserialize_f32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    f32::deserialize(deserializer).map(<$float>::from)
+                }
+
+                #[cfg(feature = "half")]
+                #[inline]
+                fn serialize_f16<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_u16(half::f16::from_f64(*self as f64).to_bits())
+                }
+
+                #[cfg(feature = "half")]
+                #[inline]
+                fn deserialize_f16<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    u16::deserialize(deserializer).map(|bits| half::f16::from_bits(bits).to_f32().into())
+                }
+            }
+        )*
+    };
+}
+
+impl_narrow_float!(f32, f64);
+
+/// Generates a wrapper storing floats at one width, a borrowed form of it
+/// for serializing elements in place, and its `#[serde(with)]` module
+macro_rules! narrow_width {
+    ($(#[$doc:meta])* $wrapper:ident, $by_ref:ident, $module:ident, $ser:ident, $de:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+        pub struct $wrapper<T>(pub T);
+
+        impl<T: Narrow> Serialize for $wrapper<T> {
+            #[inline]
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.$ser(serializer)
+            }
+        }
+
+        impl<'de, T: Narrow> Deserialize<'de> for $wrapper<T> {
+            #[inline]
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                T::$de(deserializer).map($wrapper)
+            }
+        }
+
+        struct $by_ref<'a, T>(&'a T);
+
+        impl<T: Narrow> Serialize for $by_ref<'_, T> {
+            #[inline]
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.$ser(serializer)
+            }
+        }
+
+        #[doc = concat!("Use [`", stringify!($wrapper), "`] for a field with `#[serde(with)]`")]
+        pub mod $module {
+            use super::Narrow;
+            use serde::{Deserializer, Serializer};
+
+            #[doc = concat!("Serialize a value as [`", stringify!($wrapper), "`](super::", stringify!($wrapper), ") would")]
+            #[inline]
+            pub fn serialize<T: Narrow, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+                value.$ser(serializer)
+            }
+
+            #[doc = concat!("Deserialize a value written as [`", stringify!($wrapper), "`](super::", stringify!($wrapper), ")")]
+            #[inline]
+            pub fn deserialize<'de, T: Narrow, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+                T::$de(deserializer)
+            }
+        }
+    };
+}
+
+narrow_width!(
+    /// Floats stored as `f32`, losing precision beyond about 7 digits
+    ///
+    /// ```rust
+    /// use nanobit::AsF32;
+    ///
+    /// let readings: Vec<f64> = (0..1000).map(|i| i as f64 / 8.0).collect();
+    /// let narrow = nanobit::to_bytes(&AsF32(readings.clone()))?;
+    /// assert!(narrow.len() < nanobit::to_bytes(&readings)?.len() * 6 / 10);
+    ///
+    /// // Eighths are exact in f32; most decimals are not
+    /// let AsF32(decoded) = nanobit::from_bytes::<AsF32<Vec<f64>>>(&narrow)?;
+    /// assert_eq!(decoded, readings);
+    /// let AsF32(pi) = nanobit::from_bytes::<AsF32<f64>>(&nanobit::to_bytes(&AsF32(std::f64::consts::PI))?)?;
+    /// assert_eq!(pi, std::f32::consts::PI as f64);
+    /// # Ok::<(), nanobit::Error>(())
+    /// ```
+    AsF32, F32Ref, as_f32, serialize_f32, deserialize_f32
+);
+
+#[cfg(feature = "half")]
+narrow_width!(
+    /// Floats stored as half-precision `f16`, losing precision beyond about
+    /// 3 digits and overflowing to infinity beyond ±65504
+    ///
+    /// ```rust
+    /// use nanobit::AsF16;
+    ///
+    /// let bytes = nanobit::to_bytes(&AsF16(vec![0.5f32, 1.0, 1.1, 70000.0]))?;
+    /// let AsF16(decoded) = nanobit::from_bytes::<AsF16<Vec<f32>>>(&bytes)?;
+    /// assert_eq!(decoded, [0.5, 1.0, 1.099_609_4, f32::INFINITY]);
+    /// # Ok::<(), nanobit::Error>(())
+    /// ```
+    AsF16, F16Ref, as_f16, serialize_f16, deserialize_f16
+);
+
+impl<T: Narrow> sealed::Sealed for Option<T> {}
+
+impl<T: Narrow> Narrow for Option<T> {
+    fn serialize_f32<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Some(value) => serializer.serialize_some(&F32Ref(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn deserialize_f32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<AsF32<T>>::deserialize(deserializer)?.map(|AsF32(value)| value))
+    }
+
+    #[cfg(feature = "half")]
+    fn serialize_f16<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Some(value) => serializer.serialize_some(&F16Ref(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    #[cfg(feature = "half")]
+    fn deserialize_f16<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<AsF16<T>>::deserialize(deserializer)?.map(|AsF16(value)| value))
+    }
+}
+
+impl<T: Narrow> sealed::Sealed for Vec<T> {}
+
+impl<T: Narrow> Narrow for Vec<T> {
+    fn serialize_f32<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(F32Ref))
+    }
+
+    fn deserialize_f32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<AsF32<T>>::deserialize(deserializer)?.into_iter().map(|AsF32(value)| value).collect())
+    }
+
+    #[cfg(feature = "half")]
+    fn serialize_f16<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(F16Ref))
+    }
+
+    #[cfg(feature = "half")]
+    fn deserialize_f16<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<AsF16<T>>::deserialize(deserializer)?.into_iter().map(|AsF16(value)| value).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Telemetry {
+        at: f64,
+        #[serde(with = "as_f32")]
+        load: f64,
+        #[serde(with = "as_f32")]
+        peak: Option<f64>,
+        #[serde(with = "as_f32")]
+        history: Vec<f64>,
+    }
+
+    #[test]
+    fn test_f32_narrowing() {
+        let telemetry = Telemetry {
+            at: 1_700_000_000.123_456,
+            load: 0.1,
+            peak: Some(0.75),
+            history: vec![0.5, 1.0 / 3.0, 1e300],
+        };
+        let full = crate::to_bytes(&(telemetry.at, telemetry.load, telemetry.peak, &telemetry.history)).unwrap();
+
+        for config in [Config::new(), Config::new().with_tagged_fields(true), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&telemetry, config).unwrap();
+            let decoded: Telemetry = crate::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.at, telemetry.at);
+            assert_eq!(decoded.load, 0.1f32 as f64);
+            assert_eq!(decoded.peak, Some(0.75));
+            assert_eq!(decoded.history, [0.5, (1.0f64 / 3.0) as f32 as f64, f64::INFINITY]);
+            if config == Config::new() {
+                assert_eq!(bytes.len(), full.len() - 5 * 4);
+            }
+        }
+
+        let none = Telemetry { peak: None, ..telemetry };
+        let decoded: Telemetry = crate::from_bytes(&crate::to_bytes(&none).unwrap()).unwrap();
+        assert_eq!(decoded.peak, None);
+
+        // Narrowed floats are plain f32 values on the wire
+        let bytes = crate::to_bytes(&AsF32(vec![Some(2.5f64), None])).unwrap();
+        assert_eq!(crate::from_bytes::<Vec<Option<f32>>>(&bytes).unwrap(), [Some(2.5), None]);
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn test_f16_narrowing() {
+        let values = vec![0.0f64, -2.0, 0.1, 65504.0, 1e6, f64::NAN];
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&AsF16(values.clone()), config).unwrap();
+            let AsF16(decoded) = crate::from_bytes::<AsF16<Vec<f64>>>(&bytes).unwrap();
+            assert_eq!(decoded[..4], [0.0, -2.0, 0.099_975_585_937_5, 65504.0]);
+            assert_eq!(decoded[4], f64::INFINITY);
+            assert!(decoded[5].is_nan());
+        }
+
+        let bytes = crate::to_bytes(&AsF16(Some(1.5f32))).unwrap();
+        assert_eq!(bytes.len(), crate::to_bytes(&Some(1u16)).unwrap().len());
+        assert_eq!(crate::from_bytes::<AsF16<Option<f32>>>(&bytes).unwrap(), AsF16(Some(1.5)));
+    }
+}
@@ -0,0 +1,370 @@
+//! Compact encoding for IP and socket addresses
+//!
+//! The standard library's serde impls write addresses as strings to any
+//! format that reports itself human-readable, which serde assumes by
+//! default, so `192.168.100.200:65535` takes 22 bytes. The
+//! [`CompactAddr`] wrapper, or `#[serde(with = "nanobit::net")]` on a
+//! field, writes a one-byte tag, the 4 or 16 address bytes and the port:
+//!
+//! ```rust
+//! use std::net::{IpAddr, SocketAddr};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Flow {
+//!     #[serde(with = "nanobit::net")]
+//!     source: SocketAddr,
+//!     #[serde(with = "nanobit::net")]
+//!     gateway: IpAddr,
+//!     bytes: u64,
+//! }
+//!
+//! let flow = Flow { source: "192.168.100.200:65535".parse()?, gateway: "10.0.0.1".parse()?, bytes: 1500 };
+//! let bytes = nanobit::to_bytes(&flow)?;
+//! assert!(bytes.len() < nanobit::to_bytes(&("192.168.100.200:65535", "10.0.0.1", 1500u64))?.len() - 16);
+//! assert_eq!(nanobit::from_bytes::<Flow>(&bytes)?, flow);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! # Representation
+//!
+//! An address is a byte string starting with a tag:
+//!
+//! | Tag | Followed by                                      | Length  |
+//! |-----|--------------------------------------------------|---------|
+//! | `4` | IPv4 address, then the port for socket addresses | 5 / 7   |
+//! | `6` | IPv6 address, then the port for socket addresses | 17 / 19 |
+//! | `7` | IPv6 address, port, flow info and scope id       | 27      |
+//!
+//! Multi-byte numbers are big-endian, as on the network. Tag `7` is only
+//! written for IPv6 socket addresses with a non-zero flow info or scope
+//! id. Every supported type writes the same tags, so an `Ipv4Addr` field
+//! can later widen to `IpAddr`, and `SocketAddrV6` to `SocketAddr`.
+//!
+//! Decoding also accepts the text forms written by the standard serde
+//! impls, so a field can switch to the compact encoding while existing
+//! payloads remain readable.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::str::FromStr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use serde::de::{Deserialize, Deserializer, Error as _, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+const TAG_V4: u8 = 4;
+const TAG_V6: u8 = 6;
+const TAG_V6_SCOPED: u8 = 7;
+
+/// Length of the longest encoded address, an IPv6 socket address with
+/// flow info and scope id
+pub const MAX_ENCODED_LEN: usize = 27;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Addresses that [`CompactAddr`] can encode
+pub trait NetAddr: sealed::Sealed + FromStr + Sized {
+    /// Write the compact representation into `out`, returning its length
+    fn write_compact(&self, out: &mut [u8; MAX_ENCODED_LEN]) -> usize;
+
+    /// Read an address from its compact representation
+    fn read_compact(bytes: &[u8]) -> Option<Self>;
+}
+
+fn write_ip(ip: IpAddr, out: &mut [u8; MAX_ENCODED_LEN]) -> usize {
+    match ip {
+        IpAddr::V4(ip) => {
+            out[0] = TAG_V4;
+            out[1..5].copy_from_slice(&ip.octets());
+            5
+        }
+        IpAddr::V6(ip) => {
+            out[0] = TAG_V6;
+            out[1..17].copy_from_slice(&ip.octets());
+            17
+        }
+    }
+}
+
+// Split an encoding into its address and the bytes after it
+fn read_ip(bytes: &[u8]) -> Option<(IpAddr, &[u8])> {
+    match *bytes.first()? {
+        TAG_V4 => {
+            let octets: [u8; 4] = bytes.get(1..5)?.try_into().ok()?;
+            Some((Ipv4Addr::from(octets).into(), &bytes[5..]))
+        }
+        TAG_V6 | TAG_V6_SCOPED => {
+            let octets: [u8; 16] = bytes.get(1..17)?.try_into().ok()?;
+            Some((Ipv6Addr::from(octets).into(), &bytes[17..]))
+        }
+        _ => None,
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+impl sealed::Sealed for IpAddr {}
+
+impl NetAddr for IpAddr {
+    fn write_compact(&self, out: &mut [u8; MAX_ENCODED_LEN]) -> usize {
+        write_ip(*self, out)
+    }
+
+    fn read_compact(bytes: &[u8]) -> Option<Self> {
+        match read_ip(bytes)? {
+            (ip, []) if bytes[0] != TAG_V6_SCOPED => Some(ip),
+            _ => None,
+        }
+    }
+}
+
+impl sealed::Sealed for Ipv4Addr {}
+
+impl NetAddr for Ipv4Addr {
+    fn write_compact(&self, out: &mut [u8; MAX_ENCODED_LEN]) -> usize {
+        write_ip(IpAddr::V4(*self), out)
+    }
+
+    fn read_compact(bytes: &[u8]) -> Option<Self> {
+        match IpAddr::read_compact(bytes)? {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        }
+    }
+}
+
+impl sealed::Sealed for Ipv6Addr {}
+
+impl NetAddr for Ipv6Addr {
+    fn write_compact(&self, out: &mut [u8; MAX_ENCODED_LEN]) -> usize {
+        write_ip(IpAddr::V6(*self), out)
+    }
+
+    fn read_compact(bytes: &[u8]) -> Option<Self> {
+        match IpAddr::read_compact(bytes)? {
+            IpAddr::V6(ip) => Some(ip),
+            IpAddr::V4(_) => None,
+        }
+    }
+}
+
+impl sealed::Sealed for SocketAddr {}
+
+impl NetAddr for SocketAddr {
+    fn write_compact(&self, out: &mut [u8; MAX_ENCODED_LEN]) -> usize {
+        let len = write_ip(self.ip(), out);
+        out[len..len + 2].copy_from_slice(&self.port().to_be_bytes());
+        match self {
+            SocketAddr::V6(addr) if addr.flowinfo() != 0 || addr.scope_id() != 0 => {
+                out[0] = TAG_V6_SCOPED;
+                out[19..23].copy_from_slice(&addr.flowinfo().to_be_bytes());
+                out[23..27].copy_from_slice(&addr.scope_id().to_be_bytes());
+                MAX_ENCODED_LEN
+            }
+            _ => len + 2,
+        }
+    }
+
+    fn read_compact(bytes: &[u8]) -> Option<Self> {
+        let (ip, rest) = read_ip(bytes)?;
+        match ip {
+            IpAddr::V6(ip) if bytes[0] == TAG_V6_SCOPED && rest.len() == 10 => Some(
+                SocketAddrV6::new(ip, read_u16(&rest[..2])?, read_u32(&rest[2..6])?, read_u32(&rest[6..])?).into(),
+            ),
+            _ if bytes[0] == TAG_V6_SCOPED => None,
+            ip => Some(SocketAddr::new(ip, read_u16(rest)?)),
+        }
+    }
+}
+
+impl sealed::Sealed for SocketAddrV4 {}
+
+impl NetAddr for SocketAddrV4 {
+    fn write_compact(&self, out: &mut [u8; MAX_ENCODED_LEN]) -> usize {
+        SocketAddr::V4(*self).write_compact(out)
+    }
+
+    fn read_compact(bytes: &[u8]) -> Option<Self> {
+        match SocketAddr::read_compact(bytes)? {
+            SocketAddr::V4(addr) => Some(addr),
+            SocketAddr::V6(_) => None,
+        }
+    }
+}
+
+impl sealed::Sealed for SocketAddrV6 {}
+
+impl NetAddr for SocketAddrV6 {
+    fn write_compact(&self, out: &mut [u8; MAX_ENCODED_LEN]) -> usize {
+        SocketAddr::V6(*self).write_compact(out)
+    }
+
+    fn read_compact(bytes: &[u8]) -> Option<Self> {
+        match SocketAddr::read_compact(bytes)? {
+            SocketAddr::V6(addr) => Some(addr),
+            SocketAddr::V4(_) => None,
+        }
+    }
+}
+
+/// An IP or socket address written in its compact form
+///
+/// See the [module documentation](self) for the representation.
+///
+/// ```rust
+/// use std::net::SocketAddr;
+/// use nanobit::CompactAddr;
+///
+/// let peer: SocketAddr = "[2001:db8::1]:443".parse()?;
+/// let bytes = nanobit::to_bytes(&CompactAddr(peer))?;
+/// assert_eq!(bytes.len(), nanobit::to_bytes(&())?.len() + 20);
+///
+/// let CompactAddr(decoded) = nanobit::from_bytes::<CompactAddr<SocketAddr>>(&bytes)?;
+/// assert_eq!(decoded, peer);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactAddr<T>(pub T);
+
+impl<T: NetAddr> Serialize for CompactAddr<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: NetAddr> Deserialize<'de> for CompactAddr<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(CompactAddr)
+    }
+}
+
+/// Serialize an address in its compact form
+pub fn serialize<T, S>(addr: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: NetAddr,
+    S: Serializer,
+{
+    let mut out = [0u8; MAX_ENCODED_LEN];
+    let len = addr.write_compact(&mut out);
+    serializer.serialize_bytes(&out[..len])
+}
+
+/// Deserialize an address from its compact or text form
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: NetAddr,
+    D: Deserializer<'de>,
+{
+    struct AddrVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: NetAddr> Visitor<'de> for AddrVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a compact or text network address")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<T, E> {
+            // Tags are unprintable, so anything else is the text form, which
+            // reaches here as its UTF-8 bytes when there are no type tags
+            match bytes.first() {
+                Some(&(TAG_V4 | TAG_V6 | TAG_V6_SCOPED)) => {
+                    T::read_compact(bytes).ok_or_else(|| E::custom("Invalid compact network address"))
+                }
+                _ => match core::str::from_utf8(bytes) {
+                    Ok(text) => self.visit_str(text),
+                    Err(_) => Err(E::custom("Invalid compact network address")),
+                },
+            }
+        }
+
+        fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<T, E> {
+            text.parse().map_err(|_| E::invalid_value(serde::de::Unexpected::Str(text), &self))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let mut bytes = [0u8; MAX_ENCODED_LEN];
+            let mut len = 0;
+            while let Some(byte) = seq.next_element()? {
+                if len == MAX_ENCODED_LEN {
+                    return Err(A::Error::custom("Invalid compact network address"));
+                }
+                bytes[len] = byte;
+                len += 1;
+            }
+            self.visit_bytes(&bytes[..len])
+        }
+    }
+
+    deserializer.deserialize_bytes(AddrVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    fn roundtrip<T: NetAddr + Copy + PartialEq + fmt::Debug>(addr: T, len: usize) {
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&CompactAddr(addr), config).unwrap();
+            let empty = crate::to_bytes_with_config(&(), config).unwrap().len();
+            if !config.self_describing() {
+                assert_eq!(bytes.len() - empty, len + 1);
+            }
+            assert_eq!(crate::from_bytes::<CompactAddr<T>>(&bytes).unwrap(), CompactAddr(addr));
+        }
+    }
+
+    #[test]
+    fn test_addresses_roundtrip() {
+        let v4: Ipv4Addr = "192.0.2.33".parse().unwrap();
+        let v6: Ipv6Addr = "2001:db8::ff00:42:8329".parse().unwrap();
+        roundtrip(v4, 5);
+        roundtrip(v6, 17);
+        roundtrip(IpAddr::V4(v4), 5);
+        roundtrip(IpAddr::V6(v6), 17);
+        roundtrip(SocketAddrV4::new(v4, 8080), 7);
+        roundtrip(SocketAddrV6::new(v6, 443, 0, 0), 19);
+        roundtrip(SocketAddrV6::new(v6, 443, 0x12345, 3), 27);
+        roundtrip(SocketAddr::new(v4.into(), 0), 7);
+        roundtrip(SocketAddr::from(SocketAddrV6::new(v6, 443, 0, 7)), 27);
+
+        // Narrow types widen, and the representation is spelled out
+        let bytes = crate::to_bytes(&CompactAddr(SocketAddrV4::new(v4, 8080))).unwrap();
+        assert!(bytes.ends_with(&[7, 4, 192, 0, 2, 33, 0x1f, 0x90]));
+        assert_eq!(crate::from_bytes::<CompactAddr<SocketAddr>>(&bytes).unwrap().0, SocketAddr::new(v4.into(), 8080));
+        assert!(crate::from_bytes::<CompactAddr<SocketAddrV6>>(&bytes).is_err());
+        assert!(crate::from_bytes::<CompactAddr<IpAddr>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_text_addresses_decode() {
+        for text in ["10.1.2.3:53", "[::1]:8080", "[fe80::1%2]:22"] {
+            let addr: SocketAddr = text.parse().unwrap();
+            let bytes = crate::to_bytes(&text).unwrap();
+            assert_eq!(crate::from_bytes::<CompactAddr<SocketAddr>>(&bytes).unwrap().0, addr);
+        }
+        let bytes = crate::to_bytes_with_config(&"10.1.2.3", Config::new().with_self_describing(true)).unwrap();
+        assert_eq!(crate::from_bytes::<CompactAddr<Ipv4Addr>>(&bytes).unwrap().0, Ipv4Addr::new(10, 1, 2, 3));
+
+        // Without type tags a byte string is written like a `Vec<u8>`
+        for invalid in [&[][..], &[4, 1, 2, 3], &[7; 19], &[9, 0, 0, 0, 0], b"not an address"] {
+            assert!(crate::from_bytes::<CompactAddr<SocketAddr>>(&crate::to_bytes(&invalid.to_vec()).unwrap()).is_err());
+        }
+    }
+}
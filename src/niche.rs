@@ -0,0 +1,162 @@
+//! `Option<NonZero*>` written as the bare integer
+//!
+//! A present `Option<NonZeroU64>` normally costs a tag byte on top of the
+//! 8 bytes of the integer. A `NonZero` integer is never zero, so zero is
+//! free to mean `None`. The [`NonZeroOption`] wrapper, or
+//! `#[serde(with = "nanobit::niche")]` on a field, writes the integer
+//! alone, with zero for `None`:
+//!
+//! ```rust
+//! use std::num::{NonZeroU32, NonZeroU64};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Node {
+//!     id: NonZeroU64,
+//!     #[serde(with = "nanobit::niche")]
+//!     parent: Option<NonZeroU64>,
+//!     #[serde(with = "nanobit::niche")]
+//!     owner: Option<NonZeroU32>,
+//! }
+//!
+//! let node = Node { id: NonZeroU64::new(9).unwrap(), parent: NonZeroU64::new(4), owner: None };
+//! let bytes = nanobit::to_bytes(&node)?;
+//! assert_eq!(bytes, nanobit::to_bytes(&(9u64, 4u64, 0u32))?);
+//! assert_eq!(nanobit::from_bytes::<Node>(&bytes)?, node);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! A `None` takes the full width of the integer rather than one byte, so
+//! the niche pays off for fields that are usually present.
+//!
+//! Every `NonZero` integer type is supported, signed and unsigned. The
+//! encoding is the one of the plain integer type in any configuration,
+//! so a field can move between `Option<NonZeroU16>` with this module and
+//! a bare `u16`. For strings, byte strings, sequences and maps, see
+//! [`Config::with_niche_options`](crate::Config::with_niche_options).
+
+use core::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128, NonZeroU16,
+    NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// `NonZero` integers that [`NonZeroOption`] can encode
+pub trait NonZeroInt: sealed::Sealed + Copy {
+    /// The integer type, written in place of the option
+    type Int: Serialize + for<'de> Deserialize<'de>;
+
+    /// The integer, or zero for `None`
+    fn to_int(value: Option<Self>) -> Self::Int;
+
+    /// The value, or `None` for zero
+    fn from_int(int: Self::Int) -> Option<Self>;
+}
+
+macro_rules! impl_nonzero {
+    ($($nonzero:ty => $int:ty),*) => {$(
+        impl sealed::Sealed for $nonzero {}
+
+        impl NonZeroInt for $nonzero {
+            type Int = $int;
+
+            #[inline]
+            fn to_int(value: Option<Self>) -> $int {
+                value.map_or(0, <$nonzero>::get)
+            }
+
+            #[inline]
+            fn from_int(int: $int) -> Option<Self> {
+                <$nonzero>::new(int)
+            }
+        }
+    )*};
+}
+
+impl_nonzero!(
+    NonZeroU8 => u8, NonZeroU16 => u16, NonZeroU32 => u32, NonZeroU64 => u64, NonZeroU128 => u128, NonZeroUsize => usize,
+    NonZeroI8 => i8, NonZeroI16 => i16, NonZeroI32 => i32, NonZeroI64 => i64, NonZeroI128 => i128, NonZeroIsize => isize
+);
+
+/// An optional `NonZero` integer written as the integer, zero for `None`
+///
+/// ```rust
+/// use std::num::NonZeroU16;
+/// use nanobit::niche::NonZeroOption;
+///
+/// let bytes = nanobit::to_bytes(&NonZeroOption(NonZeroU16::new(500)))?;
+/// assert_eq!(bytes, nanobit::to_bytes(&500u16)?);
+/// assert_eq!(nanobit::from_bytes::<NonZeroOption<NonZeroU16>>(&nanobit::to_bytes(&0u16)?)?.0, None);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NonZeroOption<T>(pub Option<T>);
+
+impl<T: NonZeroInt> Serialize for NonZeroOption<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: NonZeroInt> Deserialize<'de> for NonZeroOption<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(NonZeroOption)
+    }
+}
+
+/// Serialize an optional `NonZero` integer as the integer, zero for `None`
+pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: NonZeroInt,
+    S: Serializer,
+{
+    T::to_int(*value).serialize(serializer)
+}
+
+/// Deserialize an optional `NonZero` integer written by [`serialize`]
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: NonZeroInt,
+    D: Deserializer<'de>,
+{
+    T::Int::deserialize(deserializer).map(T::from_int)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_nonzero_option_roundtrip() {
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            for value in [None, NonZeroU64::new(1), NonZeroU64::new(u64::MAX)] {
+                let bytes = crate::to_bytes_with_config(&NonZeroOption(value), config).unwrap();
+                let plain = crate::to_bytes_with_config(&value.map_or(0, NonZeroU64::get), config).unwrap();
+                assert_eq!(bytes, plain);
+                assert_eq!(crate::from_bytes::<NonZeroOption<NonZeroU64>>(&bytes).unwrap().0, value);
+            }
+            for value in [None, NonZeroI8::new(i8::MIN), NonZeroI8::new(-1)] {
+                let bytes = crate::to_bytes_with_config(&NonZeroOption(value), config).unwrap();
+                assert_eq!(crate::from_bytes::<NonZeroOption<NonZeroI8>>(&bytes).unwrap().0, value);
+            }
+        }
+
+        let bytes = crate::to_bytes(&vec![NonZeroOption(NonZeroU32::new(7)), NonZeroOption(None)]).unwrap();
+        assert_eq!(bytes, crate::to_bytes(&vec![7u32, 0]).unwrap());
+        let some = crate::to_bytes(&NonZeroOption(NonZeroU32::new(7))).unwrap();
+        assert_eq!(some.len() + 1, crate::to_bytes(&NonZeroU32::new(7)).unwrap().len());
+    }
+}
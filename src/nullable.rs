@@ -0,0 +1,247 @@
+//! Presence bitmaps for structs with many optional fields
+//!
+//! Every `Option` field costs a tag byte, so a sparse record with thirty
+//! optional fields spends thirty bytes saying which ones are missing. The
+//! [`Nullable`] wrapper, or `#[serde(with = "nanobit::nullable")]` on a
+//! field holding the struct, writes one bit per field instead, followed by
+//! only the fields that are present:
+//!
+//! ```rust
+//! use nanobit::nullable::Nullable;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+//! struct Contact {
+//!     id: u32,
+//!     email: Option<String>,
+//!     phone: Option<String>,
+//!     fax: Option<String>,
+//!     company: Option<String>,
+//!     title: Option<String>,
+//!     verified: bool,
+//!     archived: bool,
+//! }
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Page {
+//!     #[serde(with = "nanobit::nullable")]
+//!     owner: Contact,
+//! }
+//!
+//! let contact = Contact { id: 7, phone: Some("555-0100".into()), verified: true, ..Contact::default() };
+//! let bytes = nanobit::to_bytes(&Nullable(&contact))?;
+//! // One bitmap byte in place of five option tags and two bools
+//! assert_eq!(nanobit::to_bytes(&contact)?.len() - bytes.len(), 7 - 1);
+//! assert_eq!(nanobit::from_bytes::<Nullable<Contact>>(&bytes)?.0, contact);
+//!
+//! let page = Page { owner: contact };
+//! assert_eq!(nanobit::from_bytes::<Page>(&nanobit::to_bytes(&page)?)?, page);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Only the fields of the wrapped struct itself use the bitmap; structs
+//! nested inside it are written as usual unless wrapped too. The wrapper
+//! has no effect on [self-describing](crate::Config::with_self_describing)
+//! and [tagged-field](crate::Config::with_tagged_fields) payloads, which
+//! write their fields with their own framing.
+//!
+//! With the `derive` feature, `#[nanobit(nullable)]` on a
+//! `#[derive(NanoBit)]` struct writes the same encoding through
+//! [`Encode`](crate::Encode) and [`Decode`](crate::Decode).
+//!
+//! # Representation
+//!
+//! The field count, then a bitmap of one bit per field, least significant
+//! bit first, then the present fields in order. The bit of an `Option`
+//! field is set when it is `Some`, and the value follows without a tag. A
+//! `bool` field lives entirely in its bit. Bits of other fields are zero,
+//! and their values are written as usual. Thirty optional fields take four
+//! bitmap bytes however many are set.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+// Newtype name the serializer recognises to write a struct with a bitmap
+pub(crate) const NULLABLE_MARKER: &str = "$nanobit::private::Nullable";
+
+/// A struct written with a presence bitmap
+///
+/// Wrap a struct, or a reference to one, when serializing, and the owned
+/// struct when deserializing. See the [module documentation](self).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Nullable<T>(pub T);
+
+impl<T> Deref for Nullable<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Nullable<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Serialize> Serialize for Nullable<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Nullable<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(Nullable)
+    }
+}
+
+/// Serialize a struct with a presence bitmap
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize + ?Sized,
+    S: Serializer,
+{
+    serializer.serialize_newtype_struct(NULLABLE_MARKER, value)
+}
+
+/// Deserialize a struct written by [`serialize`]
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    struct NullableVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for NullableVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a struct with a presence bitmap")
+        }
+
+        fn visit_newtype_struct<D>(self, deserializer: D) -> Result<T, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            T::deserialize(deserializer)
+        }
+    }
+
+    deserializer.deserialize_newtype_struct(NULLABLE_MARKER, NullableVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+    struct Sparse {
+        id: u64,
+        name: Option<String>,
+        active: bool,
+        score: Option<Option<f32>>,
+        history: Vec<Option<u8>>,
+        o0: Option<u16>, o1: Option<u16>, o2: Option<u16>, o3: Option<u16>, o4: Option<u16>,
+        o5: Option<u16>, o6: Option<u16>, o7: Option<u16>, o8: Option<u16>, o9: Option<u16>,
+    }
+
+    fn records() -> Vec<Sparse> {
+        (0..3000u64)
+            .map(|i| Sparse {
+                id: i,
+                name: (i % 5 == 0).then(|| format!("n{i}")),
+                active: i % 2 == 0,
+                score: (i % 3 == 0).then_some((i % 6 == 0).then_some(i as f32)),
+                history: vec![None, Some(i as u8)],
+                o7: (i % 7 == 0).then_some(i as u16),
+                ..Sparse::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nullable_roundtrip() {
+        let configs = [
+            Config::new(),
+            Config::new().with_niche_options(true),
+            Config::new().with_string_table(true),
+            Config::new().with_tagged_fields(true),
+            Config::new().with_self_describing(true),
+        ];
+        let wrapped: Vec<_> = records().into_iter().map(Nullable).collect();
+        for config in configs {
+            let bytes = crate::to_bytes_with_config(&wrapped, config).unwrap();
+            assert_eq!(crate::from_bytes::<Vec<Nullable<Sparse>>>(&bytes).unwrap(), wrapped);
+        }
+
+        // Framed formats ignore the wrapper
+        for config in [Config::new().with_tagged_fields(true), Config::new().with_self_describing(true)] {
+            assert_eq!(
+                crate::to_bytes_with_config(&wrapped, config).unwrap(),
+                crate::to_bytes_with_config(&records(), config).unwrap()
+            );
+        }
+
+        // Twelve option tags and a bool become a two-byte bitmap
+        let record = Sparse::default();
+        let saved = crate::to_bytes(&record).unwrap().len() - crate::to_bytes(&Nullable(&record)).unwrap().len();
+        assert_eq!(saved, 13 - 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_nullable_streamed() {
+        let wrapped: Vec<_> = records().into_iter().map(Nullable).collect();
+        let mut streamed = Vec::new();
+        let mut ser = crate::Serializer::to_stream(&mut streamed, Config::new()).unwrap();
+        wrapped.serialize(&mut ser).unwrap();
+        ser.finish().unwrap();
+        assert_eq!(streamed, crate::to_bytes(&wrapped).unwrap());
+    }
+
+    #[test]
+    fn test_nullable_scope() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Outer {
+            #[serde(with = "self")]
+            inner: Sparse,
+            plain: Sparse,
+            flag: Option<bool>,
+        }
+
+        let outer = Outer { inner: records()[6].clone(), plain: records()[6].clone(), flag: Some(false) };
+        let bytes = crate::to_bytes(&outer).unwrap();
+        assert_eq!(crate::from_bytes::<Outer>(&bytes).unwrap(), outer);
+        // The outer bitmap byte replaces the tag of `flag` and leaves the
+        // fields of `plain` alone
+        let nested = crate::to_bytes(&Nullable(&outer)).unwrap();
+        assert_eq!(nested.len(), bytes.len());
+        assert_eq!(crate::from_bytes::<Nullable<Outer>>(&nested).unwrap().0, outer);
+
+        // Values other than structs are written as usual
+        let values = vec![records()[1].clone()];
+        assert_eq!(crate::to_bytes(&Nullable(&values)).unwrap(), crate::to_bytes(&values).unwrap());
+        let bytes = crate::to_bytes(&Nullable(7u32)).unwrap();
+        assert_eq!(crate::from_bytes::<Nullable<u32>>(&bytes).unwrap().0, 7);
+
+        // The bitmap is sized from the field count, which must match
+        let record = crate::to_bytes(&Nullable((1u64, 2u64))).unwrap();
+        assert!(crate::from_bytes::<Nullable<Sparse>>(&record).is_err());
+    }
+}
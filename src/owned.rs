@@ -0,0 +1,200 @@
+//! A decoded value that carries its own buffer
+//!
+//! A struct of `&'a str` fields borrows from the bytes it was decoded
+//! from, so a function that reads a file or decompresses a payload cannot
+//! return it: the buffer is dropped at the end of the function. [`Owned`]
+//! keeps the buffer and the decoded view together, so the pair can be
+//! returned, stored and sent to other threads like any owned value:
+//!
+//! ```rust
+//! use nanobit::owned::{Owned, View};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Entry<'a> {
+//!     key: &'a str,
+//!     tags: Vec<&'a str>,
+//! }
+//!
+//! // Names the borrowing type at every lifetime
+//! impl View for Entry<'static> {
+//!     type Of<'a> = Entry<'a>;
+//! }
+//!
+//! fn load(compressed: &[u8]) -> nanobit::Result<Owned<Entry<'static>>> {
+//!     Owned::new(nanobit::decompress(compressed)?)
+//! }
+//!
+//! let entry = Entry { key: "alpha", tags: vec!["x", "y"] };
+//! let compressed = nanobit::compress_default(&nanobit::to_bytes(&entry)?)?;
+//! let owned = load(&compressed)?;
+//! assert_eq!(owned.get(), entry);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! The buffer is any byte container: `Vec<u8>` by default, or `Box<[u8]>`,
+//! `Arc<[u8]>` and, with the `bytes` feature, `Bytes`. The view is checked
+//! when the wrapper is created and decoded again from the buffer by each
+//! [`get`](Owned::get), which for borrowing types reads lengths and
+//! slices without allocating. Owned types work too, but gain nothing over
+//! decoding them directly.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::Deserialize;
+
+use crate::config::Config;
+use crate::error::Result;
+
+/// A type that borrows from its input, named at every lifetime
+///
+/// Implement it for the `'static` instance of the type, with `Of<'a>` the
+/// same type at lifetime `'a`. [`Owned`] uses it to hand out views that
+/// borrow from its buffer.
+pub trait View {
+    /// The type borrowing from input that lives for `'a`
+    type Of<'a>: Deserialize<'a>;
+}
+
+/// A buffer bundled with the value decoded from it
+///
+/// See the [module documentation](self).
+pub struct Owned<T, B = Vec<u8>> {
+    buffer: B,
+    config: Config,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T, B> Owned<T, B>
+where
+    T: View,
+    B: AsRef<[u8]>,
+{
+    /// Take ownership of `buffer`, checking that it decodes as `T`
+    pub fn new(buffer: B) -> Result<Self> {
+        Self::with_config(buffer, Config::new())
+    }
+
+    /// Take ownership of `buffer`, decoding with the given options
+    pub fn with_config(buffer: B, config: Config) -> Result<Self> {
+        crate::from_bytes_with_config::<T::Of<'_>>(buffer.as_ref(), config)?;
+        Ok(Self { buffer, config, marker: PhantomData })
+    }
+
+    /// The decoded value, borrowing from the buffer
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer no longer decodes, which only happens when a
+    /// `Deserialize` implementation of `T` does not give the same result
+    /// for the same bytes.
+    pub fn get(&self) -> T::Of<'_> {
+        crate::from_bytes_with_config(self.buffer.as_ref(), self.config)
+            .expect("buffer was validated when the value was created")
+    }
+}
+
+impl<T, B> Owned<T, B> {
+    /// The serialized bytes backing the value
+    #[inline]
+    pub fn buffer(&self) -> &B {
+        &self.buffer
+    }
+
+    /// Give the buffer back, dropping the value
+    #[inline]
+    pub fn into_buffer(self) -> B {
+        self.buffer
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: View> Owned<T> {
+    /// Read all of `reader` into a buffer and check that it decodes as `T`
+    pub fn from_reader<R: std::io::Read>(mut reader: R) -> Result<Self> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        Self::new(buffer)
+    }
+}
+
+impl<T, B: Clone> Clone for Owned<T, B> {
+    fn clone(&self) -> Self {
+        Self { buffer: self.buffer.clone(), config: self.config, marker: PhantomData }
+    }
+}
+
+impl<T, B> fmt::Debug for Owned<T, B>
+where
+    T: View,
+    B: AsRef<[u8]>,
+    for<'a> T::Of<'a>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Owned").field(&self.get()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::borrow::Cow;
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Record<'a> {
+        id: u32,
+        name: &'a str,
+        #[serde(borrow)]
+        note: Cow<'a, str>,
+        data: &'a [u8],
+    }
+
+    impl View for Record<'static> {
+        type Of<'a> = Record<'a>;
+    }
+
+    fn record() -> Record<'static> {
+        Record { id: 4, name: "widget", note: "fragile".into(), data: &[1, 2, 3] }
+    }
+
+    #[test]
+    fn test_owned_view() {
+        fn decode(bytes: &[u8]) -> Result<Owned<Record<'static>>> {
+            Owned::new(Vec::from(bytes))
+        }
+
+        let bytes = crate::to_bytes(&record()).unwrap();
+        let owned = decode(&bytes).unwrap();
+        let view = owned.get();
+        assert_eq!(view, record());
+        assert!(matches!(view.note, Cow::Borrowed(_)));
+        let range = owned.buffer().as_ptr_range();
+        assert!(range.contains(&view.name.as_ptr()));
+        assert_eq!(owned.into_buffer(), bytes);
+
+        let shared: Owned<Record<'static>, Arc<[u8]>> = Owned::new(Arc::from(&bytes[..])).unwrap();
+        let copy = shared.clone();
+        std::thread::spawn(move || assert_eq!(copy.get().name, "widget")).join().unwrap();
+        assert_eq!(format!("{shared:?}"), format!("Owned({:?})", record()));
+
+        let owned = Owned::<Record<'static>>::from_reader(&bytes[..]).unwrap();
+        assert_eq!(owned.get().id, 4);
+    }
+
+    #[test]
+    fn test_owned_rejects_invalid() {
+        let bytes = crate::to_bytes(&record()).unwrap();
+        assert!(Owned::<Record<'static>>::new(bytes[..bytes.len() - 1].to_vec()).is_err());
+
+        let config = Config::new().with_tagged_fields(true);
+        let bytes = crate::to_bytes_with_config(&record(), config).unwrap();
+        let owned = Owned::<Record<'static>>::with_config(bytes, config).unwrap();
+        assert_eq!(owned.get(), record());
+    }
+}
@@ -0,0 +1,284 @@
+//! Compact encodings for integer and repetitive sequences
+//!
+//! Two opt-in wrappers shrink sequences that the default encoding stores
+//! element by element:
+//!
+//! - [`DeltaEncoded`] writes each integer as the zigzag varint of its
+//!   difference from the previous one, so sorted timestamps and IDs take
+//!   one or two bytes per element
+//! - [`RunLength`] writes runs of equal elements as `(count, value)` pairs
+//!
+//! Both are ordinary serde types and work in every encoding mode. The
+//! output is still friendly to general compression, which runs afterwards.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, Error as _, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+
+/// Largest number of elements a [`RunLength`] sequence may expand to
+///
+/// A few bytes of run-length input can describe an arbitrarily long
+/// sequence, so decoding stops here rather than exhausting memory.
+pub const MAX_RUN_LENGTH_ELEMENTS: usize = 1 << 28;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Integers that [`DeltaEncoded`] can encode
+pub trait DeltaInt: sealed::Sealed + Copy {
+    /// The value widened to 64 bits, sign-extending signed types
+    fn to_u64(self) -> u64;
+
+    /// Narrow a widened value, or `None` if it is out of range
+    fn from_u64(value: u64) -> Option<Self>;
+}
+
+macro_rules! impl_delta_int {
+    (unsigned: $($u:ty),*; signed: $($i:ty),*) => {
+        $(
+            impl sealed::Sealed for $u {}
+
+            impl DeltaInt for $u {
+                #[inline]
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                #[inline]
+                fn from_u64(value: u64) -> Option<Self> {
+                    <$u>::try_from(value).ok()
+                }
+            }
+        )*
+        $(
+            impl sealed::Sealed for $i {}
+
+            impl DeltaInt for $i {
+                #[inline]
+                fn to_u64(self) -> u64 {
+                    self as i64 as u64
+                }
+
+                #[inline]
+                fn from_u64(value: u64) -> Option<Self> {
+                    <$i>::try_from(value as i64).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_delta_int!(unsigned: u8, u16, u32, u64, usize; signed: i8, i16, i32, i64, isize);
+
+/// A sequence of integers stored as zigzag varint deltas
+///
+/// ```rust
+/// use nanobit::DeltaEncoded;
+///
+/// let timestamps: Vec<u64> = (0..1000).map(|i| 1_700_000_000_000 + i * 250).collect();
+/// let packed = nanobit::to_bytes(&DeltaEncoded(timestamps.clone()))?;
+/// assert!(packed.len() < nanobit::to_bytes(&timestamps)?.len() / 3);
+///
+/// let DeltaEncoded(decoded) = nanobit::from_bytes::<DeltaEncoded<Vec<u64>>>(&packed)?;
+/// assert_eq!(decoded, timestamps);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DeltaEncoded<T>(pub T);
+
+impl<I: DeltaInt> Serialize for DeltaEncoded<Vec<I>> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut out = WriteBuffer::with_capacity(self.0.len() + 8);
+        let mut prev = 0u64;
+        for value in &self.0 {
+            let value = value.to_u64();
+            let delta = value.wrapping_sub(prev) as i64;
+            out.write_varint(((delta << 1) ^ (delta >> 63)) as u64)
+                .expect("writing to a WriteBuffer cannot fail");
+            prev = value;
+        }
+        serializer.serialize_bytes(out.as_slice())
+    }
+}
+
+impl<'de, I: DeltaInt> Deserialize<'de> for DeltaEncoded<Vec<I>> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DeltaVisitor<I>(PhantomData<I>);
+
+        impl<'de, I: DeltaInt> Visitor<'de> for DeltaVisitor<I> {
+            type Value = Vec<I>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a byte string of delta-encoded integers")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Vec<I>, E> {
+                let mut input = ReadBuffer::new(bytes);
+                let mut values = Vec::with_capacity(bytes.len());
+                let mut prev = 0u64;
+                let mut batch = [0u64; 64];
+                loop {
+                    let count = input.read_varints(&mut batch).map_err(E::custom)?;
+                    for &zigzag in &batch[..count] {
+                        let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+                        prev = prev.wrapping_add(delta as u64);
+                        values.push(I::from_u64(prev).ok_or_else(|| E::custom("Delta-encoded value out of range"))?);
+                    }
+                    if count < batch.len() {
+                        break;
+                    }
+                }
+                Ok(values)
+            }
+        }
+
+        deserializer.deserialize_bytes(DeltaVisitor(PhantomData)).map(DeltaEncoded)
+    }
+}
+
+/// A sequence stored as runs of equal elements
+///
+/// Encoded as a byte string of varint run lengths followed by a sequence
+/// holding the value of each run.
+///
+/// ```rust
+/// use nanobit::RunLength;
+///
+/// let mut status = vec!["ok"; 5000];
+/// status[1234] = "degraded";
+/// let packed = nanobit::to_bytes(&RunLength(status.clone()))?;
+/// assert!(packed.len() < 32);
+///
+/// let RunLength(decoded) = nanobit::from_bytes::<RunLength<Vec<&str>>>(&packed)?;
+/// assert_eq!(decoded, status);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RunLength<T>(pub T);
+
+impl<T: Serialize + PartialEq> Serialize for RunLength<Vec<T>> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut counts = WriteBuffer::with_capacity(16);
+        let mut heads = Vec::new();
+        for run in self.0.chunk_by(|a, b| a == b) {
+            counts.write_varint(run.len() as u64).expect("writing to a WriteBuffer cannot fail");
+            heads.push(&run[0]);
+        }
+
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&Bytes(counts.as_slice()))?;
+        tuple.serialize_element(&heads)?;
+        tuple.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Clone> Deserialize<'de> for RunLength<Vec<T>> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RunVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de> + Clone> Visitor<'de> for RunVisitor<T> {
+            type Value = Vec<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("run lengths and run values")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+                let missing = || A::Error::invalid_length(0, &"run lengths and run values");
+                let counts: RunCounts = seq.next_element()?.ok_or_else(missing)?;
+                let heads: Vec<T> = seq.next_element()?.ok_or_else(missing)?;
+
+                let mut counts = ReadBuffer::new(&counts.0);
+                let mut values = Vec::new();
+                for head in heads {
+                    let count = counts.read_varint().map_err(A::Error::custom)?;
+                    let total = usize::try_from(count)
+                        .ok()
+                        .and_then(|count| values.len().checked_add(count))
+                        .filter(|&total| total <= MAX_RUN_LENGTH_ELEMENTS);
+                    let Some(total) = total else {
+                        return Err(A::Error::custom("Run-length sequence too long"));
+                    };
+                    values.resize(total, head);
+                }
+                if counts.has_remaining() {
+                    return Err(A::Error::custom("More run lengths than run values"));
+                }
+                Ok(values)
+            }
+        }
+
+        deserializer.deserialize_tuple(2, RunVisitor(PhantomData)).map(RunLength)
+    }
+}
+
+// A byte string written with one call
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+// The varint run lengths of a `RunLength`
+struct RunCounts(Vec<u8>);
+
+impl<'de> Deserialize<'de> for RunCounts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        crate::byte_slice::deserialize(deserializer).map(RunCounts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_delta_encoded_roundtrip() {
+        let ids: Vec<i32> = vec![i32::MIN, -5, 0, 3, 3, 1_000_000, i32::MAX, 7];
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&DeltaEncoded(ids.clone()), config).unwrap();
+            let decoded: DeltaEncoded<Vec<i32>> = crate::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.0, ids);
+        }
+
+        // Values that do not fit the target type are rejected
+        let wide = crate::to_bytes(&DeltaEncoded(vec![1u64, 300])).unwrap();
+        assert!(crate::from_bytes::<DeltaEncoded<Vec<u8>>>(&wide).is_err());
+    }
+
+    #[test]
+    fn test_run_length_roundtrip_and_limit() {
+        let values: Vec<u16> = [vec![0; 1000], vec![7; 3], vec![0; 1000], vec![1]].concat();
+        let bytes = crate::to_bytes(&RunLength(values.clone())).unwrap();
+        assert!(bytes.len() < 24);
+        assert_eq!(crate::from_bytes::<RunLength<Vec<u16>>>(&bytes).unwrap().0, values);
+
+        let mut counts = WriteBuffer::new();
+        counts.write_varint(u64::MAX / 2).unwrap();
+        let bomb = crate::to_bytes(&(Bytes(counts.as_slice()), [1u8])).unwrap();
+        assert!(crate::from_bytes::<RunLength<Vec<u8>>>(&bomb).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Multi-threaded encoding and decoding of large sequences
+//!
+//! A sequence's elements follow each other with nothing marking where one
+//! ends, so decoding is inherently sequential. [`to_bytes_parallel`] writes
+//! a chunk index in front of the body, recording how many elements and
+//! bytes each chunk of [`CHUNK_ELEMENTS`] holds:
+//!
+//! ```text
+//! [header, extended flag 0x0800] [index length: varint]
+//! [chunk count: varint] [elements: varint, bytes: varint ...] [sequence]
+//! ```
+//!
+//! [`from_bytes_parallel`] decodes the chunks on the rayon thread pool.
+//! The sequence itself is ordinary, so [`from_bytes`](crate::from_bytes)
+//! reads indexed payloads as well, skipping the index. Self-describing
+//! payloads without an index are split by scanning their type tags, which
+//! is far cheaper than decoding; other payloads without one are decoded on
+//! the calling thread.
+
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Serialize, Serializer as _};
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+use crate::checksum::split_payload;
+use crate::config::Config;
+use crate::de::{skip_value, Deserializer};
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+use crate::ser::Serializer;
+use crate::tag;
+
+/// Number of elements in each independently decodable chunk
+pub const CHUNK_ELEMENTS: usize = 8192;
+
+/// Serialize a slice as an indexed sequence, encoding chunks in parallel
+///
+/// ```rust
+/// let readings: Vec<(u32, f64)> = (0..100_000).map(|i| (i, i as f64 * 0.1)).collect();
+/// let bytes = nanobit::to_bytes_parallel(&readings)?;
+///
+/// let decoded: Vec<(u32, f64)> = nanobit::from_bytes_parallel(&bytes)?;
+/// assert_eq!(decoded, readings);
+/// assert_eq!(nanobit::from_bytes::<Vec<(u32, f64)>>(&bytes)?, readings);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn to_bytes_parallel<T>(values: &[T]) -> Result<Vec<u8>>
+where
+    T: Serialize + Sync,
+{
+    to_bytes_parallel_with_config(values, Config::new())
+}
+
+/// Serialize a slice as an indexed sequence using the given options
+///
+/// String tables are not supported, since the table would have to be
+/// shared by every chunk.
+pub fn to_bytes_parallel_with_config<T>(values: &[T], config: Config) -> Result<Vec<u8>>
+where
+    T: Serialize + Sync,
+{
+    if config.interns_strings() {
+        return Err(Error::InvalidFormat(
+            "String tables cannot be combined with a parallel index".to_string(),
+        ));
+    }
+
+    let chunks = values
+        .par_chunks(CHUNK_ELEMENTS)
+        .map(|chunk| {
+            let mut serializer = Serializer::with_config(config);
+            for value in chunk {
+                value.serialize(&mut serializer)?;
+            }
+            Ok((chunk.len(), serializer.into_parts().0))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut index = WriteBuffer::with_capacity(chunks.len() * 4 + 8);
+    index.write_varint(chunks.len() as u64)?;
+    for (len, body) in &chunks {
+        index.write_varint(*len as u64)?;
+        index.write_varint(body.len() as u64)?;
+    }
+
+    // The sequence header, as the serializer writes it
+    let mut serializer = Serializer::with_config(config);
+    (&mut serializer).serialize_seq(Some(values.len()))?;
+    let (seq_header, _) = serializer.into_parts();
+
+    let mut flags = config.header_flags();
+    flags.set(HeaderFlags::SEQ_INDEX, true);
+    let body_len: usize = chunks.iter().map(|(_, body)| body.len()).sum();
+    let mut out = Vec::with_capacity(body_len + index.len() + 32);
+    Header::new(flags).with_schema_hash(config.schema_hash()).write_to(&mut out);
+    let mut prefix = WriteBuffer::with_capacity(index.len() + 16);
+    prefix.write_byte_slice(index.as_slice())?;
+    prefix.write_bytes(seq_header.as_slice())?;
+    out.extend_from_slice(prefix.as_slice());
+    for (_, body) in &chunks {
+        out.extend_from_slice(body.as_slice());
+    }
+    #[cfg(feature = "checksum")]
+    if let Some(checksum) = config.checksum() {
+        checksum.append(&mut out);
+    }
+    Ok(out)
+}
+
+/// Deserialize a sequence, decoding its chunks in parallel
+///
+/// Uses the index written by [`to_bytes_parallel`] when present.
+pub fn from_bytes_parallel<T>(bytes: &[u8]) -> Result<Vec<T>>
+where
+    T: DeserializeOwned + Send,
+{
+    from_bytes_parallel_with_config(bytes, Config::new())
+}
+
+/// Deserialize a sequence with the given options, decoding chunks in parallel
+pub fn from_bytes_parallel_with_config<T>(bytes: &[u8], config: Config) -> Result<Vec<T>>
+where
+    T: DeserializeOwned + Send,
+{
+    let (header, body) = split_payload(bytes)?;
+    crate::schema::verify_hash::<Vec<T>>(header.schema_hash)?;
+    let config = config.with_header_flags(header.flags)?;
+    let indexed = header.flags.contains(HeaderFlags::SEQ_INDEX);
+    if config.string_table() || !(indexed || config.self_describing()) {
+        return crate::from_bytes_with_config(bytes, config);
+    }
+
+    let mut reader = ReadBuffer::new(body);
+    let index = if indexed { Some(reader.read_byte_slice()?) } else { None };
+    if config.self_describing() {
+        match reader.read_u8()? {
+            tag::SEQ => {}
+            found => {
+                return Err(Error::InvalidFormat(format!(
+                    "Expected sequence, found {}",
+                    tag::name(found)
+                )))
+            }
+        }
+    }
+    let len = reader.read_varint()?;
+    let elements = &body[reader.position()..];
+
+    let chunks = match index {
+        Some(index) => read_index(index, elements, len)?,
+        None => scan_chunks(elements, len)?,
+    };
+    let decoded = chunks
+        .into_par_iter()
+        .map(|(count, chunk)| decode_chunk::<T>(chunk, count, config))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut values = Vec::with_capacity(len as usize);
+    for chunk in decoded {
+        values.extend(chunk);
+    }
+    Ok(values)
+}
+
+// Element count and bytes of each chunk listed in an index
+fn read_index<'a>(index: &[u8], mut elements: &'a [u8], len: u64) -> Result<Vec<(usize, &'a [u8])>> {
+    let mut reader = ReadBuffer::new(index);
+    let count = reader.read_varint()?;
+    // Every entry takes at least two bytes, which bounds the allocation
+    if count > reader.remaining() as u64 / 2 {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut chunks = Vec::with_capacity(count as usize);
+    let mut total = 0u64;
+    for _ in 0..count {
+        let elements_in_chunk = reader.read_varint()?;
+        let bytes = reader.read_varint()?;
+        if bytes > elements.len() as u64 {
+            return Err(Error::InvalidFormat("Sequence index exceeds the payload".to_string()));
+        }
+        let (chunk, rest) = elements.split_at(bytes as usize);
+        chunks.push((elements_in_chunk as usize, chunk));
+        elements = rest;
+        total = total.saturating_add(elements_in_chunk);
+    }
+    if total != len || !elements.is_empty() {
+        return Err(Error::InvalidFormat("Sequence index does not match the payload".to_string()));
+    }
+    Ok(chunks)
+}
+
+// Split self-describing elements into chunks by skipping over them
+fn scan_chunks(elements: &[u8], len: u64) -> Result<Vec<(usize, &[u8])>> {
+    let mut reader = ReadBuffer::new(elements);
+    let mut chunks = Vec::new();
+    let mut remaining = len;
+    while remaining > 0 {
+        let count = remaining.min(CHUNK_ELEMENTS as u64);
+        let start = reader.position();
+        for _ in 0..count {
+            skip_value(&mut reader)?;
+        }
+        chunks.push((count as usize, &elements[start..reader.position()]));
+        remaining -= count;
+    }
+    Ok(chunks)
+}
+
+fn decode_chunk<T>(chunk: &[u8], count: usize, config: Config) -> Result<Vec<T>>
+where
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_body(chunk, config);
+    // Elements are inside the sequence, not top-level values
+    deserializer.enter_compound();
+    let mut values = Vec::with_capacity(count.min(chunk.len()));
+    for _ in 0..count {
+        values.push(T::deserialize(&mut deserializer)?);
+    }
+    if !deserializer.is_end() {
+        return Err(Error::InvalidFormat("Sequence chunk has trailing bytes".to_string()));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Reading {
+        sensor: String,
+        value: f64,
+        flags: Option<u8>,
+    }
+
+    fn readings(n: usize) -> Vec<Reading> {
+        (0..n)
+            .map(|i| Reading { sensor: format!("s{}", i % 17), value: i as f64, flags: (i % 3 == 0).then_some(1) })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_roundtrip() {
+        let values = readings(CHUNK_ELEMENTS * 3 + 5);
+        for config in [Config::new(), Config::new().with_tagged_fields(true), Config::new().with_self_describing(true)] {
+            let bytes = to_bytes_parallel_with_config(&values, config).unwrap();
+            assert_eq!(from_bytes_parallel::<Reading>(&bytes).unwrap(), values);
+            assert_eq!(crate::from_bytes::<Vec<Reading>>(&bytes).unwrap(), values);
+
+            // Ordinary payloads decode too
+            let plain = crate::to_bytes_with_config(&values, config).unwrap();
+            assert_eq!(from_bytes_parallel::<Reading>(&plain).unwrap(), values);
+        }
+        assert!(from_bytes_parallel::<Reading>(&to_bytes_parallel::<Reading>(&[]).unwrap()).unwrap().is_empty());
+        assert!(to_bytes_parallel_with_config(&values, Config::new().with_string_table(true)).is_err());
+    }
+
+    #[test]
+    fn test_parallel_rejects_bad_index() {
+        let values = readings(10);
+        let mut bytes = to_bytes_parallel(&values).unwrap();
+        // Index length, one chunk, then its element count of 10: claim 9
+        let header_len = Header::parse(&bytes).unwrap().1;
+        assert_eq!(bytes[header_len + 2], 10);
+        bytes[header_len + 2] = 9;
+        assert!(from_bytes_parallel::<Reading>(&bytes).is_err());
+    }
+}
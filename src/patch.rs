@@ -0,0 +1,250 @@
+//! Binary deltas between two versions of a payload
+//!
+//! A patch rebuilds the new bytes from the old ones with copy and insert
+//! operations, so unchanged regions cost a few bytes each:
+//!
+//! ```text
+//! ["NBPT"] [version: u8]
+//! [old length: varint] [new length: varint] [crc32c of old: u32 LE]
+//! [operation count: varint]
+//! [operations: 0x00 offset len (copy from old) | 0x01 len bytes (insert)]
+//! [crc32c of new: u32 LE]
+//! ```
+//!
+//! Patches work on any bytes and do not decode the payloads. Applying one
+//! to a different base, or a damaged patch, fails the checksums.
+
+use std::collections::HashMap;
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+use crate::checksum::Checksum;
+use crate::error::{Error, Result};
+
+/// Magic bytes at the start of a patch
+pub const PATCH_MAGIC: &[u8; 4] = b"NBPT";
+
+const PATCH_VERSION: u8 = 1;
+const OP_COPY: u8 = 0x00;
+const OP_INSERT: u8 = 0x01;
+
+// Length of the blocks matched between old and new, and so the shortest copy
+const BLOCK: usize = 16;
+
+/// Create a patch that turns `old` into `new`
+///
+/// ```rust
+/// let old = nanobit::to_bytes(&vec![7u64; 10_000])?;
+/// let mut state = vec![7u64; 10_000];
+/// state[5_000] = 8;
+/// let new = nanobit::to_bytes(&state)?;
+///
+/// let patch = nanobit::create_patch(&old, &new);
+/// assert!(patch.len() < 64);
+/// assert_eq!(nanobit::apply_patch(&old, &patch)?, new);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn create_patch(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let ops = diff_ops(old, new);
+
+    let mut out = WriteBuffer::with_capacity(64);
+    encode_patch(&mut out, old, new, &ops).expect("writing to a WriteBuffer cannot fail");
+    out.into_vec()
+}
+
+/// Apply a patch created by [`create_patch`] to `old`
+pub fn apply_patch(old: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let mut input = ReadBuffer::new(patch);
+    if input.read_bytes(4).ok() != Some(&PATCH_MAGIC[..]) {
+        return Err(Error::InvalidFormat("Not a patch".to_string()));
+    }
+    let version = input.read_u8()?;
+    if version != PATCH_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+
+    let old_len = input.read_varint()?;
+    let new_len = input.read_varint()?;
+    let old_crc = input.read_u32()?;
+    let actual = crc(old);
+    if old_len != old.len() as u64 || old_crc != actual {
+        return Err(Error::ChecksumMismatch {
+            expected: old_crc as u64,
+            actual: actual as u64,
+        });
+    }
+
+    // Capacity is bounded by the input, not the declared length
+    let mut new = Vec::with_capacity((new_len as usize).min(old.len() + patch.len()));
+    let out_of_range = || Error::InvalidFormat("Patch operation out of range".to_string());
+    for _ in 0..input.read_varint()? {
+        match input.read_u8()? {
+            OP_COPY => {
+                let offset = input.read_varint()? as usize;
+                let len = input.read_varint()? as usize;
+                let end = offset.checked_add(len).ok_or_else(out_of_range)?;
+                new.extend_from_slice(old.get(offset..end).ok_or_else(out_of_range)?);
+            }
+            OP_INSERT => {
+                let len = input.read_varint()? as usize;
+                new.extend_from_slice(input.read_bytes(len)?);
+            }
+            other => {
+                return Err(Error::InvalidFormat(format!("Unknown patch operation: {other:#04x}")))
+            }
+        }
+        if new.len() as u64 > new_len {
+            return Err(out_of_range());
+        }
+    }
+
+    let new_crc = input.read_u32()?;
+    let actual = crc(&new);
+    if new.len() as u64 != new_len || new_crc != actual {
+        return Err(Error::ChecksumMismatch {
+            expected: new_crc as u64,
+            actual: actual as u64,
+        });
+    }
+    Ok(new)
+}
+
+enum Op {
+    Copy { offset: usize, len: usize },
+    Insert { start: usize, end: usize },
+}
+
+fn encode_patch(out: &mut WriteBuffer, old: &[u8], new: &[u8], ops: &[Op]) -> Result<()> {
+    out.write_bytes(PATCH_MAGIC)?;
+    out.write_u8(PATCH_VERSION)?;
+    out.write_varint(old.len() as u64)?;
+    out.write_varint(new.len() as u64)?;
+    out.write_u32(crc(old))?;
+    out.write_varint(ops.len() as u64)?;
+    for op in ops {
+        match *op {
+            Op::Copy { offset, len } => {
+                out.write_u8(OP_COPY)?;
+                out.write_varint(offset as u64)?;
+                out.write_varint(len as u64)?;
+            }
+            Op::Insert { start, end } => {
+                out.write_u8(OP_INSERT)?;
+                out.write_byte_slice(&new[start..end])?;
+            }
+        }
+    }
+    out.write_u32(crc(new))
+}
+
+// Match blocks of `old` in `new`, extending each match in both directions
+fn diff_ops(old: &[u8], new: &[u8]) -> Vec<Op> {
+    let mut index = HashMap::new();
+    if old.len() >= BLOCK {
+        for start in (0..=old.len() - BLOCK).step_by(BLOCK) {
+            index.entry(block_hash(&old[start..start + BLOCK])).or_insert(start);
+        }
+    }
+
+    let mut ops = Vec::new();
+    let mut literal = 0;
+    let mut i = 0;
+    while i + BLOCK <= new.len() {
+        let found = index
+            .get(&block_hash(&new[i..i + BLOCK]))
+            .copied()
+            .filter(|&start| old[start..start + BLOCK] == new[i..i + BLOCK]);
+        let Some(start) = found else {
+            i += 1;
+            continue;
+        };
+
+        // Grow the match backwards into pending literals and forwards
+        let mut back = 0;
+        while back < i - literal && back < start && old[start - back - 1] == new[i - back - 1] {
+            back += 1;
+        }
+        let mut len = BLOCK + back;
+        while start - back + len < old.len()
+            && i - back + len < new.len()
+            && old[start - back + len] == new[i - back + len]
+        {
+            len += 1;
+        }
+        if literal < i - back {
+            ops.push(Op::Insert { start: literal, end: i - back });
+        }
+        ops.push(Op::Copy { offset: start - back, len });
+        i = i - back + len;
+        literal = i;
+    }
+    if literal < new.len() {
+        ops.push(Op::Insert { start: literal, end: new.len() });
+    }
+    ops
+}
+
+#[inline]
+fn block_hash(block: &[u8]) -> u64 {
+    let a = u64::from_le_bytes(block[..8].try_into().expect("8 byte slice"));
+    let b = u64::from_le_bytes(block[8..16].try_into().expect("8 byte slice"));
+    (a ^ b.rotate_left(29)).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+#[inline]
+fn crc(data: &[u8]) -> u32 {
+    Checksum::Crc32c.compute(data) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize, Clone)]
+    struct State {
+        version: u64,
+        rows: Vec<(u32, String)>,
+    }
+
+    #[test]
+    fn test_patch_roundtrip() {
+        let mut state = State {
+            version: 1,
+            rows: (0..20_000).map(|i| (i, format!("row-{i}"))).collect(),
+        };
+        let old = crate::to_bytes(&state).unwrap();
+        state.version = 2;
+        state.rows[100].1 = "changed".to_string();
+        state.rows.insert(15_000, (0, "inserted".to_string()));
+        state.rows.pop();
+        let new = crate::to_bytes(&state).unwrap();
+
+        let patch = create_patch(&old, &new);
+        assert!(patch.len() < 200, "patch is {} bytes", patch.len());
+        assert_eq!(apply_patch(&old, &patch).unwrap(), new);
+
+        // Edge cases: empty and unrelated inputs
+        for (a, b) in [(&[][..], &new[..50]), (&old[..50], &[][..]), (b"abc", b"xyz")] {
+            assert_eq!(apply_patch(a, &create_patch(a, b)).unwrap(), b);
+        }
+    }
+
+    #[test]
+    fn test_patch_rejects_wrong_base_and_damage() {
+        let old = vec![1u8; 1000];
+        let mut new = old.clone();
+        new[500] = 2;
+        let patch = create_patch(&old, &new);
+
+        assert!(matches!(apply_patch(&new, &patch), Err(Error::ChecksumMismatch { .. })));
+
+        let mut damaged = patch.clone();
+        let last = damaged.len() - 5;
+        damaged[last] ^= 0xFF;
+        assert!(apply_patch(&old, &damaged).is_err());
+
+        let mut future = patch;
+        future[4] = 9;
+        assert!(matches!(apply_patch(&old, &future), Err(Error::UnsupportedVersion(9))));
+    }
+}
@@ -0,0 +1,310 @@
+//! Bulk encoding for slices of fixed-width numbers
+//!
+//! Serde writes a `Vec<f32>` one element at a time. Wrapping the slice in a
+//! [`PrimitiveSlice`] writes it as a single byte string instead:
+//!
+//! ```text
+//! [byte length: varint] [element 0 LE] [element 1 LE] ...
+//! ```
+//!
+//! Elements are always little-endian, whatever the host byte order, so the
+//! encoding is portable between machines. Decoding copies the elements out
+//! of the payload, since the input is not guaranteed to be aligned for `T`.
+//! With the `bytemuck` feature, fields deserialized with [`borrow`] point
+//! into the input instead whenever it is aligned and the host is
+//! little-endian, and fall back to a copy otherwise.
+
+#[cfg(not(feature = "std"))]
+use alloc::{borrow::Cow, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::{Serialize, Serializer};
+
+mod sealed {
+    pub trait Sealed: Sized {
+        // View little-endian bytes as elements without copying, if possible
+        fn cast_le(bytes: &[u8]) -> Option<&[Self]>;
+    }
+}
+
+/// Fixed-width numbers that [`PrimitiveSlice`] can encode in bulk
+pub trait Primitive: sealed::Sealed + Copy + Default + 'static {
+    /// Size of one element in bytes
+    const SIZE: usize;
+
+    /// Write the little-endian bytes of `self` to `out`
+    fn write_le(self, out: &mut [u8]);
+
+    /// Read a value from `SIZE` little-endian bytes
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_primitive {
+    ($($ty:ty),*) => {$(
+        impl sealed::Sealed for $ty {
+            #[cfg(all(feature = "bytemuck", target_endian = "little"))]
+            #[inline]
+            fn cast_le(bytes: &[u8]) -> Option<&[Self]> {
+                bytemuck::try_cast_slice(bytes).ok()
+            }
+
+            #[cfg(not(all(feature = "bytemuck", target_endian = "little")))]
+            #[inline]
+            fn cast_le(_bytes: &[u8]) -> Option<&[Self]> {
+                None
+            }
+        }
+
+        impl Primitive for $ty {
+            const SIZE: usize = core::mem::size_of::<$ty>();
+
+            #[inline]
+            fn write_le(self, out: &mut [u8]) {
+                out.copy_from_slice(&self.to_le_bytes());
+            }
+
+            #[inline]
+            fn read_le(bytes: &[u8]) -> Self {
+                <$ty>::from_le_bytes(bytes.try_into().expect("element sized slice"))
+            }
+        }
+    )*};
+}
+
+impl_primitive!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/// A slice of numbers encoded as one little-endian block
+///
+/// ```rust
+/// use nanobit::PrimitiveSlice;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Samples<'a> {
+///     rate: u32,
+///     data: PrimitiveSlice<'a, f32>,
+/// }
+///
+/// let data: Vec<f32> = (0..1000).map(|i| i as f32 * 0.5).collect();
+/// let bytes = nanobit::to_bytes(&Samples { rate: 48_000, data: PrimitiveSlice::new(&data) })?;
+///
+/// let samples: Samples<'_> = nanobit::from_bytes(&bytes)?;
+/// assert_eq!(&*samples.data, &data[..]);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, PartialEq, Default)]
+pub struct PrimitiveSlice<'a, T: Primitive> {
+    data: Cow<'a, [T]>,
+}
+
+impl<'a, T: Primitive> PrimitiveSlice<'a, T> {
+    /// Wrap a borrowed slice
+    #[inline]
+    pub fn new(data: &'a [T]) -> Self {
+        Self { data: Cow::Borrowed(data) }
+    }
+
+    /// Wrap an owned vector
+    #[inline]
+    pub fn from_vec(data: Vec<T>) -> PrimitiveSlice<'static, T> {
+        PrimitiveSlice { data: Cow::Owned(data) }
+    }
+
+    /// The elements
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Take the elements
+    pub fn into_vec(self) -> Vec<T> {
+        self.data.into_owned()
+    }
+
+    /// Check whether the elements are borrowed rather than owned
+    #[inline]
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.data, Cow::Borrowed(_))
+    }
+}
+
+impl<T: Primitive> Deref for PrimitiveSlice<'_, T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<'a, T: Primitive> From<&'a [T]> for PrimitiveSlice<'a, T> {
+    fn from(data: &'a [T]) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<T: Primitive> From<Vec<T>> for PrimitiveSlice<'static, T> {
+    fn from(data: Vec<T>) -> Self {
+        Self::from_vec(data)
+    }
+}
+
+impl<T: Primitive + fmt::Debug> fmt::Debug for PrimitiveSlice<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.data.fmt(f)
+    }
+}
+
+impl<T: Primitive> Serialize for PrimitiveSlice<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut bytes = vec![0u8; self.data.len() * T::SIZE];
+        for (chunk, &value) in bytes.chunks_exact_mut(T::SIZE).zip(self.data.iter()) {
+            value.write_le(chunk);
+        }
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+// Reads a byte string of elements, borrowing it when `BORROW` allows
+struct BlockVisitor<T, const BORROW: bool>(PhantomData<T>);
+
+impl<'de, T: Primitive, const BORROW: bool> Visitor<'de> for BlockVisitor<T, BORROW> {
+    type Value = Cow<'de, [T]>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a byte string of {}-byte elements", T::SIZE)
+    }
+
+    fn visit_borrowed_bytes<E: serde::de::Error>(self, bytes: &'de [u8]) -> core::result::Result<Self::Value, E> {
+        match T::cast_le(bytes) {
+            Some(elements) if BORROW => Ok(Cow::Borrowed(elements)),
+            _ => self.visit_bytes(bytes),
+        }
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> core::result::Result<Self::Value, E> {
+        if !bytes.len().is_multiple_of(T::SIZE) {
+            return Err(E::invalid_length(bytes.len(), &self));
+        }
+        Ok(Cow::Owned(bytes.chunks_exact(T::SIZE).map(T::read_le).collect()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> core::result::Result<Self::Value, A::Error> {
+        // Raw bytes written element by element, as by `byte_slice`
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+impl<'de, T: Primitive> Deserialize<'de> for PrimitiveSlice<'_, T> {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let data = deserializer.deserialize_bytes(BlockVisitor::<T, false>(PhantomData))?;
+        Ok(Self { data: Cow::Owned(data.into_owned()) })
+    }
+}
+
+/// Deserialize a [`PrimitiveSlice`] that borrows from the input when it can
+///
+/// Use with `#[serde(borrow, deserialize_with = "nanobit::primitive::borrow")]`.
+/// The elements are borrowed when the `bytemuck` feature is enabled, the
+/// host is little-endian and the bytes are aligned for `T`; otherwise they
+/// are copied as usual.
+///
+/// ```rust
+/// use nanobit::PrimitiveSlice;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Frame<'a> {
+///     #[serde(borrow, deserialize_with = "nanobit::primitive::borrow")]
+///     samples: PrimitiveSlice<'a, f32>,
+/// }
+///
+/// let bytes = nanobit::to_bytes(&Frame { samples: PrimitiveSlice::new(&[0.5, 1.0]) })?;
+/// let frame: Frame<'_> = nanobit::from_bytes(&bytes)?;
+/// assert_eq!(&*frame.samples, &[0.5, 1.0]);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn borrow<'de: 'a, 'a, D, T>(deserializer: D) -> core::result::Result<PrimitiveSlice<'a, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Primitive,
+{
+    let data = deserializer.deserialize_bytes(BlockVisitor::<T, true>(PhantomData))?;
+    Ok(PrimitiveSlice { data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn test_primitive_slice_roundtrip() {
+        let floats: Vec<f64> = vec![0.0, -1.5, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY];
+        let ints: Vec<i16> = vec![i16::MIN, -1, 0, 1, i16::MAX];
+
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let value = (PrimitiveSlice::new(&floats), PrimitiveSlice::new(&ints));
+            let bytes = crate::to_bytes_with_config(&value, config).unwrap();
+            let (f, i): (PrimitiveSlice<'_, f64>, PrimitiveSlice<'_, i16>) =
+                crate::from_bytes(&bytes).unwrap();
+            assert_eq!(f.as_slice(), &floats[..]);
+            assert_eq!(i.into_vec(), ints);
+        }
+
+        // One block, little-endian regardless of host
+        let bytes = crate::to_bytes(&PrimitiveSlice::new(&[1u32, 0x0102_0304])).unwrap();
+        assert!(bytes.ends_with(&[8, 1, 0, 0, 0, 4, 3, 2, 1]));
+    }
+
+    #[cfg(all(feature = "bytemuck", target_endian = "little"))]
+    #[test]
+    fn test_borrowed_primitive_slice() {
+        use serde::Deserialize;
+
+        #[derive(Serialize, Deserialize)]
+        struct Frame<'a> {
+            #[serde(borrow, deserialize_with = "borrow")]
+            samples: PrimitiveSlice<'a, u64>,
+        }
+
+        let samples: Vec<u64> = (0..64).map(|i| i * 1_000_003).collect();
+        let bytes = crate::to_bytes(&Frame { samples: PrimitiveSlice::new(&samples) }).unwrap();
+
+        // Place the payload so the elements start on an 8-byte boundary, or
+        // one byte past it
+        let offset = bytes.len() - samples.len() * 8;
+        let mut storage = vec![0u64; bytes.len() / 8 + 2];
+        for shift in [0, 1] {
+            let start = 8 - offset % 8 + shift;
+            let input = &mut bytemuck::cast_slice_mut::<u64, u8>(&mut storage)[start..start + bytes.len()];
+            input.copy_from_slice(&bytes);
+
+            let frame: Frame<'_> = crate::from_bytes(input).unwrap();
+            assert_eq!(frame.samples.is_borrowed(), shift == 0);
+            assert_eq!(&*frame.samples, &samples[..]);
+        }
+    }
+
+    #[test]
+    fn test_primitive_slice_rejects_partial_element() {
+        let bytes = crate::to_bytes(&PrimitiveSlice::new(&[1u8, 2, 3])).unwrap();
+        assert!(crate::from_bytes::<PrimitiveSlice<'_, u16>>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Byte accounting for [`ser::profile`](crate::ser::profile)
+//!
+//! The serializer reports where each field, element and variant starts and
+//! ends; [`Profiler`] folds those spans into a tree keyed by path, merging
+//! repeated paths such as the elements of a sequence.
+
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::{String, ToString}, vec, vec::Vec};
+
+use core::fmt;
+
+/// Part of a value that the serializer reports as its own node
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Part {
+    Field(&'static str),
+    Variant(&'static str),
+    // Every element of a sequence
+    Element,
+    // The next element of a tuple
+    Next,
+    Index(usize),
+    Key,
+    Value,
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Part::Field(name) | Part::Variant(name) => f.write_str(name),
+            Part::Element => f.write_str("[]"),
+            Part::Next => f.write_str("?"),
+            Part::Index(index) => write!(f, "{index}"),
+            Part::Key => f.write_str("{key}"),
+            Part::Value => f.write_str("{value}"),
+        }
+    }
+}
+
+struct Node {
+    part: Option<Part>,
+    bytes: usize,
+    count: usize,
+    children: Vec<usize>,
+}
+
+// A node being written, where it started and how many tuple elements it has
+struct Frame {
+    node: usize,
+    start: usize,
+    next: usize,
+}
+
+/// Spans of a value being serialized, merged by path
+pub(crate) struct Profiler {
+    nodes: Vec<Node>,
+    stack: Vec<Frame>,
+}
+
+impl Profiler {
+    pub(crate) fn new() -> Self {
+        Self {
+            nodes: vec![Node { part: None, bytes: 0, count: 0, children: Vec::new() }],
+            stack: vec![Frame { node: 0, start: 0, next: 0 }],
+        }
+    }
+
+    pub(crate) fn enter(&mut self, part: Part, position: usize) {
+        let frame = self.stack.last_mut().expect("the root frame is never left");
+        let part = match part {
+            Part::Next => {
+                frame.next += 1;
+                Part::Index(frame.next - 1)
+            }
+            part => part,
+        };
+        let parent = frame.node;
+        let existing = self.nodes[parent].children.iter().copied().find(|&child| self.nodes[child].part == Some(part));
+        let node = existing.unwrap_or_else(|| {
+            self.nodes.push(Node { part: Some(part), bytes: 0, count: 0, children: Vec::new() });
+            let node = self.nodes.len() - 1;
+            self.nodes[parent].children.push(node);
+            node
+        });
+        self.stack.push(Frame { node, start: position, next: 0 });
+    }
+
+    pub(crate) fn leave(&mut self, position: usize) {
+        if self.stack.len() > 1 {
+            let frame = self.stack.pop().expect("checked above");
+            let node = &mut self.nodes[frame.node];
+            node.bytes += position - frame.start;
+            node.count += 1;
+        }
+    }
+
+    /// The finished tree for a value of `len` bytes
+    pub(crate) fn finish(mut self, len: usize) -> SizeNode {
+        self.nodes[0].bytes = len;
+        self.nodes[0].count = 1;
+        self.build(0, "value".to_string())
+    }
+
+    fn build(&self, node: usize, name: String) -> SizeNode {
+        let data = &self.nodes[node];
+        SizeNode {
+            name,
+            bytes: data.bytes,
+            count: data.count,
+            children: data.children.iter().map(|&child| self.build(child, self.nodes[child].part.map_or_else(String::new, |part| part.to_string()))).collect(),
+        }
+    }
+}
+
+/// Encoded size of one part of a value
+///
+/// Struct fields and enum variants are named after themselves, tuple
+/// elements by their index, every element of a sequence shares the node
+/// `[]` and the keys and values of a map share `{key}` and `{value}`.
+/// `bytes` is the total over all `count` occurrences and includes any tag,
+/// name or length written for the part itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeNode {
+    /// Field name, variant name, tuple index, `[]`, `{key}` or `{value}`
+    pub name: String,
+    /// Bytes written for this part, over every occurrence
+    pub bytes: usize,
+    /// Number of times this part was written
+    pub count: usize,
+    /// Parts nested inside, in the order first written
+    pub children: Vec<SizeNode>,
+}
+
+impl SizeNode {
+    /// Find a nested part by its dot-separated path, like `"items.[].name"`
+    pub fn get(&self, path: &str) -> Option<&SizeNode> {
+        path.split('.').try_fold(self, |node, name| node.children.iter().find(|child| child.name == name))
+    }
+}
+
+/// Where the bytes of a serialized payload go
+///
+/// Returned by [`ser::profile`](crate::ser::profile). Displays as a tree of
+/// byte counts with their share of the whole payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    /// Bytes of the header
+    pub header: usize,
+    /// Bytes of the string table, when strings are interned
+    ///
+    /// Interned strings are counted here; the value only holds their ids.
+    pub strings: usize,
+    /// Bytes of the checksum trailer
+    pub checksum: usize,
+    /// The value itself, broken down by path
+    pub value: SizeNode,
+}
+
+impl SizeReport {
+    /// Size of the whole payload
+    pub fn total(&self) -> usize {
+        self.header + self.strings + self.value.bytes + self.checksum
+    }
+
+    /// Find a part of the value by its dot-separated path
+    pub fn get(&self, path: &str) -> Option<&SizeNode> {
+        self.value.get(path)
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let leaf = |name: &str, bytes| SizeNode { name: name.to_string(), bytes, count: 1, children: Vec::new() };
+        let mut parts = vec![leaf("header", self.header)];
+        if self.strings > 0 {
+            parts.push(leaf("string table", self.strings));
+        }
+        parts.push(self.value.clone());
+        if self.checksum > 0 {
+            parts.push(leaf("checksum", self.checksum));
+        }
+        let total = self.total();
+        let root = SizeNode { name: "payload".to_string(), bytes: total, count: 1, children: parts };
+
+        let mut lines = Vec::new();
+        flatten(&root, String::new(), String::new(), &mut lines);
+        let width = lines.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0);
+        for (label, node) in lines {
+            let share = if total == 0 { 0.0 } else { node.bytes as f64 * 100.0 / total as f64 };
+            write!(f, "{label}{:pad$}  {:>8} B  {share:>5.1}%", "", node.bytes, pad = width - label.chars().count())?;
+            if node.count > 1 {
+                write!(f, "  x{}", node.count)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Each node with its tree-drawn label, depth first
+fn flatten<'a>(node: &'a SizeNode, lead: String, indent: String, lines: &mut Vec<(String, &'a SizeNode)>) {
+    lines.push((format!("{lead}{}", node.name), node));
+    for (i, child) in node.children.iter().enumerate() {
+        let last = i + 1 == node.children.len();
+        let (branch, more) = if last { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+        flatten(child, format!("{indent}{branch}"), format!("{indent}{more}"), lines);
+    }
+}
@@ -0,0 +1,189 @@
+//! Pre-encoded values passed through without decoding
+//!
+//! A [`RawValue`] is a complete nanobit payload carried as a byte string.
+//! It uses the same encoding as [`Lazy`](crate::Lazy), so a field written
+//! as `Lazy<T>` can be read as a `RawValue` and forwarded unchanged.
+
+#[cfg(not(feature = "std"))]
+use alloc::{borrow::Cow, vec::Vec};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+
+use core::fmt;
+
+use serde::de::{Deserialize, Deserializer, Error as _, Visitor};
+use serde::{Serialize, Serializer};
+
+use crate::error::Result;
+use crate::header::Header;
+
+/// The raw bytes of an encoded value, re-emitted verbatim on serialize
+///
+/// ```rust
+/// use nanobit::RawValue;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Envelope<'a> {
+///     to: String,
+///     #[serde(borrow)]
+///     payload: RawValue<'a>,
+/// }
+///
+/// let bytes = nanobit::to_bytes(&Envelope {
+///     to: "billing".into(),
+///     payload: RawValue::from_value(&(42u32, "invoice"))?,
+/// })?;
+///
+/// // Re-address the envelope without decoding the payload
+/// let mut envelope: Envelope<'_> = nanobit::from_bytes(&bytes)?;
+/// envelope.to = "audit".into();
+/// let forwarded = nanobit::to_bytes(&envelope)?;
+///
+/// let envelope: Envelope<'_> = nanobit::from_bytes(&forwarded)?;
+/// assert_eq!(envelope.payload.deserialize::<(u32, &str)>()?, (42, "invoice"));
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct RawValue<'a> {
+    bytes: Cow<'a, [u8]>,
+}
+
+impl<'a> RawValue<'a> {
+    /// Encode a value
+    pub fn from_value<T>(value: &T) -> Result<RawValue<'static>>
+    where
+        T: Serialize + ?Sized,
+    {
+        Ok(RawValue {
+            bytes: Cow::Owned(crate::to_bytes(&value)?),
+        })
+    }
+
+    /// Wrap an encoded payload
+    ///
+    /// Only the header is checked; the value is not decoded.
+    pub fn from_encoded(bytes: impl Into<Cow<'a, [u8]>>) -> Result<Self> {
+        let bytes = bytes.into();
+        Header::parse(&bytes)?;
+        Ok(Self { bytes })
+    }
+
+    /// The encoded payload
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Take the encoded payload
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes.into_owned()
+    }
+
+    /// Copy borrowed bytes so the value outlives its input
+    pub fn into_owned(self) -> RawValue<'static> {
+        RawValue {
+            bytes: Cow::Owned(self.bytes.into_owned()),
+        }
+    }
+
+    /// Decode the payload
+    pub fn deserialize<'de, T>(&'de self) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        crate::from_bytes(&self.bytes)
+    }
+}
+
+impl fmt::Debug for RawValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawValue").field("len", &self.bytes.len()).finish()
+    }
+}
+
+impl Serialize for RawValue<'_> {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.bytes)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawValue<'a> {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = Cow<'de, [u8]>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("bytes of an encoded payload")
+            }
+
+            fn visit_borrowed_bytes<E>(self, bytes: &'de [u8]) -> core::result::Result<Self::Value, E> {
+                Ok(Cow::Borrowed(bytes))
+            }
+
+            fn visit_bytes<E>(self, bytes: &[u8]) -> core::result::Result<Self::Value, E> {
+                Ok(Cow::Owned(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> core::result::Result<Self::Value, E> {
+                Ok(Cow::Owned(bytes))
+            }
+        }
+
+        let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+        RawValue::from_encoded(bytes).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Lazy};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Typed<'a> {
+        id: u32,
+        #[serde(borrow)]
+        body: Lazy<'a, Vec<String>>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Untyped<'a> {
+        id: u32,
+        #[serde(borrow)]
+        body: RawValue<'a>,
+    }
+
+    #[test]
+    fn test_raw_value_passthrough() {
+        let body = crate::to_bytes(&vec!["x".to_string(), "y".to_string()]).unwrap();
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let typed = Typed { id: 1, body: Lazy::from_encoded(&body).unwrap() };
+            let bytes = crate::to_bytes_with_config(&typed, config).unwrap();
+
+            // Routed without knowing the body type, then re-encoded verbatim
+            let mut routed: Untyped<'_> = crate::from_bytes(&bytes).unwrap();
+            assert!(matches!(routed.body.bytes, Cow::Borrowed(_)));
+            assert_eq!(routed.body.as_bytes(), body.as_slice());
+            routed.id = 2;
+            let forwarded = crate::to_bytes_with_config(&routed, config).unwrap();
+
+            let read: Typed<'_> = crate::from_bytes(&forwarded).unwrap();
+            assert_eq!(read.id, 2);
+            assert_eq!(read.body.get().unwrap(), ["x", "y"]);
+        }
+
+        let owned = RawValue::from_value(&5u8).unwrap();
+        assert_eq!(owned.deserialize::<u8>().unwrap(), 5);
+        assert!(RawValue::from_encoded(&b"junk"[..]).is_err());
+    }
+}
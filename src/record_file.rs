@@ -0,0 +1,309 @@
+//! Record files with an index footer for random access
+//!
+//! ```text
+//! ["NBRF"] [version: u8]
+//! [record payload]...
+//! [index: count u64, then per record: offset u64, length u32,
+//!  key length u32 (u32::MAX if none), key bytes]
+//! [index offset: u64] ["NBRF"]
+//! ```
+//!
+//! Offsets and lengths are little-endian. Keys are stored as their nanobit
+//! serialization, so any `Serialize` type can be a key and lookups compare
+//! exact bytes. The reader loads only the index; each record is read and
+//! decoded on demand.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// Magic bytes at the start and end of a record file
+pub const RECORD_FILE_MAGIC: &[u8; 4] = b"NBRF";
+
+const RECORD_FILE_VERSION: u8 = 1;
+const HEADER_LEN: u64 = 5;
+const TRAILER_LEN: u64 = 12;
+const NO_KEY: u32 = u32::MAX;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    offset: u64,
+    len: u32,
+    key: Option<Vec<u8>>,
+}
+
+/// Writes records followed by an index footer
+///
+/// Call [`finish`](Self::finish) to write the index; a file without one
+/// cannot be opened.
+pub struct RecordFileWriter<W: Write> {
+    writer: W,
+    position: u64,
+    entries: Vec<Entry>,
+}
+
+impl<W: Write> RecordFileWriter<W> {
+    /// Start a record file, writing its header to `writer`
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(RECORD_FILE_MAGIC)?;
+        writer.write_all(&[RECORD_FILE_VERSION])?;
+        Ok(Self {
+            writer,
+            position: HEADER_LEN,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Append a record, returning its index
+    pub fn append<T>(&mut self, value: &T) -> Result<usize>
+    where
+        T: Serialize,
+    {
+        self.push(None, &crate::to_bytes(value)?)
+    }
+
+    /// Append a record that can also be looked up by `key`
+    pub fn append_keyed<K, T>(&mut self, key: &K, value: &T) -> Result<usize>
+    where
+        K: Serialize + ?Sized,
+        T: Serialize,
+    {
+        let key = crate::to_bytes(&key)?;
+        if key.len() >= NO_KEY as usize {
+            return Err(Error::InvalidFormat("Record key too large".to_string()));
+        }
+        self.push(Some(key), &crate::to_bytes(value)?)
+    }
+
+    fn push(&mut self, key: Option<Vec<u8>>, payload: &[u8]) -> Result<usize> {
+        let len = u32::try_from(payload.len())
+            .map_err(|_| Error::InvalidFormat("Record too large for record file".to_string()))?;
+        self.writer.write_all(payload)?;
+        self.entries.push(Entry {
+            offset: self.position,
+            len,
+            key,
+        });
+        self.position += payload.len() as u64;
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Number of records written so far
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if no records have been written
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Write the index footer and return the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        let index_offset = self.position;
+        let mut index = Vec::with_capacity(8 + self.entries.len() * 16);
+        index.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for entry in &self.entries {
+            index.extend_from_slice(&entry.offset.to_le_bytes());
+            index.extend_from_slice(&entry.len.to_le_bytes());
+            match &entry.key {
+                Some(key) => {
+                    index.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                    index.extend_from_slice(key);
+                }
+                None => index.extend_from_slice(&NO_KEY.to_le_bytes()),
+            }
+        }
+        index.extend_from_slice(&index_offset.to_le_bytes());
+        index.extend_from_slice(RECORD_FILE_MAGIC);
+        self.writer.write_all(&index)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl RecordFileWriter<BufWriter<File>> {
+    /// Create a record file at `path`, replacing any existing file
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Reads individual records from a record file
+pub struct RecordFileReader<R: Read + Seek> {
+    reader: R,
+    entries: Vec<Entry>,
+    keys: HashMap<Vec<u8>, usize>,
+}
+
+impl<R: Read + Seek> RecordFileReader<R> {
+    /// Open a record file, loading its index
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+        if &header[..4] != RECORD_FILE_MAGIC {
+            return Err(Error::InvalidFormat("Not a record file".to_string()));
+        }
+        if header[4] != RECORD_FILE_VERSION {
+            return Err(Error::UnsupportedVersion(header[4]));
+        }
+
+        let end = reader.seek(SeekFrom::End(0))?;
+        if end < HEADER_LEN + TRAILER_LEN {
+            return Err(Error::InvalidFormat("Record file has no index".to_string()));
+        }
+        let mut trailer = [0u8; TRAILER_LEN as usize];
+        reader.seek(SeekFrom::Start(end - TRAILER_LEN))?;
+        reader.read_exact(&mut trailer)?;
+        if &trailer[8..] != RECORD_FILE_MAGIC {
+            return Err(Error::InvalidFormat("Record file has no index".to_string()));
+        }
+        let index_offset = u64::from_le_bytes(trailer[..8].try_into().expect("8 byte slice"));
+        if index_offset < HEADER_LEN || index_offset > end - TRAILER_LEN {
+            return Err(Error::InvalidFormat("Record file index offset out of range".to_string()));
+        }
+
+        let mut index = vec![0u8; (end - TRAILER_LEN - index_offset) as usize];
+        reader.seek(SeekFrom::Start(index_offset))?;
+        reader.read_exact(&mut index)?;
+        let entries = parse_index(&index, index_offset)?;
+        let keys = entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| entry.key.clone().map(|key| (key, i)))
+            .collect();
+        Ok(Self { reader, entries, keys })
+    }
+
+    /// Number of records in the file
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the file holds no records
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Read the serialized payload of record `index`
+    pub fn get_raw(&mut self, index: usize) -> Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entries.get(index) else {
+            return Ok(None);
+        };
+        let mut payload = vec![0u8; entry.len as usize];
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        self.reader.read_exact(&mut payload)?;
+        Ok(Some(payload))
+    }
+
+    /// Read and decode record `index`
+    pub fn get<T>(&mut self, index: usize) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.get_raw(index)? {
+            Some(payload) => crate::from_bytes(&payload).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read and decode the record appended with `key`
+    pub fn get_by_key<K, T>(&mut self, key: &K) -> Result<Option<T>>
+    where
+        K: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        match self.index_of(key)? {
+            Some(index) => self.get(index),
+            None => Ok(None),
+        }
+    }
+
+    /// Find the index of the record appended with `key`
+    pub fn index_of<K>(&self, key: &K) -> Result<Option<usize>>
+    where
+        K: Serialize + ?Sized,
+    {
+        Ok(self.keys.get(&crate::to_bytes(&key)?).copied())
+    }
+}
+
+impl RecordFileReader<BufReader<File>> {
+    /// Open the record file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+fn parse_index(index: &[u8], index_offset: u64) -> Result<Vec<Entry>> {
+    let truncated = || Error::InvalidFormat("Record file index is truncated".to_string());
+    let mut input = crate::buffer::ReadBuffer::new(index);
+
+    let count = input.read_u64().map_err(|_| truncated())?;
+    // Every entry takes at least 16 bytes, which bounds the allocation
+    if count > (index.len() / 16) as u64 {
+        return Err(truncated());
+    }
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let offset = input.read_u64().map_err(|_| truncated())?;
+        let len = input.read_u32().map_err(|_| truncated())?;
+        let key = match input.read_u32().map_err(|_| truncated())? {
+            NO_KEY => None,
+            key_len => Some(input.read_bytes(key_len as usize).map_err(|_| truncated())?.to_vec()),
+        };
+        if offset < HEADER_LEN || offset.saturating_add(len as u64) > index_offset {
+            return Err(Error::InvalidFormat("Record file entry out of range".to_string()));
+        }
+        entries.push(Entry { offset, len, key });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_random_access() {
+        let mut writer = RecordFileWriter::new(Vec::new()).unwrap();
+        for i in 0..10u32 {
+            writer.append_keyed(&format!("key-{i}"), &vec![i; i as usize]).unwrap();
+        }
+        writer.append(&vec![99u32]).unwrap();
+        let file = writer.finish().unwrap();
+
+        let mut reader = RecordFileReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.len(), 11);
+        assert_eq!(reader.get::<Vec<u32>>(7).unwrap(), Some(vec![7; 7]));
+        assert_eq!(reader.get::<Vec<u32>>(10).unwrap(), Some(vec![99]));
+        assert_eq!(reader.get::<Vec<u32>>(11).unwrap(), None);
+
+        assert_eq!(reader.get_by_key::<_, Vec<u32>>("key-3").unwrap(), Some(vec![3; 3]));
+        assert_eq!(reader.get_by_key::<_, Vec<u32>>("key-42").unwrap(), None);
+    }
+
+    #[test]
+    fn test_damaged_file_rejected() {
+        let mut writer = RecordFileWriter::new(Vec::new()).unwrap();
+        writer.append(&1u8).unwrap();
+        let file = writer.finish().unwrap();
+
+        // Missing footer
+        assert!(RecordFileReader::new(Cursor::new(file[..file.len() - 1].to_vec())).is_err());
+
+        // Entry pointing past the records
+        let mut bad = file.clone();
+        let entry = file.len() - TRAILER_LEN as usize - 16;
+        bad[entry..entry + 8].copy_from_slice(&1000u64.to_le_bytes());
+        assert!(RecordFileReader::new(Cursor::new(bad)).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Serialization of trait objects through a registry of concrete types
+//!
+//! A `Box<dyn Trait>` is written as the registered name of its concrete
+//! type followed by that value's encoded payload, and read back by looking
+//! the name up again. Build one [`Registry`] per trait, expose it through
+//! [`Registered`], and annotate fields with
+//! `#[serde(with = "nanobit::registry")]`:
+//!
+//! ```rust
+//! use std::any::Any;
+//! use std::sync::OnceLock;
+//! use nanobit::registry::{Registered, Registry};
+//! use serde::{Deserialize, Serialize};
+//!
+//! trait Plugin: Any {
+//!     fn run(&self) -> String;
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Greeter { name: String }
+//!
+//! impl Plugin for Greeter {
+//!     fn run(&self) -> String { format!("hello {}", self.name) }
+//! }
+//!
+//! impl Registered for dyn Plugin {
+//!     fn registry() -> &'static Registry<Self> {
+//!         static PLUGINS: OnceLock<Registry<dyn Plugin>> = OnceLock::new();
+//!         PLUGINS.get_or_init(|| {
+//!             Registry::new(|plugin: &dyn Plugin| plugin as &dyn Any)
+//!                 .register::<Greeter>("greeter", |g| Box::new(g))
+//!         })
+//!     }
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Job {
+//!     #[serde(with = "nanobit::registry")]
+//!     plugin: Box<dyn Plugin>,
+//! }
+//!
+//! let bytes = nanobit::to_bytes(&Job { plugin: Box::new(Greeter { name: "ops".into() }) })?;
+//! let job: Job = nanobit::from_bytes(&bytes)?;
+//! assert_eq!(job.plugin.run(), "hello ops");
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Collections such as `Vec<Box<dyn Plugin>>` work once `Box<dyn Plugin>`
+//! implements `Serialize` and `Deserialize` by calling [`serialize`] and
+//! [`deserialize`], which the orphan rules allow in the crate defining the
+//! trait.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{DeserializeOwned, Deserializer, Error as _};
+use serde::ser::Error as _;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::error::{Error, Result};
+
+/// Trait object types with a registry of their concrete types
+pub trait Registered: 'static {
+    /// The registry for this trait object type
+    fn registry() -> &'static Registry<Self>;
+}
+
+type Encode = fn(&dyn Any) -> Result<Vec<u8>>;
+type Decode<T> = Box<dyn Fn(&[u8]) -> Result<Box<T>> + Send + Sync>;
+
+struct Entry<T: ?Sized> {
+    name: &'static str,
+    encode: Encode,
+    decode: Decode<T>,
+}
+
+/// Concrete types that may stand behind a trait object `T`
+pub struct Registry<T: ?Sized + 'static> {
+    as_any: fn(&T) -> &dyn Any,
+    entries: Vec<Entry<T>>,
+    by_name: HashMap<&'static str, usize>,
+    by_type: HashMap<TypeId, usize>,
+}
+
+impl<T: ?Sized + 'static> Registry<T> {
+    /// Create an empty registry
+    ///
+    /// `as_any` upcasts a trait object so its concrete type can be found;
+    /// with `trait Trait: Any` it is `|value: &dyn Trait| value as &dyn Any`.
+    pub fn new(as_any: fn(&T) -> &dyn Any) -> Self {
+        Self {
+            as_any,
+            entries: Vec::new(),
+            by_name: HashMap::new(),
+            by_type: HashMap::new(),
+        }
+    }
+
+    /// Add a concrete type under a name that identifies it on the wire
+    ///
+    /// `into_box` converts a decoded value into the trait object, usually
+    /// `|value| Box::new(value)`.
+    ///
+    /// # Panics
+    ///
+    /// If the name or the type is already registered.
+    pub fn register<C>(mut self, name: &'static str, into_box: fn(C) -> Box<T>) -> Self
+    where
+        C: Serialize + DeserializeOwned + 'static,
+    {
+        let index = self.entries.len();
+        assert!(self.by_name.insert(name, index).is_none(), "type name `{name}` registered twice");
+        assert!(
+            self.by_type.insert(TypeId::of::<C>(), index).is_none(),
+            "type `{}` registered twice",
+            std::any::type_name::<C>()
+        );
+        self.entries.push(Entry {
+            name,
+            encode: encode_as::<C>,
+            decode: Box::new(move |bytes| crate::from_bytes::<C>(bytes).map(into_box)),
+        });
+        self
+    }
+
+    /// Names of the registered types, in registration order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.entries.iter().map(|entry| entry.name)
+    }
+
+    /// Encode a trait object as its type name and payload
+    pub fn encode(&self, value: &T) -> Result<(&'static str, Vec<u8>)> {
+        let value = (self.as_any)(value);
+        let entry = self
+            .by_type
+            .get(&value.type_id())
+            .map(|&index| &self.entries[index])
+            .ok_or_else(|| Error::InvalidFormat("Trait object of an unregistered type".to_string()))?;
+        Ok((entry.name, (entry.encode)(value)?))
+    }
+
+    /// Decode a payload written for the type registered as `name`
+    pub fn decode(&self, name: &str, payload: &[u8]) -> Result<Box<T>> {
+        let entry = self
+            .by_name
+            .get(name)
+            .map(|&index| &self.entries[index])
+            .ok_or_else(|| Error::InvalidFormat(format!("Unregistered type name: {name}")))?;
+        (entry.decode)(payload)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Registry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.entries.iter().map(|entry| entry.name)).finish()
+    }
+}
+
+fn encode_as<C: Serialize + 'static>(value: &dyn Any) -> Result<Vec<u8>> {
+    let value = value.downcast_ref::<C>().expect("entry found by type id");
+    crate::to_bytes(value)
+}
+
+// Wire form: type name, then the payload as a byte string
+#[derive(Serialize)]
+struct TaggedRef<'a>(&'a str, #[serde(with = "crate::byte_slice")] &'a [u8]);
+
+#[derive(Deserialize)]
+struct Tagged(String, #[serde(with = "crate::byte_slice")] Vec<u8>);
+
+/// Serialize a boxed trait object with its registered type name
+#[allow(clippy::borrowed_box)] // called by serde with a reference to the field
+pub fn serialize<T, S>(value: &Box<T>, serializer: S) -> core::result::Result<S::Ok, S::Error>
+where
+    T: Registered + ?Sized,
+    S: Serializer,
+{
+    let (name, payload) = T::registry().encode(value).map_err(S::Error::custom)?;
+    TaggedRef(name, &payload).serialize(serializer)
+}
+
+/// Deserialize a boxed trait object by its registered type name
+pub fn deserialize<'de, T, D>(deserializer: D) -> core::result::Result<Box<T>, D::Error>
+where
+    T: Registered + ?Sized,
+    D: Deserializer<'de>,
+{
+    let Tagged(name, payload) = Tagged::deserialize(deserializer)?;
+    T::registry().decode(&name, &payload).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::sync::OnceLock;
+
+    trait Shape: Any {
+        fn area(&self) -> f64;
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Square(f64);
+
+    #[derive(Serialize, Deserialize)]
+    struct Rect {
+        w: f64,
+        h: f64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Unregistered;
+
+    impl Shape for Square {
+        fn area(&self) -> f64 {
+            self.0 * self.0
+        }
+    }
+
+    impl Shape for Rect {
+        fn area(&self) -> f64 {
+            self.w * self.h
+        }
+    }
+
+    impl Shape for Unregistered {
+        fn area(&self) -> f64 {
+            0.0
+        }
+    }
+
+    impl Registered for dyn Shape {
+        fn registry() -> &'static Registry<Self> {
+            static SHAPES: OnceLock<Registry<dyn Shape>> = OnceLock::new();
+            SHAPES.get_or_init(|| {
+                Registry::new(|shape: &dyn Shape| shape as &dyn Any)
+                    .register::<Square>("square", |s| Box::new(s))
+                    .register::<Rect>("rect", |r| Box::new(r))
+            })
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Layer {
+        #[serde(with = "super")]
+        a: Box<dyn Shape>,
+        #[serde(with = "super")]
+        b: Box<dyn Shape>,
+    }
+
+    #[test]
+    fn test_registry_roundtrip() {
+        let layer = Layer { a: Box::new(Square(3.0)), b: Box::new(Rect { w: 2.0, h: 5.0 }) };
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let bytes = crate::to_bytes_with_config(&layer, config).unwrap();
+            let read: Layer = crate::from_bytes(&bytes).unwrap();
+            assert_eq!((read.a.area(), read.b.area()), (9.0, 10.0));
+        }
+        assert_eq!(<dyn Shape>::registry().names().collect::<Vec<_>>(), ["square", "rect"]);
+
+        let layer = Layer { a: Box::new(Square(1.0)), b: Box::new(Unregistered) };
+        assert!(crate::to_bytes(&layer).is_err());
+        let renamed = crate::to_bytes(&(("circle", vec![0u8]), ("rect", vec![0u8]))).unwrap();
+        assert!(crate::from_bytes::<Layer>(&renamed).is_err());
+    }
+}
@@ -0,0 +1,962 @@
+//! Machine-readable descriptions of serde types
+//!
+//! [`Schema::of`] walks a type's `Deserialize` implementation with a tracing
+//! deserializer, so no sample value is needed. The result names every
+//! struct and enum the type reaches, with field names, tagged-field tags and
+//! enum variants, and is itself serializable for registries, code
+//! generators and tools in other languages:
+//!
+//! ```rust
+//! use nanobit::schema::{Container, Schema, Type};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! enum Shape { Point, Circle { radius: f64 } }
+//!
+//! #[derive(Deserialize)]
+//! struct Drawing { name: String, shapes: Vec<Shape> }
+//!
+//! let schema = Schema::of::<Drawing>()?;
+//! assert_eq!(schema.root, Type::Named("Drawing".into()));
+//! let Some(Container::Struct(fields)) = schema.get("Drawing") else { unreachable!() };
+//! assert_eq!(fields[1].ty, Type::Seq(Box::new(Type::Named("Shape".into()))));
+//! assert!(matches!(schema.get("Shape"), Some(Container::Enum(variants)) if variants.len() == 2));
+//!
+//! // Schemas are plain serde values
+//! let bytes = nanobit::to_bytes(&schema)?;
+//! assert_eq!(nanobit::from_bytes::<Schema>(&bytes)?, schema);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Each enum variant is explored by tracing the type again, and recursive
+//! types must nest through an `Option`, sequence, map or another variant of
+//! the same enum. Types whose representation depends on the data, such as
+//! untagged or internally tagged enums and flattened fields, cannot be
+//! traced.
+//!
+//! A payload can record the [`Schema::hash`] of the type it was written for
+//! (see [`to_bytes_with_schema_hash`]). [`from_bytes`](crate::from_bytes)
+//! then refuses to decode it into a type with a different schema, so a
+//! stale struct fails with [`Error::SchemaMismatch`] instead of producing
+//! shifted values. Types that cannot be traced, such as
+//! [`Value`](crate::Value), skip the check.
+
+#[cfg(not(feature = "std"))]
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+
+use core::fmt;
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{field_tag, Config};
+use crate::error::{Error, Result};
+
+/// Description of a type and every named type it contains
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    /// The described type
+    pub root: Type,
+    /// Structs and enums reachable from the root, by name
+    pub types: BTreeMap<String, Container>,
+}
+
+/// Shape of a value
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Type {
+    /// `()`
+    Unit,
+    /// `bool`
+    Bool,
+    /// `i8`
+    I8,
+    /// `i16`
+    I16,
+    /// `i32`
+    I32,
+    /// `i64`
+    I64,
+    /// `i128`
+    I128,
+    /// `u8`
+    U8,
+    /// `u16`
+    U16,
+    /// `u32`
+    U32,
+    /// `u64`
+    U64,
+    /// `u128`
+    U128,
+    /// `f32`
+    F32,
+    /// `f64`
+    F64,
+    /// `char`
+    Char,
+    /// UTF-8 string
+    Str,
+    /// Byte string
+    Bytes,
+    /// Optional value
+    Option(Box<Type>),
+    /// Variable-length sequence
+    Seq(Box<Type>),
+    /// Tuple or fixed-size array
+    Tuple(Vec<Type>),
+    /// Map
+    Map {
+        /// Key type
+        key: Box<Type>,
+        /// Value type
+        value: Box<Type>,
+    },
+    /// Struct or enum described in [`Schema::types`]
+    Named(String),
+}
+
+/// Definition of a named type
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Container {
+    /// `struct Marker;`
+    UnitStruct,
+    /// `struct Meters(f64);`
+    NewtypeStruct(Type),
+    /// `struct Point(i32, i32);`
+    TupleStruct(Vec<Type>),
+    /// Struct with named fields
+    Struct(Vec<Field>),
+    /// Enum variants in declaration order
+    Enum(Vec<Variant>),
+}
+
+/// Named field of a struct or struct variant
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    /// Serialized field name
+    pub name: String,
+    /// Tag of the field in tagged-field mode
+    pub tag: u64,
+    /// Field type
+    pub ty: Type,
+}
+
+/// Enum variant
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Variant {
+    /// Serialized variant name
+    pub name: String,
+    /// Variant contents
+    pub kind: VariantKind,
+}
+
+/// Contents of an enum variant
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VariantKind {
+    /// No payload
+    Unit,
+    /// A single unnamed value
+    Newtype(Type),
+    /// Unnamed values
+    Tuple(Vec<Type>),
+    /// Named fields
+    Struct(Vec<Field>),
+}
+
+impl Schema {
+    /// Trace the schema of a type
+    pub fn of<'de, T>() -> Result<Self>
+    where
+        T: Deserialize<'de>,
+    {
+        let mut tracer = Tracer::default();
+        loop {
+            tracer.start();
+            let mut root = Type::Unit;
+            match T::deserialize(TypeTracer { tracer: &mut tracer, ty: &mut root }) {
+                Ok(_) => {}
+                // A recursive type was reached directly; it is cut short next time
+                Err(_) if tracer.retry => continue,
+                Err(e) => return Err(e),
+            }
+            if tracer.enums.values().all(EnumTrace::is_complete) {
+                return Ok(tracer.finish(root));
+            }
+        }
+    }
+
+    /// Get the definition of a named type
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&Container> {
+        self.types.get(name)
+    }
+
+    /// Short hash of the schema, as recorded in payload headers
+    ///
+    /// Any change to a type, field or variant name, a field type or the
+    /// order of fields and variants changes the hash.
+    pub fn hash(&self) -> u32 {
+        let bytes = crate::to_bytes(self).expect("schemas always serialize");
+        // FNV-1a
+        bytes.iter().fold(0x811c_9dc5, |hash, &b| (hash ^ u32::from(b)).wrapping_mul(0x0100_0193))
+    }
+}
+
+/// Serialize a value, recording the hash of its type's schema
+///
+/// ```rust
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Invoice { id: u64, total: u32 }
+///
+/// #[derive(Deserialize)]
+/// struct Refund { id: u64, amount: u32 }
+///
+/// let bytes = nanobit::schema::to_bytes_with_schema_hash(&Invoice { id: 7, total: 1200 })?;
+/// assert!(nanobit::from_bytes::<Invoice>(&bytes).is_ok());
+/// assert!(matches!(nanobit::from_bytes::<Refund>(&bytes), Err(nanobit::Error::SchemaMismatch { .. })));
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn to_bytes_with_schema_hash<'de, T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize + Deserialize<'de>,
+{
+    let hash = Schema::of::<T>()?.hash();
+    crate::to_bytes_with_config(value, Config::new().with_schema_hash(Some(hash)))
+}
+
+/// Check a schema hash read from a payload against the schema of `T`
+pub(crate) fn verify_hash<'de, T>(found: Option<u32>) -> Result<()>
+where
+    T: Deserialize<'de>,
+{
+    let Some(found) = found else {
+        return Ok(());
+    };
+    match hash_of::<T>() {
+        Some(expected) if expected != found => Err(Error::SchemaMismatch { expected, found }),
+        _ => Ok(()),
+    }
+}
+
+// Schema hash of a type, or `None` if it cannot be traced. Tracing is
+// cached by type name, which ignores lifetimes as schemas do
+#[cfg(feature = "std")]
+fn hash_of<'de, T>() -> Option<u32>
+where
+    T: Deserialize<'de>,
+{
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+
+    static HASHES: OnceLock<Mutex<HashMap<&'static str, Option<u32>>>> = OnceLock::new();
+    let name = core::any::type_name::<T>();
+    let hashes = HASHES.get_or_init(Default::default);
+    if let Some(hash) = hashes.lock().unwrap_or_else(|e| e.into_inner()).get(name) {
+        return *hash;
+    }
+    let hash = Schema::of::<T>().ok().map(|schema| schema.hash());
+    hashes.lock().unwrap_or_else(|e| e.into_inner()).insert(name, hash);
+    hash
+}
+
+#[cfg(not(feature = "std"))]
+fn hash_of<'de, T>() -> Option<u32>
+where
+    T: Deserialize<'de>,
+{
+    Schema::of::<T>().ok().map(|schema| schema.hash())
+}
+
+// A name as serde needs it, interned so each distinct name is allocated
+// once
+#[cfg(feature = "std")]
+pub(crate) fn intern(name: &str) -> &'static str {
+    use std::collections::HashSet;
+    use std::sync::{Mutex, OnceLock};
+
+    static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut names = NAMES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    match names.get(name) {
+        Some(interned) => interned,
+        None => {
+            let leaked: &'static str = Box::leak(name.into());
+            names.insert(leaked);
+            leaked
+        }
+    }
+}
+
+// Struct field names as serde needs them, interned so each distinct list
+// is allocated once
+#[cfg(feature = "std")]
+pub(crate) fn field_names(fields: &[Field]) -> &'static [&'static str] {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+
+    static NAMES: OnceLock<Mutex<HashMap<Vec<&'static str>, &'static [&'static str]>>> = OnceLock::new();
+    let key: Vec<&'static str> = fields.iter().map(|field| intern(&field.name)).collect();
+    let mut names = NAMES.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    names.entry(key).or_insert_with_key(|key| Box::leak(key.clone().into_boxed_slice()))
+}
+
+// Resolves a struct field key, by name or index, to its index
+pub(crate) struct FieldSeed<'s>(pub(crate) &'s [Field]);
+
+impl<'de> DeserializeSeed<'de> for FieldSeed<'_> {
+    type Value = Option<usize>;
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<Self::Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FieldSeed<'_> {
+    type Value = Option<usize>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a field name or index")
+    }
+
+    fn visit_u64<E: de::Error>(self, index: u64) -> core::result::Result<Self::Value, E> {
+        Ok(usize::try_from(index).ok().filter(|&index| index < self.0.len()))
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> core::result::Result<Self::Value, E> {
+        Ok(self.0.iter().position(|field| field.name == name))
+    }
+}
+
+// Resolves a variant identifier, by name or index, to its index
+pub(crate) struct VariantSeed<'s>(pub(crate) &'s [Variant]);
+
+impl<'de> DeserializeSeed<'de> for VariantSeed<'_> {
+    type Value = usize;
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<usize, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for VariantSeed<'_> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a variant name or index")
+    }
+
+    fn visit_u64<E: de::Error>(self, index: u64) -> core::result::Result<usize, E> {
+        usize::try_from(index)
+            .ok()
+            .filter(|&index| index < self.0.len())
+            .ok_or_else(|| E::custom(format!("Unknown variant index {index}")))
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> core::result::Result<usize, E> {
+        self.0
+            .iter()
+            .position(|variant| variant.name == name)
+            .ok_or_else(|| E::custom(format!("Unknown variant {name}")))
+    }
+}
+
+// Position of an `Option`, sequence or map within the container that holds
+// it: container name, variant index and occurrence
+type Slot = (&'static str, u32, usize);
+
+struct Frame {
+    name: &'static str,
+    variant: u32,
+    slots: usize,
+}
+
+struct EnumTrace {
+    names: &'static [&'static str],
+    variants: Vec<Option<VariantKind>>,
+    in_progress: Vec<bool>,
+    next: usize,
+}
+
+impl EnumTrace {
+    fn is_complete(&self) -> bool {
+        self.variants.iter().all(Option::is_some)
+    }
+
+    // Variant to trace at this occurrence. Occurrences nested in the same
+    // enum prefer variants known to terminate; others cycle through all of
+    // them so every variant is reached
+    fn choose(&mut self, nested: bool) -> Option<usize> {
+        let free = |i: &usize| !self.in_progress[*i];
+        if nested {
+            let explored = (0..self.variants.len()).filter(free).find(|&i| self.variants[i].is_some());
+            return explored.or_else(|| (0..self.variants.len()).find(free));
+        }
+        let index = self.next % self.variants.len();
+        self.next += 1;
+        Some(index)
+    }
+}
+
+#[derive(Default)]
+struct Tracer {
+    types: BTreeMap<String, Container>,
+    enums: BTreeMap<&'static str, EnumTrace>,
+    stack: Vec<Frame>,
+    // Slots whose contents were found to recurse, and their types
+    collapsed: BTreeMap<Slot, Type>,
+    // Slot whose contents are being traced, until the next type is entered
+    pending: Option<Slot>,
+    recursed: Option<(Slot, &'static str)>,
+    retry: bool,
+}
+
+impl Tracer {
+    fn start(&mut self) {
+        self.stack.clear();
+        self.stack.push(Frame { name: "", variant: u32::MAX, slots: 0 });
+        self.pending = None;
+        self.recursed = None;
+        self.retry = false;
+    }
+
+    fn next_slot(&mut self) -> Slot {
+        let frame = self.stack.last_mut().expect("tracer stack holds the root frame");
+        frame.slots += 1;
+        (frame.name, frame.variant, frame.slots - 1)
+    }
+
+    fn is_open(&self, name: &str) -> bool {
+        self.stack.iter().skip(1).any(|frame| frame.name == name)
+    }
+
+    // Enter a struct, failing if it is already being traced
+    fn enter(&mut self, name: &'static str, pending: Option<Slot>) -> Result<()> {
+        if self.is_open(name) {
+            return match pending {
+                Some(slot) => {
+                    self.recursed = Some((slot, name));
+                    Err(Error::Serde(format!("Recursive type {name}")))
+                }
+                None => Err(Error::Serde(format!(
+                    "Cannot trace {name}: recursive types must nest through an Option, sequence or map"
+                ))),
+            };
+        }
+        self.stack.push(Frame { name, variant: u32::MAX, slots: 0 });
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    // Remember the type of a slot whose contents recursed so the next run
+    // leaves it empty
+    fn collapse(&mut self, slot: Slot, ty: impl FnOnce(&'static str) -> Type) {
+        if let Some((recursed, name)) = self.recursed {
+            if recursed == slot {
+                self.collapsed.insert(slot, ty(name));
+                self.recursed = None;
+                self.retry = true;
+            }
+        }
+    }
+
+    fn finish(mut self, root: Type) -> Schema {
+        for (name, trace) in core::mem::take(&mut self.enums) {
+            let variants = trace
+                .names
+                .iter()
+                .zip(trace.variants)
+                .map(|(name, kind)| Variant {
+                    name: name.to_string(),
+                    kind: kind.unwrap_or(VariantKind::Unit),
+                })
+                .collect();
+            self.types.insert(name.to_string(), Container::Enum(variants));
+        }
+        Schema { root, types: self.types }
+    }
+}
+
+fn fields(names: &[&str], types: Vec<Type>) -> Vec<Field> {
+    names
+        .iter()
+        .zip(types)
+        .enumerate()
+        .map(|(index, (name, ty))| Field {
+            name: name.to_string(),
+            tag: field_tag(index, name),
+            ty,
+        })
+        .collect()
+}
+
+// Deserializes a sample value and records its type in `ty`
+struct TypeTracer<'a> {
+    tracer: &'a mut Tracer,
+    ty: &'a mut Type,
+}
+
+impl<'a> TypeTracer<'a> {
+    fn scalar(self, ty: Type) {
+        self.tracer.pending = None;
+        *self.ty = ty;
+    }
+}
+
+macro_rules! trace_scalar {
+    ($($method:ident => $ty:ident, $visit:ident($sample:expr);)*) => {$(
+        fn $method<V>(self, visitor: V) -> Result<V::Value>
+        where
+            V: Visitor<'de>,
+        {
+            self.scalar(Type::$ty);
+            visitor.$visit($sample)
+        }
+    )*};
+}
+
+impl<'a, 'de> de::Deserializer<'de> for TypeTracer<'a> {
+    type Error = Error;
+
+    trace_scalar! {
+        deserialize_bool => Bool, visit_bool(false);
+        deserialize_i8 => I8, visit_i8(1);
+        deserialize_i16 => I16, visit_i16(1);
+        deserialize_i32 => I32, visit_i32(1);
+        deserialize_i64 => I64, visit_i64(1);
+        deserialize_i128 => I128, visit_i128(1);
+        deserialize_u8 => U8, visit_u8(1);
+        deserialize_u16 => U16, visit_u16(1);
+        deserialize_u32 => U32, visit_u32(1);
+        deserialize_u64 => U64, visit_u64(1);
+        deserialize_u128 => U128, visit_u128(1);
+        deserialize_f32 => F32, visit_f32(1.0);
+        deserialize_f64 => F64, visit_f64(1.0);
+        deserialize_char => Char, visit_char('a');
+        deserialize_str => Str, visit_borrowed_str("");
+        deserialize_string => Str, visit_borrowed_str("");
+        deserialize_bytes => Bytes, visit_borrowed_bytes(&[]);
+        deserialize_byte_buf => Bytes, visit_borrowed_bytes(&[]);
+        deserialize_identifier => Str, visit_borrowed_str("");
+    }
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::Serde(
+            "Cannot trace a type whose representation depends on the data".to_string(),
+        ))
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.scalar(Type::Unit);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.tracer.pending = None;
+        self.tracer.types.insert(name.to_string(), Container::UnitStruct);
+        *self.ty = Type::Named(name.to_string());
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let pending = self.tracer.pending.take();
+        self.tracer.enter(name, pending)?;
+        let mut inner = Type::Unit;
+        let value = visitor.visit_newtype_struct(TypeTracer { tracer: &mut *self.tracer, ty: &mut inner });
+        self.tracer.leave();
+        self.tracer.types.insert(name.to_string(), Container::NewtypeStruct(inner));
+        *self.ty = Type::Named(name.to_string());
+        value
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.tracer.pending = None;
+        let slot = self.tracer.next_slot();
+        if let Some(ty) = self.tracer.collapsed.get(&slot) {
+            *self.ty = ty.clone();
+            return visitor.visit_none();
+        }
+        self.tracer.pending = Some(slot);
+        let mut inner = Type::Unit;
+        let value = visitor.visit_some(TypeTracer { tracer: &mut *self.tracer, ty: &mut inner });
+        self.tracer.collapse(slot, |name| Type::Option(Box::new(Type::Named(name.to_string()))));
+        *self.ty = Type::Option(Box::new(inner));
+        value
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.tracer.pending = None;
+        let slot = self.tracer.next_slot();
+        if let Some(ty) = self.tracer.collapsed.get(&slot) {
+            *self.ty = ty.clone();
+            return visitor.visit_seq(de::value::SeqDeserializer::new(core::iter::empty::<()>()));
+        }
+        let mut item = Type::Unit;
+        let value = visitor.visit_seq(SeqTracer {
+            tracer: &mut *self.tracer,
+            slot: Some(slot),
+            types: core::slice::from_mut(&mut item).iter_mut(),
+        });
+        self.tracer.collapse(slot, |name| Type::Seq(Box::new(Type::Named(name.to_string()))));
+        *self.ty = Type::Seq(Box::new(item));
+        value
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.tracer.pending = None;
+        let mut items = vec![Type::Unit; len];
+        let value = visitor.visit_seq(SeqTracer { tracer: &mut *self.tracer, slot: None, types: items.iter_mut() });
+        *self.ty = Type::Tuple(items);
+        value
+    }
+
+    fn deserialize_tuple_struct<V>(self, name: &'static str, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let pending = self.tracer.pending.take();
+        self.tracer.enter(name, pending)?;
+        let mut items = vec![Type::Unit; len];
+        let value = visitor.visit_seq(SeqTracer { tracer: &mut *self.tracer, slot: None, types: items.iter_mut() });
+        self.tracer.leave();
+        self.tracer.types.insert(name.to_string(), Container::TupleStruct(items));
+        *self.ty = Type::Named(name.to_string());
+        value
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.tracer.pending = None;
+        let slot = self.tracer.next_slot();
+        if let Some(ty) = self.tracer.collapsed.get(&slot) {
+            *self.ty = ty.clone();
+            return visitor.visit_map(de::value::MapDeserializer::new(core::iter::empty::<((), ())>()));
+        }
+        let (mut key, mut value_ty) = (Type::Unit, Type::Unit);
+        let value = visitor.visit_map(MapTracer {
+            tracer: &mut *self.tracer,
+            slot,
+            key: &mut key,
+            value: &mut value_ty,
+            done: false,
+        });
+        let key_ty = key.clone();
+        self.tracer.collapse(slot, |name| Type::Map {
+            key: Box::new(key_ty),
+            value: Box::new(Type::Named(name.to_string())),
+        });
+        *self.ty = Type::Map { key: Box::new(key), value: Box::new(value_ty) };
+        value
+    }
+
+    fn deserialize_struct<V>(self, name: &'static str, names: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let pending = self.tracer.pending.take();
+        self.tracer.enter(name, pending)?;
+        let mut types = vec![Type::Unit; names.len()];
+        let value = visitor.visit_seq(SeqTracer { tracer: &mut *self.tracer, slot: None, types: types.iter_mut() });
+        self.tracer.leave();
+        self.tracer.types.insert(name.to_string(), Container::Struct(fields(names, types)));
+        *self.ty = Type::Named(name.to_string());
+        value
+    }
+
+    fn deserialize_enum<V>(self, name: &'static str, names: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.tracer.pending = None;
+        *self.ty = Type::Named(name.to_string());
+        if names.is_empty() {
+            return Err(Error::Serde(format!("Cannot trace {name}: it has no variants")));
+        }
+        let nested = self.tracer.is_open(name);
+        let trace = self.tracer.enums.entry(name).or_insert_with(|| EnumTrace {
+            names,
+            variants: vec![None; names.len()],
+            in_progress: vec![false; names.len()],
+            next: 0,
+        });
+        let index = trace
+            .choose(nested)
+            .ok_or_else(|| Error::Serde(format!("Cannot trace {name}: every variant recurses")))?;
+        trace.in_progress[index] = true;
+        self.tracer.stack.push(Frame { name, variant: index as u32, slots: 0 });
+
+        let mut kind = VariantKind::Unit;
+        let value = visitor.visit_enum(EnumTracer { tracer: &mut *self.tracer, index: index as u32, kind: &mut kind });
+
+        self.tracer.stack.pop();
+        let trace = self.tracer.enums.get_mut(name).expect("enum trace was inserted above");
+        trace.in_progress[index] = false;
+        if value.is_ok() {
+            trace.variants[index].get_or_insert(kind);
+        }
+        value
+    }
+}
+
+// Yields one traced element per type, or a single element for sequences
+struct SeqTracer<'a, 'b> {
+    tracer: &'a mut Tracer,
+    slot: Option<Slot>,
+    types: core::slice::IterMut<'b, Type>,
+}
+
+impl<'a, 'b, 'de> SeqAccess<'de> for SeqTracer<'a, 'b> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let Some(ty) = self.types.next() else {
+            return Ok(None);
+        };
+        self.tracer.pending = self.slot;
+        seed.deserialize(TypeTracer { tracer: &mut *self.tracer, ty }).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.types.len())
+    }
+}
+
+// Yields a single traced entry
+struct MapTracer<'a> {
+    tracer: &'a mut Tracer,
+    slot: Slot,
+    key: &'a mut Type,
+    value: &'a mut Type,
+    done: bool,
+}
+
+impl<'a, 'de> MapAccess<'de> for MapTracer<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if core::mem::replace(&mut self.done, true) {
+            return Ok(None);
+        }
+        seed.deserialize(TypeTracer { tracer: &mut *self.tracer, ty: &mut *self.key }).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.tracer.pending = Some(self.slot);
+        seed.deserialize(TypeTracer { tracer: &mut *self.tracer, ty: &mut *self.value })
+    }
+}
+
+// Picks the variant chosen by the tracer and records its contents
+struct EnumTracer<'a> {
+    tracer: &'a mut Tracer,
+    index: u32,
+    kind: &'a mut VariantKind,
+}
+
+impl<'a, 'de> EnumAccess<'de> for EnumTracer<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.index))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'a, 'de> VariantAccess<'de> for EnumTracer<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        *self.kind = VariantKind::Unit;
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let mut ty = Type::Unit;
+        let value = seed.deserialize(TypeTracer { tracer: self.tracer, ty: &mut ty });
+        *self.kind = VariantKind::Newtype(ty);
+        value
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let mut items = vec![Type::Unit; len];
+        let value = visitor.visit_seq(SeqTracer { tracer: self.tracer, slot: None, types: items.iter_mut() });
+        *self.kind = VariantKind::Tuple(items);
+        value
+    }
+
+    fn struct_variant<V>(self, names: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let mut types = vec![Type::Unit; names.len()];
+        let value = visitor.visit_seq(SeqTracer { tracer: self.tracer, slot: None, types: types.iter_mut() });
+        *self.kind = VariantKind::Struct(fields(names, types));
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    enum Expr {
+        Add(Box<Expr>, Box<Expr>),
+        Num(i64),
+        Var { name: String },
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Node<'a> {
+        #[serde(rename = "7")]
+        id: u32,
+        label: &'a str,
+        children: Vec<Node<'a>>,
+        parent: Option<Box<Node<'a>>>,
+        attrs: HashMap<String, Node<'a>>,
+        expr: Expr,
+        span: (u8, [u16; 2]),
+    }
+
+    #[test]
+    fn test_trace_recursive_types() {
+        let schema = Schema::of::<Node<'static>>().unwrap();
+        let node = || Box::new(Type::Named("Node".to_string()));
+        let field = |name: &str, tag, ty| Field { name: name.to_string(), tag, ty };
+        assert_eq!(
+            schema.get("Node"),
+            Some(&Container::Struct(vec![
+                field("7", 7, Type::U32),
+                field("label", 1, Type::Str),
+                field("children", 2, Type::Seq(node())),
+                field("parent", 3, Type::Option(node())),
+                field("attrs", 4, Type::Map { key: Box::new(Type::Str), value: node() }),
+                field("expr", 5, Type::Named("Expr".to_string())),
+                field("span", 6, Type::Tuple(vec![Type::U8, Type::Tuple(vec![Type::U16, Type::U16])])),
+            ]))
+        );
+        let expr = || Type::Named("Expr".to_string());
+        assert_eq!(
+            schema.get("Expr"),
+            Some(&Container::Enum(vec![
+                Variant { name: "Add".to_string(), kind: VariantKind::Tuple(vec![expr(), expr()]) },
+                Variant { name: "Num".to_string(), kind: VariantKind::Newtype(Type::I64) },
+                Variant {
+                    name: "Var".to_string(),
+                    kind: VariantKind::Struct(vec![field("name", 0, Type::Str)]),
+                },
+            ]))
+        );
+
+        #[allow(dead_code)]
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Untagged {
+            A(u8),
+        }
+        assert!(Schema::of::<Untagged>().is_err());
+    }
+
+    #[test]
+    fn test_schema_hash_check() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Reading {
+            sensor: u32,
+            celsius: f64,
+        }
+
+        // An older build of the same type, before `sensor` was added
+        #[derive(Deserialize, Debug)]
+        #[serde(rename = "Reading")]
+        struct StaleReading {
+            _celsius: f64,
+        }
+
+        let reading = Reading { sensor: 3, celsius: 21.5 };
+        let hash = Schema::of::<Reading>().unwrap().hash();
+        let bytes = to_bytes_with_schema_hash(&reading).unwrap();
+        assert_eq!(crate::from_bytes::<Reading>(&bytes).unwrap(), reading);
+        assert_eq!(
+            crate::from_bytes::<StaleReading>(&bytes).unwrap_err(),
+            Error::SchemaMismatch { expected: Schema::of::<StaleReading>().unwrap().hash(), found: hash }
+        );
+
+        // Untraceable types decode as before, with other header options
+        let config = Config::new().with_schema_hash(Some(hash)).with_self_describing(true);
+        let bytes = crate::to_bytes_with_config(&reading, config).unwrap();
+        assert!(crate::from_bytes::<crate::Value>(&bytes).is_ok());
+        assert_eq!(crate::from_bytes::<Reading>(&bytes).unwrap(), reading);
+    }
+}
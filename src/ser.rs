@@ -1,7 +1,9 @@
 //! Binary serialization implementation
 
 #[cfg(not(feature = "std"))]
-use alloc::{vec::Vec, string::String};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec, string::{String, ToString}};
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
 
 #[cfg(feature = "std")]
 use std::io::Write;
@@ -12,131 +14,637 @@ use serde::ser::{
 };
 
 use crate::buffer::WriteBuffer;
+#[cfg(feature = "std")]
+use crate::buffer::BufferPool;
+use crate::config::{field_tag, Config};
 use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+use crate::metrics::{Operation, Probe};
+use crate::profile::{Part, Profiler};
+use crate::tag;
+
+pub use crate::profile::{SizeNode, SizeReport};
 
 /// High-performance binary serializer
-pub struct Serializer {
+///
+/// Output is buffered in memory and returned by [`into_bytes`]. A serializer
+/// created with [`to_stream`] instead writes the buffer through to a writer
+/// whenever it grows past a threshold and no length is waiting to be
+/// back-patched, so large values are never held in memory in full.
+///
+/// [`into_bytes`]: Serializer::into_bytes
+/// [`to_stream`]: Serializer::to_stream
+pub struct Serializer<'w> {
     buffer: WriteBuffer,
+    config: Config,
+    marker: Marker,
+    pending_variant_index: u32,
+    // Buffer length just after the tag of the `Some` being written, while a
+    // length may still be folded into it
+    some_tag: Option<usize>,
+    // Buffer length where the struct field being written starts, and the
+    // byte and mask of its bit in the struct's presence bitmap
+    field_bit: Option<(usize, usize, u8)>,
+    strings: Option<StringTable>,
+    sink: Sink<'w>,
+    profiler: Option<Box<Profiler>>,
+    path: Option<Box<FieldPath>>,
 }
 
-impl Serializer {
-    /// Create a new serializer with default capacity
-    pub fn new() -> Self {
+// Distinct strings of a payload, in order of first use
+#[derive(Default)]
+pub(crate) struct StringTable {
+    ids: BTreeMap<String, u64>,
+    encoded: WriteBuffer,
+}
+
+impl StringTable {
+    fn for_config(config: &Config) -> Option<Self> {
+        config.interns_strings().then(Self::default)
+    }
+
+    // Index of `s`, adding it on first use
+    fn id(&mut self, s: &str) -> Result<u64> {
+        if let Some(&id) = self.ids.get(s) {
+            return Ok(id);
+        }
+        let id = self.ids.len() as u64;
+        self.encoded.write_str(s)?;
+        self.ids.insert(s.to_string(), id);
+        Ok(id)
+    }
+
+    // Number of strings in the table
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    // Every string in the table, each as length + bytes
+    #[inline]
+    pub(crate) fn encoded(&self) -> &[u8] {
+        self.encoded.as_slice()
+    }
+
+    // The table as written before the body: count, then each string
+    fn write_to(&self, out: &mut WriteBuffer) -> Result<()> {
+        out.write_varint(self.ids.len() as u64)?;
+        out.write_bytes(self.encoded.as_slice())
+    }
+}
+
+// Path to the part of the value being written, kept while non-finite
+// floats are denied so the error can name where one was found
+#[derive(Default)]
+struct FieldPath {
+    // Each part entered, with the number of elements written inside it
+    parts: Vec<(Part, usize)>,
+    // Elements written at the top level
+    next: usize,
+}
+
+impl FieldPath {
+    fn for_config(config: &Config) -> Option<Box<Self>> {
+        config.deny_non_finite_floats().then(Box::default)
+    }
+
+    fn enter(&mut self, part: Part) {
+        let next = match self.parts.last_mut() {
+            Some((_, next)) => next,
+            None => &mut self.next,
+        };
+        // Map keys are left unnumbered; their value carries the entry index
+        let part = match part {
+            Part::Element | Part::Next | Part::Value => {
+                *next += 1;
+                Part::Index(*next - 1)
+            }
+            part => part,
+        };
+        self.parts.push((part, 0));
+    }
+
+    fn leave(&mut self) {
+        self.parts.pop();
+    }
+
+    fn to_path(&self) -> String {
+        let mut path = String::new();
+        for (i, (part, _)) in self.parts.iter().enumerate() {
+            if i > 0 {
+                path.push('.');
+            }
+            path.push_str(&part.to_string());
+        }
+        path
+    }
+}
+
+// Writer that buffered output is flushed to, if streaming
+#[cfg(feature = "std")]
+struct Sink<'w> {
+    writer: Option<&'w mut dyn Write>,
+    open_lengths: usize,
+}
+
+#[cfg(not(feature = "std"))]
+struct Sink<'w> {
+    _writer: core::marker::PhantomData<&'w mut ()>,
+}
+
+impl Sink<'_> {
+    #[cfg(feature = "std")]
+    const fn none() -> Self {
         Self {
-            buffer: WriteBuffer::new(),
+            writer: None,
+            open_lengths: 0,
         }
     }
 
+    #[cfg(not(feature = "std"))]
+    const fn none() -> Self {
+        Self {
+            _writer: core::marker::PhantomData,
+        }
+    }
+}
+
+/// Buffered bytes above which a streaming serializer flushes to its writer
+#[cfg(feature = "std")]
+const STREAM_FLUSH_THRESHOLD: usize = 64 * 1024;
+
+// Progress through the private newtypes used to serialize `Value::Variant`,
+// `OrderedMap` and `Nullable`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    None,
+    VariantTuple,
+    VariantIndex,
+    VariantName,
+    OrderedMap,
+    UnorderedSet,
+    // Buffer length where the wrapped struct must start
+    Nullable(usize),
+}
+
+impl<'w> Serializer<'w> {
+    /// Create a new serializer with default capacity
+    pub fn new() -> Self {
+        Self::with_config(Config::new())
+    }
+
     /// Create a new serializer with specified capacity
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             buffer: WriteBuffer::with_capacity(capacity),
+            config: Config::new(),
+            marker: Marker::None,
+            pending_variant_index: 0,
+            some_tag: None,
+            field_bit: None,
+            strings: None,
+            sink: Sink::none(),
+            profiler: None,
+            path: None,
+        }
+    }
+
+    /// Create a new serializer using the given configuration
+    pub fn with_config(config: Config) -> Self {
+        Self {
+            buffer: WriteBuffer::new(),
+            config,
+            marker: Marker::None,
+            pending_variant_index: 0,
+            some_tag: None,
+            field_bit: None,
+            strings: StringTable::for_config(&config),
+            sink: Sink::none(),
+            profiler: None,
+            path: FieldPath::for_config(&config),
+        }
+    }
+
+    /// Create a serializer that writes into an existing buffer
+    ///
+    /// The buffer is cleared first; its allocation is reused.
+    pub fn with_buffer(mut buffer: WriteBuffer, config: Config) -> Self {
+        buffer.clear();
+        Self {
+            buffer,
+            config,
+            marker: Marker::None,
+            pending_variant_index: 0,
+            some_tag: None,
+            field_bit: None,
+            strings: StringTable::for_config(&config),
+            sink: Sink::none(),
+            profiler: None,
+            path: FieldPath::for_config(&config),
+        }
+    }
+
+    // Create a serializer that adds to a string table kept by the caller
+    pub(crate) fn with_string_table(mut buffer: WriteBuffer, config: Config, strings: StringTable) -> Self {
+        buffer.clear();
+        Self {
+            buffer,
+            config,
+            marker: Marker::None,
+            pending_variant_index: 0,
+            some_tag: None,
+            field_bit: None,
+            strings: Some(strings),
+            sink: Sink::none(),
+            profiler: None,
+            path: FieldPath::for_config(&config),
+        }
+    }
+
+    /// Create a serializer that streams its output to `writer`
+    ///
+    /// The header is written immediately; call [`finish`](Self::finish) after
+    /// serializing to write the rest. Checksums and string tables cannot be
+    /// streamed.
+    #[cfg(feature = "std")]
+    pub fn to_stream(writer: &'w mut dyn Write, config: Config) -> Result<Self> {
+        if config.checksum().is_some() {
+            return Err(Error::InvalidFormat(
+                "Checksums are not supported by streaming serializers".to_string(),
+            ));
+        }
+        if config.interns_strings() {
+            return Err(Error::InvalidFormat(
+                "String tables are not supported by streaming serializers".to_string(),
+            ));
+        }
+        let mut header = Vec::with_capacity(6);
+        config.header().write_to(&mut header);
+        writer.write_all(&header)?;
+
+        let mut ser = Self::with_config(config);
+        ser.sink.writer = Some(writer);
+        Ok(ser)
+    }
+
+    /// Get the configuration used by this serializer
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Write any remaining output of a streaming serializer to its writer
+    #[cfg(feature = "std")]
+    pub fn finish(mut self) -> Result<()> {
+        match self.sink.writer.take() {
+            Some(writer) => {
+                writer.write_all(self.buffer.as_slice())?;
+                Ok(())
+            }
+            None => Err(Error::InvalidFormat(
+                "Serializer was not created with to_stream".to_string(),
+            )),
+        }
+    }
+
+    // Flush buffered output to the writer once it is large and no length
+    // before it still needs back-patching
+    #[cfg(feature = "std")]
+    #[inline]
+    fn maybe_flush(&mut self) -> Result<()> {
+        if self.some_tag.is_some() {
+            // A tag that is still the last byte may yet be folded
+            if self.some_tag == Some(self.buffer.len()) {
+                return Ok(());
+            }
+            self.some_tag = None;
+        }
+        if self.sink.open_lengths == 0 && self.buffer.len() >= STREAM_FLUSH_THRESHOLD {
+            if let Some(writer) = self.sink.writer.as_mut() {
+                writer.write_all(self.buffer.as_slice())?;
+                self.buffer.clear();
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn maybe_flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // Track a length that will be back-patched, which blocks flushing
+    #[inline]
+    fn open_length(&mut self) {
+        #[cfg(feature = "std")]
+        {
+            self.sink.open_lengths += 1;
+        }
+    }
+
+    #[inline]
+    fn close_length(&mut self) {
+        #[cfg(feature = "std")]
+        {
+            self.sink.open_lengths -= 1;
         }
     }
 
     /// Finalize serialization and return the bytes
     pub fn into_bytes(self) -> Vec<u8> {
-        // Write header: magic bytes + version
-        let mut result = Vec::with_capacity(self.buffer.len() + 5);
-        result.extend_from_slice(crate::MAGIC);
-        result.push(crate::VERSION);
+        self.encoded()
+    }
+
+    /// Finalize serialization, returning the bytes and the scratch buffer
+    /// so it can be reused
+    pub fn into_bytes_and_buffer(self) -> (Vec<u8>, WriteBuffer) {
+        (self.encoded(), self.buffer)
+    }
+
+    // The serialized body, without header or checksum
+    pub(crate) fn into_buffer(self) -> WriteBuffer {
+        match self.strings {
+            Some(strings) => {
+                let mut body = WriteBuffer::with_capacity(strings.encoded.len() + self.buffer.len() + 10);
+                strings.write_to(&mut body).expect("writing to a WriteBuffer cannot fail");
+                body.write_bytes(self.buffer.as_slice()).expect("writing to a WriteBuffer cannot fail");
+                body
+            }
+            None => self.buffer,
+        }
+    }
+
+    // The serialized body without its string table, and the table
+    pub(crate) fn into_parts(self) -> (WriteBuffer, Option<StringTable>) {
+        (self.buffer, self.strings)
+    }
+
+    // Finalize serialization, recording a type name in the header
+    pub(crate) fn into_bytes_with_type_name(self, name: &str) -> Vec<u8> {
+        self.encoded_with(Some(name))
+    }
+
+    fn encoded(&self) -> Vec<u8> {
+        self.encoded_with(None)
+    }
+
+    fn encoded_with(&self, type_name: Option<&str>) -> Vec<u8> {
+        // Write header: magic bytes + version (+ flags) (+ schema hash) (+ type name)
+        let mut flags = self.config.header_flags();
+        flags.set(HeaderFlags::TYPE_NAME, type_name.is_some());
+        let header = Header::new(flags).with_schema_hash(self.config.schema_hash());
+        let mut result = Vec::with_capacity(self.buffer.len() + header.encoded_len());
+        header.write_to(&mut result);
+        if let Some(name) = type_name {
+            let mut encoded = WriteBuffer::with_capacity(name.len() + 10);
+            encoded.write_str(name).expect("writing to a WriteBuffer cannot fail");
+            result.extend_from_slice(encoded.as_slice());
+        }
+        if let Some(strings) = &self.strings {
+            let mut table = WriteBuffer::with_capacity(strings.encoded.len() + 10);
+            strings.write_to(&mut table).expect("writing to a WriteBuffer cannot fail");
+            result.extend_from_slice(table.as_slice());
+        }
         result.extend_from_slice(self.buffer.as_slice());
+        #[cfg(feature = "checksum")]
+        if let Some(checksum) = self.config.checksum() {
+            checksum.append(&mut result);
+        }
         result
     }
 }
 
-impl Default for Serializer {
+impl Default for Serializer<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl serde::Serializer for &mut Serializer {
+impl Serializer<'_> {
+    // Write a type tag when in self-describing mode
+    #[inline]
+    fn write_tag(&mut self, tag: u8) -> Result<()> {
+        if self.config.self_describing() {
+            self.buffer.write_u8(tag)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Claim the presence bit of the struct field being written, if nothing
+    // of the field has been written yet
+    #[inline]
+    fn take_field_bit(&mut self) -> Option<(usize, u8)> {
+        match self.field_bit.take() {
+            Some((at, byte, mask)) if at == self.buffer.len() => Some((byte, mask)),
+            _ => None,
+        }
+    }
+
+    // Drop the tag of the `Some` being written if nothing follows it yet,
+    // returning what to add to the length written in its place
+    #[inline]
+    fn fold_some_tag(&mut self) -> u64 {
+        match self.some_tag.take() {
+            Some(end) if end == self.buffer.len() => {
+                self.buffer.truncate(end - 1);
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    // Write an enum variant header: the index, plus the tag and name when
+    // self-describing
+    fn write_variant(&mut self, variant_index: u32, variant: &str) -> Result<()> {
+        if self.config.self_describing() {
+            self.buffer.write_u8(tag::VARIANT)?;
+            self.buffer.write_varint(variant_index as u64)?;
+            self.buffer.write_str(variant)
+        } else {
+            self.buffer.write_varint(variant_index as u64)
+        }
+    }
+
+    // Mark the start of a part of the value when profiling or tracking
+    // the path to it
+    #[inline]
+    fn enter(&mut self, part: Part) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(part, self.buffer.len());
+        }
+        if let Some(path) = &mut self.path {
+            path.enter(part);
+        }
+    }
+
+    // Mark the end of the part entered last
+    #[inline]
+    fn leave(&mut self) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.leave(self.buffer.len());
+        }
+        if let Some(path) = &mut self.path {
+            path.leave();
+        }
+    }
+
+    // Reject NaN and infinities when non-finite floats are denied
+    #[inline]
+    fn check_finite(&self, value: f64) -> Result<()> {
+        match &self.path {
+            Some(path) if !value.is_finite() => Err(Error::NonFiniteFloat { value, path: path.to_path() }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a, 'w> serde::Serializer for &'a mut Serializer<'w> {
     type Ok = ();
     type Error = Error;
 
-    type SerializeSeq = Self;
+    type SerializeSeq = Compound<'a, 'w>;
     type SerializeTuple = Self;
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
-    type SerializeStruct = Self;
-    type SerializeStructVariant = Self;
+    type SerializeMap = Compound<'a, 'w>;
+    type SerializeStruct = Compound<'a, 'w>;
+    type SerializeStructVariant = Compound<'a, 'w>;
 
     #[inline]
     fn serialize_bool(self, v: bool) -> Result<()> {
+        if let Some((byte, mask)) = self.take_field_bit() {
+            if v {
+                self.buffer.as_mut_slice()[byte] |= mask;
+            }
+            return Ok(());
+        }
+        if self.config.self_describing() {
+            return self.buffer.write_u8(if v { tag::TRUE } else { tag::FALSE });
+        }
         self.buffer.write_u8(if v { 1 } else { 0 })
     }
 
     #[inline]
     fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_tag(tag::I8)?;
         self.buffer.write_i8(v)
     }
 
     #[inline]
     fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write_tag(tag::I16)?;
         self.buffer.write_i16(v)
     }
 
     #[inline]
     fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write_tag(tag::I32)?;
         self.buffer.write_i32(v)
     }
 
     #[inline]
     fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_tag(tag::I64)?;
         self.buffer.write_i64(v)
     }
 
     #[inline]
     fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write_tag(tag::U8)?;
         self.buffer.write_u8(v)
     }
 
     #[inline]
     fn serialize_u16(self, v: u16) -> Result<()> {
+        self.write_tag(tag::U16)?;
         self.buffer.write_u16(v)
     }
 
     #[inline]
     fn serialize_u32(self, v: u32) -> Result<()> {
+        if self.marker == Marker::VariantIndex {
+            // Variant index of a `Value::Variant`, see `crate::value`
+            self.marker = Marker::VariantName;
+            self.pending_variant_index = v;
+            return Ok(());
+        }
+        self.write_tag(tag::U32)?;
         self.buffer.write_u32(v)
     }
 
     #[inline]
     fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_tag(tag::U64)?;
         self.buffer.write_u64(v)
     }
 
     #[inline]
     fn serialize_f32(self, v: f32) -> Result<()> {
+        self.check_finite(v.into())?;
+        self.write_tag(tag::F32)?;
+        let v = if self.config.canonical() { canonical_f32(v) } else { v };
         self.buffer.write_f32(v)
     }
 
     #[inline]
     fn serialize_f64(self, v: f64) -> Result<()> {
+        self.check_finite(v)?;
+        self.write_tag(tag::F64)?;
+        let v = if self.config.canonical() { canonical_f64(v) } else { v };
         self.buffer.write_f64(v)
     }
 
     #[inline]
     fn serialize_char(self, v: char) -> Result<()> {
-        // Encode char as u32
-        self.serialize_u32(v as u32)
+        // Encode char as UTF-8 when compact, otherwise as u32
+        if self.config.utf8_chars() {
+            return self.buffer.write_char_utf8(v);
+        }
+        self.write_tag(tag::CHAR)?;
+        self.buffer.write_u32(v as u32)
     }
 
     #[inline]
     fn serialize_str(self, v: &str) -> Result<()> {
+        if self.marker == Marker::VariantName {
+            self.marker = Marker::None;
+            return self.write_variant(self.pending_variant_index, v);
+        }
+        if let Some(strings) = self.strings.as_mut() {
+            let id = strings.id(v)?;
+            return self.buffer.write_varint(id);
+        }
+        self.write_tag(tag::STR)?;
+        if self.some_tag.is_some() {
+            let some = self.fold_some_tag();
+            self.buffer.write_varint(v.len() as u64 + some)?;
+            return self.buffer.write_bytes(v.as_bytes());
+        }
         self.buffer.write_str(v)
     }
 
     #[inline]
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_tag(tag::BYTES)?;
+        if self.some_tag.is_some() {
+            let some = self.fold_some_tag();
+            self.buffer.write_varint(v.len() as u64 + some)?;
+            return self.buffer.write_bytes(v);
+        }
         self.buffer.write_byte_slice(v)
     }
 
     #[inline]
     fn serialize_none(self) -> Result<()> {
-        self.serialize_u8(0)
+        if self.take_field_bit().is_some() {
+            return Ok(());
+        }
+        if self.config.self_describing() {
+            return self.buffer.write_u8(tag::NONE);
+        }
+        self.buffer.write_u8(0)
     }
 
     #[inline]
@@ -144,13 +652,27 @@ impl serde::Serializer for &mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        self.serialize_u8(1)?;
+        if let Some((byte, mask)) = self.take_field_bit() {
+            self.buffer.as_mut_slice()[byte] |= mask;
+            return value.serialize(self);
+        }
+        if self.config.self_describing() {
+            self.buffer.write_u8(tag::SOME)?;
+        } else {
+            self.buffer.write_u8(1)?;
+        }
+        if self.config.folds_option_tags() {
+            self.some_tag = Some(self.buffer.len());
+            value.serialize(&mut *self)?;
+            self.some_tag = None;
+            return Ok(());
+        }
         value.serialize(self)
     }
 
     #[inline]
     fn serialize_unit(self) -> Result<()> {
-        Ok(())
+        self.write_tag(tag::UNIT)
     }
 
     #[inline]
@@ -163,20 +685,51 @@ impl serde::Serializer for &mut Serializer {
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<()> {
-        self.buffer.write_varint(variant_index as u64)
+        self.enter(Part::Variant(variant));
+        self.write_variant(variant_index, variant)?;
+        self.write_tag(tag::UNIT)?;
+        self.leave();
+        Ok(())
     }
 
     #[inline]
     fn serialize_newtype_struct<T>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<()>
     where
         T: Serialize + ?Sized,
     {
+        if name == crate::value::VARIANT_MARKER {
+            self.marker = Marker::VariantTuple;
+        } else if name == crate::collections::ORDERED_MAP_MARKER {
+            self.marker = Marker::OrderedMap;
+            value.serialize(&mut *self)?;
+            // Left unused when the wrapped value is not a map
+            if self.marker == Marker::OrderedMap {
+                self.marker = Marker::None;
+            }
+            return Ok(());
+        } else if name == crate::collections::UNORDERED_SET_MARKER {
+            self.marker = Marker::UnorderedSet;
+            value.serialize(&mut *self)?;
+            // Left unused when the wrapped value is not a sequence
+            if self.marker == Marker::UnorderedSet {
+                self.marker = Marker::None;
+            }
+            return Ok(());
+        } else if name == crate::nullable::NULLABLE_MARKER {
+            self.marker = Marker::Nullable(self.buffer.len());
+            value.serialize(&mut *self)?;
+            // Left unused when the wrapped value is not a struct
+            if matches!(self.marker, Marker::Nullable(_)) {
+                self.marker = Marker::None;
+            }
+            return Ok(());
+        }
         value.serialize(self)
     }
 
@@ -184,28 +737,47 @@ impl serde::Serializer for &mut Serializer {
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<()>
     where
         T: Serialize + ?Sized,
     {
-        self.buffer.write_varint(variant_index as u64)?;
-        value.serialize(self)
+        self.enter(Part::Variant(variant));
+        self.write_variant(variant_index, variant)?;
+        value.serialize(&mut *self)?;
+        self.leave();
+        Ok(())
     }
 
     #[inline]
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        match len {
-            Some(len) => self.buffer.write_varint(len as u64)?,
-            None => return Err(Error::Serde("Sequences must have known length".to_string())),
+        let unordered = self.marker == Marker::UnorderedSet;
+        if unordered {
+            self.marker = Marker::None;
         }
-        Ok(self)
+        self.write_tag(tag::SEQ)?;
+        let some = self.fold_some_tag();
+        let mut compound = Compound::begin(self, len, some)?;
+        if compound.ser.config.canonical() && unordered {
+            // Elements are reordered on `end`, so none may be flushed before
+            compound.ser.open_length();
+            compound.entries = Some(Vec::new());
+        }
+        Ok(compound)
     }
 
     #[inline]
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
-        self.buffer.write_varint(len as u64)?;
+        if self.marker == Marker::VariantTuple {
+            // (index, name, value) of a `Value::Variant`: no tuple header
+            self.marker = Marker::VariantIndex;
+            return Ok(self);
+        }
+        self.write_tag(tag::SEQ)?;
+        if !self.config.omits_tuple_lengths() {
+            self.buffer.write_varint(len as u64)?;
+        }
         Ok(self)
     }
 
@@ -222,21 +794,33 @@ impl serde::Serializer for &mut Serializer {
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.buffer.write_varint(variant_index as u64)?;
-        self.buffer.write_varint(len as u64)?;
+        self.enter(Part::Variant(variant));
+        self.write_variant(variant_index, variant)?;
+        self.write_tag(tag::SEQ)?;
+        if !self.config.omits_tuple_lengths() {
+            self.buffer.write_varint(len as u64)?;
+        }
         Ok(self)
     }
 
     #[inline]
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        match len {
-            Some(len) => self.buffer.write_varint(len as u64)?,
-            None => return Err(Error::Serde("Maps must have known length".to_string())),
+        let ordered = self.marker == Marker::OrderedMap;
+        if ordered {
+            self.marker = Marker::None;
         }
-        Ok(self)
+        self.write_tag(tag::MAP)?;
+        let some = self.fold_some_tag();
+        let mut compound = Compound::begin(self, len, some)?;
+        if compound.ser.config.canonical() && !ordered {
+            // Entries are reordered on `end`, so none may be flushed before
+            compound.ser.open_length();
+            compound.entries = Some(Vec::new());
+        }
+        Ok(compound)
     }
 
     #[inline]
@@ -245,25 +829,182 @@ impl serde::Serializer for &mut Serializer {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct> {
-        self.buffer.write_varint(len as u64)?;
-        Ok(self)
+        let mut nullable = false;
+        if let Marker::Nullable(at) = self.marker {
+            self.marker = Marker::None;
+            nullable = at == self.buffer.len();
+        }
+        Compound::begin_struct(self, len, nullable)
     }
 
     fn serialize_struct_variant(
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.buffer.write_varint(variant_index as u64)?;
-        self.buffer.write_varint(len as u64)?;
-        Ok(self)
+        self.enter(Part::Variant(variant));
+        self.write_variant(variant_index, variant)?;
+        Compound::begin_struct(self, len, false)
+    }
+}
+
+/// Serializer state for sequences, maps and structs
+///
+/// When the length is not known up front (`len: None`), a byte is reserved
+/// for the varint length, elements are counted as they are written, and the
+/// length is patched in on `end`, so the encoding is identical to a
+/// known-length sequence.
+/// Structs in tagged-field mode use the same mechanism for their field count.
+pub struct Compound<'a, 'w> {
+    ser: &'a mut Serializer<'w>,
+    pending: Option<PendingLength>,
+    field_index: usize,
+    // Start of each entry's key and value, for sorting canonical maps, or
+    // start and end of each element of a canonical unordered set
+    entries: Option<Vec<(usize, usize)>>,
+    // Start and field count of a struct's presence bitmap
+    bitmap: Option<(usize, usize)>,
+}
+
+// Position of a sequence/map body whose length is still unknown
+struct PendingLength {
+    start: usize,
+    count: u64,
+}
+
+impl<'a, 'w> Compound<'a, 'w> {
+    // `some` is added to the length, see `Serializer::fold_some_tag`
+    fn begin(ser: &'a mut Serializer<'w>, len: Option<usize>, some: u64) -> Result<Self> {
+        let pending = match len {
+            Some(len) => {
+                ser.buffer.write_varint(len as u64 + some)?;
+                None
+            }
+            None => {
+                ser.open_length();
+                Some(PendingLength {
+                    start: ser.buffer.reserve_varint(),
+                    count: some,
+                })
+            }
+        };
+        Ok(Self {
+            ser,
+            pending,
+            field_index: 0,
+            entries: None,
+            bitmap: None,
+        })
+    }
+
+    // `nullable` writes a presence bitmap after the field count, see
+    // `crate::nullable`
+    fn begin_struct(ser: &'a mut Serializer<'w>, len: usize, nullable: bool) -> Result<Self> {
+        // Skipped fields make keyed field counts unknown up front
+        if ser.config.self_describing() {
+            ser.buffer.write_u8(tag::MAP)?;
+            return Self::begin(ser, None, 0);
+        }
+        if ser.config.tagged_fields() {
+            return Self::begin(ser, None, 0);
+        }
+        let mut compound = Self::begin(ser, Some(len), 0)?;
+        if nullable {
+            // Bits are set as fields are written, so none may be flushed before
+            compound.ser.open_length();
+            compound.bitmap = Some((compound.ser.buffer.len(), len));
+            for _ in 0..len.div_ceil(8) {
+                compound.ser.buffer.write_u8(0)?;
+            }
+        }
+        Ok(compound)
+    }
+
+    fn serialize_struct_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.ser.maybe_flush()?;
+        self.ser.enter(Part::Field(key));
+        self.write_struct_field(key, value)?;
+        self.ser.leave();
+        Ok(())
+    }
+
+    fn write_struct_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let index = self.field_index;
+        self.field_index += 1;
+
+        if self.ser.config.self_describing() {
+            // map entry keyed by field name
+            self.count_item();
+            serde::Serializer::serialize_str(&mut *self.ser, key)?;
+            return value.serialize(&mut *self.ser);
+        }
+
+        if let Some((start, len)) = self.bitmap {
+            if index >= len {
+                return Err(Error::Serde(format!("Struct has more than the {len} fields it declared")));
+            }
+            self.ser.field_bit = Some((self.ser.buffer.len(), start + index / 8, 1 << (index % 8)));
+            let written = value.serialize(&mut *self.ser);
+            self.ser.field_bit = None;
+            return written;
+        }
+
+        if !self.ser.config.tagged_fields() {
+            return value.serialize(&mut *self.ser);
+        }
+
+        // tag, then the value's byte length so unknown tags can be skipped
+        self.count_item();
+        self.ser.buffer.write_varint(field_tag(index, key))?;
+        let start = self.ser.buffer.reserve_varint();
+        self.ser.open_length();
+        value.serialize(&mut *self.ser)?;
+        self.ser.close_length();
+        let len = self.ser.buffer.len() - start - 1;
+        self.ser.buffer.patch_varint(start, len as u64)
+    }
+
+    #[inline]
+    fn next_item(&mut self) -> Result<()> {
+        self.ser.maybe_flush()?;
+        self.count_item();
+        Ok(())
+    }
+
+    #[inline]
+    fn count_item(&mut self) {
+        if let Some(pending) = &mut self.pending {
+            pending.count += 1;
+        }
+    }
+
+    fn finish(self) -> Result<&'a mut Serializer<'w>> {
+        let Self { ser, pending, entries, bitmap, .. } = self;
+        if bitmap.is_some() {
+            ser.close_length();
+        }
+        if let Some(entries) = entries {
+            ser.close_length();
+            sort_entries(&mut ser.buffer, &entries)?;
+        }
+        if let Some(pending) = pending {
+            ser.close_length();
+            ser.buffer.patch_varint(pending.start, pending.count)?;
+        }
+        Ok(ser)
     }
 }
 
 // Implementations for compound serialization types
-impl SerializeSeq for &mut Serializer {
+impl SerializeSeq for Compound<'_, '_> {
     type Ok = ();
     type Error = Error;
 
@@ -272,16 +1013,25 @@ impl SerializeSeq for &mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut **self)
+        self.next_item()?;
+        let start = self.ser.buffer.len();
+        self.ser.enter(Part::Element);
+        value.serialize(&mut *self.ser)?;
+        self.ser.leave();
+        if let Some(entries) = &mut self.entries {
+            // The whole element is its sort key
+            entries.push((start, self.ser.buffer.len()));
+        }
+        Ok(())
     }
 
     #[inline]
     fn end(self) -> Result<()> {
-        Ok(())
+        self.finish().map(drop)
     }
 }
 
-impl SerializeTuple for &mut Serializer {
+impl SerializeTuple for &mut Serializer<'_> {
     type Ok = ();
     type Error = Error;
 
@@ -290,7 +1040,11 @@ impl SerializeTuple for &mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut **self)
+        self.maybe_flush()?;
+        self.enter(Part::Next);
+        value.serialize(&mut **self)?;
+        self.leave();
+        Ok(())
     }
 
     #[inline]
@@ -299,7 +1053,7 @@ impl SerializeTuple for &mut Serializer {
     }
 }
 
-impl SerializeTupleStruct for &mut Serializer {
+impl SerializeTupleStruct for &mut Serializer<'_> {
     type Ok = ();
     type Error = Error;
 
@@ -308,7 +1062,11 @@ impl SerializeTupleStruct for &mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut **self)
+        self.maybe_flush()?;
+        self.enter(Part::Next);
+        value.serialize(&mut **self)?;
+        self.leave();
+        Ok(())
     }
 
     #[inline]
@@ -317,7 +1075,7 @@ impl SerializeTupleStruct for &mut Serializer {
     }
 }
 
-impl SerializeTupleVariant for &mut Serializer {
+impl SerializeTupleVariant for &mut Serializer<'_> {
     type Ok = ();
     type Error = Error;
 
@@ -326,16 +1084,21 @@ impl SerializeTupleVariant for &mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut **self)
+        self.maybe_flush()?;
+        self.enter(Part::Next);
+        value.serialize(&mut **self)?;
+        self.leave();
+        Ok(())
     }
 
     #[inline]
     fn end(self) -> Result<()> {
+        self.leave();
         Ok(())
     }
 }
 
-impl SerializeMap for &mut Serializer {
+impl SerializeMap for Compound<'_, '_> {
     type Ok = ();
     type Error = Error;
 
@@ -344,7 +1107,15 @@ impl SerializeMap for &mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        key.serialize(&mut **self)
+        self.next_item()?;
+        if let Some(entries) = &mut self.entries {
+            let start = self.ser.buffer.len();
+            entries.push((start, start));
+        }
+        self.ser.enter(Part::Key);
+        key.serialize(&mut *self.ser)?;
+        self.ser.leave();
+        Ok(())
     }
 
     #[inline]
@@ -352,61 +1123,256 @@ impl SerializeMap for &mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut **self)
+        if let Some((_, value_start)) = self.entries.as_mut().and_then(|entries| entries.last_mut()) {
+            *value_start = self.ser.buffer.len();
+        }
+        self.ser.enter(Part::Value);
+        value.serialize(&mut *self.ser)?;
+        self.ser.leave();
+        Ok(())
     }
 
     #[inline]
     fn end(self) -> Result<()> {
-        Ok(())
+        self.finish().map(drop)
     }
 }
 
-impl SerializeStruct for &mut Serializer {
+impl SerializeStruct for Compound<'_, '_> {
     type Ok = ();
     type Error = Error;
 
     #[inline]
-    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut **self)
+        self.serialize_struct_field(key, value)
     }
 
     #[inline]
-    fn end(self) -> Result<()> {
+    fn skip_field(&mut self, _key: &'static str) -> Result<()> {
+        self.field_index += 1;
         Ok(())
     }
+
+    #[inline]
+    fn end(self) -> Result<()> {
+        self.finish().map(drop)
+    }
 }
 
-impl SerializeStructVariant for &mut Serializer {
+impl SerializeStructVariant for Compound<'_, '_> {
     type Ok = ();
     type Error = Error;
 
     #[inline]
-    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: Serialize + ?Sized,
     {
-        value.serialize(&mut **self)
+        self.serialize_struct_field(key, value)
+    }
+
+    #[inline]
+    fn skip_field(&mut self, _key: &'static str) -> Result<()> {
+        self.field_index += 1;
+        Ok(())
     }
 
     #[inline]
     fn end(self) -> Result<()> {
+        self.finish()?.leave();
         Ok(())
     }
 }
 
+// Zero with a positive sign, and a single quiet NaN
+#[inline]
+fn canonical_f32(v: f32) -> f32 {
+    if v.is_nan() {
+        f32::NAN
+    } else if v == 0.0 {
+        0.0
+    } else {
+        v
+    }
+}
+
+#[inline]
+fn canonical_f64(v: f64) -> f64 {
+    if v.is_nan() {
+        f64::NAN
+    } else if v == 0.0 {
+        0.0
+    } else {
+        v
+    }
+}
+
+// Reorder the map entries or set elements at the end of `buffer` by their
+// encoded keys
+fn sort_entries(buffer: &mut WriteBuffer, entries: &[(usize, usize)]) -> Result<()> {
+    let Some(&(body_start, _)) = entries.first() else {
+        return Ok(());
+    };
+    let body = buffer.as_slice()[body_start..].to_vec();
+    let end = body.len();
+    let mut spans: Vec<(&[u8], &[u8])> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, &(key, value))| {
+            let next = entries.get(i + 1).map_or(end, |&(next, _)| next - body_start);
+            (&body[key - body_start..value - body_start], &body[key - body_start..next])
+        })
+        .collect();
+    spans.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    if spans.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return Err(Error::Serde("Duplicate map key or set element in canonical mode".to_string()));
+    }
+
+    let out = &mut buffer.as_mut_slice()[body_start..];
+    let mut position = 0;
+    for (_, entry) in spans {
+        out[position..position + entry.len()].copy_from_slice(entry);
+        position += entry.len();
+    }
+    Ok(())
+}
+
 /// Serialize a value to bytes
 pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>>
 where
     T: Serialize,
+{
+    to_bytes_with_config(value, Config::new())
+}
+
+/// Serialize a value to bytes using the given configuration
+pub fn to_bytes_with_config<T>(value: &T, config: Config) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let probe = Probe::start(Operation::Serialize, Some(core::any::type_name::<T>()));
+    let mut serializer = Serializer::with_config(config);
+    let result = value.serialize(&mut serializer).map(|_| serializer.into_bytes());
+    probe.finish(&result, result.as_ref().map_or(0, Vec::len), None);
+    result
+}
+
+/// Serialize a value to deterministic bytes, suitable for hashing or signing
+///
+/// Equal values produce identical output, provided sets and other
+/// collections whose order means nothing, apart from maps, are wrapped in
+/// [`UnorderedSet`](crate::UnorderedSet); see [`Config::with_canonical`].
+pub fn to_bytes_canonical<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    to_bytes_with_config(value, Config::new().with_canonical(true))
+}
+
+/// Serialize the items of an iterator as a sequence without collecting them
+///
+/// Iterators that know their exact length write it up front; others use
+/// the unknown-length encoding. Either way the output decodes as a `Vec`.
+pub fn to_bytes_from_iter<I>(iter: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
 {
     let mut serializer = Serializer::new();
-    value.serialize(&mut serializer)?;
+    serde::Serializer::collect_seq(&mut serializer, iter)?;
     Ok(serializer.into_bytes())
 }
 
+/// Serialize the items of an iterator as a sequence to a writer
+///
+/// With an exact length the output is streamed as items are produced;
+/// otherwise it is buffered until the length is known.
+#[cfg(feature = "std")]
+pub fn to_writer_from_iter<W, I>(writer: W, iter: I) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let mut writer = writer;
+    let mut serializer = Serializer::to_stream(&mut writer, Config::new())?;
+    serde::Serializer::collect_seq(&mut serializer, iter)?;
+    serializer.finish()
+}
+
+/// Serialize a value using a scratch buffer from `pool`
+///
+/// The buffer is returned to the pool afterwards, so repeated calls do not
+/// allocate a new scratch buffer each time.
+#[cfg(feature = "std")]
+pub fn to_bytes_pooled<T>(value: &T, pool: &BufferPool) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut serializer = Serializer::with_buffer(pool.acquire(), Config::new());
+    let result = value.serialize(&mut serializer);
+    let (bytes, buffer) = serializer.into_bytes_and_buffer();
+    pool.release(buffer);
+    result.map(|_| bytes)
+}
+
+/// Serialize a value and report how many bytes each part of it takes
+///
+/// ```rust
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct Order {
+///     id: u64,
+///     items: Vec<String>,
+/// }
+///
+/// # fn main() -> nanobit::Result<()> {
+/// let order = Order { id: 7, items: vec!["apple".into(), "pear".into()] };
+/// let report = nanobit::ser::profile(&order)?;
+/// assert_eq!(report.total(), nanobit::to_bytes(&order)?.len());
+/// assert_eq!(report.get("items.[]").unwrap().count, 2);
+/// print!("{report}");
+/// # Ok(())
+/// # }
+/// ```
+pub fn profile<T>(value: &T) -> Result<SizeReport>
+where
+    T: Serialize + ?Sized,
+{
+    profile_with_config(value, Config::new())
+}
+
+/// Profile a value as [`to_bytes_with_config`] would serialize it
+pub fn profile_with_config<T>(value: &T, config: Config) -> Result<SizeReport>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::with_config(config);
+    serializer.profiler = Some(Box::new(Profiler::new()));
+    value.serialize(&mut serializer)?;
+    let profiler = serializer.profiler.take().expect("set above");
+
+    let header = Header::new(serializer.config.header_flags())
+        .with_schema_hash(serializer.config.schema_hash())
+        .encoded_len();
+    let strings = serializer.strings.as_ref().map_or(0, |strings| {
+        let mut table = WriteBuffer::with_capacity(strings.encoded.len() + 10);
+        strings.write_to(&mut table).expect("writing to a WriteBuffer cannot fail");
+        table.len()
+    });
+    let body = serializer.buffer.len();
+    let total = serializer.encoded().len();
+    Ok(SizeReport {
+        header,
+        strings,
+        checksum: total - header - strings - body,
+        value: profiler.finish(body),
+    })
+}
+
 /// Serialize a value to a writer
 #[cfg(feature = "std")]
 pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
@@ -414,10 +1380,10 @@ where
     W: Write,
     T: Serialize,
 {
-    let bytes = to_bytes(value)?;
     let mut writer = writer;
-    writer.write_all(&bytes).map_err(Error::from)?;
-    Ok(())
+    let mut serializer = Serializer::to_stream(&mut writer, Config::new())?;
+    value.serialize(&mut serializer)?;
+    serializer.finish()
 }
 
 #[cfg(test)]
@@ -437,7 +1403,7 @@ mod tests {
     fn test_primitive_serialization() {
         assert!(to_bytes(&42u32).is_ok());
         assert!(to_bytes(&-100i64).is_ok());
-        assert!(to_bytes(&3.14f64).is_ok());
+        assert!(to_bytes(&std::f64::consts::PI).is_ok());
         assert!(to_bytes(&true).is_ok());
         assert!(to_bytes(&"hello").is_ok());
     }
@@ -498,7 +1464,223 @@ mod tests {
         assert!(to_bytes(&TestEnum::Variant3 { field: "test".to_string() }).is_ok());
     }
 
+    #[test]
+    fn test_large_tagged_struct() {
+        #[derive(Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Node {
+            id: u32,
+            name: String,
+            children: Vec<Node>,
+        }
+
+        // Wide and deep, with field lengths on both sides of 128 bytes
+        let leaf = |i: u32| Node { id: i, name: "x".repeat(i as usize % 300), children: Vec::new() };
+        let mut tree = Node { id: 0, name: String::new(), children: (0..2000).map(leaf).collect() };
+        for depth in 1..50 {
+            tree = Node { id: depth, name: "level".to_string(), children: vec![tree, leaf(depth)] };
+        }
+
+        let config = Config::new().with_tagged_fields(true);
+        let bytes = to_bytes_with_config(&tree, config).unwrap();
+        let decoded: Node = crate::from_bytes_with_config(&bytes, config).unwrap();
+        assert_eq!(decoded, tree);
+    }
+
+    #[test]
+    fn test_unknown_length_collections() {
+        use serde::ser::Serializer as _;
+
+        // Iterator adapters report no length up front
+        let mut ser = Serializer::new();
+        (&mut ser).collect_seq((1u32..=5).filter(|n| n % 2 == 1)).unwrap();
+        let bytes = ser.into_bytes();
+        assert_eq!(bytes, to_bytes(&vec![1u32, 3, 5]).unwrap());
+
+        let mut ser = Serializer::new();
+        (&mut ser)
+            .collect_map([("a", 1u8), ("b", 2u8)].into_iter().filter(|_| true))
+            .unwrap();
+        let bytes = ser.into_bytes();
+        let decoded: std::collections::BTreeMap<String, u8> = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded["b"], 2);
+
+        // Nested unknown lengths back-patch independently
+        let mut ser = Serializer::new();
+        (&mut ser)
+            .collect_seq((0..3u8).map(|n| (0..n).filter(|_| true).collect::<Vec<_>>()).filter(|_| true))
+            .unwrap();
+        let decoded: Vec<Vec<u8>> = crate::from_bytes(&ser.into_bytes()).unwrap();
+        assert_eq!(decoded, vec![vec![], vec![0], vec![0, 1]]);
+    }
+
     #[cfg(feature = "std")]
+    #[test]
+    fn test_canonical_output() {
+        use std::collections::HashMap;
+
+        let forward: HashMap<String, f64> = (0..50).map(|i| (format!("k{i}"), i as f64)).collect();
+        let backward: HashMap<String, f64> = (0..50).rev().map(|i| (format!("k{i}"), i as f64)).collect();
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let config = config.with_canonical(true);
+            let a = to_bytes_with_config(&vec![forward.clone()], config).unwrap();
+            let b = to_bytes_with_config(&vec![backward.clone()], config).unwrap();
+            assert_eq!(a, b);
+            assert_eq!(crate::from_bytes::<Vec<HashMap<String, f64>>>(&a).unwrap()[0], forward);
+        }
+
+        assert_eq!(to_bytes_canonical(&-0.0f64).unwrap(), to_bytes_canonical(&0.0f64).unwrap());
+        let nan = f32::from_bits(0x7fc0_1234);
+        assert_eq!(to_bytes_canonical(&nan).unwrap(), to_bytes_canonical(&f32::NAN).unwrap());
+        assert_ne!(to_bytes(&nan).unwrap(), to_bytes(&f32::NAN).unwrap());
+
+        struct Duplicates;
+        impl Serialize for Duplicates {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("a", &1)?;
+                map.serialize_entry("a", &2)?;
+                map.end()
+            }
+        }
+        assert!(to_bytes(&Duplicates).is_ok());
+        assert!(to_bytes_canonical(&Duplicates).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_string_table() {
+        use serde::Deserialize;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Event<'a> {
+            host: &'a str,
+            level: String,
+            seq: u32,
+        }
+
+        let hosts = ["db-1.internal", "db-2.internal", "cache.internal"];
+        let events: Vec<Event<'_>> = (0..300)
+            .map(|i| Event { host: hosts[i % 3], level: "warning".into(), seq: i as u32 })
+            .collect();
+
+        let inline = to_bytes(&events).unwrap();
+        for config in [Config::new(), Config::new().with_tagged_fields(true)] {
+            let bytes = to_bytes_with_config(&events, config.with_string_table(true)).unwrap();
+            assert!(bytes.len() < inline.len() / 2);
+            assert_eq!(crate::from_bytes::<Vec<Event<'_>>>(&bytes).unwrap(), events);
+        }
+
+        // Each value of a stream carries its own table
+        let config = Config::new().with_string_table(true);
+        let mut stream = crate::StreamSerializer::new(Vec::new(), config).unwrap();
+        for event in &events[..4] {
+            stream.serialize(event).unwrap();
+        }
+        let bytes = stream.into_inner().unwrap();
+        let read: Vec<Event<'_>> = crate::StreamDeserializer::new(&bytes).unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(read, &events[..4]);
+
+        // Not applied to self-describing output, refused when streaming
+        let sd = Config::new().with_self_describing(true);
+        assert_eq!(
+            to_bytes_with_config(&events, sd.with_string_table(true)).unwrap(),
+            to_bytes_with_config(&events, sd).unwrap()
+        );
+        assert!(Serializer::to_stream(&mut Vec::new(), config).is_err());
+    }
+
+    #[test]
+    fn test_fixed_tuples() {
+        use serde::Deserialize;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum Shape {
+            Quad([f32; 4]),
+            Segment((i16, i16), (i16, i16)),
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Block {
+            hash: [u8; 32],
+            shapes: Vec<Shape>,
+        }
+
+        let block = Block {
+            hash: [0xAB; 32],
+            shapes: vec![Shape::Quad([0.0, 0.5, 1.0, 1.5]), Shape::Segment((1, 2), (-3, -4))],
+        };
+        let config = Config::new().with_fixed_tuples(true);
+        let fixed = to_bytes_with_config(&block, config).unwrap();
+        // No lengths for the hash, the quad, the segment and its two points,
+        // less the two header bytes recording the mode
+        assert_eq!(to_bytes(&block).unwrap().len() - fixed.len(), 5 - 2);
+        assert_eq!(crate::from_bytes::<Block>(&fixed).unwrap(), block);
+
+        let tagged = to_bytes_with_config(&block, config.with_tagged_fields(true)).unwrap();
+        assert_eq!(crate::from_bytes::<Block>(&tagged).unwrap(), block);
+    }
+
+    #[test]
+    fn test_niche_options() {
+        use serde::Deserialize;
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Profile {
+            name: Option<String>,
+            avatar: Option<Vec<u8>>,
+            tags: Option<BTreeMap<String, u8>>,
+            nickname: Option<Option<String>>,
+            age: Option<u32>,
+        }
+
+        let config = Config::new().with_niche_options(true);
+        let profile = Profile {
+            name: Some("ada".to_string()),
+            avatar: Some(vec![1, 2]),
+            tags: Some(BTreeMap::from([("x".to_string(), 1)])),
+            nickname: Some(Some("a".to_string())),
+            age: Some(36),
+        };
+        let niche = to_bytes_with_config(&profile, config).unwrap();
+        // One tag each for name, avatar and tags, and the inner nickname,
+        // less the two header bytes recording the mode
+        assert_eq!(to_bytes(&profile).unwrap().len() - niche.len(), 4 - 2);
+        assert_eq!(crate::from_bytes::<Profile>(&niche).unwrap(), profile);
+
+        let empty = Profile { name: Some(String::new()), avatar: None, tags: Some(BTreeMap::new()), nickname: Some(None), age: None };
+        let bytes = to_bytes_with_config(&empty, config).unwrap();
+        assert_eq!(crate::from_bytes::<Profile>(&bytes).unwrap(), empty);
+
+        // Lengths written after the elements still take the tag
+        struct Odd;
+        impl Serialize for Odd {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+                serializer.collect_seq((0u32..40).filter(|n| n % 2 == 1))
+            }
+        }
+        let bytes = to_bytes_with_config(&Some(Odd), config).unwrap();
+        let odd: Vec<u32> = (0..40).filter(|n| n % 2 == 1).collect();
+        assert_eq!(bytes, to_bytes_with_config(&Some(&odd), config).unwrap());
+        assert_eq!(crate::from_bytes::<Option<Vec<u32>>>(&bytes).unwrap(), Some(odd));
+
+        // Streamed output matches, and self-describing output keeps its tags
+        let many: Vec<Option<String>> = (0..5000).map(|n| (n % 3 != 0).then(|| n.to_string())).collect();
+        let mut streamed = Vec::new();
+        let mut ser = Serializer::to_stream(&mut streamed, config).unwrap();
+        many.serialize(&mut ser).unwrap();
+        ser.finish().unwrap();
+        assert_eq!(streamed, to_bytes_with_config(&many, config).unwrap());
+        assert_eq!(crate::from_bytes::<Vec<Option<String>>>(&streamed).unwrap(), many);
+        let sd = config.with_self_describing(true);
+        assert_eq!(
+            to_bytes_with_config(&profile, sd).unwrap(),
+            to_bytes_with_config(&profile, Config::new().with_self_describing(true)).unwrap()
+        );
+    }
+
     #[test]
     fn test_writer_serialization() {
         let data = vec![1u32, 2, 3, 4, 5];
@@ -508,4 +1690,160 @@ mod tests {
         assert!(result.is_ok());
         assert!(!buffer.is_empty());
     }
+
+    #[test]
+    fn test_serialize_from_iter() {
+        let expected = to_bytes(&(0..100u32).collect::<Vec<_>>()).unwrap();
+
+        // Exact and unknown lengths produce the same bytes
+        assert_eq!(to_bytes_from_iter(0..100u32).unwrap(), expected);
+        assert_eq!(to_bytes_from_iter((0..200u32).filter(|i| i % 2 == 0).map(|i| i / 2)).unwrap(), expected);
+
+        #[cfg(feature = "std")]
+        {
+            let mut streamed = Vec::new();
+            to_writer_from_iter(&mut streamed, 0..100u32).unwrap();
+            assert_eq!(streamed, expected);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_streamed_output_matches_buffered() {
+        let items: Vec<String> = (0..20_000).map(|i| format!("value {i}")).collect();
+        let config = Config::new().with_tagged_fields(true);
+
+        for config in [Config::new(), config] {
+            let value = to_bytes_with_config(&items, config).unwrap();
+            let mut streamed = Vec::new();
+            let mut ser = Serializer::to_stream(&mut streamed, config).unwrap();
+            items.serialize(&mut ser).unwrap();
+            assert!(ser.buffer.len() < value.len());
+            ser.finish().unwrap();
+            assert_eq!(streamed, value);
+        }
+
+        #[cfg(feature = "checksum")]
+        {
+            let config = Config::new().with_checksum(Some(crate::Checksum::Crc32c));
+            assert!(Serializer::to_stream(&mut Vec::new(), config).is_err());
+        }
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Circle(f32),
+        Rect { w: u16, h: u16 },
+        Line(u8, u8),
+    }
+
+    #[derive(Serialize)]
+    struct Drawing {
+        title: String,
+        shapes: Vec<Shape>,
+        tags: BTreeMap<String, u32>,
+    }
+
+    #[test]
+    fn test_profile_attributes_bytes_by_path() {
+        let drawing = Drawing {
+            title: "sketch".to_string(),
+            shapes: vec![Shape::Circle(1.0), Shape::Rect { w: 3, h: 4 }, Shape::Line(1, 2), Shape::Circle(2.0)],
+            tags: BTreeMap::from([("a".to_string(), 1), ("bb".to_string(), 2)]),
+        };
+
+        for config in [Config::new(), Config::new().with_tagged_fields(true), Config::new().with_self_describing(true)] {
+            let report = profile_with_config(&drawing, config).unwrap();
+            assert_eq!(report.total(), to_bytes_with_config(&drawing, config).unwrap().len());
+
+            let value = &report.value;
+            let fields: usize = value.children.iter().map(|child| child.bytes).sum();
+            assert!(fields <= value.bytes);
+            assert_eq!(value.children.iter().map(|child| child.name.as_str()).collect::<Vec<_>>(), ["title", "shapes", "tags"]);
+
+            assert_eq!(report.get("shapes.[]").unwrap().count, 4);
+            assert_eq!(report.get("shapes.[].Circle").unwrap().count, 2);
+            assert_eq!(report.get("shapes.[].Rect.w").unwrap().count, 1);
+            assert_eq!(report.get("shapes.[].Line.1").unwrap().count, 1);
+            assert_eq!(report.get("tags.{key}").unwrap().count, 2);
+            assert!(report.get("tags.{value}").unwrap().bytes >= 2);
+            assert!(report.get("shapes.[].Square").is_none());
+        }
+    }
+
+    #[test]
+    fn test_deny_non_finite_floats_names_path() {
+        #[derive(Serialize)]
+        enum Leg {
+            Market(f32),
+            Limit { price: f64, size: u32 },
+        }
+
+        #[derive(Serialize)]
+        struct Order {
+            id: u32,
+            legs: Vec<Leg>,
+            bounds: (f64, f64),
+            fees: BTreeMap<String, f64>,
+        }
+
+        let order = Order {
+            id: 1,
+            legs: vec![Leg::Market(1.5), Leg::Limit { price: 2.0, size: 3 }],
+            bounds: (0.0, 1.0),
+            fees: BTreeMap::from([("a".to_string(), 0.5), ("b".to_string(), 0.25)]),
+        };
+        let deny = Config::new().with_deny_non_finite_floats(true);
+        assert_eq!(to_bytes_with_config(&order, deny).unwrap(), to_bytes(&order).unwrap());
+
+        let path_of = |order: &Order| match to_bytes_with_config(order, deny) {
+            Err(Error::NonFiniteFloat { path, .. }) => path,
+            other => panic!("expected a non-finite float error, got {other:?}"),
+        };
+        let mut bad = Order { legs: vec![Leg::Market(1.0), Leg::Limit { price: f64::NAN, size: 1 }], ..order };
+        assert_eq!(path_of(&bad), "legs.1.Limit.price");
+        bad.legs = vec![Leg::Market(f32::INFINITY)];
+        assert_eq!(path_of(&bad), "legs.0.Market");
+        bad.legs.clear();
+        bad.bounds = (0.0, f64::NEG_INFINITY);
+        assert_eq!(path_of(&bad), "bounds.1");
+        bad.bounds = (0.0, 0.0);
+        bad.fees.insert("b".to_string(), f64::NAN);
+        assert_eq!(path_of(&bad), "fees.1");
+
+        for config in [deny.with_tagged_fields(true), deny.with_self_describing(true)] {
+            assert!(matches!(to_bytes_with_config(&bad, config), Err(Error::NonFiniteFloat { .. })));
+        }
+        let error = to_bytes_with_config(&f32::NAN, deny).unwrap_err();
+        assert_eq!(error.to_string(), "Non-finite float NaN");
+        assert!(to_bytes(&f64::NAN).is_ok());
+    }
+
+    #[test]
+    fn test_profile_report_splits_container() {
+        let report = profile(&"hello".to_string()).unwrap();
+        assert_eq!(report.header + report.value.bytes, to_bytes(&"hello").unwrap().len());
+        assert_eq!((report.strings, report.checksum), (0, 0));
+        assert!(report.value.children.is_empty());
+
+        let interned = Config::new().with_string_table(true);
+        let names = vec!["same"; 10];
+        let report = profile_with_config(&names, interned).unwrap();
+        assert!(report.strings > 0);
+        assert_eq!(report.total(), to_bytes_with_config(&names, interned).unwrap().len());
+
+        #[cfg(feature = "checksum")]
+        {
+            let config = Config::new().with_checksum(Some(crate::Checksum::Crc32c));
+            let report = profile_with_config(&7u8, config).unwrap();
+            assert_eq!(report.checksum, 4);
+        }
+
+        let text = report.to_string();
+        assert!(text.starts_with("payload"));
+        assert!(text.contains("├─ header"));
+        assert!(text.contains("string table"));
+        assert!(text.contains("│  └─ []") || text.contains("   └─ []"));
+        assert!(text.contains("x10"));
+    }
 }
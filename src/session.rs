@@ -0,0 +1,325 @@
+//! Message codecs for long-lived connections
+//!
+//! A [`SessionEncoder`] and [`SessionDecoder`] pair share a dictionary of
+//! strings across messages: a string is sent once, with the first message
+//! that uses it, and later messages refer to it by index. Each message is
+//!
+//! ```text
+//! [flags: u8] [first new index: varint] [new string count: varint]
+//! [new strings: length + bytes ...] [body]
+//! ```
+//!
+//! where flag `0x01` tells the decoder to drop its dictionary first, and
+//! `0x02`, `0x04`, `0x08` and `0x10` mark bodies with tagged fields,
+//! compact chars, fixed tuples and niche options. Messages must be decoded in the order
+//! they were encoded; any framing that preserves order will do. A decoder
+//! that misses a message reports the gap instead of misreading strings.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::{String, ToString}, vec::Vec};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+use crate::config::Config;
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::ser::{Serializer, StringTable};
+
+/// Dictionary size at which a session starts over, unless configured
+pub const DEFAULT_MAX_STRINGS: usize = 65_536;
+
+const FLAG_RESET: u8 = 0x01;
+const FLAG_TAGGED_FIELDS: u8 = 0x02;
+const FLAG_COMPACT_CHARS: u8 = 0x04;
+const FLAG_FIXED_TUPLES: u8 = 0x08;
+const FLAG_NICHE_OPTIONS: u8 = 0x10;
+const FLAGS_KNOWN: u8 =
+    FLAG_RESET | FLAG_TAGGED_FIELDS | FLAG_COMPACT_CHARS | FLAG_FIXED_TUPLES | FLAG_NICHE_OPTIONS;
+
+/// Encodes values into messages that share a string dictionary
+///
+/// ```rust
+/// use nanobit::{SessionDecoder, SessionEncoder};
+///
+/// let mut encoder = SessionEncoder::new();
+/// let mut decoder = SessionDecoder::new();
+///
+/// let first = encoder.encode(&("eu-west-1", "checkout-service", 42u32))?;
+/// let second = encoder.encode(&("eu-west-1", "checkout-service", 43u32))?;
+/// assert!(second.len() < first.len() / 3);
+///
+/// assert_eq!(decoder.decode::<(String, String, u32)>(&first)?.2, 42);
+/// assert_eq!(decoder.decode::<(String, String, u32)>(&second)?.1, "checkout-service");
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub struct SessionEncoder {
+    config: Config,
+    strings: StringTable,
+    // Strings and encoded bytes of the dictionary already sent
+    sent: usize,
+    sent_bytes: usize,
+    max_strings: usize,
+    reset_pending: bool,
+    scratch: Option<WriteBuffer>,
+}
+
+impl SessionEncoder {
+    /// Create an encoder for plain message bodies
+    pub fn new() -> Self {
+        Self {
+            config: Config::new(),
+            strings: StringTable::default(),
+            sent: 0,
+            sent_bytes: 0,
+            max_strings: DEFAULT_MAX_STRINGS,
+            reset_pending: false,
+            scratch: None,
+        }
+    }
+
+    /// Create an encoder using the given options
+    ///
+    /// Tagged fields are supported; self-describing bodies are not, since
+    /// they keep their strings inline.
+    pub fn with_config(config: Config) -> Result<Self> {
+        if config.self_describing() {
+            return Err(Error::InvalidFormat(
+                "Session messages cannot be self-describing".to_string(),
+            ));
+        }
+        Ok(Self { config, ..Self::new() })
+    }
+
+    /// Limit the dictionary to this many strings, starting over when full
+    pub fn with_max_strings(mut self, max_strings: usize) -> Self {
+        self.max_strings = max_strings;
+        self
+    }
+
+    /// Number of strings in the dictionary
+    #[inline]
+    pub fn dictionary_len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Drop the dictionary; the next message tells the decoder to do the same
+    pub fn reset(&mut self) {
+        self.strings = StringTable::default();
+        self.sent = 0;
+        self.sent_bytes = 0;
+        self.reset_pending = true;
+    }
+
+    /// Encode a value as the next message of the session
+    ///
+    /// A value that would grow the dictionary past its limit is encoded
+    /// again after a reset; one that needs more strings than the limit on
+    /// its own is rejected.
+    pub fn encode<T>(&mut self, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut body = self.serialize_body(value)?;
+        if self.strings.len() > self.max_strings && self.sent > 0 {
+            self.scratch = Some(body);
+            self.reset();
+            body = self.serialize_body(value)?;
+        }
+        if self.strings.len() > self.max_strings {
+            self.scratch = Some(body);
+            self.reset();
+            return Err(Error::InvalidFormat(
+                "Value uses more distinct strings than the session allows".to_string(),
+            ));
+        }
+
+        let new_strings = &self.strings.encoded()[self.sent_bytes..];
+        let mut message = WriteBuffer::with_capacity(new_strings.len() + body.len() + 8);
+        let mut flags = 0;
+        if self.reset_pending {
+            flags |= FLAG_RESET;
+        }
+        if self.config.tagged_fields() {
+            flags |= FLAG_TAGGED_FIELDS;
+        }
+        if self.config.compact_chars() {
+            flags |= FLAG_COMPACT_CHARS;
+        }
+        if self.config.fixed_tuples() {
+            flags |= FLAG_FIXED_TUPLES;
+        }
+        if self.config.niche_options() {
+            flags |= FLAG_NICHE_OPTIONS;
+        }
+        message.write_u8(flags)?;
+        message.write_varint(self.sent as u64)?;
+        message.write_varint((self.strings.len() - self.sent) as u64)?;
+        message.write_bytes(new_strings)?;
+        message.write_bytes(body.as_slice())?;
+
+        self.sent = self.strings.len();
+        self.sent_bytes = self.strings.encoded().len();
+        self.reset_pending = false;
+        self.scratch = Some(body);
+        Ok(message.into_vec())
+    }
+
+    // Serialize a value against the dictionary, adding any new strings
+    fn serialize_body<T>(&mut self, value: &T) -> Result<WriteBuffer>
+    where
+        T: Serialize + ?Sized,
+    {
+        let strings = core::mem::take(&mut self.strings);
+        let scratch = self.scratch.take().unwrap_or_default();
+        let mut serializer = Serializer::with_string_table(scratch, self.config, strings);
+        let result = value.serialize(&mut serializer);
+        let (body, strings) = serializer.into_parts();
+        // Strings added by a failed value are sent with the next message
+        self.strings = strings.expect("session serializer has a string table");
+        match result {
+            Ok(()) => Ok(body),
+            Err(e) => {
+                self.scratch = Some(body);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Default for SessionEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decodes messages produced by a [`SessionEncoder`]
+#[derive(Debug)]
+pub struct SessionDecoder {
+    dictionary: Vec<String>,
+    max_strings: usize,
+}
+
+impl SessionDecoder {
+    /// Create a decoder with an empty dictionary
+    pub fn new() -> Self {
+        Self {
+            dictionary: Vec::new(),
+            max_strings: DEFAULT_MAX_STRINGS,
+        }
+    }
+
+    /// Reject messages that grow the dictionary past this many strings
+    ///
+    /// Must be at least the encoder's limit.
+    pub fn with_max_strings(mut self, max_strings: usize) -> Self {
+        self.max_strings = max_strings;
+        self
+    }
+
+    /// Number of strings in the dictionary
+    #[inline]
+    pub fn dictionary_len(&self) -> usize {
+        self.dictionary.len()
+    }
+
+    /// Decode the next message of the session
+    pub fn decode<T>(&mut self, message: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let mut reader = ReadBuffer::new(message);
+        let flags = reader.read_u8()?;
+        if flags & !FLAGS_KNOWN != 0 {
+            return Err(Error::InvalidFormat(format!("Unsupported session flags: {flags:#04x}")));
+        }
+        if flags & FLAG_RESET != 0 {
+            self.dictionary.clear();
+        }
+
+        let first = reader.read_varint()?;
+        if first != self.dictionary.len() as u64 {
+            return Err(Error::InvalidFormat(format!(
+                "Session dictionary out of sync: message adds strings from {first}, decoder has {}",
+                self.dictionary.len()
+            )));
+        }
+        let count = reader.read_varint()?;
+        if count.saturating_add(self.dictionary.len() as u64) > self.max_strings as u64 {
+            return Err(Error::InvalidFormat("Session dictionary too large".to_string()));
+        }
+        for _ in 0..count {
+            self.dictionary.push(reader.read_str()?.to_string());
+        }
+
+        let config = Config::new()
+            .with_tagged_fields(flags & FLAG_TAGGED_FIELDS != 0)
+            .with_compact_chars(flags & FLAG_COMPACT_CHARS != 0)
+            .with_fixed_tuples(flags & FLAG_FIXED_TUPLES != 0)
+            .with_niche_options(flags & FLAG_NICHE_OPTIONS != 0);
+        let strings = self.dictionary.iter().map(String::as_str).collect();
+        let body = &message[reader.position()..];
+        T::deserialize(&mut Deserializer::with_string_table(body, config, strings))
+    }
+}
+
+impl Default for SessionDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Quote {
+        symbol: String,
+        venue: String,
+        price: u64,
+    }
+
+    fn quote(i: u64) -> Quote {
+        Quote {
+            symbol: ["AAPL", "MSFT", "NVDA"][i as usize % 3].to_string(),
+            venue: "XNAS".to_string(),
+            price: 100 + i,
+        }
+    }
+
+    #[test]
+    fn test_session_roundtrip_and_reset() {
+        let config = Config::new().with_tagged_fields(true).with_fixed_tuples(true).with_niche_options(true);
+        let mut encoder = SessionEncoder::with_config(config).unwrap().with_max_strings(3);
+        let mut decoder = SessionDecoder::new().with_max_strings(3);
+
+        let mut sizes = Vec::new();
+        for i in 0..10 {
+            let message = encoder.encode(&quote(i)).unwrap();
+            sizes.push(message.len());
+            assert_eq!(decoder.decode::<Quote>(&message).unwrap(), quote(i));
+        }
+        // Later messages only carry indices; the limit forced one reset
+        assert!(sizes[5] < sizes[0]);
+        assert_eq!(decoder.dictionary_len(), encoder.dictionary_len());
+
+        encoder.reset();
+        let message = encoder.encode(&quote(1)).unwrap();
+        assert_eq!(decoder.decode::<Quote>(&message).unwrap(), quote(1));
+        assert_eq!(decoder.dictionary_len(), 2);
+    }
+
+    #[test]
+    fn test_session_detects_missed_message() {
+        let mut encoder = SessionEncoder::new();
+        let mut decoder = SessionDecoder::new();
+
+        let _missed = encoder.encode(&quote(0)).unwrap();
+        let second = encoder.encode(&quote(1)).unwrap();
+        assert!(decoder.decode::<Quote>(&second).is_err());
+        assert!(SessionEncoder::with_config(Config::new().with_self_describing(true)).is_err());
+    }
+}
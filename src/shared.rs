@@ -0,0 +1,204 @@
+//! Zero-copy integration with the `bytes` crate
+//!
+//! Payloads can be produced as [`Bytes`] without copying, and decoded from a
+//! [`Bytes`] frame with [`from_shared_bytes`]. Fields marked
+//! `#[serde(with = "nanobit::shared")]` then come out as refcounted slices of
+//! that frame instead of fresh allocations:
+//!
+//! ```rust
+//! use bytes::Bytes;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Frame {
+//!     id: u32,
+//!     #[serde(with = "nanobit::shared")]
+//!     body: Bytes,
+//! }
+//!
+//! # fn main() -> nanobit::Result<()> {
+//! let frame = Frame { id: 1, body: Bytes::from_static(b"payload") };
+//! let encoded: Bytes = nanobit::to_bytes_shared(&frame)?;
+//!
+//! let decoded: Frame = nanobit::from_shared_bytes(&encoded)?;
+//! assert_eq!(decoded.body, "payload");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cell::RefCell;
+
+use bytes::{Bytes, BytesMut};
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::ser::Serializer;
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+use crate::error::Result;
+
+std::thread_local! {
+    // Frame being decoded by `from_shared_bytes`, which `deserialize` slices
+    static SOURCE: RefCell<Option<Bytes>> = const { RefCell::new(None) };
+}
+
+/// Serialize a value straight into a [`Bytes`] without copying
+pub fn to_bytes_shared<T>(value: &T) -> Result<Bytes>
+where
+    T: serde::Serialize,
+{
+    crate::to_bytes(value).map(Bytes::from)
+}
+
+/// Deserialize a value from a shared frame
+///
+/// Byte fields using `#[serde(with = "nanobit::shared")]` borrow from `bytes`
+/// by reference count instead of being copied.
+pub fn from_shared_bytes<'a, T>(bytes: &'a Bytes) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let previous = SOURCE.with(|source| source.replace(Some(bytes.clone())));
+    let result = crate::from_bytes(bytes);
+    SOURCE.with(|source| *source.borrow_mut() = previous);
+    result
+}
+
+/// Serialize a [`Bytes`] field as a byte string
+pub fn serialize<S>(bytes: &Bytes, serializer: S) -> core::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_bytes(bytes)
+}
+
+/// Deserialize a [`Bytes`] field, sharing the frame passed to
+/// [`from_shared_bytes`] when possible and copying otherwise
+pub fn deserialize<'de, D>(deserializer: D) -> core::result::Result<Bytes, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_bytes(BytesVisitor)
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Bytes;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        formatter.write_str("a byte string")
+    }
+
+    fn visit_borrowed_bytes<E: serde::de::Error>(self, v: &'de [u8]) -> core::result::Result<Bytes, E> {
+        Ok(SOURCE.with(|source| match source.borrow().as_ref() {
+            Some(frame) if contains(frame, v) => frame.slice_ref(v),
+            _ => Bytes::copy_from_slice(v),
+        }))
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> core::result::Result<Bytes, E> {
+        Ok(Bytes::copy_from_slice(v))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> core::result::Result<Bytes, E> {
+        Ok(Bytes::from(v))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Bytes, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut out = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            out.push(byte);
+        }
+        Ok(Bytes::from(out))
+    }
+}
+
+fn contains(frame: &Bytes, slice: &[u8]) -> bool {
+    let start = frame.as_ptr() as usize;
+    let ptr = slice.as_ptr() as usize;
+    ptr >= start && ptr + slice.len() <= start + frame.len()
+}
+
+impl WriteBuffer {
+    /// Create a write buffer that reuses the allocation of a [`BytesMut`]
+    ///
+    /// Any existing contents are discarded.
+    pub fn from_bytes_mut(bytes: BytesMut) -> Self {
+        let mut data = Vec::from(bytes);
+        data.clear();
+        Self::from_vec(data)
+    }
+
+    /// Convert the buffer into [`Bytes`] without copying
+    pub fn into_bytes(self) -> Bytes {
+        Bytes::from(self.into_vec())
+    }
+}
+
+impl bytes::Buf for ReadBuffer<'_> {
+    #[inline]
+    fn remaining(&self) -> usize {
+        ReadBuffer::remaining(self)
+    }
+
+    #[inline]
+    fn chunk(&self) -> &[u8] {
+        &self.as_slice()[self.position()..]
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= ReadBuffer::remaining(self), "advance past end of ReadBuffer");
+        self.skip(cnt).expect("bounds checked above");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Frame {
+        id: u32,
+        #[serde(with = "crate::shared")]
+        body: Bytes,
+    }
+
+    #[test]
+    fn test_shared_fields() {
+        let frame = Frame { id: 3, body: Bytes::from(vec![7u8; 64]) };
+        let encoded = to_bytes_shared(&frame).unwrap();
+
+        let decoded: Frame = from_shared_bytes(&encoded).unwrap();
+        assert_eq!(decoded, frame);
+        // The field points into the frame rather than a copy
+        assert!(contains(&encoded, &decoded.body));
+
+        // Plain decoding still works, copying the field
+        let copied: Frame = crate::from_bytes(&encoded).unwrap();
+        assert!(!contains(&encoded, &copied.body));
+        assert_eq!(copied, frame);
+    }
+
+    #[test]
+    fn test_read_buffer_buf() {
+        use bytes::Buf;
+
+        let data = [1u8, 0, 2, 0, 0, 0, 9];
+        let mut reader = ReadBuffer::new(&data);
+        assert_eq!(reader.get_u16_le(), 1);
+        assert_eq!(reader.get_u32_le(), 2);
+        assert_eq!(Buf::remaining(&reader), 1);
+        assert_eq!(reader.read_u8().unwrap(), 9);
+    }
+
+    #[test]
+    fn test_write_buffer_bytes() {
+        let mut buffer = WriteBuffer::from_bytes_mut(BytesMut::from(&b"stale"[..]));
+        assert!(buffer.is_empty());
+        buffer.write_str("hi").unwrap();
+        assert_eq!(buffer.into_bytes(), Bytes::from_static(&[2, b'h', b'i']));
+    }
+}
@@ -0,0 +1,315 @@
+//! Event-sourced state with snapshots and log replay
+//!
+//! Event-sourced state is rebuilt by applying every event since the
+//! beginning, which gets slower as the history grows. A [`SnapshotLog`]
+//! keeps a snapshot of the state next to a [log](crate::log) of the
+//! events applied since, so opening it loads the snapshot and replays only
+//! the tail. [`compact`](SnapshotLog::compact) folds the tail into a new
+//! snapshot and starts an empty log:
+//!
+//! ```rust
+//! use nanobit::snapshot::{EventSourced, SnapshotLog};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Default)]
+//! struct Balance {
+//!     cents: i64,
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! enum Entry {
+//!     Deposit(i64),
+//!     Withdraw(i64),
+//! }
+//!
+//! impl EventSourced for Balance {
+//!     type Event = Entry;
+//!
+//!     fn apply(&mut self, event: &Entry) {
+//!         match event {
+//!             Entry::Deposit(cents) => self.cents += cents,
+//!             Entry::Withdraw(cents) => self.cents -= cents,
+//!         }
+//!     }
+//! }
+//!
+//! # let dir = std::env::temp_dir().join(format!("nanobit-doc-snapshot-{}", std::process::id()));
+//! let mut account = SnapshotLog::<Balance>::open(&dir)?;
+//! account.append(Entry::Deposit(500))?;
+//! account.append(Entry::Withdraw(120))?;
+//! account.compact()?;
+//! account.append(Entry::Deposit(1))?;
+//! drop(account);
+//!
+//! // Loads the snapshot and replays the one event after it
+//! let account = SnapshotLog::<Balance>::open(&dir)?;
+//! assert_eq!(account.state().cents, 381);
+//! assert_eq!(account.tail_len(), 1);
+//! # std::fs::remove_dir_all(&dir).ok();
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! # Files
+//!
+//! The directory holds `snapshot.nb`, saved with [`fs::save`](crate::fs::save),
+//! and one log per generation, `events.<generation>.log`. The snapshot
+//! records the generation of the log that follows it. Compaction creates
+//! the next log, then replaces the snapshot by renaming over it, and only
+//! then deletes the old log, so the rename decides atomically whether a
+//! crash leaves the old snapshot with the old log or the new snapshot
+//! with the new one. Opening deletes logs of other generations and cuts
+//! off a record torn by a crash.
+
+use std::fs::OpenOptions;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::fs::{self as nanobit_fs, SaveOptions};
+use crate::log::{LogReader, LogWriter, SyncPolicy};
+
+const SNAPSHOT_FILE: &str = "snapshot.nb";
+
+/// State rebuilt by applying events in order
+pub trait EventSourced: Serialize + DeserializeOwned + Default {
+    /// The events appended to the log
+    type Event: Serialize + DeserializeOwned;
+
+    /// Apply one event to the state
+    fn apply(&mut self, event: &Self::Event);
+}
+
+/// A snapshot of event-sourced state plus the log of events since
+///
+/// See the [module documentation](self).
+pub struct SnapshotLog<S: EventSourced> {
+    dir: PathBuf,
+    state: S,
+    generation: u64,
+    log: LogWriter<std::fs::File>,
+    tail_len: u64,
+    compact_after: Option<u64>,
+    _marker: PhantomData<fn(S::Event)>,
+}
+
+impl<S: EventSourced> SnapshotLog<S> {
+    /// Open the state stored in `dir`, creating the directory if needed
+    ///
+    /// Starts from `S::default()` when there is no snapshot yet.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        let (generation, mut state) = match nanobit_fs::load::<_, (u64, S)>(dir.join(SNAPSHOT_FILE)) {
+            Ok(snapshot) => snapshot,
+            Err(Error::FileNotFound(_)) => (0, S::default()),
+            Err(error) => return Err(error),
+        };
+
+        let path = log_path(&dir, generation);
+        let mut tail_len = 0;
+        if path.exists() {
+            let mut reader = LogReader::open(&path)?;
+            while let Some(event) = reader.next_record::<S::Event>()? {
+                state.apply(&event);
+                tail_len += 1;
+            }
+            if reader.torn_tail() {
+                OpenOptions::new().write(true).open(&path)?.set_len(reader.position())?;
+            }
+        }
+        remove_stale_logs(&dir, generation)?;
+
+        Ok(Self {
+            log: LogWriter::open(&path)?,
+            dir,
+            state,
+            generation,
+            tail_len,
+            compact_after: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Set when appended events are synced to disk
+    pub fn with_sync_policy(mut self, policy: SyncPolicy) -> Self {
+        self.log = self.log.with_sync_policy(policy);
+        self
+    }
+
+    /// Compact automatically once the log holds `events` events
+    pub fn with_compact_after(mut self, events: u64) -> Self {
+        self.compact_after = Some(events.max(1));
+        self
+    }
+
+    /// The current state
+    #[inline]
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Number of events logged since the last snapshot
+    #[inline]
+    pub fn tail_len(&self) -> u64 {
+        self.tail_len
+    }
+
+    /// Log an event and apply it to the state
+    pub fn append(&mut self, event: S::Event) -> Result<()> {
+        self.log.append(&event)?;
+        self.state.apply(&event);
+        self.tail_len += 1;
+        if self.compact_after.is_some_and(|limit| self.tail_len >= limit) {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Write a snapshot of the current state and start an empty log
+    pub fn compact(&mut self) -> Result<()> {
+        self.log.sync()?;
+        let generation = self.generation + 1;
+        let path = log_path(&self.dir, generation);
+        let log = LogWriter::open(&path)?.with_sync_policy(self.log.sync_policy());
+        // The rename inside `save` is the commit point
+        nanobit_fs::save(self.dir.join(SNAPSHOT_FILE), &(generation, &self.state), SaveOptions::new())?;
+
+        let old = log_path(&self.dir, self.generation);
+        self.log = log;
+        self.generation = generation;
+        self.tail_len = 0;
+        std::fs::remove_file(old)?;
+        Ok(())
+    }
+
+    /// Sync the log and return the state
+    pub fn into_state(mut self) -> Result<S> {
+        self.log.sync()?;
+        Ok(self.state)
+    }
+}
+
+fn log_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("events.{generation}.log"))
+}
+
+// Delete logs left behind by an interrupted compaction
+fn remove_stale_logs(dir: &Path, generation: u64) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let stale = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("events.")?.strip_suffix(".log")?.parse::<u64>().ok())
+            .is_some_and(|found| found != generation);
+        if stale {
+            std::fs::remove_file(dir.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    struct Inventory {
+        stock: BTreeMap<String, u32>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Change {
+        Add(String, u32),
+        Remove(String),
+    }
+
+    impl EventSourced for Inventory {
+        type Event = Change;
+
+        fn apply(&mut self, event: &Change) {
+            match event {
+                Change::Add(item, count) => *self.stock.entry(item.clone()).or_default() += count,
+                Change::Remove(item) => {
+                    self.stock.remove(item);
+                }
+            }
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nanobit-snapshot-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    fn logs(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".log"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_snapshot_replay() {
+        let dir = temp_dir("replay");
+        let mut store = SnapshotLog::<Inventory>::open(&dir).unwrap().with_compact_after(3);
+        store.append(Change::Add("bolt".into(), 10)).unwrap();
+        store.append(Change::Add("nut".into(), 4)).unwrap();
+        store.append(Change::Add("bolt".into(), 1)).unwrap();
+        assert_eq!(store.tail_len(), 0);
+        store.append(Change::Remove("nut".into())).unwrap();
+        let expected = store.into_state().unwrap();
+        assert_eq!(logs(&dir), ["events.1.log"]);
+
+        let store = SnapshotLog::<Inventory>::open(&dir).unwrap();
+        assert_eq!(store.tail_len(), 1);
+        assert_eq!(store.state(), &expected);
+        assert_eq!(expected.stock.get("bolt"), Some(&11));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_recovery() {
+        let dir = temp_dir("recovery");
+        let mut store = SnapshotLog::<Inventory>::open(&dir).unwrap();
+        store.append(Change::Add("gear".into(), 2)).unwrap();
+        store.append(Change::Add("gear".into(), 3)).unwrap();
+        drop(store);
+
+        // A crash mid-append leaves half a record, which is cut off
+        let log = log_path(&dir, 0);
+        let len = std::fs::metadata(&log).unwrap().len();
+        OpenOptions::new().write(true).open(&log).unwrap().set_len(len - 2).unwrap();
+        let mut store = SnapshotLog::<Inventory>::open(&dir).unwrap();
+        assert_eq!(store.state().stock.get("gear"), Some(&2));
+        store.append(Change::Add("gear".into(), 5)).unwrap();
+        drop(store);
+        assert_eq!(SnapshotLog::<Inventory>::open(&dir).unwrap().state().stock.get("gear"), Some(&7));
+
+        // A crash before the snapshot rename leaves a stray next log
+        std::fs::write(log_path(&dir, 1), b"").unwrap();
+        let store = SnapshotLog::<Inventory>::open(&dir).unwrap();
+        assert_eq!(store.state().stock.get("gear"), Some(&7));
+        assert_eq!(logs(&dir), ["events.0.log"]);
+        drop(store);
+
+        // A crash after it leaves the old log behind
+        let mut store = SnapshotLog::<Inventory>::open(&dir).unwrap();
+        store.compact().unwrap();
+        drop(store);
+        std::fs::write(log_path(&dir, 0), b"stale").unwrap();
+        let store = SnapshotLog::<Inventory>::open(&dir).unwrap();
+        assert_eq!(store.state().stock.get("gear"), Some(&7));
+        assert_eq!(store.tail_len(), 0);
+        assert_eq!(logs(&dir), ["events.1.log"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+//! Streams of many values written back to back
+//!
+//! A stream is a single header followed by any number of values, each
+//! encoded as it would be in the body of its own payload. Values may have
+//! different types and sizes; readers decode them one at a time.
+
+#[cfg(not(feature = "std"))]
+use alloc::string::ToString;
+
+use core::marker::PhantomData;
+
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::de::Deserializer;
+use crate::error::Result;
+use crate::header::Header;
+
+#[cfg(feature = "std")]
+use crate::{buffer::WriteBuffer, error::Error, ser::Serializer};
+
+/// Writes a stream of values to a writer
+///
+/// ```rust
+/// use nanobit::stream::{StreamDeserializer, StreamSerializer};
+///
+/// # fn main() -> nanobit::Result<()> {
+/// let mut stream = StreamSerializer::new(Vec::new(), nanobit::Config::new())?;
+/// for i in 0..3u32 {
+///     stream.serialize(&i)?;
+/// }
+/// let bytes = stream.into_inner()?;
+///
+/// let values: Vec<u32> = StreamDeserializer::new(&bytes)?.collect::<nanobit::Result<_>>()?;
+/// assert_eq!(values, [0, 1, 2]);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "std")]
+pub struct StreamSerializer<W: std::io::Write> {
+    writer: W,
+    config: Config,
+    scratch: Option<WriteBuffer>,
+    count: usize,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> StreamSerializer<W> {
+    /// Start a stream, writing its header to `writer`
+    ///
+    /// Checksums cannot be streamed.
+    pub fn new(mut writer: W, config: Config) -> Result<Self> {
+        if config.checksum().is_some() {
+            return Err(Error::InvalidFormat(
+                "Checksums are not supported by stream serializers".to_string(),
+            ));
+        }
+        let mut header = Vec::with_capacity(6);
+        config.header().write_to(&mut header);
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            config,
+            scratch: Some(WriteBuffer::new()),
+            count: 0,
+        })
+    }
+
+    /// Append a value to the stream
+    pub fn serialize<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: serde::Serialize + ?Sized,
+    {
+        let scratch = self.scratch.take().unwrap_or_default();
+        let mut serializer = Serializer::with_buffer(scratch, self.config);
+        let result = value.serialize(&mut serializer);
+        let buffer = serializer.into_buffer();
+        if result.is_ok() {
+            self.writer.write_all(buffer.as_slice())?;
+            self.count += 1;
+        }
+        self.scratch = Some(buffer);
+        result
+    }
+
+    /// Number of values written
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Check if no values have been written
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Flush and return the inner writer
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Iterates over the values of a stream
+///
+/// Yields `Result<T>` for each value and stops after the first error.
+/// [`byte_offset`](Self::byte_offset) reports where decoding stopped.
+pub struct StreamDeserializer<'de, T> {
+    de: Deserializer<'de>,
+    body_start: usize,
+    value_start: usize,
+    failed: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T> StreamDeserializer<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    /// Read a stream from bytes
+    pub fn new(data: &'de [u8]) -> Result<Self> {
+        Self::with_config(data, Config::new())
+    }
+
+    /// Read a stream from bytes using the given decoding options
+    pub fn with_config(data: &'de [u8], config: Config) -> Result<Self> {
+        let (_, body_start) = Header::parse(data)?;
+        Ok(Self {
+            de: Deserializer::with_config(data, config)?,
+            body_start,
+            value_start: 0,
+            failed: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Byte offset in the input after the last value successfully read
+    ///
+    /// After an error this is where the failing value starts.
+    pub fn byte_offset(&self) -> usize {
+        self.body_start + self.value_start
+    }
+}
+
+impl<'de, T> Iterator for StreamDeserializer<'de, T>
+where
+    T: Deserialize<'de>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.de.is_end() {
+            return None;
+        }
+        match self.de.begin_root().and_then(|()| T::deserialize(&mut self.de)) {
+            Ok(value) => {
+                self.value_start = self.de.position();
+                Some(Ok(value))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Login { user: String },
+        Click(u32, u32),
+        Logout,
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let events = vec![
+            Event::Login { user: "ann".to_string() },
+            Event::Click(3, 4),
+            Event::Logout,
+        ];
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            let mut stream = StreamSerializer::new(Vec::new(), config).unwrap();
+            for event in &events {
+                stream.serialize(event).unwrap();
+            }
+            assert_eq!(stream.len(), 3);
+            let bytes = stream.into_inner().unwrap();
+
+            let read: Vec<Event> = StreamDeserializer::new(&bytes).unwrap().collect::<Result<_>>().unwrap();
+            assert_eq!(read, events);
+        }
+    }
+
+    #[test]
+    fn test_stream_error_position() {
+        let mut stream = StreamSerializer::new(Vec::new(), Config::new()).unwrap();
+        stream.serialize("first").unwrap();
+        stream.serialize("second").unwrap();
+        let mut bytes = stream.into_inner().unwrap();
+        let second = 5 + 6;
+        bytes.truncate(bytes.len() - 2);
+
+        let mut values = StreamDeserializer::<&str>::new(&bytes).unwrap();
+        assert_eq!(values.next().unwrap().unwrap(), "first");
+        assert_eq!(values.byte_offset(), second);
+        assert!(values.next().unwrap().is_err());
+        assert_eq!(values.byte_offset(), second);
+        assert!(values.next().is_none());
+    }
+
+    #[test]
+    fn test_stream_lenient_fields() {
+        #[derive(Serialize)]
+        struct V2 {
+            a: u32,
+            b: u32,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct V1 {
+            a: u32,
+        }
+
+        let lenient = Config::new().with_lenient_field_count(true);
+        let mut stream = StreamSerializer::new(Vec::new(), Config::new()).unwrap();
+        for a in 0..3 {
+            stream.serialize(&V2 { a, b: 7 }).unwrap();
+        }
+        let bytes = stream.into_inner().unwrap();
+
+        // Extra fields cannot be skipped without swallowing the values after them
+        let mut values = StreamDeserializer::<V1>::with_config(&bytes, lenient).unwrap();
+        assert!(values.next().unwrap().is_err());
+        assert!(values.next().is_none());
+
+        let tagged = Config::new().with_tagged_fields(true);
+        let mut stream = StreamSerializer::new(Vec::new(), tagged).unwrap();
+        for a in 0..3 {
+            stream.serialize(&V2 { a, b: 7 }).unwrap();
+        }
+        let bytes = stream.into_inner().unwrap();
+        let values: Vec<V1> = StreamDeserializer::with_config(&bytes, tagged.with_lenient_field_count(true))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(values, [V1 { a: 0 }, V1 { a: 1 }, V1 { a: 2 }]);
+    }
+}
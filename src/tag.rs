@@ -0,0 +1,76 @@
+//! Type tags written before every value in self-describing mode
+
+/// Unit, unit structs and `Value::Null`
+pub(crate) const UNIT: u8 = 0x00;
+/// `Option::None`
+pub(crate) const NONE: u8 = 0x01;
+/// `Option::Some`, followed by the value
+pub(crate) const SOME: u8 = 0x02;
+/// Boolean `false`
+pub(crate) const FALSE: u8 = 0x03;
+/// Boolean `true`
+pub(crate) const TRUE: u8 = 0x04;
+/// Little-endian signed integers
+pub(crate) const I8: u8 = 0x05;
+pub(crate) const I16: u8 = 0x06;
+pub(crate) const I32: u8 = 0x07;
+pub(crate) const I64: u8 = 0x08;
+/// Little-endian unsigned integers
+pub(crate) const U8: u8 = 0x09;
+pub(crate) const U16: u8 = 0x0A;
+pub(crate) const U32: u8 = 0x0B;
+pub(crate) const U64: u8 = 0x0C;
+/// IEEE 754 floats
+pub(crate) const F32: u8 = 0x0D;
+pub(crate) const F64: u8 = 0x0E;
+/// Unicode scalar value as a u32
+pub(crate) const CHAR: u8 = 0x0F;
+/// Length-prefixed UTF-8 string
+pub(crate) const STR: u8 = 0x10;
+/// Length-prefixed byte string
+pub(crate) const BYTES: u8 = 0x11;
+/// Varint length followed by that many values (sequences and tuples)
+pub(crate) const SEQ: u8 = 0x12;
+/// Varint length followed by key/value pairs (maps and structs)
+pub(crate) const MAP: u8 = 0x13;
+/// Enum variant: varint index, variant name, then the payload value
+pub(crate) const VARIANT: u8 = 0x14;
+
+/// Human-readable name of a tag, for error messages
+pub(crate) fn name(tag: u8) -> &'static str {
+    match tag {
+        UNIT => "unit",
+        NONE => "none",
+        SOME => "some",
+        FALSE | TRUE => "bool",
+        I8 => "i8",
+        I16 => "i16",
+        I32 => "i32",
+        I64 => "i64",
+        U8 => "u8",
+        U16 => "u16",
+        U32 => "u32",
+        U64 => "u64",
+        F32 => "f32",
+        F64 => "f64",
+        CHAR => "char",
+        STR => "string",
+        BYTES => "bytes",
+        SEQ => "sequence",
+        MAP => "map",
+        VARIANT => "enum variant",
+        _ => "unknown",
+    }
+}
+
+/// Size of the fixed-width payload following a scalar tag
+pub(crate) fn fixed_size(tag: u8) -> Option<usize> {
+    match tag {
+        UNIT | NONE | FALSE | TRUE => Some(0),
+        I8 | U8 => Some(1),
+        I16 | U16 => Some(2),
+        I32 | U32 | F32 | CHAR => Some(4),
+        I64 | U64 | F64 => Some(8),
+        _ => None,
+    }
+}
@@ -0,0 +1,1048 @@
+//! Human-readable text for payloads
+//!
+//! Nanobit text writes values the way they would be written in Rust:
+//! structs and enum variants by name, `Some(..)`/`None`, `[..]` for
+//! sequences, `(..)` for tuples and `{ key: value }` for maps. Given the
+//! [`Schema`] of the type, [`to_text`] renders any payload as text and
+//! [`from_text`] encodes text back into a payload, which suits config
+//! files, golden-test fixtures and payloads crafted by hand:
+//!
+//! ```rust
+//! use nanobit::schema::Schema;
+//! use nanobit::text::{from_text, to_text};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, PartialEq, Debug)]
+//! enum Shape { Circle(f64), Rect { w: u32, h: u32 } }
+//!
+//! #[derive(Serialize, Deserialize, PartialEq, Debug)]
+//! struct Scene { name: String, shapes: Vec<Shape>, parent: Option<u64> }
+//!
+//! let schema = Schema::of::<Scene>()?;
+//! let scene = Scene { name: "intro".into(), shapes: vec![Shape::Circle(0.5)], parent: None };
+//!
+//! let text = to_text(&nanobit::to_bytes(&scene)?, &schema)?;
+//! assert_eq!(text, "Scene {\n    name: \"intro\",\n    shapes: [\n        Circle(0.5),\n    ],\n    parent: None,\n}\n");
+//!
+//! // Comments and trailing commas are fine, and `None` fields may be left out
+//! let bytes = from_text("Scene { name: \"intro\", shapes: [Circle(0.5)] } // the opening", &schema)?;
+//! assert_eq!(nanobit::from_bytes::<Scene>(&bytes)?, scene);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Names that are not identifiers, such as numeric field renames, are
+//! written as strings. The type name in front of a struct is optional when
+//! parsing.
+
+use std::fmt::{self, Write as _};
+
+use serde::de::{self, DeserializeSeed, EnumAccess, IgnoredAny, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{self, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple};
+use serde::Serialize;
+
+use crate::config::{Config, DEFAULT_MAX_DEPTH};
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::schema::{field_names, intern, Container, Field, FieldSeed, Schema, Type, Variant, VariantKind, VariantSeed};
+
+/// Render a payload written by the type `schema` describes as text
+pub fn to_text(bytes: &[u8], schema: &Schema) -> Result<String> {
+    let mut writer = Writer { out: String::new(), indent: 0 };
+    let mut de = Deserializer::new(bytes)?;
+    Emit { writer: &mut writer, schema, ty: &schema.root }.deserialize(&mut de)?;
+    match de.reader.remaining() {
+        0 => {}
+        n => return Err(Error::InvalidFormat(format!("{n} trailing bytes after the value"))),
+    }
+    writer.out.push('\n');
+    Ok(writer.out)
+}
+
+/// Encode text as a payload of the type `schema` describes
+pub fn from_text(text: &str, schema: &Schema) -> Result<Vec<u8>> {
+    from_text_with_config(text, schema, Config::new())
+}
+
+/// Encode text as a payload using the given options
+pub fn from_text_with_config(text: &str, schema: &Schema, config: Config) -> Result<Vec<u8>> {
+    let mut parser = Parser { text, pos: 0, depth: 0 };
+    let parsed = parser.value(schema, &schema.root)?;
+    parser.skip_space();
+    if parser.pos < text.len() {
+        return Err(parser.error("Expected the end of the text"));
+    }
+    crate::to_bytes_with_config(&Typed { parsed: &parsed, schema, ty: &schema.root }, config)
+}
+
+// Whether values of a type are written over several lines
+fn spans_lines(schema: &Schema, ty: &Type) -> bool {
+    match ty {
+        Type::Map { .. } => true,
+        Type::Option(inner) | Type::Seq(inner) => spans_lines(schema, inner),
+        Type::Named(name) => match schema.get(name) {
+            Some(Container::Struct(_)) => true,
+            Some(Container::Enum(variants)) => variants.iter().any(|v| matches!(v.kind, VariantKind::Struct(_))),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// A name as written in text: bare when it is an identifier or a number
+struct Name<'a>(&'a str);
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut chars = self.0.chars();
+        let bare = match chars.next() {
+            Some(c) if c.is_ascii_digit() => self.0.bytes().all(|b| b.is_ascii_digit()),
+            Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+            _ => false,
+        };
+        if bare {
+            f.write_str(self.0)
+        } else {
+            write!(f, "{:?}", self.0)
+        }
+    }
+}
+
+struct Writer {
+    out: String,
+    indent: usize,
+}
+
+impl Writer {
+    fn open(&mut self, bracket: &str, block: bool) {
+        self.out.push_str(bracket);
+        if block {
+            self.indent += 1;
+        }
+    }
+
+    // Separate the next item of a compound from the previous one
+    fn item(&mut self, first: bool, block: bool) {
+        if block {
+            if !first {
+                self.out.push(',');
+            }
+            self.newline();
+        } else if !first {
+            self.out.push_str(", ");
+        }
+    }
+
+    fn close(&mut self, bracket: &str, block: bool, empty: bool) {
+        if block {
+            self.indent -= 1;
+            if !empty {
+                self.out.push(',');
+                self.newline();
+            }
+        }
+        self.out.push_str(bracket);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+}
+
+// Writes one value of a known type
+struct Emit<'w, 's> {
+    writer: &'w mut Writer,
+    schema: &'s Schema,
+    ty: &'s Type,
+}
+
+impl<'de> DeserializeSeed<'de> for Emit<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D>(self, de: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let Emit { writer, schema, ty } = self;
+        match ty {
+            Type::Unit => de.deserialize_unit(Print { writer, schema, what: What::Scalar }),
+            Type::Bool => de.deserialize_bool(Print { writer, schema, what: What::Scalar }),
+            Type::I8 => de.deserialize_i8(Print { writer, schema, what: What::Scalar }),
+            Type::I16 => de.deserialize_i16(Print { writer, schema, what: What::Scalar }),
+            Type::I32 => de.deserialize_i32(Print { writer, schema, what: What::Scalar }),
+            Type::I64 => de.deserialize_i64(Print { writer, schema, what: What::Scalar }),
+            Type::I128 => de.deserialize_i128(Print { writer, schema, what: What::Scalar }),
+            Type::U8 => de.deserialize_u8(Print { writer, schema, what: What::Scalar }),
+            Type::U16 => de.deserialize_u16(Print { writer, schema, what: What::Scalar }),
+            Type::U32 => de.deserialize_u32(Print { writer, schema, what: What::Scalar }),
+            Type::U64 => de.deserialize_u64(Print { writer, schema, what: What::Scalar }),
+            Type::U128 => de.deserialize_u128(Print { writer, schema, what: What::Scalar }),
+            Type::F32 => de.deserialize_f32(Print { writer, schema, what: What::Scalar }),
+            Type::F64 => de.deserialize_f64(Print { writer, schema, what: What::Scalar }),
+            Type::Char => de.deserialize_char(Print { writer, schema, what: What::Scalar }),
+            Type::Str => de.deserialize_str(Print { writer, schema, what: What::Scalar }),
+            Type::Bytes => de.deserialize_bytes(Print { writer, schema, what: What::Scalar }),
+            Type::Option(inner) => de.deserialize_option(Print { writer, schema, what: What::Option(inner) }),
+            Type::Seq(item) => de.deserialize_seq(Print { writer, schema, what: What::Seq(item) }),
+            Type::Tuple(items) => de.deserialize_tuple(items.len(), Print { writer, schema, what: What::Tuple("", items) }),
+            Type::Map { key, value } => de.deserialize_map(Print { writer, schema, what: What::Map(key, value) }),
+            Type::Named(name) => match schema.get(name) {
+                Some(Container::UnitStruct) => de.deserialize_unit_struct("", Print { writer, schema, what: What::UnitStruct(name) }),
+                Some(Container::NewtypeStruct(inner)) => {
+                    de.deserialize_newtype_struct("", Print { writer, schema, what: What::Tuple(name, core::slice::from_ref(inner)) })
+                }
+                Some(Container::TupleStruct(items)) => {
+                    de.deserialize_tuple_struct("", items.len(), Print { writer, schema, what: What::Tuple(name, items) })
+                }
+                Some(Container::Struct(fields)) => {
+                    de.deserialize_struct("", field_names(fields), Print { writer, schema, what: What::Struct(name, fields) })
+                }
+                Some(Container::Enum(variants)) => de.deserialize_enum("", &[], Print { writer, schema, what: What::Enum(variants) }),
+                None => Err(de::Error::custom(format!("Schema has no type named {name}"))),
+            },
+        }
+    }
+}
+
+// What a visited value is expected to be
+enum What<'s> {
+    Scalar,
+    Option(&'s Type),
+    Seq(&'s Type),
+    // Tuples, tuple structs and newtype structs, with the struct's name
+    Tuple(&'s str, &'s [Type]),
+    Map(&'s Type, &'s Type),
+    UnitStruct(&'s str),
+    Struct(&'s str, &'s [Field]),
+    Enum(&'s [Variant]),
+}
+
+struct Print<'w, 's> {
+    writer: &'w mut Writer,
+    schema: &'s Schema,
+    what: What<'s>,
+}
+
+impl<'w, 's> Print<'w, 's> {
+    fn emit(&mut self, ty: &'s Type) -> Emit<'_, 's> {
+        Emit { writer: self.writer, schema: self.schema, ty }
+    }
+
+    fn scalar<E>(self, value: impl fmt::Display) -> core::result::Result<(), E> {
+        let _ = write!(self.writer.out, "{value}");
+        Ok(())
+    }
+
+    // Fields of a struct or struct variant read in declaration order
+    fn fields_in_order<'de, A>(&mut self, fields: &'s [Field], seq: &mut A) -> core::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.writer.open(" {", true);
+        for (i, field) in fields.iter().enumerate() {
+            self.writer.item(i == 0, true);
+            let _ = write!(self.writer.out, "{}: ", Name(&field.name));
+            if seq.next_element_seed(self.emit(&field.ty))?.is_none() {
+                return Err(de::Error::invalid_length(i, &"all fields of the struct"));
+            }
+        }
+        self.writer.close("}", true, fields.is_empty());
+        Ok(())
+    }
+
+    // Fields of a struct or struct variant read by name or tag
+    fn fields_by_key<'de, A>(&mut self, fields: &'s [Field], map: &mut A) -> core::result::Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.writer.open(" {", true);
+        let mut first = true;
+        while let Some(index) = map.next_key_seed(FieldSeed(fields))? {
+            let Some(field) = index.map(|index| &fields[index]) else {
+                // Field the schema does not know
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            };
+            self.writer.item(first, true);
+            first = false;
+            let _ = write!(self.writer.out, "{}: ", Name(&field.name));
+            map.next_value_seed(self.emit(&field.ty))?;
+        }
+        self.writer.close("}", true, first);
+        Ok(())
+    }
+}
+
+macro_rules! print_scalars {
+    ($($method:ident: $ty:ty),*) => {$(
+        fn $method<E: de::Error>(self, value: $ty) -> core::result::Result<(), E> {
+            self.scalar(value)
+        }
+    )*};
+}
+
+impl<'de> Visitor<'de> for Print<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a value matching the schema")
+    }
+
+    print_scalars! {
+        visit_bool: bool, visit_i8: i8, visit_i16: i16, visit_i32: i32, visit_i64: i64, visit_i128: i128,
+        visit_u8: u8, visit_u16: u16, visit_u32: u32, visit_u64: u64, visit_u128: u128
+    }
+
+    fn visit_f32<E: de::Error>(self, value: f32) -> core::result::Result<(), E> {
+        self.scalar(format_args!("{value:?}"))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> core::result::Result<(), E> {
+        self.scalar(format_args!("{value:?}"))
+    }
+
+    fn visit_char<E: de::Error>(self, value: char) -> core::result::Result<(), E> {
+        self.scalar(format_args!("{value:?}"))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> core::result::Result<(), E> {
+        self.scalar(format_args!("{value:?}"))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> core::result::Result<(), E> {
+        self.scalar(format_args!("b\"{}\"", value.escape_ascii()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> core::result::Result<(), E> {
+        match self.what {
+            What::UnitStruct(name) => self.scalar(Name(name)),
+            _ => self.scalar("()"),
+        }
+    }
+
+    fn visit_none<E: de::Error>(self) -> core::result::Result<(), E> {
+        self.scalar("None")
+    }
+
+    fn visit_some<D>(mut self, de: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let What::Option(inner) = self.what else {
+            return Err(de::Error::custom("Unexpected optional value"));
+        };
+        self.writer.out.push_str("Some(");
+        self.emit(inner).deserialize(de)?;
+        self.writer.out.push(')');
+        Ok(())
+    }
+
+    fn visit_newtype_struct<D>(mut self, de: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let What::Tuple(name, [inner]) = self.what else {
+            return Err(de::Error::custom("Unexpected newtype struct"));
+        };
+        let _ = write!(self.writer.out, "{}(", Name(name));
+        self.emit(inner).deserialize(de)?;
+        self.writer.out.push(')');
+        Ok(())
+    }
+
+    fn visit_seq<A>(mut self, mut seq: A) -> core::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        match self.what {
+            What::Seq(item) => {
+                let block = spans_lines(self.schema, item);
+                self.writer.open("[", block);
+                let mut first = true;
+                loop {
+                    // The separator goes before the element, which may not exist
+                    let mark = self.writer.out.len();
+                    self.writer.item(first, block);
+                    if seq.next_element_seed(self.emit(item))?.is_none() {
+                        self.writer.out.truncate(mark);
+                        break;
+                    }
+                    first = false;
+                }
+                self.writer.close("]", block, first);
+            }
+            What::Tuple(name, items) => {
+                if !name.is_empty() {
+                    let _ = write!(self.writer.out, "{}", Name(name));
+                }
+                self.writer.open("(", false);
+                for (i, ty) in items.iter().enumerate() {
+                    self.writer.item(i == 0, false);
+                    if seq.next_element_seed(self.emit(ty))?.is_none() {
+                        return Err(de::Error::invalid_length(i, &"all elements of the tuple"));
+                    }
+                }
+                if items.len() == 1 && name.is_empty() {
+                    self.writer.out.push(',');
+                }
+                self.writer.close(")", false, items.is_empty());
+            }
+            What::Struct(name, fields) => {
+                if !name.is_empty() {
+                    let _ = write!(self.writer.out, "{}", Name(name));
+                }
+                self.fields_in_order(fields, &mut seq)?;
+            }
+            _ => return Err(de::Error::custom("Unexpected sequence")),
+        }
+        Ok(())
+    }
+
+    fn visit_map<A>(mut self, mut map: A) -> core::result::Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match self.what {
+            What::Map(key, value) => {
+                self.writer.open("{", true);
+                let mut first = true;
+                loop {
+                    let mark = self.writer.out.len();
+                    self.writer.item(first, true);
+                    if map.next_key_seed(self.emit(key))?.is_none() {
+                        self.writer.out.truncate(mark);
+                        break;
+                    }
+                    self.writer.out.push_str(": ");
+                    map.next_value_seed(self.emit(value))?;
+                    first = false;
+                }
+                self.writer.close("}", true, first);
+            }
+            What::Struct(name, fields) => {
+                if !name.is_empty() {
+                    let _ = write!(self.writer.out, "{}", Name(name));
+                }
+                self.fields_by_key(fields, &mut map)?;
+            }
+            _ => return Err(de::Error::custom("Unexpected map")),
+        }
+        Ok(())
+    }
+
+    fn visit_enum<A>(self, data: A) -> core::result::Result<(), A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let What::Enum(variants) = self.what else {
+            return Err(de::Error::custom("Unexpected enum"));
+        };
+        let (index, access) = data.variant_seed(VariantSeed(variants))?;
+        let variant = &variants[index];
+        let _ = write!(self.writer.out, "{}", Name(&variant.name));
+        let (writer, schema) = (self.writer, self.schema);
+        match &variant.kind {
+            VariantKind::Unit => access.unit_variant(),
+            VariantKind::Newtype(ty) => {
+                writer.out.push('(');
+                access.newtype_variant_seed(Emit { writer: &mut *writer, schema, ty })?;
+                writer.out.push(')');
+                Ok(())
+            }
+            VariantKind::Tuple(items) => {
+                access.tuple_variant(items.len(), Print { writer, schema, what: What::Tuple("", items) })
+            }
+            VariantKind::Struct(fields) => {
+                access.struct_variant(field_names(fields), Print { writer, schema, what: What::Struct("", fields) })
+            }
+        }
+    }
+}
+
+// A value read from text, already checked against its type
+enum Parsed {
+    Unit,
+    Bool(bool),
+    Int(i128),
+    UInt(u128),
+    Float(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    Option(Option<Box<Parsed>>),
+    // Elements of sequences and tuples, or fields in declaration order
+    Items(Vec<Parsed>),
+    Entries(Vec<(Parsed, Parsed)>),
+    Variant(u32, Box<Parsed>),
+}
+
+struct Parser<'t> {
+    text: &'t str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'t> Parser<'t> {
+    fn error(&self, message: &str) -> Error {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        Error::InvalidFormat(format!("{message} at line {line}, column {column}"))
+    }
+
+    fn rest(&self) -> &'t str {
+        &self.text[self.pos..]
+    }
+
+    // Skip whitespace and `//` comments
+    fn skip_space(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with("//") {
+                return;
+            }
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_space();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected `{c}`")))
+        }
+    }
+
+    // Run of characters making up an identifier or a number
+    fn word(&mut self) -> &'t str {
+        self.skip_space();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '+' | '-')))
+            .unwrap_or(rest.len());
+        // Signs only start a word or follow an exponent
+        let len = rest[..len]
+            .char_indices()
+            .skip(1)
+            .find(|&(i, c)| matches!(c, '+' | '-') && !rest[..i].ends_with(['e', 'E']))
+            .map_or(len, |(i, _)| i);
+        self.pos += len;
+        &rest[..len]
+    }
+
+    // A struct, field or variant name
+    fn name(&mut self) -> Result<String> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        let start = self.pos;
+        match self.word() {
+            "" => Err(self.error("Expected a name")),
+            word if word.chars().all(|c| c.is_alphanumeric() || c == '_') => Ok(word.to_string()),
+            _ => {
+                self.pos = start;
+                Err(self.error("Expected a name"))
+            }
+        }
+    }
+
+    // A quoted string with Rust escapes, as bytes when `bytes` is set
+    fn quoted(&mut self, quote: char, bytes: bool) -> Result<Vec<u8>> {
+        self.expect(quote)?;
+        let mut out = Vec::new();
+        let mut chars = self.rest().char_indices();
+        loop {
+            let Some((i, c)) = chars.next() else {
+                return Err(self.error("Unterminated literal"));
+            };
+            match c {
+                c if c == quote => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some(c @ ('\\' | '"' | '\'')) => c,
+                        Some('x') => {
+                            let hex: String = chars.by_ref().take(2).map(|(_, c)| c).collect();
+                            match u8::from_str_radix(&hex, 16) {
+                                Ok(byte) if bytes || byte < 0x80 => {
+                                    out.push(byte);
+                                    continue;
+                                }
+                                _ => return Err(self.error("Invalid `\\x` escape")),
+                            }
+                        }
+                        Some('u') if !bytes => {
+                            let hex: String = chars.by_ref().map(|(_, c)| c).take_while(|&c| c != '}').collect();
+                            hex.strip_prefix('{')
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("Invalid `\\u` escape"))?
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    out.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                c if bytes && !c.is_ascii() => return Err(self.error("Byte strings must be ASCII")),
+                c => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let bytes = self.quoted('"', false)?;
+        Ok(String::from_utf8(bytes).expect("built from chars"))
+    }
+
+    fn number<N: core::str::FromStr>(&mut self, what: &str) -> Result<N> {
+        let start = self.pos;
+        let word = self.word().replace('_', "");
+        word.parse().map_err(|_| {
+            self.pos = start;
+            self.error(&format!("Expected {what}"))
+        })
+    }
+
+    fn int(&mut self, bits: u32) -> Result<Parsed> {
+        let start = self.pos;
+        let value: i128 = self.number("an integer")?;
+        if bits < 128 && !(-(1i128 << (bits - 1))..1i128 << (bits - 1)).contains(&value) {
+            self.pos = start;
+            return Err(self.error(&format!("Integer out of range for i{bits}")));
+        }
+        Ok(Parsed::Int(value))
+    }
+
+    fn uint(&mut self, bits: u32) -> Result<Parsed> {
+        let start = self.pos;
+        let value: u128 = self.number("an unsigned integer")?;
+        if bits < 128 && value >> bits != 0 {
+            self.pos = start;
+            return Err(self.error(&format!("Integer out of range for u{bits}")));
+        }
+        Ok(Parsed::UInt(value))
+    }
+
+    // Items between brackets, separated by commas with an optional trailing one
+    fn items<T>(&mut self, close: char, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        while !self.eat(close) {
+            items.push(item(self)?);
+            if !self.eat(',') {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn tuple(&mut self, schema: &Schema, types: &[Type]) -> Result<Parsed> {
+        self.expect('(')?;
+        let mut types = types.iter();
+        let items = self.items(')', |p| match types.next() {
+            Some(ty) => p.value(schema, ty),
+            None => Err(p.error("Too many elements")),
+        })?;
+        if types.next().is_some() {
+            return Err(self.error("Too few elements"));
+        }
+        Ok(Parsed::Items(items))
+    }
+
+    // Fields between braces, in any order, returned in declaration order
+    fn fields(&mut self, schema: &Schema, fields: &[Field]) -> Result<Parsed> {
+        self.expect('{')?;
+        let mut values: Vec<Option<Parsed>> = fields.iter().map(|_| None).collect();
+        self.items('}', |p| {
+            let start = p.pos;
+            let name = p.name()?;
+            let Some(index) = fields.iter().position(|field| field.name == name) else {
+                p.pos = start;
+                return Err(p.error(&format!("Unknown field `{name}`")));
+            };
+            if values[index].is_some() {
+                p.pos = start;
+                return Err(p.error(&format!("Duplicate field `{name}`")));
+            }
+            p.expect(':')?;
+            values[index] = Some(p.value(schema, &fields[index].ty)?);
+            Ok(())
+        })?;
+        let items = values
+            .into_iter()
+            .zip(fields)
+            .map(|(value, field)| match (value, &field.ty) {
+                (Some(value), _) => Ok(value),
+                (None, Type::Option(_)) => Ok(Parsed::Option(None)),
+                (None, _) => Err(self.error(&format!("Missing field `{}`", field.name))),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Parsed::Items(items))
+    }
+
+    fn value(&mut self, schema: &Schema, ty: &Type) -> Result<Parsed> {
+        if self.depth >= DEFAULT_MAX_DEPTH {
+            return Err(self.error(&format!("Nesting depth exceeds the limit of {DEFAULT_MAX_DEPTH}")));
+        }
+        self.depth += 1;
+        let value = self.value_of(schema, ty);
+        self.depth -= 1;
+        value
+    }
+
+    fn value_of(&mut self, schema: &Schema, ty: &Type) -> Result<Parsed> {
+        match ty {
+            Type::Unit => {
+                self.expect('(')?;
+                self.expect(')')?;
+                Ok(Parsed::Unit)
+            }
+            Type::Bool => match self.word() {
+                "true" => Ok(Parsed::Bool(true)),
+                "false" => Ok(Parsed::Bool(false)),
+                _ => Err(self.error("Expected `true` or `false`")),
+            },
+            Type::I8 => self.int(8),
+            Type::I16 => self.int(16),
+            Type::I32 => self.int(32),
+            Type::I64 => self.int(64),
+            Type::I128 => self.int(128),
+            Type::U8 => self.uint(8),
+            Type::U16 => self.uint(16),
+            Type::U32 => self.uint(32),
+            Type::U64 => self.uint(64),
+            Type::U128 => self.uint(128),
+            // Parsed at their own precision so that printed f32s read back exactly
+            Type::F32 => self.number::<f32>("a number").map(|f| Parsed::Float(f.into())),
+            Type::F64 => self.number("a number").map(Parsed::Float),
+            Type::Char => {
+                self.skip_space();
+                let bytes = self.quoted('\'', false)?;
+                let s = String::from_utf8(bytes).expect("built from chars");
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Parsed::Char(c)),
+                    _ => Err(self.error("Expected a single character")),
+                }
+            }
+            Type::Str => {
+                self.skip_space();
+                self.string().map(Parsed::Str)
+            }
+            Type::Bytes => {
+                if self.peek() != Some('b') {
+                    return Err(self.error("Expected a byte string"));
+                }
+                self.pos += 1;
+                self.quoted('"', true).map(Parsed::Bytes)
+            }
+            Type::Option(inner) => match self.word() {
+                "None" => Ok(Parsed::Option(None)),
+                "Some" => {
+                    self.expect('(')?;
+                    let value = self.value(schema, inner)?;
+                    self.eat(',');
+                    self.expect(')')?;
+                    Ok(Parsed::Option(Some(Box::new(value))))
+                }
+                _ => Err(self.error("Expected `Some(..)` or `None`")),
+            },
+            Type::Seq(item) => {
+                self.expect('[')?;
+                self.items(']', |p| p.value(schema, item)).map(Parsed::Items)
+            }
+            Type::Tuple(items) => self.tuple(schema, items),
+            Type::Map { key, value } => {
+                self.expect('{')?;
+                let entries = self.items('}', |p| {
+                    let key = p.value(schema, key)?;
+                    p.expect(':')?;
+                    Ok((key, p.value(schema, value)?))
+                })?;
+                Ok(Parsed::Entries(entries))
+            }
+            Type::Named(name) => {
+                let container = schema.get(name).ok_or_else(|| self.error(&format!("Schema has no type named {name}")))?;
+                if let Container::Enum(variants) = container {
+                    return self.variant(schema, variants);
+                }
+                // The type name is optional
+                let start = self.pos;
+                if self.peek().is_some_and(|c| c.is_alphabetic() || c == '_' || c == '"') {
+                    if self.name()? != *name {
+                        self.pos = start;
+                        return Err(self.error(&format!("Expected {name}")));
+                    }
+                } else if matches!(container, Container::UnitStruct) {
+                    return Err(self.error(&format!("Expected {name}")));
+                }
+                match container {
+                    Container::UnitStruct => Ok(Parsed::Unit),
+                    Container::NewtypeStruct(inner) => self.tuple(schema, core::slice::from_ref(inner)),
+                    Container::TupleStruct(items) => self.tuple(schema, items),
+                    Container::Struct(fields) => self.fields(schema, fields),
+                    Container::Enum(_) => unreachable!("handled above"),
+                }
+            }
+        }
+    }
+
+    fn variant(&mut self, schema: &Schema, variants: &[Variant]) -> Result<Parsed> {
+        let start = self.pos;
+        let name = self.name()?;
+        let Some(index) = variants.iter().position(|variant| variant.name == name) else {
+            self.pos = start;
+            return Err(self.error(&format!("Unknown variant `{name}`")));
+        };
+        let value = match &variants[index].kind {
+            VariantKind::Unit => Parsed::Unit,
+            VariantKind::Newtype(ty) => self.tuple(schema, core::slice::from_ref(ty))?,
+            VariantKind::Tuple(items) => self.tuple(schema, items)?,
+            VariantKind::Struct(fields) => self.fields(schema, fields)?,
+        };
+        Ok(Parsed::Variant(index as u32, Box::new(value)))
+    }
+}
+
+// A parsed value serialized as its type
+struct Typed<'a> {
+    parsed: &'a Parsed,
+    schema: &'a Schema,
+    ty: &'a Type,
+}
+
+impl<'a> Typed<'a> {
+    fn child(&self, parsed: &'a Parsed, ty: &'a Type) -> Typed<'a> {
+        Typed { parsed, schema: self.schema, ty }
+    }
+}
+
+impl Serialize for Typed<'_> {
+    fn serialize<S>(&self, s: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        let mismatch = || ser::Error::custom("Parsed value does not match the schema");
+        match (self.ty, self.parsed) {
+            (Type::Unit, Parsed::Unit) => s.serialize_unit(),
+            (Type::Bool, Parsed::Bool(b)) => s.serialize_bool(*b),
+            (Type::I8, Parsed::Int(i)) => s.serialize_i8(*i as i8),
+            (Type::I16, Parsed::Int(i)) => s.serialize_i16(*i as i16),
+            (Type::I32, Parsed::Int(i)) => s.serialize_i32(*i as i32),
+            (Type::I64, Parsed::Int(i)) => s.serialize_i64(*i as i64),
+            (Type::I128, Parsed::Int(i)) => s.serialize_i128(*i),
+            (Type::U8, Parsed::UInt(u)) => s.serialize_u8(*u as u8),
+            (Type::U16, Parsed::UInt(u)) => s.serialize_u16(*u as u16),
+            (Type::U32, Parsed::UInt(u)) => s.serialize_u32(*u as u32),
+            (Type::U64, Parsed::UInt(u)) => s.serialize_u64(*u as u64),
+            (Type::U128, Parsed::UInt(u)) => s.serialize_u128(*u),
+            (Type::F32, Parsed::Float(f)) => s.serialize_f32(*f as f32),
+            (Type::F64, Parsed::Float(f)) => s.serialize_f64(*f),
+            (Type::Char, Parsed::Char(c)) => s.serialize_char(*c),
+            (Type::Str, Parsed::Str(v)) => s.serialize_str(v),
+            (Type::Bytes, Parsed::Bytes(v)) => s.serialize_bytes(v),
+            (Type::Option(_), Parsed::Option(None)) => s.serialize_none(),
+            (Type::Option(inner), Parsed::Option(Some(value))) => s.serialize_some(&self.child(value, inner)),
+            (Type::Seq(item), Parsed::Items(items)) => {
+                let mut seq = s.serialize_seq(Some(items.len()))?;
+                for value in items {
+                    seq.serialize_element(&self.child(value, item))?;
+                }
+                seq.end()
+            }
+            (Type::Tuple(types), Parsed::Items(items)) => {
+                let mut tuple = s.serialize_tuple(types.len())?;
+                for (value, ty) in items.iter().zip(types) {
+                    tuple.serialize_element(&self.child(value, ty))?;
+                }
+                tuple.end()
+            }
+            (Type::Map { key, value }, Parsed::Entries(entries)) => {
+                let mut map = s.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(&self.child(k, key), &self.child(v, value))?;
+                }
+                map.end()
+            }
+            (Type::Named(name), parsed) => {
+                let container = self.schema.get(name).ok_or_else(mismatch)?;
+                let name = intern(name);
+                match (container, parsed) {
+                    (Container::UnitStruct, Parsed::Unit) => s.serialize_unit_struct(name),
+                    (Container::NewtypeStruct(inner), Parsed::Items(items)) => {
+                        s.serialize_newtype_struct(name, &self.child(&items[0], inner))
+                    }
+                    (Container::TupleStruct(types), Parsed::Items(items)) => {
+                        use serde::ser::SerializeTupleStruct;
+                        let mut tuple = s.serialize_tuple_struct(name, types.len())?;
+                        for (value, ty) in items.iter().zip(types) {
+                            tuple.serialize_field(&self.child(value, ty))?;
+                        }
+                        tuple.end()
+                    }
+                    (Container::Struct(fields), Parsed::Items(items)) => {
+                        let mut st = s.serialize_struct(name, fields.len())?;
+                        for (value, field) in items.iter().zip(fields) {
+                            st.serialize_field(intern(&field.name), &self.child(value, &field.ty))?;
+                        }
+                        st.end()
+                    }
+                    (Container::Enum(variants), Parsed::Variant(index, value)) => {
+                        self.variant(s, name, &variants[*index as usize], *index, value)
+                    }
+                    _ => Err(mismatch()),
+                }
+            }
+            _ => Err(mismatch()),
+        }
+    }
+}
+
+impl Typed<'_> {
+    fn variant<S>(&self, s: S, name: &'static str, variant: &Variant, index: u32, value: &Parsed) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use serde::ser::{SerializeStructVariant, SerializeTupleVariant};
+
+        let variant_name = intern(&variant.name);
+        match (&variant.kind, value) {
+            (VariantKind::Unit, _) => s.serialize_unit_variant(name, index, variant_name),
+            (VariantKind::Newtype(ty), Parsed::Items(items)) => {
+                s.serialize_newtype_variant(name, index, variant_name, &self.child(&items[0], ty))
+            }
+            (VariantKind::Tuple(types), Parsed::Items(items)) => {
+                let mut tuple = s.serialize_tuple_variant(name, index, variant_name, types.len())?;
+                for (value, ty) in items.iter().zip(types) {
+                    tuple.serialize_field(&self.child(value, ty))?;
+                }
+                tuple.end()
+            }
+            (VariantKind::Struct(fields), Parsed::Items(items)) => {
+                let mut st = s.serialize_struct_variant(name, index, variant_name, fields.len())?;
+                for (value, field) in items.iter().zip(fields) {
+                    st.serialize_field(intern(&field.name), &self.child(value, &field.ty))?;
+                }
+                st.end()
+            }
+            _ => Err(ser::Error::custom("Parsed value does not match the schema")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Meters(f32);
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Shape {
+        Point,
+        Circle(Meters),
+        Line(i16, i16),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Fixture {
+        name: String,
+        glyph: char,
+        #[serde(with = "crate::byte_slice")]
+        raw: Vec<u8>,
+        shapes: Vec<Shape>,
+        labels: BTreeMap<u8, String>,
+        nested: Option<Option<u128>>,
+        pair: (i64, bool),
+    }
+
+    fn fixture() -> Fixture {
+        Fixture {
+            name: "tab\there \"quoted\" é".to_string(),
+            glyph: '\'',
+            raw: vec![0, b'a', 0xFF],
+            shapes: vec![Shape::Point, Shape::Circle(Meters(1.5)), Shape::Line(-3, 4), Shape::Rect { w: 2, h: 3 }],
+            labels: BTreeMap::from([(1, "one".to_string()), (2, "two".to_string())]),
+            nested: Some(None),
+            pair: (i64::MIN, true),
+        }
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let schema = Schema::of::<Fixture>().unwrap();
+        let value = fixture();
+        for config in [
+            Config::new(),
+            Config::new().with_tagged_fields(true),
+            Config::new().with_self_describing(true),
+            Config::new().with_compact_chars(true).with_fixed_tuples(true),
+        ] {
+            let bytes = crate::to_bytes_with_config(&value, config).unwrap();
+            let text = to_text(&bytes, &schema).unwrap();
+            assert_eq!(from_text_with_config(&text, &schema, config).unwrap(), bytes, "{text}");
+        }
+
+        let text = to_text(&crate::to_bytes(&value).unwrap(), &schema).unwrap();
+        assert!(text.contains("    raw: b\"\\x00a\\xff\",\n"));
+        assert!(text.contains("        Circle(Meters(1.5)),\n        Line(-3, 4),\n        Rect {\n            w: 2,"));
+        assert!(text.contains("    nested: Some(None),\n    pair: (-9223372036854775808, true),\n}"));
+    }
+
+    #[test]
+    fn test_text_errors() {
+        let schema = Schema::of::<Fixture>().unwrap();
+        let err = |text: &str| match from_text(text, &schema) {
+            Err(Error::InvalidFormat(message)) => message,
+            other => panic!("expected an error, got {other:?}"),
+        };
+        assert_eq!(err("Fixture {\n  name: 7,\n}"), "Expected `\"` at line 2, column 9");
+        assert!(err("{ name: \"\", colour: 1 }").starts_with("Unknown field `colour`"));
+        assert!(err("{ name: \"\" }").starts_with("Missing field `glyph`"));
+        assert!(err("{ labels: { 256: \"\" } }").starts_with("Integer out of range for u8"));
+        assert!(err("{ shapes: [Square] }").starts_with("Unknown variant `Square`"));
+    }
+
+    #[test]
+    fn test_text_nesting_limit() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        enum Tree {
+            Leaf,
+            Node(Box<Tree>),
+        }
+
+        let schema = Schema::of::<Tree>().unwrap();
+        let text = |depth: usize| format!("{}Leaf{}", "Node(".repeat(depth), ")".repeat(depth));
+        assert!(from_text(&text(50), &schema).is_ok());
+        match from_text(&text(100_000), &schema) {
+            Err(Error::InvalidFormat(message)) => assert!(message.starts_with("Nesting depth exceeds the limit of 128")),
+            other => panic!("expected an error, got {other:?}"),
+        }
+    }
+}
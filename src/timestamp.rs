@@ -0,0 +1,363 @@
+//! Compact encoding for points in time
+//!
+//! Through their serde impls, `SystemTime` is a struct of two integers,
+//! and chrono and time types are RFC 3339 strings of 20 to 35 bytes. The
+//! [`Timestamp`] wrapper, or `#[serde(with = "nanobit::timestamp")]` on a
+//! field, writes the instant relative to the Unix epoch instead, in 6 to
+//! 11 bytes:
+//!
+//! ```rust
+//! use std::time::{Duration, SystemTime, UNIX_EPOCH};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Event {
+//!     id: u32,
+//!     #[serde(with = "nanobit::timestamp")]
+//!     at: SystemTime,
+//! }
+//!
+//! let event = Event { id: 7, at: UNIX_EPOCH + Duration::from_millis(1_700_000_000_250) };
+//! let bytes = nanobit::to_bytes(&event)?;
+//! assert!(bytes.len() < nanobit::to_bytes(&(event.id, event.at))?.len());
+//! assert_eq!(nanobit::from_bytes::<Event>(&bytes)?, event);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Supported types are `SystemTime`, chrono's `DateTime<Utc>` and
+//! `NaiveDateTime` (`chrono` feature), and time's `OffsetDateTime` and
+//! `PrimitiveDateTime` (`time` feature). Naive and primitive date-times
+//! are taken to be UTC, and an `OffsetDateTime` decodes in UTC: the
+//! instant survives, its offset does not.
+//!
+//! # Representation
+//!
+//! A timestamp is a byte string holding one or two varints. The instant
+//! is split into whole seconds since 1970-01-01T00:00:00Z, rounded down,
+//! and the nanoseconds after them. The first varint is
+//! `zigzag(seconds) << 2 | unit`, where `unit` is the coarsest of whole
+//! seconds (0), milliseconds (1), microseconds (2) or nanoseconds (3) that
+//! holds the nanoseconds exactly. Unless the unit is whole seconds, a
+//! second varint holds the nanoseconds counted in that unit.
+//!
+//! Any implementation can decode it from there, and payloads stay
+//! readable without the Rust types: self-describing payloads carry it as
+//! an ordinary byte string.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, Error as _, SeqAccess, Visitor};
+use serde::ser::{Error as _, Serialize, Serializer};
+
+use crate::buffer::{ReadBuffer, WriteBuffer};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+// Nanoseconds in each sub-second unit, by unit number
+const UNIT_NANOS: [u32; 4] = [NANOS_PER_SEC, 1_000_000, 1_000, 1];
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Points in time that [`Timestamp`] can encode
+pub trait EpochTime: sealed::Sealed + Sized {
+    /// Whole seconds since the Unix epoch, rounded down, and the
+    /// nanoseconds after them, or `None` beyond the encodable range
+    fn to_epoch(&self) -> Option<(i64, u32)>;
+
+    /// The instant `seconds` and `nanos` after the Unix epoch, or `None`
+    /// if the type cannot represent it
+    fn from_epoch(seconds: i64, nanos: u32) -> Option<Self>;
+}
+
+#[cfg(feature = "std")]
+impl sealed::Sealed for std::time::SystemTime {}
+
+#[cfg(feature = "std")]
+impl EpochTime for std::time::SystemTime {
+    fn to_epoch(&self) -> Option<(i64, u32)> {
+        match self.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => Some((i64::try_from(after.as_secs()).ok()?, after.subsec_nanos())),
+            Err(before) => {
+                let before = before.duration();
+                let seconds = 0i64.checked_sub_unsigned(before.as_secs())?;
+                match before.subsec_nanos() {
+                    0 => Some((seconds, 0)),
+                    nanos => Some((seconds.checked_sub(1)?, NANOS_PER_SEC - nanos)),
+                }
+            }
+        }
+    }
+
+    fn from_epoch(seconds: i64, nanos: u32) -> Option<Self> {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let whole = if seconds >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))?
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))?
+        };
+        whole.checked_add(Duration::from_nanos(nanos.into()))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl sealed::Sealed for chrono::DateTime<chrono::Utc> {}
+
+#[cfg(feature = "chrono")]
+impl EpochTime for chrono::DateTime<chrono::Utc> {
+    fn to_epoch(&self) -> Option<(i64, u32)> {
+        Some((self.timestamp(), self.timestamp_subsec_nanos()))
+    }
+
+    fn from_epoch(seconds: i64, nanos: u32) -> Option<Self> {
+        chrono::DateTime::from_timestamp(seconds, nanos)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl sealed::Sealed for chrono::NaiveDateTime {}
+
+#[cfg(feature = "chrono")]
+impl EpochTime for chrono::NaiveDateTime {
+    fn to_epoch(&self) -> Option<(i64, u32)> {
+        self.and_utc().to_epoch()
+    }
+
+    fn from_epoch(seconds: i64, nanos: u32) -> Option<Self> {
+        chrono::DateTime::from_timestamp(seconds, nanos).map(|utc| utc.naive_utc())
+    }
+}
+
+#[cfg(feature = "time")]
+impl sealed::Sealed for time::OffsetDateTime {}
+
+#[cfg(feature = "time")]
+impl EpochTime for time::OffsetDateTime {
+    fn to_epoch(&self) -> Option<(i64, u32)> {
+        Some((self.unix_timestamp(), self.nanosecond()))
+    }
+
+    fn from_epoch(seconds: i64, nanos: u32) -> Option<Self> {
+        time::OffsetDateTime::from_unix_timestamp(seconds).ok()?.replace_nanosecond(nanos).ok()
+    }
+}
+
+#[cfg(feature = "time")]
+impl sealed::Sealed for time::PrimitiveDateTime {}
+
+#[cfg(feature = "time")]
+impl EpochTime for time::PrimitiveDateTime {
+    fn to_epoch(&self) -> Option<(i64, u32)> {
+        self.assume_utc().to_epoch()
+    }
+
+    fn from_epoch(seconds: i64, nanos: u32) -> Option<Self> {
+        time::OffsetDateTime::from_epoch(seconds, nanos).map(|utc| time::PrimitiveDateTime::new(utc.date(), utc.time()))
+    }
+}
+
+/// A point in time encoded as seconds and nanoseconds since the Unix epoch
+///
+/// See the [module documentation](self) for the representation.
+///
+/// ```rust
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+/// use nanobit::Timestamp;
+///
+/// let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// let bytes = nanobit::to_bytes(&Timestamp(at))?;
+/// assert!(bytes.len() < nanobit::to_bytes(&at)?.len());
+///
+/// let Timestamp(decoded) = nanobit::from_bytes::<Timestamp<SystemTime>>(&bytes)?;
+/// assert_eq!(decoded, at);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp<T>(pub T);
+
+impl<T: EpochTime> Serialize for Timestamp<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: EpochTime> Deserialize<'de> for Timestamp<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(Timestamp)
+    }
+}
+
+/// Serialize a point in time in the compact representation
+pub fn serialize<T, S>(time: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: EpochTime,
+    S: Serializer,
+{
+    let (seconds, nanos) = time.to_epoch().ok_or_else(|| S::Error::custom("Timestamp out of range"))?;
+    let zigzag = ((seconds << 1) ^ (seconds >> 63)) as u64;
+    if zigzag >> 62 != 0 {
+        return Err(S::Error::custom("Timestamp out of range"));
+    }
+    let unit = UNIT_NANOS.iter().position(|&unit| nanos % unit == 0).unwrap_or(3);
+
+    let mut out = WriteBuffer::with_capacity(16);
+    out.write_varint(zigzag << 2 | unit as u64).expect("writing to a WriteBuffer cannot fail");
+    if unit != 0 {
+        out.write_varint((nanos / UNIT_NANOS[unit]).into()).expect("writing to a WriteBuffer cannot fail");
+    }
+    serializer.serialize_bytes(out.as_slice())
+}
+
+/// Deserialize a point in time written by [`serialize`]
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: EpochTime,
+    D: Deserializer<'de>,
+{
+    struct TimestampVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: EpochTime> Visitor<'de> for TimestampVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a byte string holding a timestamp")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<T, E> {
+            let mut input = ReadBuffer::new(bytes);
+            let head = input.read_varint().map_err(E::custom)?;
+            let zigzag = head >> 2;
+            let seconds = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            let unit = (head & 3) as usize;
+            let nanos = if unit == 0 {
+                0
+            } else {
+                let count = input.read_varint().map_err(E::custom)?;
+                // Up to two seconds' worth, which chrono uses for leap seconds
+                u32::try_from(count)
+                    .ok()
+                    .and_then(|count| count.checked_mul(UNIT_NANOS[unit]))
+                    .filter(|&nanos| nanos < 2 * NANOS_PER_SEC)
+                    .ok_or_else(|| E::custom("Timestamp nanoseconds out of range"))?
+            };
+            if input.remaining() != 0 {
+                return Err(E::custom("Trailing bytes after timestamp"));
+            }
+            T::from_epoch(seconds, nanos).ok_or_else(|| E::custom("Timestamp out of range"))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+            let mut bytes = Vec::with_capacity(16);
+            while let Some(byte) = seq.next_element()? {
+                if bytes.len() == 20 {
+                    return Err(A::Error::custom("Timestamp too long"));
+                }
+                bytes.push(byte);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    deserializer.deserialize_bytes(TimestampVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_system_time_roundtrip() {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let cases = [
+            (UNIX_EPOCH, 2),
+            (UNIX_EPOCH + Duration::from_secs(1_700_000_000), 6),
+            (UNIX_EPOCH + Duration::from_millis(1_700_000_000_250), 8),
+            (UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_250), 8),
+            (UNIX_EPOCH + Duration::from_nanos(1_700_000_000_000_000_001), 7),
+            (UNIX_EPOCH + Duration::new(1_700_000_000, 999_999_999), 11),
+            (UNIX_EPOCH - Duration::from_millis(1500), 4),
+            (UNIX_EPOCH - Duration::from_nanos(1), 7),
+        ];
+        for config in [Config::new(), Config::new().with_self_describing(true)] {
+            for (time, len) in cases {
+                let bytes = crate::to_bytes_with_config(&Timestamp(time), config).unwrap();
+                let body = &bytes[crate::to_bytes_with_config(&(), config).unwrap().len()..];
+                if !config.self_describing() {
+                    assert_eq!(body.len(), len, "{time:?}");
+                }
+                let Timestamp(decoded) = crate::from_bytes::<Timestamp<SystemTime>>(&bytes).unwrap();
+                assert_eq!(decoded, time);
+            }
+        }
+
+        // The representation, spelled out: zigzag(-2) << 2 | milliseconds, then 500
+        let bytes = crate::to_bytes(&Timestamp(UNIX_EPOCH - Duration::from_millis(1500))).unwrap();
+        assert!(bytes.ends_with(&[3, 3 << 2 | 1, 0xf4, 0x03]));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_invalid_timestamps_rejected() {
+        use std::time::SystemTime;
+
+        // Without type tags a byte string is written like a `Vec<u8>`
+        let decode = |bytes: &[u8]| crate::from_bytes::<Timestamp<SystemTime>>(&crate::to_bytes(&bytes.to_vec()).unwrap());
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[1]).is_err());
+        assert!(decode(&[1, 0xd0, 0x0f]).is_err());
+        assert!(decode(&[0, 0]).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_roundtrip() {
+        use chrono::{DateTime, NaiveDateTime, Utc};
+
+        let at = DateTime::from_timestamp(1_709_210_096, 789_000_000).unwrap();
+        let bytes = crate::to_bytes(&Timestamp(at)).unwrap();
+        assert!(bytes.len() + 16 < crate::to_bytes(&"2024-02-29T12:34:56.789Z").unwrap().len());
+        assert_eq!(crate::from_bytes::<Timestamp<DateTime<Utc>>>(&bytes).unwrap(), Timestamp(at));
+
+        let naive = crate::from_bytes::<Timestamp<NaiveDateTime>>(&bytes).unwrap();
+        assert_eq!(naive.0, at.naive_utc());
+        assert_eq!(crate::to_bytes(&naive).unwrap(), bytes);
+
+        let ancient = DateTime::<Utc>::from_timestamp(-63_549_532_800, 0).unwrap();
+        let bytes = crate::to_bytes(&Timestamp(ancient)).unwrap();
+        assert_eq!(crate::from_bytes::<Timestamp<DateTime<Utc>>>(&bytes).unwrap(), Timestamp(ancient));
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time_roundtrip() {
+        use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+        let at = OffsetDateTime::from_unix_timestamp_nanos(1_709_210_096_789_000_123).unwrap();
+        let bytes = crate::to_bytes(&Timestamp(at)).unwrap();
+        assert_eq!(crate::from_bytes::<Timestamp<OffsetDateTime>>(&bytes).unwrap(), Timestamp(at));
+
+        // The offset is dropped, the instant kept
+        let local = at.to_offset(UtcOffset::from_hms(5, 30, 0).unwrap());
+        let Timestamp(decoded) = crate::from_bytes::<Timestamp<OffsetDateTime>>(&crate::to_bytes(&Timestamp(local)).unwrap()).unwrap();
+        assert_eq!(decoded, local);
+        assert_eq!(decoded.offset(), UtcOffset::UTC);
+
+        let Timestamp(primitive) = crate::from_bytes::<Timestamp<PrimitiveDateTime>>(&bytes).unwrap();
+        assert_eq!(primitive.assume_utc(), at);
+    }
+}
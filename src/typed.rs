@@ -0,0 +1,128 @@
+//! Payloads that record the type they were written for
+//!
+//! A type-tagged payload sets extended header flag `0x0100` and stores a
+//! type name after the flags:
+//!
+//! ```text
+//! ["NANO"] [0x02] [flags | 0x80] [flags >> 8] [name length: varint] [name] [body]
+//! ```
+//!
+//! The name can be inspected with [`peek_type`] before decoding, and
+//! [`from_bytes_expect`] refuses payloads written for another type instead
+//! of misreading them. Any decoder still reads the payload as usual.
+//!
+//! [`to_bytes_tagged`] uses [`core::any::type_name`], which names the exact
+//! type (`Vec<&str>` differs from `Vec<String>`) and is not guaranteed to
+//! be stable across compiler versions. Prefer explicit tags with
+//! [`to_bytes_with_type_tag`] for data that is stored long-term.
+
+#[cfg(not(feature = "std"))]
+use alloc::{string::ToString, vec::Vec};
+
+use core::any::type_name;
+
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::ReadBuffer;
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+use crate::ser::Serializer;
+
+/// Serialize a value, recording its Rust type name
+///
+/// ```rust
+/// let bytes = nanobit::to_bytes_tagged(&vec![1u32, 2, 3])?;
+/// assert_eq!(nanobit::peek_type(&bytes)?, Some("alloc::vec::Vec<u32>"));
+///
+/// assert_eq!(nanobit::from_bytes_expect::<Vec<u32>>(&bytes)?, [1, 2, 3]);
+/// assert!(nanobit::from_bytes_expect::<Vec<u64>>(&bytes).is_err());
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+pub fn to_bytes_tagged<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    to_bytes_with_type_tag(value, type_name::<T>())
+}
+
+/// Serialize a value, recording the given type tag
+pub fn to_bytes_with_type_tag<T>(value: &T, tag: &str) -> Result<Vec<u8>>
+where
+    T: Serialize + ?Sized,
+{
+    let mut serializer = Serializer::new();
+    value.serialize(&mut serializer)?;
+    Ok(serializer.into_bytes_with_type_name(tag))
+}
+
+/// Read the type tag of a payload without decoding it
+///
+/// Returns `None` for payloads written without one.
+pub fn peek_type(bytes: &[u8]) -> Result<Option<&str>> {
+    let (header, _) = Header::parse(bytes)?;
+    if !header.flags.contains(HeaderFlags::TYPE_NAME) {
+        return Ok(None);
+    }
+    ReadBuffer::new(&bytes[header.encoded_len()..]).read_str().map(Some)
+}
+
+/// Deserialize a payload written by [`to_bytes_tagged`] for type `T`
+pub fn from_bytes_expect<'de, T>(bytes: &'de [u8]) -> Result<T>
+where
+    T: Deserialize<'de>,
+{
+    from_bytes_expect_tag(bytes, type_name::<T>())
+}
+
+/// Deserialize a payload whose type tag must equal `tag`
+pub fn from_bytes_expect_tag<'de, T>(bytes: &'de [u8], tag: &str) -> Result<T>
+where
+    T: Deserialize<'de>,
+{
+    match peek_type(bytes)? {
+        Some(found) if found == tag => crate::from_bytes(bytes),
+        found => Err(Error::TypeMismatch {
+            expected: tag.to_string(),
+            found: found.map(ToString::to_string),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Invoice {
+        id: u64,
+        total: u32,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Refund {
+        id: u64,
+        amount: u32,
+    }
+
+    #[test]
+    fn test_type_tags() {
+        let invoice = Invoice { id: 7, total: 1200 };
+        let bytes = to_bytes_with_type_tag(&invoice, "billing.Invoice/v1").unwrap();
+        assert_eq!(peek_type(&bytes).unwrap(), Some("billing.Invoice/v1"));
+        assert_eq!(from_bytes_expect_tag::<Invoice>(&bytes, "billing.Invoice/v1").unwrap(), invoice);
+
+        // Same layout, different type: a clear error instead of garbage
+        let bytes = to_bytes_tagged(&invoice).unwrap();
+        assert_eq!(from_bytes_expect::<Invoice>(&bytes).unwrap(), invoice);
+        assert!(matches!(
+            from_bytes_expect::<Refund>(&bytes),
+            Err(Error::TypeMismatch { found: Some(_), .. })
+        ));
+
+        // Untagged payloads have no type, and tagged ones decode normally
+        let untagged = crate::to_bytes(&invoice).unwrap();
+        assert_eq!(peek_type(&untagged).unwrap(), None);
+        assert!(matches!(from_bytes_expect::<Invoice>(&untagged), Err(Error::TypeMismatch { found: None, .. })));
+        assert_eq!(crate::from_bytes::<Refund>(&bytes).unwrap(), Refund { id: 7, amount: 1200 });
+    }
+}
@@ -0,0 +1,172 @@
+//! UUIDs as 16 raw bytes
+//!
+//! `uuid::Uuid` writes itself as a 36-character hyphenated string to any
+//! format that reports itself human-readable, which serde assumes by
+//! default. The [`UuidBytes`] wrapper, or
+//! `#[serde(with = "nanobit::uuid")]` on a field, writes the 16 bytes
+//! instead, so a UUID takes 17 bytes rather than 37:
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use uuid::Uuid;
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Session {
+//!     #[serde(with = "nanobit::uuid")]
+//!     id: Uuid,
+//!     user: u64,
+//! }
+//!
+//! let session = Session { id: Uuid::from_u128(0x936d_a01f_9abd_4d9d_80c7_02af_85c8_22a8), user: 7 };
+//! let bytes = nanobit::to_bytes(&session)?;
+//! assert!(bytes.len() < nanobit::to_bytes(&(session.id.to_string(), session.user))?.len() - 16);
+//! assert_eq!(nanobit::from_bytes::<Session>(&bytes)?, session);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! # Interop
+//!
+//! The UUID is a byte string holding its 16 bytes in RFC 9562 (big-endian)
+//! order, the same bytes `Uuid::as_bytes` returns. That is also what
+//! uuid's own serde impl writes to binary formats such as bincode and
+//! postcard, and what `Uuid::from_slice` reads back in other languages.
+//!
+//! Decoding accepts the text forms too (hyphenated, simple, braced and
+//! URN), so a field can switch to the compact encoding while payloads
+//! written with uuid's string form remain readable.
+
+use core::fmt;
+
+use serde::de::{Deserialize, Deserializer, Error as _, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use ::uuid::Uuid;
+
+/// A UUID written as its 16 raw bytes
+///
+/// ```rust
+/// use nanobit::UuidBytes;
+/// use uuid::Uuid;
+///
+/// let id = Uuid::from_u128(0x936d_a01f_9abd_4d9d_80c7_02af_85c8_22a8);
+/// let bytes = nanobit::to_bytes(&UuidBytes(id))?;
+/// assert!(bytes.ends_with(id.as_bytes()));
+///
+/// let UuidBytes(decoded) = nanobit::from_bytes::<UuidBytes>(&bytes)?;
+/// assert_eq!(decoded, id);
+/// # Ok::<(), nanobit::Error>(())
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UuidBytes(pub Uuid);
+
+impl From<Uuid> for UuidBytes {
+    fn from(uuid: Uuid) -> Self {
+        UuidBytes(uuid)
+    }
+}
+
+impl From<UuidBytes> for Uuid {
+    fn from(uuid: UuidBytes) -> Self {
+        uuid.0
+    }
+}
+
+impl Serialize for UuidBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UuidBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(UuidBytes)
+    }
+}
+
+/// Serialize a UUID as its 16 raw bytes
+pub fn serialize<S>(uuid: &Uuid, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_bytes(uuid.as_bytes())
+}
+
+/// Deserialize a UUID from its 16 raw bytes or any of its text forms
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Uuid, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct UuidVisitor;
+
+    impl<'de> Visitor<'de> for UuidVisitor {
+        type Value = Uuid;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("16 bytes or a string holding a UUID")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Uuid, E> {
+            // Without type tags a string reaches here as its UTF-8 bytes
+            match bytes.len() {
+                16 => Ok(Uuid::from_slice(bytes).expect("length checked")),
+                _ => Uuid::try_parse_ascii(bytes).map_err(|_| E::invalid_length(bytes.len(), &self)),
+            }
+        }
+
+        fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<Uuid, E> {
+            Uuid::try_parse(text).map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Uuid, A::Error> {
+            let mut bytes = [0u8; 16];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(i, &self))?;
+            }
+            if seq.next_element::<u8>()?.is_some() {
+                return Err(A::Error::invalid_length(17, &self));
+            }
+            Ok(Uuid::from_bytes(bytes))
+        }
+    }
+
+    deserializer.deserialize_bytes(UuidVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    const ID: Uuid = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+
+    #[test]
+    fn test_uuid_bytes_roundtrip() {
+        for config in [Config::new(), Config::new().with_self_describing(true), Config::new().with_canonical(true)] {
+            let bytes = crate::to_bytes_with_config(&UuidBytes(ID), config).unwrap();
+            let text = crate::to_bytes_with_config(&ID.to_string(), config).unwrap();
+            assert_eq!(bytes.len() + 20, text.len());
+            assert_eq!(crate::from_bytes::<UuidBytes>(&bytes).unwrap(), UuidBytes(ID));
+
+            // Payloads holding the string form still decode
+            assert_eq!(crate::from_bytes::<UuidBytes>(&text).unwrap(), UuidBytes(ID));
+        }
+
+        let bytes = crate::to_bytes(&vec![Some(UuidBytes(ID)), None]).unwrap();
+        assert_eq!(crate::from_bytes::<Vec<Option<UuidBytes>>>(&bytes).unwrap(), [Some(UuidBytes(ID)), None]);
+    }
+
+    #[test]
+    fn test_uuid_bytes_rejects_other_lengths() {
+        // Without type tags a byte string is written like a `Vec<u8>`
+        assert!(crate::from_bytes::<UuidBytes>(&crate::to_bytes(&vec![0u8; 15]).unwrap()).is_err());
+        assert!(crate::from_bytes::<UuidBytes>(&crate::to_bytes(&vec![b'0'; 36]).unwrap()).is_err());
+        let simple = ID.simple().to_string();
+        assert_eq!(crate::from_bytes::<UuidBytes>(&crate::to_bytes(&simple).unwrap()).unwrap(), UuidBytes(ID));
+    }
+}
@@ -0,0 +1,514 @@
+//! Checking payloads without decoding them into Rust values
+//!
+//! [`validate`] walks a self-describing payload tag by tag, and
+//! [`validate_with_schema`] walks any payload with the [`Schema`] of the
+//! type that wrote it. Both verify the header and any checksum, string
+//! UTF-8, lengths against the remaining input and the [`Limits`] given,
+//! and that nothing trails the value, while only borrowing from the input.
+//! That makes them a cheap gate for untrusted uploads:
+//!
+//! ```rust
+//! use nanobit::validate::{validate, Limits};
+//! use nanobit::Config;
+//!
+//! let upload = nanobit::to_bytes_with_config(&vec![vec![1u8; 4]; 3], Config::new().with_self_describing(true))?;
+//!
+//! let report = validate(&upload, Limits::new())?;
+//! assert_eq!((report.values, report.depth, report.longest_collection), (16, 2, 4));
+//!
+//! assert!(validate(&upload, Limits::new().with_max_depth(1)).is_err());
+//! assert!(validate(&upload[..upload.len() - 1], Limits::new()).is_err());
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Compressed payloads are checked after decompressing them with
+//! [`decompress_with_limit`](crate::decompress_with_limit).
+
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, EnumAccess, IgnoredAny, MapAccess, SeqAccess, VariantAccess, Visitor};
+
+use crate::checksum::split_payload;
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+use crate::schema::{field_names, Container, Field, FieldSeed, Schema, Type, Variant, VariantKind, VariantSeed};
+
+/// Bounds a valid payload must stay within
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    max_depth: usize,
+    max_len: usize,
+    max_str_len: usize,
+}
+
+impl Limits {
+    /// Allow nesting up to 128 levels deep and any lengths the input holds
+    pub const fn new() -> Self {
+        Self {
+            max_depth: 128,
+            max_len: usize::MAX,
+            max_str_len: usize::MAX,
+        }
+    }
+
+    /// Limit how deeply sequences, maps and enums nest
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Get the maximum nesting depth
+    #[inline]
+    pub const fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Limit the number of elements or entries in one sequence or map
+    pub const fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Get the maximum sequence or map length
+    #[inline]
+    pub const fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Limit the length in bytes of one string or byte string
+    pub const fn with_max_str_len(mut self, max_str_len: usize) -> Self {
+        self.max_str_len = max_str_len;
+        self
+    }
+
+    /// Get the maximum string length
+    #[inline]
+    pub const fn max_str_len(&self) -> usize {
+        self.max_str_len
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Summary of a valid payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// The payload header
+    pub header: Header,
+    /// Length of the body between the header and any checksum
+    pub body_len: usize,
+    /// Number of values, counting every element, key and field
+    pub values: u64,
+    /// Deepest nesting of sequences, maps and enums
+    pub depth: usize,
+    /// Largest number of elements or entries in one sequence or map
+    pub longest_collection: usize,
+    /// Length in bytes of the longest string or byte string
+    pub longest_string: usize,
+}
+
+/// Check a self-describing payload
+pub fn validate(bytes: &[u8], limits: Limits) -> Result<Report> {
+    let (header, _) = split_payload(bytes)?;
+    if !header.flags.contains(HeaderFlags::SELF_DESCRIBING) {
+        return Err(Error::InvalidFormat(
+            "Payload is not self-describing; validate it with a schema".to_string(),
+        ));
+    }
+    walk(bytes, limits, |walker, de| Node { walker, shape: None }.deserialize(de))
+}
+
+/// Check a payload written by the type `schema` describes
+pub fn validate_with_schema(bytes: &[u8], schema: &Schema, limits: Limits) -> Result<Report> {
+    walk(bytes, limits, |walker, de| {
+        Node { walker, shape: Some((schema, &schema.root)) }.deserialize(de)
+    })
+}
+
+fn walk<'de, F>(bytes: &'de [u8], limits: Limits, root: F) -> Result<Report>
+where
+    F: FnOnce(&mut Walker, &mut Deserializer<'de>) -> Result<()>,
+{
+    let (header, body) = split_payload(bytes)?;
+    let mut walker = Walker {
+        limits,
+        depth: 0,
+        report: Report {
+            header,
+            body_len: body.len(),
+            values: 0,
+            depth: 0,
+            longest_collection: 0,
+            longest_string: 0,
+        },
+        exceeded: None,
+    };
+    let mut de = Deserializer::new(bytes)?;
+    if let Err(err) = root(&mut walker, &mut de) {
+        return Err(walker.exceeded.take().unwrap_or(err));
+    }
+    match de.reader.remaining() {
+        0 => Ok(walker.report),
+        n => Err(Error::InvalidFormat(format!("{n} trailing bytes after the value"))),
+    }
+}
+
+struct Walker {
+    limits: Limits,
+    depth: usize,
+    report: Report,
+    // Serde errors only carry a message, so the limit hit is kept here
+    exceeded: Option<Error>,
+}
+
+impl Walker {
+    fn enter<E: de::Error>(&mut self) -> core::result::Result<(), E> {
+        self.depth += 1;
+        if self.depth > self.limits.max_depth {
+            return Err(self.exceed("Nesting depth", self.limits.max_depth));
+        }
+        self.report.depth = self.report.depth.max(self.depth);
+        Ok(())
+    }
+
+    fn exceed<E: de::Error>(&mut self, what: &'static str, limit: usize) -> E {
+        let err = Error::LimitExceeded { what, limit };
+        let message = E::custom(&err);
+        self.exceeded = Some(err);
+        message
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn collection<E: de::Error>(&mut self, len: usize) -> core::result::Result<(), E> {
+        if len > self.limits.max_len {
+            return Err(self.exceed("Collection length", self.limits.max_len));
+        }
+        self.report.longest_collection = self.report.longest_collection.max(len);
+        Ok(())
+    }
+
+    fn string<E: de::Error>(&mut self, len: usize) -> core::result::Result<(), E> {
+        if len > self.limits.max_str_len {
+            return Err(self.exceed("String length", self.limits.max_str_len));
+        }
+        self.report.longest_string = self.report.longest_string.max(len);
+        Ok(())
+    }
+}
+
+type Shape<'s> = Option<(&'s Schema, &'s Type)>;
+
+// One value to walk, with its type when a schema is known
+struct Node<'w, 's> {
+    walker: &'w mut Walker,
+    shape: Shape<'s>,
+}
+
+impl<'de> DeserializeSeed<'de> for Node<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        self.walker.report.values += 1;
+        if let Some((schema, ty)) = self.shape {
+            return typed(self.walker, schema, ty, deserializer);
+        }
+        deserializer.deserialize_any(Visit { walker: self.walker, children: Children::Any })
+    }
+}
+
+// Drive the deserializer the way the described type would
+fn typed<'de, D>(walker: &mut Walker, schema: &Schema, ty: &Type, de: D) -> core::result::Result<(), D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    match ty {
+        Type::Unit => de.deserialize_unit(Visit { walker, children: Children::Any }),
+        Type::Bool => de.deserialize_bool(Visit { walker, children: Children::Any }),
+        Type::I8 => de.deserialize_i8(Visit { walker, children: Children::Any }),
+        Type::I16 => de.deserialize_i16(Visit { walker, children: Children::Any }),
+        Type::I32 => de.deserialize_i32(Visit { walker, children: Children::Any }),
+        Type::I64 => de.deserialize_i64(Visit { walker, children: Children::Any }),
+        Type::I128 => de.deserialize_i128(Visit { walker, children: Children::Any }),
+        Type::U8 => de.deserialize_u8(Visit { walker, children: Children::Any }),
+        Type::U16 => de.deserialize_u16(Visit { walker, children: Children::Any }),
+        Type::U32 => de.deserialize_u32(Visit { walker, children: Children::Any }),
+        Type::U64 => de.deserialize_u64(Visit { walker, children: Children::Any }),
+        Type::U128 => de.deserialize_u128(Visit { walker, children: Children::Any }),
+        Type::F32 => de.deserialize_f32(Visit { walker, children: Children::Any }),
+        Type::F64 => de.deserialize_f64(Visit { walker, children: Children::Any }),
+        Type::Char => de.deserialize_char(Visit { walker, children: Children::Any }),
+        Type::Str => de.deserialize_str(Visit { walker, children: Children::Any }),
+        Type::Bytes => de.deserialize_bytes(Visit { walker, children: Children::Any }),
+        Type::Option(inner) => de.deserialize_option(Visit { walker, children: Children::One(schema, inner) }),
+        Type::Seq(item) => de.deserialize_seq(Visit { walker, children: Children::One(schema, item) }),
+        Type::Tuple(items) => de.deserialize_tuple(items.len(), Visit { walker, children: Children::Items(schema, items) }),
+        Type::Map { key, value } => de.deserialize_map(Visit { walker, children: Children::Map(schema, key, value) }),
+        Type::Named(name) => match schema.get(name) {
+            // Names only matter to the deserializer for struct fields
+            Some(Container::UnitStruct) => de.deserialize_unit_struct("", Visit { walker, children: Children::Any }),
+            Some(Container::NewtypeStruct(inner)) => {
+                de.deserialize_newtype_struct("", Visit { walker, children: Children::One(schema, inner) })
+            }
+            Some(Container::TupleStruct(items)) => {
+                de.deserialize_tuple_struct("", items.len(), Visit { walker, children: Children::Items(schema, items) })
+            }
+            Some(Container::Struct(fields)) => {
+                de.deserialize_struct("", field_names(fields), Visit { walker, children: Children::Fields(schema, fields) })
+            }
+            Some(Container::Enum(variants)) => de.deserialize_enum("", &[], Visit { walker, children: Children::Variants(schema, variants) }),
+            None => Err(de::Error::custom(format!("Schema has no type named {name}"))),
+        },
+    }
+}
+
+// What the contents of a compound value are
+enum Children<'s> {
+    Any,
+    One(&'s Schema, &'s Type),
+    Items(&'s Schema, &'s [Type]),
+    Map(&'s Schema, &'s Type, &'s Type),
+    Fields(&'s Schema, &'s [Field]),
+    Variants(&'s Schema, &'s [Variant]),
+}
+
+struct Visit<'w, 's> {
+    walker: &'w mut Walker,
+    children: Children<'s>,
+}
+
+impl<'w, 's> Visit<'w, 's> {
+    fn node(&mut self, shape: Shape<'s>) -> Node<'_, 's> {
+        Node { walker: self.walker, shape }
+    }
+
+    // Shape of the element at `index` of a sequence
+    fn element(&self, index: usize) -> Option<Shape<'s>> {
+        match self.children {
+            Children::Any => Some(None),
+            Children::One(schema, ty) => Some(Some((schema, ty))),
+            Children::Items(schema, items) => items.get(index).map(|ty| Some((schema, ty))),
+            Children::Fields(schema, fields) => fields.get(index).map(|field| Some((schema, &field.ty))),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! scalars {
+    ($($method:ident: $ty:ty),*) => {$(
+        fn $method<E: de::Error>(self, _: $ty) -> core::result::Result<(), E> {
+            Ok(())
+        }
+    )*};
+}
+
+impl<'de> Visitor<'de> for Visit<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a nanobit value")
+    }
+
+    scalars! {
+        visit_bool: bool, visit_i8: i8, visit_i16: i16, visit_i32: i32, visit_i64: i64, visit_i128: i128,
+        visit_u8: u8, visit_u16: u16, visit_u32: u32, visit_u64: u64, visit_u128: u128,
+        visit_f32: f32, visit_f64: f64, visit_char: char
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> core::result::Result<(), E> {
+        self.walker.string(s.len())
+    }
+
+    fn visit_bytes<E: de::Error>(self, b: &[u8]) -> core::result::Result<(), E> {
+        self.walker.string(b.len())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> core::result::Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_none<E: de::Error>(self) -> core::result::Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_some<D>(mut self, deserializer: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let shape = self.element(0).flatten();
+        self.node(shape).deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        self.visit_some(deserializer)
+    }
+
+    fn visit_seq<A>(mut self, mut seq: A) -> core::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.walker.enter()?;
+        if let Some(len) = seq.size_hint() {
+            self.walker.collection(len)?;
+        }
+        // Fixed-size values stop at their known length, as derived types do
+        let mut len = 0;
+        while let Some(shape) = self.element(len) {
+            if seq.next_element_seed(self.node(shape))?.is_none() {
+                break;
+            }
+            len += 1;
+        }
+        self.walker.collection(len)?;
+        self.walker.leave();
+        Ok(())
+    }
+
+    fn visit_map<A>(mut self, mut map: A) -> core::result::Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.walker.enter()?;
+        if let Some(len) = map.size_hint() {
+            self.walker.collection(len)?;
+        }
+        let mut len = 0;
+        loop {
+            let value = match self.children {
+                Children::Map(schema, key, value) => {
+                    if map.next_key_seed(self.node(Some((schema, key))))?.is_none() {
+                        break;
+                    }
+                    Some(Some((schema, value)))
+                }
+                Children::Fields(schema, fields) => {
+                    self.walker.report.values += 1;
+                    let Some(index) = map.next_key_seed(FieldSeed(fields))? else {
+                        break;
+                    };
+                    index.map(|index| Some((schema, &fields[index].ty)))
+                }
+                _ => {
+                    if map.next_key_seed(self.node(None))?.is_none() {
+                        break;
+                    }
+                    Some(None)
+                }
+            };
+            match value {
+                Some(shape) => map.next_value_seed(self.node(shape))?,
+                // Field the schema does not know
+                None => map.next_value::<IgnoredAny>().map(|_| ())?,
+            }
+            len += 1;
+        }
+        self.walker.collection(len)?;
+        self.walker.leave();
+        Ok(())
+    }
+
+    fn visit_enum<A>(mut self, data: A) -> core::result::Result<(), A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        self.walker.enter()?;
+        match self.children {
+            Children::Variants(schema, variants) => {
+                let (index, access) = data.variant_seed(VariantSeed(variants))?;
+                let walker = &mut *self.walker;
+                match &variants[index].kind {
+                    VariantKind::Unit => access.unit_variant()?,
+                    VariantKind::Newtype(ty) => access.newtype_variant_seed(Node { walker, shape: Some((schema, ty)) })?,
+                    VariantKind::Tuple(items) => {
+                        access.tuple_variant(items.len(), Visit { walker, children: Children::Items(schema, items) })?
+                    }
+                    VariantKind::Struct(fields) => access.struct_variant(
+                        field_names(fields),
+                        Visit { walker, children: Children::Fields(schema, fields) },
+                    )?,
+                }
+            }
+            _ => {
+                let (IgnoredAny, access) = data.variant()?;
+                access.newtype_variant_seed(self.node(None))?;
+            }
+        }
+        self.walker.leave();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Upload {
+        name: String,
+        tags: Vec<String>,
+        shapes: Vec<Shape>,
+        thumbnail: Option<(u16, u16)>,
+    }
+
+    #[test]
+    fn test_validate_with_schema() {
+        let upload = Upload {
+            name: "scene".to_string(),
+            tags: vec!["a".to_string(), "bb".to_string()],
+            shapes: vec![Shape::Point, Shape::Circle(1.0), Shape::Rect { w: 2, h: 3 }],
+            thumbnail: Some((64, 48)),
+        };
+        let schema = Schema::of::<Upload>().unwrap();
+        for config in [
+            Config::new(),
+            Config::new().with_tagged_fields(true),
+            Config::new().with_self_describing(true),
+            Config::new().with_string_table(true).with_fixed_tuples(true),
+        ] {
+            let bytes = crate::to_bytes_with_config(&upload, config).unwrap();
+            let report = validate_with_schema(&bytes, &schema, Limits::new()).unwrap();
+            assert_eq!((report.depth, report.longest_collection, report.longest_string), (4, 4, 5));
+
+            // Truncated and padded payloads are rejected
+            assert!(validate_with_schema(&bytes[..bytes.len() - 1], &schema, Limits::new()).is_err());
+            let mut padded = bytes.clone();
+            padded.push(0);
+            assert!(validate_with_schema(&padded, &schema, Limits::new()).is_err());
+
+            let tight = Limits::new().with_max_str_len(4);
+            assert!(matches!(
+                validate_with_schema(&bytes, &schema, tight),
+                Err(Error::LimitExceeded { what: "String length", limit: 4 })
+            ));
+        }
+
+        // Invalid UTF-8 in a string
+        let mut bytes = crate::to_bytes(&upload).unwrap();
+        let at = bytes.windows(5).position(|w| w == b"scene").unwrap();
+        bytes[at] = 0xFF;
+        assert!(validate_with_schema(&bytes, &schema, Limits::new()).is_err());
+        assert!(validate(&bytes, Limits::new()).is_err());
+    }
+}
@@ -0,0 +1,1021 @@
+//! Dynamic document model for payloads whose Rust type is not available
+//!
+//! A [`Value`] can hold anything the serde data model can express. Payloads
+//! written in self-describing mode (see [`Config::with_self_describing`]) can
+//! be decoded straight into a `Value`, inspected or edited, and written back
+//! without knowing the original type.
+//!
+//! [`Config::with_self_describing`]: crate::Config::with_self_describing
+
+#[cfg(not(feature = "std"))]
+use alloc::{borrow::ToOwned, boxed::Box, string::{String, ToString}, vec::Vec};
+
+use core::fmt;
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
+    Visitor,
+};
+use serde::ser::{self, Serialize};
+use serde::{Deserialize, Deserializer};
+
+use crate::error::{Error, Result};
+
+// Newtype name the serializers recognise to write a `Value::Variant` as a
+// real enum variant rather than as a tuple
+pub(crate) const VARIANT_MARKER: &str = "$nanobit::private::Variant";
+
+/// An owned, dynamically typed value
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Value {
+    /// Unit, `None` or a missing value
+    #[default]
+    Null,
+    /// Boolean
+    Bool(bool),
+    /// Signed integer
+    Int(i64),
+    /// Unsigned integer
+    UInt(u64),
+    /// Floating point number
+    Float(f64),
+    /// Unicode scalar value
+    Char(char),
+    /// UTF-8 string
+    Str(String),
+    /// Byte string
+    Bytes(Vec<u8>),
+    /// Sequence, tuple or array
+    Seq(Vec<Value>),
+    /// Map or struct, in encoded order
+    Map(Vec<(Value, Value)>),
+    /// Enum variant with its payload (`Null` for unit variants)
+    Variant {
+        /// Variant index
+        index: u32,
+        /// Variant name, empty if unknown
+        name: String,
+        /// Variant payload
+        value: Box<Value>,
+    },
+}
+
+impl Value {
+    /// Check whether the value is `Null`
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Get the value as a bool
+    #[inline]
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Get the value as an i64, if it is an integer in range
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            Value::UInt(u) => i64::try_from(*u).ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a u64, if it is an integer in range
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Int(i) => u64::try_from(*i).ok(),
+            Value::UInt(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// Get the value as an f64, converting integers
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            Value::Int(i) => Some(*i as f64),
+            Value::UInt(u) => Some(*u as f64),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a string slice
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get the value as a byte slice
+    #[inline]
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Get the elements of a sequence
+    #[inline]
+    pub fn as_seq(&self) -> Option<&[Value]> {
+        match self {
+            Value::Seq(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Get the entries of a map
+    #[inline]
+    pub fn as_map(&self) -> Option<&[(Value, Value)]> {
+        match self {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Look up a map entry by string key, such as a struct field
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_map()?
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Get a mutable reference to a map entry by string key
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter_mut()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Short name of the value's kind, for error messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "signed integer",
+            Value::UInt(_) => "unsigned integer",
+            Value::Float(_) => "float",
+            Value::Char(_) => "char",
+            Value::Str(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Seq(_) => "sequence",
+            Value::Map(_) => "map",
+            Value::Variant { .. } => "enum variant",
+        }
+    }
+}
+
+/// Convert any serializable value into a [`Value`]
+pub fn to_value<T>(value: &T) -> Result<Value>
+where
+    T: Serialize + ?Sized,
+{
+    value.serialize(ValueSerializer)
+}
+
+/// Convert a [`Value`] into any deserializable type
+pub fn from_value<T>(value: Value) -> Result<T>
+where
+    T: de::DeserializeOwned,
+{
+    T::deserialize(value)
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use serde::ser::{SerializeMap, SerializeSeq};
+
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::UInt(u) => serializer.serialize_u64(*u),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::Char(c) => serializer.serialize_char(*c),
+            Value::Str(s) => serializer.serialize_str(s),
+            Value::Bytes(b) => serializer.serialize_bytes(b),
+            Value::Seq(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Value::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+            Value::Variant { index, name, value } => serializer.serialize_newtype_struct(
+                VARIANT_MARKER,
+                &(*index, name.as_str(), value.as_ref()),
+            ),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> core::result::Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> core::result::Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> core::result::Result<Value, E> {
+        Ok(Value::UInt(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> core::result::Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_char<E>(self, v: char) -> core::result::Result<Value, E> {
+        Ok(Value::Char(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> core::result::Result<Value, E> {
+        Ok(Value::Str(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> core::result::Result<Value, E> {
+        Ok(Value::Str(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> core::result::Result<Value, E> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> core::result::Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_none<E>(self) -> core::result::Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_unit<E>(self) -> core::result::Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> core::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> core::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Seq(items))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> core::result::Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Value::Map(entries))
+    }
+
+    fn visit_enum<A>(self, data: A) -> core::result::Result<Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let ((index, name), variant) = data.variant_seed(VariantIdSeed)?;
+        let value = variant.newtype_variant::<Value>()?;
+        Ok(Value::Variant {
+            index,
+            name,
+            value: Box::new(value),
+        })
+    }
+}
+
+// Reads a variant identifier as (index, name), keeping whichever parts the
+// format provides
+struct VariantIdSeed;
+
+impl<'de> DeserializeSeed<'de> for VariantIdSeed {
+    type Value = (u32, String);
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(2, VariantIdVisitor)
+    }
+}
+
+struct VariantIdVisitor;
+
+impl<'de> Visitor<'de> for VariantIdVisitor {
+    type Value = (u32, String);
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a variant index or name")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> core::result::Result<Self::Value, E> {
+        let index = u32::try_from(v).map_err(|_| E::custom("variant index out of range"))?;
+        Ok((index, String::new()))
+    }
+
+    fn visit_str<E>(self, v: &str) -> core::result::Result<Self::Value, E> {
+        Ok((0, v.to_owned()))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let index = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let name = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok((index, name))
+    }
+}
+
+// Serializer producing a `Value`
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVec;
+    type SerializeMap = SerializeEntries;
+    type SerializeStruct = SerializeEntries;
+    type SerializeStructVariant = SerializeEntries;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        Ok(Value::UInt(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        Ok(Value::UInt(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        Ok(Value::UInt(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        Ok(Value::UInt(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        Ok(Value::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::Str(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        Ok(variant_value(variant_index, variant, Value::Null))
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Value>
+    where
+        T: Serialize + ?Sized,
+    {
+        let inner = value.serialize(self)?;
+        if name != VARIANT_MARKER {
+            return Ok(inner);
+        }
+        // (index, name, value) written by `Value::Variant`
+        match inner {
+            Value::Seq(parts) => match <[Value; 3]>::try_from(parts) {
+                Ok([Value::UInt(index), Value::Str(name), value]) => Ok(Value::Variant {
+                    index: index as u32,
+                    name,
+                    value: Box::new(value),
+                }),
+                _ => Err(Error::Serde("Malformed variant marker".to_string())),
+            },
+            _ => Err(Error::Serde("Malformed variant marker".to_string())),
+        }
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value>
+    where
+        T: Serialize + ?Sized,
+    {
+        Ok(variant_value(variant_index, variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec> {
+        Ok(SerializeVec {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeVec> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec> {
+        Ok(SerializeVec {
+            items: Vec::with_capacity(len),
+            variant: Some((variant_index, variant)),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeEntries> {
+        Ok(SerializeEntries {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            pending_key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeEntries> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeEntries> {
+        Ok(SerializeEntries {
+            entries: Vec::with_capacity(len),
+            pending_key: None,
+            variant: Some((variant_index, variant)),
+        })
+    }
+}
+
+fn variant_value(index: u32, name: &str, value: Value) -> Value {
+    Value::Variant {
+        index,
+        name: name.to_owned(),
+        value: Box::new(value),
+    }
+}
+
+// Collects sequence and tuple elements
+struct SerializeVec {
+    items: Vec<Value>,
+    variant: Option<(u32, &'static str)>,
+}
+
+impl SerializeVec {
+    fn finish(self) -> Value {
+        let seq = Value::Seq(self.items);
+        match self.variant {
+            Some((index, name)) => variant_value(index, name, seq),
+            None => seq,
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.finish())
+    }
+}
+
+// Collects map entries and struct fields
+struct SerializeEntries {
+    entries: Vec<(Value, Value)>,
+    pending_key: Option<Value>,
+    variant: Option<(u32, &'static str)>,
+}
+
+impl SerializeEntries {
+    fn field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.entries
+            .push((Value::Str(key.to_owned()), value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        let map = Value::Map(self.entries);
+        match self.variant {
+            Some((index, name)) => variant_value(index, name, map),
+            None => map,
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeEntries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.pending_key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self
+            .pending_key
+            .take()
+            .ok_or_else(|| Error::Serde("Map value without a key".to_string()))?;
+        self.entries.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for SerializeEntries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeEntries {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(self.finish())
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Int(i) => visitor.visit_i64(i),
+            Value::UInt(u) => visitor.visit_u64(u),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Char(c) => visitor.visit_char(c),
+            Value::Str(s) => visitor.visit_string(s),
+            Value::Bytes(b) => visitor.visit_byte_buf(b),
+            Value::Seq(items) => {
+                let mut seq = de::value::SeqDeserializer::new(items.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Map(entries) => {
+                let mut map = de::value::MapDeserializer::new(entries.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            Value::Variant { index, name, value } => visitor.visit_enum(ValueEnumAccess {
+                index,
+                name,
+                value: *value,
+            }),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Variant { .. } => self.deserialize_any(visitor),
+            // Unit variant by name
+            Value::Str(name) => visitor.visit_enum(ValueEnumAccess {
+                index: 0,
+                name,
+                value: Value::Null,
+            }),
+            // Externally tagged: a single `name => payload` entry
+            Value::Map(entries) if entries.len() == 1 => {
+                let (key, value) = entries.into_iter().next().unwrap_or_default();
+                match key {
+                    Value::Str(name) => visitor.visit_enum(ValueEnumAccess {
+                        index: 0,
+                        name,
+                        value,
+                    }),
+                    other => Err(Error::Serde(format!(
+                        "Expected variant name, found {}",
+                        other.kind()
+                    ))),
+                }
+            }
+            other => Err(Error::Serde(format!(
+                "Expected enum variant, found {}",
+                other.kind()
+            ))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+// Enum access over an owned variant
+struct ValueEnumAccess {
+    index: u32,
+    name: String,
+    value: Value,
+}
+
+impl<'de> EnumAccess<'de> for ValueEnumAccess {
+    type Error = Error;
+    type Variant = Value;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Value)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let id = if self.name.is_empty() {
+            seed.deserialize(de::value::U32Deserializer::<Error>::new(self.index))?
+        } else {
+            seed.deserialize(de::value::StringDeserializer::<Error>::new(self.name))?
+        };
+        Ok((id, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Value {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_bytes_with_config, Config};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Doc {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+        parent: Option<u32>,
+        shapes: Vec<Shape>,
+    }
+
+    fn sample() -> Doc {
+        Doc {
+            id: 7,
+            name: "doc".to_string(),
+            tags: vec!["a".to_string(), "b".to_string()],
+            parent: None,
+            shapes: vec![Shape::Empty, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+        }
+    }
+
+    #[test]
+    fn test_self_describing_roundtrip() {
+        let config = Config::new().with_self_describing(true);
+        let bytes = to_bytes_with_config(&sample(), config).unwrap();
+        let decoded: Doc = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, sample());
+    }
+
+    #[test]
+    fn test_payload_into_value() {
+        let config = Config::new().with_self_describing(true);
+        let bytes = to_bytes_with_config(&sample(), config).unwrap();
+        let value: Value = from_bytes(&bytes).unwrap();
+
+        assert_eq!(value.get("id").and_then(Value::as_u64), Some(7));
+        assert_eq!(value.get("name").and_then(Value::as_str), Some("doc"));
+        assert!(value.get("parent").unwrap().is_null());
+        let shapes = value.get("shapes").and_then(Value::as_seq).unwrap();
+        assert_eq!(
+            shapes[2],
+            Value::Variant {
+                index: 2,
+                name: "Rect".to_string(),
+                value: Box::new(Value::Map(vec![
+                    (Value::Str("w".to_string()), Value::UInt(2)),
+                    (Value::Str("h".to_string()), Value::UInt(3)),
+                ])),
+            }
+        );
+
+        // A value re-encodes without its Rust type and still decodes as one
+        let reencoded = to_bytes_with_config(&value, config).unwrap();
+        assert_eq!(from_bytes::<Value>(&reencoded).unwrap(), value);
+        assert_eq!(from_bytes::<Doc>(&reencoded).unwrap(), sample());
+        assert_eq!(from_value::<Doc>(value).unwrap(), sample());
+    }
+
+    #[test]
+    fn test_to_value_from_value() {
+        let value = to_value(&sample()).unwrap();
+        assert_eq!(value.get("tags").and_then(Value::as_seq).map(<[_]>::len), Some(2));
+        assert_eq!(from_value::<Doc>(value).unwrap(), sample());
+
+        // Externally tagged maps and bare names also decode as variants
+        let rect = Value::Map(vec![(
+            Value::Str("Rect".to_string()),
+            Value::Map(vec![
+                (Value::Str("w".to_string()), Value::UInt(1)),
+                (Value::Str("h".to_string()), Value::UInt(4)),
+            ]),
+        )]);
+        assert_eq!(from_value::<Shape>(rect).unwrap(), Shape::Rect { w: 1, h: 4 });
+        assert_eq!(from_value::<Shape>(Value::Str("Empty".to_string())).unwrap(), Shape::Empty);
+    }
+
+    #[test]
+    fn test_unknown_fields_skipped() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Partial {
+            name: String,
+        }
+
+        let config = Config::new().with_self_describing(true);
+        let bytes = to_bytes_with_config(&sample(), config).unwrap();
+        let partial: Partial = from_bytes(&bytes).unwrap();
+        assert_eq!(partial.name, "doc");
+
+        // Plain payloads carry no types to decode a value from
+        let plain = crate::to_bytes(&sample()).unwrap();
+        assert!(from_bytes::<Value>(&plain).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+//! Golden test vectors for the wire format
+//!
+//! Each [`Vector`] pairs a value from the serde data model with the exact
+//! bytes nanobit writes for it under a given [`Config`]. The vectors live
+//! in `vectors/golden.txt` in the repository and the test suite checks
+//! that the serializer reproduces every one of them byte for byte and
+//! decodes them back to the same value.
+//!
+//! The vectors are the format's stability guarantee: a release never
+//! changes the bytes of an existing vector. Payloads stored today decode
+//! with every later release, and another implementation that reproduces
+//! the vectors can exchange data with this one.
+//!
+//! ```rust
+//! let vector = nanobit::vectors::vector("u32.300").unwrap();
+//! assert_eq!(vector.value, "300u32");
+//! assert_eq!(nanobit::to_bytes_with_config(&300u32, vector.config)?, vector.bytes);
+//! # Ok::<(), nanobit::Error>(())
+//! ```
+//!
+//! Each line of `golden.txt` reads `name | config | value | hex`, where the
+//! config is `default` or options joined by `+`: `tagged`,
+//! `self-describing`, `canonical`, `string-table`, `compact-chars`,
+//! `fixed-tuples`, `niche-options`, `crc32c` and `xxh64`. Lines starting with `#` are
+//! comments.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "checksum")]
+use crate::checksum::Checksum;
+use crate::config::Config;
+
+static GOLDEN: &str = include_str!("../vectors/golden.txt");
+
+/// One value and its canonical encoding
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vector {
+    /// Name of the case, like `"struct.tagged"`
+    pub name: &'static str,
+    /// The value, written as a Rust expression
+    pub value: &'static str,
+    /// Configuration the payload is written with
+    pub config: Config,
+    /// The encoded payload, header included
+    pub bytes: Vec<u8>,
+}
+
+/// Every golden vector, in file order
+pub fn vectors() -> Vec<Vector> {
+    GOLDEN
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_line)
+        .collect()
+}
+
+/// The golden vector named `name`
+pub fn vector(name: &str) -> Option<Vector> {
+    vectors().into_iter().find(|vector| vector.name == name)
+}
+
+// The file is part of the crate and checked by its tests, so a malformed
+// line is a bug here rather than bad input. Vectors with options that are
+// not compiled in are left out.
+fn parse_line(line: &'static str) -> Option<Vector> {
+    let mut columns = line.split(" | ");
+    let mut column = || columns.next().expect("golden vectors have four columns");
+    let (name, config, value, hex) = (column(), column(), column(), column());
+    Some(Vector {
+        name,
+        value,
+        config: parse_config(config)?,
+        bytes: (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("golden vectors are hex"))
+            .collect(),
+    })
+}
+
+fn parse_config(spec: &str) -> Option<Config> {
+    let mut config = Config::new();
+    if spec == "default" {
+        return Some(config);
+    }
+    for option in spec.split('+') {
+        config = match option {
+            "tagged" => config.with_tagged_fields(true),
+            "self-describing" => config.with_self_describing(true),
+            "canonical" => config.with_canonical(true),
+            "string-table" => config.with_string_table(true),
+            "compact-chars" => config.with_compact_chars(true),
+            "fixed-tuples" => config.with_fixed_tuples(true),
+            "niche-options" => config.with_niche_options(true),
+            #[cfg(feature = "checksum")]
+            "crc32c" => config.with_checksum(Some(Checksum::Crc32c)),
+            #[cfg(feature = "checksum")]
+            "xxh64" => config.with_checksum(Some(Checksum::XxHash64)),
+            #[cfg(not(feature = "checksum"))]
+            "crc32c" | "xxh64" => return None,
+            other => panic!("unknown golden vector option {other:?}"),
+        };
+    }
+    Some(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fmt::Write as _;
+
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_bytes_with_config, to_bytes_with_config};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Unit;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Meters(f64);
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Point(i16, i16);
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Start,
+        Tick(u64),
+        Move(i8, i8),
+        Rename { from: String, to: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Record {
+        id: u32,
+        name: String,
+        tags: Vec<String>,
+        parent: Option<u32>,
+        event: Event,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Blob {
+        #[serde(with = "crate::byte_slice")]
+        data: Vec<u8>,
+    }
+
+    struct Case {
+        name: &'static str,
+        config: &'static str,
+        value: &'static str,
+        bytes: Vec<u8>,
+    }
+
+    // Encode `value`, checking that it decodes back unchanged
+    fn case<T>(name: &'static str, config: &'static str, value: &'static str, data: T) -> Case
+    where
+        T: Serialize + DeserializeOwned + PartialEq + core::fmt::Debug,
+    {
+        let bytes = to_bytes_with_config(&data, parse_config(config).unwrap()).unwrap();
+        let decoded: T = from_bytes_with_config(&bytes, parse_config(config).unwrap()).unwrap();
+        assert_eq!(decoded, data, "{name} does not round-trip");
+        Case { name, config, value, bytes }
+    }
+
+    macro_rules! case {
+        ($name:literal, $config:literal, $value:expr) => {
+            case($name, $config, stringify!($value), $value)
+        };
+    }
+
+    const RECORD: &str = r#"Record { id: 7, name: "node", tags: ["a", "node"], parent: Some(1), event: Event::Rename { from: "x", to: "y" } }"#;
+
+    fn record() -> Record {
+        Record {
+            id: 7,
+            name: "node".to_string(),
+            tags: vec!["a".to_string(), "node".to_string()],
+            parent: Some(1),
+            event: Event::Rename { from: "x".to_string(), to: "y".to_string() },
+        }
+    }
+
+    fn cases() -> Vec<Case> {
+        let mut cases = vec![
+            case!("bool.false", "default", false),
+            case!("bool.true", "default", true),
+            case!("i8.min", "default", i8::MIN),
+            case!("i16.-300", "default", -300i16),
+            case!("i32.-1", "default", -1i32),
+            case!("i32.max", "default", i32::MAX),
+            case!("i64.min", "default", i64::MIN),
+            case!("u8.255", "default", 255u8),
+            case!("u16.300", "default", 300u16),
+            case!("u32.300", "default", 300u32),
+            case!("u64.max", "default", u64::MAX),
+            case!("f32.1.5", "default", 1.5f32),
+            case!("f64.pi", "default", core::f64::consts::PI),
+            case!("f64.-0", "default", -0.0f64),
+            case!("char.ascii", "default", 'n'),
+            case!("char.astral", "default", '🦀'),
+            case!("char.compact", "compact-chars", '🦀'),
+            case!("string.empty", "default", String::new()),
+            case!("string.utf8", "default", String::from("héllo")),
+            case!("bytes", "default", Blob { data: vec![0, 1, 254, 255] }),
+            case!("option.none", "default", None::<u8>),
+            case!("option.some", "default", Some(5u8)),
+            case!("option.niche-none", "niche-options", None::<String>),
+            case!("option.niche-some", "niche-options", Some(String::from("ab"))),
+            case!("option.niche-seq", "niche-options", Some(vec![1u16, 2])),
+            case!("unit", "default", ()),
+            case!("unit-struct", "default", Unit),
+            case!("newtype-struct", "default", Meters(2.5)),
+            case!("seq.empty", "default", Vec::<u32>::new()),
+            case!("seq", "default", vec![1u32, 128, 16384]),
+            case!("tuple", "default", (1u8, String::from("b"), true)),
+            case!("tuple.fixed", "fixed-tuples", (1u8, String::from("b"), true)),
+            case!("tuple-struct", "default", Point(-1, 2)),
+            case!("map", "default", BTreeMap::from([(String::from("a"), 1u8), (String::from("b"), 2)])),
+            case!("unit-variant", "default", Event::Start),
+            case!("newtype-variant", "default", Event::Tick(1000)),
+            case!("tuple-variant", "default", Event::Move(-1, 1)),
+            case!("struct-variant", "default", Event::Rename { from: String::from("x"), to: String::from("y") }),
+            case("struct", "default", RECORD, record()),
+            case("struct.tagged", "tagged", RECORD, record()),
+            case("struct.self-describing", "self-describing", RECORD, record()),
+            case("struct.canonical", "canonical", RECORD, record()),
+            case("struct.string-table", "string-table", RECORD, record()),
+            case!("map.canonical", "canonical", std::collections::HashMap::from([(3u8, 'c'), (1, 'a'), (2, 'b')])),
+        ];
+        if cfg!(feature = "checksum") {
+            cases.push(case("struct.crc32c", "crc32c", RECORD, record()));
+            cases.push(case("struct.xxh64", "xxh64", RECORD, record()));
+        }
+        cases
+    }
+
+    fn render(cases: &[Case]) -> String {
+        let mut out = String::from(
+            "# nanobit golden test vectors\n\
+             #\n\
+             # name | config | value | hex\n\
+             #\n\
+             # The bytes of a vector never change between releases; see src/vectors.rs.\n\
+             #\n\
+             # struct Record { id: u32, name: String, tags: Vec<String>, parent: Option<u32>, event: Event }\n\
+             # enum Event { Start, Tick(u64), Move(i8, i8), Rename { from: String, to: String } }\n\
+             # struct Unit;\n\
+             # struct Meters(f64);\n\
+             # struct Point(i16, i16);\n\
+             # struct Blob { #[serde(with = \"nanobit::byte_slice\")] data: Vec<u8> }\n",
+        );
+        for case in cases {
+            write!(out, "{} | {} | {} | ", case.name, case.config, case.value).unwrap();
+            for byte in &case.bytes {
+                write!(out, "{byte:02x}").unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Run with NANOBIT_BLESS_VECTORS=1 to write new vectors; existing ones
+    // must never change
+    #[test]
+    fn test_serializer_reproduces_golden_vectors() {
+        let cases = cases();
+        if std::env::var_os("NANOBIT_BLESS_VECTORS").is_some() {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/vectors/golden.txt");
+            std::fs::write(path, render(&cases)).unwrap();
+            return;
+        }
+
+        let golden = vectors();
+        for case in &cases {
+            let Some(vector) = golden.iter().find(|vector| vector.name == case.name) else {
+                panic!("{} has no golden vector; add it with NANOBIT_BLESS_VECTORS=1", case.name);
+            };
+            assert_eq!(vector.value, case.value, "{}", case.name);
+            assert_eq!(Some(vector.config), parse_config(case.config), "{}", case.name);
+            assert_eq!(vector.bytes, case.bytes, "the encoding of {} changed", case.name);
+        }
+        assert_eq!(golden.len(), cases.len(), "every golden vector has a case");
+    }
+
+    #[test]
+    fn test_vector_lookup() {
+        let vector = vector("struct.tagged").unwrap();
+        assert!(vector.config.tagged_fields());
+        assert_eq!(crate::from_bytes::<Record>(&vector.bytes).unwrap(), record());
+        assert!(super::vector("no-such-vector").is_none());
+    }
+
+    // Truncated and corrupted vectors must fail cleanly, never panic
+    #[test]
+    fn test_mutated_vectors_do_not_panic() {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for vector in vectors() {
+            let decode = |bytes: &[u8]| {
+                let _ = crate::from_bytes::<crate::Value>(bytes);
+                let _ = crate::from_bytes::<Record>(bytes);
+                let _ = crate::from_bytes::<Vec<String>>(bytes);
+            };
+            for len in 0..vector.bytes.len() {
+                decode(&vector.bytes[..len]);
+            }
+            for _ in 0..64 {
+                let mut bytes = vector.bytes.clone();
+                let index = next() as usize % bytes.len();
+                bytes[index] = next() as u8;
+                decode(&bytes);
+            }
+        }
+    }
+}
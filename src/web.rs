@@ -0,0 +1,406 @@
+//! Request extractors and responders for axum and actix-web
+//!
+//! [`Nanobit<T>`] works like the frameworks' `Json<T>`: as an extractor it
+//! decodes the request body, and as a responder it encodes the value with
+//! the `application/x-nanobit` content type.
+//!
+//! ```rust,ignore
+//! use nanobit::web::Nanobit;
+//!
+//! async fn create_user(Nanobit(user): Nanobit<NewUser>) -> Nanobit<User> {
+//!     Nanobit(store.insert(user))
+//! }
+//! ```
+//!
+//! Bodies are read up to [`NanobitConfig::limit`] bytes, which also bounds
+//! the decompressed size of compressed payloads; those are recognized by
+//! their header, so clients may send either. Options are taken from a
+//! [`NanobitConfig`] in the request extensions with axum, or the app data
+//! with actix-web.
+
+use core::fmt;
+
+use futures_util::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::header::{Header, HeaderFlags};
+
+pub use crate::Nanobit;
+
+/// Media type of nanobit request and response bodies
+pub const CONTENT_TYPE: &str = "application/x-nanobit";
+
+/// Largest body accepted unless configured otherwise
+pub const DEFAULT_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Options for decoding request bodies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NanobitConfig {
+    config: Config,
+    limit: usize,
+}
+
+impl NanobitConfig {
+    /// Default decoding options and a limit of [`DEFAULT_LIMIT`]
+    pub const fn new() -> Self {
+        Self {
+            config: Config::new(),
+            limit: DEFAULT_LIMIT,
+        }
+    }
+
+    /// Decode bodies with the given options
+    pub const fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Refuse bodies larger than `limit` bytes, before or after decompression
+    pub const fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Get the decoding options
+    #[inline]
+    pub const fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Get the body size limit
+    #[inline]
+    pub const fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl Default for NanobitConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why a request body was refused
+#[derive(Debug)]
+pub enum NanobitRejection {
+    /// The content type is not `application/x-nanobit`, or the body has a
+    /// `Content-Encoding` other than `identity`
+    UnsupportedMediaType,
+    /// The body is larger than the configured limit
+    PayloadTooLarge {
+        /// Limit in bytes
+        limit: usize,
+    },
+    /// The body could not be read
+    Body(String),
+    /// The body is not a valid payload for the expected type
+    InvalidBody(Error),
+}
+
+impl NanobitRejection {
+    /// HTTP status code for the response
+    pub fn status(&self) -> u16 {
+        match self {
+            NanobitRejection::UnsupportedMediaType => 415,
+            NanobitRejection::PayloadTooLarge { .. } => 413,
+            NanobitRejection::Body(_) | NanobitRejection::InvalidBody(_) => 400,
+        }
+    }
+}
+
+impl fmt::Display for NanobitRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NanobitRejection::UnsupportedMediaType => write!(f, "Expected a body of type {CONTENT_TYPE}"),
+            NanobitRejection::PayloadTooLarge { limit } => write!(f, "Body exceeds the limit of {limit} bytes"),
+            NanobitRejection::Body(msg) => write!(f, "Failed to read body: {msg}"),
+            NanobitRejection::InvalidBody(e) => write!(f, "Invalid body: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NanobitRejection {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NanobitRejection::InvalidBody(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Request headers that decide whether a body is read at all
+struct BodyHeaders<'a> {
+    content_type: Option<&'a [u8]>,
+    content_encoding: Option<&'a [u8]>,
+    content_length: Option<&'a [u8]>,
+}
+
+impl BodyHeaders<'_> {
+    fn check(&self, options: &NanobitConfig) -> core::result::Result<(), NanobitRejection> {
+        let media_type = self.content_type.map(|value| value.split(|&b| b == b';').next().unwrap_or_default());
+        if !media_type.is_some_and(|value| value.trim_ascii().eq_ignore_ascii_case(CONTENT_TYPE.as_bytes())) {
+            return Err(NanobitRejection::UnsupportedMediaType);
+        }
+        if self.content_encoding.is_some_and(|value| !value.trim_ascii().eq_ignore_ascii_case(b"identity")) {
+            return Err(NanobitRejection::UnsupportedMediaType);
+        }
+        let length = self.content_length.and_then(|value| core::str::from_utf8(value).ok()?.parse::<usize>().ok());
+        if length.is_some_and(|length| length > options.limit) {
+            return Err(NanobitRejection::PayloadTooLarge { limit: options.limit });
+        }
+        Ok(())
+    }
+}
+
+// Read a body stream, refusing it once it exceeds the limit
+async fn read_body<S, B, E>(mut body: S, options: &NanobitConfig) -> core::result::Result<Vec<u8>, NanobitRejection>
+where
+    S: Stream<Item = core::result::Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: fmt::Display,
+{
+    let mut out = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| NanobitRejection::Body(e.to_string()))?;
+        if chunk.as_ref().len() > options.limit - out.len() {
+            return Err(NanobitRejection::PayloadTooLarge { limit: options.limit });
+        }
+        out.extend_from_slice(chunk.as_ref());
+    }
+    Ok(out)
+}
+
+// Decode a body, decompressing it first if needed
+fn decode_body<T>(body: &[u8], options: &NanobitConfig) -> core::result::Result<T, NanobitRejection>
+where
+    T: DeserializeOwned,
+{
+    let (header, _) = Header::parse(body).map_err(NanobitRejection::InvalidBody)?;
+    let result = if header.flags.contains(HeaderFlags::COMPRESSED) {
+        crate::compression::decompress_with_limit(body, options.limit)
+            .and_then(|decompressed| crate::from_bytes_with_config(&decompressed, options.config))
+    } else {
+        crate::from_bytes_with_config(body, options.config)
+    };
+    result.map_err(|e| match e {
+        Error::LimitExceeded { .. } => NanobitRejection::PayloadTooLarge { limit: options.limit },
+        e => NanobitRejection::InvalidBody(e),
+    })
+}
+
+fn encode_body<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    crate::to_bytes(value)
+}
+
+#[cfg(feature = "axum")]
+mod axum_support {
+    use super::*;
+    use axum::extract::{FromRequest, Request};
+    use axum::http::{header, HeaderValue, StatusCode};
+    use axum::response::{IntoResponse, Response};
+
+    impl<T, S> FromRequest<S> for Nanobit<T>
+    where
+        T: DeserializeOwned,
+        S: Send + Sync,
+    {
+        type Rejection = NanobitRejection;
+
+        async fn from_request(req: Request, _state: &S) -> core::result::Result<Self, NanobitRejection> {
+            let options = req.extensions().get::<NanobitConfig>().copied().unwrap_or_default();
+            let headers = req.headers();
+            BodyHeaders {
+                content_type: headers.get(header::CONTENT_TYPE).map(HeaderValue::as_bytes),
+                content_encoding: headers.get(header::CONTENT_ENCODING).map(HeaderValue::as_bytes),
+                content_length: headers.get(header::CONTENT_LENGTH).map(HeaderValue::as_bytes),
+            }
+            .check(&options)?;
+            let body = read_body(req.into_body().into_data_stream(), &options).await?;
+            decode_body(&body, &options).map(Nanobit)
+        }
+    }
+
+    impl<T: Serialize> IntoResponse for Nanobit<T> {
+        fn into_response(self) -> Response {
+            match encode_body(&self.0) {
+                Ok(bytes) => ([(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE))], bytes).into_response(),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            }
+        }
+    }
+
+    impl IntoResponse for NanobitRejection {
+        fn into_response(self) -> Response {
+            let status = StatusCode::from_u16(self.status()).unwrap_or(StatusCode::BAD_REQUEST);
+            (status, self.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(feature = "actix-web")]
+mod actix_support {
+    use super::*;
+    use actix_web::dev::Payload;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{FromRequest, HttpRequest, HttpResponse, Responder, ResponseError};
+    use futures_util::future::LocalBoxFuture;
+
+    impl<T> FromRequest for Nanobit<T>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        type Error = NanobitRejection;
+        type Future = LocalBoxFuture<'static, core::result::Result<Self, NanobitRejection>>;
+
+        fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+            let options = req.app_data::<NanobitConfig>().copied().unwrap_or_default();
+            let headers = req.headers();
+            let checked = BodyHeaders {
+                content_type: headers.get(header::CONTENT_TYPE).map(|value| value.as_bytes()),
+                content_encoding: headers.get(header::CONTENT_ENCODING).map(|value| value.as_bytes()),
+                content_length: headers.get(header::CONTENT_LENGTH).map(|value| value.as_bytes()),
+            }
+            .check(&options);
+            let payload = payload.take();
+            Box::pin(async move {
+                checked?;
+                let body = read_body(payload, &options).await?;
+                decode_body(&body, &options).map(Nanobit)
+            })
+        }
+    }
+
+    impl<T: Serialize> Responder for Nanobit<T> {
+        type Body = actix_web::body::BoxBody;
+
+        fn respond_to(self, _req: &HttpRequest) -> HttpResponse {
+            match encode_body(&self.0) {
+                Ok(bytes) => HttpResponse::Ok().content_type(CONTENT_TYPE).body(bytes),
+                Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+            }
+        }
+    }
+
+    impl ResponseError for NanobitRejection {
+        fn status_code(&self) -> StatusCode {
+            StatusCode::from_u16(self.status()).unwrap_or(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct NewUser {
+        name: String,
+        tags: Vec<String>,
+    }
+
+    fn user() -> NewUser {
+        NewUser { name: "ada".to_string(), tags: vec!["admin".to_string(); 64] }
+    }
+
+    #[tokio::test]
+    async fn test_body_checks() {
+        let options = NanobitConfig::new().with_limit(1024);
+        let headers = |content_type, content_encoding, content_length| BodyHeaders {
+            content_type: Some(content_type),
+            content_encoding,
+            content_length,
+        };
+
+        assert!(headers(b"application/x-nanobit; v=1", None, Some(b"1024")).check(&options).is_ok());
+        assert!(headers(b" Application/X-Nanobit ", Some(b"identity"), None).check(&options).is_ok());
+        let rejected = [
+            headers(b"application/json", None, None),
+            headers(b"application/x-nanobit", Some(b"gzip"), None),
+            headers(b"application/x-nanobit", None, Some(b"1025")),
+        ];
+        let statuses: Vec<u16> = rejected.iter().map(|h| h.check(&options).unwrap_err().status()).collect();
+        assert_eq!(statuses, [415, 415, 413]);
+        let missing = BodyHeaders { content_type: None, content_encoding: None, content_length: None };
+        assert_eq!(missing.check(&options).unwrap_err().status(), 415);
+
+        // Bodies without a Content-Length are cut off at the limit as they stream in
+        let chunks = |count| futures_util::stream::iter((0..count).map(|_| Ok::<_, Error>(vec![0u8; 256])));
+        assert_eq!(read_body(chunks(4), &options).await.unwrap().len(), 1024);
+        assert_eq!(read_body(chunks(5), &options).await.unwrap_err().status(), 413);
+        let failed = futures_util::stream::iter([Err::<Vec<u8>, _>(Error::UnexpectedEof)]);
+        assert_eq!(read_body(failed, &options).await.unwrap_err().status(), 400);
+
+        // Round trip through the body encoding, plain and compressed
+        let plain = encode_body(&user()).unwrap();
+        let compressed = crate::compress_default(&plain).unwrap();
+        assert_eq!(decode_body::<NewUser>(&plain, &options).unwrap(), user());
+        assert_eq!(decode_body::<NewUser>(&compressed, &options).unwrap(), user());
+        assert_eq!(decode_body::<NewUser>(&plain[..plain.len() - 3], &options).unwrap_err().status(), 400);
+        assert_eq!(decode_body::<NewUser>(b"not a payload", &options).unwrap_err().status(), 400);
+        let small = NanobitConfig::new().with_limit(plain.len() - 1);
+        assert_eq!(decode_body::<NewUser>(&compressed, &small).unwrap_err().status(), 413);
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_axum_extract_and_respond() {
+        use axum::body::Body;
+        use axum::extract::FromRequest;
+        use axum::http::{header, Request};
+        use axum::response::IntoResponse;
+
+        let request = |body: Vec<u8>, content_type: &str| {
+            Request::builder().header(header::CONTENT_TYPE, content_type).body(Body::from(body)).unwrap()
+        };
+        let plain = crate::to_bytes(&user()).unwrap();
+        let compressed = crate::compress_default(&plain).unwrap();
+        for body in [plain.clone(), compressed.clone()] {
+            let Nanobit(decoded) = Nanobit::<NewUser>::from_request(request(body, CONTENT_TYPE), &()).await.unwrap();
+            assert_eq!(decoded, user());
+        }
+
+        let wrong_type = Nanobit::<NewUser>::from_request(request(plain.clone(), "application/json"), &()).await;
+        assert_eq!(wrong_type.unwrap_err().status(), 415);
+        // The limit applies to the decompressed size too
+        let mut limited = request(compressed, "application/x-nanobit; v=1");
+        limited.extensions_mut().insert(NanobitConfig::new().with_limit(plain.len() - 1));
+        let too_large = Nanobit::<NewUser>::from_request(limited, &()).await;
+        assert_eq!(too_large.unwrap_err().status(), 413);
+
+        let response = Nanobit(user()).into_response();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, plain);
+    }
+
+    #[cfg(feature = "actix-web")]
+    #[tokio::test]
+    async fn test_actix_extract_and_respond() {
+        use actix_web::test::TestRequest;
+        use actix_web::{FromRequest, Responder};
+
+        let plain = crate::to_bytes(&user()).unwrap();
+        let (req, mut payload) = TestRequest::default()
+            .insert_header(("content-type", CONTENT_TYPE))
+            .set_payload(plain.clone())
+            .to_http_parts();
+        let Nanobit(decoded) = Nanobit::<NewUser>::from_request(&req, &mut payload).await.unwrap();
+        assert_eq!(decoded, user());
+
+        let (req, mut payload) = TestRequest::default()
+            .insert_header(("content-type", CONTENT_TYPE))
+            .app_data(NanobitConfig::new().with_limit(16))
+            .set_payload(plain.clone())
+            .to_http_parts();
+        let too_large = Nanobit::<NewUser>::from_request(&req, &mut payload).await;
+        assert_eq!(too_large.unwrap_err().status(), 413);
+
+        let response = Nanobit(user()).respond_to(&req);
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, plain);
+    }
+}
@@ -0,0 +1,184 @@
+//! Types that deserialize without touching the heap
+//!
+//! A counting global allocator fails these tests if decoding any of the
+//! types listed under "Zero-Allocation Decoding" in the README allocates.
+//! Keep the two lists in sync.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::cell::Cell;
+
+use nanobit::{Config, Lazy, RawValue};
+use serde::{Deserialize, Serialize};
+
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// Counts per thread, as the test harness runs tests concurrently
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+// Decode a value of the same type as `_like`, borrowing from `bytes`
+fn decode_as<'de, T: Deserialize<'de>>(_like: &T, bytes: &'de [u8], config: Config) -> nanobit::Result<T> {
+    nanobit::from_bytes_with_config(bytes, config)
+}
+
+// Encode each value, then check it decodes back with no allocation under
+// every config
+macro_rules! zero_alloc {
+    ($($value:expr),+ $(,)?) => {
+        for config in configs() {
+            $(
+                let value = $value;
+                let bytes = nanobit::to_bytes_with_config(&value, config).unwrap();
+                let (decoded, count) = allocations(|| decode_as(&value, &bytes, config));
+                assert_eq!(decoded.unwrap(), value);
+                assert_eq!(count, 0, "decoding {} allocated {count} times", stringify!($value));
+            )+
+        }
+    };
+}
+
+fn configs() -> [Config; 4] {
+    [
+        Config::new(),
+        Config::new().with_tagged_fields(true).with_compact_chars(true),
+        Config::new().with_self_describing(true),
+        Config::new().with_checksum(Some(nanobit::Checksum::Crc32c)),
+    ]
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Unit;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Meters(f64);
+
+// Plain `&[u8]` serializes as a sequence, which self-describing payloads
+// tag element by element; written as a byte string it borrows everywhere
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Bytes<'a>(#[serde(serialize_with = "nanobit::byte_slice::serialize")] &'a [u8]);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Shape<'a> {
+    Empty,
+    Circle(f32),
+    Rect(u16, u16),
+    Label { text: &'a str },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Request<'a> {
+    id: u64,
+    method: &'a str,
+    #[serde(borrow)]
+    path: Cow<'a, str>,
+    #[serde(serialize_with = "nanobit::byte_slice::serialize")]
+    body: &'a [u8],
+    retry: Option<u8>,
+    origin: (i32, i32),
+    flags: [bool; 4],
+    #[serde(borrow)]
+    shape: Shape<'a>,
+}
+
+#[test]
+fn test_primitives_decode_without_allocating() {
+    zero_alloc!(
+        true,
+        -7i8,
+        300i16,
+        i32::MIN,
+        i64::MAX,
+        255u8,
+        u16::MAX,
+        300u32,
+        u64::MAX,
+        1.5f32,
+        std::f64::consts::E,
+        '🦀',
+        (),
+    );
+}
+
+#[test]
+fn test_borrowed_data_decodes_without_allocating() {
+    zero_alloc!(
+        "borrowed str",
+        Bytes(b"borrowed bytes"),
+        Some("some"),
+        None::<&str>,
+        (1u8, "two", 3.0f64),
+        [7u32; 16],
+    );
+}
+
+#[test]
+fn test_structs_and_enums_decode_without_allocating() {
+    zero_alloc!(
+        Unit,
+        Meters(2.5),
+        Shape::Empty,
+        Shape::Circle(1.0),
+        Shape::Rect(3, 4),
+        Shape::Label { text: "label" },
+        Request {
+            id: 42,
+            method: "GET",
+            path: Cow::Borrowed("/items/42"),
+            body: b"{}",
+            retry: Some(3),
+            origin: (-1, 1),
+            flags: [true, false, true, false],
+            shape: Shape::Rect(640, 480),
+        },
+    );
+}
+
+#[test]
+fn test_deferred_values_decode_without_allocating() {
+    let inner = nanobit::to_bytes(&vec![1u64; 100]).unwrap();
+    let lazy: Lazy<'_, Vec<u64>> = Lazy::from_encoded(&inner).unwrap();
+    let raw = RawValue::from_encoded(&inner[..]).unwrap();
+    let bytes = nanobit::to_bytes(&(lazy, raw)).unwrap();
+
+    let (decoded, count) = allocations(|| nanobit::from_bytes::<(Lazy<'_, Vec<u64>>, RawValue<'_>)>(&bytes));
+    let (lazy, raw) = decoded.unwrap();
+    assert_eq!(count, 0);
+    assert_eq!(lazy.as_bytes(), &inner[..]);
+    assert_eq!(raw.as_bytes(), &inner[..]);
+}
+
+// Guards the guard: owned types must be seen allocating
+#[test]
+fn test_counter_sees_allocations() {
+    let bytes = nanobit::to_bytes(&String::from("owned")).unwrap();
+    let (decoded, count) = allocations(|| nanobit::from_bytes::<String>(&bytes));
+    assert_eq!(decoded.unwrap(), "owned");
+    assert!(count > 0);
+}